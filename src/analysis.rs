@@ -0,0 +1,518 @@
+use crate::diagnostic::Diagnostic;
+use crate::error::Span;
+use crate::interpreter::read_source_resynchronizing;
+use crate::lexer::is_generated;
+use crate::parser::*;
+use std::collections::HashMap;
+use std::fmt;
+
+// something a program does which runs but is likely a mistake, found without running it
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    // a name for the kind of warning, as tools reading diagnostics match on
+    pub code: &'static str,
+    pub message: String,
+    // the name of the binding warned about, or else the innermost call read from the source
+    // around what is warned about, or else its form
+    pub span: Span,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "warning: {}", self.message)
+    }
+}
+
+impl Warning {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::warning(self.message.clone())
+            .code(self.code)
+            .label(self.span, "")
+    }
+}
+
+// the builtins of the scheme libraries taking a number of arguments within a range, as they
+// check when called, by the least and the most they take
+const BUILTIN_ARITIES: &[(usize, usize, &str)] = &[
+    (
+        0,
+        0,
+        "command-line current-error-port current-input-port current-jiffy \
+         current-output-port current-second eof-object features \
+         get-environment-variables jiffies-per-second open-output-bytevector \
+         open-output-string",
+    ),
+    (
+        0,
+        1,
+        "char-ready? emergency-exit exit flush-output-port newline peek-char peek-u8 \
+         read read-char read-line read-u8 u8-ready?",
+    ),
+    (
+        1,
+        1,
+        "abs acos asin binary-port? bytevector-length bytevector? caaaar caaadr caaar \
+         caadar caaddr caadr caar cadaar cadadr cadar caddar cadddr caddr cadr car \
+         cdaaar cdaadr cdaar cdadar cdaddr cdadr cdar cddaar cddadr cddar cdddar cddddr \
+         cdddr cddr cdr ceiling char->integer char-alphabetic? char-downcase \
+         char-foldcase char-lower-case? char-numeric? char-upcase char-upper-case? \
+         char-whitespace? close-input-port close-output-port close-port cos \
+         create-directory delete-directory delete-file digit-value directory-files \
+         eof-object? error-object-irritants error-object-message error-object? \
+         exact-integer-sqrt exp file-error? file-exists? file-mtime file-size floor \
+         force get-environment-variable get-output-bytevector get-output-string \
+         input-port-open? input-port? integer->char length list->string list->vector \
+         list-copy list? make-promise null? open-binary-input-file \
+         open-binary-output-file open-input-bytevector open-input-file \
+         open-input-string open-output-file output-port-open? output-port? pair? port? \
+         promise? raise read-error? reverse round sin sqrt string->symbol \
+         string-downcase string-foldcase string-length string-upcase symbol->string \
+         symbol? tan textual-port? truncate vector-length vector?",
+    ),
+    (
+        1,
+        2,
+        "atan display log make-bytevector make-vector read-bytevector read-string \
+         string-join write write-char write-shared write-simple write-u8",
+    ),
+    (
+        1,
+        3,
+        "bytevector-copy string->list string->utf8 string->vector string-copy \
+         utf8->string vector->list vector->string vector-copy",
+    ),
+    (1, 4, "read-bytevector! write-bytevector write-string"),
+    (
+        2,
+        2,
+        "assq assv bytevector-u8-ref call-with-input-file call-with-output-file \
+         call-with-port call-with-values cons eq? equal? eqv? expt floor-quotient \
+         floor/ list-ref list-tail memq memv modulo quotient remainder rename-file \
+         set-car! set-cdr! string-contains string-ref string-split truncate/ vector-ref \
+         with-input-from-file with-output-to-file",
+    ),
+    (2, 3, "assoc member"),
+    (2, 4, "vector-fill!"),
+    (
+        3,
+        3,
+        "bytevector-u8-set! dynamic-wind substring subvector vector-set!",
+    ),
+];
+
+struct Binding {
+    name: String,
+    // the number of formals of a procedure the binding is defined as
+    arity: Option<usize>,
+    // defined in a body rather than bound as a formal, which the definitions shadow
+    defined: bool,
+    used: bool,
+    span: Span,
+}
+
+struct Analysis {
+    // the top level definitions, with the number of formals of those defined once as a procedure
+    globals: HashMap<String, Option<usize>>,
+    // the arities of the builtins, which the top level definitions shadow
+    builtins: HashMap<&'static str, (usize, usize)>,
+    // the bindings of the procedures and clauses being walked, innermost last
+    scopes: Vec<Vec<Binding>>,
+    warnings: Vec<Warning>,
+}
+
+// names starting with _ are unused on purpose, and generated names are made by the parser
+fn reported(name: &str) -> bool {
+    !name.starts_with('_') && !is_generated(name)
+}
+
+fn arity(expression: &Expression) -> Option<usize> {
+    match expression {
+        Expression::Procedure(SchemeProcedure(formals, ..)) => Some(formals.len()),
+        _ => None,
+    }
+}
+
+// the forms which cannot be read are left out, evaluation reports their errors
+pub fn warnings(source: &str) -> Vec<Warning> {
+    let mut forms = vec![];
+    read_source_resynchronizing(source, None, None, |form, span| {
+        forms.push((form, span));
+        Ok(())
+    });
+    let mut analysis = Analysis {
+        globals: HashMap::new(),
+        builtins: BUILTIN_ARITIES
+            .iter()
+            .flat_map(|&(least, most, names)| {
+                names
+                    .split_whitespace()
+                    .map(move |name| (name, (least, most)))
+            })
+            .collect(),
+        scopes: vec![],
+        warnings: vec![],
+    };
+    for (form, _) in &forms {
+        analysis.define_globals(form);
+    }
+    for (form, span) in &forms {
+        analysis.statement(form, *span);
+    }
+    analysis.warnings
+}
+
+impl Analysis {
+    fn warn(&mut self, code: &'static str, message: String, span: Span) {
+        self.warnings.push(Warning {
+            code,
+            message,
+            span,
+        });
+    }
+
+    fn define_globals(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Definition(Definition(name, expression)) => {
+                let defined = arity(expression);
+                self.globals
+                    .entry(name.clone())
+                    .and_modify(|arity| {
+                        if *arity != defined {
+                            *arity = None
+                        }
+                    })
+                    .or_insert(defined);
+            }
+            Statement::Sequence(statements) => {
+                statements.iter().for_each(|s| self.define_globals(s))
+            }
+            _ => (),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, span: Span) {
+        match statement {
+            Statement::Definition(Definition(_, expression))
+            | Statement::Expression(expression) => self.expression(expression, span),
+            Statement::Sequence(statements) => {
+                statements.iter().for_each(|s| self.statement(s, span))
+            }
+            Statement::ImportDeclaration(_) => (),
+        }
+    }
+
+    // a name defined twice in a body is a duplicate rather than shadowing, the first
+    // definition being left unused by the second
+    fn bind(&mut self, name: &str, arity: Option<usize>, defined: bool, span: Span) {
+        let (scope, enclosing) = self.scopes.split_last_mut().unwrap();
+        let shadowed = enclosing
+            .iter()
+            .any(|scope| scope.iter().any(|binding| binding.name == name))
+            || scope
+                .iter()
+                .any(|binding| binding.name == name && binding.defined != defined);
+        let duplicate = scope
+            .iter_mut()
+            .find(|binding| binding.name == name && binding.defined == defined)
+            .map(|binding| binding.used = true)
+            .is_some();
+        if duplicate && reported(name) {
+            self.warn(
+                "duplicate-definition",
+                format!("{} is defined more than once in the same scope", name),
+                span,
+            );
+        } else if shadowed && reported(name) {
+            self.warn(
+                "shadowed-variable",
+                format!("{} shadows a variable of an enclosing scope", name),
+                span,
+            );
+        }
+        let binding = Binding {
+            name: name.to_string(),
+            arity,
+            defined,
+            used: !reported(name),
+            span,
+        };
+        self.scopes.last_mut().unwrap().push(binding);
+    }
+
+    fn close_scope(&mut self) {
+        for binding in self.scopes.pop().unwrap_or_default() {
+            if !binding.used {
+                self.warn(
+                    "unused-variable",
+                    format!("unused variable {}", binding.name),
+                    binding.span,
+                );
+            }
+        }
+    }
+
+    // the binding a name refers to, None for top level definitions and builtins
+    fn binding(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|binding| binding.name == name)
+    }
+
+    fn expression(&mut self, expression: &Expression, span: Span) {
+        match expression {
+            Expression::Identifier(name) => {
+                if let Some(binding) = self.binding(name) {
+                    binding.used = true;
+                }
+            }
+            Expression::Vector(elements) => elements.iter().for_each(|e| self.expression(e, span)),
+            Expression::Procedure(procedure) => self.procedure(procedure, false, span),
+            Expression::ProcedureCall(operator, arguments) => self.call(operator, arguments, span),
+            Expression::LocatedCall(call) => self.call(&call.0, &call.1, call.2),
+            Expression::Conditional(conditional) => {
+                let (test, consequent, alternative) = conditional.as_ref();
+                self.expression(test, span);
+                self.expression(consequent, span);
+                if let Some(alternative) = alternative {
+                    self.expression(alternative, span);
+                }
+            }
+            Expression::Guard(guard) => {
+                let (variable, clauses, body) = guard.as_ref();
+                self.expression(body, span);
+                // the condition need not be used, none of the clauses may want it
+                self.scopes.push(vec![]);
+                self.bind(variable, None, false, span);
+                if let Some(binding) = self.binding(variable) {
+                    binding.used = true;
+                }
+                let mut after_else = false;
+                for GuardClause(test, body) in clauses {
+                    if after_else {
+                        self.warn(
+                            "unreachable-clause",
+                            "unreachable guard clause after else".to_string(),
+                            span,
+                        );
+                    }
+                    match test {
+                        Some(test) => self.expression(test, span),
+                        None => after_else = true,
+                    }
+                    body.iter().for_each(|e| self.expression(e, span));
+                }
+                self.close_scope();
+            }
+            Expression::Delay(thunk) | Expression::DelayForce(thunk) => {
+                self.expression(thunk, span)
+            }
+            Expression::StreamCons(stream) => {
+                self.expression(&stream.0, span);
+                self.expression(&stream.1, span);
+            }
+            Expression::Match(matching) => {
+                let (value, clauses) = matching.as_ref();
+                self.expression(value, span);
+                let mut after_catch_all = false;
+                for MatchClause(pattern, body) in clauses {
+                    if after_catch_all {
+                        self.warn(
+                            "unreachable-clause",
+                            "unreachable match clause after a pattern matching anything"
+                                .to_string(),
+                            span,
+                        );
+                    }
+                    self.pattern(pattern, span);
+                    self.expression(body, span);
+                    after_catch_all |= matches!(pattern, Pattern::Wildcard | Pattern::Variable(_));
+                }
+            }
+            Expression::Datum(_)
+            | Expression::Primitive(_)
+            | Expression::Boolean(_)
+            | Expression::Character(_)
+            | Expression::String(_)
+            | Expression::Integer(_)
+            | Expression::Real(_)
+            | Expression::Rational(_, _) => (),
+        }
+    }
+
+    // the predicates of a pattern are evaluated where the match is
+    fn pattern(&mut self, pattern: &Pattern, span: Span) {
+        match pattern {
+            Pattern::Predicate(predicate, patterns) => {
+                self.expression(predicate, span);
+                patterns.iter().for_each(|p| self.pattern(p, span));
+            }
+            Pattern::List(SequencePattern(patterns, _), tail) => {
+                patterns.iter().for_each(|p| self.pattern(p, span));
+                self.pattern(tail, span);
+            }
+            Pattern::Vector(SequencePattern(patterns, _)) => {
+                patterns.iter().for_each(|p| self.pattern(p, span))
+            }
+            Pattern::Wildcard | Pattern::Variable(_) | Pattern::Datum(_) => (),
+        }
+    }
+
+    // the bindings are spanned by their names when the procedure knows where they are
+    // the formals of a callback are those the procedure it is passed to gives, so they need not
+    // be used
+    fn procedure(&mut self, procedure: &SchemeProcedure, callback: bool, span: Span) {
+        let SchemeProcedure(formals, definitions, expressions, origin) = procedure;
+        let (formal_spans, definition_spans) = match origin {
+            Some(origin) => (&origin.formals[..], &origin.definitions[..]),
+            None => (&[][..], &[][..]),
+        };
+        self.scopes.push(vec![]);
+        for (index, formal) in formals.iter().enumerate() {
+            let span = formal_spans.get(index).cloned().unwrap_or(span);
+            self.bind(formal, None, false, span);
+            if callback {
+                self.scopes.last_mut().unwrap().last_mut().unwrap().used = true;
+            }
+        }
+        for (index, Definition(name, expression)) in definitions.iter().enumerate() {
+            let span = definition_spans.get(index).cloned().unwrap_or(span);
+            self.bind(name, arity(expression), true, span);
+        }
+        for Definition(_, expression) in definitions {
+            self.expression(expression, span);
+        }
+        expressions.iter().for_each(|e| self.expression(e, span));
+        self.close_scope();
+    }
+
+    // calls of procedures defined with a number of formals, and of builtins taking a number of
+    // arguments within a range, are checked against it
+    fn call(&mut self, operator: &Expression, arguments: &[Expression], span: Span) {
+        if let Expression::Identifier(name) = operator {
+            let expected = match self.binding(name) {
+                Some(binding) => binding.arity.map(|arity| (arity, arity)),
+                None => match self.globals.get(&**name) {
+                    Some(arity) => arity.map(|arity| (arity, arity)),
+                    None => self.builtins.get(&**name).cloned(),
+                },
+            };
+            let count = arguments.len();
+            match expected {
+                Some((least, most)) if !(least..=most).contains(&count) => {
+                    let expected = match least == most {
+                        true => least.to_string(),
+                        false => format!("{} to {}", least, most),
+                    };
+                    self.warn(
+                        "wrong-argument-count",
+                        format!("{} expects {} arguments, got {}", name, expected, count),
+                        span,
+                    );
+                }
+                _ => (),
+            }
+        }
+        self.expression(operator, span);
+        // the procedures the derived forms pass to primitives are written as bodies instead
+        let primitive = matches!(operator, Expression::Primitive(_));
+        for argument in arguments {
+            match argument {
+                Expression::Procedure(procedure) if !primitive => {
+                    self.procedure(procedure, true, span)
+                }
+                argument => self.expression(argument, span),
+            }
+        }
+    }
+}
+
+#[test]
+fn warning() {
+    let warned = |source: &str| -> Vec<String> {
+        warnings(source)
+            .iter()
+            .map(|warning| {
+                let Span { start, end } = warning.span;
+                let text: String = source.chars().skip(start).take(end - start).collect();
+                format!("{} at {} {}", warning.message, start, text)
+            })
+            .collect()
+    };
+    assert_eq!(
+        warned("(define (f x y) (* x 2))\n(display (f 1))\n(f 1 2)"),
+        vec![
+            "unused variable y at 13 y",
+            "f expects 2 arguments, got 1 at 34 (f 1)",
+        ]
+    );
+    assert_eq!(
+        warned("(define (g x) (lambda (x) x))\n(define (h _unused) 1)"),
+        vec![
+            "x shadows a variable of an enclosing scope at 23 x",
+            "unused variable x at 11 x",
+        ]
+    );
+    assert_eq!(
+        warned("(guard (e (else 1) (#t 2)) (raise 'oops))\n(match 1 (x x) (1 2))"),
+        vec![
+            "unreachable guard clause after else at 0 (guard (e (else 1) (#t 2)) (raise 'oops))",
+            "unreachable match clause after a pattern matching anything at 42 (match 1 (x x) (1 2))",
+        ]
+    );
+    assert_eq!(
+        warned("(define (k n) (define (loop i) (loop)) (loop n))\n(map (cut + 1 <>) '(1))"),
+        vec![
+            "loop expects 1 arguments, got 0 at 31 (loop)",
+            "unused variable i at 28 i",
+        ]
+    );
+    assert_eq!(
+        warned("(define (h2 y) (define z 3) (define z 4) (define y 5) (+ y z))"),
+        vec![
+            "z is defined more than once in the same scope at 36 z",
+            "y shadows a variable of an enclosing scope at 49 y",
+            "unused variable y at 12 y",
+        ]
+    );
+    assert_eq!(
+        warned("(receive (a . b) (values 1 2) a)\n(let-values (((c d) (values 1 2))) d)"),
+        vec!["unused variable b at 14 b", "unused variable c at 48 c"]
+    );
+    assert_eq!(
+        warned("(car 1 2)\n(display 1 (current-output-port) 2)\n(define (cdr x y) x)\n(cdr 1 2)"),
+        vec![
+            "car expects 1 arguments, got 2 at 0 (car 1 2)",
+            "display expects 1 to 2 arguments, got 3 at 10 (display 1 (current-output-port) 2)",
+            "unused variable y at 61 y",
+        ]
+    );
+    // callbacks take what they are given, whether they use it or not
+    assert_eq!(
+        warned("(for-each (lambda (k v) (write k)) '(1) '(2))\n(define (g k v) k)"),
+        vec!["unused variable v at 59 v"]
+    );
+    assert!(warned("(define (f x) x)\n(f 1 2").is_empty());
+}
+
+#[test]
+fn builtin_arities() {
+    use crate::interpreter::Interpreter;
+    // a call with one argument too few or too many is what the builtin itself rejects
+    for &(least, most, names) in BUILTIN_ARITIES {
+        for name in names.split_whitespace() {
+            let counts = std::iter::once(most + 1).chain(least.checked_sub(1));
+            for count in counts {
+                let call = format!("({}{})", name, " 0".repeat(count));
+                let error = Interpreter::new().eval(call.chars()).unwrap_err();
+                assert!(
+                    error.message.starts_with(&format!("{} takes", name)),
+                    "{}: {}",
+                    call,
+                    error
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom};
+
+// a standalone program is the interpreter's executable with the program compiled to bytecode
+// after it, then the length of the bytecode and this mark, which the executable looks for in itself
+// when it starts
+const MARK: &[u8; 8] = b"ruschm\0p";
+const TRAILER: usize = 8 + MARK.len();
+
+// the executable running the program when started
+pub fn bundle(runtime: &[u8], program: &[u8]) -> Vec<u8> {
+    let mut executable = Vec::with_capacity(runtime.len() + program.len() + TRAILER);
+    executable.extend_from_slice(runtime);
+    executable.extend_from_slice(program);
+    executable.extend_from_slice(&(program.len() as u64).to_le_bytes());
+    executable.extend_from_slice(MARK);
+    executable
+}
+
+// the program bundled in an executable, if it is one
+pub fn bundled(executable: &mut (impl Read + Seek)) -> io::Result<Option<Vec<u8>>> {
+    let size = executable.seek(SeekFrom::End(0))?;
+    if size < TRAILER as u64 {
+        return Ok(None);
+    }
+    let mut trailer = [0; TRAILER];
+    executable.seek(SeekFrom::Start(size - TRAILER as u64))?;
+    executable.read_exact(&mut trailer)?;
+    if &trailer[8..] != MARK {
+        return Ok(None);
+    }
+    let mut length = [0; 8];
+    length.copy_from_slice(&trailer[..8]);
+    let length = u64::from_le_bytes(length);
+    let start = match (size - TRAILER as u64).checked_sub(length) {
+        Some(start) => start,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated program",
+            ))
+        }
+    };
+    let mut program = vec![0; usize::try_from(length).unwrap_or(usize::MAX)];
+    executable.seek(SeekFrom::Start(start))?;
+    executable.read_exact(&mut program)?;
+    Ok(Some(program))
+}
+
+#[test]
+fn bundling() -> io::Result<()> {
+    let runtime = b"\x7fELF the interpreter";
+    let executable = bundle(runtime, b"\0rbc the program");
+    assert!(executable.starts_with(runtime));
+    assert_eq!(
+        bundled(&mut io::Cursor::new(&executable))?.as_deref(),
+        Some(&b"\0rbc the program"[..])
+    );
+    assert_eq!(bundled(&mut io::Cursor::new(runtime))?, None);
+    assert_eq!(bundled(&mut io::Cursor::new(b""))?, None);
+    assert!(bundled(&mut io::Cursor::new(&executable[runtime.len() + 4..])).is_err());
+    Ok(())
+}
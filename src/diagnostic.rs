@@ -0,0 +1,510 @@
+use crate::error::{Error, ErrorType, Frame, Span, SyntaxError};
+use crate::interpreter::ruschm::json::to_json;
+use crate::interpreter::{Number, ValueType};
+use crate::lexer::intern;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// colors are for terminals, and not wanted by anyone setting NO_COLOR
+pub fn use_color(stream: &impl IsTerminal) -> bool {
+    env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && stream.is_terminal()
+}
+
+struct Style(bool);
+
+impl Style {
+    fn paint(&self, color: &str, text: &str) -> String {
+        match self.0 {
+            true => format!("{}{}{}", color, text, RESET),
+            false => text.to_string(),
+        }
+    }
+}
+
+// the line number and column of a character offset, both from 1, and the text of its line
+fn locate(source: &str, offset: usize) -> (usize, usize, &str) {
+    let mut line_start = 0;
+    let mut line = 1;
+    let mut column = 1;
+    for (i, (index, c)) in source.char_indices().enumerate() {
+        if i == offset {
+            break;
+        }
+        match c {
+            '\n' => {
+                line += 1;
+                column = 1;
+                line_start = index + 1;
+            }
+            _ => column += 1,
+        }
+    }
+    let text = source[line_start..].lines().next().unwrap_or("");
+    (line, column, text)
+}
+
+// a span of the source with what it has to do with the diagnostic
+// the first label is the primary one, underlined with ^ and the others with -
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    // something which runs but is likely a mistake
+    Warning,
+}
+
+// a report of an error in a source text: the headline, the labelled spans, then notes and help
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // a name for the kind of diagnostic, as tools reading diagnostics match on
+    pub code: Option<&'static str>,
+    // what the headline shows before the message, like error or warning
+    pub heading: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            heading: Some("error"),
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            heading: Some("warning"),
+            ..Self::new(message)
+        }
+    }
+
+    // the error with its span as the primary label, and help for the syntax errors with a fix
+    pub fn from_error(error: &Error, span: Span) -> Self {
+        let diagnostic = Self {
+            heading: error.heading(),
+            ..Self::new(error.message.clone())
+        }
+        .code(error.code())
+        .label(span, "");
+        match &error.category {
+            ErrorType::Syntax(SyntaxError::UnmatchedParenthesis) => {
+                diagnostic.help("remove the closing parenthesis or add an opening one before it")
+            }
+            ErrorType::Syntax(SyntaxError::UnterminatedList) => {
+                diagnostic.help("add the missing closing parentheses")
+            }
+            _ => diagnostic,
+        }
+    }
+
+    pub fn code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    // a JSON object of the diagnostic for tools, its span being the primary label's
+    // spans have the character offsets and the line and column of their start, both from 1
+    pub fn to_json(&self, source: &str, name: Option<&str>) -> String {
+        let null = || ValueType::Symbol(intern("null"));
+        let string = |text: &str| ValueType::String(text.to_string());
+        let integer = |n: usize| ValueType::Number(Number::Integer(n as i64));
+        let object = |members: Vec<(&str, ValueType)>| {
+            ValueType::list(
+                members
+                    .into_iter()
+                    .map(|(key, value)| ValueType::cons(ValueType::Symbol(intern(key)), value)),
+            )
+        };
+        let span = |span: Span| {
+            let (line, column, _) = locate(source, span.start);
+            object(vec![
+                ("start", integer(span.start)),
+                ("end", integer(span.end)),
+                ("line", integer(line)),
+                ("column", integer(column)),
+            ])
+        };
+        let strings =
+            |texts: &[String]| ValueType::vector(texts.iter().map(|t| string(t)).collect());
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                let mut members = vec![("span", span(label.span))];
+                // a label without a message only marks the span
+                if !label.message.is_empty() {
+                    members.push(("message", string(&label.message)));
+                }
+                object(members)
+            })
+            .collect();
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let diagnostic = object(vec![
+            ("severity", string(severity)),
+            ("code", self.code.map_or_else(null, string)),
+            ("message", string(&self.message)),
+            ("file", name.map_or_else(null, string)),
+            (
+                "span",
+                self.labels
+                    .first()
+                    .map_or_else(null, |label| span(label.span)),
+            ),
+            ("labels", ValueType::vector(labels)),
+            ("notes", strings(&self.notes)),
+            ("help", strings(&self.help)),
+        ]);
+        // all of it is representable, only numbers, strings, vectors and alists are written
+        to_json(&diagnostic).unwrap()
+    }
+
+    // writes the headline, then each line a label starts on with the labels underlined below it
+    // the name of the source, if any, is given with the line and column of the primary label
+    pub fn render(
+        &self,
+        out: &mut dyn Write,
+        source: &str,
+        name: Option<&str>,
+        color: bool,
+    ) -> io::Result<()> {
+        let style = Style(color);
+        let primary = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+        let headline = match self.heading {
+            Some(heading) => format!("{}: {}", heading, self.message),
+            None => self.message.clone(),
+        };
+        writeln!(out, "{}", style.paint(primary, &headline))?;
+        let located: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| (label, locate(source, label.span.start)))
+            .collect();
+        let widest = located
+            .iter()
+            .map(|(_, (line, _, _))| *line)
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(widest.to_string().len());
+        if let (Some(name), Some((_, (line, column, _)))) = (name, located.first()) {
+            writeln!(
+                out,
+                "{}{} {}:{}:{}",
+                gutter,
+                style.paint(BLUE, "-->"),
+                name,
+                line,
+                column
+            )?;
+        }
+        let mut lines: Vec<_> = located
+            .iter()
+            .map(|(_, (line, _, text))| (*line, *text))
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        if !lines.is_empty() {
+            writeln!(out, "{} {}", gutter, style.paint(BLUE, "|"))?;
+        }
+        for (line, text) in lines {
+            writeln!(
+                out,
+                "{} {}",
+                style.paint(BLUE, &format!("{:>width$} |", line, width = gutter.len())),
+                style.paint(BOLD, text)
+            )?;
+            for (index, (label, (_, column, _))) in located
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, (label_line, _, _)))| *label_line == line)
+            {
+                // a span running over several lines is underlined to the end of its first one
+                let width = text.chars().count().saturating_sub(column - 1);
+                let underlined = (label.span.end - label.span.start).clamp(1, width.max(1));
+                let (mark, color) = match index {
+                    0 => ("^", primary),
+                    _ => ("-", BLUE),
+                };
+                let underline = mark.repeat(underlined);
+                let underline = match label.message.as_str() {
+                    "" => underline,
+                    message => format!("{} {}", underline, message),
+                };
+                writeln!(
+                    out,
+                    "{} {} {}{}",
+                    gutter,
+                    style.paint(BLUE, "|"),
+                    " ".repeat(column - 1),
+                    style.paint(color, &underline)
+                )?;
+            }
+        }
+        for (kind, text) in self
+            .notes
+            .iter()
+            .map(|note| ("note", note))
+            .chain(self.help.iter().map(|help| ("help", help)))
+        {
+            writeln!(
+                out,
+                "{} {} {}: {}",
+                gutter,
+                style.paint(BLUE, "="),
+                style.paint(BOLD, kind),
+                text
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// writes the error, then the source line the span starts on with the span underlined
+pub fn render(
+    out: &mut dyn Write,
+    error: &Error,
+    span: Span,
+    source: &str,
+    name: Option<&str>,
+    color: bool,
+) -> io::Result<()> {
+    Diagnostic::from_error(error, span).render(out, source, name, color)
+}
+
+// the most calls of a cycle folded in a backtrace, as the calls of mutually recursive procedures
+const LONGEST_CYCLE: usize = 8;
+
+// writes the calls an error was raised in, innermost first, each where its call expression starts
+// calls repeating in a cycle, as in runaway recursion, are written once with the times they repeat
+pub fn render_backtrace(
+    out: &mut dyn Write,
+    backtrace: &[Frame],
+    source: &str,
+    name: Option<&str>,
+    color: bool,
+) -> io::Result<()> {
+    let style = Style(color);
+    writeln!(
+        out,
+        "{}",
+        style.paint(BOLD, "backtrace, most recent call first:")
+    )?;
+    let mut index = 0;
+    while index < backtrace.len() {
+        // the shortest cycle of calls from here which comes again right after itself
+        let rest = &backtrace[index..];
+        let cycle = (1..=LONGEST_CYCLE.min(rest.len() / 2))
+            .find(|&length| rest[..length] == rest[length..2 * length])
+            .unwrap_or(1);
+        let repeated = rest
+            .chunks_exact(cycle)
+            .skip(1)
+            .take_while(|calls| *calls == &rest[..cycle])
+            .count();
+        for frame in &rest[..cycle] {
+            let (line, column, _) = locate(source, frame.span.start);
+            let location = match name {
+                Some(name) => format!("{}:{}:{}", name, line, column),
+                None => format!("{}:{}", line, column),
+            };
+            writeln!(
+                out,
+                "  {} {} {}",
+                style.paint(BOLD, &frame.procedure),
+                style.paint(BLUE, "at"),
+                location
+            )?;
+        }
+        match (repeated, cycle) {
+            (0, _) => (),
+            (_, 1) => writeln!(out, "  [the call above repeated {} more times]", repeated)?,
+            _ => writeln!(
+                out,
+                "  [the {} calls above repeated {} more times]",
+                cycle, repeated
+            )?,
+        }
+        index += cycle * (repeated + 1);
+    }
+    Ok(())
+}
+
+#[test]
+fn rendering() -> io::Result<()> {
+    use crate::interpreter::{Engine, Interpreter};
+    let interpreter = Interpreter::new();
+    let render_error = |source: &str, name| -> io::Result<String> {
+        let (error, span) = interpreter.eval_source(source).unwrap_err();
+        let mut out = vec![];
+        render(&mut out, &error, span, source, name, false)?;
+        Ok(String::from_utf8(out).unwrap())
+    };
+    assert_eq!(
+        render_error("(define x 1)\n  ; a comment\n  (car x)\n", Some("test.scm"))?,
+        "error: car: expect a pair, got 1
+ --> test.scm:3:3
+  |
+3 |   (car x)
+  |   ^^^^^^^
+"
+    );
+    assert_eq!(
+        render_error("(list 1\n      (car 2))", None)?,
+        "error: car: expect a pair, got 2
+  |
+1 | (list 1
+  | ^^^^^^^
+"
+    );
+    assert_eq!(
+        render_error("(+ 1 2))", None)?,
+        "Syntax error: unmatched closing parenthesis
+  |
+1 | (+ 1 2))
+  |        ^
+  = help: remove the closing parenthesis or add an opening one before it
+"
+    );
+    // an unexpected token is underlined itself, not the end of what was read
+    assert_eq!(
+        render_error("(import (rename (srfi 1) (a 1)))", None)?,
+        "Syntax error: expect an identifier, got Integer(1)
+  |
+1 | (import (rename (srfi 1) (a 1)))
+  |                             ^
+"
+    );
+    assert_eq!(
+        render_error("(display \"λ\" #z)", None)?,
+        "Invalid token: expect '(' 't' or 'f' after #
+  |
+1 | (display \"λ\" #z)
+  |               ^
+"
+    );
+    let mut out = vec![];
+    let error = interpreter.eval_source("(car 1)").unwrap_err().0;
+    render(
+        &mut out,
+        &error,
+        Span { start: 0, end: 7 },
+        "(car 1)",
+        None,
+        true,
+    )?;
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .starts_with("\x1b[1;31merror: "));
+    let source = "(define (f x)\n  (* x 2))\n\n  (f 1 2)\n";
+    let mut out = vec![];
+    Diagnostic::new("f: expect 1 argument, got 2")
+        .label(Span { start: 28, end: 35 }, "called with 2 arguments")
+        .label(Span { start: 8, end: 13 }, "f takes 1 argument")
+        .note("f is defined at line 1")
+        .help("remove the extra argument")
+        .render(&mut out, source, Some("test.scm"), false)?;
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "error: f: expect 1 argument, got 2
+ --> test.scm:4:3
+  |
+1 | (define (f x)
+  |         ----- f takes 1 argument
+4 |   (f 1 2)
+  |   ^^^^^^^ called with 2 arguments
+  = note: f is defined at line 1
+  = help: remove the extra argument
+"
+    );
+    let source = "(define (f n)\n  (if (= n 0) (car n) (+ (f (- n 1)) 1)))\n(display (f 3))\n";
+    let backtrace = |engine| -> io::Result<String> {
+        let interpreter = Interpreter::new();
+        interpreter.engine.set(engine);
+        let error = interpreter.eval_source(source).unwrap_err().0;
+        assert_eq!(error.to_string(), "error: car: expect a pair, got 0");
+        let mut out = vec![];
+        render_backtrace(
+            &mut out,
+            &interpreter.backtrace(),
+            source,
+            Some("test.scm"),
+            false,
+        )?;
+        Ok(String::from_utf8(out).unwrap())
+    };
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        assert_eq!(
+            backtrace(engine)?,
+            "backtrace, most recent call first:
+  car at test.scm:2:15
+  f at test.scm:2:26
+  [the call above repeated 2 more times]
+  f at test.scm:3:10
+"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn json() {
+    use crate::interpreter::Interpreter;
+    let source = "(display 1)\n(car '(1 2)";
+    let (error, span) = Interpreter::new().eval_source(source).unwrap_err();
+    assert_eq!(
+        Diagnostic::from_error(&error, span).to_json(source, Some("test.scm")),
+        r#"{"severity":"error","code":"unterminated-list","message":"unterminated list","file":"test.scm","span":{"start":22,"end":23,"line":2,"column":11},"labels":[{"span":{"start":22,"end":23,"line":2,"column":11}}],"notes":[],"help":["add the missing closing parentheses"]}"#
+    );
+    assert_eq!(
+        Diagnostic::warning("unused variable x")
+            .code("unused-variable")
+            .label(Span { start: 8, end: 9 }, "never used")
+            .note("names starting with _ are not reported")
+            .to_json("(lambda (x) 1)", None),
+        r#"{"severity":"warning","code":"unused-variable","message":"unused variable x","file":null,"span":{"start":8,"end":9,"line":1,"column":9},"labels":[{"span":{"start":8,"end":9,"line":1,"column":9},"message":"never used"}],"notes":["names starting with _ are not reported"],"help":[]}"#
+    );
+}
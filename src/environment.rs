@@ -1,38 +1,337 @@
+use crate::interpreter::gc;
+use crate::interpreter::ruschm;
 use crate::interpreter::scheme;
-use crate::interpreter::ValueType;
+use crate::interpreter::srfi;
+use crate::interpreter::{Procedure, ValueType};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::OnceLock;
 
-pub struct Environment<'a> {
-    parent: Option<&'a Environment<'a>>,
-    definitions: HashMap<String, ValueType>,
+// environments are shared between the frames evaluating in them and the procedures closing over them
+pub struct Environment {
+    parent: Option<Rc<Environment>>,
+    // the variables in the order they were first defined, which compiled code indexes them by
+    variables: RefCell<Vec<(String, ValueType)>>,
+    // the index of each variable by name, kept by the top level environment only, the frames
+    // of procedures having few variables to search
+    index: Option<RefCell<HashMap<String, usize>>>,
+    // the deferred libraries not defined yet, a bit for each
+    deferred: Cell<u32>,
+    // how many times a builtin of the outermost environment was defined again, which code
+    // folding the calls of builtins checks
+    redefined: Cell<u32>,
 }
 
-impl<'a> Environment<'a> {
+impl fmt::Debug for Environment {
+    // an environment may hold procedures closing over itself, so it is never printed recursively
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<environment>")
+    }
+}
+
+impl PartialEq for Environment {
+    fn eq(&self, rhs: &Self) -> bool {
+        std::ptr::eq(self, rhs)
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Library = fn() -> HashMap<String, ValueType>;
+
+// the libraries beyond those of r7rs, which a top level environment defines when one of their
+// names is first looked up or they are imported, rather than making all their procedures for
+// every interpreter
+const DEFERRED: &[(&str, Library)] = &[
+    ("(srfi 1)", srfi::srfi_1::srfi_1_library),
+    ("(srfi 19)", srfi::srfi_19::srfi_19_library),
+    ("(srfi 27)", srfi::srfi_27::srfi_27_library),
+    ("(srfi 28)", srfi::srfi_28::srfi_28_library),
+    ("(srfi 41)", srfi::srfi_41::srfi_41_library),
+    ("(srfi 69)", srfi::srfi_69::srfi_69_library),
+    ("(srfi 113)", srfi::srfi_113::srfi_113_library),
+    ("(srfi 128)", srfi::srfi_128::srfi_128_library),
+    ("(srfi 151)", srfi::srfi_151::srfi_151_library),
+    ("(srfi 158)", srfi::srfi_158::srfi_158_library),
+    ("(srfi 132)", srfi::srfi_132::srfi_132_library),
+    ("(ruschm digest)", ruschm::digest::digest_library),
+    ("(ruschm display)", ruschm::display::display_library),
+    ("(ruschm encoding)", ruschm::encoding::encoding_library),
+    ("(ruschm http)", ruschm::http::http_library),
+    ("(ruschm image)", ruschm::image::image_library),
+    ("(ruschm json)", ruschm::json::json_library),
+    ("(ruschm log)", ruschm::log::log_library),
+    ("(ruschm memoize)", ruschm::memoize::memoize_library),
+    ("(ruschm parallel)", ruschm::parallel::parallel_library),
+    ("(ruschm path)", ruschm::path::path_library),
+    ("(ruschm process)", ruschm::process::process_library),
+    ("(ruschm regexp)", ruschm::regexp::regexp_library),
+    ("(ruschm socket)", ruschm::socket::socket_library),
+    (
+        "(ruschm statistics)",
+        ruschm::statistics::statistics_library,
+    ),
+];
+
+// the deferred library defining each name, the last for a name defined by several as when
+// they were all defined in order
+fn deferred_names() -> &'static HashMap<String, usize> {
+    static NAMES: OnceLock<HashMap<String, usize>> = OnceLock::new();
+    NAMES.get_or_init(|| {
+        let mut names = HashMap::new();
+        for (library, (_, definitions)) in DEFERRED.iter().enumerate() {
+            names.extend(definitions().into_keys().map(|name| (name, library)));
+        }
+        names
+    })
+}
+
+impl Environment {
     pub fn new() -> Self {
+        let env = Self::empty();
+        for (name, value) in scheme::base::base_library()
+            .into_iter()
+            .chain(scheme::char::char_library())
+            .chain(scheme::cxr::cxr_library())
+            .chain(scheme::file::file_library())
+            .chain(scheme::inexact::inexact_library())
+            .chain(scheme::lazy::lazy_library())
+            .chain(scheme::process_context::process_context_library())
+            .chain(scheme::read::read_library())
+            .chain(scheme::time::time_library())
+            .chain(scheme::write::write_library())
+        {
+            env.define(name, value);
+        }
+        env.deferred.set((1 << DEFERRED.len()) - 1);
+        env
+    }
+
+    // an environment of nothing, not even the builtins
+    pub(crate) fn empty() -> Self {
         Self {
             parent: None,
-            definitions: scheme::base::base_library(),
+            variables: RefCell::new(vec![]),
+            index: Some(RefCell::new(HashMap::new())),
+            deferred: Cell::new(0),
+            redefined: Cell::new(0),
         }
     }
 
-    pub fn child(parent: &'a Environment<'a>) -> Self {
-        Self {
+    pub fn child(parent: Rc<Environment>) -> Self {
+        let env = Self {
             parent: Some(parent),
-            definitions: HashMap::new(),
+            variables: RefCell::new(vec![]),
+            index: None,
+            deferred: Cell::new(0),
+            redefined: Cell::new(0),
+        };
+        gc::allocated(&env, 0);
+        env
+    }
+
+    // the index of a variable, defining the deferred library it is in first
+    fn position(&self, name: &str) -> Option<usize> {
+        self.defined(name).or_else(|| {
+            if self.deferred.get() == 0 {
+                return None;
+            }
+            let library = *deferred_names().get(name)?;
+            self.import_deferred(library);
+            self.defined(name)
+        })
+    }
+
+    fn defined(&self, name: &str) -> Option<usize> {
+        match &self.index {
+            Some(index) => index.borrow().get(name).cloned(),
+            None => self
+                .variables
+                .borrow()
+                .iter()
+                .position(|(variable, _)| variable == name),
         }
     }
 
-    pub fn define(&mut self, name: String, value: ValueType) {
-        self.definitions.insert(name, value);
+    // defines the names of a deferred library not defined yet, but those the program defined
+    // already, as a library defined at startup would be defined again by the program
+    fn import_deferred(&self, library: usize) {
+        let bit = 1 << library;
+        if self.deferred.get() & bit == 0 {
+            return;
+        }
+        self.deferred.set(self.deferred.get() & !bit);
+        let names = deferred_names();
+        for (name, value) in (DEFERRED[library].1)() {
+            if names.get(&name) == Some(&library) && self.defined(&name).is_none() {
+                self.define(name, value);
+            }
+        }
+    }
+
+    // defines the library of an import if it is deferred, the others being defined already
+    pub fn import(&self, library: &str) {
+        if let Some(library) = DEFERRED.iter().position(|(name, _)| *name == library) {
+            self.import_deferred(library);
+        }
+    }
+
+    // a variable defined again keeps its index
+    pub fn define(&self, name: String, value: ValueType) {
+        match self.defined(&name) {
+            Some(position) => {
+                let mut variables = self.variables.borrow_mut();
+                let builtin = matches!(
+                    variables[position].1,
+                    ValueType::Procedure(Procedure::Buildin(_))
+                );
+                if builtin && self.parent.is_none() {
+                    self.redefined.set(self.redefined.get().wrapping_add(1));
+                }
+                variables[position].1 = value;
+            }
+            None => {
+                let mut variables = self.variables.borrow_mut();
+                if let Some(index) = &self.index {
+                    index.borrow_mut().insert(name.clone(), variables.len());
+                }
+                variables.push((name, value));
+            }
+        }
     }
 
-    pub fn get(&self, name: &str) -> Option<&ValueType> {
-        match self.definitions.get(name) {
-            None => match self.parent {
-                None => return None,
+    pub fn redefinitions(&self) -> u32 {
+        match &self.parent {
+            Some(parent) => parent.redefinitions(),
+            None => self.redefined.get(),
+        }
+    }
+
+    // the names bound here or in an enclosing environment, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .variables
+            .borrow()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        let deferred = self.deferred.get();
+        names.extend(
+            deferred_names()
+                .iter()
+                .filter(|(_, library)| deferred & 1 << **library != 0)
+                .map(|(name, _)| name.clone()),
+        );
+        if let Some(parent) = &self.parent {
+            names.extend(parent.names());
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // the variables of this environment, not those around it, in the order they were defined
+    pub(crate) fn variables(&self) -> Vec<(String, ValueType)> {
+        self.variables.borrow().clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<ValueType> {
+        match self.position(name) {
+            None => match &self.parent {
+                None => None,
                 Some(parent) => parent.get(name),
             },
-            value => value,
+            Some(position) => Some(self.variables.borrow()[position].1.clone()),
+        }
+    }
+
+    // the environment depth levels up, or the outermost of fewer
+    pub fn ancestor(self: &Rc<Self>, depth: usize) -> &Rc<Environment> {
+        let mut env = self;
+        for _ in 0..depth {
+            match &env.parent {
+                Some(parent) => env = parent,
+                None => break,
+            }
+        }
+        env
+    }
+
+    // looks a variable up at the index it was found at before, which it keeps however it is
+    // defined again, the cache holding usize::MAX until it is found
+    // only the outermost environment is cached, a variable of another may be shadowed later
+    pub fn get_cached(&self, name: &str, cache: &Cell<usize>) -> Option<ValueType> {
+        if self.parent.is_some() {
+            return self.get(name);
+        }
+        if let Some((_, value)) = self.variables.borrow().get(cache.get()) {
+            return Some(value.clone());
         }
+        let position = self.position(name)?;
+        cache.set(position);
+        Some(self.variables.borrow()[position].1.clone())
+    }
+
+    pub(crate) fn parent(&self) -> Option<&Rc<Environment>> {
+        self.parent.as_ref()
+    }
+
+    // visits the values of the variables for the garbage collector, returning false when they
+    // are being defined
+    pub(crate) fn trace(&self, mut visit: impl FnMut(&ValueType)) -> bool {
+        match self.variables.try_borrow() {
+            Ok(variables) => {
+                variables.iter().for_each(|(_, value)| visit(value));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // undefines every variable, returning their values
+    pub(crate) fn clear(&self) -> Vec<ValueType> {
+        self.deferred.set(0);
+        if let Some(index) = &self.index {
+            index.borrow_mut().clear();
+        }
+        let variables = std::mem::take(&mut *self.variables.borrow_mut());
+        variables.into_iter().map(|(_, value)| value).collect()
+    }
+
+    // the variable at the index of the environment depth levels up, none if it is not defined
+    // yet
+    pub fn get_at(&self, depth: usize, index: usize) -> Option<ValueType> {
+        let mut env = self;
+        for _ in 0..depth {
+            env = env.parent.as_ref()?;
+        }
+        let variables = env.variables.borrow();
+        variables.get(index).map(|(_, value)| value.clone())
+    }
+}
+
+#[test]
+fn deferred_libraries() {
+    let env = Environment::new();
+    // a name of both would have the value of the deferred library if all were defined at startup
+    let core: Vec<String> = env.variables().into_iter().map(|(name, _)| name).collect();
+    for name in &core {
+        assert!(deferred_names().get(name).is_none(), "{}", name);
     }
+    assert!(env.names().contains(&"json-read".to_string()));
+    assert!(env.defined("json-read").is_none());
+    env.define("json-write".to_string(), ValueType::Boolean(true));
+    assert!(env.get("json-read").is_some());
+    assert_eq!(env.get("json-write"), Some(ValueType::Boolean(true)));
+    assert!(env.defined("regexp-match?").is_none());
+    env.import("(ruschm regexp)");
+    assert!(env.defined("regexp-match?").is_some());
+    let child = Environment::child(Rc::new(env));
+    assert!(child.get("iota").is_some());
 }
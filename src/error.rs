@@ -1,10 +1,154 @@
+use crate::interpreter::ValueType;
+use crate::lexer::Token;
+use crate::parser::Datum;
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ErrorType {
     Lexical,
-    Syntax,
+    Syntax(SyntaxError),
     Logic,
+    // failures opening or accessing files, distinguished for file-error?
+    File,
+    // an object raised by raise or error, which guard hands back to scheme code
+    // boxed for errors to stay small, they are returned by every evaluation
+    Raise(Box<ValueType>),
+    // a request to exit with the code, handed to the host instead of exiting the process
+    Exit(i32),
+}
+
+// what is wrong with a form the parser rejects, for callers to match on rather than the message
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxError {
+    // a token where another kind of thing was expected, None for the end of input, with the
+    // span of the token when the parser is given the spans of the tokens
+    UnexpectedToken {
+        expected: Expected,
+        found: Option<Token>,
+        span: Option<Span>,
+    },
+    // the input ended inside a list, vector or pattern
+    UnterminatedList,
+    // the input ended before a datum, e.g. after a quote
+    UnexpectedEnd,
+    // a closing parenthesis without an opening one
+    UnmatchedParenthesis,
+    // a datum which is read but not allowed, e.g. 256 in a bytevector
+    InvalidDatum(Datum),
+    // a definition or import where an expression is needed
+    NotAnExpression,
+    // ()
+    EmptyCall,
+    // a lambda or define body with no expression, or with a definition after one
+    InvalidBody,
+    // a special form whose parts are not the ones it takes, named by its keyword
+    InvalidForm(String),
+}
+
+// what the parser expected where it found an unexpected token
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Expected {
+    Expression,
+    Identifier,
+    // a part of a library name
+    IdentifierOrExactInteger,
+    // (ident1 ident2), as in a rename
+    IdentifierPair,
+    Datum,
+    ClosingParenthesis,
+    Formals,
+    GuardClause,
+    MatchClause,
+    CondExpandClause,
+    LetValuesBindings,
+    LetValuesBinding,
+    ImportSet,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Expected::Expression => "an expression",
+                Expected::Identifier => "an identifier",
+                Expected::IdentifierOrExactInteger => "an identifier or exact integer",
+                Expected::IdentifierPair => "an identifier pair",
+                Expected::Datum => "a datum",
+                Expected::ClosingParenthesis => "a closing parenthesis",
+                Expected::Formals => "formals",
+                Expected::GuardClause => "a guard clause",
+                Expected::MatchClause => "a match clause",
+                Expected::CondExpandClause => "a cond-expand clause",
+                Expected::LetValuesBindings => "let-values bindings",
+                Expected::LetValuesBinding => "a let-values binding",
+                Expected::ImportSet => "an import set",
+            }
+        )
+    }
+}
+
+// a range of character offsets into a source text
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+// where a procedure was written, as a line of a source and the name of the source if any
+#[derive(Debug, PartialEq, Clone)]
+pub struct Origin {
+    pub name: Option<String>,
+    pub line: usize,
+    // the spans of the formals and of the names the body defines, for warnings about them
+    pub formals: Vec<Span>,
+    pub definitions: Vec<Span>,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}:{}", name, self.line),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
+// the lines of a source text, to tell the origin of what is read at an offset
+#[derive(Debug, PartialEq)]
+pub struct SourceLines {
+    name: Option<String>,
+    // the offsets of the first characters of the lines
+    starts: Vec<usize>,
+}
+
+impl SourceLines {
+    pub fn new(source: &str, name: Option<&str>) -> Self {
+        let breaks = source.chars().enumerate().filter(|(_, c)| *c == '\n');
+        Self {
+            name: name.map(str::to_string),
+            starts: std::iter::once(0)
+                .chain(breaks.map(|(offset, _)| offset + 1))
+                .collect(),
+        }
+    }
+
+    pub fn origin(&self, offset: usize) -> Origin {
+        Origin {
+            name: self.name.clone(),
+            line: self.starts.partition_point(|&start| start <= offset),
+            formals: vec![],
+            definitions: vec![],
+        }
+    }
+}
+
+// a call an error was raised in, named by its procedure, with the span of the call expression
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    pub procedure: String,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq)]
@@ -15,10 +159,203 @@ pub struct Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.heading() {
+            Some(heading) => write!(f, "{}: {}", heading, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl SyntaxError {
+    // a name for the kind, as tools reading diagnostics match on
+    pub fn code(&self) -> &'static str {
+        match self {
+            SyntaxError::UnexpectedToken { .. } => "unexpected-token",
+            SyntaxError::UnterminatedList => "unterminated-list",
+            SyntaxError::UnexpectedEnd => "unexpected-end",
+            SyntaxError::UnmatchedParenthesis => "unmatched-parenthesis",
+            SyntaxError::InvalidDatum(_) => "invalid-datum",
+            SyntaxError::NotAnExpression => "not-an-expression",
+            SyntaxError::EmptyCall => "empty-call",
+            SyntaxError::InvalidBody => "invalid-body",
+            SyntaxError::InvalidForm(_) => "invalid-form",
+        }
+    }
+
+    // the span of the offending token, when it is known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            SyntaxError::UnexpectedToken { span, .. } => *span,
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    // what the message is shown after, none for a request to exit
+    pub fn heading(&self) -> Option<&'static str> {
         match self.category {
-            ErrorType::Lexical => write!(f, "Invalid token: {}", self.message),
-            ErrorType::Syntax => write!(f, "Syntax error: {}", self.message),
-            ErrorType::Logic => write!(f, "error: {}", self.message),
+            ErrorType::Lexical => Some("Invalid token"),
+            ErrorType::Syntax(_) => Some("Syntax error"),
+            ErrorType::Logic | ErrorType::Raise(_) => Some("error"),
+            ErrorType::File => Some("file error"),
+            ErrorType::Exit(_) => None,
+        }
+    }
+
+    // a name for the kind of error, as tools reading diagnostics match on
+    pub fn code(&self) -> &'static str {
+        match &self.category {
+            ErrorType::Lexical => "invalid-token",
+            ErrorType::Syntax(kind) => kind.code(),
+            ErrorType::Logic => "error",
+            ErrorType::File => "file-error",
+            ErrorType::Raise(_) => "raise",
+            ErrorType::Exit(_) => "exit",
         }
     }
+
+    // the exit code an embedding host should exit with, if scheme code called exit
+    pub fn exit_code(&self) -> Option<i32> {
+        match self.category {
+            ErrorType::Exit(code) => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    // a syntax error has its kind as the source, for hosts walking the chain
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.category {
+            ErrorType::Syntax(kind) => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyntaxError::UnexpectedToken {
+                expected,
+                found: Some(token),
+                ..
+            } => write!(f, "expect {}, got {}", expected, token),
+            SyntaxError::UnexpectedToken {
+                expected,
+                found: None,
+                ..
+            } => write!(f, "expect {}, got the end of input", expected),
+            SyntaxError::UnterminatedList => write!(f, "unterminated list"),
+            SyntaxError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            SyntaxError::UnmatchedParenthesis => write!(f, "unmatched closing parenthesis"),
+            SyntaxError::InvalidDatum(datum) => write!(f, "invalid datum {}", datum),
+            SyntaxError::NotAnExpression => write!(f, "expect an expression"),
+            SyntaxError::EmptyCall => write!(f, "empty procedure call"),
+            SyntaxError::InvalidBody => write!(f, "invalid procedure body"),
+            SyntaxError::InvalidForm(keyword) => write!(f, "invalid {} form", keyword),
+        }
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+// a syntax error of the kind, whose message is the kind's
+impl From<SyntaxError> for Error {
+    fn from(kind: SyntaxError) -> Self {
+        Self {
+            message: kind.to_string(),
+            category: ErrorType::Syntax(kind),
+        }
+    }
+}
+
+// the one error type of the crate for embedders, telling reading from running a program
+// the error it wraps is its source
+#[derive(Debug, PartialEq)]
+pub enum RuschmError {
+    Lexical(Error),
+    Syntax(Error),
+    // errors of evaluation, including raised objects, file errors and requests to exit
+    Runtime(Error),
+}
+
+impl RuschmError {
+    pub fn error(&self) -> &Error {
+        match self {
+            RuschmError::Lexical(error)
+            | RuschmError::Syntax(error)
+            | RuschmError::Runtime(error) => error,
+        }
+    }
+}
+
+impl fmt::Display for RuschmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error())
+    }
+}
+
+impl std::error::Error for RuschmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error())
+    }
+}
+
+impl From<Error> for RuschmError {
+    fn from(error: Error) -> Self {
+        match error.category {
+            ErrorType::Lexical => RuschmError::Lexical(error),
+            ErrorType::Syntax(_) => RuschmError::Syntax(error),
+            _ => RuschmError::Runtime(error),
+        }
+    }
+}
+
+impl From<RuschmError> for Error {
+    fn from(error: RuschmError) -> Self {
+        match error {
+            RuschmError::Lexical(error)
+            | RuschmError::Syntax(error)
+            | RuschmError::Runtime(error) => error,
+        }
+    }
+}
+
+#[test]
+fn error_chain() {
+    use crate::interpreter::Interpreter;
+    use std::error::Error as _;
+    let interpreter = Interpreter::new();
+    let error = RuschmError::from(interpreter.eval("'(1 2".chars()).unwrap_err());
+    assert!(matches!(error, RuschmError::Syntax(_)));
+    assert_eq!(error.to_string(), "Syntax error: unterminated list");
+    let source = error.source().unwrap();
+    assert_eq!(source.to_string(), "Syntax error: unterminated list");
+    assert_eq!(source.source().unwrap().to_string(), "unterminated list");
+    let error = RuschmError::from(interpreter.eval("#z".chars()).unwrap_err());
+    assert!(matches!(error, RuschmError::Lexical(_)));
+    assert!(error.source().unwrap().source().is_none());
+    // the kind tells what was expected and where the token in its place is
+    let (error, span) = interpreter.eval_source("(lambda 5 x)").unwrap_err();
+    assert_eq!(
+        error.category,
+        ErrorType::Syntax(SyntaxError::UnexpectedToken {
+            expected: Expected::Formals,
+            found: Some(Token::Integer(5)),
+            span: Some(Span { start: 8, end: 9 }),
+        })
+    );
+    assert_eq!(error.message, "expect formals, got Integer(5)");
+    assert_eq!(span, Span { start: 8, end: 9 });
+    let error = RuschmError::from(interpreter.eval("(car 1)".chars()).unwrap_err());
+    assert!(matches!(error, RuschmError::Runtime(_)));
+    assert_eq!(
+        Error::from(error),
+        Error {
+            category: ErrorType::Logic,
+            message: "car: expect a pair, got 1".to_string()
+        }
+    );
 }
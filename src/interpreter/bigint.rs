@@ -0,0 +1,622 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// exact integers of any size, as a sign and a magnitude in base 2^32 with the least significant
+// limb first and no high zero limbs, so zero has no limbs and is never negative
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+fn trim(magnitude: &mut Vec<u32>) {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (index, &limb) in long.iter().enumerate() {
+        let total = limb as u64 + *short.get(index).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+// a must not be less than b
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (index, &limb) in a.iter().enumerate() {
+        let total = limb as i64 - *b.get(index).unwrap_or(&0) as i64 - borrow;
+        difference.push(total as u32);
+        borrow = (total < 0) as i64;
+    }
+    trim(&mut difference);
+    difference
+}
+
+fn multiply_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let total = x as u64 * y as u64 + product[i + j] as u64 + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    trim(&mut product);
+    product
+}
+
+fn divide_magnitude_by_limb(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for (index, &limb) in a.iter().enumerate().rev() {
+        let current = (remainder << 32) | limb as u64;
+        quotient[index] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    trim(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+fn shift_magnitude_left(a: &[u32], bits: usize) -> Vec<u32> {
+    if a.is_empty() {
+        return vec![];
+    }
+    let (limbs, bits) = (bits / 32, (bits % 32) as u32);
+    let mut shifted = vec![0u32; limbs];
+    match bits {
+        0 => shifted.extend_from_slice(a),
+        _ => {
+            let mut carry = 0u32;
+            for &limb in a {
+                shifted.push((limb << bits) | carry);
+                carry = limb >> (32 - bits);
+            }
+            shifted.push(carry);
+        }
+    }
+    trim(&mut shifted);
+    shifted
+}
+
+fn shift_magnitude_right(a: &[u32], bits: usize) -> Vec<u32> {
+    let (limbs, bits) = (bits / 32, (bits % 32) as u32);
+    if limbs >= a.len() {
+        return vec![];
+    }
+    let a = &a[limbs..];
+    let mut shifted = match bits {
+        0 => a.to_vec(),
+        _ => (0..a.len())
+            .map(|index| {
+                let high = a.get(index + 1).map_or(0, |&limb| limb << (32 - bits));
+                (a[index] >> bits) | high
+            })
+            .collect(),
+    };
+    trim(&mut shifted);
+    shifted
+}
+
+// long division as in knuth's algorithm d, the divisor having two limbs at least
+fn divide_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if compare_magnitudes(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = divide_magnitude_by_limb(a, b[0]);
+        return (
+            quotient,
+            if remainder == 0 {
+                vec![]
+            } else {
+                vec![remainder]
+            },
+        );
+    }
+    // the divisor is shifted so that its high limb has its high bit set
+    let shift = b[b.len() - 1].leading_zeros() as usize;
+    let divisor = shift_magnitude_left(b, shift);
+    let mut remainder = shift_magnitude_left(a, shift);
+    remainder.resize(a.len() + 1, 0);
+    let n = divisor.len();
+    let m = a.len() - n;
+    let (high, next) = (divisor[n - 1] as u64, divisor[n - 2] as u64);
+    let mut quotient = vec![0u32; m + 1];
+    for j in (0..=m).rev() {
+        let top = ((remainder[j + n] as u64) << 32) | remainder[j + n - 1] as u64;
+        let (mut estimate, mut rest) = (top / high, top % high);
+        while estimate >> 32 != 0 || estimate * next > ((rest << 32) | remainder[j + n - 2] as u64)
+        {
+            estimate -= 1;
+            rest += high;
+            if rest >> 32 != 0 {
+                break;
+            }
+        }
+        let (mut borrow, mut carry) = (0i64, 0u64);
+        for i in 0..n {
+            let product = estimate * divisor[i] as u64 + carry;
+            carry = product >> 32;
+            let total = remainder[i + j] as i64 - borrow - (product & 0xffff_ffff) as i64;
+            remainder[i + j] = total as u32;
+            borrow = (total < 0) as i64;
+        }
+        let total = remainder[j + n] as i64 - borrow - carry as i64;
+        remainder[j + n] = total as u32;
+        // the estimate was one too large, the divisor is added back
+        if total < 0 {
+            estimate -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = remainder[i + j] as u64 + divisor[i] as u64 + carry;
+                remainder[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            remainder[j + n] = remainder[j + n].wrapping_add(carry as u32);
+        }
+        quotient[j] = estimate as u32;
+    }
+    trim(&mut quotient);
+    remainder.truncate(n);
+    (quotient, shift_magnitude_right(&remainder, shift))
+}
+
+// the limbs of the two's complement of a number, with the sign bit repeated up to length limbs
+fn twos_complement(n: &BigInt, length: usize) -> Vec<u32> {
+    let mut limbs = n.magnitude.clone();
+    limbs.resize(length, 0);
+    if n.negative {
+        let mut carry = true;
+        for limb in limbs.iter_mut() {
+            let (sum, overflow) = (!*limb).overflowing_add(carry as u32);
+            *limb = sum;
+            carry = overflow;
+        }
+    }
+    limbs
+}
+
+fn from_twos_complement(mut limbs: Vec<u32>) -> BigInt {
+    let negative = limbs.last().is_some_and(|&limb| limb >> 31 == 1);
+    if negative {
+        let mut carry = true;
+        for limb in limbs.iter_mut() {
+            let (sum, overflow) = (!*limb).overflowing_add(carry as u32);
+            *limb = sum;
+            carry = overflow;
+        }
+    }
+    BigInt::new(negative, limbs)
+}
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        trim(&mut magnitude);
+        Self {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::new(false, vec![])
+    }
+
+    pub fn one() -> Self {
+        Self::new(false, vec![1])
+    }
+
+    pub fn from_i128(n: i128) -> Self {
+        let magnitude = n.unsigned_abs();
+        Self::new(
+            n < 0,
+            vec![
+                magnitude as u32,
+                (magnitude >> 32) as u32,
+                (magnitude >> 64) as u32,
+                (magnitude >> 96) as u32,
+            ],
+        )
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |n, &limb| (n << 32) | limb as u64);
+        match self.negative {
+            true if magnitude <= i64::MIN.unsigned_abs() => Some((magnitude as i64).wrapping_neg()),
+            false if magnitude <= i64::MAX as u64 => Some(magnitude as i64),
+            _ => None,
+        }
+    }
+
+    // the 64 high bits of the number as a float and the power of two scaling them to it, for
+    // the logarithms and roots of numbers beyond the range of floats
+    pub fn to_scaled_f64(&self) -> (f64, u64) {
+        let shift = self.bits().saturating_sub(64);
+        (self.shift_right(shift as usize).to_f64(), shift)
+    }
+
+    // rounds to the nearest float, big integers becoming infinite
+    pub fn to_f64(&self) -> f64 {
+        let bits = self.bits();
+        let magnitude = match bits {
+            0..=64 => self
+                .magnitude
+                .iter()
+                .rev()
+                .fold(0u64, |n, &limb| (n << 32) | limb as u64) as f64,
+            _ => {
+                // the 64 high bits, the lowest of them set when a bit below them is, round as the
+                // whole magnitude does
+                let shift = (bits - 64) as usize;
+                let high = shift_magnitude_right(&self.magnitude, shift);
+                let high = high
+                    .iter()
+                    .rev()
+                    .fold(0u64, |n, &limb| (n << 32) | limb as u64);
+                let sticky = self.trailing_zeros() < shift as u64;
+                let rounded = (high | sticky as u64) as f64;
+                match shift > 1100 {
+                    true => f64::INFINITY,
+                    false => rounded * 2f64.powi(shift as i32),
+                }
+            }
+        };
+        match self.negative {
+            true => -magnitude,
+            false => magnitude,
+        }
+    }
+
+    // the exact value of a float without a fraction
+    pub fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() || n.fract() != 0.0 {
+            return None;
+        }
+        let bits = n.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        let mantissa = match exponent {
+            0 => (bits & ((1 << 52) - 1)) << 1,
+            _ => (bits & ((1 << 52) - 1)) | (1 << 52),
+        };
+        let magnitude = Self::from_i128(mantissa as i128);
+        let magnitude = match exponent - 1075 {
+            shift if shift >= 0 => magnitude.shift_left(shift as usize),
+            shift => magnitude.shift_right(shift.unsigned_abs() as usize),
+        };
+        Some(match n < 0.0 {
+            true => magnitude.negate(),
+            false => magnitude,
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.magnitude.first().is_none_or(|limb| limb & 1 == 0)
+    }
+
+    // the number of bits of the magnitude
+    pub fn bits(&self) -> u64 {
+        match self.magnitude.last() {
+            Some(high) => self.magnitude.len() as u64 * 32 - high.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    pub fn trailing_zeros(&self) -> u64 {
+        match self.magnitude.iter().position(|&limb| limb != 0) {
+            Some(index) => index as u64 * 32 + self.magnitude[index].trailing_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    pub fn count_ones(&self) -> u64 {
+        self.magnitude
+            .iter()
+            .map(|limb| limb.count_ones() as u64)
+            .sum()
+    }
+
+    pub fn negate(&self) -> Self {
+        Self::new(!self.negative, self.magnitude.clone())
+    }
+
+    pub fn abs(&self) -> Self {
+        Self::new(false, self.magnitude.clone())
+    }
+
+    pub fn add(&self, rhs: &Self) -> Self {
+        match self.negative == rhs.negative {
+            true => Self::new(
+                self.negative,
+                add_magnitudes(&self.magnitude, &rhs.magnitude),
+            ),
+            false => match compare_magnitudes(&self.magnitude, &rhs.magnitude) {
+                Ordering::Less => Self::new(
+                    rhs.negative,
+                    subtract_magnitudes(&rhs.magnitude, &self.magnitude),
+                ),
+                _ => Self::new(
+                    self.negative,
+                    subtract_magnitudes(&self.magnitude, &rhs.magnitude),
+                ),
+            },
+        }
+    }
+
+    pub fn subtract(&self, rhs: &Self) -> Self {
+        self.add(&rhs.negate())
+    }
+
+    pub fn multiply(&self, rhs: &Self) -> Self {
+        Self::new(
+            self.negative != rhs.negative,
+            multiply_magnitudes(&self.magnitude, &rhs.magnitude),
+        )
+    }
+
+    // truncated division, the remainder has the sign of the dividend; the divisor is not zero
+    pub fn divide(&self, rhs: &Self) -> (Self, Self) {
+        let (quotient, remainder) = divide_magnitudes(&self.magnitude, &rhs.magnitude);
+        (
+            Self::new(self.negative != rhs.negative, quotient),
+            Self::new(self.negative, remainder),
+        )
+    }
+
+    // floored division, the modulo has the sign of the divisor
+    pub fn divide_floor(&self, rhs: &Self) -> (Self, Self) {
+        let (quotient, remainder) = self.divide(rhs);
+        match !remainder.is_zero() && remainder.negative != rhs.negative {
+            true => (quotient.subtract(&Self::one()), remainder.add(rhs)),
+            false => (quotient, remainder),
+        }
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> Self {
+        let (mut base, mut power) = (self.clone(), Self::one());
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                power = power.multiply(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.multiply(&base);
+            }
+        }
+        power
+    }
+
+    // never negative
+    pub fn gcd(&self, rhs: &Self) -> Self {
+        let (mut a, mut b) = (self.abs(), rhs.abs());
+        while !b.is_zero() {
+            let (_, remainder) = a.divide(&b);
+            a = b;
+            b = remainder;
+        }
+        a
+    }
+
+    // the largest integer whose square is not greater than the number, which is not negative
+    pub fn sqrt(&self) -> Self {
+        if self.is_zero() {
+            return Self::zero();
+        }
+        // newton's iteration from above converges down to the root
+        let mut root = Self::one().shift_left(self.bits().div_ceil(2) as usize);
+        loop {
+            let (quotient, _) = self.divide(&root);
+            let next = root.add(&quotient).shift_right(1);
+            if next >= root {
+                return root;
+            }
+            root = next;
+        }
+    }
+
+    pub fn shift_left(&self, bits: usize) -> Self {
+        Self::new(self.negative, shift_magnitude_left(&self.magnitude, bits))
+    }
+
+    // rounds towards negative infinity, as an arithmetic shift of the two's complement does
+    pub fn shift_right(&self, bits: usize) -> Self {
+        let shifted = Self::new(self.negative, shift_magnitude_right(&self.magnitude, bits));
+        match self.negative && self.trailing_zeros() < bits as u64 {
+            true => shifted.subtract(&Self::one()),
+            false => shifted,
+        }
+    }
+
+    // applies an operation to the limbs of the two's complements of both numbers
+    pub fn bitwise(&self, rhs: &Self, operation: fn(u32, u32) -> u32) -> Self {
+        let length = self.magnitude.len().max(rhs.magnitude.len()) + 1;
+        let (a, b) = (twos_complement(self, length), twos_complement(rhs, length));
+        from_twos_complement(a.iter().zip(b).map(|(&a, b)| operation(a, b)).collect())
+    }
+
+    pub fn not(&self) -> Self {
+        self.negate().subtract(&Self::one())
+    }
+
+    pub fn to_string_radix(&self, radix: u32) -> String {
+        // as many digits as a limb holds are divided off at a time
+        let (chunk, width) = (1..)
+            .map(|width| (radix as u64).pow(width))
+            .zip(1..)
+            .take_while(|(chunk, _)| *chunk <= u32::MAX as u64)
+            .last()
+            .unwrap();
+        let mut chunks = vec![];
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = divide_magnitude_by_limb(&magnitude, chunk as u32);
+            chunks.push(remainder);
+            magnitude = quotient;
+        }
+        let mut digits = String::new();
+        if self.negative {
+            digits.push('-');
+        }
+        match chunks.pop() {
+            Some(high) => digits.push_str(&integer_to_radix(high, radix, 0)),
+            None => digits.push('0'),
+        }
+        for chunk in chunks.iter().rev() {
+            digits.push_str(&integer_to_radix(*chunk, radix, width));
+        }
+        digits
+    }
+
+    // digits of the radix, without a sign
+    pub fn parse(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut magnitude = vec![];
+        for c in digits.chars() {
+            let digit = c.to_digit(radix)?;
+            let mut carry = digit as u64;
+            for limb in magnitude.iter_mut() {
+                let total = *limb as u64 * radix as u64 + carry;
+                *limb = total as u32;
+                carry = total >> 32;
+            }
+            if carry > 0 {
+                magnitude.push(carry as u32);
+            }
+        }
+        Some(Self::new(false, magnitude))
+    }
+
+    // the sign followed by the limbs, as images and compiled code save it
+    pub fn to_parts(&self) -> (bool, &[u32]) {
+        (self.negative, &self.magnitude)
+    }
+
+    pub fn from_parts(negative: bool, magnitude: Vec<u32>) -> Self {
+        Self::new(negative, magnitude)
+    }
+}
+
+// the digits of a limb, padded with zeros to width
+fn integer_to_radix(mut n: u32, radix: u32, width: usize) -> String {
+    let mut digits = vec![];
+    while n > 0 || digits.is_empty() {
+        digits.push(std::char::from_digit(n % radix, radix).unwrap());
+        n /= radix;
+    }
+    while digits.len() < width {
+        digits.push('0');
+    }
+    digits.iter().rev().collect()
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_string_radix(10))
+    }
+}
+
+#[test]
+fn big_arithmetic() {
+    let big = |text: &str| match text.strip_prefix('-') {
+        Some(digits) => BigInt::parse(digits, 10).unwrap().negate(),
+        None => BigInt::parse(text, 10).unwrap(),
+    };
+    let a = big("123456789012345678901234567890");
+    let b = big("-987654321098765432109876543210987");
+    assert_eq!(a.add(&b), big("-987530864309753086430975308643097"));
+    assert_eq!(
+        a.multiply(&b),
+        big("-121932631137021795226185032733744775182992648986186782045407430")
+    );
+    assert_eq!(
+        b.divide(&a),
+        (big("-8000"), big("-9000000000900000000090987"))
+    );
+    assert_eq!(
+        b.divide_floor(&a),
+        (big("-8001"), big("123447789012344778901234476903"))
+    );
+    assert_eq!(a.gcd(&big("30")), big("30"));
+    assert_eq!(
+        big("1000000000000000000000000000000").sqrt(),
+        big("1000000000000000")
+    );
+    assert_eq!(a.sqrt(), big("351364182882014"));
+    assert_eq!(a.to_string_radix(16), "18ee90ff6c373e0ee4e3f0ad2");
+    assert_eq!(
+        BigInt::parse("18ee90ff6c373e0ee4e3f0ad2", 16),
+        Some(a.clone())
+    );
+    assert_eq!(BigInt::from_i128(i64::MIN as i128).to_i64(), Some(i64::MIN));
+    assert_eq!(BigInt::from_i128(i64::MAX as i128 + 1).to_i64(), None);
+    assert_eq!(a.to_f64(), 1.2345678901234568e29);
+    assert_eq!(
+        BigInt::from_f64(-1e30),
+        Some(big("-1000000000000000019884624838656"))
+    );
+    assert_eq!(BigInt::one().shift_left(100).to_f64(), 2f64.powi(100));
+    // shifts and bitwise operations act on the two's complement
+    assert_eq!(big("-5").shift_right(1), big("-3"));
+    assert_eq!(big("-1").shift_right(200), big("-1"));
+    assert_eq!(
+        big("-1").shift_left(64).bitwise(&a, |a, b| a & b),
+        big("123456788998261831127397302272")
+    );
+    assert_eq!(big("-1").bitwise(&big("5"), |a, b| a ^ b), big("-6"));
+    assert_eq!(big("5").not(), big("-6"));
+}
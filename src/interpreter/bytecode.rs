@@ -0,0 +1,1041 @@
+use crate::error::{Origin, Span};
+use crate::interpreter::bigint::BigInt;
+use crate::interpreter::compiler::*;
+use crate::interpreter::number::MAX_INTEGER_BITS;
+use crate::interpreter::optimizer::Optimizer;
+use crate::interpreter::ruschm::digest::sha256;
+use crate::interpreter::*;
+use std::convert::TryFrom;
+
+// a program compiled ahead of time, as written by ruschm compile to a .rbc file: the mark and
+// the version of the format, the version of ruschm, the name and source of the program and
+// its hash, then the top level forms compiled and the hash of all that comes before
+// the header up to the hash is laid out the same in every version, for the source to be run
+// when the code was compiled by another version
+const MARK: &[u8; 4] = b"\0rbc";
+pub const FORMAT_VERSION: u64 = 6;
+const CHECKSUM: usize = 32;
+
+// the top level forms of a program with their spans in the source
+pub type Forms = Vec<(Rc<Code>, Span)>;
+
+pub struct Program {
+    pub name: Option<String>,
+    pub source: String,
+    pub hash: [u8; 32],
+    // none when compiled by another version of ruschm
+    pub forms: Option<Forms>,
+}
+
+#[cold]
+fn invalid() -> Error {
+    Error {
+        category: ErrorType::File,
+        message: "invalid bytecode file".to_string(),
+    }
+}
+
+pub fn hash(source: &str) -> [u8; 32] {
+    sha256(source.as_bytes())
+}
+
+// compiles the top level forms of a source without running them, as the interpreter would
+// compile them in turn, a builtin defined again not being folded in the forms after
+pub fn compile(
+    interpreter: &Interpreter,
+    source: &str,
+    name: Option<&str>,
+) -> std::result::Result<Vec<u8>, (Error, Span)> {
+    let mut optimizer = Optimizer::new(
+        interpreter.optimization_level.get(),
+        interpreter,
+        &interpreter.env,
+    );
+    let mut forms = vec![];
+    read_source(source, name, |statement, span| {
+        forms.push((
+            compiler::compile_statement(&statement, optimizer.clone())?,
+            span,
+        ));
+        for defined in defined(&statement) {
+            optimizer.bind(defined, None);
+        }
+        Ok(())
+    })?;
+    let mut writer = Writer::default();
+    writer.bytes.extend_from_slice(MARK);
+    writer.number(FORMAT_VERSION);
+    writer.string(env!("CARGO_PKG_VERSION"));
+    writer.option(&name, |w, name| w.string(name));
+    writer.string(source);
+    writer.bytes.extend_from_slice(&hash(source));
+    writer
+        .sequence(&forms, |w, (code, span)| {
+            w.code(code)?;
+            w.span(span);
+            Ok(())
+        })
+        .map_err(|e| (e, Span { start: 0, end: 0 }))?;
+    let checksum = sha256(&writer.bytes);
+    writer.bytes.extend_from_slice(&checksum);
+    Ok(writer.bytes)
+}
+
+fn defined(statement: &Statement) -> Vec<&str> {
+    match statement {
+        Statement::Definition(Definition(name, _)) => vec![name.as_str()],
+        Statement::Sequence(statements) => statements.iter().flat_map(defined).collect(),
+        _ => vec![],
+    }
+}
+
+// the program of a bytecode file, whose source must match its hash, and the whole file its
+// checksum when its code is read
+pub fn read(bytes: &[u8]) -> Result<Program> {
+    let mut reader = Reader::new(bytes);
+    let program = (|| {
+        if reader.take(MARK.len())? != MARK {
+            return None;
+        }
+        let format = reader.number()?;
+        let version = reader.string()?;
+        let current = format == FORMAT_VERSION && version == env!("CARGO_PKG_VERSION");
+        let name = reader.option(|r| r.string())?;
+        let source = reader.string()?;
+        let mut hash = [0; 32];
+        hash.copy_from_slice(reader.take(32)?);
+        let forms = match current {
+            true => {
+                let payload = bytes.len().checked_sub(CHECKSUM)?;
+                if bytes[payload..] != sha256(&bytes[..payload]) {
+                    return None;
+                }
+                reader.bytes = &bytes[..payload];
+                Some(reader.sequence(|r| Some((Rc::new(r.code()?), r.span()?)))?)
+            }
+            false => None,
+        };
+        // nothing is left after the forms
+        if current && !reader.finished() {
+            return None;
+        }
+        Some(Program {
+            name,
+            source,
+            hash,
+            forms,
+        })
+    })();
+    // a format the header of which changed cannot be told from a corrupt file
+    program
+        .filter(|program| program.hash == self::hash(&program.source))
+        .ok_or_else(invalid)
+}
+
+impl Interpreter {
+    // as load_source for the forms of a compiled program
+    pub fn load_compiled(
+        &self,
+        forms: &[(Rc<Code>, Span)],
+    ) -> std::result::Result<Option<ValueType>, Vec<(Error, Span)>> {
+        let mut last_value = None;
+        for (code, span) in forms {
+            self.backtrace.borrow_mut().clear();
+            let result = self.execute(code, &self.env);
+            if gc::collection_due() {
+                gc::collect();
+            }
+            match result {
+                Ok(value) => last_value = value,
+                Err(error) => return Err(vec![(error, *span)]),
+            }
+        }
+        Ok(last_value)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub(crate) bytes: Vec<u8>,
+}
+
+// numbers are written seven bits a byte, the least significant first
+impl Writer {
+    pub(crate) fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    pub(crate) fn number(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.byte(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.byte(n as u8);
+    }
+
+    pub(crate) fn signed(&mut self, n: i64) {
+        self.number(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    pub(crate) fn boolean(&mut self, value: bool) {
+        self.byte(value as u8);
+    }
+
+    // a sign and the limbs of the magnitude, the least significant first
+    pub(crate) fn big(&mut self, n: &BigInt) {
+        let (negative, limbs) = n.to_parts();
+        self.boolean(negative);
+        self.number(limbs.len() as u64);
+        limbs.iter().for_each(|&limb| self.number(limb as u64));
+    }
+
+    pub(crate) fn string(&mut self, s: &str) {
+        self.number(s.len() as u64);
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn option<T>(&mut self, value: &Option<T>, write: impl FnOnce(&mut Self, &T)) {
+        match value {
+            Some(value) => {
+                self.byte(1);
+                write(self, value);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    pub(crate) fn sequence<T>(
+        &mut self,
+        items: &[T],
+        mut write: impl FnMut(&mut Self, &T) -> Result<()>,
+    ) -> Result<()> {
+        self.number(items.len() as u64);
+        items.iter().try_for_each(|item| write(self, item))
+    }
+
+    fn strings(&mut self, strings: &[String]) {
+        self.number(strings.len() as u64);
+        strings.iter().for_each(|s| self.string(s));
+    }
+
+    fn span(&mut self, span: &Span) {
+        self.number(span.start as u64);
+        self.number(span.end as u64);
+    }
+
+    fn spans(&mut self, spans: &[Span]) {
+        self.number(spans.len() as u64);
+        spans.iter().for_each(|span| self.span(span));
+    }
+
+    pub(crate) fn code(&mut self, code: &Code) -> Result<()> {
+        self.sequence(&code.instructions, |w, instruction| {
+            w.instruction(instruction);
+            Ok(())
+        })?;
+        self.sequence(&code.constants, Self::constant)?;
+        self.sequence(&code.data, |w, datum| {
+            w.datum(datum);
+            Ok(())
+        })?;
+        self.strings(&code.names);
+        self.sequence(&code.codes, |w, code| w.code(code))?;
+        self.sequence(&code.calls, |w, call| {
+            w.number(call.arguments as u64);
+            w.option(&call.span, Self::span);
+            w.string(&call.operator);
+            w.boolean(call.identifier);
+            w.option(&call.global, |w, &(name, depth)| {
+                w.number(name as u64);
+                w.number(depth as u64);
+            });
+            Ok(())
+        })?;
+        self.sequence(&code.guards, |w, guard| {
+            w.string(&guard.variable);
+            w.code(&guard.body)?;
+            w.sequence(&guard.clauses, |w, (test, body)| {
+                for code in [test, body] {
+                    w.boolean(code.is_some());
+                    if let Some(code) = code {
+                        w.code(code)?;
+                    }
+                }
+                Ok(())
+            })
+        })?;
+        self.sequence(&code.matches, |w, clauses| {
+            w.sequence(clauses, |w, (pattern, body)| {
+                w.pattern(pattern);
+                w.code(body)
+            })
+        })?;
+        self.option(&code.procedure, Self::procedure);
+        self.sequence(&code.captured, |w, (name, depth, index)| {
+            w.string(name);
+            w.number(*depth as u64);
+            w.number(*index as u64);
+            Ok(())
+        })
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        let (tag, operands): (u8, &[u32]) = match *instruction {
+            Instruction::Constant(i) => (0, &[i]),
+            Instruction::Datum(i) => (1, &[i]),
+            Instruction::Global(name, depth) => (2, &[name, depth]),
+            Instruction::Local(name, depth, index) => (3, &[name, depth as u32, index as u32]),
+            Instruction::Define(name) => (4, &[name]),
+            Instruction::Closure(i) => (5, &[i]),
+            Instruction::FlatClosure(i, depth) => (6, &[i, depth]),
+            Instruction::Call(i) => (7, &[i]),
+            Instruction::CallGlobal(i) => (8, &[i]),
+            Instruction::Jump(to) => (9, &[to]),
+            Instruction::JumpUnless(to) => (10, &[to]),
+            Instruction::Pop => (11, &[]),
+            Instruction::Vector(n) => (12, &[n]),
+            Instruction::Delay => (13, &[]),
+            Instruction::DelayForce => (14, &[]),
+            Instruction::StreamCons => (15, &[]),
+            Instruction::Match(i) => (16, &[i]),
+            Instruction::Guard(i) => (17, &[i]),
+            Instruction::Return => (18, &[]),
+            // the count is of the interpreter compiling, the code read by another is folded
+            // until it defines a builtin again
+            Instruction::Unchanged(_, to) => (19, &[to]),
+            Instruction::Primitive(i) => (20, &[i]),
+            Instruction::ConstantCall(c, i) => (21, &[c, i]),
+            Instruction::ConstantCallGlobal(c, i) => (22, &[c, i]),
+        };
+        self.byte(tag);
+        operands
+            .iter()
+            .for_each(|&operand| self.number(operand as u64));
+    }
+
+    // the constants of code are those of literals and the values folded
+    fn constant(&mut self, value: &ValueType) -> Result<()> {
+        match value {
+            ValueType::Number(Number::Integer(n)) => {
+                self.byte(0);
+                self.signed(*n);
+            }
+            ValueType::Number(Number::Real(n)) => {
+                self.byte(1);
+                self.number(n.to_bits());
+            }
+            ValueType::Number(Number::Rational(a, b)) => {
+                self.byte(2);
+                self.signed(*a);
+                self.signed(*b);
+            }
+            ValueType::Number(Number::Big(n)) => {
+                self.byte(8);
+                self.big(n);
+            }
+            ValueType::Number(Number::BigRational(a, b)) => {
+                self.byte(9);
+                self.big(a);
+                self.big(b);
+            }
+            ValueType::Boolean(value) => {
+                self.byte(3);
+                self.boolean(*value);
+            }
+            ValueType::Character(c) => {
+                self.byte(4);
+                self.number(*c as u64);
+            }
+            ValueType::String(s) => {
+                self.byte(5);
+                self.string(s);
+            }
+            ValueType::Symbol(s) => {
+                self.byte(6);
+                self.string(s);
+            }
+            ValueType::Void => self.byte(7),
+            value => logic_error!("constant {} cannot be written as bytecode", value),
+        }
+        Ok(())
+    }
+
+    fn datum(&mut self, datum: &Datum) {
+        match datum {
+            Datum::Boolean(value) => {
+                self.byte(0);
+                self.boolean(*value);
+            }
+            Datum::Integer(n) => {
+                self.byte(1);
+                self.signed(*n);
+            }
+            Datum::Real(n) => {
+                self.byte(2);
+                self.string(n);
+            }
+            Datum::Rational(a, b) => {
+                self.byte(3);
+                self.signed(*a);
+                self.number(*b);
+            }
+            Datum::Character(c) => {
+                self.byte(4);
+                self.number(*c as u64);
+            }
+            Datum::String(s) => {
+                self.byte(5);
+                self.string(s);
+            }
+            Datum::Symbol(s) => {
+                self.byte(6);
+                self.string(s);
+            }
+            Datum::List(list) => {
+                self.byte(7);
+                self.data(list);
+            }
+            Datum::Pair(list, tail) => {
+                self.byte(8);
+                self.data(list);
+                self.datum(tail);
+            }
+            Datum::Vector(vector) => {
+                self.byte(9);
+                self.data(vector);
+            }
+            Datum::Bytevector(bytes) => {
+                self.byte(10);
+                self.number(bytes.len() as u64);
+                self.bytes.extend_from_slice(bytes);
+            }
+        }
+    }
+
+    fn data(&mut self, data: &[Datum]) {
+        self.number(data.len() as u64);
+        data.iter().for_each(|datum| self.datum(datum));
+    }
+
+    pub(crate) fn procedure(&mut self, procedure: &SchemeProcedure) {
+        let SchemeProcedure(formals, definitions, expressions, origin) = procedure;
+        self.strings(formals);
+        self.number(definitions.len() as u64);
+        for Definition(name, expression) in definitions {
+            self.string(name);
+            self.expression(expression);
+        }
+        self.expressions(expressions);
+        self.option(origin, |w, origin| {
+            w.option(&origin.name, |w, name| w.string(name));
+            w.number(origin.line as u64);
+            w.spans(&origin.formals);
+            w.spans(&origin.definitions);
+        });
+    }
+
+    fn expressions(&mut self, expressions: &[Expression]) {
+        self.number(expressions.len() as u64);
+        expressions.iter().for_each(|e| self.expression(e));
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(name) => {
+                self.byte(0);
+                self.string(name);
+            }
+            Expression::Integer(n) => {
+                self.byte(1);
+                self.signed(*n);
+            }
+            Expression::Boolean(value) => {
+                self.byte(2);
+                self.boolean(*value);
+            }
+            Expression::Real(n) => {
+                self.byte(3);
+                self.string(n);
+            }
+            Expression::Rational(a, b) => {
+                self.byte(4);
+                self.signed(*a);
+                self.number(*b);
+            }
+            Expression::Character(c) => {
+                self.byte(5);
+                self.number(*c as u64);
+            }
+            Expression::String(s) => {
+                self.byte(6);
+                self.string(s);
+            }
+            Expression::Vector(elements) => {
+                self.byte(7);
+                self.expressions(elements);
+            }
+            Expression::Procedure(procedure) => {
+                self.byte(8);
+                self.procedure(procedure);
+            }
+            Expression::ProcedureCall(operator, arguments) => {
+                self.byte(9);
+                self.expression(operator);
+                self.expressions(arguments);
+            }
+            Expression::LocatedCall(call) => {
+                self.byte(10);
+                self.expression(&call.0);
+                self.expressions(&call.1);
+                self.span(&call.2);
+            }
+            Expression::Conditional(conditional) => {
+                let (test, consequent, alternative) = conditional.as_ref();
+                self.byte(11);
+                self.expression(test);
+                self.expression(consequent);
+                self.option(alternative, Self::expression);
+            }
+            Expression::Guard(guard) => {
+                let (variable, clauses, body) = guard.as_ref();
+                self.byte(12);
+                self.string(variable);
+                self.number(clauses.len() as u64);
+                for GuardClause(test, body) in clauses {
+                    self.option(test, Self::expression);
+                    self.expressions(body);
+                }
+                self.expression(body);
+            }
+            Expression::Delay(thunk) => {
+                self.byte(13);
+                self.expression(thunk);
+            }
+            Expression::DelayForce(thunk) => {
+                self.byte(14);
+                self.expression(thunk);
+            }
+            Expression::StreamCons(stream) => {
+                self.byte(15);
+                self.expression(&stream.0);
+                self.expression(&stream.1);
+            }
+            Expression::Match(matching) => {
+                let (value, clauses) = matching.as_ref();
+                self.byte(16);
+                self.expression(value);
+                self.number(clauses.len() as u64);
+                for MatchClause(pattern, body) in clauses {
+                    self.pattern(pattern);
+                    self.expression(body);
+                }
+            }
+            Expression::Datum(datum) => {
+                self.byte(17);
+                self.datum(datum);
+            }
+            Expression::Primitive(primitive) => {
+                self.byte(18);
+                self.number(primitive.index() as u64);
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.byte(0),
+            Pattern::Variable(name) => {
+                self.byte(1);
+                self.string(name);
+            }
+            Pattern::Datum(datum) => {
+                self.byte(2);
+                self.datum(datum);
+            }
+            Pattern::List(elements, tail) => {
+                self.byte(3);
+                self.sequence_pattern(elements);
+                self.pattern(tail);
+            }
+            Pattern::Vector(elements) => {
+                self.byte(4);
+                self.sequence_pattern(elements);
+            }
+            Pattern::Predicate(predicate, patterns) => {
+                self.byte(5);
+                self.expression(predicate);
+                self.patterns(patterns);
+            }
+        }
+    }
+
+    fn patterns(&mut self, patterns: &[Pattern]) {
+        self.number(patterns.len() as u64);
+        patterns.iter().for_each(|pattern| self.pattern(pattern));
+    }
+
+    fn sequence_pattern(&mut self, SequencePattern(elements, ellipsis): &SequencePattern) {
+        self.patterns(elements);
+        self.option(ellipsis, |w, &index| w.number(index as u64));
+    }
+}
+
+// reads what the writer wrote, none when the bytes are not
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, at: 0 }
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.at == self.bytes.len()
+    }
+
+    pub(crate) fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let taken = self.bytes.get(self.at..self.at.checked_add(length)?)?;
+        self.at += length;
+        Some(taken)
+    }
+
+    pub(crate) fn byte(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub(crate) fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.at).copied()
+    }
+
+    pub(crate) fn number(&mut self) -> Option<u64> {
+        let mut n = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Some(n);
+            }
+        }
+        None
+    }
+
+    pub(crate) fn index<T: TryFrom<u64>>(&mut self) -> Option<T> {
+        T::try_from(self.number()?).ok()
+    }
+
+    pub(crate) fn signed(&mut self) -> Option<i64> {
+        let n = self.number()?;
+        Some((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    pub(crate) fn boolean(&mut self) -> Option<bool> {
+        match self.byte()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn big(&mut self) -> Option<BigInt> {
+        let negative = self.boolean()?;
+        let length: u64 = self.number()?;
+        if length > MAX_INTEGER_BITS / 32 {
+            return None;
+        }
+        let limbs = (0..length)
+            .map(|_| u32::try_from(self.number()?).ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(BigInt::from_parts(negative, limbs))
+    }
+
+    pub(crate) fn character(&mut self) -> Option<char> {
+        std::char::from_u32(self.index()?)
+    }
+
+    pub(crate) fn string(&mut self) -> Option<String> {
+        let length = self.index()?;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.boolean()? {
+            true => Some(Some(read(self)?)),
+            false => Some(None),
+        }
+    }
+
+    pub(crate) fn sequence<T>(
+        &mut self,
+        mut read: impl FnMut(&mut Self) -> Option<T>,
+    ) -> Option<Vec<T>> {
+        let length: usize = self.index()?;
+        // the length is not trusted for the memory it would take
+        let mut items = Vec::with_capacity(length.min(self.bytes.len() - self.at));
+        for _ in 0..length {
+            items.push(read(self)?);
+        }
+        Some(items)
+    }
+
+    fn span(&mut self) -> Option<Span> {
+        Some(Span {
+            start: self.index()?,
+            end: self.index()?,
+        })
+    }
+
+    pub(crate) fn code(&mut self) -> Option<Code> {
+        let instructions = self.sequence(Self::instruction)?;
+        let constants = self.sequence(Self::constant)?;
+        let data = self.sequence(Self::datum)?;
+        let names = self.sequence(Self::string)?;
+        let codes = self.sequence(|r| Some(Rc::new(r.code()?)))?;
+        let calls = self.sequence(|r| {
+            Some(CallSite {
+                arguments: r.index()?,
+                span: r.option(Self::span)?,
+                operator: r.string()?,
+                identifier: r.boolean()?,
+                global: r.option(|r| Some((r.index()?, r.index()?)))?,
+            })
+        })?;
+        let guards = self.sequence(|r| {
+            Some(GuardHandler {
+                variable: r.string()?,
+                body: Rc::new(r.code()?),
+                clauses: r.sequence(|r| {
+                    let test = r.option(|r| Some(Rc::new(r.code()?)))?;
+                    let body = r.option(|r| Some(Rc::new(r.code()?)))?;
+                    Some((test, body))
+                })?,
+            })
+        })?;
+        let matches =
+            self.sequence(|r| r.sequence(|r| Some((r.pattern()?, Rc::new(r.code()?)))))?;
+        let procedure = self.option(Self::procedure)?;
+        let captured = self.sequence(|r| Some((r.string()?, r.index()?, r.index()?)))?;
+        let code = Code {
+            caches: names.iter().map(|_| Cell::new(usize::MAX)).collect(),
+            instructions,
+            constants,
+            data,
+            names,
+            codes,
+            calls,
+            guards,
+            matches,
+            procedure,
+            captured,
+        };
+        // the operands index the tables of the code and the stack holds the values the
+        // instructions take, which the vm does not check
+        match code.instructions.iter().all(|i| valid(&code, i)) && balanced(&code) {
+            true => Some(code),
+            false => None,
+        }
+    }
+
+    fn instruction(&mut self) -> Option<Instruction> {
+        Some(match self.byte()? {
+            0 => Instruction::Constant(self.index()?),
+            1 => Instruction::Datum(self.index()?),
+            2 => Instruction::Global(self.index()?, self.index()?),
+            3 => Instruction::Local(self.index()?, self.index()?, self.index()?),
+            4 => Instruction::Define(self.index()?),
+            5 => Instruction::Closure(self.index()?),
+            6 => Instruction::FlatClosure(self.index()?, self.index()?),
+            7 => Instruction::Call(self.index()?),
+            8 => Instruction::CallGlobal(self.index()?),
+            9 => Instruction::Jump(self.index()?),
+            10 => Instruction::JumpUnless(self.index()?),
+            11 => Instruction::Pop,
+            12 => Instruction::Vector(self.index()?),
+            13 => Instruction::Delay,
+            14 => Instruction::DelayForce,
+            15 => Instruction::StreamCons,
+            16 => Instruction::Match(self.index()?),
+            17 => Instruction::Guard(self.index()?),
+            18 => Instruction::Return,
+            19 => Instruction::Unchanged(0, self.index()?),
+            20 => Instruction::Primitive(self.index()?),
+            21 => Instruction::ConstantCall(self.index()?, self.index()?),
+            22 => Instruction::ConstantCallGlobal(self.index()?, self.index()?),
+            _ => return None,
+        })
+    }
+
+    fn constant(&mut self) -> Option<ValueType> {
+        Some(match self.byte()? {
+            0 => ValueType::Number(Number::Integer(self.signed()?)),
+            1 => ValueType::Number(Number::Real(f64::from_bits(self.number()?))),
+            2 => ValueType::Number(Number::Rational(self.signed()?, self.signed()?)),
+            3 => ValueType::Boolean(self.boolean()?),
+            4 => ValueType::Character(self.character()?),
+            5 => ValueType::String(self.string()?),
+            6 => ValueType::Symbol(intern(&self.string()?)),
+            7 => ValueType::Void,
+            8 => ValueType::Number(Number::integer(self.big()?)),
+            9 => ValueType::Number(Number::fraction_of(self.big()?, self.big()?).ok()?),
+            _ => return None,
+        })
+    }
+
+    fn datum(&mut self) -> Option<Datum> {
+        Some(match self.byte()? {
+            0 => Datum::Boolean(self.boolean()?),
+            1 => Datum::Integer(self.signed()?),
+            2 => Datum::Real(intern(&self.string()?)),
+            3 => Datum::Rational(self.signed()?, self.number().filter(|&b| b > 0)?),
+            4 => Datum::Character(self.character()?),
+            5 => Datum::String(self.string()?),
+            6 => Datum::Symbol(intern(&self.string()?)),
+            7 => Datum::List(self.sequence(Self::datum)?),
+            8 => Datum::Pair(self.sequence(Self::datum)?, Box::new(self.datum()?)),
+            9 => Datum::Vector(self.sequence(Self::datum)?),
+            10 => {
+                let length = self.index()?;
+                Datum::Bytevector(self.take(length)?.to_vec())
+            }
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn procedure(&mut self) -> Option<SchemeProcedure> {
+        Some(SchemeProcedure(
+            self.sequence(Self::string)?,
+            self.sequence(|r| Some(Definition(r.string()?, r.expression()?)))?,
+            self.sequence(Self::expression)?,
+            self.option(|r| {
+                Some(Rc::new(Origin {
+                    name: r.option(Self::string)?,
+                    line: r.index()?,
+                    formals: r.sequence(Self::span)?,
+                    definitions: r.sequence(Self::span)?,
+                }))
+            })?,
+        ))
+    }
+
+    fn expression(&mut self) -> Option<Expression> {
+        Some(match self.byte()? {
+            0 => Expression::Identifier(intern(&self.string()?)),
+            1 => Expression::Integer(self.signed()?),
+            2 => Expression::Boolean(self.boolean()?),
+            3 => Expression::Real(intern(&self.string()?)),
+            4 => Expression::Rational(self.signed()?, self.number().filter(|&b| b > 0)?),
+            5 => Expression::Character(self.character()?),
+            6 => Expression::String(self.string()?),
+            7 => Expression::Vector(self.sequence(Self::expression)?),
+            8 => Expression::Procedure(self.procedure()?),
+            9 => Expression::ProcedureCall(
+                Box::new(self.expression()?),
+                self.sequence(Self::expression)?,
+            ),
+            10 => Expression::LocatedCall(Box::new((
+                self.expression()?,
+                self.sequence(Self::expression)?,
+                self.span()?,
+            ))),
+            11 => Expression::Conditional(Box::new((
+                self.expression()?,
+                self.expression()?,
+                self.option(Self::expression)?,
+            ))),
+            12 => Expression::Guard(Box::new((
+                self.string()?,
+                self.sequence(|r| {
+                    Some(GuardClause(
+                        r.option(Self::expression)?,
+                        r.sequence(Self::expression)?,
+                    ))
+                })?,
+                self.expression()?,
+            ))),
+            13 => Expression::Delay(Box::new(self.expression()?)),
+            14 => Expression::DelayForce(Box::new(self.expression()?)),
+            15 => Expression::StreamCons(Box::new((self.expression()?, self.expression()?))),
+            16 => Expression::Match(Box::new((
+                self.expression()?,
+                self.sequence(|r| Some(MatchClause(r.pattern()?, r.expression()?)))?,
+            ))),
+            17 => Expression::Datum(Box::new(self.datum()?)),
+            18 => Expression::Primitive(*Primitive::ALL.get(self.index::<usize>()?)?),
+            _ => return None,
+        })
+    }
+
+    fn pattern(&mut self) -> Option<Pattern> {
+        Some(match self.byte()? {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Variable(self.string()?),
+            2 => Pattern::Datum(self.datum()?),
+            3 => Pattern::List(self.sequence_pattern()?, Box::new(self.pattern()?)),
+            4 => Pattern::Vector(self.sequence_pattern()?),
+            5 => Pattern::Predicate(self.expression()?, self.sequence(Self::pattern)?),
+            _ => return None,
+        })
+    }
+
+    fn sequence_pattern(&mut self) -> Option<SequencePattern> {
+        let elements = self.sequence(Self::pattern)?;
+        let ellipsis = self.option(Self::index)?;
+        match ellipsis {
+            Some(index) if index >= elements.len() => None,
+            _ => Some(SequencePattern(elements, ellipsis)),
+        }
+    }
+}
+
+fn valid(code: &Code, instruction: &Instruction) -> bool {
+    let within = |i: u32, length: usize| (i as usize) < length;
+    match *instruction {
+        Instruction::Constant(i) => within(i, code.constants.len()),
+        Instruction::Datum(i) => within(i, code.data.len()),
+        Instruction::Primitive(i) => within(i, Primitive::ALL.len()),
+        Instruction::Global(name, _) | Instruction::Local(name, ..) | Instruction::Define(name) => {
+            within(name, code.names.len())
+        }
+        Instruction::Closure(i) | Instruction::FlatClosure(i, _) => within(i, code.codes.len()),
+        Instruction::Call(i) => within(i, code.calls.len()),
+        Instruction::CallGlobal(i) => code
+            .calls
+            .get(i as usize)
+            .and_then(|call| call.global)
+            .is_some_and(|(name, _)| within(name, code.names.len())),
+        // the constant is the last argument
+        Instruction::ConstantCall(c, i) | Instruction::ConstantCallGlobal(c, i) => {
+            let call = match *instruction {
+                Instruction::ConstantCall(..) => Instruction::Call(i),
+                _ => Instruction::CallGlobal(i),
+            };
+            within(c, code.constants.len())
+                && valid(code, &call)
+                && code.calls[i as usize].arguments > 0
+        }
+        Instruction::Jump(to) | Instruction::JumpUnless(to) | Instruction::Unchanged(_, to) => {
+            within(to, code.instructions.len())
+        }
+        Instruction::Match(i) => within(i, code.matches.len()),
+        Instruction::Guard(i) => within(i, code.guards.len()),
+        _ => true,
+    }
+}
+
+// whether every path through the code ends with a return, each instruction finding as many
+// values on the stack whichever path leads to it and at least those it pops
+fn balanced(code: &Code) -> bool {
+    let mut heights: Vec<Option<usize>> = vec![None; code.instructions.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((pc, height)) = pending.pop() {
+        let instruction = match code.instructions.get(pc) {
+            Some(instruction) => instruction,
+            None => return false,
+        };
+        match heights[pc] {
+            Some(known) if known == height => continue,
+            Some(_) => return false,
+            None => heights[pc] = Some(height),
+        }
+        let (pops, pushes) = match *instruction {
+            Instruction::Constant(_)
+            | Instruction::Datum(_)
+            | Instruction::Primitive(_)
+            | Instruction::Global(..)
+            | Instruction::Local(..)
+            | Instruction::Closure(_)
+            | Instruction::FlatClosure(..)
+            | Instruction::Guard(_) => (0, 1),
+            Instruction::Define(_) | Instruction::Pop | Instruction::JumpUnless(_) => (1, 0),
+            Instruction::Call(i) => (code.calls[i as usize].arguments + 1, 1),
+            Instruction::CallGlobal(i) => (code.calls[i as usize].arguments, 1),
+            // the constant pushed is an argument
+            Instruction::ConstantCall(_, i) => (code.calls[i as usize].arguments, 1),
+            Instruction::ConstantCallGlobal(_, i) => (code.calls[i as usize].arguments - 1, 1),
+            Instruction::Vector(n) => (n as usize, 1),
+            Instruction::Delay | Instruction::DelayForce | Instruction::Match(_) => (1, 1),
+            Instruction::StreamCons => (2, 1),
+            Instruction::Jump(_) | Instruction::Unchanged(..) | Instruction::Return => (0, 0),
+        };
+        let height = match height.checked_sub(pops) {
+            Some(height) => height + pushes,
+            None => return false,
+        };
+        match *instruction {
+            Instruction::Return => (),
+            Instruction::Jump(to) => pending.push((to as usize, height)),
+            Instruction::JumpUnless(to) | Instruction::Unchanged(_, to) => {
+                pending.push((to as usize, height));
+                pending.push((pc + 1, height));
+            }
+            _ => pending.push((pc + 1, height)),
+        }
+    }
+    true
+}
+
+#[test]
+fn compiling_ahead() -> Result<()> {
+    let it = Interpreter::new();
+    let source =
+        "(define (f x) (match x ((a . b) (list 'pair a b)) (_ (guard (e (#t e)) (raise x)))))
+        (define (+ a b) (* a b))
+        (list (f '(1 2)) (f #(1.5 \"s\" #\\c 1/2)) (+ 2 3) (f 123456789012345678901)
+          (- 0 (/ 1 100000000000000000000)))";
+    let bytes = compile(&it, source, Some("program.scm")).map_err(|(e, _)| e)?;
+    let program = read(&bytes)?;
+    assert_eq!(program.name.as_deref(), Some("program.scm"));
+    assert_eq!(program.source, source);
+    let forms = program.forms.unwrap();
+    assert_eq!(forms.len(), 3);
+    assert_eq!(
+        it.load_compiled(&forms)
+            .map_err(|mut errors| errors.pop().unwrap().0)?
+            .map(|value| value.to_string()),
+        Some(
+            "((pair 1 (2)) #(1.5 \"s\" #\\c 1/2) 6 123456789012345678901 -1/100000000000000000000)"
+                .to_string()
+        )
+    );
+    // the code of another version is not read, its source is
+    let mut other = bytes.clone();
+    other[MARK.len()] = FORMAT_VERSION as u8 + 1;
+    let program = read(&other)?;
+    assert!(program.forms.is_none());
+    assert_eq!(program.source, source);
+    // nor is a file cut short or changed
+    assert!(read(&bytes[..bytes.len() - 1]).is_err());
+    let mut changed = bytes.clone();
+    let offset = bytes
+        .windows(source.len())
+        .position(|window| window == source.as_bytes())
+        .unwrap();
+    changed[offset] ^= 1;
+    assert!(read(&changed).is_err());
+    // nor is one whose code changed, nor code running off its end or popping an empty stack
+    let mut changed = bytes.clone();
+    changed[bytes.len() - CHECKSUM - 1] ^= 1;
+    assert!(read(&changed).is_err());
+    for instructions in [
+        vec![Instruction::Pop, Instruction::Return],
+        vec![Instruction::Constant(0)],
+        vec![
+            Instruction::Constant(0),
+            Instruction::JumpUnless(3),
+            Instruction::Constant(0),
+            Instruction::Constant(0),
+            Instruction::Return,
+        ],
+    ] {
+        let code = Code {
+            instructions,
+            constants: vec![ValueType::Boolean(true)],
+            ..Code::default()
+        };
+        let mut writer = Writer::default();
+        writer.code(&code)?;
+        assert!(Reader::new(&writer.bytes).code().is_none());
+    }
+    Ok(())
+}
@@ -0,0 +1,850 @@
+use crate::error::Span;
+use crate::interpreter::optimizer::Optimizer;
+use crate::interpreter::*;
+use std::convert::TryFrom;
+use std::fmt;
+
+// an operation of the vm, taking its operands from the value stack, the u32 indexes the tables
+// of the code it is in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // pushes a constant
+    Constant(u32),
+    // pushes a fresh copy of quoted data, which may be mutated
+    Datum(u32),
+    // pushes the builtin of a derived form
+    Primitive(u32),
+    // pushes the value of the named variable, which is not bound by the procedures and clauses
+    // around, looked up from the environment of the top level form as many levels up
+    Global(u32, u32),
+    // pushes the value of the named variable at the index of the environment as many levels
+    // up, looking it up by name if it is not defined there yet
+    Local(u32, u16, u16),
+    // binds the named variable to the value popped
+    Define(u32),
+    // pushes a procedure of the code closing over the environment
+    Closure(u32),
+    // pushes a procedure of the code closing over a copy of the variables it captures, in an
+    // environment of the top level form's one as many levels up
+    FlatClosure(u32, u32),
+    // calls the operator under the arguments of the call site with them
+    Call(u32),
+    // calls the top level variable of the call site with the arguments, pushing them then
+    // calling taking one instruction less
+    CallGlobal(u32),
+    // pushes the constant, the last argument, then calls as Call and CallGlobal do
+    ConstantCall(u32, u32),
+    ConstantCallGlobal(u32, u32),
+    Jump(u32),
+    // jumps on #f, the value popped must be a boolean
+    JumpUnless(u32),
+    // jumps, to the code as written of what was folded before, if a builtin of the top level
+    // environment was defined again since the code was compiled, as many times as when it was
+    Unchanged(u32, u32),
+    Pop,
+    // pushes a vector of as many values popped
+    Vector(u32),
+    // pushes a promise of the thunk popped
+    Delay,
+    DelayForce,
+    // pushes a stream of the car and cdr thunks popped
+    StreamCons,
+    // matches the value popped against the clauses of a match
+    Match(u32),
+    // runs a guarded body, with the clauses handling its errors
+    Guard(u32),
+    // returns the value on the top of the stack above the frame, or none at the top level
+    Return,
+}
+
+// a call as written, for the backtrace of errors unwinding through it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    pub arguments: usize,
+    pub span: Option<Span>,
+    // the identifier called, or else the operator as written
+    pub operator: String,
+    pub identifier: bool,
+    // the name and levels up to the top level environment of the variable a CallGlobal calls
+    pub global: Option<(u32, u32)>,
+}
+
+// the test of a guard clause, none for else, and its body, none when the value of the test is
+// returned
+pub type GuardClauseCode = (Option<Rc<Code>>, Option<Rc<Code>>);
+
+#[derive(Debug)]
+pub struct GuardHandler {
+    pub variable: String,
+    pub body: Rc<Code>,
+    pub clauses: Vec<GuardClauseCode>,
+}
+
+// the code of a top level form, a procedure or a block run by an instruction, with the tables
+// its instructions index
+#[derive(Debug, Default)]
+pub struct Code {
+    pub instructions: Vec<Instruction>,
+    pub constants: Vec<ValueType>,
+    pub data: Vec<Datum>,
+    pub names: Vec<String>,
+    // the index each name was last found at in the top level environment, for looking it up
+    // again without hashing
+    pub caches: Vec<Cell<usize>>,
+    pub codes: Vec<Rc<Code>>,
+    pub calls: Vec<CallSite>,
+    pub guards: Vec<GuardHandler>,
+    pub matches: Vec<Vec<(Pattern, Rc<Code>)>>,
+    // the procedure compiled, which its closures are written as and bind the formals of
+    pub procedure: Option<SchemeProcedure>,
+    // the variables a flat closure of the procedure copies, from the levels up and indexes of
+    // the environment it is made in
+    pub captured: Vec<(String, u16, u16)>,
+}
+
+// codes are the same when they are the same compilation
+impl PartialEq for Code {
+    fn eq(&self, rhs: &Self) -> bool {
+        std::ptr::eq(self, rhs)
+    }
+}
+
+// one instruction a line with the name or value it refers to, the nested codes after it
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(procedure) = &self.procedure {
+            writeln!(f, "{}", procedure)?;
+        }
+        for (offset, instruction) in self.instructions.iter().enumerate() {
+            write!(f, "{:4} {:?}", offset, instruction)?;
+            match *instruction {
+                Instruction::Constant(i) => write!(f, " ; {}", self.constants[i as usize])?,
+                Instruction::Datum(i) => write!(f, " ; {}", self.data[i as usize])?,
+                Instruction::Global(i, _) | Instruction::Define(i) | Instruction::Local(i, ..) => {
+                    write!(f, " ; {}", self.names[i as usize])?
+                }
+                Instruction::Call(i) | Instruction::CallGlobal(i) => {
+                    write!(f, " ; {}", self.calls[i as usize].operator)?
+                }
+                Instruction::ConstantCall(c, i) | Instruction::ConstantCallGlobal(c, i) => write!(
+                    f,
+                    " ; {} {}",
+                    self.constants[c as usize], self.calls[i as usize].operator
+                )?,
+                _ => (),
+            }
+            writeln!(f)?;
+        }
+        for code in &self.codes {
+            write!(f, "{}", code)?;
+        }
+        Ok(())
+    }
+}
+
+// a top level form, leaving its value if it has one, as a definition has not
+pub fn compile_statement(statement: &Statement, optimizer: Optimizer) -> Result<Rc<Code>> {
+    let mut compiler = Compiler::new(optimizer, vec![]);
+    compiler.statement(statement)?;
+    compiler.emit(Instruction::Return);
+    Ok(Rc::new(compiler.code))
+}
+
+// the procedure is written as itself in its closures, however its body is optimized
+fn compile_procedure(
+    procedure: &SchemeProcedure,
+    optimizer: Optimizer,
+    mut frames: Vec<Frame>,
+) -> Result<Code> {
+    let SchemeProcedure(formals, definitions, expressions, _) = procedure;
+    // the frame of a call, binding the formals and then the definitions, as they are defined
+    let mut frame = Frame::default();
+    for name in formals.iter().chain(definitions.iter().map(|d| &d.0)) {
+        if !frame.variables.contains(name) {
+            let defined = definitions
+                .iter()
+                .any(|Definition(defined, _)| defined == name);
+            frame.variables.push(name.clone());
+            frame.fixed.push(!defined);
+        }
+    }
+    frames.push(frame);
+    let mut compiler = Compiler::new(optimizer, frames);
+    for Definition(name, expression) in definitions {
+        compiler.expression(expression)?;
+        // the value is known from the definition on if it is the only one of the name
+        if definitions.iter().filter(|d| &d.0 == name).count() == 1 {
+            let value = compiler.optimizer.constant(expression);
+            compiler.optimizer.bind(name, value);
+            if let Expression::Procedure(procedure) = expression {
+                compiler
+                    .optimizer
+                    .bind_procedure(name, procedure, definitions);
+            }
+        }
+        let name = compiler.name(name);
+        compiler.emit(Instruction::Define(name));
+    }
+    compiler.sequence(expressions)?;
+    compiler.emit(Instruction::Return);
+    compiler.code.procedure = Some(procedure.clone());
+    Ok(compiler.code)
+}
+
+// expressions evaluated in turn for the value of the last
+fn compile_block(
+    expressions: &[Expression],
+    optimizer: Optimizer,
+    frames: Vec<Frame>,
+) -> Result<Rc<Code>> {
+    let mut compiler = Compiler::new(optimizer, frames);
+    compiler.sequence(expressions)?;
+    compiler.emit(Instruction::Return);
+    Ok(Rc::new(compiler.code))
+}
+
+// the variables of an environment code runs in, and whether each is bound before the code runs
+// and never defined again, which closures may then copy
+#[derive(Clone, Default)]
+struct Frame {
+    variables: Vec<String>,
+    fixed: Vec<bool>,
+}
+
+// the variables an expression refers to which it does not bind, the names bound around it left
+// out, in the order they are first referred to
+fn free_variables(expression: &Expression, bound: &mut Vec<String>, free: &mut Vec<String>) {
+    let mut walk =
+        |expression: &Expression, bound: &mut Vec<String>| free_variables(expression, bound, free);
+    match expression {
+        Expression::Identifier(name) => {
+            if !bound
+                .iter()
+                .chain(free.iter())
+                .any(|variable| **variable == **name)
+            {
+                free.push(name.to_string());
+            }
+        }
+        Expression::Procedure(procedure) => procedure_free_variables(procedure, bound, free),
+        Expression::ProcedureCall(operator, arguments) => {
+            walk(operator, bound);
+            arguments.iter().for_each(|e| walk(e, bound));
+        }
+        Expression::LocatedCall(call) => {
+            walk(&call.0, bound);
+            call.1.iter().for_each(|e| walk(e, bound));
+        }
+        Expression::Vector(elements) => elements.iter().for_each(|e| walk(e, bound)),
+        Expression::Conditional(conditional) => {
+            let (test, consequent, alternative) = conditional.as_ref();
+            walk(test, bound);
+            walk(consequent, bound);
+            if let Some(alternative) = alternative {
+                walk(alternative, bound);
+            }
+        }
+        Expression::Guard(guard) => {
+            let (variable, clauses, body) = guard.as_ref();
+            walk(body, bound);
+            bound.push(variable.clone());
+            for GuardClause(test, body) in clauses {
+                test.iter().chain(body).for_each(|e| walk(e, bound));
+            }
+            bound.pop();
+        }
+        Expression::Delay(thunk) | Expression::DelayForce(thunk) => walk(thunk, bound),
+        Expression::StreamCons(stream) => {
+            walk(&stream.0, bound);
+            walk(&stream.1, bound);
+        }
+        // the predicates of patterns are evaluated where the match is
+        Expression::Match(matching) => {
+            let (value, clauses) = matching.as_ref();
+            walk(value, bound);
+            for MatchClause(pattern, body) in clauses {
+                let mut predicates = vec![];
+                pattern_predicates(pattern, &mut predicates);
+                predicates.into_iter().for_each(|e| walk(e, bound));
+                walk(body, bound);
+            }
+        }
+        Expression::Datum(_)
+        | Expression::Primitive(_)
+        | Expression::Boolean(_)
+        | Expression::Character(_)
+        | Expression::String(_)
+        | Expression::Integer(_)
+        | Expression::Real(_)
+        | Expression::Rational(_, _) => (),
+    }
+}
+
+fn procedure_free_variables(
+    procedure: &SchemeProcedure,
+    bound: &mut Vec<String>,
+    free: &mut Vec<String>,
+) {
+    let SchemeProcedure(formals, definitions, expressions, _) = procedure;
+    let depth = bound.len();
+    bound.extend(formals.iter().cloned());
+    bound.extend(definitions.iter().map(|Definition(name, _)| name.clone()));
+    for Definition(_, expression) in definitions {
+        free_variables(expression, bound, free);
+    }
+    expressions
+        .iter()
+        .for_each(|e| free_variables(e, bound, free));
+    bound.truncate(depth);
+}
+
+fn pattern_predicates<'p>(pattern: &'p Pattern, predicates: &mut Vec<&'p Expression>) {
+    match pattern {
+        Pattern::Predicate(predicate, patterns) => {
+            predicates.push(predicate);
+            patterns
+                .iter()
+                .for_each(|p| pattern_predicates(p, predicates));
+        }
+        Pattern::List(SequencePattern(patterns, _), tail) => {
+            patterns
+                .iter()
+                .for_each(|p| pattern_predicates(p, predicates));
+            pattern_predicates(tail, predicates);
+        }
+        Pattern::Vector(SequencePattern(patterns, _)) => patterns
+            .iter()
+            .for_each(|p| pattern_predicates(p, predicates)),
+        Pattern::Wildcard | Pattern::Variable(_) | Pattern::Datum(_) => (),
+    }
+}
+
+struct Compiler<'a> {
+    code: Code,
+    optimizer: Optimizer<'a>,
+    // the environments the code runs in, innermost last, up to the one of the top level form
+    frames: Vec<Frame>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(optimizer: Optimizer<'a>, frames: Vec<Frame>) -> Self {
+        Self {
+            code: Code::default(),
+            optimizer,
+            frames,
+        }
+    }
+
+    // the levels up and the index of the environment a variable is in, if it is bound by a
+    // procedure or clause around the code
+    fn address(&self, name: &str) -> Option<(u16, u16)> {
+        self.frames
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, frame)| {
+                let index = frame
+                    .variables
+                    .iter()
+                    .position(|variable| variable == name)?;
+                Some((u16::try_from(depth).ok()?, u16::try_from(index).ok()?))
+            })
+    }
+
+    // a closure copies the variables it uses from the procedures and clauses around when they
+    // are all fixed, so that it keeps neither their frames alive nor looks through them
+    fn closure(&mut self, procedure: &SchemeProcedure, optimizer: Optimizer) -> Result<()> {
+        let mut free = vec![];
+        procedure_free_variables(procedure, &mut vec![], &mut free);
+        let inlined = optimizer.inlined_variables(&free);
+        free.extend(inlined);
+        let captured: Vec<(String, u16, u16)> = free
+            .into_iter()
+            .filter_map(|name| {
+                let (depth, index) = self.address(&name)?;
+                Some((name, depth, index))
+            })
+            .collect();
+        let flat = captured.iter().all(|(_, depth, index)| {
+            let frame = &self.frames[self.frames.len() - 1 - *depth as usize];
+            frame.fixed[*index as usize]
+        });
+        if !flat {
+            let code = compile_procedure(procedure, optimizer, self.frames.clone())?;
+            self.code.codes.push(Rc::new(code));
+            self.emit(Instruction::Closure(self.code.codes.len() as u32 - 1));
+            return Ok(());
+        }
+        let frames = match captured.is_empty() {
+            true => vec![],
+            false => vec![Frame {
+                variables: captured.iter().map(|(name, ..)| name.clone()).collect(),
+                fixed: vec![true; captured.len()],
+            }],
+        };
+        let mut code = compile_procedure(procedure, optimizer, frames)?;
+        code.captured = captured;
+        self.code.codes.push(Rc::new(code));
+        self.emit(Instruction::FlatClosure(
+            self.code.codes.len() as u32 - 1,
+            self.frames.len() as u32,
+        ));
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.code.instructions.push(instruction);
+        self.code.instructions.len() - 1
+    }
+
+    // the jump at offset goes to the next instruction emitted
+    fn patch(&mut self, offset: usize) {
+        let target = self.code.instructions.len() as u32;
+        match &mut self.code.instructions[offset] {
+            Instruction::Jump(to) | Instruction::JumpUnless(to) | Instruction::Unchanged(_, to) => {
+                *to = target
+            }
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    fn constant(&mut self, value: ValueType) {
+        self.code.constants.push(value);
+        let index = self.code.constants.len() as u32 - 1;
+        self.emit(Instruction::Constant(index));
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        let names = &mut self.code.names;
+        match names.iter().position(|known| known == name) {
+            Some(index) => index as u32,
+            None => {
+                names.push(name.to_string());
+                self.code.caches.push(Cell::new(usize::MAX));
+                names.len() as u32 - 1
+            }
+        }
+    }
+
+    // returns whether the statement leaves a value
+    fn statement(&mut self, statement: &Statement) -> Result<bool> {
+        Ok(match statement {
+            Statement::ImportDeclaration(_) => false,
+            Statement::Expression(expression) => {
+                self.expression(expression)?;
+                true
+            }
+            Statement::Definition(Definition(name, expression)) => {
+                self.expression(expression)?;
+                // a builtin defined again is not folded in the rest of the form
+                self.optimizer.bind(name, None);
+                let name = self.name(name);
+                self.emit(Instruction::Define(name));
+                false
+            }
+            Statement::Sequence(statements) => {
+                let mut valued = false;
+                for statement in statements {
+                    if valued {
+                        self.emit(Instruction::Pop);
+                    }
+                    valued = self.statement(statement)?;
+                }
+                valued
+            }
+        })
+    }
+
+    fn sequence(&mut self, expressions: &[Expression]) -> Result<()> {
+        for (i, expression) in expressions.iter().enumerate() {
+            if i > 0 {
+                self.emit(Instruction::Pop);
+            }
+            self.expression(expression)?;
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        operator: &Expression,
+        arguments: &[Expression],
+        span: Option<Span>,
+    ) -> Result<()> {
+        // the call of a small procedure is its body, which is in the tail position if the call
+        // is
+        if let Some(body) = self
+            .optimizer
+            .inline(operator, arguments, |argument| self.simple(argument))
+        {
+            return self.expression(&body);
+        }
+        // a top level variable called with simple arguments is looked up after them, which
+        // cannot tell, by the instruction calling it
+        let global = match operator {
+            Expression::Identifier(name)
+                if self.address(name).is_none()
+                    && self.optimizer.constant(operator).is_none()
+                    && arguments.iter().all(|argument| self.simple(argument)) =>
+            {
+                Some((self.name(name), self.frames.len() as u32))
+            }
+            _ => None,
+        };
+        match operator {
+            // a procedure called where it is written binds its formals to the arguments, as
+            // let does
+            Expression::Procedure(procedure) if self.optimizer.level() >= 2 => {
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|argument| self.optimizer.constant(argument))
+                    .collect();
+                let optimizer = self.optimizer.procedure(procedure, &arguments);
+                self.closure(procedure, optimizer)?;
+            }
+            _ if global.is_some() => (),
+            _ => self.expression(operator)?,
+        }
+        let mut last = self.code.instructions.len();
+        for argument in arguments {
+            last = self.code.instructions.len();
+            self.expression(argument)?;
+        }
+        // a last argument compiled to a constant alone is pushed by the call, no jump going
+        // between them
+        let constant = match self.code.instructions[last..] {
+            [Instruction::Constant(c)] if !arguments.is_empty() => {
+                self.code.instructions.pop();
+                Some(c)
+            }
+            _ => None,
+        };
+        let (operator, identifier) = match operator {
+            Expression::Identifier(name) => (name.to_string(), true),
+            other => (other.to_string(), false),
+        };
+        self.code.calls.push(CallSite {
+            arguments: arguments.len(),
+            span,
+            operator,
+            identifier,
+            global,
+        });
+        let site = self.code.calls.len() as u32 - 1;
+        match (global, constant) {
+            (Some(_), Some(c)) => self.emit(Instruction::ConstantCallGlobal(c, site)),
+            (Some(_), None) => self.emit(Instruction::CallGlobal(site)),
+            (None, Some(c)) => self.emit(Instruction::ConstantCall(c, site)),
+            (None, None) => self.emit(Instruction::Call(site)),
+        };
+        Ok(())
+    }
+
+    // whether the expression is read without running any code, and so cannot fail
+    fn simple(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::Boolean(_)
+            | Expression::Character(_)
+            | Expression::String(_)
+            | Expression::Integer(_)
+            | Expression::Real(_)
+            | Expression::Rational(..) => true,
+            Expression::Identifier(name) => match self.address(name) {
+                Some((depth, index)) => {
+                    self.frames[self.frames.len() - 1 - depth as usize].fixed[index as usize]
+                }
+                None => self.optimizer.constant(expression).is_some(),
+            },
+            _ => self.optimizer.constant(expression).is_some(),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<()> {
+        let computed = matches!(
+            expression,
+            Expression::Identifier(_)
+                | Expression::ProcedureCall(..)
+                | Expression::LocatedCall(_)
+                | Expression::Conditional(_)
+        );
+        if let Some(value) = self.optimizer.constant(expression).filter(|_| computed) {
+            return self.guarded(
+                |compiler| {
+                    compiler.constant(value);
+                    Ok(())
+                },
+                |compiler| compiler.written(expression),
+            );
+        }
+        self.written(expression)
+    }
+
+    // the code of what is folded, which holds while the builtins it calls are those it was
+    // folded with, then the code of the expression as written, which runs instead once one of
+    // the builtins is defined again
+    fn guarded(
+        &mut self,
+        folded: impl FnOnce(&mut Self) -> Result<()>,
+        written: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        let redefinitions = self.optimizer.redefinitions();
+        let to_written = self.emit(Instruction::Unchanged(redefinitions, 0));
+        folded(self)?;
+        let to_end = self.emit(Instruction::Jump(0));
+        self.patch(to_written);
+        let optimizer = self.optimizer.clone();
+        self.optimizer = optimizer.unfolded();
+        let result = written(self);
+        self.optimizer = optimizer;
+        result?;
+        self.patch(to_end);
+        Ok(())
+    }
+
+    // the expression compiled but for folding itself
+    fn written(&mut self, expression: &Expression) -> Result<()> {
+        match expression {
+            Expression::ProcedureCall(operator, arguments) => {
+                self.call(operator, arguments, None)?
+            }
+            Expression::LocatedCall(call) => {
+                let (operator, arguments, span) = call.as_ref();
+                self.call(operator, arguments, Some(*span))?
+            }
+            Expression::Vector(elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                self.emit(Instruction::Vector(elements.len() as u32));
+            }
+            Expression::Procedure(procedure) => {
+                let optimizer = self.optimizer.procedure(procedure, &[]);
+                self.closure(procedure, optimizer)?;
+            }
+            Expression::Conditional(conditional) => {
+                let (test, consequent, alternative) = conditional.as_ref();
+                // only the branch a constant test chooses is compiled but for the conditional
+                // as written
+                let chosen = match self.optimizer.constant(test) {
+                    Some(ValueType::Boolean(true)) => Some(Some(consequent)),
+                    Some(ValueType::Boolean(false)) => Some(alternative.as_ref()),
+                    _ => None,
+                };
+                if let Some(branch) = chosen {
+                    return self.guarded(
+                        |compiler| match branch {
+                            Some(branch) => compiler.expression(branch),
+                            None => {
+                                compiler.constant(ValueType::Void);
+                                Ok(())
+                            }
+                        },
+                        |compiler| compiler.written(expression),
+                    );
+                }
+                self.expression(test)?;
+                let to_alternative = self.emit(Instruction::JumpUnless(0));
+                self.expression(consequent)?;
+                let to_end = self.emit(Instruction::Jump(0));
+                self.patch(to_alternative);
+                match alternative {
+                    Some(alternative) => self.expression(alternative)?,
+                    None => self.constant(ValueType::Void),
+                }
+                self.patch(to_end);
+            }
+            Expression::Delay(thunk) => {
+                self.expression(thunk)?;
+                self.emit(Instruction::Delay);
+            }
+            Expression::DelayForce(thunk) => {
+                self.expression(thunk)?;
+                self.emit(Instruction::DelayForce);
+            }
+            Expression::StreamCons(stream) => {
+                self.expression(&stream.0)?;
+                self.expression(&stream.1)?;
+                self.emit(Instruction::StreamCons);
+            }
+            Expression::Match(matching) => {
+                let (value, clauses) = matching.as_ref();
+                self.expression(value)?;
+                let clauses = clauses
+                    .iter()
+                    .map(|MatchClause(pattern, body)| {
+                        let optimizer = self.optimizer.enter(pattern.variables());
+                        let body = compile_block(
+                            std::slice::from_ref(body),
+                            optimizer,
+                            self.frames.clone(),
+                        )?;
+                        Ok((pattern.clone(), body))
+                    })
+                    .collect::<Result<_>>()?;
+                self.code.matches.push(clauses);
+                self.emit(Instruction::Match(self.code.matches.len() as u32 - 1));
+            }
+            Expression::Guard(guard) => {
+                let (variable, clauses, body) = guard.as_ref();
+                let handler = self.optimizer.enter(vec![variable.clone()]);
+                // the clauses run in an environment of their own binding the condition
+                let mut clause_frames = self.frames.clone();
+                clause_frames.push(Frame {
+                    variables: vec![variable.clone()],
+                    fixed: vec![true],
+                });
+                let clauses = clauses
+                    .iter()
+                    .map(|GuardClause(test, body)| {
+                        let test = match test {
+                            Some(test) => Some(compile_block(
+                                std::slice::from_ref(test),
+                                handler.clone(),
+                                clause_frames.clone(),
+                            )?),
+                            None => None,
+                        };
+                        let body = match body.is_empty() {
+                            true => None,
+                            false => {
+                                Some(compile_block(body, handler.clone(), clause_frames.clone())?)
+                            }
+                        };
+                        Ok((test, body))
+                    })
+                    .collect::<Result<_>>()?;
+                self.code.guards.push(GuardHandler {
+                    variable: variable.clone(),
+                    body: compile_block(
+                        std::slice::from_ref(body),
+                        self.optimizer.clone(),
+                        self.frames.clone(),
+                    )?,
+                    clauses,
+                });
+                self.emit(Instruction::Guard(self.code.guards.len() as u32 - 1));
+            }
+            // the parts of quoted data are made afresh each time, as the tree-walker does
+            Expression::Datum(datum) => match datum.as_ref() {
+                Datum::List(_) | Datum::Pair(..) | Datum::Vector(_) | Datum::Bytevector(_) => {
+                    self.code.data.push(datum.as_ref().clone());
+                    self.emit(Instruction::Datum(self.code.data.len() as u32 - 1));
+                }
+                atom => self.constant(atom.into()),
+            },
+            Expression::Primitive(primitive) => {
+                self.emit(Instruction::Primitive(primitive.index()));
+            }
+            Expression::Boolean(value) => self.constant(ValueType::Boolean(*value)),
+            Expression::Character(value) => self.constant(ValueType::Character(*value)),
+            Expression::String(value) => self.constant(ValueType::String(value.clone())),
+            Expression::Integer(value) => self.constant(ValueType::Number(Number::Integer(*value))),
+            Expression::Real(number_literal) => self.constant(ValueType::Number(
+                Number::parse(number_literal, 10).unwrap(),
+            )),
+            Expression::Rational(a, b) => {
+                self.constant(ValueType::Number(Number::rational(*a, *b as i64)?))
+            }
+            Expression::Identifier(name) => {
+                let address = self.address(name);
+                let name = self.name(name);
+                match address {
+                    Some((depth, index)) => self.emit(Instruction::Local(name, depth, index)),
+                    None => self.emit(Instruction::Global(name, self.frames.len() as u32)),
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn compiling() -> Result<()> {
+    let mut forms = vec![];
+    read_source("(define (f x) (if x 1 'a))", None, |form, _| {
+        forms.push(form);
+        Ok(())
+    })
+    .unwrap();
+    let it = Interpreter::new();
+    let code = compile_statement(&forms[0], Optimizer::new(0, &it, &it.env))?;
+    assert_eq!(
+        code.instructions,
+        vec![
+            Instruction::FlatClosure(0, 0),
+            Instruction::Define(0),
+            Instruction::Return
+        ]
+    );
+    assert_eq!(
+        code.codes[0].instructions,
+        vec![
+            Instruction::Local(0, 0, 0),
+            Instruction::JumpUnless(4),
+            Instruction::Constant(0),
+            Instruction::Jump(5),
+            Instruction::Constant(1),
+            Instruction::Return
+        ]
+    );
+    assert_eq!(
+        code.to_string(),
+        "   0 FlatClosure(0, 0)\n   1 Define(0) ; f\n   2 Return\n(lambda (x) (if x 1 (quote a)))\n   0 Local(0, 0, 0) ; x\n   1 JumpUnless(4)\n   2 Constant(0) ; 1\n   3 Jump(5)\n   4 Constant(1) ; a\n   5 Return\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn fusing() -> Result<()> {
+    let mut forms = vec![];
+    read_source(
+        "(define (f g n) (if (< n 2) (g n 1) (g (f g (- n 1)) (if (= n 2) 1 2))))",
+        None,
+        |form, _| {
+            forms.push(form);
+            Ok(())
+        },
+    )
+    .unwrap();
+    let it = Interpreter::new();
+    let code = compile_statement(&forms[0], Optimizer::new(0, &it, &it.env))?;
+    // a constant last argument is pushed by the call, of a top level variable or not, but not
+    // one a jump goes to
+    assert_eq!(
+        code.codes[0].instructions,
+        vec![
+            Instruction::Local(1, 0, 1),
+            Instruction::ConstantCallGlobal(0, 0),
+            Instruction::JumpUnless(7),
+            Instruction::Local(2, 0, 0),
+            Instruction::Local(1, 0, 1),
+            Instruction::ConstantCall(1, 1),
+            Instruction::Jump(20),
+            Instruction::Local(2, 0, 0),
+            Instruction::Global(3, 1),
+            Instruction::Local(2, 0, 0),
+            Instruction::Local(1, 0, 1),
+            Instruction::ConstantCallGlobal(2, 2),
+            Instruction::Call(3),
+            Instruction::Local(1, 0, 1),
+            Instruction::ConstantCallGlobal(3, 4),
+            Instruction::JumpUnless(18),
+            Instruction::Constant(4),
+            Instruction::Jump(19),
+            Instruction::Constant(5),
+            Instruction::Call(5),
+            Instruction::Return
+        ]
+    );
+    for engine in [vm::Engine::TreeWalker, vm::Engine::Bytecode] {
+        it.engine.set(engine);
+        it.eval(
+            "(define (f g n) (if (< n 2) (g n 1) (g (f g (- n 1)) (if (= n 2) 1 2))))".chars(),
+        )?;
+        assert_eq!(
+            it.eval("(f + 5)".chars())?,
+            Some(ValueType::Number(Number::Integer(9)))
+        );
+    }
+    Ok(())
+}
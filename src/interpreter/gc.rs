@@ -0,0 +1,348 @@
+use crate::environment::Environment;
+use crate::interpreter::*;
+use std::rc::Weak;
+use std::time::{Duration, Instant};
+
+// values are reference counted, which frees everything but reference cycles: the collector
+// finds those by tracing from the objects a cycle may go through, the pairs and vectors mutated
+// and the environments closed over, as a cycle can only be made by changing an object after it
+// is made to refer to one made after it
+// an object is alive when it has more references than those traced to it, held by the host,
+// the stacks of the evaluators or objects not traced such as hash tables, and so is what it
+// refers to; the rest are garbage and emptied, which frees them
+
+#[derive(Clone)]
+enum Object {
+    Pair(Rc<RefCell<Pair>>),
+    Vector(Rc<RefCell<Vec<ValueType>>>),
+    Environment(Rc<Environment>),
+}
+
+enum WeakObject {
+    Pair(Weak<RefCell<Pair>>),
+    Vector(Weak<RefCell<Vec<ValueType>>>),
+    Environment(Weak<Environment>),
+}
+
+// collections run once as many objects were registered as were alive after the last, and
+// at least this many
+const MIN_COLLECTION: usize = 1024;
+
+struct Roots {
+    objects: Vec<WeakObject>,
+    // the registrations since the last collection
+    registered: usize,
+    // the number of objects the freed ones are let go of at
+    limit: usize,
+}
+
+thread_local! {
+    static ROOTS: RefCell<Roots> = const {
+        RefCell::new(Roots {
+            objects: vec![],
+            registered: 0,
+            limit: MIN_COLLECTION,
+        })
+    };
+}
+
+// what the interpreters of a thread allocated and collected, which runtime-statistics reports
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HeapStatistics {
+    // the bytes of the pairs, vectors, bytevectors and environments made, freed or not
+    pub allocated: u64,
+    pub collections: u64,
+    // the time spent collecting
+    pub pause: Duration,
+}
+
+thread_local! {
+    static STATISTICS: Cell<HeapStatistics> = const {
+        Cell::new(HeapStatistics {
+            allocated: 0,
+            collections: 0,
+            pause: Duration::ZERO,
+        })
+    };
+}
+
+// counts an object of a reference counted value made
+pub(crate) fn allocated<T: ?Sized>(value: &T, extra: usize) {
+    // the counts are kept next to the value
+    let bytes = std::mem::size_of_val(value) + 2 * std::mem::size_of::<usize>() + extra;
+    STATISTICS.with(|statistics| {
+        let mut current = statistics.get();
+        current.allocated += bytes as u64;
+        statistics.set(current);
+    })
+}
+
+pub fn heap_statistics() -> HeapStatistics {
+    STATISTICS.with(Cell::get)
+}
+
+impl Object {
+    fn address(&self) -> usize {
+        match self {
+            Object::Pair(pair) => Rc::as_ptr(pair) as *const () as usize,
+            Object::Vector(vector) => Rc::as_ptr(vector) as *const () as usize,
+            Object::Environment(env) => Rc::as_ptr(env) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Pair(pair) => Rc::strong_count(pair),
+            Object::Vector(vector) => Rc::strong_count(vector),
+            Object::Environment(env) => Rc::strong_count(env),
+        }
+    }
+
+    fn downgrade(&self) -> WeakObject {
+        match self {
+            Object::Pair(pair) => WeakObject::Pair(Rc::downgrade(pair)),
+            Object::Vector(vector) => WeakObject::Vector(Rc::downgrade(vector)),
+            Object::Environment(env) => WeakObject::Environment(Rc::downgrade(env)),
+        }
+    }
+
+    // the objects this refers to, none if it is being changed, which keeps it alive
+    fn references(&self) -> Option<Vec<Object>> {
+        let mut references = vec![];
+        match self {
+            Object::Pair(pair) => {
+                let pair = pair.try_borrow().ok()?;
+                referred(&pair.car, &mut references);
+                referred(&pair.cdr, &mut references);
+            }
+            Object::Vector(vector) => {
+                for element in vector.try_borrow().ok()?.iter() {
+                    referred(element, &mut references);
+                }
+            }
+            Object::Environment(env) => {
+                if !env.trace(|value| referred(value, &mut references)) {
+                    return None;
+                }
+                if let Some(parent) = env.parent() {
+                    references.push(Object::Environment(parent.clone()));
+                }
+            }
+        }
+        Some(references)
+    }
+
+    // returns what the object referred to, to be dropped once every garbage object is emptied
+    fn empty(&self) -> Vec<ValueType> {
+        match self {
+            Object::Pair(pair) => {
+                let mut pair = pair.borrow_mut();
+                vec![
+                    std::mem::replace(&mut pair.car, ValueType::EmptyList),
+                    std::mem::replace(&mut pair.cdr, ValueType::EmptyList),
+                ]
+            }
+            Object::Vector(vector) => std::mem::take(&mut *vector.borrow_mut()),
+            Object::Environment(env) => env.clear(),
+        }
+    }
+}
+
+impl WeakObject {
+    fn upgrade(&self) -> Option<Object> {
+        Some(match self {
+            WeakObject::Pair(pair) => Object::Pair(pair.upgrade()?),
+            WeakObject::Vector(vector) => Object::Vector(vector.upgrade()?),
+            WeakObject::Environment(env) => Object::Environment(env.upgrade()?),
+        })
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            WeakObject::Pair(pair) => pair.as_ptr() as *const () as usize,
+            WeakObject::Vector(vector) => vector.as_ptr() as *const () as usize,
+            WeakObject::Environment(env) => env.as_ptr() as *const () as usize,
+        }
+    }
+
+    fn alive(&self) -> bool {
+        match self {
+            WeakObject::Pair(pair) => pair.strong_count() > 0,
+            WeakObject::Vector(vector) => vector.strong_count() > 0,
+            WeakObject::Environment(env) => env.strong_count() > 0,
+        }
+    }
+}
+
+fn referred(value: &ValueType, references: &mut Vec<Object>) {
+    match value {
+        ValueType::Pair(pair) => references.push(Object::Pair(pair.clone())),
+        ValueType::Vector(vector) => references.push(Object::Vector(vector.clone())),
+        ValueType::Procedure(Procedure::User(_, env))
+        | ValueType::Procedure(Procedure::Compiled(_, env)) => {
+            references.push(Object::Environment(env.clone()))
+        }
+        ValueType::Values(values) => values.iter().for_each(|v| referred(v, references)),
+        _ => (),
+    }
+}
+
+fn register(object: WeakObject) {
+    ROOTS.with(|roots| {
+        let mut roots = roots.borrow_mut();
+        // as when closures are made in a loop
+        if roots.objects.last().map(WeakObject::address) == Some(object.address()) {
+            return;
+        }
+        roots.objects.push(object);
+        roots.registered += 1;
+        // the objects freed since are let go of, as a weak reference keeps their memory
+        if roots.objects.len() > roots.limit {
+            roots.objects.retain(WeakObject::alive);
+            roots.limit = MIN_COLLECTION.max(roots.objects.len() * 2);
+        }
+    })
+}
+
+pub(crate) fn register_pair(pair: &Rc<RefCell<Pair>>) {
+    register(WeakObject::Pair(Rc::downgrade(pair)))
+}
+
+pub(crate) fn register_vector(vector: &Rc<RefCell<Vec<ValueType>>>) {
+    register(WeakObject::Vector(Rc::downgrade(vector)))
+}
+
+pub(crate) fn register_environment(env: &Rc<Environment>) {
+    register(WeakObject::Environment(Rc::downgrade(env)))
+}
+
+pub(crate) fn collection_due() -> bool {
+    ROOTS.with(|roots| {
+        let roots = roots.borrow();
+        roots.registered >= MIN_COLLECTION.max(roots.objects.len().saturating_sub(roots.registered))
+    })
+}
+
+// frees the objects in reference cycles nothing else refers to, returning how many
+pub(crate) fn collect() -> usize {
+    let start = Instant::now();
+    let garbage = collect_cycles();
+    STATISTICS.with(|statistics| {
+        let mut current = statistics.get();
+        current.collections += 1;
+        current.pause += start.elapsed();
+        statistics.set(current);
+    });
+    garbage
+}
+
+fn collect_cycles() -> usize {
+    let registered = ROOTS.with(|roots| std::mem::take(&mut roots.borrow_mut().objects));
+    let mut objects: Vec<Object> = vec![];
+    let mut found: HashMap<usize, usize> = HashMap::new();
+    for object in registered.iter().filter_map(WeakObject::upgrade) {
+        if let std::collections::hash_map::Entry::Vacant(entry) = found.entry(object.address()) {
+            entry.insert(objects.len());
+            objects.push(object);
+        }
+    }
+    let roots = objects.len();
+    drop(registered);
+    // the objects reachable from the roots, with the references traced to each
+    let mut traced: Vec<usize> = vec![0; objects.len()];
+    let mut edges: Vec<Option<Vec<usize>>> = vec![];
+    let mut next = 0;
+    while next < objects.len() {
+        let references = objects[next].references();
+        edges.push(references.map(|references| {
+            references
+                .into_iter()
+                .map(|reference| {
+                    let index = *found.entry(reference.address()).or_insert_with(|| {
+                        objects.push(reference);
+                        traced.push(0);
+                        objects.len() - 1
+                    });
+                    traced[index] += 1;
+                    index
+                })
+                .collect()
+        }));
+        next += 1;
+    }
+    // the references held here, one to each object, are not counted
+    let mut alive: Vec<bool> = objects
+        .iter()
+        .zip(&edges)
+        .zip(&traced)
+        .map(|((object, edges), &traced)| edges.is_none() || object.strong_count() - 1 > traced)
+        .collect();
+    let mut pending: Vec<usize> = (0..objects.len()).filter(|&i| alive[i]).collect();
+    while let Some(index) = pending.pop() {
+        for &reference in edges[index].iter().flatten() {
+            if !alive[reference] {
+                alive[reference] = true;
+                pending.push(reference);
+            }
+        }
+    }
+    let mut freed = vec![];
+    let mut garbage = 0;
+    for (object, &alive) in objects.iter().zip(&alive) {
+        if !alive {
+            freed.extend(object.empty());
+            garbage += 1;
+        }
+    }
+    // the roots alive may be in cycles later
+    let kept: Vec<WeakObject> = objects[..roots]
+        .iter()
+        .zip(&alive)
+        .filter(|(_, &alive)| alive)
+        .map(|(object, _)| object.downgrade())
+        .collect();
+    drop(objects);
+    drop(freed);
+    ROOTS.with(|roots| {
+        let mut roots = roots.borrow_mut();
+        roots.objects.extend(kept);
+        roots.registered = 0;
+        roots.limit = MIN_COLLECTION.max(roots.objects.len() * 2);
+    });
+    garbage
+}
+
+#[test]
+fn collecting() {
+    let interpreter = Interpreter::new();
+    let global = |name: &str| interpreter.env.get(name).unwrap();
+    interpreter
+        .eval_source("(define l (list 1 2)) (set-cdr! (cdr l) l)")
+        .unwrap();
+    let list = match global("l") {
+        ValueType::Pair(pair) => Rc::downgrade(&pair),
+        _ => unreachable!(),
+    };
+    interpreter
+        .eval_source("(define (make) (define (loop) loop) loop) (define g (make))")
+        .unwrap();
+    let frame = match global("g") {
+        ValueType::Procedure(Procedure::Compiled(_, env)) => Rc::downgrade(&env),
+        _ => unreachable!(),
+    };
+    // what is referred to from elsewhere, here from the host, is kept
+    let kept = global("g");
+    interpreter
+        .eval_source("(define l 0) (define g 0)")
+        .unwrap();
+    interpreter.collect_garbage();
+    assert!(list.upgrade().is_none());
+    assert!(frame.upgrade().is_some());
+    assert_eq!(interpreter.call_procedure(&kept, vec![]).unwrap(), kept);
+    drop(kept);
+    interpreter.collect_garbage();
+    assert!(frame.upgrade().is_none());
+    let env = Rc::downgrade(&interpreter.env);
+    drop(interpreter);
+    assert!(env.upgrade().is_none());
+}
@@ -3,161 +3,72 @@ use crate::environment::Environment;
 use crate::error::*;
 use crate::lexer::*;
 use crate::parser::*;
-use std::cell::RefCell;
-use std::cmp::Ordering;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::rc::Rc;
 
-use std::iter::Iterator;
+use std::iter::{Iterator, Peekable};
 
 type Result<T> = std::result::Result<T, Error>;
 
 macro_rules! logic_error {
     ($($arg:tt)*) => (
-        return Err(Error {category: ErrorType::Logic , message: format!($($arg)*) });
+        return Err(Error {category: ErrorType::Logic , message: format!($($arg)*) })
     )
 }
 
-pub mod scheme;
-
-#[derive(Debug, Clone, Copy)]
-pub enum Number {
-    Integer(i64),
-    Real(f64),
-    Rational(i64, i64),
-}
-
-impl PartialEq for Number {
-    fn eq(&self, other: &Number) -> bool {
-        match upcast_oprands((*self, *other)) {
-            NumberBinaryOperand::Integer(a, b) => a.eq(&b),
-            NumberBinaryOperand::Rational(a1, a2, b1, b2) => (a1 * b2).eq(&(b1 * a2)),
-            NumberBinaryOperand::Real(a, b) => a.eq(&b),
-        }
-    }
-}
-
-impl PartialOrd for Number {
-    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
-        match upcast_oprands((*self, *other)) {
-            NumberBinaryOperand::Integer(a, b) => a.partial_cmp(&b),
-            NumberBinaryOperand::Rational(a1, a2, b1, b2) => (a1 * b2).partial_cmp(&(b1 * a2)),
-            NumberBinaryOperand::Real(a, b) => a.partial_cmp(&b),
-        }
-    }
-}
-
-enum NumberBinaryOperand {
-    Integer(i64, i64),
-    Real(f64, f64),
-    Rational(i64, i64, i64, i64),
-}
-
-// Integer => Rational => Real
-fn upcast_oprands(operand: (Number, Number)) -> NumberBinaryOperand {
-    match operand {
-        (Number::Rational(dividend, dividor), Number::Real(b)) => {
-            NumberBinaryOperand::Real(dividend as f64 / dividor as f64, b)
-        }
-        (Number::Real(a), Number::Rational(dividend, dividor)) => {
-            NumberBinaryOperand::Real(a, dividend as f64 / dividor as f64)
-        }
-        (Number::Integer(a), Number::Real(b)) => (NumberBinaryOperand::Real(a as f64, b)),
-        (Number::Real(a), Number::Integer(b)) => (NumberBinaryOperand::Real(a, b as f64)),
-        (Number::Rational(dividend, dividor), Number::Integer(b)) => {
-            NumberBinaryOperand::Rational(dividend, dividor, b, 1)
-        }
-        (Number::Integer(a), Number::Rational(dividend, dividor)) => {
-            NumberBinaryOperand::Rational(a, 1, dividend, dividor)
-        }
-        (Number::Integer(a), Number::Integer(b)) => (NumberBinaryOperand::Integer(a, b)),
-        (Number::Real(a), Number::Real(b)) => (NumberBinaryOperand::Real(a, b)),
-        (Number::Rational(a1, a2), Number::Rational(b1, b2)) => {
-            NumberBinaryOperand::Rational(a1, a2, b1, b2)
-        }
-    }
-}
-
-impl NumberBinaryOperand {
-    pub fn lhs(&self) -> Number {
-        match self {
-            NumberBinaryOperand::Integer(a, _) => Number::Integer(*a),
-            NumberBinaryOperand::Real(a, _) => Number::Real(*a),
-            NumberBinaryOperand::Rational(a1, a2, _, _) => Number::Rational(*a1, *a2),
-        }
-    }
-
-    pub fn rhs(&self) -> Number {
-        match self {
-            NumberBinaryOperand::Integer(_, b) => Number::Integer(*b),
-            NumberBinaryOperand::Real(_, b) => Number::Real(*b),
-            NumberBinaryOperand::Rational(_, _, b1, b2) => Number::Rational(*b1, *b2),
-        }
-    }
+macro_rules! function_mapping {
+    ($ident:expr, $function:tt) => {
+        (
+            $ident.to_string(),
+            ValueType::Procedure(Procedure::Buildin(BuildinProcedure(
+                $ident,
+                |arguments, _| $function(arguments),
+            ))),
+        )
+    };
 }
 
-impl std::ops::Add<Number> for Number {
-    type Output = Number;
-    fn add(self, rhs: Number) -> Number {
-        match upcast_oprands((self, rhs)) {
-            NumberBinaryOperand::Integer(a, b) => Number::Integer(a + b),
-            NumberBinaryOperand::Real(a, b) => Number::Real(a + b),
-            NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
-                Number::Rational(a1 * b2 + a2 * b1, a2 * b2)
-            }
-        }
-    }
+// for procedures calling back into the interpreter, like those taking procedure arguments
+macro_rules! interpreter_function_mapping {
+    ($ident:expr, $function:tt) => {
+        (
+            $ident.to_string(),
+            ValueType::Procedure(Procedure::Buildin(BuildinProcedure($ident, $function))),
+        )
+    };
 }
 
-impl std::ops::Sub<Number> for Number {
-    type Output = Number;
-    fn sub(self, rhs: Number) -> Number {
-        match upcast_oprands((self, rhs)) {
-            NumberBinaryOperand::Integer(a, b) => Number::Integer(a - b),
-            NumberBinaryOperand::Real(a, b) => Number::Real(a - b),
-            NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
-                Number::Rational(a1 * b2 - a2 * b1, a2 * b2)
-            }
-        }
-    }
-}
+pub mod bigint;
+pub mod bytecode;
+pub mod compiler;
+pub mod gc;
+pub mod number;
+pub mod optimizer;
+pub mod port;
+pub mod printer;
+pub mod ruschm;
+pub mod scheme;
+pub mod srfi;
+pub mod vm;
 
-impl std::ops::Mul<Number> for Number {
-    type Output = Number;
-    fn mul(self, rhs: Number) -> Number {
-        match upcast_oprands((self, rhs)) {
-            NumberBinaryOperand::Integer(a, b) => Number::Integer(a * b),
-            NumberBinaryOperand::Real(a, b) => Number::Real(a * b),
-            NumberBinaryOperand::Rational(a1, a2, b1, b2) => Number::Rational(a1 * b1, a2 * b2),
-        }
-    }
-}
+pub use number::Number;
+pub use port::Port;
+pub use printer::{Displayed, External, Labeling, Limits};
+pub use ruschm::display::DisplayHandler;
+pub use ruschm::log::{LogFilter, LogHandler};
+pub use scheme::process_context::EnvironmentVariables;
+pub use vm::Engine;
 
-impl std::ops::Div<Number> for Number {
-    type Output = Result<Number>;
-    fn div(self, rhs: Number) -> Result<Number> {
-        match upcast_oprands((self, rhs)) {
-            NumberBinaryOperand::Integer(a, b) => {
-                check_division_by_zero(b)?;
-                match a % b {
-                    0 => Ok(Number::Integer(a / b)),
-                    _ => Ok(Number::Rational(a, b)),
-                }
-            }
-            NumberBinaryOperand::Real(a, b) => Ok(Number::Real(a / b)),
-            NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
-                check_division_by_zero(b1)?;
-                check_division_by_zero(a2)?;
-                check_division_by_zero(b2)?;
-                Ok(Number::Rational(a1 * b2, a2 * b1))
-            }
-        }
-    }
-}
+// arguments are evaluated lazily as the procedure consumes them
+pub type Arguments<'a> = Box<dyn Iterator<Item = Result<ValueType>> + 'a>;
 
+// the interpreter is used by procedures calling back into scheme
 #[derive(Clone)]
 pub struct BuildinProcedure(
     &'static str,
-    fn(Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType>,
+    fn(Arguments, &Interpreter) -> Result<ValueType>,
 );
 
 impl fmt::Display for BuildinProcedure {
@@ -179,94 +90,619 @@ impl PartialEq for BuildinProcedure {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Procedure {
-    User(SchemeProcedure),
+    User(Rc<SchemeProcedure>, Rc<Environment>),
+    // a procedure made by compiled code, run by the vm
+    Compiled(Rc<compiler::Code>, Rc<Environment>),
     Buildin(BuildinProcedure),
 }
 
+impl Procedure {
+    // the lambda of a procedure defined in scheme, none for builtins
+    pub fn scheme_procedure(&self) -> Option<&SchemeProcedure> {
+        match self {
+            Procedure::User(procedure, _) => Some(procedure),
+            Procedure::Compiled(code, _) => code.procedure.as_ref(),
+            Procedure::Buildin(_) => None,
+        }
+    }
+}
+
 impl fmt::Display for Procedure {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Procedure::User(procedure) => write!(f, "{}", procedure),
+            Procedure::User(procedure, _) => write!(f, "{}", procedure),
+            Procedure::Compiled(code, _) => write!(f, "{}", code.procedure.as_ref().unwrap()),
             Procedure::Buildin(fp) => write!(f, "{}", fp),
         }
     }
 }
 
+// numbers, booleans, characters, the empty list and the other constants are held in the value
+// itself, so making or copying them never allocates, and symbols share the interned names of
+// the lexer, where the common ones are kept for good
 #[derive(Debug, Clone, PartialEq)]
 pub enum ValueType {
     Number(Number),
     Boolean(bool),
-    Datum(Box<Statement>),
+    Character(char),
+    String(String),
+    Symbol(Name),
+    Pair(Rc<RefCell<Pair>>),
+    EmptyList,
     Procedure(Procedure),
-    Vector(Vec<ValueType>),
+    Vector(Rc<RefCell<Vec<ValueType>>>),
+    Bytevector(Rc<RefCell<Vec<u8>>>),
+    Port(Rc<Port>),
+    ErrorObject(Rc<ErrorObject>),
+    Time(srfi::srfi_19::Time),
+    Date(Box<srfi::srfi_19::Date>),
+    RandomSource(Rc<RefCell<srfi::srfi_27::RandomSource>>),
+    HashTable(Rc<RefCell<srfi::srfi_69::HashTable>>),
+    Comparator(Rc<srfi::srfi_128::Comparator>),
+    // a set or a bag of SRFI 113
+    Collection(Rc<srfi::srfi_113::Collection>),
+    Promise(Rc<RefCell<scheme::lazy::Promise>>),
+    // the states closed over by SRFI 158 generator and accumulator procedures
+    Generator(Rc<RefCell<srfi::srfi_158::Generator>>),
+    Accumulator(Rc<RefCell<srfi::srfi_158::Accumulator>>),
+    Regexp(Rc<ruschm::regexp::Regexp>),
+    RegexpMatch(Rc<ruschm::regexp::RegexpMatch>),
+    HttpResponse(Rc<ruschm::http::HttpResponse>),
+    Socket(Rc<ruschm::socket::Socket>),
+    // the cache closed over by a memoized procedure
+    Memo(Rc<RefCell<ruschm::memoize::Memo>>),
+    // the end of file object returned by input procedures
+    Eof,
+    // multiple values returned by `values` and the R7RS integer division procedures
+    Values(Vec<ValueType>),
     Void,
 }
 
+// written with datum labels for cycles, so printing circular structures terminates
 impl fmt::Display for ValueType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", External::new(self, true, Labeling::Cycles))
+    }
+}
+
+// raised by error, or made from a failing builtin when guard catches it
+#[derive(Debug, PartialEq)]
+pub struct ErrorObject {
+    pub category: ErrorType,
+    pub message: String,
+    pub irritants: Vec<ValueType>,
+}
+
+impl ErrorObject {
+    // the message followed by the written irritants, as reported when uncaught
+    pub fn description(&self) -> String {
+        std::iter::once(self.message.clone())
+            .chain(
+                self.irritants
+                    .iter()
+                    .map(|irritant| format!("{}", irritant)),
+            )
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// raising an object which is not an error object is reported with its written form
+pub(crate) fn raise(value: ValueType) -> Error {
+    let message = match &value {
+        ValueType::ErrorObject(object) => object.description(),
+        other => format!("uncaught exception {}", other),
+    };
+    Error {
+        category: ErrorType::Raise(Box::new(value)),
+        message,
+    }
+}
+
+// the object guard binds for an error, raised objects are handed back unchanged
+pub(crate) fn condition_object(error: &Error) -> ValueType {
+    match &error.category {
+        ErrorType::Raise(value) => value.as_ref().clone(),
+        category => ValueType::ErrorObject(Rc::new(ErrorObject {
+            category: category.clone(),
+            message: error.message.clone(),
+            irritants: vec![],
+        })),
+    }
+}
+
+// the builtin a derived form calls
+pub(crate) fn primitive(primitive: Primitive) -> ValueType {
+    let procedure = match primitive {
+        Primitive::Time => BuildinProcedure(primitive.name(), scheme::time::time_thunk),
+        Primitive::CallWithValues => {
+            BuildinProcedure(primitive.name(), scheme::base::call_with_values)
+        }
+        Primitive::CallWithRestValues => {
+            BuildinProcedure(primitive.name(), scheme::base::call_with_rest_values)
+        }
+    };
+    ValueType::Procedure(Procedure::Buildin(procedure))
+}
+
+// a procedure closing over native state, as random sources, generators, memoized and traced
+// procedures do: a call passes the state and then its arguments to next, with the state
+// bound under generated names
+pub(crate) fn native_closure(
+    interpreter: &Interpreter,
+    state: Vec<ValueType>,
+    next: BuildinProcedure,
+    formals: Vec<String>,
+    origin: Option<Rc<Origin>>,
+) -> ValueType {
+    let env = Environment::child(interpreter.env.clone());
+    let mut arguments = vec![];
+    for (index, value) in state.into_iter().enumerate() {
+        let name = generated_name(&format!("state {}", index));
+        env.define(name.to_string(), value);
+        arguments.push(Expression::Identifier(name));
+    }
+    let next_name = generated_name("next procedure");
+    env.define(
+        next_name.to_string(),
+        ValueType::Procedure(Procedure::Buildin(next)),
+    );
+    arguments.extend(
+        formals
+            .iter()
+            .map(|formal| Expression::Identifier(intern(formal))),
+    );
+    let body = Expression::ProcedureCall(Box::new(Expression::Identifier(next_name)), arguments);
+    ValueType::Procedure(Procedure::User(
+        Rc::new(SchemeProcedure(formals, vec![], vec![body], origin)),
+        Rc::new(env),
+    ))
+}
+
+// the formals of a native closure taking any arguments the wrapped procedure takes
+pub(crate) fn hidden_formals(arity: usize) -> Vec<String> {
+    (0..arity)
+        .map(|index| generated_name(&format!("argument {}", index)).to_string())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pair {
+    pub car: ValueType,
+    pub cdr: ValueType,
+}
+
+// unlinks uniquely owned list spines one pair at a time, as dropping them recursively
+// overflows the stack on long lists, forced streams are spines of pairs behind promises
+impl Drop for Pair {
+    fn drop(&mut self) {
+        let mut cdr = std::mem::replace(&mut self.cdr, ValueType::EmptyList);
+        loop {
+            cdr = match cdr {
+                ValueType::Pair(pair) => match Rc::try_unwrap(pair) {
+                    Ok(pair) => std::mem::replace(&mut pair.borrow_mut().cdr, ValueType::EmptyList),
+                    Err(_) => break,
+                },
+                ValueType::Promise(promise) => match Rc::try_unwrap(promise) {
+                    Ok(promise) => match promise.into_inner() {
+                        scheme::lazy::Promise::Forced(value) => value,
+                        _ => break,
+                    },
+                    Err(_) => break,
+                },
+                _ => break,
+            }
+        }
+    }
+}
+
+// non-pair objects are improper lists of no pairs
+pub enum ListShape {
+    Proper(usize),
+    Improper,
+    Circular,
+}
+
+impl ValueType {
+    pub fn cons(car: ValueType, cdr: ValueType) -> ValueType {
+        let pair = Rc::new(RefCell::new(Pair { car, cdr }));
+        gc::allocated(&*pair, 0);
+        ValueType::Pair(pair)
+    }
+
+    pub fn list(elements: impl DoubleEndedIterator<Item = ValueType>) -> ValueType {
+        Self::list_with_tail(elements, ValueType::EmptyList)
+    }
+
+    // an improper list if the tail is not the empty list
+    pub fn list_with_tail(
+        elements: impl DoubleEndedIterator<Item = ValueType>,
+        tail: ValueType,
+    ) -> ValueType {
+        elements.rev().fold(tail, |cdr, car| Self::cons(car, cdr))
+    }
+
+    pub fn list_shape(&self) -> ListShape {
+        let mut length = 0;
+        let mut slow = self.clone();
+        let mut fast = self.clone();
+        loop {
+            for _ in 0..2 {
+                fast = match fast {
+                    ValueType::EmptyList => return ListShape::Proper(length),
+                    ValueType::Pair(pair) => {
+                        length += 1;
+                        pair.borrow().cdr.clone()
+                    }
+                    _ => return ListShape::Improper,
+                };
+            }
+            if let ValueType::Pair(pair) = slow {
+                slow = pair.borrow().cdr.clone();
+            }
+            if let (ValueType::Pair(a), ValueType::Pair(b)) = (&slow, &fast) {
+                if Rc::ptr_eq(a, b) {
+                    return ListShape::Circular;
+                }
+            }
+        }
+    }
+
+    // the element count of a proper list, None for improper or circular lists
+    pub fn list_length(&self) -> Option<usize> {
+        match self.list_shape() {
+            ListShape::Proper(length) => Some(length),
+            _ => None,
+        }
+    }
+
+    // the elements of a proper list, None for improper or circular lists
+    pub fn list_to_vec(&self) -> Option<Vec<ValueType>> {
+        let mut elements = Vec::with_capacity(self.list_length()?);
+        let mut current = self.clone();
+        while let ValueType::Pair(pair) = current {
+            elements.push(pair.borrow().car.clone());
+            current = pair.borrow().cdr.clone();
+        }
+        Some(elements)
+    }
+
+    // the text of a symbol or a string, as procedures taking names accept either
+    pub(crate) fn text(&self) -> Option<&str> {
         match self {
-            ValueType::Number(num) => match num {
-                Number::Integer(n) => write!(f, "{}", n),
-                Number::Real(n) => write!(f, "{:?}", n),
-                Number::Rational(a, b) => write!(f, "{}/{}", a, b),
-            },
-            ValueType::Datum(expr) => write!(f, "{}", expr),
-            ValueType::Procedure(p) => write!(f, "{}", p),
-            ValueType::Void => write!(f, "Void"),
-            ValueType::Boolean(true) => write!(f, "#t"),
-            ValueType::Boolean(false) => write!(f, "#f"),
-            ValueType::Vector(vec) => write!(
-                f,
-                "#({})",
-                vec.iter()
-                    .map(|v| format!("{}", v))
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            ),
+            ValueType::Symbol(symbol) => Some(symbol),
+            ValueType::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    // every value other than #f counts as true
+    pub fn is_true(&self) -> bool {
+        !matches!(self, ValueType::Boolean(false))
+    }
+
+    pub fn vector(elements: Vec<ValueType>) -> ValueType {
+        let extra = elements.capacity() * std::mem::size_of::<ValueType>();
+        let vector = Rc::new(RefCell::new(elements));
+        gc::allocated(&*vector, extra);
+        ValueType::Vector(vector)
+    }
+
+    pub fn bytevector(bytes: Vec<u8>) -> ValueType {
+        let extra = bytes.capacity();
+        let bytes = Rc::new(RefCell::new(bytes));
+        gc::allocated(&*bytes, extra);
+        ValueType::Bytevector(bytes)
+    }
+
+    // strings are values rather than mutable objects, so they are compared by content
+    pub fn eqv(&self, other: &ValueType) -> bool {
+        match (self, other) {
+            (ValueType::Number(Number::Real(a)), ValueType::Number(Number::Real(b))) => {
+                (a == b && a.is_sign_negative() == b.is_sign_negative())
+                    || (a.is_nan() && b.is_nan())
+            }
+            (ValueType::Number(a), ValueType::Number(b)) => a.is_exact() == b.is_exact() && a == b,
+            (ValueType::Pair(a), ValueType::Pair(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Vector(a), ValueType::Vector(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Bytevector(a), ValueType::Bytevector(b)) => Rc::ptr_eq(a, b),
+            (ValueType::ErrorObject(a), ValueType::ErrorObject(b)) => Rc::ptr_eq(a, b),
+            (ValueType::RandomSource(a), ValueType::RandomSource(b)) => Rc::ptr_eq(a, b),
+            (ValueType::HashTable(a), ValueType::HashTable(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Comparator(a), ValueType::Comparator(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Collection(a), ValueType::Collection(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Promise(a), ValueType::Promise(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Generator(a), ValueType::Generator(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Accumulator(a), ValueType::Accumulator(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Regexp(a), ValueType::Regexp(b)) => Rc::ptr_eq(a, b),
+            (ValueType::RegexpMatch(a), ValueType::RegexpMatch(b)) => Rc::ptr_eq(a, b),
+            (ValueType::HttpResponse(a), ValueType::HttpResponse(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Socket(a), ValueType::Socket(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Memo(a), ValueType::Memo(b)) => Rc::ptr_eq(a, b),
+            (ValueType::Values(_), _) | (_, ValueType::Values(_)) => false,
+            (a, b) => a == b,
+        }
+    }
+
+    pub fn equal(&self, other: &ValueType) -> bool {
+        self.equal_visited(other, &mut HashSet::new())
+    }
+
+    // objects already under comparison are assumed equal, which terminates on circular structures
+    // lists are walked along their cdrs rather than recursing, however long they are
+    fn equal_visited(&self, other: &ValueType, visited: &mut HashSet<(usize, usize)>) -> bool {
+        match (self, other) {
+            (ValueType::Pair(_), ValueType::Pair(_)) => {
+                let (mut a, mut b) = (self.clone(), other.clone());
+                loop {
+                    let (cdr_a, cdr_b) = match (&a, &b) {
+                        (ValueType::Pair(a), ValueType::Pair(b)) => {
+                            if Rc::ptr_eq(a, b)
+                                || !visited.insert((a.as_ptr() as usize, b.as_ptr() as usize))
+                            {
+                                return true;
+                            }
+                            let (a, b) = (a.borrow(), b.borrow());
+                            if !a.car.equal_visited(&b.car, visited) {
+                                return false;
+                            }
+                            (a.cdr.clone(), b.cdr.clone())
+                        }
+                        (a, b) => return a.equal_visited(b, visited),
+                    };
+                    a = cdr_a;
+                    b = cdr_b;
+                }
+            }
+            (ValueType::Vector(a), ValueType::Vector(b)) => {
+                if Rc::ptr_eq(a, b) || !visited.insert((a.as_ptr() as usize, b.as_ptr() as usize)) {
+                    return true;
+                }
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(a, b)| a.equal_visited(b, visited))
+            }
+            (ValueType::Bytevector(a), ValueType::Bytevector(b)) => a == b,
+            (a, b) => a.eqv(b),
+        }
+    }
+}
+
+impl From<&Datum> for ValueType {
+    fn from(datum: &Datum) -> Self {
+        match datum {
+            Datum::Boolean(b) => ValueType::Boolean(*b),
+            Datum::Integer(n) => ValueType::Number(Number::Integer(*n)),
+            Datum::Real(n) => ValueType::Number(Number::parse(n, 10).unwrap()),
+            // the lexer never produces a zero denominator
+            Datum::Rational(a, b) => ValueType::Number(Number::rational(*a, *b as i64).unwrap()),
+            Datum::Character(c) => ValueType::Character(*c),
+            Datum::String(s) => ValueType::String(s.clone()),
+            Datum::Symbol(s) => ValueType::Symbol(s.clone()),
+            Datum::List(list) => ValueType::list(list.iter().map(ValueType::from)),
+            Datum::Pair(list, tail) => {
+                ValueType::list_with_tail(list.iter().map(ValueType::from), tail.as_ref().into())
+            }
+            Datum::Vector(vector) => {
+                ValueType::vector(vector.iter().map(ValueType::from).collect())
+            }
+            Datum::Bytevector(bytes) => ValueType::bytevector(bytes.clone()),
         }
     }
 }
 
-fn check_division_by_zero(num: i64) -> Result<()> {
-    match num {
-        0 => logic_error!("division by exact zero"),
-        _ => Ok(()),
+pub struct Interpreter {
+    env: Rc<Environment>,
+    // the current ports, which are parameters of the dynamic extent
+    pub current_input_port: RefCell<Rc<Port>>,
+    pub current_output_port: RefCell<Rc<Port>>,
+    pub current_error_port: RefCell<Rc<Port>>,
+    // the host may hide environment variables from embedded code
+    pub environment_variables: RefCell<EnvironmentVariables>,
+    // the script name and its arguments, as returned by command-line
+    pub command_line: RefCell<Vec<String>>,
+    // the host may let embedded code run other programs, which it may not by default
+    pub allow_subprocesses: Cell<bool>,
+    // or connect to the network
+    pub allow_network: Cell<bool>,
+    // which log records are kept, and where they go instead of the current error port
+    pub log_filter: RefCell<LogFilter>,
+    pub log_handler: RefCell<Option<LogHandler>>,
+    // where display-data shows its data instead of writing the text to the current output port
+    pub display_handler: RefCell<Option<DisplayHandler>>,
+    // the after thunks of the dynamic-wind calls being evaluated, innermost last
+    pub(crate) winds: RefCell<Vec<ValueType>>,
+    // the nesting of calls to traced procedures, which indents their trace
+    pub(crate) trace_depth: Cell<usize>,
+    // how deeply procedure calls may nest, so that runaway recursion is an error rather than
+    // a stack overflow
+    pub max_call_depth: Cell<usize>,
+    // the bytes of stack the thread evaluating has, calls failing once three quarters of it
+    // are used whatever their depth; the stack size of the thread by default
+    pub stack_size: Cell<usize>,
+    // where the stack was when the outermost call being evaluated was entered
+    stack_base: Cell<usize>,
+    // how many errors load_source reports before it stops reading, none for no limit
+    pub max_errors: Cell<Option<usize>>,
+    pub(crate) call_depth: Cell<usize>,
+    // how top level forms are evaluated
+    pub engine: Cell<Engine>,
+    // how much the bytecode compiler optimizes, up to optimizer::MAX_OPTIMIZATION_LEVEL
+    pub optimization_level: Cell<u8>,
+    // how many worker threads parallel-map runs callbacks on
+    pub parallelism: Cell<usize>,
+    // the located calls the last error of eval_source was raised in, innermost first
+    pub(crate) backtrace: RefCell<Vec<Frame>>,
+    // the expressions the tree-walker evaluated and the instructions the vm executed
+    pub(crate) evaluations: Cell<u64>,
+}
+
+const FIXNUM_OPERATORS: [&str; 5] = ["+", "-", "*", "<", "="];
+
+// the result of a fixnum operator on two fixnums, computed without building the arguments of
+// the builtin; none when the operands are other numbers, or the result overflows, for the
+// builtin to compute or report
+pub(crate) fn fixnum_operation(
+    procedure: &ValueType,
+    a: &ValueType,
+    b: &ValueType,
+) -> Option<ValueType> {
+    let (name, a, b) = match (procedure, a, b) {
+        (
+            ValueType::Procedure(Procedure::Buildin(BuildinProcedure(name, _))),
+            ValueType::Number(Number::Integer(a)),
+            ValueType::Number(Number::Integer(b)),
+        ) => (*name, *a, *b),
+        _ => return None,
+    };
+    let integer = |n| ValueType::Number(Number::Integer(n));
+    match name {
+        "+" => a.checked_add(b).map(integer),
+        "-" => a.checked_sub(b).map(integer),
+        "*" => a.checked_mul(b).map(integer),
+        "<" => Some(ValueType::Boolean(a < b)),
+        "=" => Some(ValueType::Boolean(a == b)),
+        _ => None,
     }
 }
 
-pub struct Interpreter<'a> {
-    env: RefCell<Environment<'a>>,
+// the max_call_depth of an interpreter unless its host sets another; the calls a compiled
+// procedure makes to itself in the tail position, as loops do, return from its frame rather
+// than nest, but the tree-walker nests every call
+pub const MAX_CALL_DEPTH: usize = 10_000;
+// the stack the default call depth needs, a debug build taking the most of it, which the
+// ruschm executable runs programs on
+pub const MAX_CALL_DEPTH_STACK: usize = 512 << 20;
+// the stack std gives the threads it spawns, less than a main thread usually has
+pub const DEFAULT_STACK_SIZE: usize = 2 << 20;
+
+thread_local! {
+    static THREAD_STACK_SIZE: Cell<usize> = const { Cell::new(DEFAULT_STACK_SIZE) };
+}
+
+// tells the interpreters made on this thread how much stack it has, for a host running them
+// on a thread spawned with another stack size than DEFAULT_STACK_SIZE
+pub fn set_thread_stack_size(size: usize) {
+    THREAD_STACK_SIZE.with(|stack_size| stack_size.set(size));
 }
 
-impl<'a> Interpreter<'a> {
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the top level environment is in a cycle with every procedure defined in it
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        let env = std::mem::replace(&mut self.env, Rc::new(Environment::empty()));
+        gc::register_environment(&env);
+        drop(env);
+        gc::collect();
+    }
+}
+
+impl Interpreter {
     pub fn new() -> Self {
         Self {
-            env: RefCell::new(Environment::new()),
+            env: Rc::new(Environment::new()),
+            current_input_port: RefCell::new(Rc::new(Port::stdin())),
+            current_output_port: RefCell::new(Rc::new(Port::stdout())),
+            current_error_port: RefCell::new(Rc::new(Port::stderr())),
+            environment_variables: RefCell::new(EnvironmentVariables::All),
+            command_line: RefCell::new(std::env::args().collect()),
+            allow_subprocesses: Cell::new(false),
+            allow_network: Cell::new(false),
+            log_filter: RefCell::new(LogFilter::from_environment()),
+            log_handler: RefCell::new(None),
+            display_handler: RefCell::new(None),
+            winds: RefCell::new(vec![]),
+            trace_depth: Cell::new(0),
+            max_call_depth: Cell::new(MAX_CALL_DEPTH),
+            stack_size: Cell::new(THREAD_STACK_SIZE.with(Cell::get)),
+            stack_base: Cell::new(0),
+            max_errors: Cell::new(None),
+            call_depth: Cell::new(0),
+            engine: Cell::new(Engine::Bytecode),
+            optimization_level: Cell::new(1),
+            parallelism: Cell::new(ruschm::parallel::default_parallelism()),
+            backtrace: RefCell::new(vec![]),
+            evaluations: Cell::new(0),
         }
     }
 
-    pub fn define<'b>(
-        &'a self,
-        definition: &Definition,
-        env: &'b RefCell<Environment<'b>>,
-    ) -> Result<()> {
+    pub fn define(&self, definition: &Definition, env: &Rc<Environment>) -> Result<()> {
         let Definition(name, expression) = definition;
-        let value = self.eval_expression(&expression, env)?;
-        env.borrow_mut().define(name.clone(), value);
+        let value = self.eval_expression(expression, env)?;
+        env.define(name.clone(), value);
+        Ok(())
+    }
+
+    // counts one more call being evaluated, apply_procedure counting it out again
+    // a frame of its own, so that the frames of recursive calls stay small
+    // the stack used is measured from the frame of the outermost call
+    fn enter_call(&self, procedure: &SchemeProcedure) -> Result<()> {
+        let depth = self.call_depth.get();
+        let here = &depth as *const usize as usize;
+        if depth == 0 {
+            self.stack_base.set(here);
+        }
+        if depth >= self.max_call_depth.get() {
+            logic_error!(
+                "{}: maximum call depth of {} exceeded",
+                procedure,
+                self.max_call_depth.get()
+            )
+        }
+        if self.stack_base.get().abs_diff(here) > self.stack_size.get() / 4 * 3 {
+            logic_error!(
+                "{}: stack exhausted at a call depth of {}",
+                procedure,
+                depth
+            )
+        }
+        self.call_depth.set(depth + 1);
         Ok(())
     }
 
-    fn eval_scheme_procedure<'b>(
-        &'a self,
-        formals: &Vec<String>,
-        definitions: &Vec<Definition>,
-        expressions: &Vec<Expression>,
+    // records a located call an error is unwinding through
+    fn unwind_call(&self, operator: &Expression, procedure: &ValueType, span: Span) {
+        let procedure = match (operator, procedure) {
+            (Expression::Identifier(name), _) => name.to_string(),
+            (_, ValueType::Procedure(Procedure::Buildin(BuildinProcedure(name, _)))) => {
+                name.to_string()
+            }
+            (_, ValueType::Procedure(_)) => "lambda".to_string(),
+            _ => operator.to_string(),
+        };
+        self.backtrace.borrow_mut().push(Frame { procedure, span });
+    }
+
+    // the calls the last error of eval_source was raised in, innermost first
+    pub fn backtrace(&self) -> Vec<Frame> {
+        self.backtrace.borrow().clone()
+    }
+
+    fn eval_scheme_procedure(
+        &self,
+        procedure: &SchemeProcedure,
         args: impl Iterator<Item = Result<ValueType>>,
-        parent_env: &'b Environment<'b>,
+        closure_env: &Rc<Environment>,
     ) -> Result<ValueType> {
-        let child_env = RefCell::new(Environment::child(parent_env));
-        for (param, arg) in formals.iter().zip(args) {
-            child_env.borrow_mut().define(param.clone(), arg?);
+        let SchemeProcedure(formals, definitions, expressions, _) = procedure;
+        let child_env = Rc::new(Environment::child(closure_env.clone()));
+        let mut arg_count = 0;
+        for arg in args {
+            let arg = arg?;
+            if let Some(param) = formals.get(arg_count) {
+                child_env.define(param.clone(), arg);
+            }
+            arg_count += 1;
+        }
+        if arg_count != formals.len() {
+            return Err(arity_error(procedure, arg_count));
         }
         for def in definitions {
             self.define(def, &child_env)?;
@@ -282,40 +718,104 @@ impl<'a> Interpreter<'a> {
         }
     }
 
-    pub fn eval_root_expression(&'a self, expression: Expression) -> Result<ValueType> {
+    // applies a procedure to arguments which are already evaluated
+    pub fn call_procedure(&self, procedure: &ValueType, args: Vec<ValueType>) -> Result<ValueType> {
+        self.apply_procedure(procedure, Box::new(args.into_iter().map(Ok)))
+    }
+
+    pub fn apply_procedure(&self, procedure: &ValueType, args: Arguments) -> Result<ValueType> {
+        match procedure {
+            ValueType::Procedure(Procedure::Buildin(BuildinProcedure(_, fp))) => fp(args, self),
+            ValueType::Procedure(Procedure::User(procedure, closure_env)) => {
+                self.enter_call(procedure)?;
+                let result = self.eval_scheme_procedure(procedure, args, closure_env);
+                self.call_depth.set(self.call_depth.get() - 1);
+                result
+            }
+            ValueType::Procedure(Procedure::Compiled(code, closure_env)) => {
+                self.apply_compiled(code, closure_env, args)
+            }
+            _ => logic_error!("expect a procedure here"),
+        }
+    }
+
+    // a call of a fixnum operator on two arguments, which are evaluated before the operator is
+    // applied as the builtins evaluate all their arguments anyway
+    fn fixnum_call(
+        &self,
+        procedure: &ValueType,
+        arguments: &[Expression],
+        evaluate: impl Fn(&Expression) -> Result<ValueType>,
+    ) -> Option<Result<ValueType>> {
+        match (procedure, arguments) {
+            (ValueType::Procedure(Procedure::Buildin(BuildinProcedure(name, _))), [a, b])
+                if FIXNUM_OPERATORS.contains(name) =>
+            {
+                let evaluated = evaluate(a).and_then(|a| Ok((a, evaluate(b)?)));
+                Some(
+                    evaluated.and_then(|(a, b)| match fixnum_operation(procedure, &a, &b) {
+                        Some(value) => Ok(value),
+                        None => self
+                            .apply_procedure(procedure, Box::new(vec![a, b].into_iter().map(Ok))),
+                    }),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    // the environment top level definitions are made in
+    pub fn global_environment(&self) -> &Rc<Environment> {
+        &self.env
+    }
+
+    pub fn eval_root_expression(&self, expression: Expression) -> Result<ValueType> {
         self.eval_expression(&expression, &self.env)
     }
 
-    pub fn eval_expression<'b>(
-        &'a self,
+    pub fn eval_expression(
+        &self,
         expression: &Expression,
-        env: &'b RefCell<Environment<'b>>,
+        env: &Rc<Environment>,
     ) -> Result<ValueType> {
+        self.evaluations.set(self.evaluations.get() + 1);
         Ok(match expression {
             Expression::ProcedureCall(procedure_expr, arguments) => {
                 let procedure = self.eval_expression(procedure_expr, env)?;
-                let evaluated_args = Box::new(
-                    arguments
-                        .into_iter()
-                        .map(|arg| self.eval_expression(arg, env)),
-                );
-                let parent_env = &env.borrow();
-                match procedure {
-                    ValueType::Procedure(Procedure::Buildin(BuildinProcedure(_, fp))) => {
-                        fp(evaluated_args)?
+                match self.fixnum_call(&procedure, arguments, |arg| self.eval_expression(arg, env))
+                {
+                    Some(result) => result?,
+                    None => {
+                        let evaluated_args =
+                            Box::new(arguments.iter().map(|arg| self.eval_expression(arg, env)));
+                        self.apply_procedure(&procedure, evaluated_args)?
+                    }
+                }
+            }
+            Expression::LocatedCall(call) => {
+                let (procedure_expr, arguments, span) = call.as_ref();
+                let procedure = self.eval_expression(procedure_expr, env)?;
+                // procedures take their arguments as they are evaluated, an error raised in one
+                // not being in the call, which the vm enters only after
+                let in_argument = Cell::new(false);
+                let argument = |arg: &Expression| {
+                    self.eval_expression(arg, env)
+                        .inspect_err(|_| in_argument.set(true))
+                };
+                let result = match self.fixnum_call(&procedure, arguments, argument) {
+                    Some(result) => result,
+                    None => {
+                        self.apply_procedure(&procedure, Box::new(arguments.iter().map(argument)))
+                    }
+                };
+                match result {
+                    Ok(value) => value,
+                    Err(error) => {
+                        if !in_argument.get() {
+                            self.unwind_call(procedure_expr, &procedure, *span);
+                        }
+                        return Err(error);
                     }
-                    ValueType::Procedure(Procedure::User(SchemeProcedure(
-                        formals,
-                        definitions,
-                        expressions,
-                    ))) => self.eval_scheme_procedure(
-                        &formals,
-                        &definitions,
-                        &expressions,
-                        evaluated_args,
-                        parent_env,
-                    )?,
-                    _ => logic_error!("expect a procedure here"),
                 }
             }
             Expression::Vector(vector) => {
@@ -323,63 +823,281 @@ impl<'a> Interpreter<'a> {
                 for expr in vector {
                     values.push(self.eval_expression(expr, env)?);
                 }
-                ValueType::Vector(values)
+                ValueType::vector(values)
+            }
+            Expression::Procedure(scheme) => {
+                gc::register_environment(env);
+                ValueType::Procedure(Procedure::User(Rc::new(scheme.clone()), env.clone()))
             }
-            Expression::Procedure(scheme) => ValueType::Procedure(Procedure::User(scheme.clone())),
             Expression::Conditional(cond) => {
                 let &(test, consequent, alternative) = &cond.as_ref();
-                match self.eval_expression(&test, env)? {
-                    ValueType::Boolean(true) => self.eval_expression(&consequent, env)?,
+                match self.eval_expression(test, env)? {
+                    ValueType::Boolean(true) => self.eval_expression(consequent, env)?,
                     ValueType::Boolean(false) => match alternative {
-                        Some(alter) => self.eval_expression(&alter, env)?,
+                        Some(alter) => self.eval_expression(alter, env)?,
                         None => ValueType::Void,
                     },
                     _ => logic_error!("if condition should be a boolean expression"),
                 }
             }
-            Expression::Datum(datum) => ValueType::Datum(datum.clone()),
+            Expression::Delay(thunk) => {
+                scheme::lazy::Promise::delayed(self.eval_expression(thunk, env)?, vec![])
+            }
+            Expression::DelayForce(thunk) => {
+                scheme::lazy::Promise::delayed_force(self.eval_expression(thunk, env)?, vec![])
+            }
+            // neither part of the pair is evaluated before the stream is
+            Expression::StreamCons(stream) => {
+                let (car, cdr) = stream.as_ref();
+                scheme::lazy::Promise::value(ValueType::cons(
+                    scheme::lazy::Promise::delayed(self.eval_expression(car, env)?, vec![]),
+                    scheme::lazy::Promise::delayed_force(self.eval_expression(cdr, env)?, vec![]),
+                ))
+            }
+            Expression::Match(matching) => {
+                let (value, clauses) = matching.as_ref();
+                let value = self.eval_expression(value, env)?;
+                for MatchClause(pattern, body) in clauses {
+                    let mut bindings = HashMap::new();
+                    if self.match_pattern(pattern, &value, env, &mut bindings)? {
+                        let arguments = pattern
+                            .variables()
+                            .iter()
+                            .map(|variable| bindings.remove(variable).unwrap())
+                            .collect();
+                        let body = self.eval_expression(body, env)?;
+                        return self.call_procedure(&body, arguments);
+                    }
+                }
+                logic_error!("match: no clause matches {}", value)
+            }
+            Expression::Guard(guard) => {
+                let (variable, clauses, body) = guard.as_ref();
+                let depth = self.backtrace.borrow().len();
+                match self.eval_expression(body, env) {
+                    Ok(value) => value,
+                    // exiting is not an exception
+                    Err(error) if error.exit_code().is_some() => return Err(error),
+                    Err(error) => {
+                        // the frames the error unwound through stay with it if it is raised again
+                        let unwound = self.backtrace.borrow_mut().split_off(depth);
+                        let clause_env = Rc::new(Environment::child(env.clone()));
+                        clause_env.define(variable.clone(), condition_object(&error));
+                        match self.eval_guard_clauses(clauses, &clause_env)? {
+                            Some(value) => value,
+                            None => {
+                                self.backtrace.borrow_mut().extend(unwound);
+                                return Err(error);
+                            }
+                        }
+                    }
+                }
+            }
+            Expression::Datum(datum) => datum.as_ref().into(),
+            Expression::Primitive(p) => primitive(*p),
             Expression::Boolean(value) => ValueType::Boolean(*value),
+            Expression::Character(value) => ValueType::Character(*value),
+            Expression::String(value) => ValueType::String(value.clone()),
             Expression::Integer(value) => ValueType::Number(Number::Integer(*value)),
             Expression::Real(number_literal) => {
-                ValueType::Number(Number::Real(number_literal.parse::<f64>().unwrap()))
+                ValueType::Number(Number::parse(number_literal, 10).unwrap())
+            }
+            Expression::Rational(a, b) => ValueType::Number(Number::rational(*a, *b as i64)?),
+            Expression::Identifier(ident) => match env.get(ident) {
+                Some(value) => value,
+                None => return Err(undefined_identifier(ident, env)),
+            },
+        })
+    }
+
+    // binds the pattern's variables when the value matches
+    fn match_pattern(
+        &self,
+        pattern: &Pattern,
+        value: &ValueType,
+        env: &Rc<Environment>,
+        bindings: &mut HashMap<String, ValueType>,
+    ) -> Result<bool> {
+        Ok(match pattern {
+            Pattern::Wildcard => true,
+            Pattern::Variable(variable) => {
+                bindings.insert(variable.clone(), value.clone());
+                true
+            }
+            Pattern::Datum(datum) => ValueType::from(datum).equal(value),
+            // an ellipsis takes all the pairs, leaving the final cdr to the tail
+            Pattern::List(sequence, tail) => {
+                let limit = match sequence.1 {
+                    Some(_) if matches!(value.list_shape(), ListShape::Circular) => {
+                        return Ok(false)
+                    }
+                    Some(_) => usize::MAX,
+                    None => sequence.0.len(),
+                };
+                let mut items = vec![];
+                let mut rest = value.clone();
+                while items.len() < limit {
+                    rest = match &rest {
+                        ValueType::Pair(pair) => {
+                            let pair = pair.borrow();
+                            items.push(pair.car.clone());
+                            pair.cdr.clone()
+                        }
+                        _ => break,
+                    };
+                }
+                self.match_sequence(sequence, &items, env, bindings)?
+                    && self.match_pattern(tail, &rest, env, bindings)?
             }
-            // TODO: apply gcd here.
-            Expression::Rational(a, b) => ValueType::Number(Number::Rational(*a, *b as i64)),
-            Expression::Identifier(ident) => match env.borrow().get(ident.as_str()) {
-                Some(value) => value.clone(),
-                None => logic_error!("undefined identifier: {}", ident),
+            Pattern::Vector(sequence) => match value {
+                ValueType::Vector(vector) => {
+                    let items = vector.borrow().clone();
+                    self.match_sequence(sequence, &items, env, bindings)?
+                }
+                _ => false,
             },
+            Pattern::Predicate(predicate, patterns) => {
+                let predicate = self.eval_expression(predicate, env)?;
+                if !self
+                    .call_procedure(&predicate, vec![value.clone()])?
+                    .is_true()
+                {
+                    return Ok(false);
+                }
+                for pattern in patterns {
+                    if !self.match_pattern(pattern, value, env, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
         })
     }
 
-    pub fn eval_ast<'b>(
-        &'a self,
-        ast: &Statement,
-        env: &'b RefCell<Environment<'b>>,
+    // the variables of a repeated pattern are bound to the lists of their matches
+    fn match_sequence(
+        &self,
+        SequencePattern(elements, ellipsis): &SequencePattern,
+        items: &[ValueType],
+        env: &Rc<Environment>,
+        bindings: &mut HashMap<String, ValueType>,
+    ) -> Result<bool> {
+        let (before, after) = match ellipsis {
+            Some(index) if items.len() + 1 >= elements.len() => {
+                (*index, elements.len() - index - 1)
+            }
+            None if items.len() == elements.len() => (elements.len(), 0),
+            _ => return Ok(false),
+        };
+        let repeated_end = items.len() - after;
+        for (pattern, item) in elements[..before].iter().zip(&items[..before]).chain(
+            elements[elements.len() - after..]
+                .iter()
+                .zip(&items[repeated_end..]),
+        ) {
+            if !self.match_pattern(pattern, item, env, bindings)? {
+                return Ok(false);
+            }
+        }
+        if let Some(index) = ellipsis {
+            let repeated = &elements[*index];
+            let variables = repeated.variables();
+            let mut matches = vec![vec![]; variables.len()];
+            for item in &items[before..repeated_end] {
+                let mut repeated_bindings = HashMap::new();
+                if !self.match_pattern(repeated, item, env, &mut repeated_bindings)? {
+                    return Ok(false);
+                }
+                for (values, variable) in matches.iter_mut().zip(&variables) {
+                    values.push(repeated_bindings.remove(variable).unwrap());
+                }
+            }
+            for (variable, values) in variables.into_iter().zip(matches) {
+                bindings.insert(variable, ValueType::list(values.into_iter()));
+            }
+        }
+        Ok(true)
+    }
+
+    // the value of the first clause whose test is true, None to raise again
+    fn eval_guard_clauses(
+        &self,
+        clauses: &[GuardClause],
+        env: &Rc<Environment>,
     ) -> Result<Option<ValueType>> {
+        for GuardClause(test, body) in clauses {
+            let mut value = match test {
+                Some(test) => self.eval_expression(test, env)?,
+                None => ValueType::Boolean(true),
+            };
+            if value.is_true() {
+                for expression in body {
+                    value = self.eval_expression(expression, env)?;
+                }
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    // the reference cycles made by top level forms are collected after them from time to time
+    pub fn eval_ast(&self, ast: &Statement, env: &Rc<Environment>) -> Result<Option<ValueType>> {
+        if let Statement::ImportDeclaration(imports) = ast {
+            imports
+                .iter()
+                .for_each(|import| env.import(import.library()));
+        }
+        let result = match self.engine.get() {
+            Engine::Bytecode => {
+                let optimizer = optimizer::Optimizer::new(self.optimization_level.get(), self, env);
+                self.execute(&compiler::compile_statement(ast, optimizer)?, env)
+            }
+            Engine::TreeWalker => self.walk_ast(ast, env),
+        };
+        if self.call_depth.get() == 0 && gc::collection_due() {
+            gc::collect();
+        }
+        result
+    }
+
+    // frees the values in reference cycles nothing else refers to, returning how many objects
+    // were freed
+    pub fn collect_garbage(&self) -> usize {
+        gc::collect()
+    }
+
+    fn walk_ast(&self, ast: &Statement, env: &Rc<Environment>) -> Result<Option<ValueType>> {
         Ok(match ast {
-            Statement::ImportDeclaration(_) => None, // TODO
-            Statement::Expression(expr) => Some(self.eval_expression(&expr, env)?),
+            // the libraries are defined by eval_ast
+            Statement::ImportDeclaration(_) => None,
+            Statement::Expression(expr) => Some(self.eval_expression(expr, env)?),
             Statement::Definition(definition) => {
                 self.define(definition, env)?;
                 None
             }
+            Statement::Sequence(statements) => {
+                let mut result = None;
+                for statement in statements {
+                    result = self.walk_ast(statement, env)?;
+                }
+                result
+            }
         })
     }
 
-    pub fn eval_root_ast(&'a self, ast: &Statement) -> Result<Option<ValueType>> {
+    pub fn eval_root_ast(&self, ast: &Statement) -> Result<Option<ValueType>> {
         self.eval_ast(ast, &self.env)
     }
 
-    pub fn eval_program(
-        &'a self,
+    pub fn eval_program<'a>(
+        &self,
         asts: impl IntoIterator<Item = &'a Statement>,
     ) -> Result<Option<ValueType>> {
         asts.into_iter()
             .try_fold(None, |_, ast| self.eval_root_ast(ast))
     }
 
-    pub fn eval(&'a self, char_stream: impl Iterator<Item = char>) -> Result<Option<ValueType>> {
+    pub fn eval(&self, char_stream: impl Iterator<Item = char>) -> Result<Option<ValueType>> {
         {
             let mut char_visitor = char_stream.peekable();
             let mut last_value = None;
@@ -393,6 +1111,316 @@ impl<'a> Interpreter<'a> {
             }
         }
     }
+
+    // like eval, but an error comes with the span of the top level form it stopped, or for
+    // errors reading the source the span of the character it was found at
+    pub fn eval_source(
+        &self,
+        source: &str,
+    ) -> std::result::Result<Option<ValueType>, (Error, Span)> {
+        self.eval_named_source(source, None)
+    }
+
+    // as eval_source, the procedures of the source telling its name, e.g. of a file, as where
+    // they are written
+    pub fn eval_named_source(
+        &self,
+        source: &str,
+        name: Option<&str>,
+    ) -> std::result::Result<Option<ValueType>, (Error, Span)> {
+        let mut last_value = None;
+        read_source(source, name, |ast, _| {
+            self.backtrace.borrow_mut().clear();
+            last_value = self.eval_root_ast(&ast)?;
+            Ok(())
+        })?;
+        Ok(last_value)
+    }
+
+    // as eval_named_source for a file loaded, whose forms after one which cannot be read are
+    // still evaluated, returning the errors of all of them, the last the one which stopped the
+    // evaluation if any
+    pub fn load_source(
+        &self,
+        source: &str,
+        name: Option<&str>,
+    ) -> std::result::Result<Option<ValueType>, Vec<(Error, Span)>> {
+        let mut last_value = None;
+        let max_errors = self.max_errors.get();
+        let errors = read_source_resynchronizing(source, name, max_errors, |ast, _| {
+            self.backtrace.borrow_mut().clear();
+            last_value = self.eval_root_ast(&ast)?;
+            Ok(())
+        });
+        match errors.is_empty() {
+            true => Ok(last_value),
+            false => Err(errors),
+        }
+    }
+}
+
+// kept out of eval_scheme_procedure, which is on the stack of every nested call
+#[cold]
+fn arity_error(procedure: &SchemeProcedure, arg_count: usize) -> Error {
+    let SchemeProcedure(formals, _, _, origin) = procedure;
+    let mut message = format!(
+        "{} expects {} arguments, got {}",
+        procedure,
+        formals.len(),
+        arg_count
+    );
+    if let Some(origin) = origin {
+        message.push_str(&format!(", defined at {}", origin));
+    }
+    Error {
+        category: ErrorType::Logic,
+        message,
+    }
+}
+
+// the number of insertions, deletions, substitutions and swaps of adjacent characters turning
+// one text into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // the distances from the prefixes of a to the prefixes of b, a row for each prefix of a
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i];
+        for j in 1..=b.len() {
+            let substitution = rows[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let mut distance = substitution.min(rows[i - 1][j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            row.push(distance);
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+// the error of an identifier bound nowhere, suggesting up to three names close to it
+fn undefined_identifier(name: &str, env: &Environment) -> Error {
+    let length = name.chars().count();
+    let mut candidates: Vec<(usize, String)> = env
+        .names()
+        .into_iter()
+        .filter(|candidate| !is_generated(candidate))
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= (length / 3).max(1) && *distance < length)
+        .collect();
+    candidates.sort();
+    let suggested: Vec<String> = candidates
+        .into_iter()
+        .take(3)
+        .map(|(_, name)| name)
+        .collect();
+    let message = match suggested.split_last() {
+        None => format!("undefined identifier: {}", name),
+        Some((last, [])) => format!("undefined identifier: {}, did you mean {}?", name, last),
+        Some((last, others)) => format!(
+            "undefined identifier: {}, did you mean {} or {}?",
+            name,
+            others.join(", "),
+            last
+        ),
+    };
+    Error {
+        category: ErrorType::Logic,
+        message,
+    }
+}
+
+// reads the top level forms of a source in turn, their calls located, and hands each to visit
+// with its span, stopping at the first error, which comes with the span of the form visited,
+// or for errors reading the source the span of the character it was found at
+pub fn read_source(
+    source: &str,
+    name: Option<&str>,
+    visit: impl FnMut(Statement, Span) -> Result<()>,
+) -> std::result::Result<(), (Error, Span)> {
+    match read_forms(source, name, false, 1, visit).pop() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+// as read_source, but a form which cannot be read is skipped and the reading goes on after it,
+// up to max_errors errors if given, returning the errors in the order found, the first error
+// of visit still stops the reading
+pub fn read_source_resynchronizing(
+    source: &str,
+    name: Option<&str>,
+    max_errors: Option<usize>,
+    visit: impl FnMut(Statement, Span) -> Result<()>,
+) -> Vec<(Error, Span)> {
+    read_forms(source, name, true, max_errors.unwrap_or(usize::MAX), visit)
+}
+
+// resynchronizing, the reading goes on after the forms which cannot be read until it found
+// max_errors errors
+fn read_forms(
+    source: &str,
+    name: Option<&str>,
+    resynchronize: bool,
+    max_errors: usize,
+    mut visit: impl FnMut(Statement, Span) -> Result<()>,
+) -> Vec<(Error, Span)> {
+    let characters: Vec<char> = source.chars().collect();
+    let spans = Rc::new(RefCell::new(VecDeque::new()));
+    let lines = Rc::new(SourceLines::new(source, name));
+    let mut errors = vec![];
+    // the offset the characters are read from, again after a form which cannot be read
+    let mut resume = Some(0);
+    while let Some(from) = resume.take() {
+        let consumed = Cell::new(from);
+        let mut char_visitor = source
+            .chars()
+            .skip(from)
+            .inspect(|_| consumed.set(consumed.get() + 1))
+            .peekable();
+        // the offset of the next character, which may have been peeked already
+        let offset = |chars: &mut Peekable<_>| {
+            let peeked = chars.peek().is_some() as usize;
+            consumed.get() - peeked
+        };
+        loop {
+            let start = skip_atmosphere(&characters, offset(&mut char_visitor));
+            // the tokens are read up to the first lexical error, which is the error of the form
+            let mut lexical_error = None;
+            let mut token_stream = TokenGenerator::new(&mut char_visitor);
+            let tokens = std::iter::from_fn(|| {
+                let start = skip_atmosphere(&characters, offset(token_stream.text_iterator));
+                match token_stream.next()? {
+                    Ok(token) => {
+                        let end = offset(token_stream.text_iterator);
+                        spans.borrow_mut().push_back(Span { start, end });
+                        Some(token)
+                    }
+                    Err(e) => {
+                        lexical_error = Some(e);
+                        None
+                    }
+                }
+            });
+            let parsed = Parser::located(tokens, spans.clone(), lines.clone()).parse();
+            spans.borrow_mut().clear();
+            let end = offset(&mut char_visitor);
+            match lexical_error.map_or(parsed, Err) {
+                Ok(Some(ast)) => {
+                    let span = Span { start, end };
+                    if let Err(e) = visit(ast, span) {
+                        errors.push((e, span));
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(mut e) => {
+                    let found = end.saturating_sub(1).max(start);
+                    // the token the parser found out of place, or the last one read
+                    let mut span = match &e.category {
+                        ErrorType::Syntax(kind) => kind.span(),
+                        _ => None,
+                    }
+                    .unwrap_or(Span { start: found, end });
+                    if resynchronize {
+                        let next = resynchronization(&characters, start);
+                        // an error found past where the form is taken to end is of a form read
+                        // on into the next, which is rather reported at its open parenthesis
+                        if found >= next {
+                            e = Error::from(SyntaxError::UnterminatedList);
+                        }
+                        if matches!(e.category, ErrorType::Syntax(SyntaxError::UnterminatedList)) {
+                            span = Span {
+                                start,
+                                end: start + 1,
+                            };
+                        }
+                        if errors.len() + 1 < max_errors {
+                            resume = Some(next);
+                        }
+                    }
+                    errors.push((e, span));
+                    break;
+                }
+            }
+        }
+    }
+    errors
+}
+
+// where reading goes on after the form starting at start cannot be read: after the close
+// parenthesis matching its first open parenthesis, or at a line starting with an open
+// parenthesis if one comes first, as the next top level form likely does
+fn resynchronization(characters: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut in_comment = false;
+    let mut i = start;
+    while let Some(&c) = characters.get(i) {
+        if i > start && c == '(' && characters[i - 1] == '\n' && !in_string {
+            return i;
+        }
+        match (c, in_string, in_comment) {
+            ('\n', _, true) => in_comment = false,
+            (_, _, true) => (),
+            ('\\', true, _) => i += 1,
+            ('"', _, _) => in_string = !in_string,
+            (_, true, _) => (),
+            (';', _, _) => in_comment = true,
+            // a character literal, as #\( which opens nothing
+            ('#', _, _) if characters.get(i + 1) == Some(&'\\') => i += 2,
+            ('(', _, _) => depth += 1,
+            (')', _, _) => {
+                depth -= 1;
+                if depth <= 0 {
+                    return i + 1;
+                }
+            }
+            // a form which is not a list ends at whitespace
+            (c, _, _) if depth == 0 && c.is_whitespace() => return i,
+            _ => (),
+        }
+        i += 1;
+    }
+    characters.len()
+}
+
+// the offset of the first character from offset on which is neither whitespace nor in a comment
+fn skip_atmosphere(characters: &[char], offset: usize) -> usize {
+    let mut in_comment = false;
+    for (i, c) in characters.iter().enumerate().skip(offset) {
+        match (c, in_comment) {
+            (';', false) => in_comment = true,
+            ('\n', true) => in_comment = false,
+            (_, true) => (),
+            (c, false) if c.is_whitespace() => (),
+            _ => return i,
+        }
+    }
+    characters.len()
+}
+
+// asserts that each source evaluates, in turn in the interpreter, to the value printed as the
+// result, or to none
+#[cfg(test)]
+pub(crate) fn assert_printed<'a, R: Into<Option<&'a str>> + Copy>(
+    interpreter: &Interpreter,
+    cases: &[(&str, R)],
+) -> Result<()> {
+    for (source, result) in cases {
+        assert_eq!(
+            interpreter
+                .eval(source.chars())?
+                .map(|value| value.to_string())
+                .as_deref(),
+            (*result).into(),
+            "{}",
+            source
+        );
+    }
+    Ok(())
 }
 
 #[test]
@@ -407,7 +1435,7 @@ fn number() -> Result<()> {
         ValueType::Number(Number::Rational(1, 3))
     );
     assert_eq!(
-        interpreter.eval_root_expression(Expression::Real("-3.45e-7".to_string()))?,
+        interpreter.eval_root_expression(Expression::Real("-3.45e-7".into()))?,
         ValueType::Number(Number::Real(-3.45e-7))
     );
     Ok(())
@@ -418,7 +1446,7 @@ fn arithmetic() -> Result<()> {
     let interpreter = Interpreter::new();
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("+".to_string())),
+            Box::new(Expression::Identifier("+".into())),
             vec![Expression::Integer(1), Expression::Integer(2)]
         ))?,
         ValueType::Number(Number::Integer(3))
@@ -426,7 +1454,7 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("+".to_string())),
+            Box::new(Expression::Identifier("+".into())),
             vec![Expression::Integer(1), Expression::Rational(1, 2)]
         ))?,
         ValueType::Number(Number::Rational(3, 2))
@@ -434,18 +1462,15 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("*".to_string())),
-            vec![
-                Expression::Rational(1, 2),
-                Expression::Real("2.0".to_string()),
-            ]
+            Box::new(Expression::Identifier("*".into())),
+            vec![Expression::Rational(1, 2), Expression::Real("2.0".into()),]
         ))?,
         ValueType::Number(Number::Real(1.0)),
     );
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("/".to_string())),
+            Box::new(Expression::Identifier("/".into())),
             vec![Expression::Integer(1), Expression::Integer(0)]
         )),
         Err(Error {
@@ -456,22 +1481,22 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("max".to_string())),
-            vec![Expression::Integer(1), Expression::Real("1.3".to_string()),]
+            Box::new(Expression::Identifier("max".into())),
+            vec![Expression::Integer(1), Expression::Real("1.3".into()),]
         ))?,
         ValueType::Number(Number::Real(1.3)),
     );
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("min".to_string())),
-            vec![Expression::Integer(1), Expression::Real("1.3".to_string()),]
+            Box::new(Expression::Identifier("min".into())),
+            vec![Expression::Integer(1), Expression::Real("1.3".into()),]
         ))?,
         ValueType::Number(Number::Real(1.0)),
     );
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("min".to_string())),
-            vec![Expression::Identifier("+".to_string()),]
+            Box::new(Expression::Identifier("min".into())),
+            vec![Expression::Identifier("+".into()),]
         )),
         Err(Error {
             category: ErrorType::Logic,
@@ -481,8 +1506,8 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("max".to_string())),
-            vec![Expression::Identifier("+".to_string())]
+            Box::new(Expression::Identifier("max".into())),
+            vec![Expression::Identifier("+".into())]
         )),
         Err(Error {
             category: ErrorType::Logic,
@@ -492,14 +1517,14 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("sqrt".to_string())),
+            Box::new(Expression::Identifier("sqrt".into())),
             vec![Expression::Integer(4)]
         ))?,
-        ValueType::Number(Number::Real(2.0)),
+        ValueType::Number(Number::Integer(2)),
     );
 
     match interpreter.eval_root_expression(Expression::ProcedureCall(
-        Box::new(Expression::Identifier("sqrt".to_string())),
+        Box::new(Expression::Identifier("sqrt".into())),
         vec![Expression::Integer(-4)],
     ))? {
         ValueType::Number(Number::Real(should_be_nan)) => assert!(should_be_nan.is_nan()),
@@ -512,11 +1537,11 @@ fn arithmetic() -> Result<()> {
     {
         assert_eq!(
             interpreter.eval_root_expression(Expression::ProcedureCall(
-                Box::new(Expression::Identifier(cmp.to_string())),
+                Box::new(Expression::Identifier((*cmp).into())),
                 vec![
                     Expression::Integer(1),
                     Expression::Rational(1, 1),
-                    Expression::Real("1.0".to_string()),
+                    Expression::Real("1.0".into()),
                 ],
             ))?,
             ValueType::Boolean(*result)
@@ -526,28 +1551,151 @@ fn arithmetic() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn fixnum_arithmetic() {
+    for engine in [vm::Engine::TreeWalker, vm::Engine::Bytecode] {
+        let interpreter = Interpreter::new();
+        interpreter.engine.set(engine);
+        let result = |source: &str| match interpreter.eval_source(source) {
+            Ok(value) => value.map(|value| value.to_string()).unwrap_or_default(),
+            Err((error, _)) => error.message,
+        };
+        assert_eq!(
+            result("(list (+ 1 2) (- 1 3) (* 4 -5) (< 1 2) (= 2 3) (+ 1 1/2) (< 1 2.5) (* 2 1.5))"),
+            "(3 -2 -20 #t #f 3/2 #t 3.0)"
+        );
+        assert_eq!(result("(* 4611686018427387904 -2)"), "-9223372036854775808");
+        // overflowing results are left to the builtins
+        assert_eq!(result("(+ 9223372036854775807 1)"), "9223372036854775808");
+        assert_eq!(result("(- -9223372036854775808 1)"), "-9223372036854775809");
+        assert_eq!(result("(* 4611686018427387904 2)"), "9223372036854775808");
+        assert_eq!(
+            result("(- (* 100000000000000000000 100000000000000000000) 1)"),
+            "9999999999999999999999999999999999999999"
+        );
+        assert_eq!(
+            result("(list (expt 2 100) (* 1.0 (expt 2 100)) (/ 1 (expt 2 70)))"),
+            "(1267650600228229401496703205376 1.2676506002282294e30 1/1180591620717411303424)"
+        );
+        assert_eq!(
+            result("(define (plus a b) (+ a b)) (define (f +) (+ 1 2)) (list (plus 1 2) (f -))"),
+            "(3 -1)"
+        );
+    }
+}
+
 #[test]
 fn undefined() {
     let interpreter = Interpreter::new();
     assert_eq!(
-        interpreter.eval_root_expression(Expression::Identifier("foo".to_string())),
+        interpreter.eval_root_expression(Expression::Identifier("foo".into())),
         Err(Error {
             category: ErrorType::Logic,
             message: "undefined identifier: foo".to_string(),
         })
     );
+    assert_eq!(
+        interpreter.eval("(lenght '(1 2))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "undefined identifier: lenght, did you mean length?".to_string(),
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(vector-refs #(1) 0)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "undefined identifier: vector-refs, did you mean vector-ref or vector-set!?"
+                .to_string(),
+        })
+    );
+    interpreter
+        .eval("(define (my-procedure) 1)".chars())
+        .unwrap();
+    assert_eq!(
+        interpreter.eval("(my-procdure)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "undefined identifier: my-procdure, did you mean my-procedure?".to_string(),
+        })
+    );
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("form", "from"), 1);
+}
+
+#[test]
+fn resynchronizing() {
+    let interpreter = Interpreter::new();
+    let source = "(define (f x) (+ x 1)\n(define y 2)\n(define z #z)) (define w \"(\")\n(+ y 1";
+    let errors = interpreter.load_source(source, None).unwrap_err();
+    let found: Vec<(&str, String)> = errors
+        .iter()
+        .map(|(error, span)| (error.code(), source[span.start..].chars().take(8).collect()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("unterminated-list", "(define ".to_string()),
+            ("invalid-token", ")) (defi".to_string()),
+            ("unmatched-parenthesis", ") (defin".to_string()),
+            ("unterminated-list", "(+ y 1".to_string()),
+        ]
+    );
+    assert_eq!(
+        interpreter.eval("(list y w)".chars()),
+        interpreter.eval("'(2 \"(\")".chars())
+    );
+    assert_eq!(
+        interpreter
+            .eval_source("(define v 1) (define (f x) (+ x 1)\n(define u 2)")
+            .map_err(|(error, _)| error.code()),
+        Err("unterminated-list")
+    );
+    assert!(interpreter.eval("v".chars()).is_ok());
+    assert!(interpreter.eval("u".chars()).is_err());
+    let interpreter = Interpreter::new();
+    interpreter.max_errors.set(Some(2));
+    assert_eq!(interpreter.load_source(source, None).unwrap_err().len(), 2);
+    // the forms after the last error reported are not read
+    assert!(interpreter.eval("w".chars()).is_err());
+}
+
+#[test]
+fn wrong_arity() {
+    let interpreter = Interpreter::new();
+    assert_eq!(
+        interpreter.eval("((lambda (x) x))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "(lambda (x) x) expects 1 arguments, got 0".to_string(),
+        })
+    );
+    let source = "(define (f x y)\n  (+ x y))\n(define g\n  (lambda (x) (f x)))\n(g 1)";
+    let (error, span) = interpreter
+        .eval_named_source(source, Some("sum.scm"))
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "(lambda (x y) (+ x y)) expects 2 arguments, got 1, defined at sum.scm:1"
+    );
+    assert_eq!(&source[span.start..], "(g 1)");
+    let (error, _) = interpreter.eval_source("(g 1 2)").unwrap_err();
+    assert_eq!(
+        error.message,
+        "(lambda (x) (f x)) expects 1 arguments, got 2, defined at sum.scm:4"
+    );
 }
 
 #[test]
 fn variable_definition() -> Result<()> {
     let interpreter = Interpreter::new();
-    let program = vec![
+    let program = [
         Statement::Definition(Definition("a".to_string(), Expression::Integer(1))),
         Statement::Definition(Definition(
             "b".to_string(),
-            Expression::Identifier("a".to_string()),
+            Expression::Identifier("a".into()),
         )),
-        Statement::Expression(Expression::Identifier("b".to_string())),
+        Statement::Expression(Expression::Identifier("b".into())),
     ];
     assert_eq!(
         interpreter.eval_program(program.iter())?,
@@ -559,14 +1707,14 @@ fn variable_definition() -> Result<()> {
 #[test]
 fn buildin_procedural() -> Result<()> {
     let interpreter = Interpreter::new();
-    let program = vec![
+    let program = [
         Statement::Definition(Definition(
             "get-add".to_string(),
-            simple_procedure(vec![], Expression::Identifier("+".to_string())),
+            simple_procedure(vec![], Expression::Identifier("+".into())),
         )),
         Statement::Expression(Expression::ProcedureCall(
             Box::new(Expression::ProcedureCall(
-                Box::new(Expression::Identifier("get-add".to_string())),
+                Box::new(Expression::Identifier("get-add".into())),
                 vec![],
             )),
             vec![Expression::Integer(1), Expression::Integer(2)],
@@ -582,22 +1730,22 @@ fn buildin_procedural() -> Result<()> {
 #[test]
 fn procedure_definition() -> Result<()> {
     let interpreter = Interpreter::new();
-    let program = vec![
+    let program = [
         Statement::Definition(Definition(
             "add".to_string(),
             simple_procedure(
                 vec!["x".to_string(), "y".to_string()],
                 Expression::ProcedureCall(
-                    Box::new(Expression::Identifier("+".to_string())),
+                    Box::new(Expression::Identifier("+".into())),
                     vec![
-                        Expression::Identifier("x".to_string()),
-                        Expression::Identifier("y".to_string()),
+                        Expression::Identifier("x".into()),
+                        Expression::Identifier("y".into()),
                     ],
                 ),
             ),
         )),
         Statement::Expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("add".to_string())),
+            Box::new(Expression::Identifier("add".into())),
             vec![Expression::Integer(1), Expression::Integer(2)],
         )),
     ];
@@ -611,14 +1759,14 @@ fn procedure_definition() -> Result<()> {
 #[test]
 fn lambda_call() -> Result<()> {
     let interpreter = Interpreter::new();
-    let program = vec![Statement::Expression(Expression::ProcedureCall(
+    let program = [Statement::Expression(Expression::ProcedureCall(
         Box::new(simple_procedure(
             vec!["x".to_string(), "y".to_string()],
             Expression::ProcedureCall(
-                Box::new(Expression::Identifier("+".to_string())),
+                Box::new(Expression::Identifier("+".into())),
                 vec![
-                    Expression::Identifier("x".to_string()),
-                    Expression::Identifier("y".to_string()),
+                    Expression::Identifier("x".into()),
+                    Expression::Identifier("y".into()),
                 ],
             ),
         )),
@@ -634,7 +1782,7 @@ fn lambda_call() -> Result<()> {
 #[test]
 fn condition() -> Result<()> {
     let interpreter = Interpreter::new();
-    let program = vec![Statement::Expression(Expression::Conditional(Box::new((
+    let program = [Statement::Expression(Expression::Conditional(Box::new((
         Expression::Boolean(true),
         Expression::Integer(1),
         Some(Expression::Integer(2)),
@@ -649,23 +1797,23 @@ fn condition() -> Result<()> {
 #[test]
 fn local_environment() -> Result<()> {
     let interpreter = Interpreter::new();
-    let program = vec![
+    let program = [
         Statement::Definition(Definition(
             "adda".to_string(),
             simple_procedure(
                 vec!["x".to_string()],
                 Expression::ProcedureCall(
-                    Box::new(Expression::Identifier("+".to_string())),
+                    Box::new(Expression::Identifier("+".into())),
                     vec![
-                        Expression::Identifier("x".to_string()),
-                        Expression::Identifier("a".to_string()),
+                        Expression::Identifier("x".into()),
+                        Expression::Identifier("a".into()),
                     ],
                 ),
             ),
         )),
         Statement::Definition(Definition("a".to_string(), Expression::Integer(1))),
         Statement::Expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("adda".to_string())),
+            Box::new(Expression::Identifier("adda".into())),
             vec![Expression::Integer(2)],
         )),
     ];
@@ -679,16 +1827,16 @@ fn local_environment() -> Result<()> {
 #[test]
 fn procedure_as_data() -> Result<()> {
     let interpreter = Interpreter::new();
-    let program = vec![
+    let program = [
         Statement::Definition(Definition(
             "add".to_string(),
             simple_procedure(
                 vec!["x".to_string(), "y".to_string()],
                 Expression::ProcedureCall(
-                    Box::new(Expression::Identifier("+".to_string())),
+                    Box::new(Expression::Identifier("+".into())),
                     vec![
-                        Expression::Identifier("x".to_string()),
-                        Expression::Identifier("y".to_string()),
+                        Expression::Identifier("x".into()),
+                        Expression::Identifier("y".into()),
                     ],
                 ),
             ),
@@ -698,18 +1846,18 @@ fn procedure_as_data() -> Result<()> {
             simple_procedure(
                 vec!["op".to_string(), "x".to_string(), "y".to_string()],
                 Expression::ProcedureCall(
-                    Box::new(Expression::Identifier("op".to_string())),
+                    Box::new(Expression::Identifier("op".into())),
                     vec![
-                        Expression::Identifier("x".to_string()),
-                        Expression::Identifier("y".to_string()),
+                        Expression::Identifier("x".into()),
+                        Expression::Identifier("y".into()),
                     ],
                 ),
             ),
         )),
         Statement::Expression(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("apply-op".to_string())),
+            Box::new(Expression::Identifier("apply-op".into())),
             vec![
-                Expression::Identifier("add".to_string()),
+                Expression::Identifier("add".into()),
                 Expression::Integer(1),
                 Expression::Integer(2),
             ],
@@ -721,3 +1869,171 @@ fn procedure_as_data() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn pattern_matching() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "(define (describe x)
+           (match x
+             (0 'zero)
+             ((? symbol? s) (list 'symbol s))
+             (\"text\" 'text)
+             ('(quoted) 'quoted)
+             ((a b) (list 'two a b))
+             ((first rest ...) (list 'many first rest))
+             (#(x _ z) (list 'vector x z))
+             ((? vector? (? (lambda (v) (= (vector-length v) 2)))) 'short-vector)
+             (_ 'other)))"
+            .chars(),
+    )?;
+    assert_printed(
+        &interpreter,
+        &[
+            ("(describe 0)", "zero"),
+            ("(describe 'a)", "(symbol a)"),
+            ("(describe \"text\")", "text"),
+            ("(describe '(quoted))", "quoted"),
+            ("(describe '(1 2))", "(two 1 2)"),
+            ("(describe '(1 2 3))", "(many 1 (2 3))"),
+            ("(describe '(1))", "(many 1 ())"),
+            ("(describe #(1 2 3))", "(vector 1 3)"),
+            ("(describe #(1 2))", "short-vector"),
+            ("(describe 5)", "other"),
+            (
+                "(match '((a 1) (b 2) (c 3)) (((names values) ...) (list names values)))",
+                "((a b c) (1 2 3))",
+            ),
+            (
+                "(match '(1 2 3 4) ((a b ... c) (list a b c)))",
+                "(1 (2 3) 4)",
+            ),
+            ("(match '(1 2 . 3) ((a . b) (list a b)))", "(1 (2 . 3))"),
+            ("(match '() (() 'empty))", "empty"),
+            ("(match '(1 2) ((a b) (define sum (+ a b)) (* sum 2)))", "6"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(match 1 (2 'two))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "match: no clause matches 1".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn cut() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("((cut + 1 <>) 2)", "3"),
+            ("((cut <> 1 2) -)", "-1"),
+            ("((cut list <> 'b <>) 'a 'c)", "(a b c)"),
+            // cute evaluates the non-slot expressions once
+            (
+                "(define port (open-output-string))
+             (define (noted x) (write-char #\\n port) x)
+             (define add (cute + (noted 1) <>))
+             (add 1) (add 2)
+             (define add-each-time (cut + (noted 1) <>))
+             (add-each-time 1) (add-each-time 2)
+             (get-output-string port)",
+                "\"nnn\"",
+            ),
+            // the slots are bound to no name the program can write
+            (
+                "(define |slot 1| 100) ((cut list <> |slot 1|) 7)",
+                "(7 100)",
+            ),
+            (
+                "(define |value 1| 100) ((cute list |value 1| <>) 7)",
+                "(100 7)",
+            ),
+        ],
+    )?;
+    Ok(())
+}
+
+#[test]
+fn closure() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let program = "
+        (define (make-adder n) (lambda (x) (+ x n)))
+        (define add2 (make-adder 2))
+        (define n 10)
+        (add2 1)";
+    assert_eq!(
+        interpreter.eval(program.chars())?,
+        Some(ValueType::Number(Number::Integer(3)))
+    );
+    assert!(matches!(
+        interpreter.eval("(add2 1 2)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            ..
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn call_depth() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.max_call_depth.set(30);
+    interpreter.eval("(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1)))))".chars())?;
+    assert_eq!(
+        interpreter.eval("(count 60)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "(lambda (n) (if (= n 0) 0 (+ 1 (count (- n 1))))): maximum call depth of 30 exceeded"
+                .to_string()
+        })
+    );
+    // the depth unwinds with the error
+    assert_eq!(
+        interpreter.eval("(count 29)".chars())?,
+        Some(ValueType::Number(Number::Integer(29)))
+    );
+    // a loop calling itself last runs in one frame, as long as it takes
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "(define (count n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+         (define (sum n) (define (add n acc) (if (= n 0) acc (add (- n 1) (+ acc n)))) (add n 0))"
+            .chars(),
+    )?;
+    assert_eq!(
+        interpreter.eval("(count 50000 0)".chars())?,
+        Some(ValueType::Number(Number::Integer(50000)))
+    );
+    assert_eq!(
+        interpreter.eval("(sum 50000)".chars())?,
+        Some(ValueType::Number(Number::Integer(1250025000)))
+    );
+    // the tree-walker recursing on the stack of a test thread runs out of it before the
+    // default depth, the vm does not recurse on it
+    for (engine, exhausted) in [
+        (vm::Engine::TreeWalker, "stack exhausted at a call depth of"),
+        (vm::Engine::Bytecode, "maximum call depth of 10000 exceeded"),
+    ] {
+        let interpreter = Interpreter::new();
+        interpreter.engine.set(engine);
+        interpreter.eval("(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1)))))".chars())?;
+        let error = interpreter.eval("(count 1000000)".chars()).unwrap_err();
+        assert!(error.message.contains(exhausted), "{}", error.message);
+        assert_eq!(
+            interpreter.eval("(count 20)".chars())?,
+            Some(ValueType::Number(Number::Integer(20)))
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn value_size() {
+    // values are copied onto the stack of the vm and into every pair and vector, so the
+    // variants keep what does not fit in four words behind a pointer
+    assert_eq!(std::mem::size_of::<ValueType>(), 32);
+}
@@ -0,0 +1,1179 @@
+use crate::error::*;
+use crate::interpreter::bigint::BigInt;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone)]
+pub enum Number {
+    Integer(i64),
+    Real(f64),
+    // always normalized: the denominator is greater than 1 and coprime to the numerator
+    Rational(i64, i64),
+    // exact integers out of the range of i64
+    Big(Rc<BigInt>),
+    // rationals with a numerator or denominator out of the range of i64, normalized as those
+    BigRational(Rc<BigInt>, Rc<BigInt>),
+}
+
+// the bits exact integers may have, so that a computation runs out of range rather than
+// out of memory
+pub(crate) const MAX_INTEGER_BITS: u64 = 1 << 20;
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Integer(n) => write!(f, "{}", n),
+            Number::Real(n) if n.is_nan() => write!(f, "+nan.0"),
+            Number::Real(n) if n.is_infinite() => match n.is_sign_positive() {
+                true => write!(f, "+inf.0"),
+                false => write!(f, "-inf.0"),
+            },
+            Number::Real(n) => write!(f, "{:?}", n),
+            Number::Rational(a, b) => write!(f, "{}/{}", a, b),
+            Number::Big(n) => write!(f, "{}", n),
+            Number::BigRational(a, b) => write!(f, "{}/{}", a, b),
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+// exact and inexact numbers are compared without converting the exact one to a float,
+// which would lose precision for integers beyond 2^53
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Real(a), Number::Real(b)) => a.partial_cmp(b),
+            (exact, Number::Real(b)) => compare_exact_with_real(exact, *b),
+            (Number::Real(a), exact) => compare_exact_with_real(exact, *a).map(Ordering::reverse),
+            (a, b) => match upcast_oprands(a, b) {
+                NumberBinaryOperand::Integer(a, b) => a.partial_cmp(&b),
+                NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
+                    (a1 as i128 * b2 as i128).partial_cmp(&(b1 as i128 * a2 as i128))
+                }
+                NumberBinaryOperand::Real(a, b) => a.partial_cmp(&b),
+                NumberBinaryOperand::Big((a1, a2), (b1, b2)) => {
+                    Some(a1.multiply(&b2).cmp(&b1.multiply(&a2)))
+                }
+            },
+        }
+    }
+}
+
+// compares numerator / denominator with a fraction in [0, 1) by writing the float as
+// mantissa * 2^-shift, both sides are then compared as integers
+fn compare_fraction_with_real(numerator: u128, denominator: u128, fraction: f64) -> Ordering {
+    if numerator == 0 || fraction == 0.0 {
+        return (numerator > 0).cmp(&(fraction > 0.0));
+    }
+    let bits = fraction.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let (mantissa, shift) = match exponent {
+        0 => (bits & ((1 << 52) - 1), 1074),
+        _ => ((bits & ((1 << 52) - 1)) | (1 << 52), 1075 - exponent),
+    };
+    let numerator_bits = 128 - numerator.leading_zeros() as i32;
+    match numerator_bits + shift > 127 {
+        // the mantissa times the denominator is less than 2^116
+        true => numerator.cmp(&0),
+        false => (numerator << shift).cmp(&(mantissa as u128 * denominator)),
+    }
+}
+
+fn compare_exact_with_real(exact: &Number, real: f64) -> Option<Ordering> {
+    if real.is_nan() {
+        return None;
+    }
+    if real.is_infinite() {
+        return Some(match real > 0.0 {
+            true => Ordering::Less,
+            false => Ordering::Greater,
+        });
+    }
+    // big numbers are compared with the exact value of the float
+    if let (Number::Big(_) | Number::BigRational(..), Some((b1, b2))) =
+        (exact, fraction_of_f64(real))
+    {
+        let (a1, a2) = exact.fraction()?;
+        return Some(a1.multiply(&b2).cmp(&b1.multiply(&a2)));
+    }
+    let real_floor = real.floor();
+    // the cast saturates, which is still far beyond the range of i64
+    let integer_order = match exact.floor() {
+        Number::Integer(n) => (n as i128).cmp(&(real_floor as i128)),
+        _ => unreachable!(),
+    };
+    match (integer_order, exact) {
+        (Ordering::Equal, Number::Rational(a, b)) => Some(compare_fraction_with_real(
+            a.rem_euclid(*b) as u128,
+            *b as u128,
+            real - real_floor,
+        )),
+        (Ordering::Equal, _) => 0.0.partial_cmp(&(real - real_floor)),
+        (order, _) => Some(order),
+    }
+}
+
+pub(crate) enum NumberBinaryOperand {
+    Integer(i64, i64),
+    Real(f64, f64),
+    Rational(i64, i64, i64, i64),
+    // the numerators and denominators of exact numbers, one of which at least is big
+    Big((BigInt, BigInt), (BigInt, BigInt)),
+}
+
+// Integer => Rational => Big => Real
+pub(crate) fn upcast_oprands(a: &Number, b: &Number) -> NumberBinaryOperand {
+    match (a, b) {
+        (Number::Real(a), b) => NumberBinaryOperand::Real(*a, b.to_f64()),
+        (a, Number::Real(b)) => NumberBinaryOperand::Real(a.to_f64(), *b),
+        (Number::Rational(dividend, dividor), Number::Integer(b)) => {
+            NumberBinaryOperand::Rational(*dividend, *dividor, *b, 1)
+        }
+        (Number::Integer(a), Number::Rational(dividend, dividor)) => {
+            NumberBinaryOperand::Rational(*a, 1, *dividend, *dividor)
+        }
+        (Number::Integer(a), Number::Integer(b)) => NumberBinaryOperand::Integer(*a, *b),
+        (Number::Rational(a1, a2), Number::Rational(b1, b2)) => {
+            NumberBinaryOperand::Rational(*a1, *a2, *b1, *b2)
+        }
+        // both are exact
+        (a, b) => NumberBinaryOperand::Big(a.fraction().unwrap(), b.fraction().unwrap()),
+    }
+}
+
+pub(crate) fn check_division_by_zero(num: i64) -> Result<()> {
+    match num {
+        0 => logic_error!("division by exact zero"),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn integer_overflow() -> Error {
+    Error {
+        category: ErrorType::Logic,
+        message: "integer overflow".to_string(),
+    }
+}
+
+// the product of big integers, which must not have more than MAX_INTEGER_BITS bits
+fn checked_product(a: &BigInt, b: &BigInt) -> Result<BigInt> {
+    match a.bits() + b.bits() > MAX_INTEGER_BITS {
+        true => Err(integer_overflow()),
+        false => Ok(a.multiply(b)),
+    }
+}
+
+fn gcd_u128(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+// reduce the fraction and demote it to an integer when the denominator is 1
+fn normalize_rational(numerator: i128, denominator: i128) -> Result<Number> {
+    if denominator == 0 {
+        logic_error!("division by exact zero")
+    }
+    let divisor = gcd_u128(numerator.unsigned_abs(), denominator.unsigned_abs()) as i128;
+    let (mut numerator, mut denominator) = (numerator / divisor, denominator / divisor);
+    if denominator < 0 {
+        numerator = -numerator;
+        denominator = -denominator;
+    }
+    match (
+        i64::try_from(numerator).ok(),
+        i64::try_from(denominator).ok(),
+    ) {
+        (Some(n), Some(1)) => Ok(Number::Integer(n)),
+        (Some(n), Some(d)) => Ok(Number::Rational(n, d)),
+        _ => normalize_fraction(BigInt::from_i128(numerator), BigInt::from_i128(denominator)),
+    }
+}
+
+// the same for big numerators and denominators, which are demoted to the smallest
+// representation of the number
+fn normalize_fraction(numerator: BigInt, denominator: BigInt) -> Result<Number> {
+    if denominator.is_zero() {
+        logic_error!("division by exact zero")
+    }
+    let (mut numerator, mut denominator) = match denominator == BigInt::one() {
+        true => (numerator, denominator),
+        false => {
+            let divisor = numerator.gcd(&denominator);
+            (numerator.divide(&divisor).0, denominator.divide(&divisor).0)
+        }
+    };
+    if denominator.is_negative() {
+        numerator = numerator.negate();
+        denominator = denominator.negate();
+    }
+    Ok(match (numerator.to_i64(), denominator.to_i64()) {
+        (Some(n), Some(1)) => Number::Integer(n),
+        (Some(n), Some(d)) => Number::Rational(n, d),
+        _ if denominator == BigInt::one() => Number::Big(Rc::new(numerator)),
+        _ => Number::BigRational(Rc::new(numerator), Rc::new(denominator)),
+    })
+}
+
+// the exact value of a finite float, not reduced
+fn fraction_of_f64(n: f64) -> Option<(BigInt, BigInt)> {
+    if !n.is_finite() {
+        return None;
+    }
+    let bits = n.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64;
+    let (mantissa, exponent) = match exponent {
+        0 => (bits & ((1 << 52) - 1), -1074),
+        _ => ((bits & ((1 << 52) - 1)) | (1 << 52), exponent - 1075),
+    };
+    let mantissa = match n < 0.0 {
+        true => BigInt::from_i128(-(mantissa as i128)),
+        false => BigInt::from_i128(mantissa as i128),
+    };
+    Some(match exponent < 0 {
+        true => (
+            mantissa,
+            BigInt::one().shift_left(exponent.unsigned_abs() as usize),
+        ),
+        false => (mantissa.shift_left(exponent as usize), BigInt::one()),
+    })
+}
+
+// the quotient is computed to 65 bits at least, the lowest of which is set when the division
+// is inexact, so that it rounds as the fraction does
+// the root of mantissa * 2^shift, halving an even power of two
+fn scaled_sqrt(mantissa: f64, shift: i64) -> f64 {
+    let (mantissa, shift) = match shift.rem_euclid(2) {
+        0 => (mantissa, shift),
+        _ => (mantissa * 2.0, shift - 1),
+    };
+    mantissa.sqrt() * 2f64.powi((shift / 2) as i32)
+}
+
+fn big_ln(n: &BigInt) -> f64 {
+    match n.is_negative() {
+        true => f64::NAN,
+        false => {
+            let (mantissa, shift) = n.to_scaled_f64();
+            mantissa.ln() + shift as f64 * std::f64::consts::LN_2
+        }
+    }
+}
+
+fn fraction_to_f64(numerator: &BigInt, denominator: &BigInt) -> f64 {
+    if numerator.is_zero() {
+        return 0.0;
+    }
+    let magnitude = numerator.abs();
+    let shift = denominator.bits() as i64 - magnitude.bits() as i64 + 65;
+    let (quotient, remainder) = match shift > 0 {
+        true => magnitude.shift_left(shift as usize).divide(denominator),
+        false => magnitude.divide(&denominator.shift_left(shift.unsigned_abs() as usize)),
+    };
+    let quotient = match remainder.is_zero() {
+        true => quotient,
+        false => quotient.bitwise(&BigInt::one(), |a, b| a | b),
+    };
+    let mut scaled = quotient.to_f64();
+    let mut exponent = -shift;
+    while exponent != 0 {
+        let step = exponent.clamp(-1000, 1000);
+        scaled *= 2f64.powi(step as i32);
+        exponent -= step;
+    }
+    match numerator.is_negative() {
+        true => -scaled,
+        false => scaled,
+    }
+}
+
+fn exact_sqrt(n: u64) -> Option<u64> {
+    let root = n.isqrt();
+    match root * root == n {
+        true => Some(root),
+        false => None,
+    }
+}
+
+fn exact_big_sqrt(n: &BigInt) -> Option<BigInt> {
+    let root = n.sqrt();
+    match root.multiply(&root) == *n {
+        true => Some(root),
+        false => None,
+    }
+}
+
+fn exact_power(mut base: Number, mut exponent: u64) -> Result<Number> {
+    let mut result = Number::Integer(1);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = (result * base.clone())?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = (base.clone() * base)?;
+        }
+    }
+    Ok(result)
+}
+
+enum IntegerOperands {
+    Exact(i64, i64),
+    Big(BigInt, BigInt),
+    Inexact(f64, f64),
+}
+
+fn integer_operands(name: &str, a: &Number, b: &Number) -> Result<IntegerOperands> {
+    match (a, b) {
+        (Number::Integer(a), Number::Integer(b)) => Ok(IntegerOperands::Exact(*a, *b)),
+        (Number::Integer(_) | Number::Big(_), Number::Integer(_) | Number::Big(_)) => Ok(
+            IntegerOperands::Big(a.fraction().unwrap().0, b.fraction().unwrap().0),
+        ),
+        _ if a.is_integer() && b.is_integer() => {
+            Ok(IntegerOperands::Inexact(a.to_f64(), b.to_f64()))
+        }
+        _ => logic_error!("{}: expect integers, got {} and {}", name, a, b),
+    }
+}
+
+fn check_big_division_by_zero(num: &BigInt) -> Result<()> {
+    match num.is_zero() {
+        true => logic_error!("division by exact zero"),
+        false => Ok(()),
+    }
+}
+
+fn check_inexact_division_by_zero(num: f64) -> Result<()> {
+    match num == 0.0 {
+        true => logic_error!("division by zero"),
+        false => Ok(()),
+    }
+}
+
+impl Number {
+    pub fn rational(numerator: i64, denominator: i64) -> Result<Number> {
+        normalize_rational(numerator as i128, denominator as i128)
+    }
+
+    // demotes integers in the range of i64
+    pub fn integer(n: BigInt) -> Number {
+        match n.to_i64() {
+            Some(n) => Number::Integer(n),
+            None => Number::Big(Rc::new(n)),
+        }
+    }
+
+    pub fn from_i128(n: i128) -> Number {
+        match i64::try_from(n) {
+            Ok(n) => Number::Integer(n),
+            Err(_) => Number::Big(Rc::new(BigInt::from_i128(n))),
+        }
+    }
+
+    pub fn fraction_of(numerator: BigInt, denominator: BigInt) -> Result<Number> {
+        normalize_fraction(numerator, denominator)
+    }
+
+    // the numerator and denominator of an exact number
+    pub fn fraction(&self) -> Option<(BigInt, BigInt)> {
+        match self {
+            Number::Integer(n) => Some((BigInt::from_i128(*n as i128), BigInt::one())),
+            Number::Rational(a, b) => {
+                Some((BigInt::from_i128(*a as i128), BigInt::from_i128(*b as i128)))
+            }
+            Number::Big(n) => Some((n.as_ref().clone(), BigInt::one())),
+            Number::BigRational(a, b) => Some((a.as_ref().clone(), b.as_ref().clone())),
+            Number::Real(_) => None,
+        }
+    }
+
+    // the value of an exact integer of any size
+    pub fn to_big(&self) -> Option<BigInt> {
+        match self {
+            Number::Integer(n) => Some(BigInt::from_i128(*n as i128)),
+            Number::Big(n) => Some(n.as_ref().clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, Number::Real(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Number::Integer(_) | Number::Big(_) => true,
+            Number::Real(n) => n.is_finite() && n.fract() == 0.0,
+            Number::Rational(_, _) | Number::BigRational(..) => false,
+        }
+    }
+
+    pub fn is_nan(&self) -> bool {
+        matches!(self, Number::Real(n) if n.is_nan())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Integer(n) => *n as f64,
+            Number::Real(n) => *n,
+            Number::Rational(a, b) => *a as f64 / *b as f64,
+            Number::Big(n) => n.to_f64(),
+            Number::BigRational(a, b) => fraction_to_f64(a, b),
+        }
+    }
+
+    pub fn to_inexact(&self) -> Number {
+        Number::Real(self.to_f64())
+    }
+
+    pub fn abs(&self) -> Result<Number> {
+        Ok(match self {
+            Number::Integer(n) => Number::from_i128((*n as i128).abs()),
+            Number::Real(n) => Number::Real(n.abs()),
+            Number::Rational(a, b) => match a.checked_abs() {
+                Some(a) => Number::Rational(a, *b),
+                None => normalize_rational((*a as i128).abs(), *b as i128)?,
+            },
+            Number::Big(n) => Number::Big(Rc::new(n.abs())),
+            Number::BigRational(a, b) => Number::BigRational(Rc::new(a.abs()), b.clone()),
+        })
+    }
+
+    // truncated division, the remainder has the sign of the dividend
+    pub fn quotient(&self, rhs: &Number) -> Result<Number> {
+        match integer_operands("quotient", self, rhs)? {
+            IntegerOperands::Exact(a, b) => {
+                check_division_by_zero(b)?;
+                Ok(Number::from_i128(a as i128 / b as i128))
+            }
+            IntegerOperands::Big(a, b) => {
+                check_big_division_by_zero(&b)?;
+                Ok(Number::integer(a.divide(&b).0))
+            }
+            IntegerOperands::Inexact(a, b) => {
+                check_inexact_division_by_zero(b)?;
+                Ok(Number::Real((a / b).trunc()))
+            }
+        }
+    }
+
+    pub fn remainder(&self, rhs: &Number) -> Result<Number> {
+        match integer_operands("remainder", self, rhs)? {
+            IntegerOperands::Exact(a, b) => {
+                check_division_by_zero(b)?;
+                // i64::MIN % -1 overflows in rust, but the remainder is 0 mathematically
+                Ok(Number::Integer(a.checked_rem(b).unwrap_or(0)))
+            }
+            IntegerOperands::Big(a, b) => {
+                check_big_division_by_zero(&b)?;
+                Ok(Number::integer(a.divide(&b).1))
+            }
+            IntegerOperands::Inexact(a, b) => {
+                check_inexact_division_by_zero(b)?;
+                Ok(Number::Real(a % b))
+            }
+        }
+    }
+
+    // floored division, the modulo has the sign of the divisor
+    pub fn modulo(&self, rhs: &Number) -> Result<Number> {
+        match integer_operands("modulo", self, rhs)? {
+            IntegerOperands::Exact(a, b) => {
+                check_division_by_zero(b)?;
+                let r = a.checked_rem(b).unwrap_or(0);
+                Ok(Number::Integer(match r != 0 && (r < 0) != (b < 0) {
+                    true => r + b,
+                    false => r,
+                }))
+            }
+            IntegerOperands::Big(a, b) => {
+                check_big_division_by_zero(&b)?;
+                Ok(Number::integer(a.divide_floor(&b).1))
+            }
+            IntegerOperands::Inexact(a, b) => {
+                check_inexact_division_by_zero(b)?;
+                Ok(Number::Real(a - b * (a / b).floor()))
+            }
+        }
+    }
+
+    pub fn floor_quotient(&self, rhs: &Number) -> Result<Number> {
+        match integer_operands("floor-quotient", self, rhs)? {
+            IntegerOperands::Exact(a, b) => {
+                check_division_by_zero(b)?;
+                let q = a as i128 / b as i128;
+                Ok(Number::from_i128(match a % b != 0 && (a < 0) != (b < 0) {
+                    true => q - 1,
+                    false => q,
+                }))
+            }
+            IntegerOperands::Big(a, b) => {
+                check_big_division_by_zero(&b)?;
+                Ok(Number::integer(a.divide_floor(&b).0))
+            }
+            IntegerOperands::Inexact(a, b) => {
+                check_inexact_division_by_zero(b)?;
+                Ok(Number::Real((a / b).floor()))
+            }
+        }
+    }
+
+    // denominators of normalized rationals are always positive, so euclidean division floors
+    pub fn floor(&self) -> Number {
+        match self {
+            Number::Integer(_) | Number::Big(_) => self.clone(),
+            Number::Real(n) => Number::Real(n.floor()),
+            Number::Rational(a, b) => Number::Integer(a.div_euclid(*b)),
+            Number::BigRational(a, b) => Number::integer(a.divide_floor(b).0),
+        }
+    }
+
+    pub fn ceiling(&self) -> Number {
+        match self {
+            Number::Integer(_) | Number::Big(_) => self.clone(),
+            Number::Real(n) => Number::Real(n.ceil()),
+            Number::Rational(a, b) => Number::Integer(a.div_euclid(*b) + 1),
+            Number::BigRational(a, b) => Number::integer(a.divide_floor(b).0.add(&BigInt::one())),
+        }
+    }
+
+    pub fn truncate(&self) -> Number {
+        match self {
+            Number::Integer(_) | Number::Big(_) => self.clone(),
+            Number::Real(n) => Number::Real(n.trunc()),
+            Number::Rational(a, b) => Number::Integer(a / b),
+            Number::BigRational(a, b) => Number::integer(a.divide(b).0),
+        }
+    }
+
+    // rounds to even when the number is halfway between two integers
+    pub fn round(&self) -> Number {
+        match self {
+            Number::Integer(_) | Number::Big(_) => self.clone(),
+            Number::Real(n) => Number::Real(n.round_ties_even()),
+            Number::Rational(a, b) => {
+                let floor = a.div_euclid(*b);
+                let twice_remainder = 2 * a.rem_euclid(*b) as i128;
+                Number::Integer(match twice_remainder.cmp(&(*b as i128)) {
+                    Ordering::Less => floor,
+                    Ordering::Greater => floor + 1,
+                    Ordering::Equal => floor + floor.rem_euclid(2),
+                })
+            }
+            Number::BigRational(a, b) => {
+                let (floor, remainder) = a.divide_floor(b);
+                let up = match remainder.shift_left(1).cmp(b) {
+                    Ordering::Less => false,
+                    Ordering::Greater => true,
+                    Ordering::Equal => !floor.is_even(),
+                };
+                Number::integer(match up {
+                    true => floor.add(&BigInt::one()),
+                    false => floor,
+                })
+            }
+        }
+    }
+
+    // exact for exact perfect squares, NaN for negative numbers as complex is not supported
+    pub fn sqrt(&self) -> Number {
+        match self {
+            Number::Integer(n) if *n >= 0 => match exact_sqrt(*n as u64) {
+                Some(root) => Number::Integer(root as i64),
+                None => Number::Real((*n as f64).sqrt()),
+            },
+            Number::Rational(a, b) if *a > 0 => {
+                match (exact_sqrt(*a as u64), exact_sqrt(*b as u64)) {
+                    (Some(a), Some(b)) => Number::Rational(a as i64, b as i64),
+                    _ => Number::Real(self.to_f64().sqrt()),
+                }
+            }
+            Number::Big(n) if !n.is_negative() => match exact_big_sqrt(n) {
+                Some(root) => Number::integer(root),
+                None => {
+                    let (mantissa, shift) = n.to_scaled_f64();
+                    Number::Real(scaled_sqrt(mantissa, shift as i64))
+                }
+            },
+            Number::BigRational(a, b) if !a.is_negative() => {
+                match (exact_big_sqrt(a), exact_big_sqrt(b)) {
+                    (Some(a), Some(b)) => {
+                        normalize_fraction(a, b).unwrap_or_else(|_| self.to_inexact())
+                    }
+                    _ => {
+                        let ((a, a_shift), (b, b_shift)) = (a.to_scaled_f64(), b.to_scaled_f64());
+                        Number::Real(scaled_sqrt(a / b, a_shift as i64 - b_shift as i64))
+                    }
+                }
+            }
+            _ => Number::Real(self.to_f64().sqrt()),
+        }
+    }
+
+    // the natural logarithm, of exact numbers too large for a float as well
+    pub fn ln(&self) -> f64 {
+        match self {
+            Number::Big(n) => big_ln(n),
+            Number::BigRational(a, b) => big_ln(a) - big_ln(b),
+            _ => self.to_f64().ln(),
+        }
+    }
+
+    // returns s and r where s * s + r equals to the number
+    pub fn exact_integer_sqrt(&self) -> Result<(Number, Number)> {
+        match self {
+            Number::Integer(n) if *n >= 0 => {
+                let root = (*n as u64).isqrt() as i64;
+                Ok((Number::Integer(root), Number::Integer(n - root * root)))
+            }
+            Number::Big(n) if !n.is_negative() => {
+                let root = n.sqrt();
+                let rest = n.subtract(&root.multiply(&root));
+                Ok((Number::integer(root), Number::integer(rest)))
+            }
+            _ => logic_error!(
+                "exact-integer-sqrt: expect an exact non-negative integer, got {}",
+                self
+            ),
+        }
+    }
+
+    // exact integer exponents of exact bases keep the result exact
+    pub fn expt(&self, exponent: &Number) -> Result<Number> {
+        match (self, exponent) {
+            (_, Number::Integer(e)) if self.is_exact() => {
+                let power = exact_power(self.clone(), e.unsigned_abs())?;
+                match *e < 0 {
+                    true => Number::Integer(1) / power,
+                    false => Ok(power),
+                }
+            }
+            // only the powers of 0, 1 and -1 are not too big
+            (Number::Integer(base @ -1..=1), Number::Big(e)) => match (base, e.is_negative()) {
+                (0, true) => logic_error!("division by exact zero"),
+                (-1, _) if !e.is_even() => Ok(Number::Integer(-1)),
+                (0, false) => Ok(Number::Integer(0)),
+                _ => Ok(Number::Integer(1)),
+            },
+            (_, Number::Big(_)) if self.is_exact() => Err(integer_overflow()),
+            (Number::Real(base), Number::Integer(e)) if i32::try_from(*e).is_ok() => {
+                Ok(Number::Real(base.powi(*e as i32)))
+            }
+            _ => Ok(Number::Real(self.to_f64().powf(exponent.to_f64()))),
+        }
+    }
+
+    pub fn gcd(&self, rhs: &Number) -> Result<Number> {
+        match integer_operands("gcd", self, rhs)? {
+            IntegerOperands::Exact(a, b) => Ok(Number::from_i128(gcd_u128(
+                a.unsigned_abs() as u128,
+                b.unsigned_abs() as u128,
+            ) as i128)),
+            IntegerOperands::Big(a, b) => Ok(Number::integer(a.gcd(&b))),
+            IntegerOperands::Inexact(a, b) => {
+                let (mut a, mut b) = (a.abs(), b.abs());
+                while b != 0.0 {
+                    let r = a % b;
+                    a = b;
+                    b = r;
+                }
+                Ok(Number::Real(a))
+            }
+        }
+    }
+
+    pub fn lcm(&self, rhs: &Number) -> Result<Number> {
+        let divisor = self.gcd(rhs)?;
+        match integer_operands("lcm", self, rhs)? {
+            IntegerOperands::Exact(a, b) => match divisor {
+                Number::Integer(0) => Ok(Number::Integer(0)),
+                Number::Integer(d) => Ok(Number::from_i128((a / d) as i128 * b as i128).abs()?),
+                // the divisor of i64::MIN and i64::MIN
+                _ => Ok(Number::from_i128(i64::MIN as i128).abs()?),
+            },
+            IntegerOperands::Big(a, b) => match divisor.to_big() {
+                Some(divisor) if divisor.is_zero() => Ok(Number::Integer(0)),
+                Some(divisor) => Ok(Number::integer(
+                    checked_product(&a.divide(&divisor).0, &b)?.abs(),
+                )),
+                None => unreachable!(),
+            },
+            IntegerOperands::Inexact(a, b) => {
+                let divisor = divisor.to_f64();
+                Ok(Number::Real(match divisor == 0.0 {
+                    true => 0.0,
+                    false => (a / divisor * b).abs(),
+                }))
+            }
+        }
+    }
+}
+
+fn integer_to_string(n: i64, radix: u32) -> String {
+    let mut digits = vec![];
+    let mut magnitude = n.unsigned_abs();
+    loop {
+        digits.push(std::char::from_digit((magnitude % radix as u64) as u32, radix).unwrap());
+        magnitude /= radix as u64;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+impl Number {
+    pub fn to_string_radix(&self, radix: u32) -> Result<String> {
+        match (self, radix) {
+            (_, 10) => Ok(format!("{}", self)),
+            (Number::Real(_), 2) | (Number::Real(_), 8) | (Number::Real(_), 16) => {
+                logic_error!("inexact numbers can only be written in radix 10")
+            }
+            (Number::Integer(n), 2) | (Number::Integer(n), 8) | (Number::Integer(n), 16) => {
+                Ok(integer_to_string(*n, radix))
+            }
+            (Number::Rational(a, b), 2)
+            | (Number::Rational(a, b), 8)
+            | (Number::Rational(a, b), 16) => Ok(format!(
+                "{}/{}",
+                integer_to_string(*a, radix),
+                integer_to_string(*b, radix)
+            )),
+            (Number::Big(n), 2) | (Number::Big(n), 8) | (Number::Big(n), 16) => {
+                Ok(n.to_string_radix(radix))
+            }
+            (Number::BigRational(a, b), 2)
+            | (Number::BigRational(a, b), 8)
+            | (Number::BigRational(a, b), 16) => Ok(format!(
+                "{}/{}",
+                a.to_string_radix(radix),
+                b.to_string_radix(radix)
+            )),
+            _ => logic_error!("radix should be one of 2, 8, 10 and 16, got {}", radix),
+        }
+    }
+
+    // parses the R7RS numeric syntax, returns None if the text is not a number
+    pub fn parse(text: &str, default_radix: u32) -> Option<Number> {
+        let mut radix = None;
+        let mut exactness = None;
+        let mut rest = text;
+        while let Some(prefix) = rest.strip_prefix('#') {
+            let mut chars = prefix.chars();
+            match chars.next()?.to_ascii_lowercase() {
+                'x' if radix.is_none() => radix = Some(16),
+                'o' if radix.is_none() => radix = Some(8),
+                'b' if radix.is_none() => radix = Some(2),
+                'd' if radix.is_none() => radix = Some(10),
+                'e' if exactness.is_none() => exactness = Some(true),
+                'i' if exactness.is_none() => exactness = Some(false),
+                _ => return None,
+            }
+            rest = chars.as_str();
+        }
+        let radix = radix.unwrap_or(default_radix);
+        let number = parse_real(rest, radix)?;
+        match (exactness, &number) {
+            (Some(true), Number::Real(n)) => match radix {
+                10 => exact_from_text(rest).or_else(|| exact_from_f64(*n)),
+                _ => exact_from_f64(*n),
+            },
+            (Some(false), _) => Some(number.to_inexact()),
+            _ => Some(number),
+        }
+    }
+}
+
+fn parse_uinteger(text: &str, radix: u32) -> Option<BigInt> {
+    BigInt::parse(text, radix)
+}
+
+fn split_sign(text: &str) -> (bool, &str) {
+    match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    }
+}
+
+// integers with more than MAX_INTEGER_BITS bits are read as inexact numbers
+fn parse_real(text: &str, radix: u32) -> Option<Number> {
+    match text {
+        "+inf.0" => return Some(Number::Real(f64::INFINITY)),
+        "-inf.0" => return Some(Number::Real(f64::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => return Some(Number::Real(f64::NAN)),
+        _ => (),
+    }
+    let (negative, unsigned) = split_sign(text);
+    let signed = |n: BigInt| -> BigInt {
+        match negative {
+            true => n.negate(),
+            false => n,
+        }
+    };
+    let exact = |n: Number| -> Number {
+        match n {
+            Number::Big(ref big) if big.bits() > MAX_INTEGER_BITS => n.to_inexact(),
+            Number::BigRational(ref a, ref b) if a.bits().max(b.bits()) > MAX_INTEGER_BITS => {
+                n.to_inexact()
+            }
+            n => n,
+        }
+    };
+    match unsigned.find('/') {
+        Some(index) => {
+            let numerator = parse_uinteger(&unsigned[..index], radix)?;
+            let denominator = parse_uinteger(&unsigned[index + 1..], radix)?;
+            normalize_fraction(signed(numerator), denominator)
+                .ok()
+                .map(exact)
+        }
+        None => match parse_uinteger(unsigned, radix) {
+            Some(n) => Some(exact(Number::integer(signed(n)))),
+            None if radix == 10 && is_decimal(unsigned) => {
+                text.parse::<f64>().ok().map(Number::Real)
+            }
+            None => None,
+        },
+    }
+}
+
+// digits with an optional fraction and exponent, at least one digit before the exponent
+fn is_decimal(text: &str) -> bool {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(index) => (&text[..index], Some(&text[index + 1..])),
+        None => (text, None),
+    };
+    let digits = mantissa.chars().filter(|c| c.is_ascii_digit()).count();
+    let dots = mantissa.chars().filter(|c| *c == '.').count();
+    digits > 0
+        && dots <= 1
+        && digits + dots == mantissa.len()
+        && exponent.is_none_or(|exponent| {
+            let (_, digits) = split_sign(exponent);
+            !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+// converts a decimal literal directly, so #e1.2 is exactly 6/5 instead of the float's value
+fn exact_from_text(text: &str) -> Option<Number> {
+    let (negative, unsigned) = split_sign(text);
+    if !is_decimal(unsigned) {
+        return None;
+    }
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(index) => (
+            &unsigned[..index],
+            unsigned[index + 1..].parse::<i32>().ok()?,
+        ),
+        None => (unsigned, 0),
+    };
+    let (integral, fraction) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+        None => (mantissa, ""),
+    };
+    let digits = parse_uinteger(&format!("{}{}", integral, fraction), 10)?;
+    let exponent = exponent.checked_sub(fraction.len() as i32)?;
+    // each power of ten takes less than four bits
+    if exponent.unsigned_abs() as u64 * 4 > MAX_INTEGER_BITS {
+        return None;
+    }
+    let scale = BigInt::from_i128(10).pow(exponent.unsigned_abs());
+    let digits = match negative {
+        true => digits.negate(),
+        false => digits,
+    };
+    match exponent < 0 {
+        true => normalize_fraction(digits, scale).ok(),
+        false => normalize_fraction(checked_product(&digits, &scale).ok()?, BigInt::one()).ok(),
+    }
+}
+
+fn exact_from_f64(n: f64) -> Option<Number> {
+    let (numerator, denominator) = fraction_of_f64(n)?;
+    normalize_fraction(numerator, denominator).ok()
+}
+
+impl std::ops::Add<Number> for Number {
+    type Output = Result<Number>;
+    fn add(self, rhs: Number) -> Result<Number> {
+        match upcast_oprands(&self, &rhs) {
+            NumberBinaryOperand::Integer(a, b) => Ok(Number::from_i128(a as i128 + b as i128)),
+            NumberBinaryOperand::Real(a, b) => Ok(Number::Real(a + b)),
+            NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
+                let (a1, a2, b1, b2) = (a1 as i128, a2 as i128, b1 as i128, b2 as i128);
+                normalize_rational(a1 * b2 + a2 * b1, a2 * b2)
+            }
+            NumberBinaryOperand::Big((a1, a2), (b1, b2)) => normalize_fraction(
+                checked_product(&a1, &b2)?.add(&checked_product(&a2, &b1)?),
+                checked_product(&a2, &b2)?,
+            ),
+        }
+    }
+}
+
+impl std::ops::Sub<Number> for Number {
+    type Output = Result<Number>;
+    fn sub(self, rhs: Number) -> Result<Number> {
+        match upcast_oprands(&self, &rhs) {
+            NumberBinaryOperand::Integer(a, b) => Ok(Number::from_i128(a as i128 - b as i128)),
+            NumberBinaryOperand::Real(a, b) => Ok(Number::Real(a - b)),
+            NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
+                let (a1, a2, b1, b2) = (a1 as i128, a2 as i128, b1 as i128, b2 as i128);
+                normalize_rational(a1 * b2 - a2 * b1, a2 * b2)
+            }
+            NumberBinaryOperand::Big((a1, a2), (b1, b2)) => normalize_fraction(
+                checked_product(&a1, &b2)?.subtract(&checked_product(&a2, &b1)?),
+                checked_product(&a2, &b2)?,
+            ),
+        }
+    }
+}
+
+impl std::ops::Mul<Number> for Number {
+    type Output = Result<Number>;
+    fn mul(self, rhs: Number) -> Result<Number> {
+        match upcast_oprands(&self, &rhs) {
+            NumberBinaryOperand::Integer(a, b) => Ok(Number::from_i128(a as i128 * b as i128)),
+            NumberBinaryOperand::Real(a, b) => Ok(Number::Real(a * b)),
+            NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
+                normalize_rational(a1 as i128 * b1 as i128, a2 as i128 * b2 as i128)
+            }
+            NumberBinaryOperand::Big((a1, a2), (b1, b2)) => {
+                normalize_fraction(checked_product(&a1, &b1)?, checked_product(&a2, &b2)?)
+            }
+        }
+    }
+}
+
+impl std::ops::Div<Number> for Number {
+    type Output = Result<Number>;
+    fn div(self, rhs: Number) -> Result<Number> {
+        match upcast_oprands(&self, &rhs) {
+            NumberBinaryOperand::Integer(a, b) => {
+                check_division_by_zero(b)?;
+                Number::rational(a, b)
+            }
+            NumberBinaryOperand::Real(a, b) => Ok(Number::Real(a / b)),
+            NumberBinaryOperand::Rational(a1, a2, b1, b2) => {
+                check_division_by_zero(b1)?;
+                normalize_rational(a1 as i128 * b2 as i128, a2 as i128 * b1 as i128)
+            }
+            NumberBinaryOperand::Big((a1, a2), (b1, b2)) => {
+                check_big_division_by_zero(&b1)?;
+                normalize_fraction(checked_product(&a1, &b2)?, checked_product(&a2, &b1)?)
+            }
+        }
+    }
+}
+
+#[test]
+fn rational_normalization() -> Result<()> {
+    assert_eq!(Number::rational(2, 4)?, Number::Rational(1, 2));
+    assert_eq!(Number::rational(3, -6)?, Number::Rational(-1, 2));
+    assert!(matches!(Number::rational(4, 2)?, Number::Integer(2)));
+    assert!(matches!(
+        (Number::Rational(1, 2) + Number::Rational(1, 2))?,
+        Number::Integer(1)
+    ));
+    assert_eq!(
+        Number::rational(1, 0),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "division by exact zero".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn integer_division() -> Result<()> {
+    let int = Number::Integer;
+    assert_eq!(int(-7).quotient(&int(2))?, int(-3));
+    assert_eq!(int(-7).remainder(&int(2))?, int(-1));
+    assert_eq!(int(-7).modulo(&int(2))?, int(1));
+    assert_eq!(int(7).modulo(&int(-2))?, int(-1));
+    assert!(matches!(
+        Number::Real(-7.0).modulo(&int(2))?,
+        Number::Real(n) if n == 1.0
+    ));
+    assert_eq!(
+        int(1).quotient(&Number::Rational(1, 2)),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "quotient: expect integers, got 1 and 1/2".to_string()
+        })
+    );
+    assert_eq!(int(12).gcd(&int(-18))?, int(6));
+    assert_eq!(int(4).lcm(&int(-6))?, int(12));
+    assert_eq!(int(0).lcm(&int(5))?, int(0));
+    Ok(())
+}
+
+#[test]
+fn overflow() -> Result<()> {
+    let big = |text| Number::parse(text, 10).unwrap();
+    let sum = (Number::Integer(i64::MAX) + Number::Integer(1))?;
+    assert!(matches!(sum, Number::Big(_)));
+    assert_eq!(sum.to_string(), "9223372036854775808");
+    assert_eq!(Number::Integer(i64::MIN).abs()?, sum);
+    assert!(matches!(
+        (sum.clone() - Number::Integer(1))?,
+        Number::Integer(i64::MAX)
+    ));
+    assert_eq!(
+        Number::Integer(2).expt(&Number::Integer(100))?.to_string(),
+        "1267650600228229401496703205376"
+    );
+    assert_eq!(
+        ((big("99999999999") * big("99999999999"))? * big("99999999999"))?.to_string(),
+        "999999999970000000000299999999999"
+    );
+    assert_eq!(
+        big("100000000000000000000")
+            .quotient(&big("-7"))?
+            .to_string(),
+        "-14285714285714285714"
+    );
+    assert_eq!(
+        big("100000000000000000000").modulo(&big("-7"))?,
+        Number::Integer(-5)
+    );
+    assert_eq!(
+        Number::fraction_of(big("1").to_big().unwrap(), sum.to_big().unwrap())?.to_string(),
+        "1/9223372036854775808"
+    );
+    assert!(sum > Number::Real(9.2e18) && sum < Number::Real(9.3e18));
+    assert!(big("9007199254740993") > Number::Real(9007199254740992.0));
+    assert_eq!(
+        Number::Integer(2).expt(&Number::Integer(1 << 30)),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "integer overflow".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn rounding() -> Result<()> {
+    let int = Number::Integer;
+    for (number, floor, ceiling, truncate, round) in [
+        (Number::rational(7, 2)?, 3, 4, 3, 4),
+        (Number::rational(5, 2)?, 2, 3, 2, 2),
+        (Number::rational(-5, 2)?, -3, -2, -2, -2),
+        (Number::rational(-7, 3)?, -3, -2, -2, -2),
+        (int(7), 7, 7, 7, 7),
+    ]
+    .iter()
+    {
+        assert_eq!(number.floor(), int(*floor));
+        assert_eq!(number.ceiling(), int(*ceiling));
+        assert_eq!(number.truncate(), int(*truncate));
+        assert_eq!(number.round(), int(*round));
+    }
+    assert_eq!(Number::Real(2.5).round(), Number::Real(2.0));
+    assert_eq!(Number::Real(-3.5).round(), Number::Real(-4.0));
+    assert_eq!(Number::Real(-4.3).floor(), Number::Real(-5.0));
+    assert_eq!(int(-7).floor_quotient(&int(2))?, int(-4));
+    assert_eq!(int(7).floor_quotient(&int(2))?, int(3));
+    Ok(())
+}
+
+#[test]
+fn power_and_root() -> Result<()> {
+    let int = Number::Integer;
+    assert!(matches!(int(16).sqrt(), Number::Integer(4)));
+    assert_eq!(Number::rational(4, 9)?.sqrt(), Number::Rational(2, 3));
+    assert!(matches!(int(2).sqrt(), Number::Real(_)));
+    // roots of numbers beyond the range of floats
+    let big = int(10).expt(&int(401))?;
+    assert!(
+        matches!(big.sqrt(), Number::Real(n) if (n / 3.1622776601683794e200 - 1.0).abs() < 1e-15)
+    );
+    let tiny = (int(1) / big)?;
+    assert!(
+        matches!(tiny.sqrt(), Number::Real(n) if (n * 3.1622776601683794e200 - 1.0).abs() < 1e-15)
+    );
+    assert_eq!(int(17).exact_integer_sqrt()?, (int(4), int(1)));
+    assert!(matches!(int(2).expt(&int(10))?, Number::Integer(1024)));
+    assert_eq!(int(2).expt(&int(-2))?, Number::Rational(1, 4));
+    assert_eq!(
+        Number::rational(-2, 3)?.expt(&int(3))?,
+        Number::Rational(-8, 27)
+    );
+    assert!(matches!(int(0).expt(&int(0))?, Number::Integer(1)));
+    assert!(matches!(int(4).expt(&Number::Real(0.5))?, Number::Real(n) if n == 2.0));
+    assert_eq!(
+        int(0).expt(&int(-1)),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "division by exact zero".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn parse_number() {
+    for (text, radix, result) in [
+        ("123", 10, Some(Number::Integer(123))),
+        ("-17", 10, Some(Number::Integer(-17))),
+        ("#xff", 10, Some(Number::Integer(255))),
+        ("ff", 16, Some(Number::Integer(255))),
+        ("#b-101", 10, Some(Number::Integer(-5))),
+        ("#o17/2", 10, Some(Number::Rational(15, 2))),
+        ("6/4", 10, Some(Number::Rational(3, 2))),
+        ("1.5e2", 10, Some(Number::Real(150.0))),
+        (".5", 10, Some(Number::Real(0.5))),
+        ("#e1.2", 10, Some(Number::Rational(6, 5))),
+        ("#e1e3", 10, Some(Number::Integer(1000))),
+        ("#i3/4", 10, Some(Number::Real(0.75))),
+        ("#x#e10", 10, Some(Number::Integer(16))),
+        ("-inf.0", 10, Some(Number::Real(f64::NEG_INFINITY))),
+        ("1/0", 10, None),
+        ("abc", 10, None),
+        ("1.2.3", 10, None),
+        ("1e", 10, None),
+        ("", 10, None),
+        ("#x#x1", 10, None),
+        ("12", 2, None),
+    ]
+    .iter()
+    {
+        let parsed = Number::parse(text, *radix);
+        assert_eq!(parsed, *result, "{}", text);
+        assert_eq!(
+            parsed.map(|n| n.is_exact()),
+            result.as_ref().map(|n| n.is_exact()),
+            "{}",
+            text
+        );
+    }
+    assert!(Number::parse("+nan.0", 10).unwrap().is_nan());
+}
+
+#[test]
+fn number_to_string() -> Result<()> {
+    assert_eq!(Number::Integer(-255).to_string_radix(16)?, "-ff");
+    assert_eq!(Number::Integer(5).to_string_radix(2)?, "101");
+    assert_eq!(Number::Rational(-15, 2).to_string_radix(8)?, "-17/2");
+    assert_eq!(Number::Real(1.5).to_string_radix(10)?, "1.5");
+    assert_eq!(Number::Real(f64::NAN).to_string_radix(10)?, "+nan.0");
+    assert!(Number::Real(1.5).to_string_radix(2).is_err());
+    Ok(())
+}
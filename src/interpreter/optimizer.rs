@@ -0,0 +1,538 @@
+use crate::interpreter::*;
+
+// the builtins computing a number or a boolean from numbers alone, which calls on constants
+// can be folded into
+const FOLDED: &[&str] = &[
+    "+",
+    "-",
+    "*",
+    "/",
+    "quotient",
+    "remainder",
+    "modulo",
+    "floor-quotient",
+    "floor-remainder",
+    "truncate-quotient",
+    "truncate-remainder",
+    "floor",
+    "ceiling",
+    "round",
+    "truncate",
+    "gcd",
+    "lcm",
+    "abs",
+    "=",
+    "<",
+    "<=",
+    ">",
+    ">=",
+    "min",
+    "max",
+    "sqrt",
+    "expt",
+];
+
+// the most expressions the body of a procedure inlined is made of
+const MAX_INLINED_SIZE: usize = 12;
+
+// a procedure defined in a body, whose calls are compiled as its body with the arguments in
+// place of the formals
+struct Inlined {
+    formals: Vec<String>,
+    body: Expression,
+    // the variables the body refers to besides the formals, which must be bound at the call as
+    // where the procedure is defined
+    free: Vec<String>,
+    // whether the body is a call referring to each formal once, in order, so that arguments
+    // which are computed are still computed once and in turn
+    ordered: bool,
+    // the scope the procedure is defined in
+    depth: usize,
+}
+
+#[derive(Clone, Default)]
+struct Binding {
+    constant: Option<ValueType>,
+    inlined: Option<Rc<Inlined>>,
+}
+
+// what the compiler knows of the code it compiles, to compute the expressions it can without
+// running them
+// level 0 compiles code as written, level 1 folds the calls of arithmetic builtins on
+// constants and the conditionals of a constant test, and level 2 also propagates the
+// constants procedures are called with and define in their bodies, and inlines the small
+// procedures bodies define
+// folded builtins are those bound when the code is compiled, not after
+#[derive(Clone)]
+pub struct Optimizer<'a> {
+    level: u8,
+    interpreter: &'a Interpreter,
+    env: &'a Environment,
+    // the variables bound around the code, innermost last, with what is known of their values
+    scopes: Vec<HashMap<String, Binding>>,
+}
+
+pub const MAX_OPTIMIZATION_LEVEL: u8 = 2;
+
+impl<'a> Optimizer<'a> {
+    pub fn new(level: u8, interpreter: &'a Interpreter, env: &'a Environment) -> Self {
+        Self {
+            level,
+            interpreter,
+            env,
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    // the optimizer of the code as written of what is folded, which folds nothing
+    pub fn unfolded(&self) -> Self {
+        Self {
+            level: 0,
+            ..self.clone()
+        }
+    }
+
+    pub fn redefinitions(&self) -> u32 {
+        self.env.redefinitions()
+    }
+
+    // the variables of a scope, taking over those of the same names around it
+    pub fn enter(&self, variables: impl IntoIterator<Item = String>) -> Self {
+        let mut optimizer = self.clone();
+        optimizer.scopes.push(
+            variables
+                .into_iter()
+                .map(|name| (name, Binding::default()))
+                .collect(),
+        );
+        optimizer
+    }
+
+    // the variable is known to stay bound to the value from now on, if it is a constant
+    pub fn bind(&mut self, name: &str, value: Option<ValueType>) {
+        let constant = value.filter(|_| self.level >= 2);
+        let binding = Binding {
+            constant,
+            inlined: None,
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), binding);
+    }
+
+    // the variable bound to the procedure is known to stay bound to it from now on, as a
+    // definition of a body which is the only one of its name is, and its calls are inlined if
+    // its body is a small expression
+    // a procedure is not inlined in itself nor in the other procedures of the body, and so
+    // neither in those it is inlined in, so inlining never recurses
+    pub fn bind_procedure(
+        &mut self,
+        name: &str,
+        procedure: &SchemeProcedure,
+        definitions: &[Definition],
+    ) {
+        let SchemeProcedure(formals, body_definitions, expressions, _) = procedure;
+        if self.level < 2 || !body_definitions.is_empty() || expressions.len() != 1 {
+            return;
+        }
+        let body = &expressions[0];
+        let mut free = vec![];
+        match inlined_size(body, formals, &mut free) {
+            Some(size) if size <= MAX_INLINED_SIZE => (),
+            _ => return,
+        }
+        if free.iter().any(|variable| {
+            variable == name
+                || definitions
+                    .iter()
+                    .any(|Definition(defined, _)| defined == variable)
+        }) {
+            return;
+        }
+        let ordered = match body {
+            Expression::ProcedureCall(operator, arguments) => ordered(operator, arguments, formals),
+            Expression::LocatedCall(call) => ordered(&call.0, &call.1, formals),
+            _ => false,
+        };
+        let inlined = Inlined {
+            formals: formals.clone(),
+            body: body.clone(),
+            free,
+            ordered,
+            depth: self.scopes.len() - 1,
+        };
+        if let Some(binding) = self.scopes.last_mut().unwrap().get_mut(name) {
+            binding.inlined = Some(Rc::new(inlined));
+        }
+    }
+
+    // the scope of a procedure's body, with the constants it is called with if known
+    // a formal or definition defined again in the body is not a constant
+    pub fn procedure(&self, procedure: &SchemeProcedure, arguments: &[Option<ValueType>]) -> Self {
+        let SchemeProcedure(formals, definitions, ..) = procedure;
+        let mut optimizer = self.enter(
+            definitions
+                .iter()
+                .map(|Definition(name, _)| name.clone())
+                .chain(formals.iter().cloned()),
+        );
+        for (formal, argument) in formals.iter().zip(arguments) {
+            if !definitions.iter().any(|Definition(name, _)| name == formal) {
+                optimizer.bind(formal, argument.clone());
+            }
+        }
+        optimizer
+    }
+
+    fn bound(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // the procedure bound to the variable, if its calls here are inlined
+    fn inlined(&self, name: &str) -> Option<&Inlined> {
+        let inlined = self.bound(name)?.inlined.as_deref()?;
+        let shadowed = self.scopes[inlined.depth + 1..].iter().any(|scope| {
+            inlined
+                .free
+                .iter()
+                .any(|variable| scope.contains_key(variable))
+        });
+        match shadowed {
+            true => None,
+            false => Some(inlined),
+        }
+    }
+
+    // the body replacing a call, with the arguments in place of the formals
+    // arguments are substituted as they are when they cannot fail and so are the same however
+    // many times the body refers to them, or else when the body computes each one once and in
+    // turn
+    pub fn inline(
+        &self,
+        operator: &Expression,
+        arguments: &[Expression],
+        simple: impl Fn(&Expression) -> bool,
+    ) -> Option<Expression> {
+        let inlined = match operator {
+            Expression::Identifier(name) => self.inlined(name)?,
+            _ => return None,
+        };
+        if inlined.formals.len() != arguments.len()
+            || !(inlined.ordered || arguments.iter().all(simple))
+        {
+            return None;
+        }
+        Some(substitute(&inlined.body, &inlined.formals, arguments))
+    }
+
+    // the variables the bodies of the procedures inlined refer to, which the code calling them
+    // refers to as well
+    pub fn inlined_variables(&self, names: &[String]) -> Vec<String> {
+        let mut variables = vec![];
+        for name in names {
+            if let Some(inlined) = self.inlined(name) {
+                for variable in &inlined.free {
+                    if !names.contains(variable) && !variables.contains(variable) {
+                        variables.push(variable.clone());
+                    }
+                }
+            }
+        }
+        variables
+    }
+
+    // the value of the expression when it is known without running it
+    pub fn constant(&self, expression: &Expression) -> Option<ValueType> {
+        if self.level == 0 {
+            return None;
+        }
+        match expression {
+            Expression::Integer(n) => Some(ValueType::Number(Number::Integer(*n))),
+            Expression::Real(n) => Some(ValueType::Number(Number::parse(n, 10)?)),
+            Expression::Rational(a, b) => {
+                Some(ValueType::Number(Number::rational(*a, *b as i64).ok()?))
+            }
+            Expression::Boolean(value) => Some(ValueType::Boolean(*value)),
+            Expression::Identifier(name) => self.bound(name)?.constant.clone(),
+            Expression::ProcedureCall(operator, arguments) => self.fold(operator, arguments),
+            Expression::LocatedCall(call) => self.fold(&call.0, &call.1),
+            Expression::Conditional(conditional) => {
+                let (test, consequent, alternative) = conditional.as_ref();
+                match self.constant(test)? {
+                    ValueType::Boolean(true) => self.constant(consequent),
+                    ValueType::Boolean(false) => self.constant(alternative.as_ref()?),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // the builtin is called when compiling, the call is left for running when it fails
+    fn fold(&self, operator: &Expression, arguments: &[Expression]) -> Option<ValueType> {
+        let name = match operator {
+            Expression::Identifier(name) if FOLDED.contains(&&**name) => name,
+            _ => return None,
+        };
+        if self.bound(name).is_some() {
+            return None;
+        }
+        let function = match self.env.get(name) {
+            Some(ValueType::Procedure(Procedure::Buildin(builtin))) if builtin.0 == &**name => {
+                builtin.1
+            }
+            _ => return None,
+        };
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.constant(argument))
+            .collect::<Option<Vec<_>>>()?;
+        function(Box::new(arguments.into_iter().map(Ok)), self.interpreter)
+            .ok()
+            .filter(|value| matches!(value, ValueType::Number(_) | ValueType::Boolean(_)))
+    }
+}
+
+// the number of expressions the body of a procedure is made of if it can be inlined, that is if
+// it binds no variables, with the variables it refers to besides the formals
+fn inlined_size(
+    expression: &Expression,
+    formals: &[String],
+    free: &mut Vec<String>,
+) -> Option<usize> {
+    let mut size = |expressions: &[Expression]| -> Option<usize> {
+        expressions
+            .iter()
+            .map(|e| inlined_size(e, formals, free))
+            .sum::<Option<usize>>()
+    };
+    Some(match expression {
+        Expression::Identifier(name) => {
+            if !formals
+                .iter()
+                .chain(free.iter())
+                .any(|variable| **variable == **name)
+            {
+                free.push(name.to_string());
+            }
+            1
+        }
+        Expression::ProcedureCall(operator, arguments) => {
+            1 + size(std::slice::from_ref(operator))? + size(arguments)?
+        }
+        Expression::LocatedCall(call) => 1 + size(std::slice::from_ref(&call.0))? + size(&call.1)?,
+        Expression::Vector(elements) => 1 + size(elements)?,
+        Expression::Conditional(conditional) => {
+            let (test, consequent, alternative) = conditional.as_ref();
+            1 + size(std::slice::from_ref(test))?
+                + size(std::slice::from_ref(consequent))?
+                + size(alternative.as_slice())?
+        }
+        Expression::Integer(_)
+        | Expression::Boolean(_)
+        | Expression::Real(_)
+        | Expression::Rational(..)
+        | Expression::Character(_)
+        | Expression::String(_)
+        | Expression::Datum(_)
+        | Expression::Primitive(_) => 1,
+        Expression::Procedure(_)
+        | Expression::Guard(_)
+        | Expression::Delay(_)
+        | Expression::DelayForce(_)
+        | Expression::StreamCons(_)
+        | Expression::Match(_) => return None,
+    })
+}
+
+// whether the call refers to the formals only as its arguments, each once and in order, and
+// computes nothing else than them
+fn ordered(operator: &Expression, arguments: &[Expression], formals: &[String]) -> bool {
+    let formal = |expression: &Expression| match expression {
+        Expression::Identifier(name) => formals.iter().position(|formal| **formal == **name),
+        _ => None,
+    };
+    let referred = arguments.iter().filter_map(formal).collect::<Vec<_>>();
+    matches!(operator, Expression::Identifier(_))
+        && formal(operator).is_none()
+        && referred.iter().copied().eq(0..formals.len())
+        && arguments.iter().all(|argument| {
+            !matches!(
+                argument,
+                Expression::ProcedureCall(..)
+                    | Expression::LocatedCall(_)
+                    | Expression::Conditional(_)
+                    | Expression::Vector(_)
+            )
+        })
+}
+
+fn substitute(expression: &Expression, formals: &[String], arguments: &[Expression]) -> Expression {
+    let each = |expressions: &[Expression]| -> Vec<Expression> {
+        expressions
+            .iter()
+            .map(|e| substitute(e, formals, arguments))
+            .collect()
+    };
+    match expression {
+        Expression::Identifier(name) => {
+            match formals.iter().position(|formal| **formal == **name) {
+                Some(i) => arguments[i].clone(),
+                None => expression.clone(),
+            }
+        }
+        Expression::ProcedureCall(operator, operands) => Expression::ProcedureCall(
+            Box::new(substitute(operator, formals, arguments)),
+            each(operands),
+        ),
+        Expression::LocatedCall(call) => Expression::LocatedCall(Box::new((
+            substitute(&call.0, formals, arguments),
+            each(&call.1),
+            call.2,
+        ))),
+        Expression::Vector(elements) => Expression::Vector(each(elements)),
+        Expression::Conditional(conditional) => {
+            let (test, consequent, alternative) = conditional.as_ref();
+            Expression::Conditional(Box::new((
+                substitute(test, formals, arguments),
+                substitute(consequent, formals, arguments),
+                alternative
+                    .as_ref()
+                    .map(|alternative| substitute(alternative, formals, arguments)),
+            )))
+        }
+        _ => expression.clone(),
+    }
+}
+
+#[test]
+fn optimizing() -> Result<()> {
+    let it = Interpreter::new();
+    let compiled = |source: &str, level: u8| -> Result<String> {
+        let mut forms = vec![];
+        read_source(source, None, |form, _| {
+            forms.push(form);
+            Ok(())
+        })
+        .map_err(|(e, _)| e)?;
+        let optimizer = Optimizer::new(level, &it, &it.env);
+        Ok(compiler::compile_statement(&forms[0], optimizer)?.to_string())
+    };
+    // what is folded is followed by the code as written, run once a builtin is defined again
+    assert!(compiled("(* (+ 1 2) (/ 1 4) 0.5)", 1)?
+        .starts_with("   0 Unchanged(0, 3)\n   1 Constant(0) ; 0.375\n   2 Jump(9)\n"));
+    assert!(
+        compiled("(if (< 1 2) (display 1) (display 2))", 1)?.starts_with(
+            "   0 Unchanged(0, 3)\n   1 ConstantCallGlobal(0, 0) ; 1 display\n   2 Jump("
+        )
+    );
+    // errors are left for running to report
+    assert_eq!(
+        compiled("(/ 1 0)", 1)?,
+        "   0 Constant(0) ; 1\n   1 ConstantCallGlobal(1, 0) ; 0 /\n   2 Return\n"
+    );
+    assert_eq!(
+        compiled("(+ 1 2)", 0)?,
+        "   0 Constant(0) ; 1\n   1 ConstantCallGlobal(1, 0) ; 2 +\n   2 Return\n"
+    );
+    let let_like = "((lambda (x) (define y (* x 2)) (lambda (+) (+ x y))) 3)";
+    assert!(compiled(let_like, 2)?.contains("   2 Constant(0) ; 3\n"));
+    assert!(compiled(let_like, 2)?.contains("   6 Constant(1) ; 6\n"));
+    assert!(compiled(let_like, 1)?.contains("Local(1, 1, 0) ; x\n"));
+    // shadowed and redefined builtins are called
+    assert!(compiled("(lambda (+) (+ 1 2))", 2)?.contains(" ConstantCall(1, 0) ; 2 +"));
+    it.eval("(define (- a b) (+ a b))".chars())?;
+    assert!(compiled("(- 1 2)", 2)?.contains("ConstantCallGlobal(1, 0) ; 2 -"));
+    for engine in [vm::Engine::TreeWalker, vm::Engine::Bytecode] {
+        for level in 0..=MAX_OPTIMIZATION_LEVEL {
+            let it = Interpreter::new();
+            it.engine.set(engine);
+            it.optimization_level.set(level);
+            it.eval("(define (f) (* 3 4)) (define (* a b) 0)".chars())?;
+            assert_eq!(
+                it.eval("(f)".chars())?,
+                Some(ValueType::Number(Number::Integer(0)))
+            );
+        }
+    }
+    for level in 0..=MAX_OPTIMIZATION_LEVEL {
+        it.optimization_level.set(level);
+        assert_eq!(
+            it.eval("((lambda (n) (define m (if (> n 1) (* n 10) 0)) (+ m (- n 1))) 4)".chars())?,
+            Some(ValueType::Number(Number::Integer(45)))
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn inlining() -> Result<()> {
+    let it = Interpreter::new();
+    let compiled = |source: &str, level: u8| -> Result<String> {
+        let mut forms = vec![];
+        read_source(source, None, |form, _| {
+            forms.push(form);
+            Ok(())
+        })
+        .map_err(|(e, _)| e)?;
+        let optimizer = Optimizer::new(level, &it, &it.env);
+        Ok(compiler::compile_statement(&forms[0], optimizer)?.to_string())
+    };
+    let calls = |code: String, name: &str| {
+        code.lines()
+            .any(|line| line.contains("Call") && line.ends_with(&format!(" {}", name)))
+    };
+    let small = "(define (f k v)
+                   (define (sq x) (* x x))
+                   (define (add a b) (+ a b))
+                   (add (sq k) (add (vector-ref v 0) (sq 3))))";
+    assert!(!calls(compiled(small, 2)?, "sq"));
+    assert!(!calls(compiled(small, 2)?, "add"));
+    assert!(compiled(small, 2)?.contains("; 9\n"));
+    assert!(calls(compiled(small, 1)?, "sq"));
+    // arguments computed more than once or out of order are not substituted
+    let twice = "(define (f v) (define (sq x) (* x x)) (sq (vector-ref v 0)))";
+    assert!(calls(compiled(twice, 2)?, "sq"));
+    let reversed = "(define (f v) (define (sub a b) (- b a)) (sub (car v) 1))";
+    assert!(calls(compiled(reversed, 2)?, "sub"));
+    // nor are recursive procedures and those whose variables are bound again at the call
+    let recursive = "(define (f n) (define (g x) (if (= x 0) 0 (g (- x 1)))) (g n))";
+    assert!(calls(compiled(recursive, 2)?, "g"));
+    let shadowed = "(define (f k) (define (scale x) (* k x)) (lambda (k) (scale 2)))";
+    assert!(calls(compiled(shadowed, 2)?, "scale"));
+    for engine in [vm::Engine::TreeWalker, vm::Engine::Bytecode] {
+        it.engine.set(engine);
+        for level in 0..=MAX_OPTIMIZATION_LEVEL {
+            it.optimization_level.set(level);
+            it.eval(small.chars())?;
+            assert_eq!(
+                it.eval("(f 2 (vector 5))".chars())?,
+                Some(ValueType::Number(Number::Integer(18)))
+            );
+            // the variables of an inlined body are copied by the closures calling it
+            it.eval("(define (g k) (define (scale x) (* k x)) (lambda (y) (scale y)))".chars())?;
+            assert_eq!(
+                it.eval("((g 3) 4)".chars())?,
+                Some(ValueType::Number(Number::Integer(12)))
+            );
+            // the argument is computed once
+            it.eval(
+                "(define (h n)
+                   (define (twice x) (+ x x))
+                   (define (next) (vector-set! n 0 (+ (vector-ref n 0) 1)) (vector-ref n 0))
+                   (twice (next)))"
+                    .chars(),
+            )?;
+            assert_eq!(
+                it.eval("(h (vector 0))".chars())?,
+                Some(ValueType::Number(Number::Integer(2)))
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,440 @@
+use crate::error::*;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::net::{Shutdown, TcpStream};
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub enum PortKind {
+    Stdin,
+    Stdout,
+    Stderr,
+    InputFile(BufReader<File>),
+    OutputFile(BufWriter<File>),
+    // the two directions of a TCP connection, each with its own handle
+    InputSocket(BufReader<TcpStream>),
+    OutputSocket(BufWriter<TcpStream>),
+    InputString(Vec<char>, usize),
+    OutputString(String),
+    InputBytevector(Vec<u8>, usize),
+    OutputBytevector(Vec<u8>),
+}
+
+// ports are compared by identity, and a closed port has no underlying resource
+pub struct Port {
+    name: String,
+    input: bool,
+    textual: bool,
+    // a character peeked from a textual input port but not consumed yet
+    lookahead: RefCell<Option<char>>,
+    kind: RefCell<Option<PortKind>>,
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = if self.input { "input" } else { "output" };
+        write!(f, "<{} port ({})>", direction, self.name)
+    }
+}
+
+impl fmt::Debug for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl PartialEq for Port {
+    fn eq(&self, rhs: &Self) -> bool {
+        std::ptr::eq(self, rhs)
+    }
+}
+
+// decodes one UTF-8 character, None at the end of input
+fn decode_char(reader: &mut impl BufRead) -> io::Result<Option<char>> {
+    let mut bytes = [0u8; 4];
+    match reader.fill_buf()?.first() {
+        Some(byte) => bytes[0] = *byte,
+        None => return Ok(None),
+    }
+    reader.consume(1);
+    let width = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 0,
+    };
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8 sequence");
+    if width == 0 {
+        return Err(invalid());
+    }
+    reader.read_exact(&mut bytes[1..width])?;
+    match std::str::from_utf8(&bytes[..width]) {
+        Ok(text) => Ok(text.chars().next()),
+        Err(_) => Err(invalid()),
+    }
+}
+
+pub fn file_error(action: &str, path: &str, error: std::io::Error) -> Error {
+    Error {
+        category: ErrorType::File,
+        message: format!("cannot {} {}: {}", action, path, error),
+    }
+}
+
+impl Port {
+    fn new(name: &str, input: bool, textual: bool, kind: PortKind) -> Self {
+        Self {
+            name: name.to_string(),
+            input,
+            textual,
+            lookahead: RefCell::new(None),
+            kind: RefCell::new(Some(kind)),
+        }
+    }
+
+    pub fn stdin() -> Self {
+        Self::new("stdin", true, true, PortKind::Stdin)
+    }
+
+    pub fn stdout() -> Self {
+        Self::new("stdout", false, true, PortKind::Stdout)
+    }
+
+    pub fn stderr() -> Self {
+        Self::new("stderr", false, true, PortKind::Stderr)
+    }
+
+    pub fn open_input_file(path: &str) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => Ok(Self::new(
+                path,
+                true,
+                true,
+                PortKind::InputFile(BufReader::new(file)),
+            )),
+            Err(e) => Err(file_error("open input file", path, e)),
+        }
+    }
+
+    pub fn open_output_file(path: &str) -> Result<Self> {
+        match File::create(path) {
+            Ok(file) => Ok(Self::new(
+                path,
+                false,
+                true,
+                PortKind::OutputFile(BufWriter::new(file)),
+            )),
+            Err(e) => Err(file_error("open output file", path, e)),
+        }
+    }
+
+    pub fn open_binary_input_file(path: &str) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => Ok(Self::new(
+                path,
+                true,
+                false,
+                PortKind::InputFile(BufReader::new(file)),
+            )),
+            Err(e) => Err(file_error("open binary input file", path, e)),
+        }
+    }
+
+    pub fn open_binary_output_file(path: &str) -> Result<Self> {
+        match File::create(path) {
+            Ok(file) => Ok(Self::new(
+                path,
+                false,
+                false,
+                PortKind::OutputFile(BufWriter::new(file)),
+            )),
+            Err(e) => Err(file_error("open binary output file", path, e)),
+        }
+    }
+
+    pub fn socket_input(name: &str, stream: TcpStream, textual: bool) -> Self {
+        Self::new(
+            name,
+            true,
+            textual,
+            PortKind::InputSocket(BufReader::new(stream)),
+        )
+    }
+
+    pub fn socket_output(name: &str, stream: TcpStream, textual: bool) -> Self {
+        Self::new(
+            name,
+            false,
+            textual,
+            PortKind::OutputSocket(BufWriter::new(stream)),
+        )
+    }
+
+    pub fn open_input_bytevector(bytes: Vec<u8>) -> Self {
+        Self::new(
+            "bytevector",
+            true,
+            false,
+            PortKind::InputBytevector(bytes, 0),
+        )
+    }
+
+    pub fn open_output_bytevector() -> Self {
+        Self::new(
+            "bytevector",
+            false,
+            false,
+            PortKind::OutputBytevector(vec![]),
+        )
+    }
+
+    pub fn open_input_string(text: &str) -> Self {
+        Self::new(
+            "string",
+            true,
+            true,
+            PortKind::InputString(text.chars().collect(), 0),
+        )
+    }
+
+    pub fn open_output_string() -> Self {
+        Self::new("string", false, true, PortKind::OutputString(String::new()))
+    }
+
+    // the characters written to a string output port so far
+    pub fn output_string(&self) -> Result<String> {
+        match &*self.kind.borrow() {
+            Some(PortKind::OutputString(s)) => Ok(s.clone()),
+            _ => logic_error!("{} is not an open string output port", self),
+        }
+    }
+
+    // the bytes written to a bytevector output port so far
+    pub fn output_bytevector(&self) -> Result<Vec<u8>> {
+        match &*self.kind.borrow() {
+            Some(PortKind::OutputBytevector(bytes)) => Ok(bytes.clone()),
+            _ => logic_error!("{} is not an open bytevector output port", self),
+        }
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.input
+    }
+
+    pub fn is_output(&self) -> bool {
+        !self.input
+    }
+
+    pub fn is_textual(&self) -> bool {
+        self.textual
+    }
+
+    pub fn is_binary(&self) -> bool {
+        !self.textual
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.kind.borrow().is_some()
+    }
+
+    // flushes pending output, closing an already closed port does nothing,
+    // and closing a socket output port ends the input of the peer
+    pub fn close(&self) -> Result<()> {
+        self.flush()?;
+        self.lookahead.borrow_mut().take();
+        if let Some(PortKind::OutputSocket(socket)) = self.kind.borrow_mut().take() {
+            // the peer may have closed the connection already
+            let _ = socket.get_ref().shutdown(Shutdown::Write);
+        }
+        Ok(())
+    }
+
+    pub fn write_str(&self, text: &str) -> Result<()> {
+        if !self.textual || self.input {
+            logic_error!("{} is not a textual output port", self)
+        }
+        let result = match &mut *self.kind.borrow_mut() {
+            Some(PortKind::Stdout) => std::io::stdout().write_all(text.as_bytes()),
+            Some(PortKind::Stderr) => std::io::stderr().write_all(text.as_bytes()),
+            Some(PortKind::OutputFile(file)) => file.write_all(text.as_bytes()),
+            Some(PortKind::OutputSocket(socket)) => socket.write_all(text.as_bytes()),
+            Some(PortKind::OutputString(s)) => {
+                s.push_str(text);
+                Ok(())
+            }
+            Some(_) => logic_error!("{} is not a textual output port", self),
+            None => logic_error!("cannot write to closed {}", self),
+        };
+        result.map_err(|e| file_error("write to", &self.name, e))
+    }
+
+    pub fn write_bytes(&self, bytes: &[u8]) -> Result<()> {
+        if self.textual || self.input {
+            logic_error!("{} is not a binary output port", self)
+        }
+        let result = match &mut *self.kind.borrow_mut() {
+            Some(PortKind::OutputFile(file)) => file.write_all(bytes),
+            Some(PortKind::OutputSocket(socket)) => socket.write_all(bytes),
+            Some(PortKind::OutputBytevector(output)) => {
+                output.extend_from_slice(bytes);
+                Ok(())
+            }
+            Some(_) => logic_error!("{} is not a binary output port", self),
+            None => logic_error!("cannot write to closed {}", self),
+        };
+        result.map_err(|e| file_error("write to", &self.name, e))
+    }
+
+    // the next byte without consuming it, None at the end of input
+    pub fn peek_u8(&self) -> Result<Option<u8>> {
+        self.read_u8_with(false)
+    }
+
+    pub fn read_u8(&self) -> Result<Option<u8>> {
+        self.read_u8_with(true)
+    }
+
+    fn read_u8_with(&self, consume: bool) -> Result<Option<u8>> {
+        if self.textual || !self.input {
+            logic_error!("{} is not a binary input port", self)
+        }
+        match &mut *self.kind.borrow_mut() {
+            Some(PortKind::InputFile(file)) => self.buffered_u8(file, consume),
+            Some(PortKind::InputSocket(socket)) => self.buffered_u8(socket, consume),
+            Some(PortKind::InputBytevector(bytes, position)) => {
+                let byte = bytes.get(*position).copied();
+                if consume && byte.is_some() {
+                    *position += 1;
+                }
+                Ok(byte)
+            }
+            Some(_) => logic_error!("{} is not a binary input port", self),
+            None => logic_error!("cannot read from closed {}", self),
+        }
+    }
+
+    fn buffered_u8(&self, reader: &mut impl BufRead, consume: bool) -> Result<Option<u8>> {
+        let byte = match reader.fill_buf() {
+            Ok(buffer) => buffer.first().copied(),
+            Err(e) => return Err(file_error("read from", &self.name, e)),
+        };
+        if consume && byte.is_some() {
+            reader.consume(1);
+        }
+        Ok(byte)
+    }
+
+    // the next character without consuming it, None at the end of input
+    pub fn peek_char(&self) -> Result<Option<char>> {
+        let mut lookahead = self.lookahead.borrow_mut();
+        if lookahead.is_none() {
+            *lookahead = self.next_char()?;
+        }
+        Ok(*lookahead)
+    }
+
+    pub fn read_char(&self) -> Result<Option<char>> {
+        match self.lookahead.borrow_mut().take() {
+            Some(c) => Ok(Some(c)),
+            None => self.next_char(),
+        }
+    }
+
+    // gives back a character read too far, so it is the next one read
+    pub(crate) fn unread_char(&self, c: char) {
+        *self.lookahead.borrow_mut() = Some(c);
+    }
+
+    // the characters up to the end of the line, which is \n or \r\n, None at the end of input
+    pub fn read_line(&self) -> Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            match self.read_char()? {
+                Some('\n') => break,
+                Some(c) => line.push(c),
+                None if line.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    // at most k characters, fewer only at the end of input
+    pub fn read_string(&self, k: usize) -> Result<String> {
+        let mut text = String::new();
+        for _ in 0..k {
+            match self.read_char()? {
+                Some(c) => text.push(c),
+                None => break,
+            }
+        }
+        Ok(text)
+    }
+
+    // whether reading a character will not block, only an interactive stdin
+    // and a socket with nothing buffered may block
+    pub fn char_ready(&self) -> Result<bool> {
+        if !self.textual || !self.input {
+            logic_error!("{} is not a textual input port", self)
+        }
+        if self.lookahead.borrow().is_some() {
+            return Ok(true);
+        }
+        match &*self.kind.borrow() {
+            Some(PortKind::Stdin) => Ok(!io::stdin().is_terminal()),
+            Some(PortKind::InputSocket(socket)) => Ok(!socket.buffer().is_empty()),
+            Some(_) => Ok(true),
+            None => logic_error!("cannot read from closed {}", self),
+        }
+    }
+
+    fn next_char(&self) -> Result<Option<char>> {
+        if !self.textual || !self.input {
+            logic_error!("{} is not a textual input port", self)
+        }
+        let result = match &mut *self.kind.borrow_mut() {
+            Some(PortKind::Stdin) => decode_char(&mut io::stdin().lock()),
+            Some(PortKind::InputFile(file)) => decode_char(file),
+            Some(PortKind::InputSocket(socket)) => decode_char(socket),
+            Some(PortKind::InputString(chars, position)) => {
+                let c = chars.get(*position).copied();
+                if c.is_some() {
+                    *position += 1;
+                }
+                Ok(c)
+            }
+            Some(_) => logic_error!("{} is not a textual input port", self),
+            None => logic_error!("cannot read from closed {}", self),
+        };
+        result.map_err(|e| file_error("read from", &self.name, e))
+    }
+
+    // at most k bytes, fewer only at the end of input
+    pub fn read_bytes(&self, k: usize) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(k);
+        while bytes.len() < k {
+            match self.read_u8()? {
+                Some(byte) => bytes.push(byte),
+                None => break,
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        let result = match &mut *self.kind.borrow_mut() {
+            Some(PortKind::Stdout) => std::io::stdout().flush(),
+            Some(PortKind::Stderr) => std::io::stderr().flush(),
+            Some(PortKind::OutputFile(file)) => file.flush(),
+            Some(PortKind::OutputSocket(socket)) => socket.flush(),
+            _ => Ok(()),
+        };
+        result.map_err(|e| file_error("flush", &self.name, e))
+    }
+}
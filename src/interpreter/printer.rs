@@ -0,0 +1,360 @@
+use crate::interpreter::srfi::srfi_113::CollectionKind;
+use crate::interpreter::*;
+use std::collections::{HashMap, HashSet};
+
+// which pairs and vectors are written with datum labels like #0=(a . #0#)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Labeling {
+    // no labels, which never terminates on circular structures
+    Simple,
+    // labels for the objects in cycles only
+    Cycles,
+    // labels for every object appearing more than once
+    Shared,
+}
+
+// how much of a value is written, the rest of lists and vectors beyond them is written as ...
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Limits {
+    // the nesting of lists and vectors within each other
+    pub depth: Option<usize>,
+    // the elements of each list or vector
+    pub length: Option<usize>,
+}
+
+// the external representation of a value, or the human readable form used by display
+// which writes strings, characters and symbols as they are
+pub struct External<'a> {
+    value: &'a ValueType,
+    written: bool,
+    labeling: Labeling,
+    limits: Limits,
+}
+
+impl<'a> External<'a> {
+    pub fn new(value: &'a ValueType, written: bool, labeling: Labeling) -> Self {
+        Self {
+            value,
+            written,
+            labeling,
+            limits: Limits::default(),
+        }
+    }
+
+    // an abbreviated representation, which is no longer re-readable once elided
+    pub fn limited(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+pub struct Displayed<'a>(pub &'a ValueType);
+
+impl fmt::Display for Displayed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", External::new(self.0, false, Labeling::Cycles))
+    }
+}
+
+// the identity of the objects which may be shared
+fn object_id(value: &ValueType) -> Option<usize> {
+    match value {
+        ValueType::Pair(pair) => Some(pair.as_ptr() as usize),
+        ValueType::Vector(vector) => Some(vector.as_ptr() as usize),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct LabelSearch {
+    visited: HashSet<usize>,
+    // objects on the path from the root, reaching one of them again closes a cycle
+    active: HashSet<usize>,
+    labeled: HashSet<usize>,
+}
+
+impl LabelSearch {
+    // recursive on cars and vector elements, but iterative along the cdrs of lists
+    fn search(&mut self, value: &ValueType, labeling: Labeling) {
+        let mut current = value.clone();
+        let mut chain = vec![];
+        while let Some(id) = object_id(&current) {
+            if self.active.contains(&id)
+                || (labeling == Labeling::Shared && self.visited.contains(&id))
+            {
+                self.labeled.insert(id);
+                break;
+            }
+            if !self.visited.insert(id) {
+                break;
+            }
+            self.active.insert(id);
+            chain.push(id);
+            current = match &current {
+                ValueType::Pair(pair) => {
+                    let (car, cdr) = {
+                        let pair = pair.borrow();
+                        (pair.car.clone(), pair.cdr.clone())
+                    };
+                    self.search(&car, labeling);
+                    cdr
+                }
+                ValueType::Vector(vector) => {
+                    let elements = vector.borrow().clone();
+                    for element in &elements {
+                        self.search(element, labeling);
+                    }
+                    break;
+                }
+                _ => unreachable!(),
+            };
+        }
+        for id in chain {
+            self.active.remove(&id);
+        }
+    }
+}
+
+struct Printer {
+    written: bool,
+    // labeled objects and their label numbers, assigned when first written
+    labels: HashMap<usize, Option<usize>>,
+    next_label: usize,
+    limits: Limits,
+    depth: usize,
+}
+
+impl Printer {
+    // writes the #n= prefix of a labeled object, or its #n# reference after the first time
+    fn label(
+        &mut self,
+        f: &mut fmt::Formatter,
+        value: &ValueType,
+    ) -> std::result::Result<bool, fmt::Error> {
+        let id = match object_id(value) {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+        match self.labels.get_mut(&id) {
+            Some(Some(label)) => {
+                write!(f, "#{}#", label)?;
+                Ok(true)
+            }
+            Some(label) => {
+                *label = Some(self.next_label);
+                write!(f, "#{}=", self.next_label)?;
+                self.next_label += 1;
+                Ok(false)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn is_labeled(&self, value: &ValueType) -> bool {
+        object_id(value).is_some_and(|id| self.labels.contains_key(&id))
+    }
+
+    fn is_elided(&self, index: usize) -> bool {
+        self.limits.length.is_some_and(|length| index >= length)
+    }
+
+    fn write(&mut self, f: &mut fmt::Formatter, value: &ValueType) -> fmt::Result {
+        // the labels of elided objects are assigned when they are first written in full
+        if object_id(value).is_some() && self.limits.depth.is_some_and(|depth| self.depth >= depth)
+        {
+            return write!(f, "...");
+        }
+        if self.label(f, value)? {
+            return Ok(());
+        }
+        self.depth += 1;
+        let result = self.write_unlabeled(f, value);
+        self.depth -= 1;
+        result
+    }
+
+    fn write_unlabeled(&mut self, f: &mut fmt::Formatter, value: &ValueType) -> fmt::Result {
+        let written = self.written;
+        match value {
+            ValueType::Number(num) => write!(f, "{}", num),
+            ValueType::Character(c) if written => write!(f, "{}", escape_character(*c)),
+            ValueType::Character(c) => write!(f, "{}", c),
+            ValueType::String(s) if written => write!(f, "{}", escape_string(s)),
+            ValueType::String(s) => write!(f, "{}", s),
+            ValueType::Symbol(s) if written => write!(f, "{}", escape_symbol(s)),
+            ValueType::Symbol(s) => write!(f, "{}", s),
+            ValueType::Pair(pair) => {
+                let mut pair = pair.clone();
+                write!(f, "(")?;
+                let car = pair.borrow().car.clone();
+                match self.is_elided(0) {
+                    true => write!(f, "...")?,
+                    false => self.write(f, &car)?,
+                }
+                for index in 1.. {
+                    let cdr = pair.borrow().cdr.clone();
+                    match cdr {
+                        ValueType::EmptyList => break,
+                        ValueType::Pair(_) if self.is_elided(index) => {
+                            write!(f, " ...")?;
+                            break;
+                        }
+                        // a labeled pair in the middle of a list is written as a dotted tail
+                        ValueType::Pair(next) if !self.is_labeled(&cdr) => {
+                            write!(f, " ")?;
+                            let car = next.borrow().car.clone();
+                            self.write(f, &car)?;
+                            pair = next;
+                        }
+                        tail => {
+                            write!(f, " . ")?;
+                            self.write(f, &tail)?;
+                            break;
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+            ValueType::EmptyList => write!(f, "()"),
+            ValueType::Procedure(p) => write!(f, "{}", p),
+            ValueType::Port(port) => write!(f, "{}", port),
+            ValueType::ErrorObject(object) => {
+                write!(f, "<error object ({})>", object.description())
+            }
+            ValueType::Time(time) => write!(f, "{}", time),
+            ValueType::Date(date) => write!(f, "{}", date),
+            ValueType::RandomSource(_) => write!(f, "<random source>"),
+            ValueType::HashTable(_) => write!(f, "<hash table>"),
+            ValueType::Comparator(_) => write!(f, "<comparator>"),
+            ValueType::Promise(_) => write!(f, "<promise>"),
+            ValueType::Generator(_) => write!(f, "<generator>"),
+            ValueType::Accumulator(_) => write!(f, "<accumulator>"),
+            ValueType::Regexp(regexp) => write!(f, "{}", regexp),
+            ValueType::RegexpMatch(_) => write!(f, "<regexp match>"),
+            ValueType::HttpResponse(response) => write!(f, "<http response {}>", response.status),
+            ValueType::Socket(socket) => write!(f, "{}", socket),
+            ValueType::Memo(_) => write!(f, "<memo>"),
+            ValueType::Collection(collection) => match collection.kind {
+                CollectionKind::Set => write!(f, "<set>"),
+                CollectionKind::Bag => write!(f, "<bag>"),
+            },
+            ValueType::Eof => write!(f, "<eof>"),
+            ValueType::Values(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    self.write(f, value)?;
+                }
+                Ok(())
+            }
+            ValueType::Void => write!(f, "Void"),
+            ValueType::Boolean(true) => write!(f, "#t"),
+            ValueType::Boolean(false) => write!(f, "#f"),
+            ValueType::Bytevector(bytes) => {
+                write!(f, "#u8({})", join_displayable(bytes.borrow().iter()))
+            }
+            ValueType::Vector(vector) => {
+                let elements = vector.borrow().clone();
+                write!(f, "#(")?;
+                for (i, value) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    if self.is_elided(i) {
+                        write!(f, "...")?;
+                        break;
+                    }
+                    self.write(f, value)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for External<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut search = LabelSearch::default();
+        if self.labeling != Labeling::Simple {
+            search.search(self.value, self.labeling);
+        }
+        Printer {
+            written: self.written,
+            labels: search.labeled.into_iter().map(|id| (id, None)).collect(),
+            next_label: 0,
+            limits: self.limits,
+            depth: 0,
+        }
+        .write(f, self.value)
+    }
+}
+
+#[test]
+fn datum_labels() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let program = "
+        (define x (list 1 2 3))
+        (set-cdr! (cdr (cdr x)) x)
+        (define shared (list 'a))
+        (define y (list shared shared))
+        (define v (vector 1 2))
+        (vector-set! v 1 v)";
+    interpreter.eval(program.chars())?;
+    let get = |name: &str| interpreter.eval(name.chars()).map(Option::unwrap);
+    assert_eq!(format!("{}", get("x")?), "#0=(1 2 3 . #0#)");
+    assert_eq!(format!("{}", get("y")?), "((a) (a))");
+    assert_eq!(format!("{}", get("v")?), "#0=#(1 #0#)");
+    assert_eq!(
+        format!("{}", External::new(&get("y")?, true, Labeling::Shared)),
+        "(#0=(a) #0#)"
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            External::new(&get("(list x x)")?, true, Labeling::Shared)
+        ),
+        "(#0=(1 2 3 . #0#) #0#)"
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            External::new(&get("(cons y y)")?, true, Labeling::Shared)
+        ),
+        "(#0=(#1=(a) #1#) . #0#)"
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            External::new(&get("(list x x)")?, true, Labeling::Cycles)
+        ),
+        "(#0=(1 2 3 . #0#) #0#)"
+    );
+    assert_eq!(format!("{}", get("(iota 100000)")?).len(), 588_891);
+    Ok(())
+}
+
+#[test]
+fn limits() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval("(define x (list 1 2 3)) (set-cdr! (cdr (cdr x)) x)".chars())?;
+    let limited = |source: &str, depth, length| -> Result<String> {
+        let value = interpreter.eval(source.chars())?.unwrap();
+        Ok(format!(
+            "{}",
+            External::new(&value, true, Labeling::Cycles).limited(Limits { depth, length })
+        ))
+    };
+    assert_eq!(
+        limited("'(1 (2 (3 (4))) #(5 (6)))", Some(2), None)?,
+        "(1 (2 ...) #(5 ...))"
+    );
+    assert_eq!(limited("'(1 (2 (3 (4))) #(5 (6)))", Some(0), None)?, "...");
+    assert_eq!(limited("(iota 5)", None, Some(3))?, "(0 1 2 ...)");
+    assert_eq!(limited("(iota 3)", None, Some(3))?, "(0 1 2)");
+    assert_eq!(limited("'(1 2 . 3)", None, Some(2))?, "(1 2 . 3)");
+    assert_eq!(limited("(make-vector 4 'a)", None, Some(2))?, "#(a a ...)");
+    assert_eq!(limited("(list x)", Some(1), None)?, "(...)");
+    assert_eq!(limited("x", None, Some(5))?, "#0=(1 2 3 . #0#)");
+    Ok(())
+}
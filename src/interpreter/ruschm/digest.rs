@@ -0,0 +1,349 @@
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// the message padded with a one bit, zeros and its length in bits, to a multiple of 64 bytes
+fn pad(message: &[u8], big_endian: bool) -> Vec<u8> {
+    let bits = (message.len() as u64).wrapping_mul(8);
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend(match big_endian {
+        true => bits.to_be_bytes(),
+        false => bits.to_le_bytes(),
+    });
+    padded
+}
+
+const SHA256_ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// FIPS 180-4
+pub fn sha256(message: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    for block in pad(message, true).chunks(64) {
+        let mut schedule = [0u32; 64];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = schedule[i - 15].rotate_right(7)
+                ^ schedule[i - 15].rotate_right(18)
+                ^ (schedule[i - 15] >> 3);
+            let s1 = schedule[i - 2].rotate_right(17)
+                ^ schedule[i - 2].rotate_right(19)
+                ^ (schedule[i - 2] >> 10);
+            schedule[i] = schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_ROUND_CONSTANTS[i])
+                .wrapping_add(schedule[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *word = word.wrapping_add(*value);
+        }
+    }
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+// FIPS 180-4, broken for collision resistance but still common in checksums
+// RFC 2104, keys longer than the block are hashed first
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    match key.len() > BLOCK {
+        true => block[..32].copy_from_slice(&sha256(key)),
+        false => block[..key.len()].copy_from_slice(key),
+    }
+    let mut inner: Vec<u8> = block.iter().map(|byte| byte ^ 0x36).collect();
+    inner.extend_from_slice(message);
+    let mut outer: Vec<u8> = block.iter().map(|byte| byte ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256(&inner));
+    sha256(&outer)
+}
+
+pub fn sha1(message: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    for block in pad(message, true).chunks(64) {
+        let mut schedule = [0u32; 80];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            schedule[i] = (schedule[i - 3] ^ schedule[i - 8] ^ schedule[i - 14] ^ schedule[i - 16])
+                .rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in schedule.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e].iter()) {
+            *word = word.wrapping_add(*value);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+// RFC 1321, broken for collision resistance but still common in checksums
+pub fn md5(message: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in pad(message, false).chunks(64) {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i {
+                0..=15 => ((b & c) | (!b & d), i),
+                16..=31 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            // the constants are the integer parts of 2^32 * |sin(i + 1)|
+            let k = ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32;
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(k)
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i / 16 * 4 + i % 4]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d].iter()) {
+            *word = word.wrapping_add(*value);
+        }
+    }
+    let mut digest = [0; 16];
+    for (bytes, word) in digest.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+// the 64 bit FNV-1a hash, fast on short inputs, for hash tables and content addressing
+// where nobody chooses inputs to collide
+pub fn fnv1a(message: &[u8]) -> [u8; 8] {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in message {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash.to_be_bytes()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// compares every byte whatever the first difference, for the time taken not to tell a peer
+// how much of a guessed signature or token was right
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+// (name data [format]), hashing a bytevector or the UTF-8 of a string,
+// into a bytevector, or a lowercase hex string when the format is 'hex
+fn digest(
+    name: &str,
+    mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    hash: fn(&[u8]) -> Vec<u8>,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(data), format, None) => {
+            let digest = match data? {
+                ValueType::Bytevector(bytes) => hash(&bytes.borrow()),
+                ValueType::String(text) => hash(text.as_bytes()),
+                other => logic_error!("{}: expect a bytevector or a string, got {}", name, other),
+            };
+            match format.transpose()? {
+                None => Ok(ValueType::bytevector(digest)),
+                Some(ValueType::Symbol(format)) if &*format == "bytevector" => {
+                    Ok(ValueType::bytevector(digest))
+                }
+                Some(ValueType::Symbol(format)) if &*format == "hex" => {
+                    Ok(ValueType::String(hex(&digest)))
+                }
+                Some(other) => logic_error!("{}: unknown format {}", name, other),
+            }
+        }
+        _ => logic_error!("{} takes one or two arguments", name),
+    }
+}
+
+pub(crate) fn digest_library() -> HashMap<String, ValueType> {
+    macro_rules! digest_procedure {
+        ($function:ident, $name:literal, $hash:ident) => {
+            fn $function(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                digest($name, arguments, |message| $hash(message).to_vec())
+            }
+        };
+    }
+
+    digest_procedure!(sha256_procedure, "sha-256", sha256);
+    digest_procedure!(sha1_procedure, "sha-1", sha1);
+    digest_procedure!(md5_procedure, "md5", md5);
+    digest_procedure!(fnv1a_procedure, "fnv-1a", fnv1a);
+
+    [
+        function_mapping!("sha-256", sha256_procedure),
+        function_mapping!("sha-1", sha1_procedure),
+        function_mapping!("md5", md5_procedure),
+        function_mapping!("fnv-1a", fnv1a_procedure),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn digests() {
+    let million = vec![b'a'; 1_000_000];
+    for (hash, message, expected) in [
+        (
+            sha256 as fn(&[u8]) -> [u8; 32],
+            b"" as &[u8],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        ),
+        (
+            sha256,
+            b"abc",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ),
+        (
+            sha256,
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        ),
+        (
+            sha256,
+            &million,
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+        ),
+    ]
+    .iter()
+    {
+        assert_eq!(hex(&hash(message)), *expected);
+    }
+    assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    assert_eq!(
+        hex(&sha1(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+    );
+    assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(
+        hex(&md5(b"The quick brown fox jumps over the lazy dog")),
+        "9e107d9d372bb6826bd81d3542a419d6"
+    );
+    assert!(constant_time_eq(b"abc", b"abc"));
+    assert!(!constant_time_eq(b"abc", b"abd") && !constant_time_eq(b"abc", b"ab"));
+    assert_eq!(hex(&fnv1a(b"")), "cbf29ce484222325");
+    assert_eq!(hex(&fnv1a(b"a")), "af63dc4c8601ec8c");
+    assert_eq!(
+        hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert_eq!(
+        hex(&hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First"
+        )),
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    );
+}
+
+#[test]
+fn digest_procedures() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(sha-1 \"abc\" 'hex)",
+                "\"a9993e364706816aba3e25717850c26c9cd0d89d\"",
+            ),
+            (
+                "(md5 #u8(97 98 99) 'hex)",
+                "\"900150983cd24fb0d6963f7d28e17f72\"",
+            ),
+            ("(fnv-1a \"a\")", "#u8(175 99 220 76 134 1 236 140)"),
+            ("(bytevector-length (sha-256 #u8()))", "32"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(md5 'abc)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "md5: expect a bytevector or a string, got abc".to_string()
+        })
+    );
+    Ok(())
+}
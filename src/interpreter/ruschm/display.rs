@@ -0,0 +1,61 @@
+use crate::interpreter::scheme::base::expect_string;
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// receives the data shown by display-data with its MIME type, e.g. a notebook showing html
+pub type DisplayHandler = Box<dyn Fn(&str, &str)>;
+
+pub(crate) fn display_library() -> HashMap<String, ValueType> {
+    // (display-data mime-type text)
+    // without a handler only the text is written, to the current output port
+    fn display_data(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(mime_type), Some(text), None) => {
+                let mime_type = expect_string("display-data", mime_type?)?;
+                let text = expect_string("display-data", text?)?;
+                match &*interpreter.display_handler.borrow() {
+                    Some(handler) => handler(&mime_type, &text),
+                    None => {
+                        let port = interpreter.current_output_port.borrow().clone();
+                        port.write_str(&text)?;
+                    }
+                }
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("display-data takes a MIME type and a text"),
+        }
+    }
+
+    [interpreter_function_mapping!("display-data", display_data)]
+        .iter()
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn displaying() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter
+        .current_output_port
+        .replace(Rc::new(Port::open_output_string()));
+    interpreter.eval("(display-data \"text/html\" \"<b>bold</b>\")".chars())?;
+    assert_eq!(
+        interpreter.current_output_port.borrow().output_string()?,
+        "<b>bold</b>"
+    );
+    let shown = Rc::new(RefCell::new(vec![]));
+    let sink = shown.clone();
+    *interpreter.display_handler.borrow_mut() = Some(Box::new(move |mime_type, text| {
+        sink.borrow_mut().push(format!("{} {}", mime_type, text))
+    }));
+    interpreter.eval("(display-data \"image/svg+xml\" \"<svg/>\")".chars())?;
+    assert_eq!(*shown.borrow(), vec!["image/svg+xml <svg/>".to_string()]);
+    assert_eq!(
+        interpreter.eval("(display-data 'html \"x\")".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "display-data: expect a string, got html".to_string()
+        })
+    );
+    Ok(())
+}
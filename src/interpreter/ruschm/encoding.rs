@@ -0,0 +1,268 @@
+use crate::interpreter::scheme::base::{expect_bytevector, expect_string};
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    // UTF-16 of the byte order given by the byte order mark, big endian without one
+    Utf16,
+    Latin1,
+}
+
+const ENCODINGS: [(&str, Encoding); 6] = [
+    ("utf-8", Encoding::Utf8),
+    ("utf-16le", Encoding::Utf16Le),
+    ("utf-16be", Encoding::Utf16Be),
+    ("utf-16", Encoding::Utf16),
+    ("latin-1", Encoding::Latin1),
+    ("iso-8859-1", Encoding::Latin1),
+];
+
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF16BE_BOM: [u8; 2] = [0xFE, 0xFF];
+
+fn expect_encoding(name: &str, value: ValueType) -> Result<Encoding> {
+    match value.text() {
+        Some(symbol) => {
+            match ENCODINGS
+                .iter()
+                .find(|(encoding, _)| symbol.eq_ignore_ascii_case(encoding))
+            {
+                Some((_, encoding)) => Ok(*encoding),
+                None => logic_error!("{}: unknown encoding {}", name, symbol),
+            }
+        }
+        _ => logic_error!("{}: expect an encoding name, got {}", name, value),
+    }
+}
+
+fn decode_utf16(name: &str, bytes: &[u8], little_endian: bool) -> Result<String> {
+    if !bytes.len().is_multiple_of(2) {
+        logic_error!("{}: odd number of bytes for UTF-16", name);
+    }
+    let units = bytes.chunks(2).map(|pair| match little_endian {
+        true => u16::from_le_bytes([pair[0], pair[1]]),
+        false => u16::from_be_bytes([pair[0], pair[1]]),
+    });
+    let mut decoded = String::new();
+    for c in std::char::decode_utf16(units) {
+        match c {
+            Ok(c) => decoded.push(c),
+            Err(_) => logic_error!(
+                "{}: unpaired surrogate at byte {}",
+                name,
+                2 * decoded.encode_utf16().count()
+            ),
+        }
+    }
+    Ok(decoded)
+}
+
+// a leading byte order mark of the encoding is dropped
+fn decode(name: &str, bytes: &[u8], encoding: Encoding) -> Result<String> {
+    match encoding {
+        Encoding::Utf8 => {
+            let bytes = bytes.strip_prefix(&UTF8_BOM[..]).unwrap_or(bytes);
+            match std::str::from_utf8(bytes) {
+                Ok(s) => Ok(s.to_string()),
+                Err(e) => logic_error!(
+                    "{}: invalid UTF-8 sequence at byte {}",
+                    name,
+                    e.valid_up_to()
+                ),
+            }
+        }
+        Encoding::Utf16Le => {
+            let bytes = bytes.strip_prefix(&UTF16LE_BOM[..]).unwrap_or(bytes);
+            decode_utf16(name, bytes, true)
+        }
+        Encoding::Utf16Be => {
+            let bytes = bytes.strip_prefix(&UTF16BE_BOM[..]).unwrap_or(bytes);
+            decode_utf16(name, bytes, false)
+        }
+        Encoding::Utf16 => match bytes.strip_prefix(&UTF16LE_BOM[..]) {
+            Some(bytes) => decode_utf16(name, bytes, true),
+            None => decode(name, bytes, Encoding::Utf16Be),
+        },
+        Encoding::Latin1 => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+    }
+}
+
+fn encode(name: &str, text: &str, encoding: Encoding, bom: bool) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    match encoding {
+        Encoding::Utf8 => {
+            if bom {
+                bytes.extend(&UTF8_BOM);
+            }
+            bytes.extend(text.as_bytes());
+        }
+        Encoding::Utf16Le => {
+            if bom {
+                bytes.extend(&UTF16LE_BOM);
+            }
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+        // the mark is always written, as readers of UTF-16 without one may guess either order
+        Encoding::Utf16 => return encode(name, text, Encoding::Utf16Be, true),
+        Encoding::Utf16Be => {
+            if bom {
+                bytes.extend(&UTF16BE_BOM);
+            }
+            bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        }
+        Encoding::Latin1 => {
+            if bom {
+                logic_error!("{}: Latin-1 has no byte order mark", name);
+            }
+            for c in text.chars() {
+                match u8::try_from(c as u32) {
+                    Ok(byte) => bytes.push(byte),
+                    Err(_) => logic_error!("{}: {:?} is not in Latin-1", name, c),
+                }
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn encoding_library() -> HashMap<String, ValueType> {
+    // (bytevector->string bytevector encoding)
+    fn bytevector_to_string(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(bytes), Some(encoding), None) => {
+                let bytes = expect_bytevector("bytevector->string", bytes?)?;
+                let encoding = expect_encoding("bytevector->string", encoding?)?;
+                let decoded = decode("bytevector->string", &bytes.borrow(), encoding)?;
+                Ok(ValueType::String(decoded))
+            }
+            _ => logic_error!("bytevector->string takes exactly two arguments"),
+        }
+    }
+
+    // (string->bytevector string encoding [bom]), where a true bom writes a byte order mark
+    fn string_to_bytevector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(text), Some(encoding), bom, None) => {
+                let text = expect_string("string->bytevector", text?)?;
+                let encoding = expect_encoding("string->bytevector", encoding?)?;
+                let bom = !matches!(bom.transpose()?, None | Some(ValueType::Boolean(false)));
+                Ok(ValueType::bytevector(encode(
+                    "string->bytevector",
+                    &text,
+                    encoding,
+                    bom,
+                )?))
+            }
+            _ => logic_error!("string->bytevector takes two or three arguments"),
+        }
+    }
+
+    // the encoding named by a leading byte order mark, or #f
+    fn bytevector_bom_encoding(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(bytes), None) => {
+                let bytes = expect_bytevector("bytevector-bom-encoding", bytes?)?;
+                let bytes = bytes.borrow();
+                let marks: [(&[u8], &str); 3] = [
+                    (&UTF8_BOM, "utf-8"),
+                    (&UTF16LE_BOM, "utf-16le"),
+                    (&UTF16BE_BOM, "utf-16be"),
+                ];
+                Ok(match marks.iter().find(|(bom, _)| bytes.starts_with(bom)) {
+                    Some((_, encoding)) => ValueType::Symbol(intern(encoding)),
+                    None => ValueType::Boolean(false),
+                })
+            }
+            _ => logic_error!("bytevector-bom-encoding takes exactly one argument"),
+        }
+    }
+
+    [
+        function_mapping!("bytevector->string", bytevector_to_string),
+        function_mapping!("string->bytevector", string_to_bytevector),
+        function_mapping!("bytevector-bom-encoding", bytevector_bom_encoding),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn encodings() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(string->bytevector \"aλ\" 'utf-16le)", "#u8(97 0 187 3)"),
+            (
+                "(string->bytevector \"a\" 'utf-16be #t)",
+                "#u8(254 255 0 97)",
+            ),
+            ("(string->bytevector \"a\" 'utf-16)", "#u8(254 255 0 97)"),
+            (
+                "(string->bytevector \"a\" 'utf-8 #t)",
+                "#u8(239 187 191 97)",
+            ),
+            ("(string->bytevector \"é\" 'latin-1)", "#u8(233)"),
+            ("(bytevector->string #u8(233 120) 'iso-8859-1)", "\"éx\""),
+            // a surrogate pair, after a byte order mark which is dropped
+            (
+                "(bytevector->string #u8(255 254 61 216 0 222) 'utf-16le)",
+                "\"😀\"",
+            ),
+            ("(bytevector->string #u8(255 254 97 0) 'utf-16)", "\"a\""),
+            ("(bytevector->string #u8(0 97) 'UTF-16)", "\"a\""),
+            ("(bytevector->string #u8(239 187 191 97) 'utf-8)", "\"a\""),
+            ("(bytevector-bom-encoding #u8(254 255 0 97))", "utf-16be"),
+            ("(bytevector-bom-encoding #u8(97))", "#f"),
+        ],
+    )?;
+    for (source, message) in [
+        (
+            "(string->bytevector \"λ\" 'latin-1)",
+            "string->bytevector: 'λ' is not in Latin-1",
+        ),
+        (
+            "(bytevector->string #u8(97 0 0) 'utf-16le)",
+            "bytevector->string: odd number of bytes for UTF-16",
+        ),
+        (
+            "(bytevector->string #u8(97 0 0 220) 'utf-16le)",
+            "bytevector->string: unpaired surrogate at byte 2",
+        ),
+        (
+            "(bytevector->string #u8(97) 'ebcdic)",
+            "bytevector->string: unknown encoding ebcdic",
+        ),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(source.chars()),
+            Err(Error {
+                category: ErrorType::Logic,
+                message: message.to_string()
+            }),
+            "{}",
+            source
+        );
+    }
+    Ok(())
+}
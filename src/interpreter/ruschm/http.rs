@@ -0,0 +1,529 @@
+use crate::interpreter::port::file_error;
+use crate::interpreter::scheme::base::{expect_list, expect_string};
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// redirects beyond this are assumed to loop
+const REDIRECT_LIMIT: usize = 5;
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub reason: String,
+    // the names are lowercase, in the order the server sent them
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub(crate) fn check_network_permitted(name: &str, interpreter: &Interpreter) -> Result<()> {
+    if !interpreter.allow_network.get() {
+        logic_error!("{}: network access is not permitted", name)
+    }
+    Ok(())
+}
+
+struct Url {
+    // the host and the port as written, for the Host header
+    authority: String,
+    host: String,
+    port: u16,
+    // the path and the query, starting with a slash
+    target: String,
+}
+
+// only http is spoken, as there is no TLS implementation to speak https with
+fn parse_url(name: &str, url: &str) -> Result<Url> {
+    let rest = match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest,
+        Some((scheme, _)) if scheme.eq_ignore_ascii_case("https") => {
+            logic_error!("{}: https is not supported, only http", name)
+        }
+        _ => logic_error!("{}: expect an http URL, got {}", name, url),
+    };
+    let (authority, target) = match rest.find(['/', '?', '#']) {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let target = target.split('#').next().unwrap();
+    let target = match target.starts_with('/') {
+        true => target.to_string(),
+        false => format!("/{}", target),
+    };
+    // an IPv6 address is bracketed, so its colons are not taken for the port
+    let (host, port) = match authority.rfind(':') {
+        Some(index) if !authority[index..].contains(']') => (
+            &authority[..index],
+            match authority[index + 1..].parse() {
+                Ok(port) => port,
+                Err(_) => logic_error!("{}: invalid port in {}", name, url),
+            },
+        ),
+        _ => (authority, 80),
+    };
+    if host.is_empty() {
+        logic_error!("{}: expect a host in {}", name, url);
+    }
+    Ok(Url {
+        authority: authority.to_string(),
+        host: host.to_string(),
+        port,
+        target,
+    })
+}
+
+fn decode_chunked(name: &str, mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = vec![];
+    loop {
+        let line_end = match body.windows(2).position(|window| window == b"\r\n") {
+            Some(index) => index,
+            None => logic_error!("{}: malformed chunked body", name),
+        };
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = size.split(';').next().unwrap().trim();
+        let size = match usize::from_str_radix(size, 16) {
+            Ok(size) => size,
+            Err(_) => logic_error!("{}: malformed chunk size {}", name, size),
+        };
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size {
+            logic_error!("{}: truncated chunked body", name);
+        }
+        decoded.extend(&body[..size]);
+        body = body.get(size + 2..).unwrap_or(&[]);
+    }
+}
+
+fn parse_response(name: &str, response: &[u8]) -> Result<HttpResponse> {
+    let head_end = match response.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(index) => index,
+        None => logic_error!("{}: malformed response", name),
+    };
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap();
+    let mut parts = status_line.splitn(3, ' ');
+    let status = match (parts.next(), parts.next().map(str::parse)) {
+        (Some(version), Some(Ok(status))) if version.starts_with("HTTP/") => status,
+        _ => logic_error!("{}: malformed status line {}", name, status_line),
+    };
+    let reason = parts.next().unwrap_or("").to_string();
+    let mut headers = vec![];
+    for line in lines {
+        match line.split_once(':') {
+            Some((header, value)) => {
+                headers.push((header.trim().to_lowercase(), value.trim().to_string()))
+            }
+            None => logic_error!("{}: malformed header {}", name, line),
+        }
+    }
+    let mut response = HttpResponse {
+        status,
+        reason,
+        headers,
+        body: response[head_end + 4..].to_vec(),
+    };
+    if response
+        .header("transfer-encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        response.body = decode_chunked(name, &response.body)?;
+    } else if let Some(length) = response
+        .header("content-length")
+        .and_then(|length| length.parse().ok())
+    {
+        response.body.truncate(length);
+    }
+    Ok(response)
+}
+
+// one exchange over a connection which the server closes after the response
+fn exchange(
+    name: &str,
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: Option<&[u8]>,
+) -> Result<HttpResponse> {
+    let parsed = parse_url(name, url)?;
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+        method, parsed.target, parsed.authority
+    );
+    let given = |header: &str| {
+        headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(header))
+    };
+    if !given("user-agent") {
+        request.push_str(concat!(
+            "User-Agent: ruschm/",
+            env!("CARGO_PKG_VERSION"),
+            "\r\n"
+        ));
+    }
+    if let Some(body) = body {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    for (header, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", header, value));
+    }
+    request.push_str("\r\n");
+    let mut request = request.into_bytes();
+    request.extend(body.unwrap_or(&[]));
+    let host = parsed.host.trim_start_matches('[').trim_end_matches(']');
+    let mut stream =
+        TcpStream::connect((host, parsed.port)).map_err(|e| file_error("connect to", url, e))?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .and_then(|_| stream.write_all(&request))
+        .map_err(|e| file_error("send a request to", url, e))?;
+    let mut response = vec![];
+    stream
+        .read_to_end(&mut response)
+        .map_err(|e| file_error("read the response of", url, e))?;
+    parse_response(name, &response)
+}
+
+fn resolve_location(url: &str, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+    let (scheme, rest) = url.split_once("://").unwrap();
+    let authority = rest.split(['/', '?', '#']).next().unwrap();
+    match location.starts_with('/') {
+        true => format!("{}://{}{}", scheme, authority, location),
+        false => {
+            let path = &rest[authority.len()..];
+            let directory = &path[..path.rfind('/').map_or(0, |index| index + 1)];
+            let directory = if directory.is_empty() { "/" } else { directory };
+            format!("{}://{}{}{}", scheme, authority, directory, location)
+        }
+    }
+}
+
+// redirects are followed, where 303 and a redirected POST turn into a GET without a body
+pub fn http_request(
+    name: &str,
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: Option<&[u8]>,
+) -> Result<HttpResponse> {
+    let (mut method, mut url, mut body) = (method.to_string(), url.to_string(), body);
+    for _ in 0..=REDIRECT_LIMIT {
+        let response = exchange(name, &method, &url, headers, body)?;
+        let location = match (response.status, response.header("location")) {
+            (301 | 302 | 303 | 307 | 308, Some(location)) => location.to_string(),
+            _ => return Ok(response),
+        };
+        if response.status == 303 || (response.status <= 302 && method == "POST") {
+            method = "GET".to_string();
+            body = None;
+        }
+        url = resolve_location(&url, &location);
+    }
+    logic_error!("{}: too many redirects", name)
+}
+
+// an alist of symbol or string names to string values
+fn expect_headers(name: &str, headers: Option<Result<ValueType>>) -> Result<Vec<(String, String)>> {
+    let headers = match headers.transpose()? {
+        Some(headers) => expect_list(name, headers)?,
+        None => return Ok(vec![]),
+    };
+    headers
+        .into_iter()
+        .map(|header| {
+            let (header, value) = match &header {
+                ValueType::Pair(pair) => {
+                    let pair = pair.borrow();
+                    (pair.car.clone(), pair.cdr.clone())
+                }
+                other => logic_error!("{}: expect a header pair, got {}", name, other),
+            };
+            let header = match header.text() {
+                Some(header) => header.to_string(),
+                None => logic_error!("{}: expect a header name, got {}", name, header),
+            };
+            if header.contains(['\r', '\n', ':']) {
+                logic_error!("{}: invalid header name {:?}", name, header);
+            }
+            let value = expect_string(name, value)?;
+            if value.contains(['\r', '\n']) {
+                logic_error!("{}: invalid header value {:?}", name, value);
+            }
+            Ok((header, value))
+        })
+        .collect()
+}
+
+fn expect_body(name: &str, body: ValueType) -> Result<Vec<u8>> {
+    match body {
+        ValueType::String(text) => Ok(text.into_bytes()),
+        ValueType::Bytevector(bytes) => Ok(bytes.borrow().clone()),
+        other => logic_error!(
+            "{}: expect a string or bytevector body, got {}",
+            name,
+            other
+        ),
+    }
+}
+
+fn expect_response(name: &str, value: ValueType) -> Result<Rc<HttpResponse>> {
+    match value {
+        ValueType::HttpResponse(response) => Ok(response),
+        other => logic_error!("{}: expect an HTTP response, got {}", name, other),
+    }
+}
+
+pub(crate) fn http_library() -> HashMap<String, ValueType> {
+    // (http-get url [headers])
+    fn http_get(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_network_permitted("http-get", interpreter)?;
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(url), headers, None) => {
+                let url = expect_string("http-get", url?)?;
+                let headers = expect_headers("http-get", headers)?;
+                let response = http_request("http-get", "GET", &url, &headers, None)?;
+                Ok(ValueType::HttpResponse(Rc::new(response)))
+            }
+            _ => logic_error!("http-get takes one or two arguments"),
+        }
+    }
+
+    // (http-post url body [headers])
+    fn http_post(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_network_permitted("http-post", interpreter)?;
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(url), Some(body), headers, None) => {
+                let url = expect_string("http-post", url?)?;
+                let body = expect_body("http-post", body?)?;
+                let headers = expect_headers("http-post", headers)?;
+                let response = http_request("http-post", "POST", &url, &headers, Some(&body))?;
+                Ok(ValueType::HttpResponse(Rc::new(response)))
+            }
+            _ => logic_error!("http-post takes two or three arguments"),
+        }
+    }
+
+    // (http-request method url [headers [body]]) for the other methods
+    fn http_request_procedure(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        check_network_permitted("http-request", interpreter)?;
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(method), Some(url), headers, body, None) => {
+                let method = method?;
+                let method = match method.text() {
+                    Some(method) => method.to_uppercase(),
+                    None => logic_error!("http-request: expect a method, got {}", method),
+                };
+                if method.is_empty() || !method.chars().all(|c| c.is_ascii_alphabetic()) {
+                    logic_error!("http-request: invalid method {}", method);
+                }
+                let url = expect_string("http-request", url?)?;
+                let headers = expect_headers("http-request", headers)?;
+                let body = body
+                    .map(|body| expect_body("http-request", body?))
+                    .transpose()?;
+                let response =
+                    http_request("http-request", &method, &url, &headers, body.as_deref())?;
+                Ok(ValueType::HttpResponse(Rc::new(response)))
+            }
+            _ => logic_error!("http-request takes two to four arguments"),
+        }
+    }
+
+    fn is_http_response(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(
+                value?,
+                ValueType::HttpResponse(_)
+            ))),
+            _ => logic_error!("http-response? takes exactly one argument"),
+        }
+    }
+
+    macro_rules! response_accessor {
+        ($function:ident, $name:literal, $value:expr) => {
+            fn $function(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(response), None) => {
+                        let response = expect_response($name, response?)?;
+                        let value: fn(&HttpResponse) -> Result<ValueType> = $value;
+                        value(&response)
+                    }
+                    _ => logic_error!("{} takes exactly one argument", $name),
+                }
+            }
+        };
+    }
+
+    response_accessor!(http_response_status, "http-response-status", |response| Ok(
+        ValueType::Number(Number::Integer(response.status as i64))
+    ));
+    response_accessor!(http_response_reason, "http-response-reason", |response| Ok(
+        ValueType::String(response.reason.clone())
+    ));
+    response_accessor!(http_response_headers, "http-response-headers", |response| {
+        Ok(ValueType::list(response.headers.iter().map(
+            |(header, value)| {
+                ValueType::cons(
+                    ValueType::Symbol(intern(header)),
+                    ValueType::String(value.clone()),
+                )
+            },
+        )))
+    });
+    response_accessor!(http_response_body, "http-response-body", |response| Ok(
+        ValueType::bytevector(response.body.clone())
+    ));
+    // the body decoded as UTF-8
+    response_accessor!(http_response_text, "http-response-text", |response| {
+        match String::from_utf8(response.body.clone()) {
+            Ok(text) => Ok(ValueType::String(text)),
+            Err(e) => logic_error!(
+                "http-response-text: invalid UTF-8 sequence at byte {}",
+                e.utf8_error().valid_up_to()
+            ),
+        }
+    });
+
+    [
+        interpreter_function_mapping!("http-get", http_get),
+        interpreter_function_mapping!("http-post", http_post),
+        interpreter_function_mapping!("http-request", http_request_procedure),
+        function_mapping!("http-response?", is_http_response),
+        function_mapping!("http-response-status", http_response_status),
+        function_mapping!("http-response-reason", http_response_reason),
+        function_mapping!("http-response-headers", http_response_headers),
+        function_mapping!("http-response-body", http_response_body),
+        function_mapping!("http-response-text", http_response_text),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn urls() {
+    assert_eq!(
+        resolve_location("http://a.b/x/y?q", "z"),
+        "http://a.b/x/z".to_string()
+    );
+    assert_eq!(resolve_location("http://a.b", "/z"), "http://a.b/z");
+    let url = parse_url("t", "http://[::1]:8080?q=1#end").unwrap();
+    assert_eq!(
+        (url.host.as_str(), url.port, url.target.as_str()),
+        ("[::1]", 8080, "/?q=1")
+    );
+    assert_eq!(
+        parse_url("t", "https://a.b").err().unwrap().message,
+        "t: https is not supported, only http"
+    );
+    assert_eq!(
+        decode_chunked("t", b"3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n").unwrap(),
+        b"abcde".to_vec()
+    );
+}
+
+#[test]
+fn http() -> Result<()> {
+    use std::net::TcpListener;
+    use std::thread;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    // answers two connections, redirecting the first, and echoes the requests it got
+    let server = thread::spawn(move || {
+        let mut requests = vec![];
+        for response in [
+            "HTTP/1.1 303 See Other\r\nLocation: /done\r\nContent-Length: 0\r\n\r\n".to_string(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nh\u{e9}\r\n1\r\n!\r\n0\r\n\r\n"
+                .to_string(),
+        ]
+        .iter()
+        {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+            // the request ends after its head, and the four bytes of the first body
+            while !String::from_utf8_lossy(&request).contains("\r\n\r\n")
+                || (requests.is_empty() && !request.ends_with(b"data"))
+            {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend(&buffer[..read]);
+            }
+            requests.push(String::from_utf8(request).unwrap());
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+        requests
+    });
+    let interpreter = Interpreter::new();
+    assert_eq!(
+        interpreter.eval("(http-get \"http://localhost/\")".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "http-get: network access is not permitted".to_string()
+        })
+    );
+    interpreter.allow_network.set(true);
+    let source = format!(
+        "(define response (http-post \"http://{}/submit\" \"data\" '((x-token . \"t\"))))
+         (list (http-response? response) (http-response-status response) (http-response-reason response)
+               (http-response-headers response) (http-response-text response))",
+        address
+    );
+    assert_eq!(
+        format!("{}", interpreter.eval(source.chars())?.unwrap()),
+        "(#t 200 \"OK\" ((content-type . \"text/plain\") (transfer-encoding . \"chunked\")) \"hé!\")"
+    );
+    let requests = server.join().unwrap();
+    assert_eq!(
+        requests[0],
+        format!(
+            "POST /submit HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: ruschm/{}\r\nContent-Length: 4\r\nx-token: t\r\n\r\ndata",
+            address,
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+    assert!(
+        requests[1].starts_with("GET /done HTTP/1.1\r\n"),
+        "{}",
+        requests[1]
+    );
+    assert!(!requests[1].contains("Content-Length"), "{}", requests[1]);
+    Ok(())
+}
@@ -0,0 +1,620 @@
+use crate::environment::Environment;
+use crate::interpreter::bytecode::{Reader, Writer};
+use crate::interpreter::compiler::Code;
+use crate::interpreter::port::file_error;
+use crate::interpreter::scheme::base::expect_string;
+use crate::interpreter::srfi::srfi_69::HashTable;
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::fs;
+
+// an image of the top level environment, as saved by save-image and restored at startup: the
+// mark and the versions of the format and of ruschm, then the variables defined by the
+// program with their values
+// the objects with an identity, pairs, vectors, bytevectors, hash tables, environments and the
+// code of procedures, are written the first time they are reached and referred to by number
+// after, which keeps them shared and their cycles
+// the pairs of a list follow one another, each cdr written after its car, and are written and
+// read in turn rather than recursing, however long the list is
+// builtins are written by name, and the values of the host, as ports, cannot be saved
+const MARK: &[u8; 4] = b"\0rim";
+const FORMAT_VERSION: u64 = 6;
+
+// the tags of the values
+const INTEGER: u8 = 0;
+const REAL: u8 = 1;
+const RATIONAL: u8 = 2;
+const BOOLEAN: u8 = 3;
+const CHARACTER: u8 = 4;
+const STRING: u8 = 5;
+const SYMBOL: u8 = 6;
+const EMPTY_LIST: u8 = 7;
+const EOF: u8 = 8;
+const VOID: u8 = 9;
+const VALUES: u8 = 10;
+const OBJECT: u8 = 11;
+const PAIR: u8 = 12;
+const VECTOR: u8 = 13;
+const BYTEVECTOR: u8 = 14;
+const BUILTIN: u8 = 15;
+const USER: u8 = 16;
+const COMPILED: u8 = 17;
+const HASH_TABLE: u8 = 18;
+const BIG: u8 = 19;
+const BIG_RATIONAL: u8 = 20;
+// and of the environments and codes
+const TOP: u8 = 21;
+const DETACHED: u8 = 22;
+const CHILD: u8 = 23;
+const NEW: u8 = 24;
+
+#[cold]
+fn invalid(path: &str) -> Error {
+    Error {
+        category: ErrorType::File,
+        message: format!("{} is not an image of this version of ruschm", path),
+    }
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+struct ImageWriter<'a> {
+    writer: Writer,
+    top: &'a Rc<Environment>,
+    // the numbers of the objects written, by address
+    written: HashMap<usize, u64>,
+}
+
+impl<'a> ImageWriter<'a> {
+    fn number(&mut self, address: usize) {
+        let number = self.written.len() as u64;
+        self.written.insert(address, number);
+    }
+
+    // whether the object is written already, writing its number if so, else numbering it
+    fn known(&mut self, address: usize) -> bool {
+        match self.written.get(&address) {
+            Some(&number) => {
+                self.writer.byte(OBJECT);
+                self.writer.number(number);
+                true
+            }
+            None => {
+                self.number(address);
+                false
+            }
+        }
+    }
+
+    fn value(&mut self, value: &ValueType) -> Result<()> {
+        let w = &mut self.writer;
+        match value {
+            ValueType::Number(Number::Integer(n)) => {
+                w.byte(INTEGER);
+                w.signed(*n);
+            }
+            ValueType::Number(Number::Real(n)) => {
+                w.byte(REAL);
+                w.number(n.to_bits());
+            }
+            ValueType::Number(Number::Rational(a, b)) => {
+                w.byte(RATIONAL);
+                w.signed(*a);
+                w.signed(*b);
+            }
+            ValueType::Number(Number::Big(n)) => {
+                w.byte(BIG);
+                w.big(n);
+            }
+            ValueType::Number(Number::BigRational(a, b)) => {
+                w.byte(BIG_RATIONAL);
+                w.big(a);
+                w.big(b);
+            }
+            ValueType::Boolean(value) => {
+                w.byte(BOOLEAN);
+                w.boolean(*value);
+            }
+            ValueType::Character(c) => {
+                w.byte(CHARACTER);
+                w.number(*c as u64);
+            }
+            ValueType::String(s) => {
+                w.byte(STRING);
+                w.string(s);
+            }
+            ValueType::Symbol(s) => {
+                w.byte(SYMBOL);
+                w.string(s);
+            }
+            ValueType::EmptyList => w.byte(EMPTY_LIST),
+            ValueType::Eof => w.byte(EOF),
+            ValueType::Void => w.byte(VOID),
+            ValueType::Values(values) => {
+                w.byte(VALUES);
+                w.number(values.len() as u64);
+                for value in values {
+                    self.value(value)?;
+                }
+            }
+            ValueType::Pair(pair) => {
+                let mut pair = pair.clone();
+                while !self.known(address(&pair)) {
+                    self.writer.byte(PAIR);
+                    let cdr = {
+                        let pair = pair.borrow();
+                        self.value(&pair.car)?;
+                        pair.cdr.clone()
+                    };
+                    match cdr {
+                        ValueType::Pair(next) => pair = next,
+                        cdr => return self.value(&cdr),
+                    }
+                }
+            }
+            ValueType::Vector(vector) => {
+                if !self.known(address(vector)) {
+                    self.writer.byte(VECTOR);
+                    let vector = vector.borrow();
+                    self.writer.number(vector.len() as u64);
+                    for element in vector.iter() {
+                        self.value(element)?;
+                    }
+                }
+            }
+            ValueType::Bytevector(bytes) => {
+                if !self.known(address(bytes)) {
+                    let bytes = bytes.borrow();
+                    self.writer.byte(BYTEVECTOR);
+                    self.writer.number(bytes.len() as u64);
+                    self.writer.bytes.extend_from_slice(&bytes);
+                }
+            }
+            ValueType::HashTable(table) => {
+                if !self.known(address(table)) {
+                    self.writer.byte(HASH_TABLE);
+                    let (equivalence, hash, entries) = {
+                        let table = table.borrow();
+                        let (equivalence, hash, entries) = table.parts();
+                        (equivalence.clone(), hash.clone(), entries)
+                    };
+                    self.value(&equivalence)?;
+                    self.value(&hash)?;
+                    self.writer.number(entries.len() as u64);
+                    for (code, key, value) in entries {
+                        self.writer.number(code);
+                        self.value(&key)?;
+                        self.value(&value)?;
+                    }
+                }
+            }
+            ValueType::Procedure(Procedure::Buildin(builtin)) => {
+                w.byte(BUILTIN);
+                w.string(builtin.0);
+            }
+            ValueType::Procedure(Procedure::User(procedure, env)) => {
+                self.writer.byte(USER);
+                if !self.known(address(procedure)) {
+                    self.writer.byte(NEW);
+                    self.writer.procedure(procedure);
+                }
+                self.environment(env)?;
+            }
+            ValueType::Procedure(Procedure::Compiled(code, env)) => {
+                self.writer.byte(COMPILED);
+                if !self.known(address(code)) {
+                    self.writer.byte(NEW);
+                    self.writer.code(code)?;
+                }
+                self.environment(env)?;
+            }
+            value => logic_error!("cannot save {}", value),
+        }
+        Ok(())
+    }
+
+    // the environment around is written first, and may write this one in its variables
+    fn environment(&mut self, env: &Rc<Environment>) -> Result<()> {
+        if Rc::ptr_eq(env, self.top) {
+            self.writer.byte(TOP);
+            return Ok(());
+        }
+        if let Some(&number) = self.written.get(&address(env)) {
+            self.writer.byte(OBJECT);
+            self.writer.number(number);
+            return Ok(());
+        }
+        match env.parent() {
+            Some(parent) => {
+                self.writer.byte(CHILD);
+                self.environment(parent)?;
+                if self.known(address(env)) {
+                    return Ok(());
+                }
+                self.writer.byte(NEW);
+            }
+            None => {
+                self.writer.byte(DETACHED);
+                self.number(address(env));
+            }
+        }
+        self.variables(&env.variables())
+    }
+
+    fn variables(&mut self, variables: &[(String, ValueType)]) -> Result<()> {
+        self.writer.number(variables.len() as u64);
+        for (name, value) in variables {
+            self.writer.string(name);
+            self.value(value)?;
+        }
+        Ok(())
+    }
+}
+
+enum Object {
+    Pair(Rc<RefCell<Pair>>),
+    Vector(Rc<RefCell<Vec<ValueType>>>),
+    Bytevector(Rc<RefCell<Vec<u8>>>),
+    HashTable(Rc<RefCell<HashTable>>),
+    Procedure(Rc<SchemeProcedure>),
+    Code(Rc<Code>),
+    Environment(Rc<Environment>),
+}
+
+struct ImageReader<'a> {
+    reader: Reader<'a>,
+    top: &'a Rc<Environment>,
+    // the objects read, in the order they are numbered
+    objects: Vec<Object>,
+}
+
+impl<'a> ImageReader<'a> {
+    fn object(&mut self) -> Option<&Object> {
+        let number: usize = self.reader.index()?;
+        self.objects.get(number)
+    }
+
+    fn value(&mut self) -> Option<ValueType> {
+        let r = &mut self.reader;
+        Some(match r.byte()? {
+            INTEGER => ValueType::Number(Number::Integer(r.signed()?)),
+            REAL => ValueType::Number(Number::Real(f64::from_bits(r.number()?))),
+            RATIONAL => ValueType::Number(Number::Rational(r.signed()?, r.signed()?)),
+            BIG => ValueType::Number(Number::integer(r.big()?)),
+            BIG_RATIONAL => ValueType::Number(Number::fraction_of(r.big()?, r.big()?).ok()?),
+            BOOLEAN => ValueType::Boolean(r.boolean()?),
+            CHARACTER => ValueType::Character(r.character()?),
+            STRING => ValueType::String(r.string()?),
+            SYMBOL => ValueType::Symbol(intern(&r.string()?)),
+            EMPTY_LIST => ValueType::EmptyList,
+            EOF => ValueType::Eof,
+            VOID => ValueType::Void,
+            VALUES => {
+                let length: usize = r.index()?;
+                let mut values = vec![];
+                for _ in 0..length {
+                    values.push(self.value()?);
+                }
+                ValueType::Values(values)
+            }
+            OBJECT => match self.object()? {
+                Object::Pair(pair) => ValueType::Pair(pair.clone()),
+                Object::Vector(vector) => ValueType::Vector(vector.clone()),
+                Object::Bytevector(bytes) => ValueType::Bytevector(bytes.clone()),
+                Object::HashTable(table) => ValueType::HashTable(table.clone()),
+                _ => return None,
+            },
+            PAIR => {
+                let new_pair = |objects: &mut Vec<Object>| {
+                    let pair = Rc::new(RefCell::new(Pair {
+                        car: ValueType::EmptyList,
+                        cdr: ValueType::EmptyList,
+                    }));
+                    objects.push(Object::Pair(pair.clone()));
+                    pair
+                };
+                let first = new_pair(&mut self.objects);
+                let mut pair = first.clone();
+                loop {
+                    let car = self.value()?;
+                    pair.borrow_mut().car = car;
+                    let cdr = match self.reader.peek()? {
+                        PAIR => {
+                            self.reader.byte()?;
+                            let next = new_pair(&mut self.objects);
+                            pair.borrow_mut().cdr = ValueType::Pair(next.clone());
+                            gc::register_pair(&pair);
+                            pair = next;
+                            continue;
+                        }
+                        _ => self.value()?,
+                    };
+                    pair.borrow_mut().cdr = cdr;
+                    gc::register_pair(&pair);
+                    break ValueType::Pair(first);
+                }
+            }
+            VECTOR => {
+                let vector = Rc::new(RefCell::new(vec![]));
+                self.objects.push(Object::Vector(vector.clone()));
+                let length: usize = self.reader.index()?;
+                for _ in 0..length {
+                    let element = self.value()?;
+                    vector.borrow_mut().push(element);
+                }
+                gc::register_vector(&vector);
+                ValueType::Vector(vector)
+            }
+            BYTEVECTOR => {
+                let length = r.index()?;
+                let bytes = Rc::new(RefCell::new(r.take(length)?.to_vec()));
+                self.objects.push(Object::Bytevector(bytes.clone()));
+                ValueType::Bytevector(bytes)
+            }
+            HASH_TABLE => {
+                let table = Rc::new(RefCell::new(HashTable::new(
+                    ValueType::Void,
+                    ValueType::Void,
+                )));
+                self.objects.push(Object::HashTable(table.clone()));
+                let equivalence = self.value()?;
+                let hash = self.value()?;
+                let length: usize = self.reader.index()?;
+                let mut entries = vec![];
+                for _ in 0..length {
+                    let code = self.reader.number()?;
+                    entries.push((code, self.value()?, self.value()?));
+                }
+                *table.borrow_mut() = HashTable::restored(equivalence, hash, entries);
+                ValueType::HashTable(table)
+            }
+            // as bound to their own names at startup
+            BUILTIN => {
+                let name = r.string()?;
+                match self.top.get(&name)? {
+                    ValueType::Procedure(Procedure::Buildin(builtin)) if builtin.0 == name => {
+                        ValueType::Procedure(Procedure::Buildin(builtin))
+                    }
+                    _ => return None,
+                }
+            }
+            USER => {
+                let procedure = match r.byte()? {
+                    OBJECT => match self.object()? {
+                        Object::Procedure(procedure) => procedure.clone(),
+                        _ => return None,
+                    },
+                    NEW => {
+                        let procedure = Rc::new(r.procedure()?);
+                        self.objects.push(Object::Procedure(procedure.clone()));
+                        procedure
+                    }
+                    _ => return None,
+                };
+                ValueType::Procedure(Procedure::User(procedure, self.environment()?))
+            }
+            COMPILED => {
+                let code = match r.byte()? {
+                    OBJECT => match self.object()? {
+                        Object::Code(code) => code.clone(),
+                        _ => return None,
+                    },
+                    NEW => {
+                        let code = Rc::new(r.code()?);
+                        self.objects.push(Object::Code(code.clone()));
+                        code
+                    }
+                    _ => return None,
+                };
+                ValueType::Procedure(Procedure::Compiled(code, self.environment()?))
+            }
+            _ => return None,
+        })
+    }
+
+    fn environment(&mut self) -> Option<Rc<Environment>> {
+        let env = match self.reader.byte()? {
+            TOP => return Some(self.top.clone()),
+            OBJECT => match self.object()? {
+                Object::Environment(env) => return Some(env.clone()),
+                _ => return None,
+            },
+            DETACHED => Environment::empty(),
+            CHILD => {
+                let parent = self.environment()?;
+                match self.reader.byte()? {
+                    OBJECT => match self.object()? {
+                        Object::Environment(env) => return Some(env.clone()),
+                        _ => return None,
+                    },
+                    NEW => Environment::child(parent),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        let env = Rc::new(env);
+        self.objects.push(Object::Environment(env.clone()));
+        let length: usize = self.reader.index()?;
+        for _ in 0..length {
+            let name = self.reader.string()?;
+            let value = self.value()?;
+            env.define(name, value);
+        }
+        gc::register_environment(&env);
+        Some(env)
+    }
+}
+
+// the variables with their values as an image
+pub(crate) fn encode(top: &Rc<Environment>, variables: &[(String, ValueType)]) -> Result<Vec<u8>> {
+    let mut image = ImageWriter {
+        writer: Writer::default(),
+        top,
+        written: HashMap::new(),
+    };
+    image.writer.bytes.extend_from_slice(MARK);
+    image.writer.number(FORMAT_VERSION);
+    image.writer.string(env!("CARGO_PKG_VERSION"));
+    image.variables(variables)?;
+    Ok(image.writer.bytes)
+}
+
+pub(crate) fn savable(top: &Rc<Environment>, value: &ValueType) -> bool {
+    let mut image = ImageWriter {
+        writer: Writer::default(),
+        top,
+        written: HashMap::new(),
+    };
+    image.value(value).is_ok()
+}
+
+// the variables of an image, their procedures closing over the top level environment
+pub(crate) fn decode(top: &Rc<Environment>, bytes: &[u8]) -> Option<Vec<(String, ValueType)>> {
+    let mut image = ImageReader {
+        reader: Reader::new(bytes),
+        top,
+        objects: vec![],
+    };
+    let r = &mut image.reader;
+    if r.take(MARK.len())? != MARK
+        || r.number()? != FORMAT_VERSION
+        || r.string()? != env!("CARGO_PKG_VERSION")
+    {
+        return None;
+    }
+    let length: usize = r.index()?;
+    let mut variables = vec![];
+    for _ in 0..length {
+        let name = image.reader.string()?;
+        variables.push((name, image.value()?));
+    }
+    Some(variables).filter(|_| image.reader.finished())
+}
+
+impl Interpreter {
+    // the variables the program defined at the top level, all of them unless only those which
+    // can be saved are asked for
+    // the builtins bound to their own names are there at startup, as are the values of the
+    // host other variables are bound to then, as the default random source
+    pub(crate) fn defined_variables(&self, savable_only: bool) -> Vec<(String, ValueType)> {
+        let startup = Environment::new();
+        self.env
+            .variables()
+            .into_iter()
+            .filter(|(name, value)| match value {
+                ValueType::Procedure(Procedure::Buildin(builtin)) => builtin.0 != name,
+                value if savable_only || startup.get(name).is_some() => savable(&self.env, value),
+                _ => true,
+            })
+            .collect()
+    }
+
+    // writes the variables the program defined at the top level, and the values they refer to,
+    // to the file
+    pub fn save_image(&self, path: &str) -> Result<()> {
+        let image = encode(&self.env, &self.defined_variables(false)).map_err(|e| Error {
+            message: format!("save-image: {}", e.message),
+            ..e
+        })?;
+        fs::write(path, image).map_err(|e| file_error("write", path, e))
+    }
+
+    // defines the variables of an image in the top level environment
+    pub fn load_image(&self, path: &str) -> Result<()> {
+        let bytes = fs::read(path).map_err(|e| file_error("read", path, e))?;
+        for (name, value) in decode(&self.env, &bytes).ok_or_else(|| invalid(path))? {
+            self.env.define(name, value);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn image_library() -> HashMap<String, ValueType> {
+    // (save-image path)
+    fn save_image(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                interpreter.save_image(&expect_string("save-image", path?)?)?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("save-image takes one argument"),
+        }
+    }
+
+    [interpreter_function_mapping!("save-image", save_image)]
+        .iter()
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn images() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("ruschm-image-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    for engine in [vm::Engine::Bytecode, vm::Engine::TreeWalker] {
+        let saved = Interpreter::new();
+        saved.engine.set(engine);
+        saved
+            .eval_source(
+                "(define (counter)
+                   (define n (vector 0))
+                   (lambda () (vector-set! n 0 (+ (vector-ref n 0) 1)) (vector-ref n 0)))
+                 (define next (counter))
+                 (next)
+                 (define same next)
+                 (define l (list 1 2.5 1/2 #\\a \"s\" 'x))
+                 (set-cdr! (cdr (cdr (cdr (cdr (cdr l))))) l)
+                 (define v (vector l #u8(1 2) (if #f #f)))
+                 (define first car)
+                 (define (even? n) (if (= n 0) #t (odd? (- n 1))))
+                 (define (odd? n) (if (= n 0) #f (even? (- n 1))))
+                 (define table (make-hash-table))
+                 (hash-table-set! table 'a 1)
+                 (hash-table-set! table \"s\" (list table))
+                 (define key (list 1))
+                 (define by-identity (make-hash-table eq?))
+                 (hash-table-set! by-identity key 'found)
+                 (define long (iota 100000))
+                 (define big (list (expt 2 100) (/ -1 (expt 2 70))))",
+            )
+            .map_err(|(e, _)| e)?;
+        saved.save_image(path)?;
+        let restored = Interpreter::new();
+        restored.load_image(path)?;
+        assert_eq!(
+            restored
+                .eval_source(
+                    "(list (next) (same) (eq? next same) (first (vector-ref v 0))
+                       (eq? l (vector-ref v 0)) (list-ref l 11) (vector-ref v 1) (odd? 7))"
+                )
+                .map_err(|(e, _)| e)?
+                .map(|value| value.to_string()),
+            Some("(2 3 #t 1 #t x #u8(1 2) #t)".to_string())
+        );
+        assert_eq!(
+            restored
+                .eval_source(
+                    "(list (hash-table-ref/default table 'a #f)
+                       (eq? table (car (hash-table-ref/default table \"s\" #f)))
+                       (hash-table-ref/default by-identity key #f) (length long) (list-ref long 99999) big)"
+                )
+                .map_err(|(e, _)| e)?
+                .map(|value| value.to_string()),
+            Some(
+                "(1 #t found 100000 99999 (1267650600228229401496703205376 -1/1180591620717411303424))"
+                    .to_string()
+            )
+        );
+    }
+    let it = Interpreter::new();
+    it.eval_source("(define port (current-output-port))")
+        .map_err(|(e, _)| e)?;
+    assert!(it.save_image(path).is_err());
+    fs::write(path, "(define x 1)").unwrap();
+    assert!(Interpreter::new().load_image(path).is_err());
+    fs::remove_file(path).unwrap();
+    Ok(())
+}
@@ -0,0 +1,536 @@
+use crate::interpreter::scheme::base::{expect_list, expect_pair, input_port, output_port};
+use crate::interpreter::srfi::srfi_69::{default_hash, equivalence_procedure, insert, HashTable};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// how the JSON literals and objects are represented, given as an alist of
+// (null . datum), (true . datum), (false . datum) and (object . alist|hash-table);
+// by default null is the symbol null and objects are alists keyed by symbols, as in SRFI 180
+struct JsonOptions {
+    null: ValueType,
+    true_value: ValueType,
+    false_value: ValueType,
+    hash_tables: bool,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            null: ValueType::Symbol(intern("null")),
+            true_value: ValueType::Boolean(true),
+            false_value: ValueType::Boolean(false),
+            hash_tables: false,
+        }
+    }
+}
+
+impl JsonOptions {
+    fn parse(name: &str, options: Option<Result<ValueType>>) -> Result<Self> {
+        let mut parsed = Self::default();
+        let options = match options.transpose()? {
+            Some(options) => expect_list(name, options)?,
+            None => return Ok(parsed),
+        };
+        for option in options {
+            let option = expect_pair(name, option)?;
+            let (car, cdr) = {
+                let option = option.borrow();
+                (option.car.clone(), option.cdr.clone())
+            };
+            match (&car, cdr) {
+                (ValueType::Symbol(key), value) if &**key == "null" => parsed.null = value,
+                (ValueType::Symbol(key), value) if &**key == "true" => parsed.true_value = value,
+                (ValueType::Symbol(key), value) if &**key == "false" => parsed.false_value = value,
+                (ValueType::Symbol(key), ValueType::Symbol(kind)) if &**key == "object" => {
+                    parsed.hash_tables = match &*kind {
+                        "alist" => false,
+                        "hash-table" => true,
+                        _ => logic_error!("{}: unknown object representation {}", name, kind),
+                    }
+                }
+                _ => logic_error!("{}: unknown option {}", name, car),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+struct JsonReader<'a> {
+    port: &'a Port,
+    options: &'a JsonOptions,
+    interpreter: &'a Interpreter,
+}
+
+impl JsonReader<'_> {
+    fn peek(&self) -> Result<Option<char>> {
+        self.port.peek_char()
+    }
+
+    fn next(&self) -> Result<char> {
+        match self.port.read_char()? {
+            Some(c) => Ok(c),
+            None => logic_error!("json-read: unexpected end of input"),
+        }
+    }
+
+    fn skip_whitespace(&self) -> Result<()> {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek()? {
+            self.port.read_char()?;
+        }
+        Ok(())
+    }
+
+    fn expect(&self, expected: char) -> Result<()> {
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => logic_error!("json-read: expect {:?}, got {:?}", expected, c),
+        }
+    }
+
+    // the rest of a literal whose first character was already read
+    fn literal(&self, rest: &str, value: &ValueType) -> Result<ValueType> {
+        for expected in rest.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value.clone())
+    }
+
+    fn value(&self) -> Result<ValueType> {
+        self.skip_whitespace()?;
+        match self.next()? {
+            'n' => self.literal("ull", &self.options.null),
+            't' => self.literal("rue", &self.options.true_value),
+            'f' => self.literal("alse", &self.options.false_value),
+            '"' => Ok(ValueType::String(self.string()?)),
+            '[' => self.array(),
+            '{' => self.object(),
+            c @ ('-' | '0'..='9') => self.number(c),
+            c => logic_error!("json-read: unexpected character {:?}", c),
+        }
+    }
+
+    // the elements of an array or the members of an object, up to the closing bracket
+    fn sequence(&self, close: char, mut element: impl FnMut() -> Result<()>) -> Result<()> {
+        self.skip_whitespace()?;
+        if self.peek()? == Some(close) {
+            self.port.read_char()?;
+            return Ok(());
+        }
+        loop {
+            element()?;
+            self.skip_whitespace()?;
+            match self.next()? {
+                ',' => continue,
+                c if c == close => return Ok(()),
+                c => logic_error!("json-read: expect ',' or {:?}, got {:?}", close, c),
+            }
+        }
+    }
+
+    fn array(&self) -> Result<ValueType> {
+        let mut elements = vec![];
+        self.sequence(']', || {
+            elements.push(self.value()?);
+            Ok(())
+        })?;
+        Ok(ValueType::vector(elements))
+    }
+
+    fn object(&self) -> Result<ValueType> {
+        let mut members = vec![];
+        self.sequence('}', || {
+            self.skip_whitespace()?;
+            self.expect('"')?;
+            let key = self.string()?;
+            self.skip_whitespace()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            Ok(())
+        })?;
+        if self.options.hash_tables {
+            let equivalence = equivalence_procedure(true);
+            let hash = default_hash(&equivalence);
+            let table = RefCell::new(HashTable::new(equivalence, hash));
+            for (key, value) in members {
+                insert(
+                    self.interpreter,
+                    &table,
+                    ValueType::Symbol(intern(&key)),
+                    value,
+                )?;
+            }
+            Ok(ValueType::HashTable(Rc::new(table)))
+        } else {
+            Ok(ValueType::list(members.into_iter().map(|(key, value)| {
+                ValueType::cons(ValueType::Symbol(intern(&key)), value)
+            })))
+        }
+    }
+
+    fn hex_escape(&self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            match self.next()?.to_digit(16) {
+                Some(digit) => code = code * 16 + digit,
+                None => logic_error!("json-read: invalid \\u escape"),
+            }
+        }
+        Ok(code)
+    }
+
+    // the rest of a string whose opening quote was already read
+    fn string(&self) -> Result<String> {
+        let mut string = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(string),
+                '\\' => string.push(match self.next()? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => self.escaped_character()?,
+                    c => logic_error!("json-read: invalid escape \\{}", c),
+                }),
+                c if (c as u32) < 0x20 => {
+                    logic_error!("json-read: unescaped control character {:?}", c)
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    // characters beyond the basic multilingual plane are escaped as surrogate pairs
+    fn escaped_character(&self) -> Result<char> {
+        let high = self.hex_escape()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                self.expect('\\')?;
+                self.expect('u')?;
+                match self.hex_escape()? {
+                    low @ 0xDC00..=0xDFFF => 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00),
+                    _ => logic_error!("json-read: unpaired surrogate in \\u escape"),
+                }
+            }
+            code => code,
+        };
+        match char::from_u32(code) {
+            Some(c) => Ok(c),
+            None => logic_error!("json-read: unpaired surrogate in \\u escape"),
+        }
+    }
+
+    // integers which fit are exact, other numbers are inexact
+    fn number(&self, first: char) -> Result<ValueType> {
+        let mut text = first.to_string();
+        let mut exact = true;
+        while let Some(c) = self.peek()? {
+            match c {
+                '0'..='9' | '-' | '+' => (),
+                '.' | 'e' | 'E' => exact = false,
+                _ => break,
+            }
+            text.push(c);
+            self.port.read_char()?;
+        }
+        let valid = {
+            let digits = text.strip_prefix('-').unwrap_or(&text);
+            let (mantissa, exponent) = match digits.find(['e', 'E']) {
+                Some(index) => (&digits[..index], Some(&digits[index + 1..])),
+                None => (digits, None),
+            };
+            let (integer, fraction) = match mantissa.split_once('.') {
+                Some((integer, fraction)) => (integer, Some(fraction)),
+                None => (mantissa, None),
+            };
+            let all_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+            all_digits(integer)
+                && (integer == "0" || !integer.starts_with('0'))
+                && fraction.is_none_or(all_digits)
+                && exponent.is_none_or(|exponent| {
+                    all_digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))
+                })
+        };
+        if !valid {
+            logic_error!("json-read: invalid number {}", text);
+        }
+        Ok(ValueType::Number(match text.parse() {
+            Ok(integer) if exact => Number::Integer(integer),
+            _ => Number::Real(text.parse().unwrap()),
+        }))
+    }
+}
+
+// the next JSON value of the port, or the eof object when only whitespace is left
+fn read_json(interpreter: &Interpreter, port: &Port, options: &JsonOptions) -> Result<ValueType> {
+    let reader = JsonReader {
+        port,
+        options,
+        interpreter,
+    };
+    reader.skip_whitespace()?;
+    match port.peek_char()? {
+        None => Ok(ValueType::Eof),
+        Some(_) => reader.value(),
+    }
+}
+
+fn write_json_string(text: &str, output: &mut String) {
+    output.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\u{8}' => output.push_str("\\b"),
+            '\u{c}' => output.push_str("\\f"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+fn write_json_number(number: Number, output: &mut String) -> Result<()> {
+    let real = match number {
+        Number::Integer(_) | Number::Big(_) => {
+            output.push_str(&number.to_string());
+            return Ok(());
+        }
+        Number::Real(n) => n,
+        // JSON has no fractions, so they are written as their nearest float
+        Number::Rational(..) | Number::BigRational(..) => number.to_f64(),
+    };
+    if !real.is_finite() {
+        logic_error!("json-write: cannot write {} as JSON", number);
+    }
+    output.push_str(&format!("{:?}", real));
+    Ok(())
+}
+
+fn json_key(key: &ValueType) -> Result<String> {
+    match key.text() {
+        Some(text) => Ok(text.to_string()),
+        None => logic_error!(
+            "json-write: object keys must be symbols or strings, got {}",
+            key
+        ),
+    }
+}
+
+fn write_json_object(
+    members: Vec<(String, ValueType)>,
+    options: &JsonOptions,
+    output: &mut String,
+) -> Result<()> {
+    output.push('{');
+    for (index, (key, value)) in members.into_iter().enumerate() {
+        if index > 0 {
+            output.push(',');
+        }
+        write_json_string(&key, output);
+        output.push(':');
+        write_json(&value, options, output)?;
+    }
+    output.push('}');
+    Ok(())
+}
+
+// the literals are compared with their representations first, so a symbol null writes null
+fn write_json(value: &ValueType, options: &JsonOptions, output: &mut String) -> Result<()> {
+    if value.equal(&options.null) {
+        output.push_str("null");
+        return Ok(());
+    }
+    if value.equal(&options.true_value) {
+        output.push_str("true");
+        return Ok(());
+    }
+    if value.equal(&options.false_value) {
+        output.push_str("false");
+        return Ok(());
+    }
+    match value {
+        ValueType::Number(number) => write_json_number(number.clone(), output)?,
+        ValueType::String(string) => write_json_string(string, output),
+        ValueType::Vector(vector) => {
+            output.push('[');
+            for (index, element) in vector.borrow().iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                write_json(element, options, output)?;
+            }
+            output.push(']');
+        }
+        // the keys of a table are sorted, so the same table is always written the same way
+        ValueType::HashTable(table) => {
+            let mut members = table
+                .borrow()
+                .entries()
+                .into_iter()
+                .map(|(key, value)| Ok((json_key(&key)?, value)))
+                .collect::<Result<Vec<_>>>()?;
+            members.sort_by(|(a, _), (b, _)| a.cmp(b));
+            write_json_object(members, options, output)?;
+        }
+        ValueType::EmptyList | ValueType::Pair(_) => {
+            let members = match value.list_to_vec() {
+                Some(members) => members,
+                None => logic_error!("json-write: cannot write an improper list as JSON"),
+            };
+            let mut entries = vec![];
+            for member in members {
+                match member {
+                    ValueType::Pair(pair) => {
+                        let pair = pair.borrow();
+                        entries.push((json_key(&pair.car)?, pair.cdr.clone()))
+                    }
+                    other => logic_error!("json-write: expect an alist member, got {}", other),
+                }
+            }
+            write_json_object(entries, options, output)?;
+        }
+        other => logic_error!("json-write: cannot write {} as JSON", other),
+    }
+    Ok(())
+}
+
+// the value of a JSON text, with the default representations
+pub(crate) fn parse_json(interpreter: &Interpreter, text: &str) -> Result<ValueType> {
+    read_json(
+        interpreter,
+        &Port::open_input_string(text),
+        &JsonOptions::default(),
+    )
+}
+
+pub(crate) fn to_json(value: &ValueType) -> Result<String> {
+    let mut output = String::new();
+    write_json(value, &JsonOptions::default(), &mut output)?;
+    Ok(output)
+}
+
+pub(crate) fn json_library() -> HashMap<String, ValueType> {
+    // (json-read [port [options]])
+    fn json_read(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (port, options, None) => {
+                let port = input_port("json-read", port, interpreter)?;
+                let options = JsonOptions::parse("json-read", options)?;
+                read_json(interpreter, &port, &options)
+            }
+            _ => logic_error!("json-read takes zero to two arguments"),
+        }
+    }
+
+    // (json-write value [port [options]])
+    fn json_write(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(value), port, options, None) => {
+                let value = value?;
+                let port = output_port("json-write", port, interpreter)?;
+                let options = JsonOptions::parse("json-write", options)?;
+                let mut output = String::new();
+                write_json(&value, &options, &mut output)?;
+                port.write_str(&output)?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("json-write takes one to three arguments"),
+        }
+    }
+
+    [
+        interpreter_function_mapping!("json-read", json_read),
+        interpreter_function_mapping!("json-write", json_write),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn json() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(json-read (open-input-string \" {\\\"a\\\": [1, -2.5e1, true, null], \\\"b\\\": {}} \"))",
+                "((a . #(1 -25.0 #t null)) (b))",
+            ),
+            (
+                "(json-read (open-input-string \"\\\"\\\\u00e9\\\\ud83d\\\\ude00\\\\n\\\"\"))",
+                "\"é😀\\n\"",
+            ),
+            (
+                "(json-read (open-input-string \"[null, false]\") '((null . nil) (false . no)))",
+                "#(nil no)",
+            ),
+            (
+                "(define table (json-read (open-input-string \"{\\\"x\\\": 1}\") '((object . hash-table))))
+                 (hash-table-ref table 'x)",
+                "1",
+            ),
+            ("(json-read (open-input-string \"  \"))", "<eof>"),
+            (
+                "(define port (open-output-string))
+                 (json-write '((name . \"a\\\"b\") (list . #(1 1/2 2.5 #t #f null)) (empty)) port)
+                 (get-output-string port)",
+                "\"{\\\"name\\\":\\\"a\\\\\\\"b\\\",\\\"list\\\":[1,0.5,2.5,true,false,null],\\\"empty\\\":{}}\"",
+            ),
+            (
+                "(define port (open-output-string))
+                 (define table (make-hash-table))
+                 (hash-table-set! table 'b 2)
+                 (hash-table-set! table \"a\" 1)
+                 (json-write (vector table 'none 'yes) port '((null . none) (true . yes)))
+                 (get-output-string port)",
+                "\"[{\\\"a\\\":1,\\\"b\\\":2},null,true]\"",
+            ),
+        ],
+    )?;
+    for (source, message) in [
+        (
+            "(json-read (open-input-string \"[1 2]\"))",
+            "json-read: expect ',' or ']', got '2'",
+        ),
+        (
+            "(json-read (open-input-string \"01\"))",
+            "json-read: invalid number 01",
+        ),
+        (
+            "(json-read (open-input-string \"[1,\"))",
+            "json-read: unexpected end of input",
+        ),
+        (
+            "(json-write (list 1))",
+            "json-write: expect an alist member, got 1",
+        ),
+        ("(json-write 'sym)", "json-write: cannot write sym as JSON"),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(source.chars()),
+            Err(Error {
+                category: ErrorType::Logic,
+                message: message.to_string()
+            }),
+            "{}",
+            source
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use crate::interpreter::srfi::srfi_19::{format_date, Date, Time, TimeType};
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+const LEVELS: [(&str, LogLevel); 5] = [
+    ("trace", LogLevel::Trace),
+    ("debug", LogLevel::Debug),
+    ("info", LogLevel::Info),
+    ("warn", LogLevel::Warn),
+    ("error", LogLevel::Error),
+];
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        LEVELS
+            .iter()
+            .find(|(level_name, _)| name.eq_ignore_ascii_case(level_name))
+            .map(|(_, level)| *level)
+    }
+
+    pub fn name(self) -> &'static str {
+        LEVELS.iter().find(|(_, level)| *level == self).unwrap().0
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name().to_uppercase())
+    }
+}
+
+// one call of a logging procedure, with the message and its irritants already written
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub target: String,
+    pub message: String,
+    pub time: SystemTime,
+}
+
+// written as the default handler writes it, with the time in UTC
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nanoseconds = match self.time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_nanos() as i128,
+            Err(before) => -(before.duration().as_nanos() as i128),
+        };
+        let time = Time::from_nanoseconds(TimeType::Utc, nanoseconds);
+        let timestamp = format_date(&Date::from_time(&time, 0), "~5").map_err(|_| fmt::Error)?;
+        write!(
+            f,
+            "{}Z {} {}: {}",
+            timestamp, self.level, self.target, self.message
+        )
+    }
+}
+
+// the least severe level kept for each target, None turns logging off, and the longest
+// target prefix given decides, like RUST_LOG: "warn,net=debug,net.noisy=off"
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    pub default: Option<LogLevel>,
+    pub targets: Vec<(String, Option<LogLevel>)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            default: Some(LogLevel::Info),
+            targets: vec![],
+        }
+    }
+}
+
+impl LogFilter {
+    // unknown directives are ignored, so a bad variable does not stop the program
+    pub fn parse(directives: &str) -> Self {
+        let mut filter = Self::default();
+        for directive in directives.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Some(level) = parse_threshold(level) {
+                        filter.set(Some(target.trim()), level);
+                    }
+                }
+                None => {
+                    if let Some(level) = parse_threshold(directive) {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+        filter
+    }
+
+    // from the RUSCHM_LOG environment variable, info when it is not set
+    pub fn from_environment() -> Self {
+        match std::env::var("RUSCHM_LOG") {
+            Ok(directives) => Self::parse(&directives),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn set(&mut self, target: Option<&str>, level: Option<LogLevel>) {
+        match target {
+            Some(target) => match self.targets.iter_mut().find(|(name, _)| name == target) {
+                Some(entry) => entry.1 = level,
+                None => self.targets.push((target.to_string(), level)),
+            },
+            None => self.default = level,
+        }
+    }
+
+    pub fn enabled(&self, level: LogLevel, target: &str) -> bool {
+        let threshold = self
+            .targets
+            .iter()
+            .filter(|(name, _)| target.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.default, |(_, threshold)| *threshold);
+        threshold.is_some_and(|threshold| level >= threshold)
+    }
+}
+
+fn parse_threshold(name: &str) -> Option<Option<LogLevel>> {
+    match name.trim() {
+        "off" => Some(None),
+        name => LogLevel::parse(name).map(Some),
+    }
+}
+
+// embedders route records into their own logging, for example `log::log!` or `tracing::event!`
+pub type LogHandler = Box<dyn Fn(&LogRecord)>;
+
+// the target of the logging procedures which do not name one
+const DEFAULT_TARGET: &str = "scheme";
+
+fn expect_level(name: &str, value: ValueType) -> Result<LogLevel> {
+    match &value {
+        ValueType::Symbol(symbol) => match LogLevel::parse(symbol) {
+            Some(level) => Ok(level),
+            None => logic_error!("{}: unknown log level {}", name, symbol),
+        },
+        _ => logic_error!("{}: expect a log level symbol, got {}", name, value),
+    }
+}
+
+fn expect_target(name: &str, value: ValueType) -> Result<String> {
+    match value.text() {
+        Some(target) => Ok(target.to_string()),
+        None => logic_error!("{}: expect a symbol or string target, got {}", name, value),
+    }
+}
+
+// the message is displayed and the irritants written, as in error messages
+fn log(
+    interpreter: &Interpreter,
+    level: LogLevel,
+    target: String,
+    arguments: Arguments,
+) -> Result<ValueType> {
+    let arguments = arguments.collect::<Result<Vec<_>>>()?;
+    if !interpreter.log_filter.borrow().enabled(level, &target) {
+        return Ok(ValueType::Void);
+    }
+    let mut words = vec![];
+    for (index, argument) in arguments.iter().enumerate() {
+        words.push(match index {
+            0 => format!("{}", Displayed(argument)),
+            _ => format!("{}", argument),
+        });
+    }
+    let record = LogRecord {
+        level,
+        target,
+        message: words.join(" "),
+        time: SystemTime::now(),
+    };
+    match &*interpreter.log_handler.borrow() {
+        Some(handler) => handler(&record),
+        None => {
+            let port = interpreter.current_error_port.borrow().clone();
+            port.write_str(&format!("{}\n", record))?;
+        }
+    }
+    Ok(ValueType::Void)
+}
+
+pub(crate) fn log_library() -> HashMap<String, ValueType> {
+    macro_rules! level_procedure {
+        ($function:ident, $level:expr) => {
+            fn $function(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+                log(interpreter, $level, DEFAULT_TARGET.to_string(), arguments)
+            }
+        };
+    }
+
+    level_procedure!(log_trace, LogLevel::Trace);
+    level_procedure!(log_debug, LogLevel::Debug);
+    level_procedure!(log_info, LogLevel::Info);
+    level_procedure!(log_warn, LogLevel::Warn);
+    level_procedure!(log_error, LogLevel::Error);
+
+    // (log-message level target message irritant ...)
+    fn log_message(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(level), Some(target)) => {
+                let level = expect_level("log-message", level?)?;
+                let target = expect_target("log-message", target?)?;
+                log(interpreter, level, target, arguments)
+            }
+            _ => logic_error!("log-message takes a level, a target and a message"),
+        }
+    }
+
+    fn is_log_enabled(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(level), target, None) => {
+                let level = expect_level("log-enabled?", level?)?;
+                let target = match target {
+                    Some(target) => expect_target("log-enabled?", target?)?,
+                    None => DEFAULT_TARGET.to_string(),
+                };
+                Ok(ValueType::Boolean(
+                    interpreter.log_filter.borrow().enabled(level, &target),
+                ))
+            }
+            _ => logic_error!("log-enabled? takes one or two arguments"),
+        }
+    }
+
+    // (set-log-level! level [target]), #f turns logging off
+    fn set_log_level(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(level), target, None) => {
+                let level = match level? {
+                    ValueType::Boolean(false) => None,
+                    level => Some(expect_level("set-log-level!", level)?),
+                };
+                let target = target
+                    .map(|target| expect_target("set-log-level!", target?))
+                    .transpose()?;
+                interpreter
+                    .log_filter
+                    .borrow_mut()
+                    .set(target.as_deref(), level);
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("set-log-level! takes one or two arguments"),
+        }
+    }
+
+    [
+        interpreter_function_mapping!("log-trace", log_trace),
+        interpreter_function_mapping!("log-debug", log_debug),
+        interpreter_function_mapping!("log-info", log_info),
+        interpreter_function_mapping!("log-warn", log_warn),
+        interpreter_function_mapping!("log-error", log_error),
+        interpreter_function_mapping!("log-message", log_message),
+        interpreter_function_mapping!("log-enabled?", is_log_enabled),
+        interpreter_function_mapping!("set-log-level!", set_log_level),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn filters() {
+    let filter = LogFilter::parse("warn, net=debug ,net.noisy=off,bad=loud");
+    assert!(filter.enabled(LogLevel::Error, "scheme"));
+    assert!(!filter.enabled(LogLevel::Info, "scheme"));
+    assert!(filter.enabled(LogLevel::Debug, "net.http"));
+    assert!(!filter.enabled(LogLevel::Error, "net.noisy"));
+    assert!(!filter.enabled(LogLevel::Info, "bad"));
+    assert_eq!(LogFilter::parse("nonsense"), LogFilter::default());
+}
+
+#[test]
+fn logging() -> Result<()> {
+    let interpreter = Interpreter::new();
+    *interpreter.log_filter.borrow_mut() = LogFilter::default();
+    let records = Rc::new(RefCell::new(vec![]));
+    let sink = records.clone();
+    *interpreter.log_handler.borrow_mut() = Some(Box::new(move |record: &LogRecord| {
+        sink.borrow_mut().push(format!(
+            "{} {} {}",
+            record.level, record.target, record.message
+        ))
+    }));
+    interpreter.eval(
+        "(log-info \"started\" 'x \"y\")
+         (log-debug \"hidden\")
+         (set-log-level! 'debug 'db)
+         (log-message 'debug 'db.query \"select\" 1)
+         (log-message 'trace 'db \"hidden\")
+         (set-log-level! #f)
+         (log-error \"hidden\")"
+            .chars(),
+    )?;
+    assert_eq!(
+        *records.borrow(),
+        vec![
+            "INFO scheme started x \"y\"".to_string(),
+            "DEBUG db.query select 1".to_string()
+        ]
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            interpreter
+                .eval("(list (log-enabled? 'warn) (log-enabled? 'info 'db))".chars())?
+                .unwrap()
+        ),
+        "(#f #t)"
+    );
+    // the default handler writes to the current error port
+    *interpreter.log_handler.borrow_mut() = None;
+    interpreter.eval(
+        "(define port (open-output-string))
+         (set-log-level! 'info)"
+            .chars(),
+    )?;
+    let port = match interpreter.eval("port".chars())?.unwrap() {
+        ValueType::Port(port) => port,
+        _ => unreachable!(),
+    };
+    *interpreter.current_error_port.borrow_mut() = port;
+    let record = LogRecord {
+        level: LogLevel::Warn,
+        target: "scheme".to_string(),
+        message: "low \"disk\"".to_string(),
+        time: UNIX_EPOCH + std::time::Duration::from_secs(86400),
+    };
+    assert_eq!(
+        format!("{}", record),
+        "1970-01-02T00:00:00Z WARN scheme: low \"disk\""
+    );
+    interpreter.eval("(log-warn \"low\" \"disk\")".chars())?;
+    let written = format!(
+        "{}",
+        interpreter
+            .eval("(get-output-string port)".chars())?
+            .unwrap()
+    );
+    assert!(
+        written.ends_with("Z WARN scheme: low \\\"disk\\\"\\n\""),
+        "{}",
+        written
+    );
+    assert_eq!(
+        interpreter.eval("(log-message 'loud 'x \"m\")".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "log-message: unknown log level loud".to_string()
+        })
+    );
+    Ok(())
+}
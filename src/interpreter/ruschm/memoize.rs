@@ -0,0 +1,379 @@
+use crate::interpreter::scheme::base::{expect_index, expect_list};
+use crate::interpreter::srfi::srfi_69::value_hash;
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::rc::Weak;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MemoizeOptions {
+    // the most results kept, the least recently used is dropped for a new one
+    pub capacity: Option<usize>,
+    // whether the cache keeps its keys alive, weak keys are compared by identity with eqv?
+    pub weak_keys: bool,
+    // the number of arguments, which only user procedures tell themselves
+    pub arity: Option<usize>,
+}
+
+// an argument of a cached call, where the objects with an identity are held weakly for weak keys
+enum Key {
+    Strong(ValueType),
+    Pair(Weak<RefCell<Pair>>),
+    Vector(Weak<RefCell<Vec<ValueType>>>),
+    Bytevector(Weak<RefCell<Vec<u8>>>),
+    HashTable(Weak<RefCell<srfi::srfi_69::HashTable>>),
+}
+
+impl Key {
+    fn new(value: &ValueType, weak: bool) -> Self {
+        match value {
+            ValueType::Pair(pair) if weak => Key::Pair(Rc::downgrade(pair)),
+            ValueType::Vector(vector) if weak => Key::Vector(Rc::downgrade(vector)),
+            ValueType::Bytevector(bytes) if weak => Key::Bytevector(Rc::downgrade(bytes)),
+            ValueType::HashTable(table) if weak => Key::HashTable(Rc::downgrade(table)),
+            value => Key::Strong(value.clone()),
+        }
+    }
+
+    fn alive(&self) -> bool {
+        match self {
+            Key::Strong(_) => true,
+            Key::Pair(pair) => pair.strong_count() > 0,
+            Key::Vector(vector) => vector.strong_count() > 0,
+            Key::Bytevector(bytes) => bytes.strong_count() > 0,
+            Key::HashTable(table) => table.strong_count() > 0,
+        }
+    }
+
+    fn matches(&self, value: &ValueType, weak: bool) -> bool {
+        match (self, value) {
+            (Key::Strong(key), value) if weak => key.eqv(value),
+            (Key::Strong(key), value) => key.equal(value),
+            (Key::Pair(key), ValueType::Pair(value)) => key.as_ptr() == Rc::as_ptr(value),
+            (Key::Vector(key), ValueType::Vector(value)) => key.as_ptr() == Rc::as_ptr(value),
+            (Key::Bytevector(key), ValueType::Bytevector(value)) => {
+                key.as_ptr() == Rc::as_ptr(value)
+            }
+            (Key::HashTable(key), ValueType::HashTable(value)) => key.as_ptr() == Rc::as_ptr(value),
+            _ => false,
+        }
+    }
+}
+
+struct Entry {
+    arguments: Vec<Key>,
+    value: ValueType,
+    // the tick of the last call which used the entry
+    used: u64,
+}
+
+// the results of a procedure by the hash of their arguments
+pub struct Memo {
+    procedure: ValueType,
+    options: MemoizeOptions,
+    entries: HashMap<u64, Vec<Entry>>,
+    size: usize,
+    tick: u64,
+}
+
+impl fmt::Debug for Memo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<memo of {} results>", self.size)
+    }
+}
+
+impl PartialEq for Memo {
+    fn eq(&self, rhs: &Self) -> bool {
+        std::ptr::eq(self, rhs)
+    }
+}
+
+impl Memo {
+    pub fn new(procedure: ValueType, options: MemoizeOptions) -> Self {
+        Self {
+            procedure,
+            options,
+            entries: HashMap::new(),
+            size: 0,
+            tick: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    fn hash(&self, arguments: &[ValueType]) -> u64 {
+        let structural = !self.options.weak_keys;
+        arguments
+            .iter()
+            .fold(arguments.len() as u64, |hash, argument| {
+                hash.wrapping_mul(31)
+                    .wrapping_add(value_hash(argument, structural))
+            })
+    }
+
+    fn lookup(&mut self, code: u64, arguments: &[ValueType]) -> Option<ValueType> {
+        let weak = self.options.weak_keys;
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(&code)?.iter_mut().find(|entry| {
+            entry.arguments.iter().all(Key::alive)
+                && entry.arguments.len() == arguments.len()
+                && entry
+                    .arguments
+                    .iter()
+                    .zip(arguments)
+                    .all(|(key, argument)| key.matches(argument, weak))
+        })?;
+        entry.used = tick;
+        Some(entry.value.clone())
+    }
+
+    fn remove_where(&mut self, mut remove: impl FnMut(&Entry) -> bool) {
+        let mut removed = 0;
+        self.entries.retain(|_, bucket| {
+            let before = bucket.len();
+            bucket.retain(|entry| !remove(entry));
+            removed += before - bucket.len();
+            !bucket.is_empty()
+        });
+        self.size -= removed;
+    }
+
+    fn insert(&mut self, code: u64, arguments: &[ValueType], value: ValueType) {
+        let weak = self.options.weak_keys;
+        if weak {
+            self.remove_where(|entry| !entry.arguments.iter().all(Key::alive));
+        }
+        if let Some(capacity) = self.options.capacity {
+            if self.size >= capacity {
+                let oldest = self
+                    .entries
+                    .values()
+                    .flatten()
+                    .map(|entry| entry.used)
+                    .min();
+                self.remove_where(|entry| Some(entry.used) == oldest);
+            }
+        }
+        self.entries.entry(code).or_default().push(Entry {
+            arguments: arguments
+                .iter()
+                .map(|argument| Key::new(argument, weak))
+                .collect(),
+            value,
+            used: self.tick,
+        });
+        self.size += 1;
+    }
+
+    // calls the procedure without the memo borrowed, so it may call itself through the memo
+    pub fn call(
+        memo: &RefCell<Memo>,
+        interpreter: &Interpreter,
+        arguments: Vec<ValueType>,
+    ) -> Result<ValueType> {
+        let code = memo.borrow().hash(&arguments);
+        if let Some(value) = memo.borrow_mut().lookup(code, &arguments) {
+            return Ok(value);
+        }
+        let procedure = memo.borrow().procedure.clone();
+        let value = interpreter.call_procedure(&procedure, arguments.clone())?;
+        memo.borrow_mut().insert(code, &arguments, value.clone());
+        Ok(value)
+    }
+}
+
+// the cached procedure native code hands to scheme, which takes as many arguments as the original
+pub fn memoize(
+    interpreter: &Interpreter,
+    procedure: ValueType,
+    options: MemoizeOptions,
+) -> Result<ValueType> {
+    fn next(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match arguments.next() {
+            Some(Ok(ValueType::Memo(memo))) => {
+                Memo::call(&memo, interpreter, arguments.collect::<Result<_>>()?)
+            }
+            _ => logic_error!("memoize: broken memo state"),
+        }
+    }
+    let arity = match (&procedure, options.arity) {
+        (_, Some(arity)) => arity,
+        (ValueType::Procedure(defined), None) => match defined.scheme_procedure() {
+            Some(SchemeProcedure(formals, ..)) => formals.len(),
+            None => logic_error!("memoize: the arity of {} must be given", procedure),
+        },
+        (other, _) => logic_error!("memoize: expect a procedure, got {}", other),
+    };
+    if options.capacity == Some(0) {
+        logic_error!("memoize: expect a positive size");
+    }
+    Ok(native_closure(
+        interpreter,
+        vec![ValueType::Memo(Rc::new(RefCell::new(Memo::new(
+            procedure, options,
+        ))))],
+        BuildinProcedure("memoized", next),
+        hidden_formals(arity),
+        None,
+    ))
+}
+
+// the options alist of memoize, with the keys size, weak and arity
+fn expect_options(value: ValueType) -> Result<MemoizeOptions> {
+    let mut options = MemoizeOptions::default();
+    for option in expect_list("memoize", value)? {
+        let (key, value) = match &option {
+            ValueType::Pair(pair) => {
+                let pair = pair.borrow();
+                (pair.car.clone(), pair.cdr.clone())
+            }
+            other => logic_error!("memoize: expect an option pair, got {}", other),
+        };
+        match (&key, value) {
+            (ValueType::Symbol(key), ValueType::Boolean(false)) if &**key == "size" => {
+                options.capacity = None
+            }
+            (ValueType::Symbol(key), value) if &**key == "size" => {
+                options.capacity = Some(expect_index("memoize", value)?)
+            }
+            (ValueType::Symbol(key), value) if &**key == "weak" => {
+                options.weak_keys = value != ValueType::Boolean(false)
+            }
+            (ValueType::Symbol(key), value) if &**key == "arity" => {
+                options.arity = Some(expect_index("memoize", value)?)
+            }
+            _ => logic_error!("memoize: unknown option {}", key),
+        }
+    }
+    Ok(options)
+}
+
+pub(crate) fn memoize_library() -> HashMap<String, ValueType> {
+    // (memoize procedure [options])
+    fn memoize_procedure(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(procedure), options, None) => {
+                let options = match options {
+                    Some(options) => expect_options(options?)?,
+                    None => MemoizeOptions::default(),
+                };
+                memoize(interpreter, procedure?, options)
+            }
+            _ => logic_error!("memoize takes one or two arguments"),
+        }
+    }
+
+    [interpreter_function_mapping!("memoize", memoize_procedure)]
+        .iter()
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn memoization() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            // without the cache this takes millions of calls
+            (
+                "(define fib (memoize (lambda (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))))
+             (fib 30)",
+                "832040",
+            ),
+            // the log shows the calls of the procedure
+            (
+                "(define log (open-output-string))
+             (define (logged x) (write x log) (* x x))
+             (define square (memoize logged '((size . 2))))
+             (list (square 1) (square 2) (square 1) (square 3) (square 1) (square 2)
+                   (get-output-string log))",
+                "(1 4 1 9 1 4 \"1232\")",
+            ),
+            (
+                "(define add (memoize + '((arity . 2))))
+             (list (add 1 2) (add 1 2) ((memoize (lambda (l) (length l))) '(a b)))",
+                "(3 3 2)",
+            ),
+        ],
+    )?;
+    for (source, message) in [
+        (
+            "(memoize car)",
+            "memoize: the arity of <build-in procedure (car)> must be given",
+        ),
+        (
+            "(memoize car '((size . 0) (arity . 1)))",
+            "memoize: expect a positive size",
+        ),
+        (
+            "(memoize car '((colour . red)))",
+            "memoize: unknown option colour",
+        ),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(source.chars()),
+            Err(Error {
+                category: ErrorType::Logic,
+                message: message.to_string()
+            }),
+            "{}",
+            source
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn weak_keys() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let procedure = interpreter
+        .eval(
+            "(define log (open-output-string)) (lambda (l) (write-char #\\x log) (length l))"
+                .chars(),
+        )?
+        .unwrap();
+    let memo = RefCell::new(Memo::new(
+        procedure,
+        MemoizeOptions {
+            weak_keys: true,
+            ..MemoizeOptions::default()
+        },
+    ));
+    let list = || ValueType::list(vec![ValueType::Boolean(true)].into_iter());
+    let first = list();
+    Memo::call(&memo, &interpreter, vec![first.clone()])?;
+    Memo::call(&memo, &interpreter, vec![first.clone()])?;
+    // an equal list is another key, as weak keys are compared by identity
+    let second = list();
+    Memo::call(&memo, &interpreter, vec![second.clone()])?;
+    assert_eq!(memo.borrow().len(), 2);
+    // the entry of a dropped key is removed when the next result is kept
+    drop(first);
+    Memo::call(&memo, &interpreter, vec![list()])?;
+    assert_eq!(memo.borrow().len(), 2);
+    Memo::call(&memo, &interpreter, vec![second])?;
+    assert_eq!(
+        format!(
+            "{}",
+            interpreter
+                .eval("(get-output-string log)".chars())?
+                .unwrap()
+        ),
+        "\"xxx\""
+    );
+    Ok(())
+}
@@ -0,0 +1,15 @@
+pub mod digest;
+pub mod display;
+pub mod encoding;
+pub mod http;
+pub mod image;
+pub mod json;
+pub mod log;
+pub mod memoize;
+pub mod parallel;
+pub mod path;
+pub mod process;
+pub mod regexp;
+pub mod socket;
+pub mod statistics;
+pub mod trace;
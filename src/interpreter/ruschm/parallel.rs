@@ -0,0 +1,281 @@
+use crate::interpreter::ruschm::image::{decode, encode};
+use crate::interpreter::scheme::base::expect_list;
+use crate::interpreter::scheme::process_context::EnvironmentVariables;
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// values are not shared between threads: the callback, its arguments and the global variables
+// are copied to each worker as an image, which an interpreter of its own loads, and the results
+// are copied back the same way
+// so mutating shared state in a callback changes a copy, which neither the caller nor the other
+// workers see, and the identity of objects is not kept between the caller and the workers;
+// only the external effects of callbacks happen, in no particular order, output going to the
+// standard ports of the process; globals which cannot be copied, as ports, are not defined in
+// the workers
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// the stack of a worker, callbacks recursing deeper than it allows failing with an error
+const WORKER_STACK_SIZE: usize = 64 << 20;
+
+// the worker threads of an interpreter thread, as many as its parallelism
+struct Pool {
+    threads: usize,
+    jobs: Sender<Job>,
+}
+
+impl Pool {
+    fn new(threads: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            // workers exit when the pool is dropped, which closes the channel
+            let worker = move || {
+                set_thread_stack_size(WORKER_STACK_SIZE);
+                loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        Ok(job) => {
+                            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                        }
+                        Err(_) => return,
+                    }
+                }
+            };
+            thread::Builder::new()
+                .name("ruschm-worker".to_string())
+                .stack_size(WORKER_STACK_SIZE)
+                .spawn(worker)
+                .expect("cannot start a worker thread");
+        }
+        Self { threads, jobs }
+    }
+}
+
+thread_local! {
+    static POOL: RefCell<Option<Pool>> = const { RefCell::new(None) };
+}
+
+// the number of worker threads when the host does not set the interpreter's parallelism
+pub fn default_parallelism() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+// what a worker interpreter is allowed, as the one calling it is
+struct Settings {
+    max_call_depth: usize,
+    engine: vm::Engine,
+    optimization_level: u8,
+    allow_subprocesses: bool,
+    allow_network: bool,
+    environment_variables: EnvironmentVariables,
+    command_line: Vec<String>,
+}
+
+impl Settings {
+    fn interpreter(&self) -> Interpreter {
+        let it = Interpreter::new();
+        it.max_call_depth.set(self.max_call_depth);
+        it.engine.set(self.engine);
+        it.optimization_level.set(self.optimization_level);
+        it.allow_subprocesses.set(self.allow_subprocesses);
+        it.allow_network.set(self.allow_network);
+        *it.environment_variables.borrow_mut() = self.environment_variables.clone();
+        *it.command_line.borrow_mut() = self.command_line.clone();
+        it
+    }
+}
+
+// the image of a call: the globals, the procedure, then the arguments of each element
+struct Call {
+    image: Vec<u8>,
+    globals: usize,
+    settings: Settings,
+    // whether the results are sent back
+    results: bool,
+}
+
+// applies the procedure to the elements of a chunk in a worker interpreter
+fn work(call: &Call, chunk: std::ops::Range<usize>) -> std::result::Result<Vec<u8>, String> {
+    let it = call.settings.interpreter();
+    let top = it.global_environment().clone();
+    let mut variables = decode(&top, &call.image)
+        .ok_or_else(|| "invalid image".to_string())?
+        .into_iter();
+    for (name, value) in variables.by_ref().take(call.globals) {
+        top.define(name, value);
+    }
+    let procedure = variables.next().map(|(_, procedure)| procedure);
+    let procedure = procedure.ok_or_else(|| "invalid image".to_string())?;
+    let mut results = vec![];
+    for (_, arguments) in variables.skip(chunk.start).take(chunk.len()) {
+        let arguments = arguments.list_to_vec().unwrap_or_default();
+        let result = it
+            .apply_procedure(&procedure, Box::new(arguments.into_iter().map(Ok)))
+            .map_err(|e| e.message)?;
+        if call.results {
+            results.push((String::new(), result));
+        }
+    }
+    encode(&top, &results).map_err(|e| format!("{} for the caller", e.message))
+}
+
+// the results of applying the procedure to the elements of the lists, stopping at the end of the
+// shortest, in order
+fn parallel_apply(
+    name: &str,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+    results: bool,
+) -> Result<Vec<ValueType>> {
+    let procedure = match arguments.next() {
+        Some(procedure) => procedure?,
+        None => logic_error!("{} takes a procedure and lists", name),
+    };
+    if !matches!(procedure, ValueType::Procedure(_)) {
+        logic_error!("{}: expect a procedure, got {}", name, procedure)
+    }
+    let lists = arguments
+        .map(|list| expect_list(name, list?))
+        .collect::<Result<Vec<_>>>()?;
+    if lists.is_empty() {
+        logic_error!("{} takes a procedure and lists", name)
+    }
+    let length = lists.iter().map(Vec::len).min().unwrap_or(0);
+    if length == 0 {
+        return Ok(vec![]);
+    }
+    let threads = interpreter.parallelism.get().max(1);
+    let top = interpreter.global_environment();
+    let mut variables = interpreter.defined_variables(true);
+    let globals = variables.len();
+    variables.push((String::new(), procedure));
+    for i in 0..length {
+        let elements = lists.iter().map(|list| list[i].clone());
+        variables.push((String::new(), ValueType::list(elements)));
+    }
+    let image = encode(top, &variables).map_err(|e| Error {
+        message: format!("{}: {} for a worker thread", name, e.message),
+        ..e
+    })?;
+    let call = Arc::new(Call {
+        image,
+        globals,
+        settings: Settings {
+            max_call_depth: interpreter.max_call_depth.get(),
+            engine: interpreter.engine.get(),
+            optimization_level: interpreter.optimization_level.get(),
+            allow_subprocesses: interpreter.allow_subprocesses.get(),
+            allow_network: interpreter.allow_network.get(),
+            environment_variables: interpreter.environment_variables.borrow().clone(),
+            command_line: interpreter.command_line.borrow().clone(),
+        },
+        results,
+    });
+    let size = length.div_ceil(threads);
+    let chunks = length.div_ceil(size);
+    let (sender, receiver) = mpsc::channel();
+    POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.as_ref().map(|pool| pool.threads) != Some(threads) {
+            *pool = Some(Pool::new(threads));
+        }
+        let jobs = &pool.as_ref().unwrap().jobs;
+        for chunk in 0..chunks {
+            let (call, sender) = (call.clone(), sender.clone());
+            let range = chunk * size..(chunk * size + size).min(length);
+            let _ = jobs.send(Box::new(move || {
+                let _ = sender.send((chunk, work(&call, range)));
+            }));
+        }
+    });
+    drop(sender);
+    let mut images = vec![None; chunks];
+    for (chunk, image) in receiver.iter() {
+        images[chunk] = Some(image);
+    }
+    let mut values = vec![];
+    for image in images {
+        match image {
+            Some(Ok(image)) => match decode(top, &image) {
+                Some(results) => values.extend(results.into_iter().map(|(_, value)| value)),
+                None => logic_error!("{}: invalid results from a worker thread", name),
+            },
+            Some(Err(message)) => logic_error!("{}: {}", name, message),
+            None => logic_error!("{}: a worker thread failed", name),
+        }
+    }
+    Ok(values)
+}
+
+pub(crate) fn parallel_library() -> HashMap<String, ValueType> {
+    // (parallel-map procedure list1 list2 ...)
+    fn parallel_map(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let values = parallel_apply("parallel-map", arguments, interpreter, true)?;
+        Ok(ValueType::list(values.into_iter()))
+    }
+
+    // (parallel-for-each procedure list1 list2 ...)
+    fn parallel_for_each(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        parallel_apply("parallel-for-each", arguments, interpreter, false)?;
+        Ok(ValueType::Void)
+    }
+
+    [
+        interpreter_function_mapping!("parallel-map", parallel_map),
+        interpreter_function_mapping!("parallel-for-each", parallel_for_each),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn parallel_mapping() -> Result<()> {
+    for engine in [vm::Engine::Bytecode, vm::Engine::TreeWalker] {
+        let it = Interpreter::new();
+        it.engine.set(engine);
+        it.parallelism.set(3);
+        let result = |source: &str| {
+            it.eval_source(source)
+                .map(|value| value.map(|value| value.to_string()))
+                .map_err(|(e, _)| e.message)
+        };
+        assert_eq!(
+            result(
+                "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+                 (define offset 100)
+                 (parallel-map (lambda (n m) (+ (fib n) m offset)) '(1 2 3 4 5 6 7) '(1 2 3 4 5))"
+            ),
+            Ok(Some("(102 103 105 107 110)".to_string()))
+        );
+        // the callbacks mutate copies
+        assert_eq!(
+            result(
+                "(define counter (vector 0))
+                 (define v (vector 0))
+                 (parallel-for-each (lambda (n) (vector-set! counter 0 n) (vector-set! v 0 n))
+                   '(1 2 3))
+                 (list (vector-ref counter 0) (vector-ref v 0)
+                   (parallel-map (lambda (v) (vector-set! v 0 1) v) (list v)) v)"
+            ),
+            Ok(Some("(0 0 (#(1)) #(0))".to_string()))
+        );
+        assert_eq!(result("(parallel-map car '())"), Ok(Some("()".to_string())));
+        assert_eq!(
+            result("(parallel-map (lambda (n) (car n)) '((1) 2))"),
+            Err("parallel-map: car: expect a pair, got 2".to_string())
+        );
+        assert!(result("(parallel-map (lambda (n) (current-output-port)) '(1))").is_err());
+        assert!(result("(parallel-map car 1)").is_err());
+    }
+    Ok(())
+}
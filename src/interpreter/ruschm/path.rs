@@ -0,0 +1,154 @@
+use crate::interpreter::port::file_error;
+use crate::interpreter::scheme::base::expect_string;
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+fn path_string(path: &Path) -> ValueType {
+    ValueType::String(path.to_string_lossy().into_owned())
+}
+
+// a missing component is #f
+fn component(component: Option<&OsStr>) -> ValueType {
+    match component {
+        Some(component) => ValueType::String(component.to_string_lossy().into_owned()),
+        None => ValueType::Boolean(false),
+    }
+}
+
+pub(crate) fn path_library() -> HashMap<String, ValueType> {
+    // an absolute component replaces the path joined so far
+    fn path_join(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let mut path = PathBuf::new();
+        for component in arguments {
+            path.push(expect_string("path-join", component?)?);
+        }
+        Ok(path_string(&path))
+    }
+
+    fn path_directory(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("path-directory", path?)?;
+                Ok(match Path::new(&path).parent() {
+                    Some(parent) => path_string(parent),
+                    None => ValueType::Boolean(false),
+                })
+            }
+            _ => logic_error!("path-directory takes exactly one argument"),
+        }
+    }
+
+    fn path_filename(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("path-filename", path?)?;
+                Ok(component(Path::new(&path).file_name()))
+            }
+            _ => logic_error!("path-filename takes exactly one argument"),
+        }
+    }
+
+    fn path_extension(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("path-extension", path?)?;
+                Ok(component(Path::new(&path).extension()))
+            }
+            _ => logic_error!("path-extension takes exactly one argument"),
+        }
+    }
+
+    fn is_path_absolute(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => Ok(ValueType::Boolean(
+                Path::new(&expect_string("path-absolute?", path?)?).is_absolute(),
+            )),
+            _ => logic_error!("path-absolute? takes exactly one argument"),
+        }
+    }
+
+    // relative paths are resolved against the working directory, without touching the file
+    fn path_absolute(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("path-absolute", path?)?;
+                let directory =
+                    std::env::current_dir().map_err(|e| file_error("resolve", &path, e))?;
+                Ok(path_string(&directory.join(&path)))
+            }
+            _ => logic_error!("path-absolute takes exactly one argument"),
+        }
+    }
+
+    // symbolic links and . or .. components are resolved, so the file must exist
+    fn path_canonical(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("path-canonical", path?)?;
+                match std::fs::canonicalize(&path) {
+                    Ok(canonical) => Ok(path_string(&canonical)),
+                    Err(e) => Err(file_error("resolve", &path, e)),
+                }
+            }
+            _ => logic_error!("path-canonical takes exactly one argument"),
+        }
+    }
+
+    [
+        function_mapping!("path-join", path_join),
+        function_mapping!("path-directory", path_directory),
+        function_mapping!("path-filename", path_filename),
+        function_mapping!("path-extension", path_extension),
+        function_mapping!("path-absolute?", is_path_absolute),
+        function_mapping!("path-absolute", path_absolute),
+        function_mapping!("path-canonical", path_canonical),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn paths() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(path-join \"a\" \"b\" \"c.scm\")", "\"a/b/c.scm\""),
+            ("(path-join \"a\" \"/b\")", "\"/b\""),
+            ("(path-directory \"/usr/lib/file.tar.gz\")", "\"/usr/lib\""),
+            ("(path-directory \"/\")", "#f"),
+            (
+                "(path-filename \"/usr/lib/file.tar.gz\")",
+                "\"file.tar.gz\"",
+            ),
+            ("(path-filename \"/usr/..\")", "#f"),
+            ("(path-extension \"/usr/lib/file.tar.gz\")", "\"gz\""),
+            ("(path-extension \".profile\")", "#f"),
+            ("(path-absolute? \"/usr\")", "#t"),
+            ("(path-absolute? \"usr\")", "#f"),
+            ("(path-absolute? (path-absolute \"usr\"))", "#t"),
+            ("(path-canonical \"/\")", "\"/\""),
+            (
+                "(guard (e ((file-error? e) 'missing)) (path-canonical \"/nonexistent/path\"))",
+                "missing",
+            ),
+        ],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use crate::interpreter::port::file_error;
+use crate::interpreter::scheme::base::{expect_list, expect_string};
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+fn check_permitted(name: &str, interpreter: &Interpreter) -> Result<()> {
+    if !interpreter.allow_subprocesses.get() {
+        logic_error!("{}: running programs is not permitted", name)
+    }
+    Ok(())
+}
+
+// runs the program to completion with its standard streams captured
+fn run(name: &str, program: &str, arguments: &[String], input: Option<String>) -> Result<Output> {
+    let mut child = Command::new(program)
+        .args(arguments)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| file_error("run", program, e))?;
+    // the input is fed from another thread, so a program filling its output cannot block us
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => {
+            Some(thread::spawn(move || stdin.write_all(input.as_bytes())))
+        }
+        _ => None,
+    };
+    let output = child
+        .wait_with_output()
+        .map_err(|e| file_error("run", program, e))?;
+    if let Some(writer) = writer {
+        match writer.join() {
+            Ok(Ok(())) => (),
+            // the program may exit without reading all of its input
+            Ok(Err(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => (),
+            Ok(Err(e)) => return Err(file_error("write to", program, e)),
+            Err(_) => logic_error!("{}: cannot write the input of {}", name, program),
+        }
+    }
+    Ok(output)
+}
+
+// a status of #f means the program was terminated by a signal
+fn exit_status(output: &Output) -> ValueType {
+    match output.status.code() {
+        Some(code) => ValueType::Number(Number::Integer(code as i64)),
+        None => ValueType::Boolean(false),
+    }
+}
+
+pub(crate) fn process_library() -> HashMap<String, ValueType> {
+    // (run-program program (argument ...) [input]) is (status stdout stderr)
+    fn run_program(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_permitted("run-program", interpreter)?;
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(program), Some(program_arguments), input, None) => {
+                let program = expect_string("run-program", program?)?;
+                let program_arguments = expect_list("run-program", program_arguments?)?
+                    .into_iter()
+                    .map(|argument| expect_string("run-program", argument))
+                    .collect::<Result<Vec<_>>>()?;
+                let input = input
+                    .map(|input| expect_string("run-program", input?))
+                    .transpose()?;
+                let output = run("run-program", &program, &program_arguments, input)?;
+                Ok(ValueType::list(
+                    vec![
+                        exit_status(&output),
+                        ValueType::String(String::from_utf8_lossy(&output.stdout).into_owned()),
+                        ValueType::String(String::from_utf8_lossy(&output.stderr).into_owned()),
+                    ]
+                    .into_iter(),
+                ))
+            }
+            _ => logic_error!("run-program takes two or three arguments"),
+        }
+    }
+
+    // the standard output of a program, as scsh's run/string
+    fn run_string(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_permitted("run/string", interpreter)?;
+        let mut arguments = arguments
+            .map(|argument| expect_string("run/string", argument?))
+            .collect::<Result<Vec<_>>>()?;
+        if arguments.is_empty() {
+            logic_error!("run/string takes at least one argument")
+        }
+        let program = arguments.remove(0);
+        let output = run("run/string", &program, &arguments, None)?;
+        Ok(ValueType::String(
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        ))
+    }
+
+    fn run_status(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_permitted("run/status", interpreter)?;
+        let mut arguments = arguments
+            .map(|argument| expect_string("run/status", argument?))
+            .collect::<Result<Vec<_>>>()?;
+        if arguments.is_empty() {
+            logic_error!("run/status takes at least one argument")
+        }
+        let program = arguments.remove(0);
+        Ok(exit_status(&run("run/status", &program, &arguments, None)?))
+    }
+
+    [
+        interpreter_function_mapping!("run-program", run_program),
+        interpreter_function_mapping!("run/string", run_string),
+        interpreter_function_mapping!("run/status", run_status),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn subprocesses() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_eq!(
+        interpreter.eval("(run/string \"echo\")".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "run/string: running programs is not permitted".to_string()
+        })
+    );
+    interpreter.allow_subprocesses.set(true);
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(run/string \"echo\" \"hello\" \"world\")",
+                "\"hello world\\n\"",
+            ),
+            ("(run/status \"sh\" \"-c\" \"exit 3\")", "3"),
+            (
+                "(run-program \"cat\" '() \"piped input\")",
+                "(0 \"piped input\" \"\")",
+            ),
+            (
+                "(run-program \"sh\" (list \"-c\" \"echo out; echo err >&2; exit 1\"))",
+                "(1 \"out\\n\" \"err\\n\")",
+            ),
+            (
+                "(guard (e ((file-error? e) 'missing)) (run/string \"/nonexistent/program\"))",
+                "missing",
+            ),
+        ],
+    )?;
+    Ok(())
+}
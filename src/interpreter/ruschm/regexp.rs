@@ -0,0 +1,1001 @@
+use crate::interpreter::scheme::base::{expect_index, expect_range, expect_string};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// the longest counted repetition, as each one is expanded into the program
+const REPETITION_LIMIT: usize = 1000;
+
+// \d matches ASCII digits, while \w and \s follow the Unicode alphanumeric and whitespace properties
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => c.is_alphanumeric() || c == '_',
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    // the bool negates the class, as in \D
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+impl Class {
+    fn perl(perl: Perl, negated: bool) -> Self {
+        Self {
+            negated: false,
+            items: vec![ClassItem::Perl(perl, negated)],
+        }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match item {
+            ClassItem::Range(low, high) => (*low..=*high).contains(&c),
+            ClassItem::Perl(perl, negated) => perl.matches(c) != *negated,
+        }) != self.negated
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    // any character but a newline
+    Any,
+    Class(Class),
+    Assertion(Assertion),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+struct PatternParser<'a> {
+    pattern: &'a str,
+    chars: Vec<char>,
+    index: usize,
+    groups: usize,
+}
+
+impl PatternParser<'_> {
+    fn error<T>(&self, message: &str) -> Result<T> {
+        logic_error!(
+            "regexp: {} at {} in {:?}",
+            message,
+            self.index,
+            self.pattern
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.index += 1;
+        c
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    fn alternation(&mut self) -> Result<Node> {
+        let mut alternatives = vec![self.concatenation()?];
+        while self.eat('|') {
+            alternatives.push(self.concatenation()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Node::Alternate(alternatives),
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Node> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repetition(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.index;
+        while let Some('0'..='9') = self.peek() {
+            self.index += 1;
+        }
+        self.chars[start..self.index]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn repetition(&mut self, node: Node) -> Result<Node> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.index += 1;
+                let min = match self.number() {
+                    Some(min) => min,
+                    None => return self.error("expect a repetition count"),
+                };
+                let max = match self.eat(',') {
+                    true if self.peek() == Some('}') => None,
+                    true => match self.number() {
+                        Some(max) => Some(max),
+                        None => return self.error("expect a repetition count"),
+                    },
+                    false => Some(min),
+                };
+                if self.peek() != Some('}') {
+                    return self.error("expect '}'");
+                }
+                if max.is_some_and(|max| max < min) || max.unwrap_or(min) > REPETITION_LIMIT {
+                    return self.error("invalid repetition count");
+                }
+                (min, max)
+            }
+            _ => return Ok(node),
+        };
+        self.index += 1;
+        if let Node::Assertion(_) | Node::Empty = node {
+            return self.error("nothing to repeat");
+        }
+        let greedy = !self.eat('?');
+        if let Some('*') | Some('+') | Some('?') | Some('{') = self.peek() {
+            return self.error("nothing to repeat");
+        }
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        match self.next() {
+            Some('(') => {
+                let capture = match self.peek() == Some('?') {
+                    true => {
+                        self.index += 1;
+                        if !self.eat(':') {
+                            return self.error("unknown group kind");
+                        }
+                        None
+                    }
+                    false => {
+                        self.groups += 1;
+                        Some(self.groups)
+                    }
+                };
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    return self.error("expect ')'");
+                }
+                Ok(Node::Group(Box::new(node), capture))
+            }
+            Some('[') => self.class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Assertion(Assertion::Start)),
+            Some('$') => Ok(Node::Assertion(Assertion::End)),
+            Some('\\') => match self.next() {
+                Some('b') => Ok(Node::Assertion(Assertion::WordBoundary)),
+                Some('B') => Ok(Node::Assertion(Assertion::NotWordBoundary)),
+                _ => {
+                    self.index -= 1;
+                    match self.escape()? {
+                        ClassItem::Range(c, _) => Ok(Node::Char(c)),
+                        ClassItem::Perl(perl, negated) => {
+                            Ok(Node::Class(Class::perl(perl, negated)))
+                        }
+                    }
+                }
+            },
+            Some('*') | Some('+') | Some('?') | Some('{') => {
+                self.index -= 1;
+                self.error("nothing to repeat")
+            }
+            Some(c) => Ok(Node::Char(c)),
+            None => self.error("unexpected end"),
+        }
+    }
+
+    // the character after a backslash, as a single character range or a Perl class
+    fn escape(&mut self) -> Result<ClassItem> {
+        let single = |c| Ok(ClassItem::Range(c, c));
+        match self.next() {
+            Some('d') => Ok(ClassItem::Perl(Perl::Digit, false)),
+            Some('D') => Ok(ClassItem::Perl(Perl::Digit, true)),
+            Some('w') => Ok(ClassItem::Perl(Perl::Word, false)),
+            Some('W') => Ok(ClassItem::Perl(Perl::Word, true)),
+            Some('s') => Ok(ClassItem::Perl(Perl::Space, false)),
+            Some('S') => Ok(ClassItem::Perl(Perl::Space, true)),
+            Some('n') => single('\n'),
+            Some('t') => single('\t'),
+            Some('r') => single('\r'),
+            Some('f') => single('\u{c}'),
+            Some('v') => single('\u{b}'),
+            Some(c) if !c.is_alphanumeric() => single(c),
+            Some(_) => {
+                self.index -= 1;
+                self.error("unknown escape")
+            }
+            None => self.error("unexpected end"),
+        }
+    }
+
+    // the rest of a bracketed class, where a leading ']' is literal
+    fn class(&mut self) -> Result<Node> {
+        let negated = self.eat('^');
+        let mut items = vec![];
+        loop {
+            let low = match self.next() {
+                Some(']') if !items.is_empty() => break,
+                Some('\\') => match self.escape()? {
+                    ClassItem::Range(c, _) => c,
+                    perl => {
+                        items.push(perl);
+                        continue;
+                    }
+                },
+                Some(c) => c,
+                None => return self.error("expect ']'"),
+            };
+            let high = match (self.peek(), self.chars.get(self.index + 1)) {
+                (Some('-'), Some(&high)) if high != ']' => {
+                    self.index += 2;
+                    match high {
+                        '\\' => match self.escape()? {
+                            ClassItem::Range(c, _) => c,
+                            _ => return self.error("invalid class range"),
+                        },
+                        high => high,
+                    }
+                }
+                _ => low,
+            };
+            if high < low {
+                return self.error("invalid class range");
+            }
+            items.push(ClassItem::Range(low, high));
+        }
+        Ok(Node::Class(Class { negated, items }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {
+    Char(char),
+    Any,
+    Class(Class),
+    Assertion(Assertion),
+    // records the position in a capture slot, 2n and 2n + 1 for the start and end of group n
+    Save(usize),
+    // tries the first branch before the second
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+fn compile(node: &Node, program: &mut Vec<Instruction>) {
+    match node {
+        Node::Empty => (),
+        Node::Char(c) => program.push(Instruction::Char(*c)),
+        Node::Any => program.push(Instruction::Any),
+        Node::Class(class) => program.push(Instruction::Class(class.clone())),
+        Node::Assertion(assertion) => program.push(Instruction::Assertion(*assertion)),
+        Node::Group(node, None) => compile(node, program),
+        Node::Group(node, Some(group)) => {
+            program.push(Instruction::Save(group * 2));
+            compile(node, program);
+            program.push(Instruction::Save(group * 2 + 1));
+        }
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, program)),
+        Node::Alternate(alternatives) => {
+            let mut jumps = vec![];
+            for (index, alternative) in alternatives.iter().enumerate() {
+                let split = program.len();
+                if index + 1 < alternatives.len() {
+                    program.push(Instruction::Split(split + 1, 0));
+                }
+                compile(alternative, program);
+                if index + 1 < alternatives.len() {
+                    jumps.push(program.len());
+                    program.push(Instruction::Jump(0));
+                    program[split] = Instruction::Split(split + 1, program.len());
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Instruction::Jump(end);
+            }
+        }
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            let split = |body, out| match greedy {
+                true => Instruction::Split(body, out),
+                false => Instruction::Split(out, body),
+            };
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    let start = program.len();
+                    program.push(Instruction::Jump(0));
+                    compile(node, program);
+                    program.push(Instruction::Jump(start));
+                    program[start] = split(start + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Instruction::Jump(0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for start in splits {
+                        program[start] = split(start + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regexp {
+    pattern: String,
+    ignore_case: bool,
+    program: Vec<Instruction>,
+    // the number of groups, counting the whole match as group 0
+    groups: usize,
+}
+
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+impl Regexp {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Self> {
+        let mut parser = PatternParser {
+            pattern,
+            chars: pattern.chars().collect(),
+            index: 0,
+            groups: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.index < parser.chars.len() {
+            return parser.error("unmatched ')'");
+        }
+        let mut program = vec![Instruction::Save(0)];
+        compile(&node, &mut program);
+        program.push(Instruction::Save(1));
+        program.push(Instruction::Match);
+        Ok(Self {
+            pattern: pattern.to_string(),
+            ignore_case,
+            program,
+            groups: parser.groups + 1,
+        })
+    }
+
+    fn char_matches(&self, instruction: &Instruction, c: char) -> bool {
+        match instruction {
+            Instruction::Char(expected) if self.ignore_case => fold_case(*expected) == fold_case(c),
+            Instruction::Char(expected) => *expected == c,
+            Instruction::Any => c != '\n',
+            Instruction::Class(class) if self.ignore_case => {
+                class.contains(c)
+                    || class.contains(fold_case(c))
+                    || c.to_uppercase().any(|c| class.contains(c))
+            }
+            Instruction::Class(class) => class.contains(c),
+            _ => false,
+        }
+    }
+
+    // the assertions see the range searched as the whole text
+    fn assertion_holds(
+        assertion: Assertion,
+        text: &[char],
+        (start, end): (usize, usize),
+        position: usize,
+    ) -> bool {
+        let word = |index: usize| Perl::Word.matches(text[index]);
+        let boundary = || {
+            let before = position > start && word(position - 1);
+            let after = position < end && word(position);
+            before != after
+        };
+        match assertion {
+            Assertion::Start => position == start,
+            Assertion::End => position == end,
+            Assertion::WordBoundary => boundary(),
+            Assertion::NotWordBoundary => !boundary(),
+        }
+    }
+
+    // a backtracking search which never visits a state twice, as a state which failed once
+    // fails again however it was reached, so matching takes time linear in the text
+    fn execute(
+        &self,
+        text: &[char],
+        bounds: (usize, usize),
+        starts: impl Iterator<Item = usize>,
+        whole: bool,
+    ) -> Option<Vec<Option<usize>>> {
+        enum Job {
+            Thread(usize, usize),
+            Restore(usize, Option<usize>),
+        }
+        let (start, end) = bounds;
+        let width = end - start + 1;
+        let mut visited = vec![false; self.program.len() * width];
+        let mut slots = vec![None; self.groups * 2];
+        for first in starts {
+            let mut jobs = vec![Job::Thread(0, first)];
+            while let Some(job) = jobs.pop() {
+                let (mut pc, mut position) = match job {
+                    Job::Thread(pc, position) => (pc, position),
+                    Job::Restore(slot, value) => {
+                        slots[slot] = value;
+                        continue;
+                    }
+                };
+                loop {
+                    let state = pc * width + position - start;
+                    if visited[state] {
+                        break;
+                    }
+                    visited[state] = true;
+                    match &self.program[pc] {
+                        Instruction::Assertion(assertion) => {
+                            if !Self::assertion_holds(*assertion, text, bounds, position) {
+                                break;
+                            }
+                            pc += 1;
+                        }
+                        Instruction::Save(slot) => {
+                            jobs.push(Job::Restore(*slot, slots[*slot]));
+                            slots[*slot] = Some(position);
+                            pc += 1;
+                        }
+                        Instruction::Split(first, second) => {
+                            jobs.push(Job::Thread(*second, position));
+                            pc = *first;
+                        }
+                        Instruction::Jump(target) => pc = *target,
+                        Instruction::Match => {
+                            if whole && position != end {
+                                break;
+                            }
+                            return Some(slots);
+                        }
+                        instruction => {
+                            if position == end || !self.char_matches(instruction, text[position]) {
+                                break;
+                            }
+                            pc += 1;
+                            position += 1;
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn found(&self, text: &Rc<[char]>, slots: Option<Vec<Option<usize>>>) -> Option<RegexpMatch> {
+        slots.map(|slots| RegexpMatch {
+            text: text.clone(),
+            positions: slots
+                .chunks(2)
+                .map(|slot| match slot {
+                    [Some(start), Some(end)] => Some((*start, *end)),
+                    _ => None,
+                })
+                .collect(),
+        })
+    }
+
+    // a match of the whole range
+    pub fn matches(&self, text: &Rc<[char]>, start: usize, end: usize) -> Option<RegexpMatch> {
+        let slots = self.execute(text, (start, end), std::iter::once(start), true);
+        self.found(text, slots)
+    }
+
+    // the leftmost match in the range, preferring earlier alternatives and greedy repetitions
+    pub fn search(&self, text: &Rc<[char]>, start: usize, end: usize) -> Option<RegexpMatch> {
+        let slots = self.execute(text, (start, end), start..=end, false);
+        self.found(text, slots)
+    }
+
+    // the matches which do not overlap, where an empty match is not tried again after itself
+    fn search_all(&self, text: &Rc<[char]>) -> Vec<RegexpMatch> {
+        let mut matches = vec![];
+        let mut position = 0;
+        while position <= text.len() {
+            let slots = self.execute(text, (0, text.len()), position..=text.len(), false);
+            let found = match self.found(text, slots) {
+                Some(found) => found,
+                None => break,
+            };
+            let (start, end) = found.positions[0].unwrap();
+            position = if start == end { end + 1 } else { end };
+            matches.push(found);
+        }
+        matches
+    }
+}
+
+impl fmt::Display for Regexp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<regexp {}>", ValueType::String(self.pattern.clone()))
+    }
+}
+
+// the text searched and the character positions of each group, None for groups which did not match
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexpMatch {
+    text: Rc<[char]>,
+    positions: Vec<Option<(usize, usize)>>,
+}
+
+impl RegexpMatch {
+    fn submatch(&self, group: usize) -> Option<String> {
+        self.positions[group].map(|(start, end)| self.text[start..end].iter().collect())
+    }
+}
+
+fn expect_regexp(name: &str, value: ValueType) -> Result<Rc<Regexp>> {
+    match value {
+        ValueType::Regexp(regexp) => Ok(regexp),
+        // a pattern string is compiled for a single use
+        ValueType::String(pattern) => Ok(Rc::new(Regexp::new(&pattern, false)?)),
+        other => logic_error!(
+            "{}: expect a regexp or a pattern string, got {}",
+            name,
+            other
+        ),
+    }
+}
+
+fn expect_match(name: &str, value: ValueType) -> Result<Rc<RegexpMatch>> {
+    match value {
+        ValueType::RegexpMatch(found) => Ok(found),
+        other => logic_error!("{}: expect a regexp match, got {}", name, other),
+    }
+}
+
+fn expect_group(
+    name: &str,
+    found: &RegexpMatch,
+    group: Option<Result<ValueType>>,
+) -> Result<usize> {
+    let group = match group {
+        Some(group) => expect_index(name, group?)?,
+        None => 0,
+    };
+    if group >= found.positions.len() {
+        logic_error!(
+            "{}: no group {} in a match of {} groups",
+            name,
+            group,
+            found.positions.len()
+        );
+    }
+    Ok(group)
+}
+
+// $n is replaced by group n, and $$ by a dollar sign
+fn expand_replacement(replacement: &str, found: &RegexpMatch) -> Result<String> {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'$') {
+            chars.next();
+            expanded.push('$');
+            continue;
+        }
+        let mut digits = String::new();
+        while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+            digits.push(*digit);
+            chars.next();
+        }
+        match digits.parse::<usize>() {
+            Ok(group) if group < found.positions.len() => {
+                expanded.push_str(&found.submatch(group).unwrap_or_default())
+            }
+            Ok(group) => logic_error!("regexp-replace: no group {} in the regexp", group),
+            Err(_) => logic_error!("regexp-replace: expect a group number after $"),
+        }
+    }
+    Ok(expanded)
+}
+
+// the replacement is a string with $n references, or a procedure of the match returning a string
+fn replace(
+    interpreter: &Interpreter,
+    name: &str,
+    mut arguments: Arguments,
+    all: bool,
+) -> Result<ValueType> {
+    let (regexp, text, replacement) = match (
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+    ) {
+        (Some(regexp), Some(text), Some(replacement), None) => (
+            expect_regexp(name, regexp?)?,
+            expect_string(name, text?)?,
+            replacement?,
+        ),
+        _ => logic_error!("{} takes exactly three arguments", name),
+    };
+    let text: Rc<[char]> = text.chars().collect();
+    let matches = match all {
+        true => regexp.search_all(&text),
+        false => regexp.search(&text, 0, text.len()).into_iter().collect(),
+    };
+    let mut replaced = String::new();
+    let mut position = 0;
+    for found in matches {
+        let (start, end) = found.positions[0].unwrap();
+        replaced.extend(&text[position..start]);
+        match &replacement {
+            ValueType::String(replacement) => {
+                replaced.push_str(&expand_replacement(replacement, &found)?)
+            }
+            procedure @ ValueType::Procedure(_) => {
+                match interpreter
+                    .call_procedure(procedure, vec![ValueType::RegexpMatch(Rc::new(found))])?
+                {
+                    ValueType::String(s) => replaced.push_str(&s),
+                    other => logic_error!(
+                        "{}: expect the replacement procedure to return a string, got {}",
+                        name,
+                        other
+                    ),
+                }
+            }
+            other => logic_error!(
+                "{}: expect a replacement string or procedure, got {}",
+                name,
+                other
+            ),
+        }
+        position = end;
+    }
+    replaced.extend(&text[position..]);
+    Ok(ValueType::String(replaced))
+}
+
+pub(crate) fn regexp_library() -> HashMap<String, ValueType> {
+    // (regexp pattern ['ignore-case])
+    fn regexp(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(pattern), flag, None) => {
+                let pattern = expect_string("regexp", pattern?)?;
+                let ignore_case = match flag.transpose()? {
+                    None => false,
+                    Some(ValueType::Symbol(flag)) if &*flag == "ignore-case" => true,
+                    Some(other) => logic_error!("regexp: unknown flag {}", other),
+                };
+                Ok(ValueType::Regexp(Rc::new(Regexp::new(
+                    &pattern,
+                    ignore_case,
+                )?)))
+            }
+            _ => logic_error!("regexp takes one or two arguments"),
+        }
+    }
+
+    macro_rules! type_predicate {
+        ($function:ident, $name:literal, $pattern:pat) => {
+            fn $function(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, $pattern))),
+                    _ => logic_error!("{} takes exactly one argument", $name),
+                }
+            }
+        };
+    }
+
+    type_predicate!(is_regexp, "regexp?", ValueType::Regexp(_));
+    type_predicate!(is_regexp_match, "regexp-match?", ValueType::RegexpMatch(_));
+
+    // (name regexp string [start [end]]) finds a match or returns #f
+    macro_rules! match_procedure {
+        ($function:ident, $name:literal, $method:ident) => {
+            fn $function(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(regexp), Some(text)) => {
+                        let regexp = expect_regexp($name, regexp?)?;
+                        let text: Rc<[char]> = expect_string($name, text?)?.chars().collect();
+                        let (start, end) =
+                            expect_range($name, text.len(), arguments.next(), arguments.next())?;
+                        if arguments.next().is_some() {
+                            logic_error!("{} takes two to four arguments", $name);
+                        }
+                        Ok(match regexp.$method(&text, start, end) {
+                            Some(found) => ValueType::RegexpMatch(Rc::new(found)),
+                            None => ValueType::Boolean(false),
+                        })
+                    }
+                    _ => logic_error!("{} takes two to four arguments", $name),
+                }
+            }
+        };
+    }
+
+    match_procedure!(regexp_matches, "regexp-matches", matches);
+    match_procedure!(regexp_search, "regexp-search", search);
+
+    fn regexp_replace(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        replace(interpreter, "regexp-replace", arguments, false)
+    }
+
+    fn regexp_replace_all(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        replace(interpreter, "regexp-replace-all", arguments, true)
+    }
+
+    // the pieces between matches, where empty matches do not split
+    fn regexp_split(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(regexp), Some(text), None) => {
+                let regexp = expect_regexp("regexp-split", regexp?)?;
+                let text: Rc<[char]> = expect_string("regexp-split", text?)?.chars().collect();
+                let mut pieces = vec![];
+                let mut position = 0;
+                for found in regexp.search_all(&text) {
+                    let (start, end) = found.positions[0].unwrap();
+                    if start < end {
+                        pieces.push(ValueType::String(text[position..start].iter().collect()));
+                        position = end;
+                    }
+                }
+                pieces.push(ValueType::String(text[position..].iter().collect()));
+                Ok(ValueType::list(pieces.into_iter()))
+            }
+            _ => logic_error!("regexp-split takes exactly two arguments"),
+        }
+    }
+
+    fn regexp_match_count(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(found), None) => {
+                let found = expect_match("regexp-match-count", found?)?;
+                Ok(ValueType::Number(Number::Integer(
+                    found.positions.len() as i64
+                )))
+            }
+            _ => logic_error!("regexp-match-count takes exactly one argument"),
+        }
+    }
+
+    // (name match [group]), #f when the group did not take part in the match
+    macro_rules! group_procedure {
+        ($function:ident, $name:literal, $value:expr) => {
+            fn $function(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(found), group, None) => {
+                        let found = expect_match($name, found?)?;
+                        let group = expect_group($name, &found, group)?;
+                        let value: fn(&RegexpMatch, usize) -> Option<ValueType> = $value;
+                        Ok(value(&found, group).unwrap_or(ValueType::Boolean(false)))
+                    }
+                    _ => logic_error!("{} takes one or two arguments", $name),
+                }
+            }
+        };
+    }
+
+    group_procedure!(
+        regexp_match_submatch,
+        "regexp-match-submatch",
+        |found, group| { found.submatch(group).map(ValueType::String) }
+    );
+    group_procedure!(regexp_match_start, "regexp-match-start", |found, group| {
+        found.positions[group].map(|(start, _)| ValueType::Number(Number::Integer(start as i64)))
+    });
+    group_procedure!(regexp_match_end, "regexp-match-end", |found, group| {
+        found.positions[group].map(|(_, end)| ValueType::Number(Number::Integer(end as i64)))
+    });
+
+    fn regexp_match_to_list(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(found), None) => {
+                let found = expect_match("regexp-match->list", found?)?;
+                Ok(ValueType::list((0..found.positions.len()).map(|group| {
+                    match found.submatch(group) {
+                        Some(submatch) => ValueType::String(submatch),
+                        None => ValueType::Boolean(false),
+                    }
+                })))
+            }
+            _ => logic_error!("regexp-match->list takes exactly one argument"),
+        }
+    }
+
+    [
+        function_mapping!("regexp", regexp),
+        function_mapping!("regexp?", is_regexp),
+        function_mapping!("regexp-matches", regexp_matches),
+        function_mapping!("regexp-search", regexp_search),
+        interpreter_function_mapping!("regexp-replace", regexp_replace),
+        interpreter_function_mapping!("regexp-replace-all", regexp_replace_all),
+        function_mapping!("regexp-split", regexp_split),
+        function_mapping!("regexp-match?", is_regexp_match),
+        function_mapping!("regexp-match-count", regexp_match_count),
+        function_mapping!("regexp-match-submatch", regexp_match_submatch),
+        function_mapping!("regexp-match-start", regexp_match_start),
+        function_mapping!("regexp-match-end", regexp_match_end),
+        function_mapping!("regexp-match->list", regexp_match_to_list),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn patterns() -> Result<()> {
+    let search = |pattern: &str, text: &str| -> Result<Option<Vec<Option<String>>>> {
+        let regexp = Regexp::new(pattern, false)?;
+        let text: Rc<[char]> = text.chars().collect();
+        Ok(regexp.search(&text, 0, text.len()).map(|found| {
+            (0..found.positions.len())
+                .map(|group| found.submatch(group))
+                .collect()
+        }))
+    };
+    let some = |groups: &[Option<&str>]| {
+        Some(
+            groups
+                .iter()
+                .map(|group| group.map(str::to_string))
+                .collect(),
+        )
+    };
+    assert_eq!(search("b+", "abbbc")?, some(&[Some("bbb")]));
+    assert_eq!(search("b+?", "abbbc")?, some(&[Some("b")]));
+    assert_eq!(
+        search("(a|ab)(c|bcd)", "abcd")?,
+        some(&[Some("abcd"), Some("a"), Some("bcd")])
+    );
+    assert_eq!(search("(x)?y", "y")?, some(&[Some("y"), None]));
+    assert_eq!(search("^[^\\d\\s]{2,3}$", "ab")?, some(&[Some("ab")]));
+    assert_eq!(search("^[^\\d\\s]{2,3}$", "a1")?, None);
+    assert_eq!(search("\\bcat\\b", "concat cat")?, some(&[Some("cat")]));
+    assert_eq!(search("[a-c-]+", "x-ab-d")?, some(&[Some("-ab-")]));
+    assert_eq!(search("(?:a*)*b", "aaab")?, some(&[Some("aaab")]));
+    assert_eq!(search("(a*)*$", "aa")?, some(&[Some("aa"), Some("aa")]));
+    assert_eq!(search("a.c", "a\nc abc")?, some(&[Some("abc")]));
+    // exponential patterns for a naive backtracker take linear time
+    assert_eq!(search("(a*)*b", &"a".repeat(5000))?, None);
+    for (pattern, message) in [
+        ("a**", "regexp: nothing to repeat at 2 in \"a**\""),
+        ("(a", "regexp: expect ')' at 2 in \"(a\""),
+        ("a)", "regexp: unmatched ')' at 1 in \"a)\""),
+        ("[z-a]", "regexp: invalid class range at 4 in \"[z-a]\""),
+        ("\\q", "regexp: unknown escape at 1 in \"\\\\q\""),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            Regexp::new(pattern, false),
+            Err(Error {
+                category: ErrorType::Logic,
+                message: message.to_string()
+            })
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn regexps() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(define m (regexp-search (regexp \"(\\\\d+)-(\\\\d+)\") \"tel: 555-1234\"))
+                 (list (regexp-match-submatch m) (regexp-match-submatch m 2)
+                       (regexp-match-start m 1) (regexp-match-end m) (regexp-match-count m))",
+                "(\"555-1234\" \"1234\" 5 13 3)",
+            ),
+            ("(regexp-matches \"a+\" \"aab\")", "#f"),
+            ("(regexp-match->list (regexp-matches \"(a+)(c)?\" \"xaab\" 1 3))", "(\"aa\" \"aa\" #f)"),
+            ("(regexp-match? (regexp-search (regexp \"ABC\" 'ignore-case) \"xabc\"))", "#t"),
+            ("(regexp-replace \"o\" \"foo\" \"0\")", "\"f0o\""),
+            ("(regexp-replace-all \"(\\\\w+)@(\\\\w+)\" \"a@b c@d\" \"$2 at $1 $$\")", "\"b at a $ d at c $\""),
+            ("(regexp-replace-all \"a*\" \"baaac\" \"-\")", "\"-b--c-\""),
+            (
+                "(regexp-replace-all \"[0-9]+\" \"1 22\" (lambda (m) (number->string (string-length (regexp-match-submatch m)))))",
+                "\"1 2\"",
+            ),
+            ("(regexp-split \",\\\\s*\" \"a, b,,c\")", "(\"a\" \"b\" \"\" \"c\")"),
+            ("(regexp-split \"x*\" \"ab\")", "(\"ab\")"),
+            ("(regexp \"a\\\"b\")", "<regexp \"a\\\"b\">"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(regexp-match-submatch m 3)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "regexp-match-submatch: no group 3 in a match of 3 groups".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,470 @@
+use crate::interpreter::port::file_error;
+use crate::interpreter::ruschm::http::check_network_permitted;
+use crate::interpreter::scheme::base::{expect_index, expect_integer, expect_string};
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+
+pub enum SocketKind {
+    Tcp(TcpStream),
+    Listener(TcpListener),
+    Udp(UdpSocket),
+}
+
+// sockets are compared by identity, and a closed socket has no underlying resource
+pub struct Socket {
+    kind: RefCell<Option<SocketKind>>,
+}
+
+impl Socket {
+    pub fn new(kind: SocketKind) -> Self {
+        Self {
+            kind: RefCell::new(Some(kind)),
+        }
+    }
+
+    fn kind_name(&self) -> &'static str {
+        match &*self.kind.borrow() {
+            Some(SocketKind::Tcp(_)) => "tcp",
+            Some(SocketKind::Listener(_)) => "tcp listener",
+            Some(SocketKind::Udp(_)) => "udp",
+            None => "closed",
+        }
+    }
+
+    fn local_address(&self) -> Option<SocketAddr> {
+        match &*self.kind.borrow() {
+            Some(SocketKind::Tcp(stream)) => stream.local_addr().ok(),
+            Some(SocketKind::Listener(listener)) => listener.local_addr().ok(),
+            Some(SocketKind::Udp(socket)) => socket.local_addr().ok(),
+            None => None,
+        }
+    }
+}
+
+impl fmt::Display for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.local_address() {
+            Some(address) => write!(f, "<{} socket {}>", self.kind_name(), address),
+            None => write!(f, "<{} socket>", self.kind_name()),
+        }
+    }
+}
+
+impl fmt::Debug for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl PartialEq for Socket {
+    fn eq(&self, rhs: &Self) -> bool {
+        std::ptr::eq(self, rhs)
+    }
+}
+
+fn expect_socket(name: &str, value: ValueType) -> Result<Rc<Socket>> {
+    match value {
+        ValueType::Socket(socket) => Ok(socket),
+        other => logic_error!("{}: expect a socket, got {}", name, other),
+    }
+}
+
+fn expect_port_number(name: &str, value: ValueType) -> Result<u16> {
+    let number = expect_integer(name, value)?;
+    match u16::try_from(number) {
+        Ok(number) => Ok(number),
+        Err(_) => logic_error!("{}: expect a port number, got {}", name, number),
+    }
+}
+
+// a bytevector, or a string sent as UTF-8
+fn expect_data(name: &str, value: ValueType) -> Result<Vec<u8>> {
+    match value {
+        ValueType::Bytevector(bytes) => Ok(bytes.borrow().clone()),
+        ValueType::String(text) => Ok(text.into_bytes()),
+        other => logic_error!("{}: expect a bytevector or a string, got {}", name, other),
+    }
+}
+
+fn address_string(address: SocketAddr) -> ValueType {
+    ValueType::String(address.to_string())
+}
+
+// a handle of the connection of a TCP socket, for ports and sends which outlive the borrow
+fn tcp_stream(name: &str, socket: &Socket) -> Result<TcpStream> {
+    match &*socket.kind.borrow() {
+        Some(SocketKind::Tcp(stream)) => stream
+            .try_clone()
+            .map_err(|e| file_error("use", &format!("{}", socket), e)),
+        _ => logic_error!("{}: expect a connected tcp socket, got {}", name, socket),
+    }
+}
+
+// the optional host of listening and binding, all interfaces by default
+fn bind_address(
+    name: &str,
+    port: ValueType,
+    host: Option<Result<ValueType>>,
+) -> Result<(String, u16)> {
+    let port = expect_port_number(name, port)?;
+    let host = match host {
+        Some(host) => expect_string(name, host?)?,
+        None => "0.0.0.0".to_string(),
+    };
+    Ok((host, port))
+}
+
+pub(crate) fn socket_library() -> HashMap<String, ValueType> {
+    // (tcp-connect host port)
+    fn tcp_connect(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_network_permitted("tcp-connect", interpreter)?;
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(host), Some(port), None) => {
+                let host = expect_string("tcp-connect", host?)?;
+                let port = expect_port_number("tcp-connect", port?)?;
+                let stream = TcpStream::connect((host.as_str(), port))
+                    .map_err(|e| file_error("connect to", &format!("{}:{}", host, port), e))?;
+                Ok(ValueType::Socket(Rc::new(Socket::new(SocketKind::Tcp(
+                    stream,
+                )))))
+            }
+            _ => logic_error!("tcp-connect takes exactly two arguments"),
+        }
+    }
+
+    // (tcp-listen port [host]), where port 0 picks a free port
+    fn tcp_listen(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_network_permitted("tcp-listen", interpreter)?;
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(port), host, None) => {
+                let (host, port) = bind_address("tcp-listen", port?, host)?;
+                let listener = TcpListener::bind((host.as_str(), port))
+                    .map_err(|e| file_error("listen on", &format!("{}:{}", host, port), e))?;
+                Ok(ValueType::Socket(Rc::new(Socket::new(
+                    SocketKind::Listener(listener),
+                ))))
+            }
+            _ => logic_error!("tcp-listen takes one or two arguments"),
+        }
+    }
+
+    // waits for the next connection to a listener
+    fn tcp_accept(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_network_permitted("tcp-accept", interpreter)?;
+        match (arguments.next(), arguments.next()) {
+            (Some(listener), None) => {
+                let listener = expect_socket("tcp-accept", listener?)?;
+                let stream = match &*listener.kind.borrow() {
+                    Some(SocketKind::Listener(socket)) => {
+                        socket
+                            .accept()
+                            .map_err(|e| file_error("accept on", &format!("{}", listener), e))?
+                            .0
+                    }
+                    _ => logic_error!("tcp-accept: expect a tcp listener, got {}", listener),
+                };
+                Ok(ValueType::Socket(Rc::new(Socket::new(SocketKind::Tcp(
+                    stream,
+                )))))
+            }
+            _ => logic_error!("tcp-accept takes exactly one argument"),
+        }
+    }
+
+    // (udp-open [port [host]]), bound to a free port by default
+    fn udp_open(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        check_network_permitted("udp-open", interpreter)?;
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (port, host, None) => {
+                let port = port.unwrap_or(Ok(ValueType::Number(Number::Integer(0))))?;
+                let (host, port) = bind_address("udp-open", port, host)?;
+                let socket = UdpSocket::bind((host.as_str(), port))
+                    .map_err(|e| file_error("bind", &format!("{}:{}", host, port), e))?;
+                Ok(ValueType::Socket(Rc::new(Socket::new(SocketKind::Udp(
+                    socket,
+                )))))
+            }
+            _ => logic_error!("udp-open takes zero to two arguments"),
+        }
+    }
+
+    // (socket-input-port socket ['binary]), textual by default
+    macro_rules! port_procedure {
+        ($function:ident, $name:literal, $constructor:ident) => {
+            fn $function(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(socket), kind, None) => {
+                        let socket = expect_socket($name, socket?)?;
+                        let textual = match kind.transpose()? {
+                            None => true,
+                            Some(ValueType::Symbol(kind)) if &*kind == "textual" => true,
+                            Some(ValueType::Symbol(kind)) if &*kind == "binary" => false,
+                            Some(other) => logic_error!("{}: unknown port kind {}", $name, other),
+                        };
+                        let stream = tcp_stream($name, &socket)?;
+                        let name = match stream.peer_addr() {
+                            Ok(address) => format!("tcp {}", address),
+                            Err(_) => "tcp".to_string(),
+                        };
+                        Ok(ValueType::Port(Rc::new(Port::$constructor(
+                            &name, stream, textual,
+                        ))))
+                    }
+                    _ => logic_error!("{} takes one or two arguments", $name),
+                }
+            }
+        };
+    }
+
+    port_procedure!(socket_input_port, "socket-input-port", socket_input);
+    port_procedure!(socket_output_port, "socket-output-port", socket_output);
+
+    // (socket-send socket data) writes all of the data to a tcp connection
+    fn socket_send(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(socket), Some(data), None) => {
+                let socket = expect_socket("socket-send", socket?)?;
+                let data = expect_data("socket-send", data?)?;
+                tcp_stream("socket-send", &socket)?
+                    .write_all(&data)
+                    .map_err(|e| file_error("send to", &format!("{}", socket), e))?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("socket-send takes exactly two arguments"),
+        }
+    }
+
+    // (socket-receive socket k) reads at most k bytes once they arrive, the eof object
+    // when the peer closed the connection
+    fn socket_receive(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(socket), Some(k), None) => {
+                let socket = expect_socket("socket-receive", socket?)?;
+                let mut buffer = vec![0; expect_index("socket-receive", k?)?];
+                let read = tcp_stream("socket-receive", &socket)?
+                    .read(&mut buffer)
+                    .map_err(|e| file_error("receive from", &format!("{}", socket), e))?;
+                if read == 0 && !buffer.is_empty() {
+                    return Ok(ValueType::Eof);
+                }
+                buffer.truncate(read);
+                Ok(ValueType::bytevector(buffer))
+            }
+            _ => logic_error!("socket-receive takes exactly two arguments"),
+        }
+    }
+
+    // (udp-send-to socket data host port)
+    fn udp_send_to(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(socket), Some(data), Some(host), Some(port), None) => {
+                let socket = expect_socket("udp-send-to", socket?)?;
+                let data = expect_data("udp-send-to", data?)?;
+                let host = expect_string("udp-send-to", host?)?;
+                let port = expect_port_number("udp-send-to", port?)?;
+                match &*socket.kind.borrow() {
+                    Some(SocketKind::Udp(udp)) => udp
+                        .send_to(&data, (host.as_str(), port))
+                        .map_err(|e| file_error("send to", &format!("{}:{}", host, port), e))?,
+                    _ => logic_error!("udp-send-to: expect a udp socket, got {}", socket),
+                };
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("udp-send-to takes exactly four arguments"),
+        }
+    }
+
+    // (udp-receive-from socket k) waits for a datagram, and is (bytevector address),
+    // where a datagram longer than k bytes is cut
+    fn udp_receive_from(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(socket), Some(k), None) => {
+                let socket = expect_socket("udp-receive-from", socket?)?;
+                let mut buffer = vec![0; expect_index("udp-receive-from", k?)?];
+                let (read, address) = match &*socket.kind.borrow() {
+                    Some(SocketKind::Udp(udp)) => udp
+                        .recv_from(&mut buffer)
+                        .map_err(|e| file_error("receive from", &format!("{}", socket), e))?,
+                    _ => logic_error!("udp-receive-from: expect a udp socket, got {}", socket),
+                };
+                buffer.truncate(read);
+                Ok(ValueType::list(
+                    vec![ValueType::bytevector(buffer), address_string(address)].into_iter(),
+                ))
+            }
+            _ => logic_error!("udp-receive-from takes exactly two arguments"),
+        }
+    }
+
+    fn socket_address(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(socket), None) => {
+                let socket = expect_socket("socket-address", socket?)?;
+                match socket.local_address() {
+                    Some(address) => Ok(address_string(address)),
+                    None => logic_error!("socket-address: {} has no address", socket),
+                }
+            }
+            _ => logic_error!("socket-address takes exactly one argument"),
+        }
+    }
+
+    fn socket_port_number(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(socket), None) => {
+                let socket = expect_socket("socket-port-number", socket?)?;
+                match socket.local_address() {
+                    Some(address) => Ok(ValueType::Number(Number::Integer(address.port() as i64))),
+                    None => logic_error!("socket-port-number: {} has no address", socket),
+                }
+            }
+            _ => logic_error!("socket-port-number takes exactly one argument"),
+        }
+    }
+
+    fn socket_peer_address(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(socket), None) => {
+                let socket = expect_socket("socket-peer-address", socket?)?;
+                let address = tcp_stream("socket-peer-address", &socket)?
+                    .peer_addr()
+                    .map_err(|e| file_error("use", &format!("{}", socket), e))?;
+                Ok(address_string(address))
+            }
+            _ => logic_error!("socket-peer-address takes exactly one argument"),
+        }
+    }
+
+    // ports of the socket keep the connection open until they are closed too
+    fn socket_close(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(socket), None) => {
+                expect_socket("socket-close", socket?)?
+                    .kind
+                    .borrow_mut()
+                    .take();
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("socket-close takes exactly one argument"),
+        }
+    }
+
+    fn is_socket(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Socket(_)))),
+            _ => logic_error!("socket? takes exactly one argument"),
+        }
+    }
+
+    [
+        interpreter_function_mapping!("tcp-connect", tcp_connect),
+        interpreter_function_mapping!("tcp-listen", tcp_listen),
+        interpreter_function_mapping!("tcp-accept", tcp_accept),
+        interpreter_function_mapping!("udp-open", udp_open),
+        function_mapping!("socket-input-port", socket_input_port),
+        function_mapping!("socket-output-port", socket_output_port),
+        function_mapping!("socket-send", socket_send),
+        function_mapping!("socket-receive", socket_receive),
+        function_mapping!("udp-send-to", udp_send_to),
+        function_mapping!("udp-receive-from", udp_receive_from),
+        function_mapping!("socket-address", socket_address),
+        function_mapping!("socket-port-number", socket_port_number),
+        function_mapping!("socket-peer-address", socket_peer_address),
+        function_mapping!("socket-close", socket_close),
+        function_mapping!("socket?", is_socket),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn sockets() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_eq!(
+        interpreter.eval("(udp-open)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "udp-open: network access is not permitted".to_string()
+        })
+    );
+    interpreter.allow_network.set(true);
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(define listener (tcp-listen 0 \"127.0.0.1\"))
+             (define client (tcp-connect \"127.0.0.1\" (socket-port-number listener)))
+             (define server (tcp-accept listener))
+             (define out (socket-output-port client))
+             (write-string \"hello\\nworld\" out)
+             (close-port out)
+             (define in (socket-input-port server))
+             (list (socket? server) (read-line in) (read-line in) (eof-object? (read-char in)))",
+                "(#t \"hello\" \"world\" #t)",
+            ),
+            (
+                "(socket-send server #u8(1 2 3))
+             (socket-receive client 2)",
+                "#u8(1 2)",
+            ),
+            (
+                "(define bin (socket-input-port client 'binary))
+             (list (read-u8 bin) (equal? (socket-peer-address client) (socket-address server)))",
+                "(3 #t)",
+            ),
+            (
+                "(close-port in) (socket-close server) (socket-receive client 4)",
+                "<eof>",
+            ),
+            (
+                "(define a (udp-open 0 \"127.0.0.1\"))
+             (define b (udp-open 0 \"127.0.0.1\"))
+             (udp-send-to a \"ping\" \"127.0.0.1\" (socket-port-number b))
+             (define received (udp-receive-from b 16))
+             (list (utf8->string (car received)) (equal? (cadr received) (socket-address a)))",
+                "(\"ping\" #t)",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(socket-send a \"x\")".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: format!(
+                "socket-send: expect a connected tcp socket, got {}",
+                interpreter.eval("a".chars())?.unwrap()
+            )
+        })
+    );
+    Ok(())
+}
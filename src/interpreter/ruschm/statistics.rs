@@ -0,0 +1,112 @@
+use crate::interpreter::gc::heap_statistics;
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// counts of the work done since the interpreter started, whose differences profile code
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Statistics {
+    // the expressions the tree-walker evaluated and the instructions the vm executed
+    pub evaluations: u64,
+    // the allocated bytes and collections are those of every interpreter of the thread
+    pub allocated: u64,
+    pub collections: u64,
+    pub pause: Duration,
+}
+
+impl Statistics {
+    pub fn since(&self, start: &Statistics) -> Statistics {
+        Statistics {
+            evaluations: self.evaluations.saturating_sub(start.evaluations),
+            allocated: self.allocated.saturating_sub(start.allocated),
+            collections: self.collections.saturating_sub(start.collections),
+            pause: self.pause.saturating_sub(start.pause),
+        }
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} evaluations, {} bytes allocated, {} collections taking {:?}",
+            self.evaluations, self.allocated, self.collections, self.pause
+        )
+    }
+}
+
+impl Interpreter {
+    pub fn statistics(&self) -> Statistics {
+        let heap = heap_statistics();
+        Statistics {
+            evaluations: self.evaluations.get(),
+            allocated: heap.allocated,
+            collections: heap.collections,
+            pause: heap.pause,
+        }
+    }
+}
+
+pub(crate) fn statistics_library() -> HashMap<String, ValueType> {
+    // (runtime-statistics), an association list of the counts
+    fn runtime_statistics(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        if arguments.count() != 0 {
+            logic_error!("runtime-statistics takes no arguments")
+        }
+        let statistics = interpreter.statistics();
+        let integer = |n: u64| ValueType::Number(Number::Integer(n as i64));
+        let entries = vec![
+            ("evaluations", integer(statistics.evaluations)),
+            ("allocated-bytes", integer(statistics.allocated)),
+            ("collections", integer(statistics.collections)),
+            (
+                "collection-seconds",
+                ValueType::Number(Number::Real(statistics.pause.as_secs_f64())),
+            ),
+        ];
+        Ok(ValueType::list(entries.into_iter().map(|(key, value)| {
+            ValueType::cons(ValueType::Symbol(intern(key)), value)
+        })))
+    }
+
+    [interpreter_function_mapping!(
+        "runtime-statistics",
+        runtime_statistics
+    )]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn runtime_statistics() -> Result<()> {
+    for engine in [vm::Engine::TreeWalker, vm::Engine::Bytecode] {
+        let interpreter = Interpreter::new();
+        interpreter.engine.set(engine);
+        let start = interpreter.statistics();
+        interpreter
+            .eval_source(
+                "(define (loop n) (if (= n 0) '() (cons n (loop (- n 1)))))
+                 (define l (loop 20))
+                 (set-cdr! l l)
+                 (vector 1 2 3)",
+            )
+            .map_err(|(e, _)| e)?;
+        interpreter.collect_garbage();
+        let used = interpreter.statistics().since(&start);
+        assert!(used.evaluations > 20, "{}", used);
+        assert!(used.allocated >= 20 * 32, "{}", used);
+        assert!(used.collections >= 1, "{}", used);
+    }
+    let interpreter = Interpreter::new();
+    assert_eq!(
+        interpreter
+            .eval_source(
+                "(define s (runtime-statistics)) (list (length s) (car (car s)) (car (list-ref s 3)))"
+            )
+            .map_err(|(e, _)| e)?
+            .map(|value| value.to_string()),
+        Some("(4 evaluations collection-seconds)".to_string())
+    );
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use crate::interpreter::*;
+
+// writes a line of the trace to the current output port, indented by the depth of traced calls
+fn trace_line(interpreter: &Interpreter, depth: usize, text: &str) -> Result<()> {
+    let port = interpreter.current_output_port.borrow().clone();
+    port.write_str(&format!("{}{}\n", "  ".repeat(depth), text))
+}
+
+// a procedure calling the original one, which writes each call and its result
+// only user procedures can be traced, as the wrapper takes as many arguments as the original
+pub fn trace(interpreter: &Interpreter, name: &str, procedure: ValueType) -> Result<ValueType> {
+    fn next(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (name, procedure) = match (arguments.next(), arguments.next()) {
+            (Some(Ok(name)), Some(Ok(procedure))) => (name, procedure),
+            _ => logic_error!("trace: broken trace state"),
+        };
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        let call = ValueType::cons(name, ValueType::list(arguments.clone().into_iter()));
+        let depth = interpreter.trace_depth.get();
+        trace_line(interpreter, depth, &format!("{}", call))?;
+        interpreter.trace_depth.set(depth + 1);
+        let result = interpreter.call_procedure(&procedure, arguments);
+        interpreter.trace_depth.set(depth);
+        let value = result?;
+        trace_line(interpreter, depth, &format!("=> {}", value))?;
+        Ok(value)
+    }
+    // the traced procedure is told of as written where the procedure is
+    let (arity, origin) = match &procedure {
+        ValueType::Procedure(defined) => match defined.scheme_procedure() {
+            Some(SchemeProcedure(formals, .., origin)) => (formals.len(), origin.clone()),
+            None => logic_error!(
+                "trace: {} is a builtin procedure, only procedures defined in scheme can be traced",
+                name
+            ),
+        },
+        other => logic_error!("trace: expect a procedure, got {}", other),
+    };
+    Ok(native_closure(
+        interpreter,
+        vec![ValueType::Symbol(intern(name)), procedure],
+        BuildinProcedure("traced", next),
+        hidden_formals(arity),
+        origin,
+    ))
+}
+
+#[test]
+fn tracing() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter
+        .current_output_port
+        .replace(Rc::new(Port::open_output_string()));
+    let fact = interpreter
+        .eval("(define (fact n) (if (= n 0) 1 (* n (fact (- n 1))))) fact".chars())?
+        .unwrap();
+    let traced = trace(&interpreter, "fact", fact)?;
+    interpreter
+        .global_environment()
+        .define("fact".to_string(), traced);
+    interpreter.eval("(fact 2)".chars())?;
+    assert_eq!(
+        interpreter.current_output_port.borrow().output_string()?,
+        "(fact 2)\n  (fact 1)\n    (fact 0)\n    => 1\n  => 1\n=> 2\n"
+    );
+    assert_eq!(
+        trace(
+            &interpreter,
+            "car",
+            interpreter.eval("car".chars())?.unwrap()
+        ),
+        Err(Error {
+            category: ErrorType::Logic,
+            message:
+                "trace: car is a builtin procedure, only procedures defined in scheme can be traced"
+                    .to_string()
+        })
+    );
+    Ok(())
+}
@@ -1,191 +1,2057 @@
 use crate::interpreter::*;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-pub(crate) fn base_library<'a>() -> HashMap<String, ValueType> {
+pub(crate) fn expect_number(value: ValueType) -> Result<Number> {
+    match value {
+        ValueType::Number(number) => Ok(number),
+        _ => logic_error!("expect a number!"),
+    }
+}
+
+pub(crate) fn expect_pair(name: &str, value: ValueType) -> Result<Rc<RefCell<Pair>>> {
+    match value {
+        ValueType::Pair(pair) => Ok(pair),
+        other => logic_error!("{}: expect a pair, got {}", name, other),
+    }
+}
+
+// circular lists are not printed in the message, as printing them never ends
+pub(crate) fn expect_list(name: &str, value: ValueType) -> Result<Vec<ValueType>> {
+    match (value.list_to_vec(), value.list_shape()) {
+        (Some(list), _) => Ok(list),
+        (None, ListShape::Circular) => logic_error!("{}: expect a list, got <circular list>", name),
+        (None, _) => logic_error!("{}: expect a list, got {}", name, value),
+    }
+}
+
+pub(crate) fn expect_index(name: &str, value: ValueType) -> Result<usize> {
+    match value {
+        ValueType::Number(Number::Integer(index)) if index >= 0 => Ok(index as usize),
+        other => logic_error!(
+            "{}: expect a non-negative exact integer, got {}",
+            name,
+            other
+        ),
+    }
+}
+
+pub(crate) fn expect_integer(name: &str, value: ValueType) -> Result<i64> {
+    match value {
+        ValueType::Number(Number::Integer(n)) => Ok(n),
+        other => logic_error!("{}: expect an exact integer, got {}", name, other),
+    }
+}
+
+pub(crate) fn expect_string(name: &str, value: ValueType) -> Result<String> {
+    match value {
+        ValueType::String(s) => Ok(s),
+        other => logic_error!("{}: expect a string, got {}", name, other),
+    }
+}
+
+pub(crate) fn expect_vector(name: &str, value: ValueType) -> Result<Rc<RefCell<Vec<ValueType>>>> {
+    match value {
+        ValueType::Vector(vector) => Ok(vector),
+        other => logic_error!("{}: expect a vector, got {}", name, other),
+    }
+}
+
+pub(crate) fn expect_bytevector(name: &str, value: ValueType) -> Result<Rc<RefCell<Vec<u8>>>> {
+    match value {
+        ValueType::Bytevector(bytes) => Ok(bytes),
+        other => logic_error!("{}: expect a bytevector, got {}", name, other),
+    }
+}
+
+pub(crate) fn expect_byte(name: &str, value: ValueType) -> Result<u8> {
+    match value {
+        ValueType::Number(Number::Integer(n)) if (0..=255).contains(&n) => Ok(n as u8),
+        other => logic_error!("{}: expect a byte, got {}", name, other),
+    }
+}
+
+// the optional port argument of output procedures, defaulting to the current output port
+pub(crate) fn output_port(
+    name: &str,
+    port: Option<Result<ValueType>>,
+    interpreter: &Interpreter,
+) -> Result<Rc<Port>> {
+    match port.transpose()? {
+        None => Ok(interpreter.current_output_port.borrow().clone()),
+        Some(ValueType::Port(port)) if port.is_output() => Ok(port),
+        Some(other) => logic_error!("{}: expect an output port, got {}", name, other),
+    }
+}
+
+// the optional port argument of input procedures, defaulting to the current input port
+pub(crate) fn input_port(
+    name: &str,
+    port: Option<Result<ValueType>>,
+    interpreter: &Interpreter,
+) -> Result<Rc<Port>> {
+    match port.transpose()? {
+        None => Ok(interpreter.current_input_port.borrow().clone()),
+        Some(ValueType::Port(port)) if port.is_input() => Ok(port),
+        Some(other) => logic_error!("{}: expect an input port, got {}", name, other),
+    }
+}
+
+pub(crate) fn expect_character(name: &str, value: ValueType) -> Result<char> {
+    match value {
+        ValueType::Character(c) => Ok(c),
+        other => logic_error!("{}: expect a character, got {}", name, other),
+    }
+}
+
+// optional start and end arguments of a sequence, defaulting to the whole sequence
+pub(crate) fn expect_range(
+    name: &str,
+    length: usize,
+    start: Option<Result<ValueType>>,
+    end: Option<Result<ValueType>>,
+) -> Result<(usize, usize)> {
+    let start = match start {
+        Some(start) => expect_index(name, start?)?,
+        None => 0,
+    };
+    let end = match end {
+        Some(end) => expect_index(name, end?)?,
+        None => length,
+    };
+    if start > end || end > length {
+        logic_error!(
+            "{}: expect 0 <= start <= end <= {}, got start {} and end {}",
+            name,
+            length,
+            start,
+            end
+        )
+    }
+    Ok((start, end))
+}
+
+pub(crate) fn list_tail(name: &str, list: ValueType, k: usize) -> Result<ValueType> {
+    (0..k).try_fold(list, |list, _| {
+        Ok(expect_pair(name, list)?.borrow().cdr.clone())
+    })
+}
+
+// the first sublist of a proper list whose car satisfies the predicate
+fn member_procedure(
+    name: &str,
+    list: ValueType,
+    mut predicate: impl FnMut(ValueType) -> Result<bool>,
+) -> Result<ValueType> {
+    expect_list(name, list.clone())?;
+    let mut current = list;
+    while let ValueType::Pair(pair) = current.clone() {
+        // the predicate may mutate the list, so no borrow is held while calling it
+        let element = pair.borrow().car.clone();
+        if predicate(element)? {
+            return Ok(current);
+        }
+        current = pair.borrow().cdr.clone();
+    }
+    Ok(ValueType::Boolean(false))
+}
+
+// the first pair of an association list whose car satisfies the predicate
+fn assoc_procedure(
+    name: &str,
+    alist: ValueType,
+    mut predicate: impl FnMut(ValueType) -> Result<bool>,
+) -> Result<ValueType> {
+    for entry in expect_list(name, alist)? {
+        let key = expect_pair(name, entry.clone())?.borrow().car.clone();
+        if predicate(key)? {
+            return Ok(entry);
+        }
+    }
+    Ok(ValueType::Boolean(false))
+}
+
+// applies a user supplied equivalence predicate
+pub(crate) fn call_comparator(
+    interpreter: &Interpreter,
+    comparator: &ValueType,
+    a: ValueType,
+    b: ValueType,
+) -> Result<bool> {
+    Ok(interpreter
+        .call_procedure(comparator, vec![a, b])?
+        .is_true())
+}
+
+// the consumer is applied to the values the producer returns
+pub(crate) fn call_with_values(
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(producer), Some(consumer), None) => {
+            let (producer, consumer) = (producer?, consumer?);
+            let values = produced_values(&producer, interpreter)?;
+            interpreter.call_procedure(&consumer, values)
+        }
+        _ => logic_error!("call-with-values takes exactly two arguments"),
+    }
+}
+
+// receive and let-values with a rest formal apply the consumer to the first values and a list
+// of those after them
+pub(crate) fn call_with_rest_values(
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+    ) {
+        (Some(producer), Some(consumer), Some(count), None) => {
+            let (producer, consumer) = (producer?, consumer?);
+            let count = expect_index("call-with-rest-values", count?)?;
+            let mut values = produced_values(&producer, interpreter)?;
+            if values.len() < count {
+                logic_error!("expect at least {} values, got {}", count, values.len())
+            }
+            let rest = ValueType::list(values.split_off(count).into_iter());
+            values.push(rest);
+            interpreter.call_procedure(&consumer, values)
+        }
+        _ => logic_error!("call-with-rest-values takes exactly three arguments"),
+    }
+}
+
+fn produced_values(producer: &ValueType, interpreter: &Interpreter) -> Result<Vec<ValueType>> {
+    Ok(match interpreter.call_procedure(producer, vec![])? {
+        ValueType::Values(values) => values,
+        value => vec![value],
+    })
+}
+
+pub(crate) fn base_library() -> HashMap<String, ValueType> {
     fn add(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-        arguments.try_fold(ValueType::Number(Number::Integer(0)), |a, b| {
-            match (a, b?) {
-                (ValueType::Number(num1), ValueType::Number(num2)) => {
-                    Ok(ValueType::Number(num1 + num2))
+        arguments
+            .try_fold(Number::Integer(0), |a, b| a + expect_number(b?)?)
+            .map(ValueType::Number)
+    }
+
+    fn sub(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let init = match arguments.next() {
+            None => logic_error!("'-' needs at least one argument"),
+            Some(first) => {
+                let first_num = expect_number(first?)?;
+                match arguments.next() {
+                    Some(second) => (first_num - expect_number(second?)?)?,
+                    None => (Number::Integer(0) - first_num)?,
+                }
+            }
+        };
+        arguments
+            .try_fold(init, |a, b| a - expect_number(b?)?)
+            .map(ValueType::Number)
+    }
+
+    fn mul(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        arguments
+            .try_fold(Number::Integer(1), |a, b| a * expect_number(b?)?)
+            .map(ValueType::Number)
+    }
+
+    fn div(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let init = match arguments.next() {
+            None => logic_error!("'/' needs at least one argument"),
+            Some(first) => {
+                let first_num = expect_number(first?)?;
+                match arguments.next() {
+                    Some(second) => (first_num / expect_number(second?)?)?,
+                    None => (Number::Integer(1) / first_num)?,
+                }
+            }
+        };
+        arguments
+            .try_fold(init, |a, b| a / expect_number(b?)?)
+            .map(ValueType::Number)
+    }
+
+    macro_rules! integer_division {
+        ($name:tt, $ident:tt, $method:ident) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(dividend), Some(divisor), None) => Ok(ValueType::Number(
+                        expect_number(dividend?)?.$method(&expect_number(divisor?)?)?,
+                    )),
+                    _ => logic_error!("{} takes exactly two arguments", $ident),
+                }
+            }
+        };
+    }
+
+    integer_division!(quotient, "quotient", quotient);
+    integer_division!(remainder, "remainder", remainder);
+    integer_division!(modulo, "modulo", modulo);
+
+    integer_division!(floor_quotient, "floor-quotient", floor_quotient);
+
+    macro_rules! integer_division_values {
+        ($name:tt, $ident:tt, $quotient:ident, $remainder:ident) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(dividend), Some(divisor), None) => {
+                        let (dividend, divisor) =
+                            (expect_number(dividend?)?, expect_number(divisor?)?);
+                        Ok(ValueType::Values(vec![
+                            ValueType::Number(dividend.$quotient(&divisor)?),
+                            ValueType::Number(dividend.$remainder(&divisor)?),
+                        ]))
+                    }
+                    _ => logic_error!("{} takes exactly two arguments", $ident),
+                }
+            }
+        };
+    }
+
+    integer_division_values!(floor_division, "floor/", floor_quotient, modulo);
+    integer_division_values!(truncate_division, "truncate/", quotient, remainder);
+
+    macro_rules! rounding {
+        ($name:tt, $ident:tt, $method:ident) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(value), None) => Ok(ValueType::Number(expect_number(value?)?.$method())),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    rounding!(floor, "floor", floor);
+    rounding!(ceiling, "ceiling", ceiling);
+    rounding!(round, "round", round);
+    rounding!(truncate, "truncate", truncate);
+
+    fn values(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let mut values: Vec<ValueType> = arguments.collect::<Result<_>>()?;
+        Ok(match values.len() {
+            1 => values.pop().unwrap(),
+            _ => ValueType::Values(values),
+        })
+    }
+
+    fn gcd(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        arguments
+            .try_fold(Number::Integer(0), |a, b| a.gcd(&expect_number(b?)?))
+            .map(ValueType::Number)
+    }
+
+    fn lcm(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        arguments
+            .try_fold(Number::Integer(1), |a, b| a.lcm(&expect_number(b?)?))
+            .map(ValueType::Number)
+    }
+
+    fn abs(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Number(expect_number(value?)?.abs()?)),
+            _ => logic_error!("abs takes exactly one argument"),
+        }
+    }
+
+    // fn cond(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {}
+
+    // all arguments are type checked even after the chain is known to be false
+    macro_rules! comparision {
+        ($name:tt, $ident:tt, $operator:tt) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                let mut last = match arguments.next() {
+                    None => return Ok(ValueType::Boolean(true)),
+                    Some(first) => first?,
+                };
+                let mut result = true;
+                for current in arguments {
+                    match (last, current?) {
+                        (ValueType::Number(a), ValueType::Number(b)) => {
+                            result = result && a $operator b;
+                            last = ValueType::Number(b);
+                        }
+                        _ => logic_error!("{} comparision can only between numbers!", $ident),
+                    }
+                }
+                match last {
+                    ValueType::Number(_) => Ok(ValueType::Boolean(result)),
+                    _ => logic_error!("{} comparision can only between numbers!", $ident),
+                }
+            }
+        };
+    }
+
+    comparision!(equals, "=", ==);
+    comparision!(greater, ">", >);
+    comparision!(greater_equal, ">=", >=);
+    comparision!(less, "<", <);
+    comparision!(less_equal, "<=", <=);
+
+    // the result is inexact if any argument is inexact
+    macro_rules! first_of_order {
+        ($name:tt, $ident:tt, $cmp:tt) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                let first = match arguments.next() {
+                    None => logic_error!("{} requires at least one argument!", $ident),
+                    Some(value) => expect_number(value?)?,
+                };
+                arguments
+                    .try_fold(first, |a, b| {
+                        let b = expect_number(b?)?;
+                        let exact = a.is_exact() && b.is_exact();
+                        let chosen = match (a.is_nan(), b.is_nan(), a $cmp b) {
+                            (true, _, _) | (false, false, true) => a,
+                            _ => b,
+                        };
+                        Ok(match exact {
+                            true => chosen,
+                            false => chosen.to_inexact(),
+                        })
+                    })
+                    .map(ValueType::Number)
+            }
+        };
+    }
+
+    first_of_order!(max, "max", >);
+    first_of_order!(min, "min", <);
+
+    fn sqrt(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => match value? {
+                ValueType::Number(number) => Ok(ValueType::Number(number.sqrt())),
+                other => logic_error!("sqrt requires a number, got {:?}", other),
+            },
+            _ => logic_error!("sqrt takes exactly one argument"),
+        }
+    }
+
+    fn exact_integer_sqrt(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => {
+                let (root, rest) = expect_number(value?)?.exact_integer_sqrt()?;
+                Ok(ValueType::Values(vec![
+                    ValueType::Number(root),
+                    ValueType::Number(rest),
+                ]))
+            }
+            _ => logic_error!("exact-integer-sqrt takes exactly one argument"),
+        }
+    }
+
+    fn expt(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(base), Some(exponent), None) => Ok(ValueType::Number(
+                expect_number(base?)?.expt(&expect_number(exponent?)?)?,
+            )),
+            _ => logic_error!("expt takes exactly two arguments"),
+        }
+    }
+
+    fn expect_radix(value: Option<Result<ValueType>>) -> Result<u32> {
+        match value {
+            None => Ok(10),
+            Some(value) => match value? {
+                ValueType::Number(Number::Integer(radix)) if [2, 8, 10, 16].contains(&radix) => {
+                    Ok(radix as u32)
+                }
+                other => logic_error!("radix should be one of 2, 8, 10 and 16, got {}", other),
+            },
+        }
+    }
+
+    fn number_to_string(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let number = match arguments.next() {
+            Some(value) => expect_number(value?)?,
+            None => logic_error!("number->string takes one or two arguments"),
+        };
+        let radix = expect_radix(arguments.next())?;
+        match arguments.next() {
+            None => Ok(ValueType::String(number.to_string_radix(radix)?)),
+            Some(_) => logic_error!("number->string takes one or two arguments"),
+        }
+    }
+
+    fn string_to_number(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let text = match arguments.next() {
+            Some(value) => match value? {
+                ValueType::String(text) => text,
+                other => logic_error!("string->number requires a string, got {}", other),
+            },
+            None => logic_error!("string->number takes one or two arguments"),
+        };
+        let radix = expect_radix(arguments.next())?;
+        match arguments.next() {
+            None => Ok(match Number::parse(text.as_str(), radix) {
+                Some(number) => ValueType::Number(number),
+                None => ValueType::Boolean(false),
+            }),
+            Some(_) => logic_error!("string->number takes one or two arguments"),
+        }
+    }
+
+    fn is_pair(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Pair(_)))),
+            _ => logic_error!("pair? takes exactly one argument"),
+        }
+    }
+
+    fn is_null(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::EmptyList))),
+            _ => logic_error!("null? takes exactly one argument"),
+        }
+    }
+
+    fn is_list(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(value?.list_length().is_some())),
+            _ => logic_error!("list? takes exactly one argument"),
+        }
+    }
+
+    fn cons(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(car), Some(cdr), None) => Ok(ValueType::cons(car?, cdr?)),
+            _ => logic_error!("cons takes exactly two arguments"),
+        }
+    }
+
+    fn car(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(pair), None) => Ok(expect_pair("car", pair?)?.borrow().car.clone()),
+            _ => logic_error!("car takes exactly one argument"),
+        }
+    }
+
+    fn cdr(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(pair), None) => Ok(expect_pair("cdr", pair?)?.borrow().cdr.clone()),
+            _ => logic_error!("cdr takes exactly one argument"),
+        }
+    }
+
+    fn set_car(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(pair), Some(value), None) => {
+                let pair = expect_pair("set-car!", pair?)?;
+                gc::register_pair(&pair);
+                pair.borrow_mut().car = value?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("set-car! takes exactly two arguments"),
+        }
+    }
+
+    fn set_cdr(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(pair), Some(value), None) => {
+                let pair = expect_pair("set-cdr!", pair?)?;
+                gc::register_pair(&pair);
+                pair.borrow_mut().cdr = value?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("set-cdr! takes exactly two arguments"),
+        }
+    }
+
+    fn list(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let elements = arguments.collect::<Result<Vec<_>>>()?;
+        Ok(ValueType::list(elements.into_iter()))
+    }
+
+    fn length(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => Ok(ValueType::Number(Number::Integer(
+                expect_list("length", list?)?.len() as i64,
+            ))),
+            _ => logic_error!("length takes exactly one argument"),
+        }
+    }
+
+    // the result shares structure with the last argument, which can be any object
+    fn append(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let mut lists = arguments.collect::<Result<Vec<_>>>()?;
+        let tail = lists.pop().unwrap_or(ValueType::EmptyList);
+        let mut elements = vec![];
+        for list in lists {
+            elements.extend(expect_list("append", list)?);
+        }
+        Ok(ValueType::list_with_tail(elements.into_iter(), tail))
+    }
+
+    fn reverse(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => Ok(ValueType::list(
+                expect_list("reverse", list?)?.into_iter().rev(),
+            )),
+            _ => logic_error!("reverse takes exactly one argument"),
+        }
+    }
+
+    fn list_tail_procedure(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(list), Some(k), None) => {
+                list_tail("list-tail", list?, expect_index("list-tail", k?)?)
+            }
+            _ => logic_error!("list-tail takes exactly two arguments"),
+        }
+    }
+
+    fn list_ref(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(list), Some(k), None) => {
+                let tail = list_tail("list-ref", list?, expect_index("list-ref", k?)?)?;
+                Ok(expect_pair("list-ref", tail)?.borrow().car.clone())
+            }
+            _ => logic_error!("list-ref takes exactly two arguments"),
+        }
+    }
+
+    // copies the spine of a list, improper tails and non-list objects are returned as is
+    fn list_copy(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => {
+                let mut elements = vec![];
+                let mut current = list?;
+                while let ValueType::Pair(pair) = current {
+                    elements.push(pair.borrow().car.clone());
+                    current = pair.borrow().cdr.clone();
                 }
-                _ => logic_error!("expect a number!"),
+                Ok(ValueType::list_with_tail(elements.into_iter(), current))
+            }
+            _ => logic_error!("list-copy takes exactly one argument"),
+        }
+    }
+
+    macro_rules! equivalence {
+        ($name:tt, $ident:tt, $method:ident) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(a), Some(b), None) => Ok(ValueType::Boolean(a?.$method(&b?))),
+                    _ => logic_error!("{} takes exactly two arguments", $ident),
+                }
+            }
+        };
+    }
+
+    equivalence!(is_eq, "eq?", eqv);
+    equivalence!(is_eqv, "eqv?", eqv);
+    equivalence!(is_equal, "equal?", equal);
+
+    macro_rules! member_by {
+        ($name:tt, $ident:tt, $search:ident, $method:ident) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(x), Some(list), None) => {
+                        let x = x?;
+                        $search($ident, list?, |element| Ok(x.$method(&element)))
+                    }
+                    _ => logic_error!("{} takes exactly two arguments", $ident),
+                }
+            }
+        };
+    }
+
+    member_by!(memq, "memq", member_procedure, eqv);
+    member_by!(memv, "memv", member_procedure, eqv);
+    member_by!(assq, "assq", assoc_procedure, eqv);
+    member_by!(assv, "assv", assoc_procedure, eqv);
+
+    macro_rules! member_with_comparator {
+        ($name:tt, $ident:tt, $search:ident) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+                interpreter: &Interpreter,
+            ) -> Result<ValueType> {
+                match (
+                    arguments.next(),
+                    arguments.next(),
+                    arguments.next(),
+                    arguments.next(),
+                ) {
+                    (Some(x), Some(list), None, None) => {
+                        let x = x?;
+                        $search($ident, list?, |element| Ok(x.equal(&element)))
+                    }
+                    (Some(x), Some(list), Some(compare), None) => {
+                        let (x, list, compare) = (x?, list?, compare?);
+                        $search($ident, list, |element| {
+                            call_comparator(interpreter, &compare, x.clone(), element)
+                        })
+                    }
+                    _ => logic_error!("{} takes two or three arguments", $ident),
+                }
+            }
+        };
+    }
+
+    member_with_comparator!(member, "member", member_procedure);
+    member_with_comparator!(assoc, "assoc", assoc_procedure);
+
+    fn vector(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let vector: Result<Vec<ValueType>> = arguments.collect();
+        Ok(ValueType::vector(vector?))
+    }
+
+    fn is_vector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Vector(_)))),
+            _ => logic_error!("vector? takes exactly one argument"),
+        }
+    }
+
+    // the fill defaults to #f
+    fn make_vector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(k), fill, None) => {
+                let k = expect_index("make-vector", k?)?;
+                let fill = fill.transpose()?.unwrap_or(ValueType::Boolean(false));
+                Ok(ValueType::vector(vec![fill; k]))
+            }
+            _ => logic_error!("make-vector takes one or two arguments"),
+        }
+    }
+
+    fn vector_length(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(vector), None) => Ok(ValueType::Number(Number::Integer(
+                expect_vector("vector-length", vector?)?.borrow().len() as i64,
+            ))),
+            _ => logic_error!("vector-length takes exactly one argument"),
+        }
+    }
+
+    fn vector_ref(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(vector), Some(k), None) => {
+                let vector = expect_vector("vector-ref", vector?)?;
+                let k = expect_index("vector-ref", k?)?;
+                let vector = vector.borrow();
+                match vector.get(k) {
+                    Some(value) => Ok(value.clone()),
+                    None => logic_error!(
+                        "vector-ref: index {} out of range for length {}",
+                        k,
+                        vector.len()
+                    ),
+                }
+            }
+            _ => logic_error!("vector-ref takes exactly two arguments"),
+        }
+    }
+
+    fn vector_set(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(vector), Some(k), Some(value), None) => {
+                let vector = expect_vector("vector-set!", vector?)?;
+                gc::register_vector(&vector);
+                let k = expect_index("vector-set!", k?)?;
+                let value = value?;
+                let mut vector = vector.borrow_mut();
+                let length = vector.len();
+                match vector.get_mut(k) {
+                    Some(element) => *element = value,
+                    None => logic_error!(
+                        "vector-set!: index {} out of range for length {}",
+                        k,
+                        length
+                    ),
+                }
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("vector-set! takes exactly three arguments"),
+        }
+    }
+
+    // the elements of a vector between the optional start and end arguments
+    fn vector_slice(
+        name: &str,
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<Vec<ValueType>> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(vector), start, end, None) => {
+                let vector = expect_vector(name, vector?)?;
+                let vector = vector.borrow();
+                let (start, end) = expect_range(name, vector.len(), start, end)?;
+                Ok(vector[start..end].to_vec())
+            }
+            _ => logic_error!("{} takes one to three arguments", name),
+        }
+    }
+
+    fn vector_to_list(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let elements = vector_slice("vector->list", arguments)?;
+        Ok(ValueType::list(elements.into_iter()))
+    }
+
+    fn list_to_vector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => Ok(ValueType::vector(expect_list("list->vector", list?)?)),
+            _ => logic_error!("list->vector takes exactly one argument"),
+        }
+    }
+
+    fn vector_copy(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        Ok(ValueType::vector(vector_slice("vector-copy", arguments)?))
+    }
+
+    fn subvector(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        if arguments.len() != 3 {
+            logic_error!("subvector takes exactly three arguments")
+        }
+        let elements = vector_slice("subvector", Box::new(arguments.into_iter().map(Ok)))?;
+        Ok(ValueType::vector(elements))
+    }
+
+    fn vector_append(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let mut elements = vec![];
+        for vector in arguments {
+            elements.extend(
+                expect_vector("vector-append", vector?)?
+                    .borrow()
+                    .iter()
+                    .cloned(),
+            );
+        }
+        Ok(ValueType::vector(elements))
+    }
+
+    fn vector_fill(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(vector), Some(fill), start, end, None) => {
+                let vector = expect_vector("vector-fill!", vector?)?;
+                gc::register_vector(&vector);
+                let fill = fill?;
+                let mut vector = vector.borrow_mut();
+                let (start, end) = expect_range("vector-fill!", vector.len(), start, end)?;
+                for element in &mut vector[start..end] {
+                    *element = fill.clone();
+                }
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("vector-fill! takes two to four arguments"),
+        }
+    }
+
+    // the source is copied out first, so overlapping ranges of the same vector are handled
+    fn vector_copy_to(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(to), Some(at)) => {
+                let to = expect_vector("vector-copy!", to?)?;
+                gc::register_vector(&to);
+                let at = expect_index("vector-copy!", at?)?;
+                let from = arguments.collect::<Result<Vec<_>>>()?;
+                if from.is_empty() || from.len() > 3 {
+                    logic_error!("vector-copy! takes three to five arguments")
+                }
+                let elements = vector_slice("vector-copy!", Box::new(from.into_iter().map(Ok)))?;
+                let mut to = to.borrow_mut();
+                if at + elements.len() > to.len() {
+                    logic_error!(
+                        "vector-copy!: cannot copy {} elements to index {} of a vector of length {}",
+                        elements.len(),
+                        at,
+                        to.len()
+                    )
+                }
+                to[at..at + elements.len()].clone_from_slice(&elements);
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("vector-copy! takes three to five arguments"),
+        }
+    }
+
+    fn vector_to_string(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        Ok(ValueType::String(
+            vector_slice("vector->string", arguments)?
+                .into_iter()
+                .map(|c| expect_character("vector->string", c))
+                .collect::<Result<String>>()?,
+        ))
+    }
+
+    fn string_to_vector(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let chars = string_slice("string->vector", arguments)?;
+        Ok(ValueType::vector(
+            chars.into_iter().map(ValueType::Character).collect(),
+        ))
+    }
+
+    fn bytevector(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let bytes = arguments
+            .map(|byte| expect_byte("bytevector", byte?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ValueType::bytevector(bytes))
+    }
+
+    fn is_bytevector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(
+                value?,
+                ValueType::Bytevector(_)
+            ))),
+            _ => logic_error!("bytevector? takes exactly one argument"),
+        }
+    }
+
+    // the fill defaults to 0
+    fn make_bytevector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(k), fill, None) => {
+                let k = expect_index("make-bytevector", k?)?;
+                let fill = match fill {
+                    Some(fill) => expect_byte("make-bytevector", fill?)?,
+                    None => 0,
+                };
+                Ok(ValueType::bytevector(vec![fill; k]))
+            }
+            _ => logic_error!("make-bytevector takes one or two arguments"),
+        }
+    }
+
+    fn bytevector_length(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(bytes), None) => Ok(ValueType::Number(Number::Integer(
+                expect_bytevector("bytevector-length", bytes?)?
+                    .borrow()
+                    .len() as i64,
+            ))),
+            _ => logic_error!("bytevector-length takes exactly one argument"),
+        }
+    }
+
+    fn bytevector_u8_ref(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(bytes), Some(k), None) => {
+                let bytes = expect_bytevector("bytevector-u8-ref", bytes?)?;
+                let k = expect_index("bytevector-u8-ref", k?)?;
+                let bytes = bytes.borrow();
+                match bytes.get(k) {
+                    Some(byte) => Ok(ValueType::Number(Number::Integer(*byte as i64))),
+                    None => logic_error!(
+                        "bytevector-u8-ref: index {} out of range for length {}",
+                        k,
+                        bytes.len()
+                    ),
+                }
+            }
+            _ => logic_error!("bytevector-u8-ref takes exactly two arguments"),
+        }
+    }
+
+    fn bytevector_u8_set(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(bytes), Some(k), Some(byte), None) => {
+                let bytes = expect_bytevector("bytevector-u8-set!", bytes?)?;
+                let k = expect_index("bytevector-u8-set!", k?)?;
+                let byte = expect_byte("bytevector-u8-set!", byte?)?;
+                let mut bytes = bytes.borrow_mut();
+                let length = bytes.len();
+                match bytes.get_mut(k) {
+                    Some(element) => *element = byte,
+                    None => logic_error!(
+                        "bytevector-u8-set!: index {} out of range for length {}",
+                        k,
+                        length
+                    ),
+                }
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("bytevector-u8-set! takes exactly three arguments"),
+        }
+    }
+
+    // the bytes of a bytevector between the optional start and end arguments
+    fn bytevector_slice(
+        name: &str,
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<Vec<u8>> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(bytes), start, end, None) => {
+                let bytes = expect_bytevector(name, bytes?)?;
+                let bytes = bytes.borrow();
+                let (start, end) = expect_range(name, bytes.len(), start, end)?;
+                Ok(bytes[start..end].to_vec())
+            }
+            _ => logic_error!("{} takes one to three arguments", name),
+        }
+    }
+
+    fn bytevector_copy(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        Ok(ValueType::bytevector(bytevector_slice(
+            "bytevector-copy",
+            arguments,
+        )?))
+    }
+
+    // the source is copied out first, so overlapping ranges of the same bytevector are handled
+    fn bytevector_copy_to(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(to), Some(at)) => {
+                let to = expect_bytevector("bytevector-copy!", to?)?;
+                let at = expect_index("bytevector-copy!", at?)?;
+                let from = arguments.collect::<Result<Vec<_>>>()?;
+                if from.is_empty() || from.len() > 3 {
+                    logic_error!("bytevector-copy! takes three to five arguments")
+                }
+                let bytes =
+                    bytevector_slice("bytevector-copy!", Box::new(from.into_iter().map(Ok)))?;
+                let mut to = to.borrow_mut();
+                if at + bytes.len() > to.len() {
+                    logic_error!(
+                        "bytevector-copy!: cannot copy {} bytes to index {} of a bytevector of length {}",
+                        bytes.len(),
+                        at,
+                        to.len()
+                    )
+                }
+                to[at..at + bytes.len()].copy_from_slice(&bytes);
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("bytevector-copy! takes three to five arguments"),
+        }
+    }
+
+    fn bytevector_append(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let mut result = vec![];
+        for bytes in arguments {
+            result.extend_from_slice(&expect_bytevector("bytevector-append", bytes?)?.borrow());
+        }
+        Ok(ValueType::bytevector(result))
+    }
+
+    fn utf8_to_string(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let bytes = bytevector_slice("utf8->string", arguments)?;
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(ValueType::String(s)),
+            Err(e) => logic_error!(
+                "utf8->string: invalid UTF-8 sequence at byte {}",
+                e.utf8_error().valid_up_to()
+            ),
+        }
+    }
+
+    fn string_to_utf8(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let chars = string_slice("string->utf8", arguments)?;
+        Ok(ValueType::bytevector(
+            chars.into_iter().collect::<String>().into_bytes(),
+        ))
+    }
+
+    fn is_symbol(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Symbol(_)))),
+            _ => logic_error!("symbol? takes exactly one argument"),
+        }
+    }
+
+    // strings are values, so the result can never alias the name of the symbol
+    fn symbol_to_string(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(symbol), None) => match symbol? {
+                ValueType::Symbol(name) => Ok(ValueType::String(name.to_string())),
+                other => logic_error!("symbol->string: expect a symbol, got {}", other),
+            },
+            _ => logic_error!("symbol->string takes exactly one argument"),
+        }
+    }
+
+    fn string_to_symbol(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(s), None) => Ok(ValueType::Symbol(intern(&expect_string(
+                "string->symbol",
+                s?,
+            )?))),
+            _ => logic_error!("string->symbol takes exactly one argument"),
+        }
+    }
+
+    fn symbol_equals(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let names = arguments
+            .map(|symbol| match symbol? {
+                ValueType::Symbol(name) => Ok(name),
+                other => logic_error!("symbol=?: expect a symbol, got {}", other),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if names.is_empty() {
+            logic_error!("symbol=? requires at least one argument!")
+        }
+        Ok(ValueType::Boolean(
+            names.windows(2).all(|pair| pair[0] == pair[1]),
+        ))
+    }
+
+    fn string(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let mut s = String::new();
+        for c in arguments {
+            s.push(expect_character("string", c?)?);
+        }
+        Ok(ValueType::String(s))
+    }
+
+    fn string_length(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(s), None) => Ok(ValueType::Number(Number::Integer(
+                expect_string("string-length", s?)?.chars().count() as i64,
+            ))),
+            _ => logic_error!("string-length takes exactly one argument"),
+        }
+    }
+
+    fn string_ref(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(s), Some(k), None) => {
+                let s = expect_string("string-ref", s?)?;
+                let k = expect_index("string-ref", k?)?;
+                match s.chars().nth(k) {
+                    Some(c) => Ok(ValueType::Character(c)),
+                    None => logic_error!(
+                        "string-ref: index {} out of range for length {}",
+                        k,
+                        s.chars().count()
+                    ),
+                }
+            }
+            _ => logic_error!("string-ref takes exactly two arguments"),
+        }
+    }
+
+    // the characters of a string between the optional start and end arguments
+    fn string_slice(
+        name: &str,
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<Vec<char>> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(s), start, end, None) => {
+                let chars = expect_string(name, s?)?.chars().collect::<Vec<_>>();
+                let (start, end) = expect_range(name, chars.len(), start, end)?;
+                Ok(chars[start..end].to_vec())
+            }
+            _ => logic_error!("{} takes one to three arguments", name),
+        }
+    }
+
+    fn substring(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        if arguments.len() != 3 {
+            logic_error!("substring takes exactly three arguments")
+        }
+        let chars = string_slice("substring", Box::new(arguments.into_iter().map(Ok)))?;
+        Ok(ValueType::String(chars.into_iter().collect()))
+    }
+
+    fn string_copy(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let chars = string_slice("string-copy", arguments)?;
+        Ok(ValueType::String(chars.into_iter().collect()))
+    }
+
+    fn string_to_list(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let chars = string_slice("string->list", arguments)?;
+        Ok(ValueType::list(chars.into_iter().map(ValueType::Character)))
+    }
+
+    fn list_to_string(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => Ok(ValueType::String(
+                expect_list("list->string", list?)?
+                    .into_iter()
+                    .map(|c| expect_character("list->string", c))
+                    .collect::<Result<String>>()?,
+            )),
+            _ => logic_error!("list->string takes exactly one argument"),
+        }
+    }
+
+    fn string_append(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let mut result = String::new();
+        for s in arguments {
+            result.push_str(&expect_string("string-append", s?)?);
+        }
+        Ok(ValueType::String(result))
+    }
+
+    // string-contains, string-split and string-join are extensions beyond R7RS
+
+    // the character index of the first occurrence of the pattern
+    fn string_contains(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(s), Some(pattern), None) => {
+                let s = expect_string("string-contains", s?)?;
+                let pattern = expect_string("string-contains", pattern?)?;
+                Ok(match s.find(pattern.as_str()) {
+                    Some(i) => ValueType::Number(Number::Integer(s[..i].chars().count() as i64)),
+                    None => ValueType::Boolean(false),
+                })
+            }
+            _ => logic_error!("string-contains takes exactly two arguments"),
+        }
+    }
+
+    // the delimiter is a character or a non-empty string, empty fields are kept
+    fn string_split(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(s), Some(delimiter), None) => {
+                let s = expect_string("string-split", s?)?;
+                let fields: Vec<&str> = match delimiter? {
+                    ValueType::Character(c) => s.split(c).collect(),
+                    ValueType::String(d) if !d.is_empty() => s.split(d.as_str()).collect(),
+                    other => logic_error!(
+                        "string-split: expect a character or a non-empty string, got {}",
+                        other
+                    ),
+                };
+                Ok(ValueType::list(
+                    fields
+                        .into_iter()
+                        .map(|field| ValueType::String(field.to_string())),
+                ))
+            }
+            _ => logic_error!("string-split takes exactly two arguments"),
+        }
+    }
+
+    // the delimiter defaults to a single space
+    fn string_join(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(list), delimiter, None) => {
+                let delimiter = match delimiter {
+                    Some(d) => expect_string("string-join", d?)?,
+                    None => " ".to_string(),
+                };
+                Ok(ValueType::String(
+                    expect_list("string-join", list?)?
+                        .into_iter()
+                        .map(|s| expect_string("string-join", s))
+                        .collect::<Result<Vec<_>>>()?
+                        .join(&delimiter),
+                ))
+            }
+            _ => logic_error!("string-join takes one or two arguments"),
+        }
+    }
+
+    macro_rules! string_comparision {
+        ($name:tt, $ident:tt, $operator:tt) => {
+            fn $name(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                let strings = arguments
+                    .map(|s| expect_string($ident, s?))
+                    .collect::<Result<Vec<_>>>()?;
+                if strings.is_empty() {
+                    logic_error!("{} requires at least one argument!", $ident)
+                }
+                Ok(ValueType::Boolean(
+                    strings.windows(2).all(|pair| pair[0] $operator pair[1]),
+                ))
+            }
+        };
+    }
+
+    string_comparision!(string_equals, "string=?", ==);
+    string_comparision!(string_less, "string<?", <);
+    string_comparision!(string_greater, "string>?", >);
+    string_comparision!(string_less_equal, "string<=?", <=);
+    string_comparision!(string_greater_equal, "string>=?", >=);
+
+    macro_rules! char_comparision {
+        ($name:tt, $ident:tt, $operator:tt) => {
+            fn $name(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                let chars = arguments
+                    .map(|c| expect_character($ident, c?))
+                    .collect::<Result<Vec<_>>>()?;
+                if chars.is_empty() {
+                    logic_error!("{} requires at least one argument!", $ident)
+                }
+                Ok(ValueType::Boolean(
+                    chars.windows(2).all(|pair| pair[0] $operator pair[1]),
+                ))
+            }
+        };
+    }
+
+    char_comparision!(char_equals, "char=?", ==);
+    char_comparision!(char_less, "char<?", <);
+    char_comparision!(char_greater, "char>?", >);
+    char_comparision!(char_less_equal, "char<=?", <=);
+    char_comparision!(char_greater_equal, "char>=?", >=);
+
+    fn char_to_integer(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(c), None) => Ok(ValueType::Number(Number::Integer(expect_character(
+                "char->integer",
+                c?,
+            )? as i64))),
+            _ => logic_error!("char->integer takes exactly one argument"),
+        }
+    }
+
+    fn integer_to_char(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(n), None) => {
+                let n = expect_index("integer->char", n?)?;
+                match std::char::from_u32(n as u32).filter(|_| n <= u32::MAX as usize) {
+                    Some(c) => Ok(ValueType::Character(c)),
+                    None => logic_error!("integer->char: {} is not a unicode scalar value", n),
+                }
+            }
+            _ => logic_error!("integer->char takes exactly one argument"),
+        }
+    }
+
+    fn newline(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (port, None) => {
+                output_port("newline", port, interpreter)?.write_str("\n")?;
+                Ok(ValueType::Void)
             }
-        })
+            _ => logic_error!("newline takes zero or one argument"),
+        }
     }
 
-    fn sub(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-        let init = match arguments.next() {
-            None => logic_error!("'-' needs at least one argument"),
-            Some(first) => match first? {
-                ValueType::Number(first_num) => match arguments.next() {
-                    Some(second) => match second? {
-                        ValueType::Number(second_num) => ValueType::Number(first_num - second_num),
-                        _ => logic_error!("expect a number!"),
-                    },
-                    None => ValueType::Number(Number::Integer(0) - first_num),
-                },
-                _ => logic_error!("expect a number!"),
-            },
-        };
-        arguments.try_fold(init, |a, b| match (a, b?) {
-            (ValueType::Number(num1), ValueType::Number(num2)) => {
-                Ok(ValueType::Number(num1 - num2))
+    fn write_char(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(c), port, None) => {
+                let c = expect_character("write-char", c?)?;
+                output_port("write-char", port, interpreter)?.write_str(&c.to_string())?;
+                Ok(ValueType::Void)
             }
-            _ => logic_error!("expect a number!"),
-        })
+            _ => logic_error!("write-char takes one or two arguments"),
+        }
     }
 
-    fn mul(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-        arguments.try_fold(ValueType::Number(Number::Integer(1)), |a, b| {
-            match (a, b?) {
-                (ValueType::Number(num1), ValueType::Number(num2)) => {
-                    Ok(ValueType::Number(num1 * num2))
+    fn write_string(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(s), port, start, end, None) => {
+                let chars = expect_string("write-string", s?)?
+                    .chars()
+                    .collect::<Vec<_>>();
+                let port = output_port("write-string", port, interpreter)?;
+                let (start, end) = expect_range("write-string", chars.len(), start, end)?;
+                port.write_str(&chars[start..end].iter().collect::<String>())?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("write-string takes one to four arguments"),
+        }
+    }
+
+    macro_rules! read_character {
+        ($name:tt, $ident:tt, $method:ident) => {
+            fn $name(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (port, None) => Ok(match input_port($ident, port, interpreter)?.$method()? {
+                        Some(c) => ValueType::Character(c),
+                        None => ValueType::Eof,
+                    }),
+                    _ => logic_error!("{} takes zero or one argument", $ident),
                 }
-                _ => logic_error!("expect a number!"),
             }
-        })
+        };
     }
 
-    fn div(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-        let init = match arguments.next() {
-            None => logic_error!("'-' needs at least one argument"),
-            Some(first) => match first? {
-                ValueType::Number(first_num) => match arguments.next() {
-                    Some(second) => match second? {
-                        ValueType::Number(second_num) => {
-                            ValueType::Number((first_num / second_num)?)
-                        }
-                        _ => logic_error!("expect a number!"),
-                    },
-                    None => ValueType::Number((Number::Integer(1) / first_num)?),
+    read_character!(read_char, "read-char", read_char);
+    read_character!(peek_char, "peek-char", peek_char);
+
+    fn read_line(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (port, None) => Ok(
+                match input_port("read-line", port, interpreter)?.read_line()? {
+                    Some(line) => ValueType::String(line),
+                    None => ValueType::Eof,
                 },
-                _ => logic_error!("expect a number!"),
+            ),
+            _ => logic_error!("read-line takes zero or one argument"),
+        }
+    }
+
+    fn read_string(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(k), port, None) => {
+                let k = expect_index("read-string", k?)?;
+                let text = input_port("read-string", port, interpreter)?.read_string(k)?;
+                Ok(if text.is_empty() && k > 0 {
+                    ValueType::Eof
+                } else {
+                    ValueType::String(text)
+                })
+            }
+            _ => logic_error!("read-string takes one or two arguments"),
+        }
+    }
+
+    fn char_ready(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (port, None) => Ok(ValueType::Boolean(
+                input_port("char-ready?", port, interpreter)?.char_ready()?,
+            )),
+            _ => logic_error!("char-ready? takes zero or one argument"),
+        }
+    }
+
+    fn current_input_port(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Port(
+                interpreter.current_input_port.borrow().clone(),
+            )),
+            _ => logic_error!("current-input-port takes no arguments"),
+        }
+    }
+
+    fn open_input_string(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(s), None) => Ok(ValueType::Port(Rc::new(Port::open_input_string(
+                &expect_string("open-input-string", s?)?,
+            )))),
+            _ => logic_error!("open-input-string takes exactly one argument"),
+        }
+    }
+
+    fn open_output_string(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Port(Rc::new(Port::open_output_string()))),
+            _ => logic_error!("open-output-string takes no arguments"),
+        }
+    }
+
+    fn get_output_string(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(port), None) => match port? {
+                ValueType::Port(port) => Ok(ValueType::String(port.output_string()?)),
+                other => logic_error!("get-output-string: expect a port, got {}", other),
             },
-        };
-        arguments.try_fold(init, |a, b| match (a, b?) {
-            (ValueType::Number(num1), ValueType::Number(num2)) => {
-                Ok(ValueType::Number((num1 / num2)?))
+            _ => logic_error!("get-output-string takes exactly one argument"),
+        }
+    }
+
+    fn open_input_bytevector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(bytes), None) => {
+                let bytes = expect_bytevector("open-input-bytevector", bytes?)?;
+                let bytes = bytes.borrow().clone();
+                Ok(ValueType::Port(Rc::new(Port::open_input_bytevector(bytes))))
             }
-            _ => logic_error!("expect a number!"),
-        })
+            _ => logic_error!("open-input-bytevector takes exactly one argument"),
+        }
     }
 
-    // fn cond(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {}
+    fn open_output_bytevector(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Port(Rc::new(Port::open_output_bytevector()))),
+            _ => logic_error!("open-output-bytevector takes no arguments"),
+        }
+    }
 
-    macro_rules! comparision {
-        ($name:tt, $operator:tt) => {
-            fn $name(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-                match arguments.next() {
-                    None => Ok(ValueType::Boolean(true)),
-                    Some(first) => {
-                                let mut last = first?;
-                                for current in arguments {
-                                    match (last, current?) {
-                                        (ValueType::Number(a), ValueType::Number(b)) => {
-                                            if !(a $operator b) {
-                                                return Ok(ValueType::Boolean(false));
-                                            }
-                                            last = ValueType::Number(b);
-                                        }
-                                        _ => logic_error!("{} comparision can only between numbers!", stringify!($operator)),
-                                    }
-                                }
-                                Ok(ValueType::Boolean(true))
-                            }
+    fn get_output_bytevector(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(port), None) => match port? {
+                ValueType::Port(port) => Ok(ValueType::bytevector(port.output_bytevector()?)),
+                other => logic_error!("get-output-bytevector: expect a port, got {}", other),
+            },
+            _ => logic_error!("get-output-bytevector takes exactly one argument"),
+        }
+    }
+
+    fn eof_object(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Eof),
+            _ => logic_error!("eof-object takes no arguments"),
+        }
+    }
+
+    fn features(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::list(
+                crate::parser::features()
+                    .iter()
+                    .map(|feature| ValueType::Symbol(intern(feature))),
+            )),
+            _ => logic_error!("features takes no arguments"),
+        }
+    }
+
+    fn is_eof_object(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Eof))),
+            _ => logic_error!("eof-object? takes exactly one argument"),
+        }
+    }
 
+    macro_rules! read_byte {
+        ($name:tt, $ident:tt, $method:ident) => {
+            fn $name(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (port, None) => Ok(match input_port($ident, port, interpreter)?.$method()? {
+                        Some(byte) => ValueType::Number(Number::Integer(byte as i64)),
+                        None => ValueType::Eof,
+                    }),
+                    _ => logic_error!("{} takes zero or one argument", $ident),
                 }
             }
+        };
+    }
+
+    read_byte!(read_u8, "read-u8", read_u8);
+    read_byte!(peek_u8, "peek-u8", peek_u8);
+
+    // reading bytes from files and bytevectors never blocks
+    fn u8_ready(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (port, None) => {
+                let port = input_port("u8-ready?", port, interpreter)?;
+                if port.is_textual() {
+                    logic_error!("u8-ready?: {} is not a binary input port", port)
+                }
+                Ok(ValueType::Boolean(true))
+            }
+            _ => logic_error!("u8-ready? takes zero or one argument"),
         }
     }
 
-    comparision!(equals, ==);
-    comparision!(greater, >);
-    comparision!(greater_equal, >=);
-    comparision!(less, <);
-    comparision!(less_equal, <=);
+    fn read_bytevector(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(k), port, None) => {
+                let k = expect_index("read-bytevector", k?)?;
+                let port = input_port("read-bytevector", port, interpreter)?;
+                let bytes = port.read_bytes(k)?;
+                Ok(if bytes.is_empty() && k > 0 {
+                    ValueType::Eof
+                } else {
+                    ValueType::bytevector(bytes)
+                })
+            }
+            _ => logic_error!("read-bytevector takes one or two arguments"),
+        }
+    }
 
-    macro_rules! first_of_order {
-        ($name:tt, $cmp:tt) => {
-            fn $name(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-                match arguments.next() {
-                    None => logic_error!("min requires at least one argument!"),
-                    Some(Ok(ValueType::Number(num))) => {
-                        arguments.try_fold(ValueType::Number(num), |a, b| match (a, b?) {
-                                    (ValueType::Number(num1), ValueType::Number(num2)) => {
-                                        Ok(ValueType::Number(match num1 $cmp num2 {
-                                            true => upcast_oprands((num1, num2)).lhs(),
-                                            false => upcast_oprands((num1, num2)).rhs(),
-                                        }))
-                                    }
-                                    _ => logic_error!("expect a number!"),
-                                })
-                            },
-                    Some(Err(e)) => Err(e),
-                    _ => logic_error!("expect a number!"),
-                    }
+    // the number of bytes read into the range of the bytevector
+    fn read_bytevector_into(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(bytes), port, start, end, None) => {
+                let bytes = expect_bytevector("read-bytevector!", bytes?)?;
+                let port = input_port("read-bytevector!", port, interpreter)?;
+                let length = bytes.borrow().len();
+                let (start, end) = expect_range("read-bytevector!", length, start, end)?;
+                let read = port.read_bytes(end - start)?;
+                if read.is_empty() && end > start {
+                    return Ok(ValueType::Eof);
+                }
+                bytes.borrow_mut()[start..start + read.len()].copy_from_slice(&read);
+                Ok(ValueType::Number(Number::Integer(read.len() as i64)))
+            }
+            _ => logic_error!("read-bytevector! takes one to four arguments"),
+        }
+    }
+
+    fn write_u8(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(byte), port, None) => {
+                let byte = expect_byte("write-u8", byte?)?;
+                output_port("write-u8", port, interpreter)?.write_bytes(&[byte])?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("write-u8 takes one or two arguments"),
+        }
+    }
+
+    fn write_bytevector(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(bytes), port, start, end, None) => {
+                let bytes = expect_bytevector("write-bytevector", bytes?)?;
+                let port = output_port("write-bytevector", port, interpreter)?;
+                let bytes = bytes.borrow();
+                let (start, end) = expect_range("write-bytevector", bytes.len(), start, end)?;
+                port.write_bytes(&bytes[start..end])?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("write-bytevector takes one to four arguments"),
+        }
+    }
+
+    // after runs however the thunk is left, unless exit has already run it
+    fn dynamic_wind(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(before), Some(thunk), Some(after), None) => {
+                let (before, thunk, after) = (before?, thunk?, after?);
+                interpreter.call_procedure(&before, vec![])?;
+                let depth = {
+                    let mut winds = interpreter.winds.borrow_mut();
+                    winds.push(after.clone());
+                    winds.len()
+                };
+                let result = interpreter.call_procedure(&thunk, vec![]);
+                if interpreter.winds.borrow().len() < depth {
+                    return result;
                 }
+                interpreter.winds.borrow_mut().truncate(depth - 1);
+                interpreter.call_procedure(&after, vec![])?;
+                result
             }
+            _ => logic_error!("dynamic-wind takes exactly three arguments"),
+        }
     }
 
-    first_of_order!(max, >);
-    first_of_order!(min, <);
+    // the port is closed once the procedure returns
+    fn call_with_port(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(port), Some(procedure), None) => match port? {
+                ValueType::Port(port) => {
+                    let procedure = procedure?;
+                    let result = interpreter
+                        .call_procedure(&procedure, vec![ValueType::Port(port.clone())])?;
+                    port.close()?;
+                    Ok(result)
+                }
+                other => logic_error!("call-with-port: expect a port, got {}", other),
+            },
+            _ => logic_error!("call-with-port takes exactly two arguments"),
+        }
+    }
 
-    fn sqrt(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-        match arguments.next() {
-            Some(Ok(ValueType::Number(number))) => Ok(ValueType::Number(match number {
-                Number::Integer(num) => Number::Real((num as f64).sqrt()),
-                Number::Real(num) => Number::Real(num.sqrt()),
-                Number::Rational(a, b) => Number::Real((a as f64 / b as f64).sqrt()),
-            })),
-            Some(Err(e)) => Err(e),
-            Some(other) => logic_error!("sqrt requires a number, got {:?}", other),
-            _ => logic_error!("sqrt takes exactly one argument"),
+    fn current_output_port(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Port(
+                interpreter.current_output_port.borrow().clone(),
+            )),
+            _ => logic_error!("current-output-port takes no arguments"),
         }
     }
 
-    fn vector(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
-        let vector: Result<Vec<ValueType>> = arguments.collect();
-        Ok(ValueType::Vector(vector?))
+    fn current_error_port(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Port(
+                interpreter.current_error_port.borrow().clone(),
+            )),
+            _ => logic_error!("current-error-port takes no arguments"),
+        }
+    }
+
+    fn error(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let message = match arguments.next() {
+            Some(message) => expect_string("error", message?)?,
+            None => logic_error!("error takes at least one argument"),
+        };
+        let irritants = arguments.collect::<Result<Vec<_>>>()?;
+        Err(raise(ValueType::ErrorObject(Rc::new(ErrorObject {
+            category: ErrorType::Logic,
+            message,
+            irritants,
+        }))))
     }
 
-    fn display(
+    fn raise_procedure(
         mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
     ) -> Result<ValueType> {
-        Ok(match arguments.next() {
-            Some(value) => {
-                print!("{}", value?);
-                ValueType::Void
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Err(raise(value?)),
+            _ => logic_error!("raise takes exactly one argument"),
+        }
+    }
+
+    // the optional message and irritants are reported like those of error
+    fn assert(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.next() {
+            Some(test) => {
+                if test?.is_true() {
+                    return Ok(ValueType::Void);
+                }
+                let message = match arguments.next() {
+                    Some(message) => expect_string("assert", message?)?,
+                    None => "assertion failed".to_string(),
+                };
+                let irritants = arguments.collect::<Result<Vec<_>>>()?;
+                Err(raise(ValueType::ErrorObject(Rc::new(ErrorObject {
+                    category: ErrorType::Logic,
+                    message,
+                    irritants,
+                }))))
             }
-            None => logic_error!("display takes exactly one argument"),
-        })
+            None => logic_error!("assert takes at least one argument"),
+        }
+    }
+
+    macro_rules! error_object_predicate {
+        ($name:tt, $ident:tt, $($pattern:pat)|+) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(value), None) => Ok(ValueType::Boolean(match value? {
+                        ValueType::ErrorObject(object) => matches!(object.category, $($pattern)|+),
+                        _ => false,
+                    })),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    error_object_predicate!(is_error_object, "error-object?", _);
+    error_object_predicate!(is_file_error, "file-error?", ErrorType::File);
+    error_object_predicate!(
+        is_read_error,
+        "read-error?",
+        ErrorType::Lexical | ErrorType::Syntax(_)
+    );
+
+    fn error_object_message(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => match value? {
+                ValueType::ErrorObject(object) => Ok(ValueType::String(object.message.clone())),
+                other => logic_error!(
+                    "error-object-message: expect an error object, got {}",
+                    other
+                ),
+            },
+            _ => logic_error!("error-object-message takes exactly one argument"),
+        }
     }
 
-    fn newline(
+    fn error_object_irritants(
         mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
     ) -> Result<ValueType> {
-        Ok(match arguments.next() {
-            None => {
-                println!("");
-                ValueType::Void
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => match value? {
+                ValueType::ErrorObject(object) => {
+                    Ok(ValueType::list(object.irritants.iter().cloned()))
+                }
+                other => logic_error!(
+                    "error-object-irritants: expect an error object, got {}",
+                    other
+                ),
+            },
+            _ => logic_error!("error-object-irritants takes exactly one argument"),
+        }
+    }
+
+    // closing an already closed port does nothing
+    macro_rules! close_procedure {
+        ($name:tt, $ident:tt, $accept:expr, $expect:tt) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(port), None) => match port? {
+                        ValueType::Port(port) if $accept(&port) => {
+                            port.close()?;
+                            Ok(ValueType::Void)
+                        }
+                        other => logic_error!("{}: expect {}, got {}", $ident, $expect, other),
+                    },
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
             }
-            _ => logic_error!("display takes exactly one argument"),
-        })
+        };
     }
 
-    macro_rules! function_mapping {
-        ($ident:tt, $function:tt) => {
-            (
-                $ident.to_string(),
-                ValueType::Procedure(Procedure::Buildin(BuildinProcedure($ident, $function))),
-            )
+    close_procedure!(close_port, "close-port", |_: &Port| true, "a port");
+    close_procedure!(
+        close_input_port,
+        "close-input-port",
+        Port::is_input,
+        "an input port"
+    );
+    close_procedure!(
+        close_output_port,
+        "close-output-port",
+        Port::is_output,
+        "an output port"
+    );
+
+    fn flush_output_port(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (port, None) => {
+                output_port("flush-output-port", port, interpreter)?.flush()?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("flush-output-port takes zero or one argument"),
+        }
+    }
+
+    macro_rules! port_open_predicate {
+        ($name:tt, $ident:tt, $direction:expr, $expect:tt) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(port), None) => match port? {
+                        ValueType::Port(port) if $direction(&port) => {
+                            Ok(ValueType::Boolean(port.is_open()))
+                        }
+                        other => logic_error!("{}: expect {}, got {}", $ident, $expect, other),
+                    },
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    port_open_predicate!(
+        is_input_port_open,
+        "input-port-open?",
+        Port::is_input,
+        "an input port"
+    );
+    port_open_predicate!(
+        is_output_port_open,
+        "output-port-open?",
+        Port::is_output,
+        "an output port"
+    );
+
+    macro_rules! port_predicate {
+        ($name:tt, $ident:tt, $predicate:expr) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(value), None) => Ok(ValueType::Boolean(match value? {
+                        ValueType::Port(port) => $predicate(&port),
+                        _ => false,
+                    })),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
         };
     }
 
+    port_predicate!(is_port, "port?", |_: &Port| true);
+    port_predicate!(is_input_port, "input-port?", Port::is_input);
+    port_predicate!(is_output_port, "output-port?", Port::is_output);
+    port_predicate!(is_textual_port, "textual-port?", Port::is_textual);
+    port_predicate!(is_binary_port, "binary-port?", Port::is_binary);
+
     [
         function_mapping!("+", add),
         function_mapping!("-", sub),
         function_mapping!("*", mul),
         function_mapping!("/", div),
+        function_mapping!("quotient", quotient),
+        function_mapping!("remainder", remainder),
+        function_mapping!("modulo", modulo),
+        function_mapping!("floor/", floor_division),
+        function_mapping!("floor-quotient", floor_quotient),
+        function_mapping!("floor-remainder", modulo),
+        function_mapping!("truncate/", truncate_division),
+        function_mapping!("truncate-quotient", quotient),
+        function_mapping!("truncate-remainder", remainder),
+        function_mapping!("floor", floor),
+        function_mapping!("ceiling", ceiling),
+        function_mapping!("round", round),
+        function_mapping!("truncate", truncate),
+        function_mapping!("values", values),
+        interpreter_function_mapping!("call-with-values", call_with_values),
+        // what receive and let-values call
+        function_mapping!("gcd", gcd),
+        function_mapping!("lcm", lcm),
+        function_mapping!("abs", abs),
         function_mapping!("=", equals),
         function_mapping!("<", less),
         function_mapping!("<=", less_equal),
@@ -194,11 +2060,908 @@ pub(crate) fn base_library<'a>() -> HashMap<String, ValueType> {
         function_mapping!("min", min),
         function_mapping!("max", max),
         function_mapping!("sqrt", sqrt),
-        function_mapping!("display", display),
-        function_mapping!("newline", newline),
+        function_mapping!("exact-integer-sqrt", exact_integer_sqrt),
+        function_mapping!("expt", expt),
+        function_mapping!("number->string", number_to_string),
+        function_mapping!("string->number", string_to_number),
+        interpreter_function_mapping!("newline", newline),
+        interpreter_function_mapping!("write-char", write_char),
+        interpreter_function_mapping!("write-string", write_string),
+        interpreter_function_mapping!("current-input-port", current_input_port),
+        interpreter_function_mapping!("current-output-port", current_output_port),
+        interpreter_function_mapping!("call-with-port", call_with_port),
+        interpreter_function_mapping!("dynamic-wind", dynamic_wind),
+        function_mapping!("open-input-string", open_input_string),
+        function_mapping!("open-output-string", open_output_string),
+        function_mapping!("get-output-string", get_output_string),
+        function_mapping!("open-input-bytevector", open_input_bytevector),
+        function_mapping!("open-output-bytevector", open_output_bytevector),
+        function_mapping!("get-output-bytevector", get_output_bytevector),
+        function_mapping!("eof-object", eof_object),
+        function_mapping!("features", features),
+        function_mapping!("eof-object?", is_eof_object),
+        interpreter_function_mapping!("read-char", read_char),
+        interpreter_function_mapping!("peek-char", peek_char),
+        interpreter_function_mapping!("read-line", read_line),
+        interpreter_function_mapping!("read-string", read_string),
+        interpreter_function_mapping!("char-ready?", char_ready),
+        interpreter_function_mapping!("read-u8", read_u8),
+        interpreter_function_mapping!("peek-u8", peek_u8),
+        interpreter_function_mapping!("u8-ready?", u8_ready),
+        interpreter_function_mapping!("read-bytevector", read_bytevector),
+        interpreter_function_mapping!("read-bytevector!", read_bytevector_into),
+        interpreter_function_mapping!("write-u8", write_u8),
+        interpreter_function_mapping!("write-bytevector", write_bytevector),
+        interpreter_function_mapping!("current-error-port", current_error_port),
+        function_mapping!("port?", is_port),
+        function_mapping!("input-port?", is_input_port),
+        function_mapping!("output-port?", is_output_port),
+        function_mapping!("textual-port?", is_textual_port),
+        function_mapping!("binary-port?", is_binary_port),
+        function_mapping!("error", error),
+        function_mapping!("raise", raise_procedure),
+        function_mapping!("assert", assert),
+        function_mapping!("error-object?", is_error_object),
+        function_mapping!("file-error?", is_file_error),
+        function_mapping!("read-error?", is_read_error),
+        function_mapping!("error-object-message", error_object_message),
+        function_mapping!("error-object-irritants", error_object_irritants),
+        function_mapping!("close-port", close_port),
+        function_mapping!("close-input-port", close_input_port),
+        function_mapping!("close-output-port", close_output_port),
+        interpreter_function_mapping!("flush-output-port", flush_output_port),
+        function_mapping!("input-port-open?", is_input_port_open),
+        function_mapping!("output-port-open?", is_output_port_open),
         function_mapping!("vector", vector),
+        function_mapping!("vector?", is_vector),
+        function_mapping!("make-vector", make_vector),
+        function_mapping!("vector-length", vector_length),
+        function_mapping!("vector-ref", vector_ref),
+        function_mapping!("vector-set!", vector_set),
+        function_mapping!("vector->list", vector_to_list),
+        function_mapping!("list->vector", list_to_vector),
+        function_mapping!("vector-copy", vector_copy),
+        function_mapping!("subvector", subvector),
+        function_mapping!("vector-append", vector_append),
+        function_mapping!("vector-fill!", vector_fill),
+        function_mapping!("vector-copy!", vector_copy_to),
+        function_mapping!("vector->string", vector_to_string),
+        function_mapping!("string->vector", string_to_vector),
+        function_mapping!("pair?", is_pair),
+        function_mapping!("null?", is_null),
+        function_mapping!("list?", is_list),
+        function_mapping!("cons", cons),
+        function_mapping!("car", car),
+        function_mapping!("cdr", cdr),
+        function_mapping!("set-car!", set_car),
+        function_mapping!("set-cdr!", set_cdr),
+        function_mapping!("list", list),
+        function_mapping!("length", length),
+        function_mapping!("append", append),
+        function_mapping!("reverse", reverse),
+        function_mapping!("list-tail", list_tail_procedure),
+        function_mapping!("list-ref", list_ref),
+        function_mapping!("list-copy", list_copy),
+        function_mapping!("eq?", is_eq),
+        function_mapping!("eqv?", is_eqv),
+        function_mapping!("equal?", is_equal),
+        function_mapping!("memq", memq),
+        function_mapping!("memv", memv),
+        interpreter_function_mapping!("member", member),
+        function_mapping!("assq", assq),
+        function_mapping!("assv", assv),
+        interpreter_function_mapping!("assoc", assoc),
+        function_mapping!("bytevector", bytevector),
+        function_mapping!("bytevector?", is_bytevector),
+        function_mapping!("make-bytevector", make_bytevector),
+        function_mapping!("bytevector-length", bytevector_length),
+        function_mapping!("bytevector-u8-ref", bytevector_u8_ref),
+        function_mapping!("bytevector-u8-set!", bytevector_u8_set),
+        function_mapping!("bytevector-copy", bytevector_copy),
+        function_mapping!("bytevector-copy!", bytevector_copy_to),
+        function_mapping!("bytevector-append", bytevector_append),
+        function_mapping!("utf8->string", utf8_to_string),
+        function_mapping!("string->utf8", string_to_utf8),
+        function_mapping!("symbol?", is_symbol),
+        function_mapping!("symbol->string", symbol_to_string),
+        function_mapping!("string->symbol", string_to_symbol),
+        function_mapping!("symbol=?", symbol_equals),
+        function_mapping!("string", string),
+        function_mapping!("string-length", string_length),
+        function_mapping!("string-ref", string_ref),
+        function_mapping!("substring", substring),
+        function_mapping!("string-append", string_append),
+        function_mapping!("string-copy", string_copy),
+        function_mapping!("string->list", string_to_list),
+        function_mapping!("list->string", list_to_string),
+        function_mapping!("string-contains", string_contains),
+        function_mapping!("string-split", string_split),
+        function_mapping!("string-join", string_join),
+        function_mapping!("char=?", char_equals),
+        function_mapping!("char<?", char_less),
+        function_mapping!("char>?", char_greater),
+        function_mapping!("char<=?", char_less_equal),
+        function_mapping!("char>=?", char_greater_equal),
+        function_mapping!("char->integer", char_to_integer),
+        function_mapping!("integer->char", integer_to_char),
+        function_mapping!("string=?", string_equals),
+        function_mapping!("string<?", string_less),
+        function_mapping!("string>?", string_greater),
+        function_mapping!("string<=?", string_less_equal),
+        function_mapping!("string>=?", string_greater_equal),
     ]
     .iter()
     .cloned()
     .collect()
 }
+
+#[test]
+fn integer_operations() -> Result<()> {
+    let interpreter = Interpreter::new();
+    for (source, result) in [
+        ("(quotient 17 -5)", Number::Integer(-3)),
+        ("(remainder 17 -5)", Number::Integer(2)),
+        ("(modulo 17 -5)", Number::Integer(-3)),
+        ("(modulo -7 2.)", Number::Real(1.0)),
+        ("(gcd 32 -36)", Number::Integer(4)),
+        ("(gcd)", Number::Integer(0)),
+        ("(lcm 32 -36)", Number::Integer(288)),
+        ("(lcm 32.0 -36)", Number::Real(288.0)),
+        ("(lcm)", Number::Integer(1)),
+        ("(abs -7/2)", Number::Rational(7, 2)),
+        ("(- 1/2 1/2)", Number::Integer(0)),
+        ("(/ 6 4)", Number::Rational(3, 2)),
+        ("(/ 2)", Number::Rational(1, 2)),
+        ("(max 1/2 3)", Number::Integer(3)),
+        ("(min 1 2.0 3)", Number::Real(1.0)),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(source.chars())?,
+            Some(ValueType::Number(result.clone())),
+            "{}",
+            source
+        );
+    }
+    assert_eq!(
+        interpreter.eval("(modulo 1 0)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "division by exact zero".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(remainder 1)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "remainder takes exactly two arguments".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn integer_division_values() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_eq!(
+        interpreter.eval("(floor/ -5 2)".chars())?,
+        Some(ValueType::Values(vec![
+            ValueType::Number(Number::Integer(-3)),
+            ValueType::Number(Number::Integer(1))
+        ]))
+    );
+    assert_eq!(
+        interpreter.eval("(truncate/ -5 2)".chars())?,
+        Some(ValueType::Values(vec![
+            ValueType::Number(Number::Integer(-2)),
+            ValueType::Number(Number::Integer(-1))
+        ]))
+    );
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(call-with-values (lambda () (floor/ -5 2)) list)",
+                "(-3 1)",
+            ),
+            ("(call-with-values (lambda () (values)) list)", "()"),
+            (
+                "(call-with-values (lambda () 4) (lambda (x) (* x x)))",
+                "16",
+            ),
+            ("(receive (q r) (truncate/ -5 2) (list q r))", "(-2 -1)"),
+            (
+                "(let-values (((q r) (floor/ 7 2)) ((x) (values 10))) (+ q r x))",
+                "14",
+            ),
+            // the inits are evaluated where let-values is
+            (
+                "((lambda (q) (let-values (((q r) (floor/ 7 2)) ((s) q)) (list q r s))) 10)",
+                "(3 1 10)",
+            ),
+            ("(let-values () (define x 1) x)", "1"),
+            ("(receive (a . r) (values 1 2 3) (list a r))", "(1 (2 3))"),
+            ("(receive all (values 1 2) all)", "(1 2)"),
+            (
+                "(let-values (((a . r) (values 1 2)) (all (values))) (list a r all))",
+                "(1 (2) ())",
+            ),
+            // the forms bind and call nothing the program can name
+            (
+                "(define (|receive values| p c) 'hijacked) (receive (a) 1 a)",
+                "1",
+            ),
+            (
+                "(define |values 0 0| 100) (let-values (((a b) (values 1 2))) (list a |values 0 0|))",
+                "(1 100)",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(receive (a b . r) (values 1) a)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "expect at least 2 values, got 1".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(round 7/2)".chars())?,
+        Some(ValueType::Number(Number::Integer(4)))
+    );
+    assert_eq!(
+        interpreter.eval("(values 1)".chars())?,
+        Some(ValueType::Number(Number::Integer(1)))
+    );
+    Ok(())
+}
+
+#[test]
+fn chained_comparison() -> Result<()> {
+    let interpreter = Interpreter::new();
+    for (source, result) in [
+        ("(< 1 2 3)", true),
+        ("(< 1 3 2)", false),
+        ("(<= 1 1 2)", true),
+        ("(> 3 2.5 2)", true),
+        ("(>= 3 3 4)", false),
+        ("(= 1 1.0 2/2)", true),
+        ("(= 1/3 0.3333333333333333)", false),
+        ("(< 9007199254740992 9007199254740993)", true),
+        ("(= 9007199254740993 9007199254740992.0)", false),
+        ("(< 9007199254740992.0 9007199254740993)", true),
+        ("(< 1 (/ 0. 0.))", false),
+        ("(>= 1 (/ 0. 0.))", false),
+        ("(= (/ 0. 0.) (/ 0. 0.))", false),
+        ("(< 1 (/ 1. 0.))", true),
+        ("(< 1)", true),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(source.chars())?,
+            Some(ValueType::Boolean(*result)),
+            "{}",
+            source
+        );
+    }
+    assert_eq!(
+        interpreter.eval("(< 2 1 #t)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "< comparision can only between numbers!".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn number_string_conversion() -> Result<()> {
+    let interpreter = Interpreter::new();
+    for (source, result) in [
+        (
+            "(number->string 255 16)",
+            ValueType::String("ff".to_string()),
+        ),
+        (
+            "(number->string -1/2)",
+            ValueType::String("-1/2".to_string()),
+        ),
+        (
+            "(number->string 2.5 10)",
+            ValueType::String("2.5".to_string()),
+        ),
+        (
+            "(string->number \"#b101\")",
+            ValueType::Number(Number::Integer(5)),
+        ),
+        (
+            "(string->number \"ff\" 16)",
+            ValueType::Number(Number::Integer(255)),
+        ),
+        (
+            "(string->number \"1e2\")",
+            ValueType::Number(Number::Real(100.0)),
+        ),
+        ("(string->number \"one\")", ValueType::Boolean(false)),
+        // literals in sources read as string->number reads them
+        (
+            "(list (eqv? #xff (string->number \"#xff\")) (eqv? #b101 (string->number \"#b101\"))
+                   (eqv? #e1.5 (string->number \"#e1.5\")) (eqv? -inf.0 (string->number \"-inf.0\"))
+                   (eqv? #x10000000000000000 (string->number \"#x10000000000000000\")))",
+            ValueType::list(vec![ValueType::Boolean(true); 5].into_iter()),
+        ),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(source.chars())?,
+            Some(result.clone()),
+            "{}",
+            source
+        );
+    }
+    assert_eq!(
+        interpreter.eval("(number->string 1 3)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "radix should be one of 2, 8, 10 and 16, got 3".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn list_operations() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(cons 1 2)", "(1 . 2)"),
+            ("(list 1 '(2 3) \"s\")", "(1 (2 3) \"s\")"),
+            ("(car '(a b))", "a"),
+            ("(cdr '(a b))", "(b)"),
+            ("(length '(1 2 3))", "3"),
+            ("(append '(1) '(2 3) '() 4)", "(1 2 3 . 4)"),
+            ("(append)", "()"),
+            ("(reverse '(1 (2 3) 4))", "(4 (2 3) 1)"),
+            ("(list-tail '(1 2 3 4) 2)", "(3 4)"),
+            ("(list-ref '(a b c) 1)", "b"),
+            ("(list-copy '(1 2 . 3))", "(1 2 . 3)"),
+            ("(list? '(1 2))", "#t"),
+            ("(list? '(1 . 2))", "#f"),
+            ("(pair? '())", "#f"),
+            ("(null? '())", "#t"),
+            ("''a", "(quote a)"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(car '())".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "car: expect a pair, got ()".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(list-ref '(1 2) 2)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "list-ref: expect a pair, got ()".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn circular_list() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let program = "
+        (define x (list 1 2 3))
+        (set-cdr! (cdr (cdr x)) x)
+        (list? x)";
+    assert_eq!(
+        interpreter.eval(program.chars())?,
+        Some(ValueType::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.eval("(length x)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "length: expect a list, got <circular list>".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn member_and_assoc() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(memq 'c '(a b c d))", "(c d)"),
+            ("(memq 'e '(a b c))", "#f"),
+            ("(memv 1.0 '(1 1.0 2))", "(1.0 2)"),
+            ("(member '(b) '(a (b) c))", "((b) c)"),
+            ("(member 2.0 '(1 2 3) =)", "(2 3)"),
+            ("(member 2 '(1 2 3) (lambda (a b) (< a b)))", "(3)"),
+            ("(assq 'b '((a 1) (b 2)))", "(b 2)"),
+            ("(assv 5/2 '((2 a) (5/2 b)))", "(5/2 b)"),
+            ("(assoc '(a) '(((a)) ((b))))", "((a))"),
+            ("(assoc 2.0 '((1 one) (2 two)) =)", "(2 two)"),
+            ("(assoc 4 '((1 one)))", "#f"),
+            ("(equal? '(1 #(2 \"s\")) (list 1 (vector 2 \"s\")))", "#t"),
+            ("(eqv? 2 2.0)", "#f"),
+            ("(eq? '() '())", "#t"),
+        ],
+    )?;
+    let program = "
+        (define x (list 1 2))
+        (set-cdr! (cdr x) x)
+        (define y (list 1 2 1 2))
+        (set-cdr! (cdr (cdr (cdr y))) y)
+        (equal? x y)";
+    assert_eq!(
+        interpreter.eval(program.chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    // long lists are compared without recursing along them
+    let long = |last| {
+        ValueType::list((0..200_000).map(|n| ValueType::Number(Number::Integer(n.min(last)))))
+    };
+    assert!(long(200_000).equal(&long(200_000)));
+    assert!(!long(200_000).equal(&long(199_998)));
+    assert_eq!(
+        interpreter.eval("(assq 'a '(b))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "assq: expect a pair, got b".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(member 1 '(1) car)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "car takes exactly one argument".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn string_operations() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(string-length \"λx\")", "2"),
+            ("(string-ref \"abc\" 1)", "#\\b"),
+            ("(substring \"hello\" 1 3)", "\"el\""),
+            ("(string-append \"a\" \"\" \"bc\")", "\"abc\""),
+            ("(string-copy \"hello\" 2)", "\"llo\""),
+            ("(string->list \"a b\")", "(#\\a #\\space #\\b)"),
+            ("(list->string '(#\\a #\\x3bb))", "\"aλ\""),
+            ("(string #\\a #\\b)", "\"ab\""),
+            ("(string-contains \"λabcabc\" \"ca\")", "3"),
+            ("(string-contains \"abc\" \"d\")", "#f"),
+            ("(string-split \"a,b,,c\" #\\,)", "(\"a\" \"b\" \"\" \"c\")"),
+            ("(string-split \"a::b\" \"::\")", "(\"a\" \"b\")"),
+            ("(string-join '(\"a\" \"b\"))", "\"a b\""),
+            ("(string-join '(\"a\" \"b\") \", \")", "\"a, b\""),
+            ("(string<? \"abc\" \"abd\" \"b\")", "#t"),
+            ("(string=? \"a\" \"a\" \"b\")", "#f"),
+            ("(string>=? \"b\" \"b\" \"a\")", "#t"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(substring \"abc\" 2 1)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "substring: expect 0 <= start <= end <= 3, got start 2 and end 1".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(string-ref \"abc\" 3)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "string-ref: index 3 out of range for length 3".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn vector_operations() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(make-vector 2 'a)", "#(a a)"),
+            ("(vector-length #(1 2 3))", "3"),
+            ("(vector-ref #(1 2 3) 1)", "2"),
+            ("(vector->list #(1 2 3) 1)", "(2 3)"),
+            ("(list->vector '(1 (2)))", "#(1 (2))"),
+            ("(vector-copy #(1 2 3) 1 2)", "#(2)"),
+            ("(subvector #(1 2 3 4) 1 3)", "#(2 3)"),
+            ("(vector-append #(1) #() #(2 3))", "#(1 2 3)"),
+            ("(vector->string #(#\\a #\\b))", "\"ab\""),
+            ("(string->vector \"abc\" 1)", "#(#\\b #\\c)"),
+            ("(vector? #())", "#t"),
+        ],
+    )?;
+    let program = "
+        (define v (vector 1 2 3 4 5))
+        (vector-fill! v 0 3)
+        (vector-set! v 0 'x)
+        (vector-copy! v 1 v 0 2)
+        v";
+    assert_eq!(
+        format!("{}", interpreter.eval(program.chars())?.unwrap()),
+        "#(x x 2 0 0)"
+    );
+    assert_eq!(
+        interpreter.eval("(eqv? v v)".chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(eqv? v (vector-copy v))".chars())?,
+        Some(ValueType::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.eval("(vector-copy! v 4 #(1 2))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "vector-copy!: cannot copy 2 elements to index 4 of a vector of length 5"
+                .to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn bytevector_operations() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("#u8(1 2 255)", "#u8(1 2 255)"),
+            ("'#u8()", "#u8()"),
+            ("(bytevector 1 2)", "#u8(1 2)"),
+            ("(make-bytevector 2 7)", "#u8(7 7)"),
+            ("(bytevector-u8-ref #u8(5 6) 1)", "6"),
+            ("(bytevector-copy #u8(1 2 3) 1)", "#u8(2 3)"),
+            ("(bytevector-append #u8(1) #u8() #u8(2))", "#u8(1 2)"),
+            ("(utf8->string #u8(206 187 120))", "\"λx\""),
+            ("(utf8->string #u8(97 98 99) 1 2)", "\"b\""),
+            ("(string->utf8 \"aλ\")", "#u8(97 206 187)"),
+            ("(string->utf8 \"abc\" 2)", "#u8(99)"),
+            ("(equal? #u8(1 2) (bytevector 1 2))", "#t"),
+            ("(eqv? #u8(1 2) #u8(1 2))", "#f"),
+        ],
+    )?;
+    let program = "
+        (define b (bytevector 1 2 3 4 5))
+        (bytevector-u8-set! b 0 9)
+        (bytevector-copy! b 1 b 0 3)
+        b";
+    assert_eq!(
+        format!("{}", interpreter.eval(program.chars())?.unwrap()),
+        "#u8(9 9 2 3 5)"
+    );
+    assert_eq!(
+        interpreter.eval("(utf8->string #u8(97 255))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "utf8->string: invalid UTF-8 sequence at byte 1".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(bytevector 256)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "bytevector: expect a byte, got 256".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("#u8(1 a)".chars()),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::InvalidDatum(Datum::Symbol("a".into()))),
+            message: "bytevector elements should be integers between 0 and 255, got a".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn symbol_conversion() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(symbol->string 'abc)", "\"abc\""),
+            ("(symbol->string '|two words|)", "\"two words\""),
+            ("(string->symbol \"hello world\")", "|hello world|"),
+            ("(string->symbol \"\")", "||"),
+            ("(string->symbol \"42\")", "|42|"),
+            ("(string->symbol \"a|b\")", "|a\\|b|"),
+            ("'(|x y| z)", "(|x y| z)"),
+            ("(symbol=? 'a (string->symbol \"a\") '|a|)", "#t"),
+            ("(symbol=? 'a 'b)", "#f"),
+            ("(symbol? 'a)", "#t"),
+            ("(symbol? \"a\")", "#f"),
+        ],
+    )?;
+    Ok(())
+}
+
+#[test]
+fn string_ports() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let program = "
+        (define port (open-output-string))
+        (write 'a port)
+        (write-char #\\space port)
+        (display \"b\" port)
+        (newline port)
+        (write-string \"cde\" port 1 2)
+        (get-output-string port)";
+    assert_eq!(
+        interpreter.eval(program.chars())?,
+        Some(ValueType::String("a b\nd".to_string()))
+    );
+    assert_eq!(
+        interpreter.eval("(input-port? (open-input-string \"abc\"))".chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(get-output-string (current-output-port))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "<output port (stdout)> is not an open string output port".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(display 1 (open-input-string \"\"))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "display: expect an output port, got <input port (string)>".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn bytevector_ports() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(define in (open-input-bytevector #u8(1 2 3 4 5)))", None),
+            ("(peek-u8 in)", Some("1")),
+            ("(read-u8 in)", Some("1")),
+            ("(read-bytevector 2 in)", Some("#u8(2 3)")),
+            ("(define b (make-bytevector 4 0))", None),
+            ("(read-bytevector! b in 1)", Some("2")),
+            ("b", Some("#u8(0 4 5 0)")),
+            ("(read-u8 in)", Some("<eof>")),
+            ("(eof-object? (read-bytevector 1 in))", Some("#t")),
+            ("(define out (open-output-bytevector))", None),
+            ("(write-u8 7 out)", Some("Void")),
+            ("(write-bytevector #u8(1 2 3) out 1)", Some("Void")),
+            ("(get-output-bytevector out)", Some("#u8(7 2 3)")),
+            ("(binary-port? out)", Some("#t")),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(read-u8 (open-input-string \"a\"))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "<input port (string)> is not a binary input port".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn textual_input() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(define in (open-input-string \"héllo\\r\\nworld\\n\\nend\"))",
+                None,
+            ),
+            ("(char-ready? in)", Some("#t")),
+            ("(peek-char in)", Some("#\\h")),
+            ("(peek-char in)", Some("#\\h")),
+            ("(read-char in)", Some("#\\h")),
+            ("(read-string 2 in)", Some("\"él\"")),
+            ("(read-line in)", Some("\"lo\"")),
+            ("(read-line in)", Some("\"world\"")),
+            ("(read-line in)", Some("\"\"")),
+            ("(read-string 10 in)", Some("\"end\"")),
+            ("(read-line in)", Some("<eof>")),
+            ("(read-char in)", Some("<eof>")),
+            ("(peek-char in)", Some("<eof>")),
+            ("(read-string 0 in)", Some("\"\"")),
+            ("(read-string 1 in)", Some("<eof>")),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(read-char (open-input-bytevector #u8(1)))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "<input port (bytevector)> is not a textual input port".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn port_control() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(define in (open-input-string \"abc\"))", None),
+            ("(define out (open-output-string))", None),
+            ("(input-port-open? in)", Some("#t")),
+            ("(close-input-port in)", Some("Void")),
+            ("(input-port-open? in)", Some("#f")),
+            ("(close-port in)", Some("Void")),
+            ("(flush-output-port out)", Some("Void")),
+            ("(output-port-open? out)", Some("#t")),
+            ("(close-output-port out)", Some("Void")),
+            ("(close-output-port out)", Some("Void")),
+            ("(output-port-open? out)", Some("#f")),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(read-char in)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "cannot read from closed <input port (string)>".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(close-input-port out)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "close-input-port: expect an input port, got <output port (string)>"
+                .to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn error_objects() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(guard (e (#t 1)) 2)", "2"),
+            (
+                "(guard (e ((error-object? e) (list (error-object-message e) (error-object-irritants e)))) (error \"bad\" 1 'x))",
+                "(\"bad\" (1 x))",
+            ),
+            ("(guard (e ((symbol? e) e)) (raise 'oops))", "oops"),
+            ("(guard (e ((vector? e) 1) (else 2)) (raise 'oops))", "2"),
+            ("(guard (e ((assq 'a e))) (raise (list (cons 'a 42))))", "(a . 42)"),
+            (
+                "(guard (e ((file-error? e) #t)) (open-input-file \"/nonexistent/file\"))",
+                "#t",
+            ),
+            (
+                "(guard (e ((read-error? e) #t)) (read (open-input-string \"(1\")))",
+                "#t",
+            ),
+            (
+                "(guard (e ((error-object? e) (error-object-message e))) (car 1))",
+                "\"car: expect a pair, got 1\"",
+            ),
+            (
+                "(guard (e ((symbol? e) (list e))) (guard (e ((vector? e) e)) (raise 'inner)))",
+                "(inner)",
+            ),
+            ("(assert (= 1 1))", "Void"),
+        ],
+    )?;
+    let error = interpreter
+        .eval("(error \"out of range:\" 5 \"x\")".chars())
+        .unwrap_err();
+    assert_eq!(format!("{}", error), "error: out of range: 5 \"x\"");
+    let error = interpreter.eval("(raise 42)".chars()).unwrap_err();
+    assert_eq!(format!("{}", error), "error: uncaught exception 42");
+    let error = interpreter.eval("(assert (= 1 2))".chars()).unwrap_err();
+    assert_eq!(format!("{}", error), "error: assertion failed");
+    Ok(())
+}
+
+#[test]
+fn dynamic_wind() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "(define trail (make-vector 1 '()))
+         (define (note x) (vector-set! trail 0 (cons x (vector-ref trail 0))))
+         (define (noting x) (lambda () (note x)))"
+            .chars(),
+    )?;
+    for (source, result, trail) in [
+        (
+            "(dynamic-wind (noting 'in) (lambda () 1) (noting 'out))",
+            "1",
+            "(out in)",
+        ),
+        (
+            "(guard (e (#t e)) (dynamic-wind (noting 'in) (lambda () (raise 2)) (noting 'out)))",
+            "2",
+            "(out in)",
+        ),
+        (
+            "(dynamic-wind (noting 'a) (lambda () (dynamic-wind (noting 'b) (lambda () 3) (noting 'c))) (noting 'd))",
+            "3",
+            "(d c b a)",
+        ),
+    ]
+    .iter()
+    {
+        interpreter.eval("(vector-set! trail 0 '())".chars())?;
+        assert_eq!(
+            format!("{}", interpreter.eval(source.chars())?.unwrap()),
+            *result,
+            "{}",
+            source
+        );
+        assert_eq!(
+            format!("{}", interpreter.eval("(vector-ref trail 0)".chars())?.unwrap()),
+            *trail,
+            "{}",
+            source
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn features() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(car (features))", "r7rs"),
+            ("(car (memq 'ruschm (features)))", "ruschm"),
+            ("(cond-expand (r7rs 1) (else 2))", "1"),
+            ("(cond-expand ((not r7rs) 1) (else 2))", "2"),
+            (
+                "(cond-expand ((and r7rs (or no-such-feature ratios)) 'yes))",
+                "yes",
+            ),
+            (
+                "(cond-expand ((library (scheme base)) 'base) (else 'none))",
+                "base",
+            ),
+            (
+                "(cond-expand ((library (scheme no-such-library)) 'found) (else 'none))",
+                "none",
+            ),
+            // only the chosen clause needs to be valid code
+            ("(cond-expand (no-such-feature (define)) (else 3))", "3"),
+            (
+                "(cond-expand (r7rs (define a 1) (define b 2))) (+ a b)",
+                "3",
+            ),
+            ("(define (f) (cond-expand (else (define x 4))) x) (f)", "4"),
+            ("(+ 1 (cond-expand (else 4 5)))", "6"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(cond-expand ((unless r7rs) 1))".chars()),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::InvalidForm("cond-expand".to_string())),
+            message: "cond-expand: invalid feature requirement (unless r7rs)".to_string()
+        })
+    );
+    Ok(())
+}
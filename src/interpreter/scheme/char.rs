@@ -0,0 +1,284 @@
+use crate::interpreter::scheme::base::{expect_character, expect_string};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// full case foldings which differ from the lowercase mapping, from Unicode CaseFolding.txt
+const SPECIAL_FOLDINGS: [(char, &str); 14] = [
+    ('ß', "ss"),
+    ('ẞ', "ss"),
+    ('ς', "σ"),
+    ('ŉ', "\u{02bc}n"),
+    ('ǰ', "j\u{030c}"),
+    ('ΐ', "\u{03b9}\u{0308}\u{0301}"),
+    ('ΰ', "\u{03c5}\u{0308}\u{0301}"),
+    ('ﬀ', "ff"),
+    ('ﬁ', "fi"),
+    ('ﬂ', "fl"),
+    ('ﬃ', "ffi"),
+    ('ﬄ', "ffl"),
+    ('ﬅ', "st"),
+    ('ﬆ', "st"),
+];
+
+pub(crate) fn foldcase(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.chars() {
+        match SPECIAL_FOLDINGS.iter().find(|(special, _)| *special == c) {
+            Some((_, folding)) => folded.push_str(folding),
+            None => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+// the single character folding, characters folding to several characters are kept as is
+pub(crate) fn char_foldcase(c: char) -> char {
+    let folded = foldcase(&c.to_string());
+    let mut chars = folded.chars();
+    match (chars.next(), chars.next()) {
+        (Some(folded), None) => folded,
+        _ => c,
+    }
+}
+
+// the zeros of the Unicode decimal digit (Nd) ranges, each followed by the digits one to nine
+const DIGIT_ZEROS: [u32; 64] = [
+    0x0030, 0x0660, 0x06f0, 0x07c0, 0x0966, 0x09e6, 0x0a66, 0x0ae6, 0x0b66, 0x0be6, 0x0c66, 0x0ce6,
+    0x0d66, 0x0de6, 0x0e50, 0x0ed0, 0x0f20, 0x1040, 0x1090, 0x17e0, 0x1810, 0x1946, 0x19d0, 0x1a80,
+    0x1a90, 0x1b50, 0x1bb0, 0x1c40, 0x1c50, 0xa620, 0xa8d0, 0xa900, 0xa9d0, 0xa9f0, 0xaa50, 0xabf0,
+    0xff10, 0x104a0, 0x10d30, 0x11066, 0x110f0, 0x11136, 0x111d0, 0x112f0, 0x11450, 0x114d0,
+    0x11650, 0x116c0, 0x11730, 0x118e0, 0x11950, 0x11c50, 0x11d50, 0x11da0, 0x16a60, 0x16b50,
+    0x1d7ce, 0x1d7d8, 0x1d7e2, 0x1d7ec, 0x1d7f6, 0x1e140, 0x1e2f0, 0x1e950,
+];
+
+pub(crate) fn digit_value(c: char) -> Option<u32> {
+    let c = c as u32;
+    DIGIT_ZEROS
+        .iter()
+        .find(|zero| (**zero..**zero + 10).contains(&c))
+        .map(|zero| c - zero)
+}
+
+pub(crate) fn char_library() -> HashMap<String, ValueType> {
+    macro_rules! string_case {
+        ($name:tt, $ident:tt, $convert:expr) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(s), None) => Ok(ValueType::String($convert(
+                        expect_string($ident, s?)?.as_str(),
+                    ))),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    string_case!(string_upcase, "string-upcase", str::to_uppercase);
+    string_case!(string_downcase, "string-downcase", str::to_lowercase);
+    string_case!(string_foldcase, "string-foldcase", foldcase);
+
+    macro_rules! string_ci_comparision {
+        ($name:tt, $ident:tt, $operator:tt) => {
+            fn $name(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                let strings = arguments
+                    .map(|s| Ok(foldcase(&expect_string($ident, s?)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                if strings.is_empty() {
+                    logic_error!("{} requires at least one argument!", $ident)
+                }
+                Ok(ValueType::Boolean(
+                    strings.windows(2).all(|pair| pair[0] $operator pair[1]),
+                ))
+            }
+        };
+    }
+
+    string_ci_comparision!(string_ci_equals, "string-ci=?", ==);
+    string_ci_comparision!(string_ci_less, "string-ci<?", <);
+    string_ci_comparision!(string_ci_greater, "string-ci>?", >);
+    string_ci_comparision!(string_ci_less_equal, "string-ci<=?", <=);
+    string_ci_comparision!(string_ci_greater_equal, "string-ci>=?", >=);
+
+    macro_rules! char_ci_comparision {
+        ($name:tt, $ident:tt, $operator:tt) => {
+            fn $name(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                let chars = arguments
+                    .map(|c| Ok(char_foldcase(expect_character($ident, c?)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                if chars.is_empty() {
+                    logic_error!("{} requires at least one argument!", $ident)
+                }
+                Ok(ValueType::Boolean(
+                    chars.windows(2).all(|pair| pair[0] $operator pair[1]),
+                ))
+            }
+        };
+    }
+
+    char_ci_comparision!(char_ci_equals, "char-ci=?", ==);
+    char_ci_comparision!(char_ci_less, "char-ci<?", <);
+    char_ci_comparision!(char_ci_greater, "char-ci>?", >);
+    char_ci_comparision!(char_ci_less_equal, "char-ci<=?", <=);
+    char_ci_comparision!(char_ci_greater_equal, "char-ci>=?", >=);
+
+    macro_rules! char_predicate {
+        ($name:tt, $ident:tt, $predicate:expr) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(c), None) => Ok(ValueType::Boolean($predicate(expect_character(
+                        $ident, c?,
+                    )?))),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    char_predicate!(is_alphabetic, "char-alphabetic?", char::is_alphabetic);
+    char_predicate!(is_numeric, "char-numeric?", |c| digit_value(c).is_some());
+    char_predicate!(is_whitespace, "char-whitespace?", char::is_whitespace);
+    char_predicate!(is_upper_case, "char-upper-case?", char::is_uppercase);
+    char_predicate!(is_lower_case, "char-lower-case?", char::is_lowercase);
+
+    macro_rules! char_case {
+        ($name:tt, $ident:tt, $convert:expr) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(c), None) => {
+                        let c = expect_character($ident, c?)?;
+                        let mut converted = $convert(c);
+                        // mappings to several characters leave the character unchanged
+                        Ok(ValueType::Character(
+                            match (converted.next(), converted.next()) {
+                                (Some(converted), None) => converted,
+                                _ => c,
+                            },
+                        ))
+                    }
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    char_case!(char_upcase, "char-upcase", char::to_uppercase);
+    char_case!(char_downcase, "char-downcase", char::to_lowercase);
+
+    fn char_foldcase_procedure(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(c), None) => Ok(ValueType::Character(char_foldcase(expect_character(
+                "char-foldcase",
+                c?,
+            )?))),
+            _ => logic_error!("char-foldcase takes exactly one argument"),
+        }
+    }
+
+    fn digit_value_procedure(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(c), None) => Ok(match digit_value(expect_character("digit-value", c?)?) {
+                Some(value) => ValueType::Number(Number::Integer(value as i64)),
+                None => ValueType::Boolean(false),
+            }),
+            _ => logic_error!("digit-value takes exactly one argument"),
+        }
+    }
+
+    [
+        function_mapping!("char-ci=?", char_ci_equals),
+        function_mapping!("char-ci<?", char_ci_less),
+        function_mapping!("char-ci>?", char_ci_greater),
+        function_mapping!("char-ci<=?", char_ci_less_equal),
+        function_mapping!("char-ci>=?", char_ci_greater_equal),
+        function_mapping!("char-alphabetic?", is_alphabetic),
+        function_mapping!("char-numeric?", is_numeric),
+        function_mapping!("char-whitespace?", is_whitespace),
+        function_mapping!("char-upper-case?", is_upper_case),
+        function_mapping!("char-lower-case?", is_lower_case),
+        function_mapping!("char-upcase", char_upcase),
+        function_mapping!("char-downcase", char_downcase),
+        function_mapping!("char-foldcase", char_foldcase_procedure),
+        function_mapping!("digit-value", digit_value_procedure),
+        function_mapping!("string-upcase", string_upcase),
+        function_mapping!("string-downcase", string_downcase),
+        function_mapping!("string-foldcase", string_foldcase),
+        function_mapping!("string-ci=?", string_ci_equals),
+        function_mapping!("string-ci<?", string_ci_less),
+        function_mapping!("string-ci>?", string_ci_greater),
+        function_mapping!("string-ci<=?", string_ci_less_equal),
+        function_mapping!("string-ci>=?", string_ci_greater_equal),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn string_case() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(string-upcase \"straße\")", "\"STRASSE\""),
+            ("(string-downcase \"ΟΔΟΣ\")", "\"οδος\""),
+            ("(string-foldcase \"Straße\")", "\"strasse\""),
+            ("(string-foldcase \"ﬁΣ\")", "\"fiσ\""),
+            ("(string-ci=? \"STRASSE\" \"Straße\" \"strasse\")", "#t"),
+            ("(string-ci<? \"apple\" \"Banana\")", "#t"),
+            ("(string<? \"apple\" \"Banana\")", "#f"),
+            ("(string-ci>=? \"b\" \"B\" \"a\")", "#t"),
+        ],
+    )?;
+    Ok(())
+}
+
+#[test]
+fn char_procedures() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(char<? #\\a #\\b #\\c)", "#t"),
+            ("(char=? #\\a #\\A)", "#f"),
+            ("(char-ci=? #\\a #\\A)", "#t"),
+            ("(char-ci=? #\\ς #\\Σ)", "#t"),
+            ("(char->integer #\\λ)", "955"),
+            ("(integer->char 65)", "#\\A"),
+            ("(char-alphabetic? #\\λ)", "#t"),
+            ("(char-numeric? #\\٣)", "#t"),
+            ("(char-numeric? #\\½)", "#f"),
+            ("(char-whitespace? #\\tab)", "#t"),
+            ("(char-upper-case? #\\A)", "#t"),
+            ("(char-lower-case? #\\A)", "#f"),
+            ("(digit-value #\\7)", "7"),
+            ("(digit-value #\\x0664)", "4"),
+            ("(digit-value #\\a)", "#f"),
+            ("(char-upcase #\\ß)", "#\\ß"),
+            ("(char-upcase #\\ä)", "#\\Ä"),
+            ("(char-downcase #\\A)", "#\\a"),
+            ("(char-foldcase #\\Σ)", "#\\σ"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(integer->char 55296)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "integer->char: 55296 is not a unicode scalar value".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use crate::interpreter::scheme::base::expect_pair;
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// the letters between 'c' and 'r' are applied from right to left, so cadr is car of cdr
+fn apply_accessors(name: &str, value: ValueType) -> Result<ValueType> {
+    name[1..name.len() - 1]
+        .chars()
+        .rev()
+        .try_fold(value, |value, accessor| {
+            let pair = expect_pair(name, value)?;
+            let pair = pair.borrow();
+            Ok(match accessor {
+                'a' => pair.car.clone(),
+                _ => pair.cdr.clone(),
+            })
+        })
+}
+
+pub(crate) fn cxr_library() -> HashMap<String, ValueType> {
+    macro_rules! accessor {
+        ($($name:ident),*) => {{
+            $(
+                fn $name(
+                    mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+                ) -> Result<ValueType> {
+                    match (arguments.next(), arguments.next()) {
+                        (Some(value), None) => apply_accessors(stringify!($name), value?),
+                        _ => logic_error!("{} takes exactly one argument", stringify!($name)),
+                    }
+                }
+            )*
+            [$(function_mapping!(stringify!($name), $name)),*]
+                .iter()
+                .cloned()
+                .collect()
+        }};
+    }
+
+    accessor!(
+        caar, cadr, cdar, cddr, caaar, caadr, cadar, caddr, cdaar, cdadr, cddar, cdddr, caaaar,
+        caaadr, caadar, caaddr, cadaar, cadadr, caddar, cadddr, cdaaar, cdaadr, cdadar, cdaddr,
+        cddaar, cddadr, cdddar, cddddr
+    )
+}
+
+#[test]
+fn accessors() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(cadr '(1 2 3))", "2"),
+            ("(cddr '(1 2 3))", "(3)"),
+            ("(caar '((1) 2))", "1"),
+            ("(caddr '(1 2 3))", "3"),
+            ("(cadadr '(1 (2 3)))", "3"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(caddr '(1 2))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "caddr: expect a pair, got ()".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,392 @@
+use crate::interpreter::port::file_error;
+use crate::interpreter::scheme::base::expect_string;
+use crate::interpreter::srfi::srfi_19::{Time, TimeType};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// applies the procedure to the port, and closes the port once the procedure returns
+fn call_with_port(
+    interpreter: &Interpreter,
+    port: Rc<Port>,
+    procedure: &ValueType,
+) -> Result<ValueType> {
+    let result = interpreter.call_procedure(procedure, vec![ValueType::Port(port.clone())])?;
+    port.close()?;
+    Ok(result)
+}
+
+// calls the thunk with a current port rebound, the previous port is restored even on errors
+fn call_with_current_port(
+    interpreter: &Interpreter,
+    current: &RefCell<Rc<Port>>,
+    port: Rc<Port>,
+    thunk: &ValueType,
+) -> Result<ValueType> {
+    let previous = current.replace(port.clone());
+    let result = interpreter.call_procedure(thunk, vec![]);
+    current.replace(previous);
+    port.close()?;
+    result
+}
+
+pub(crate) fn file_library() -> HashMap<String, ValueType> {
+    fn open_input_file(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("open-input-file", path?)?;
+                Ok(ValueType::Port(Rc::new(Port::open_input_file(&path)?)))
+            }
+            _ => logic_error!("open-input-file takes exactly one argument"),
+        }
+    }
+
+    fn open_output_file(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("open-output-file", path?)?;
+                Ok(ValueType::Port(Rc::new(Port::open_output_file(&path)?)))
+            }
+            _ => logic_error!("open-output-file takes exactly one argument"),
+        }
+    }
+
+    fn open_binary_input_file(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("open-binary-input-file", path?)?;
+                Ok(ValueType::Port(Rc::new(Port::open_binary_input_file(
+                    &path,
+                )?)))
+            }
+            _ => logic_error!("open-binary-input-file takes exactly one argument"),
+        }
+    }
+
+    fn open_binary_output_file(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("open-binary-output-file", path?)?;
+                Ok(ValueType::Port(Rc::new(Port::open_binary_output_file(
+                    &path,
+                )?)))
+            }
+            _ => logic_error!("open-binary-output-file takes exactly one argument"),
+        }
+    }
+
+    fn call_with_input_file(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(path), Some(procedure), None) => {
+                let path = expect_string("call-with-input-file", path?)?;
+                let procedure = procedure?;
+                let port = Rc::new(Port::open_input_file(&path)?);
+                call_with_port(interpreter, port, &procedure)
+            }
+            _ => logic_error!("call-with-input-file takes exactly two arguments"),
+        }
+    }
+
+    fn call_with_output_file(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(path), Some(procedure), None) => {
+                let path = expect_string("call-with-output-file", path?)?;
+                let procedure = procedure?;
+                let port = Rc::new(Port::open_output_file(&path)?);
+                call_with_port(interpreter, port, &procedure)
+            }
+            _ => logic_error!("call-with-output-file takes exactly two arguments"),
+        }
+    }
+
+    fn with_input_from_file(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(path), Some(thunk), None) => {
+                let path = expect_string("with-input-from-file", path?)?;
+                let thunk = thunk?;
+                let port = Rc::new(Port::open_input_file(&path)?);
+                call_with_current_port(interpreter, &interpreter.current_input_port, port, &thunk)
+            }
+            _ => logic_error!("with-input-from-file takes exactly two arguments"),
+        }
+    }
+
+    fn with_output_to_file(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(path), Some(thunk), None) => {
+                let path = expect_string("with-output-to-file", path?)?;
+                let thunk = thunk?;
+                let port = Rc::new(Port::open_output_file(&path)?);
+                call_with_current_port(interpreter, &interpreter.current_output_port, port, &thunk)
+            }
+            _ => logic_error!("with-output-to-file takes exactly two arguments"),
+        }
+    }
+
+    fn file_exists(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => Ok(ValueType::Boolean(
+                std::path::Path::new(&expect_string("file-exists?", path?)?).exists(),
+            )),
+            _ => logic_error!("file-exists? takes exactly one argument"),
+        }
+    }
+
+    fn delete_file(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("delete-file", path?)?;
+                match std::fs::remove_file(&path) {
+                    Ok(()) => Ok(ValueType::Void),
+                    Err(e) => Err(file_error("delete file", &path, e)),
+                }
+            }
+            _ => logic_error!("delete-file takes exactly one argument"),
+        }
+    }
+
+    fn rename_file(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(from), Some(to), None) => {
+                let from = expect_string("rename-file", from?)?;
+                let to = expect_string("rename-file", to?)?;
+                match std::fs::rename(&from, &to) {
+                    Ok(()) => Ok(ValueType::Void),
+                    Err(e) => Err(file_error("rename file", &from, e)),
+                }
+            }
+            _ => logic_error!("rename-file takes exactly two arguments"),
+        }
+    }
+
+    fn create_directory(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("create-directory", path?)?;
+                match std::fs::create_dir(&path) {
+                    Ok(()) => Ok(ValueType::Void),
+                    Err(e) => Err(file_error("create directory", &path, e)),
+                }
+            }
+            _ => logic_error!("create-directory takes exactly one argument"),
+        }
+    }
+
+    // only empty directories are deleted
+    fn delete_directory(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("delete-directory", path?)?;
+                match std::fs::remove_dir(&path) {
+                    Ok(()) => Ok(ValueType::Void),
+                    Err(e) => Err(file_error("delete directory", &path, e)),
+                }
+            }
+            _ => logic_error!("delete-directory takes exactly one argument"),
+        }
+    }
+
+    // the names of the entries, sorted so the result does not depend on the file system
+    fn directory_files(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("directory-files", path?)?;
+                let mut names = std::fs::read_dir(&path)
+                    .and_then(|entries| {
+                        entries
+                            .map(|entry| {
+                                entry.map(|entry| entry.file_name().to_string_lossy().into_owned())
+                            })
+                            .collect::<std::io::Result<Vec<_>>>()
+                    })
+                    .map_err(|e| file_error("read directory", &path, e))?;
+                names.sort();
+                Ok(ValueType::list(names.into_iter().map(ValueType::String)))
+            }
+            _ => logic_error!("directory-files takes exactly one argument"),
+        }
+    }
+
+    fn file_size(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("file-size", path?)?;
+                match std::fs::metadata(&path) {
+                    Ok(metadata) => Ok(ValueType::Number(Number::Integer(metadata.len() as i64))),
+                    Err(e) => Err(file_error("read metadata of", &path, e)),
+                }
+            }
+            _ => logic_error!("file-size takes exactly one argument"),
+        }
+    }
+
+    // the modification time is a time-utc of srfi 19
+    fn file_mtime(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(path), None) => {
+                let path = expect_string("file-mtime", path?)?;
+                let modified = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|e| file_error("read metadata of", &path, e))?;
+                let nanoseconds = match modified.duration_since(std::time::UNIX_EPOCH) {
+                    Ok(elapsed) => elapsed.as_nanos() as i128,
+                    Err(e) => -(e.duration().as_nanos() as i128),
+                };
+                Ok(ValueType::Time(Time::from_nanoseconds(
+                    TimeType::Utc,
+                    nanoseconds,
+                )))
+            }
+            _ => logic_error!("file-mtime takes exactly one argument"),
+        }
+    }
+
+    [
+        function_mapping!("open-input-file", open_input_file),
+        function_mapping!("open-output-file", open_output_file),
+        function_mapping!("open-binary-input-file", open_binary_input_file),
+        function_mapping!("open-binary-output-file", open_binary_output_file),
+        interpreter_function_mapping!("call-with-input-file", call_with_input_file),
+        interpreter_function_mapping!("call-with-output-file", call_with_output_file),
+        interpreter_function_mapping!("with-input-from-file", with_input_from_file),
+        interpreter_function_mapping!("with-output-to-file", with_output_to_file),
+        function_mapping!("file-exists?", file_exists),
+        function_mapping!("delete-file", delete_file),
+        function_mapping!("rename-file", rename_file),
+        function_mapping!("create-directory", create_directory),
+        function_mapping!("delete-directory", delete_directory),
+        function_mapping!("directory-files", directory_files),
+        function_mapping!("file-size", file_size),
+        function_mapping!("file-mtime", file_mtime),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn file_ports() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let directory = std::env::temp_dir().join(format!("ruschm-file-ports-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("out.scm").to_str().unwrap().to_string();
+    let program = format!(
+        "
+        (define path \"{}\")
+        (call-with-output-file path (lambda (port) (write '(1 \"a\") port) (output-port? port)))",
+        path
+    );
+    assert_eq!(
+        interpreter.eval(program.chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "(1 \"a\")");
+    let program = "
+        (with-output-to-file path (lambda () (display \"x\") (newline) 'done))";
+    assert_eq!(
+        format!("{}", interpreter.eval(program.chars())?.unwrap()),
+        "done"
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "x\n");
+    assert_eq!(
+        interpreter.eval("(call-with-input-file path input-port?)".chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    // the current output port is restored when the thunk fails
+    assert!(interpreter
+        .eval("(with-output-to-file path (lambda () (car '())))".chars())
+        .is_err());
+    assert_eq!(
+        interpreter.eval("(eq? (current-output-port) (current-output-port))".chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            interpreter.eval("(current-output-port)".chars())?.unwrap()
+        ),
+        "<output port (stdout)>"
+    );
+    let program = "
+        (call-with-port (open-binary-output-file path)
+          (lambda (port) (write-bytevector #u8(1 2 3) port)))
+        (call-with-port (open-binary-input-file path)
+          (lambda (port) (list (read-u8 port) (read-bytevector 5 port) (read-u8 port))))";
+    assert_eq!(
+        format!("{}", interpreter.eval(program.chars())?.unwrap()),
+        "(1 #u8(2 3) <eof>)"
+    );
+    interpreter.eval("(delete-file path)".chars())?;
+    assert_eq!(
+        interpreter.eval("(file-exists? path)".chars())?,
+        Some(ValueType::Boolean(false))
+    );
+    assert!(matches!(
+        interpreter.eval("(open-input-file path)".chars()),
+        Err(Error {
+            category: ErrorType::File,
+            ..
+        })
+    ));
+    std::fs::remove_dir_all(&directory).unwrap();
+    Ok(())
+}
+
+#[test]
+fn file_system() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let directory = std::env::temp_dir().join(format!("ruschm-file-system-{}", std::process::id()));
+    let program = format!(
+        "
+        (define directory \"{}\")
+        (create-directory directory)
+        (define (in-directory name) (string-append directory \"/\" name))
+        (call-with-output-file (in-directory \"b\") (lambda (port) (display \"12345\" port)))
+        (rename-file (in-directory \"b\") (in-directory \"a\"))
+        (create-directory (in-directory \"c\"))
+        (list (directory-files directory) (file-size (in-directory \"a\")) (time? (file-mtime (in-directory \"a\"))))",
+        directory.to_str().unwrap()
+    );
+    assert_eq!(
+        format!("{}", interpreter.eval(program.chars())?.unwrap()),
+        "((\"a\" \"c\") 5 #t)"
+    );
+    // directories must be empty to be deleted
+    assert!(matches!(
+        interpreter.eval("(delete-directory directory)".chars()),
+        Err(Error {
+            category: ErrorType::File,
+            ..
+        })
+    ));
+    let program = "
+        (delete-file (in-directory \"a\"))
+        (delete-directory (in-directory \"c\"))
+        (directory-files directory)";
+    assert_eq!(
+        format!("{}", interpreter.eval(program.chars())?.unwrap()),
+        "()"
+    );
+    interpreter.eval("(delete-directory directory)".chars())?;
+    assert_eq!(
+        format!(
+            "{}",
+            interpreter
+                .eval("(guard (e ((file-error? e) 'gone)) (file-size directory))".chars())?
+                .unwrap()
+        ),
+        "gone"
+    );
+    Ok(())
+}
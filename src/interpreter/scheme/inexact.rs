@@ -0,0 +1,106 @@
+use crate::interpreter::scheme::base::expect_number;
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+pub(crate) fn inexact_library() -> HashMap<String, ValueType> {
+    macro_rules! unary_real_function {
+        ($name:tt, $ident:tt, $method:ident) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(value), None) => Ok(ValueType::Number(Number::Real(
+                        expect_number(value?)?.to_f64().$method(),
+                    ))),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    unary_real_function!(exp, "exp", exp);
+    unary_real_function!(sin, "sin", sin);
+    unary_real_function!(cos, "cos", cos);
+    unary_real_function!(tan, "tan", tan);
+    unary_real_function!(asin, "asin", asin);
+    unary_real_function!(acos, "acos", acos);
+
+    fn log(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(value), None, None) => {
+                Ok(ValueType::Number(Number::Real(expect_number(value?)?.ln())))
+            }
+            (Some(value), Some(base), None) => {
+                let value = expect_number(value?)?.ln();
+                let base = expect_number(base?)?.ln();
+                Ok(ValueType::Number(Number::Real(value / base)))
+            }
+            _ => logic_error!("log takes one or two arguments"),
+        }
+    }
+
+    fn atan(mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(value), None, None) => Ok(ValueType::Number(Number::Real(
+                expect_number(value?)?.to_f64().atan(),
+            ))),
+            (Some(y), Some(x), None) => {
+                let y = expect_number(y?)?.to_f64();
+                let x = expect_number(x?)?.to_f64();
+                Ok(ValueType::Number(Number::Real(y.atan2(x))))
+            }
+            _ => logic_error!("atan takes one or two arguments"),
+        }
+    }
+
+    [
+        function_mapping!("exp", exp),
+        function_mapping!("log", log),
+        function_mapping!("sin", sin),
+        function_mapping!("cos", cos),
+        function_mapping!("tan", tan),
+        function_mapping!("asin", asin),
+        function_mapping!("acos", acos),
+        function_mapping!("atan", atan),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn transcendental() -> Result<()> {
+    let interpreter = Interpreter::new();
+    for (source, result) in [
+        ("(exp 0)", 1.0),
+        ("(log 1)", 0.0),
+        ("(log 100 10)", 2.0),
+        ("(log (expt 10 400))", 400.0 * std::f64::consts::LN_10),
+        ("(log (expt 10 400) 10)", 400.0),
+        (
+            "(log (/ 1 (expt 10 400)))",
+            -400.0 * std::f64::consts::LN_10,
+        ),
+        ("(sin 0)", 0.0),
+        ("(cos 0)", 1.0),
+        ("(atan 1 1)", std::f64::consts::FRAC_PI_4),
+        ("(acos 1)", 0.0),
+    ]
+    .iter()
+    {
+        match interpreter.eval(source.chars())? {
+            Some(ValueType::Number(Number::Real(n))) => {
+                assert!((n - result).abs() < 1e-12, "{}", source)
+            }
+            other => panic!("{} should be a real number, got {:?}", source, other),
+        }
+    }
+    assert_eq!(
+        interpreter.eval("(atan 1 2 3)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "atan takes one or two arguments".to_string()
+        })
+    );
+    Ok(())
+}
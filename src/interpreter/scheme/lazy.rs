@@ -0,0 +1,135 @@
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// a promise computes its value once, by applying a procedure to arguments,
+// which are empty for the thunks of delay and delay-force
+#[derive(Debug, Clone, PartialEq)]
+pub enum Promise {
+    Forced(ValueType),
+    Delayed(ValueType, Vec<ValueType>),
+    // the procedure returns another promise, whose value this promise takes
+    DelayedForce(ValueType, Vec<ValueType>),
+}
+
+impl Promise {
+    pub(crate) fn value(value: ValueType) -> ValueType {
+        ValueType::Promise(Rc::new(RefCell::new(Promise::Forced(value))))
+    }
+
+    pub(crate) fn delayed(procedure: ValueType, arguments: Vec<ValueType>) -> ValueType {
+        ValueType::Promise(Rc::new(RefCell::new(Promise::Delayed(
+            procedure, arguments,
+        ))))
+    }
+
+    pub(crate) fn delayed_force(procedure: ValueType, arguments: Vec<ValueType>) -> ValueType {
+        ValueType::Promise(Rc::new(RefCell::new(Promise::DelayedForce(
+            procedure, arguments,
+        ))))
+    }
+}
+
+// chains of delay-force are followed in a loop, so iterative lazy algorithms run in constant space
+pub(crate) fn force(
+    interpreter: &Interpreter,
+    promise: &Rc<RefCell<Promise>>,
+) -> Result<ValueType> {
+    loop {
+        let state = promise.borrow().clone();
+        match state {
+            Promise::Forced(value) => return Ok(value),
+            Promise::Delayed(procedure, arguments) => {
+                let value = interpreter.call_procedure(&procedure, arguments)?;
+                // forcing the promise again from within its own computation forced it first
+                if let Promise::Forced(value) = &*promise.borrow() {
+                    return Ok(value.clone());
+                }
+                promise.replace(Promise::Forced(value.clone()));
+                return Ok(value);
+            }
+            Promise::DelayedForce(procedure, arguments) => {
+                let next = match interpreter.call_procedure(&procedure, arguments)? {
+                    ValueType::Promise(next) => next,
+                    other => logic_error!("delay-force: expect a promise, got {}", other),
+                };
+                if let Promise::Forced(value) = &*promise.borrow() {
+                    return Ok(value.clone());
+                }
+                let next = next.borrow().clone();
+                promise.replace(next);
+            }
+        }
+    }
+}
+
+pub(crate) fn lazy_library() -> HashMap<String, ValueType> {
+    // anything but a promise is its own value
+    fn force_procedure(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => match value? {
+                ValueType::Promise(promise) => force(interpreter, &promise),
+                other => Ok(other),
+            },
+            _ => logic_error!("force takes exactly one argument"),
+        }
+    }
+
+    fn make_promise(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(match value? {
+                promise @ ValueType::Promise(_) => promise,
+                other => Promise::value(other),
+            }),
+            _ => logic_error!("make-promise takes exactly one argument"),
+        }
+    }
+
+    fn is_promise(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Promise(_)))),
+            _ => logic_error!("promise? takes exactly one argument"),
+        }
+    }
+
+    [
+        interpreter_function_mapping!("force", force_procedure),
+        function_mapping!("make-promise", make_promise),
+        function_mapping!("promise?", is_promise),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn promises() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(force (delay (+ 1 2)))", "3"),
+            ("(force 5)", "5"),
+            ("(promise? (delay 1))", "#t"),
+            ("(promise? 1)", "#f"),
+            ("(force (make-promise 'a))", "a"),
+            ("(force (delay-force (delay 'b)))", "b"),
+            (
+                "(define port (open-output-string))
+                 (define p (delay (begin-output)))
+                 (define (begin-output) (write-char #\\x port) 'done)
+                 (force p) (force p) (list (force p) (get-output-string port))",
+                "(done \"x\")",
+            ),
+            // a long chain of delay-force runs iteratively
+            (
+                "(define (loop n) (delay-force (if (= n 0) (delay 'end) (loop (- n 1))))) (force (loop 100000))",
+                "end",
+            ),
+        ],
+    )?;
+    Ok(())
+}
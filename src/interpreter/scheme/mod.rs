@@ -1 +1,10 @@
 pub mod base;
+pub mod char;
+pub mod cxr;
+pub mod file;
+pub mod inexact;
+pub mod lazy;
+pub mod process_context;
+pub mod read;
+pub mod time;
+pub mod write;
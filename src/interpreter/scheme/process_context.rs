@@ -0,0 +1,270 @@
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::env;
+
+// which environment variables are visible to scheme code
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvironmentVariables {
+    All,
+    None,
+    Only(Vec<String>),
+}
+
+impl EnvironmentVariables {
+    pub fn is_visible(&self, name: &str) -> bool {
+        match self {
+            EnvironmentVariables::All => true,
+            EnvironmentVariables::None => false,
+            EnvironmentVariables::Only(names) => names.iter().any(|n| n == name),
+        }
+    }
+}
+
+// #t and no value are success, #f is failure and exact integers are exit codes, those no
+// process can exit with being failure as well
+fn exit_code(value: Option<Result<ValueType>>) -> Result<i32> {
+    Ok(match value.transpose()? {
+        None | Some(ValueType::Boolean(true)) => 0,
+        Some(ValueType::Number(Number::Integer(code))) if (0..=255).contains(&code) => code as i32,
+        Some(_) => 1,
+    })
+}
+
+// runs the after thunks of the dynamic-wind calls being left, innermost first
+fn unwind(interpreter: &Interpreter) -> Result<()> {
+    loop {
+        let after = interpreter.winds.borrow_mut().pop();
+        match after {
+            Some(after) => interpreter.call_procedure(&after, vec![])?,
+            None => return Ok(()),
+        };
+    }
+}
+
+fn exit_error(code: i32) -> Error {
+    Error {
+        category: ErrorType::Exit(code),
+        message: format!("exit with code {}", code),
+    }
+}
+
+pub(crate) fn process_context_library() -> HashMap<String, ValueType> {
+    // the dynamic-wind calls being evaluated are left and pending output is flushed, the host
+    // decides how to exit
+    fn exit(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (code, None) => {
+                let code = exit_code(code)?;
+                unwind(interpreter)?;
+                interpreter.current_output_port.borrow().flush()?;
+                interpreter.current_error_port.borrow().flush()?;
+                Err(exit_error(code))
+            }
+            _ => logic_error!("exit takes zero or one argument"),
+        }
+    }
+
+    // without running the after thunks of dynamic-wind
+    fn emergency_exit(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (code, None) => {
+                let code = exit_code(code)?;
+                interpreter.winds.borrow_mut().clear();
+                Err(exit_error(code))
+            }
+            _ => logic_error!("emergency-exit takes zero or one argument"),
+        }
+    }
+
+    // values which are not valid unicode are decoded lossily
+    fn get_environment_variable(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(name), None) => match name? {
+                ValueType::String(name) => Ok(
+                    match env::var_os(&name)
+                        .filter(|_| interpreter.environment_variables.borrow().is_visible(&name))
+                    {
+                        Some(value) => ValueType::String(value.to_string_lossy().into_owned()),
+                        None => ValueType::Boolean(false),
+                    },
+                ),
+                other => logic_error!("get-environment-variable: expect a string, got {}", other),
+            },
+            _ => logic_error!("get-environment-variable takes exactly one argument"),
+        }
+    }
+
+    fn get_environment_variables(
+        arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => {
+                let visible = interpreter.environment_variables.borrow();
+                Ok(ValueType::list(
+                    env::vars_os()
+                        .map(|(name, value)| {
+                            (
+                                name.to_string_lossy().into_owned(),
+                                value.to_string_lossy().into_owned(),
+                            )
+                        })
+                        .filter(|(name, _)| visible.is_visible(name))
+                        .map(|(name, value)| {
+                            ValueType::cons(ValueType::String(name), ValueType::String(value))
+                        })
+                        .collect::<Vec<_>>()
+                        .into_iter(),
+                ))
+            }
+            _ => logic_error!("get-environment-variables takes no arguments"),
+        }
+    }
+
+    fn command_line(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::list(
+                interpreter
+                    .command_line
+                    .borrow()
+                    .iter()
+                    .cloned()
+                    .map(ValueType::String),
+            )),
+            _ => logic_error!("command-line takes no arguments"),
+        }
+    }
+
+    [
+        interpreter_function_mapping!("command-line", command_line),
+        interpreter_function_mapping!("exit", exit),
+        interpreter_function_mapping!("emergency-exit", emergency_exit),
+        interpreter_function_mapping!("get-environment-variable", get_environment_variable),
+        interpreter_function_mapping!("get-environment-variables", get_environment_variables),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn exit_codes() -> Result<()> {
+    let interpreter = Interpreter::new();
+    for (source, code) in [
+        ("(exit)", 0),
+        ("(exit #t)", 0),
+        ("(exit #f)", 1),
+        ("(exit 3)", 3),
+        ("(emergency-exit 4)", 4),
+        ("(guard (e (#t 0)) (exit 5))", 5),
+        ("(exit 4294967296)", 1),
+        ("(exit 256)", 1),
+        ("(exit -1)", 1),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(source.chars()).map_err(|e| e.exit_code()),
+            Err(Some(*code)),
+            "{}",
+            source
+        );
+    }
+    interpreter.eval(
+        "(define trail (make-vector 1 '()))
+         (define (noting x) (lambda () (vector-set! trail 0 (cons x (vector-ref trail 0)))))
+         (define (wound exit)
+           (dynamic-wind (noting 'a)
+                         (lambda () (dynamic-wind (noting 'b) (lambda () (exit 2)) (noting 'c)))
+                         (noting 'd)))"
+            .chars(),
+    )?;
+    for (source, trail) in [
+        ("(wound exit)", "(d c b a)"),
+        ("(wound emergency-exit)", "(b a)"),
+    ]
+    .iter()
+    {
+        interpreter.eval("(vector-set! trail 0 '())".chars())?;
+        assert_eq!(
+            interpreter.eval(source.chars()).map_err(|e| e.exit_code()),
+            Err(Some(2))
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                interpreter.eval("(vector-ref trail 0)".chars())?.unwrap()
+            ),
+            *trail
+        );
+        assert!(interpreter.winds.borrow().is_empty());
+    }
+    Ok(())
+}
+
+#[test]
+fn environment_variables() -> Result<()> {
+    env::set_var("RUSCHM_TEST_VARIABLE", "value");
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(get-environment-variable \"RUSCHM_TEST_VARIABLE\")",
+                "\"value\"",
+            ),
+            (
+                "(get-environment-variable \"RUSCHM_NO_SUCH_VARIABLE\")",
+                "#f",
+            ),
+            (
+                "(cdr (assoc \"RUSCHM_TEST_VARIABLE\" (get-environment-variables)))",
+                "\"value\"",
+            ),
+        ],
+    )?;
+    interpreter
+        .environment_variables
+        .replace(EnvironmentVariables::Only(vec!["PATH".to_string()]));
+    assert_printed(
+        &interpreter,
+        &[
+            ("(get-environment-variable \"RUSCHM_TEST_VARIABLE\")", "#f"),
+            (
+                "(assoc \"RUSCHM_TEST_VARIABLE\" (get-environment-variables))",
+                "#f",
+            ),
+        ],
+    )?;
+    interpreter
+        .environment_variables
+        .replace(EnvironmentVariables::None);
+    assert_eq!(
+        format!(
+            "{}",
+            interpreter
+                .eval("(get-environment-variables)".chars())?
+                .unwrap()
+        ),
+        "()"
+    );
+    Ok(())
+}
+
+#[test]
+fn command_line() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.command_line.replace(vec![
+        "script.scm".to_string(),
+        "--verbose".to_string(),
+        "input".to_string(),
+    ]);
+    assert_eq!(
+        format!("{}", interpreter.eval("(command-line)".chars())?.unwrap()),
+        "(\"script.scm\" \"--verbose\" \"input\")"
+    );
+    Ok(())
+}
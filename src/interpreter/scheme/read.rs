@@ -0,0 +1,105 @@
+use crate::interpreter::scheme::base::input_port;
+use crate::interpreter::*;
+use crate::lexer::TokenGenerator;
+use crate::parser::Parser;
+use std::collections::HashMap;
+
+// the characters of a textual input port, consumed as they are lexed
+struct PortChars<'a>(&'a Port, Option<Error>);
+
+impl Iterator for PortChars<'_> {
+    type Item = char;
+    fn next(&mut self) -> Option<char> {
+        match self.0.read_char() {
+            Ok(c) => c,
+            Err(e) => {
+                self.1 = Some(e);
+                None
+            }
+        }
+    }
+}
+
+// reads one datum, leaving the characters after it in the port
+fn read_datum(port: &Port) -> Result<ValueType> {
+    let mut source = PortChars(port, None);
+    let mut chars = (&mut source).peekable();
+    let mut lexical_error = None;
+    let datum = {
+        let tokens = TokenGenerator::new(&mut chars).map_while(|token| match token {
+            Ok(token) => Some(token),
+            Err(e) => {
+                lexical_error = Some(e);
+                None
+            }
+        });
+        Parser::new(tokens).parse_datum()
+    };
+    // the lexer may have looked one character past the datum
+    if let Some(c) = chars.peek() {
+        port.unread_char(*c);
+    }
+    drop(chars);
+    if let Some(e) = source.1.or(lexical_error) {
+        return Err(e);
+    }
+    Ok(match datum? {
+        Some(datum) => ValueType::from(&datum),
+        None => ValueType::Eof,
+    })
+}
+
+pub(crate) fn read_library() -> HashMap<String, ValueType> {
+    fn read(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (port, None) => read_datum(&*input_port("read", port, interpreter)?),
+            _ => logic_error!("read takes zero or one argument"),
+        }
+    }
+
+    [interpreter_function_mapping!("read", read)]
+        .iter()
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn read_data() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "(define port (open-input-string \"(a . (b 2)) #(\\\"c\\\" #\\\\d) sym;comment\n'e\"))"
+            .chars(),
+    )?;
+    for result in [
+        "(a b 2)",
+        "#(\"c\" #\\d)",
+        "sym",
+        "(quote e)",
+        "<eof>",
+        "<eof>",
+    ]
+    .iter()
+    {
+        assert_eq!(
+            format!("{}", interpreter.eval("(read port)".chars())?.unwrap()),
+            *result
+        );
+    }
+    assert_eq!(
+        format!(
+            "{}",
+            interpreter
+                .eval("(define p (open-input-string \"ab(c)\")) (list (read p) (read p))".chars())?
+                .unwrap()
+        ),
+        "(ab (c))"
+    );
+    assert_eq!(
+        interpreter.eval("(read (open-input-string \"(1 2\"))".chars()),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::UnterminatedList),
+            message: "unterminated list".to_string()
+        })
+    );
+    Ok(())
+}
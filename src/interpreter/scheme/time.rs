@@ -0,0 +1,136 @@
+use crate::interpreter::ruschm::statistics::Statistics;
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const JIFFIES_PER_SECOND: i64 = 1_000_000;
+
+// jiffies count from the first use of the clock, which is monotonic unlike current-second
+pub(crate) fn jiffy_epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+// the report of the time form and the ,time command of the repl
+pub(crate) fn elapsed_report(elapsed: Duration, used: &Statistics) -> String {
+    format!("; elapsed time: {:?}, {}", elapsed, used)
+}
+
+// called by the time form, the report goes to the current output port after the thunk returns
+pub(crate) fn time_thunk(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(thunk), None) => {
+            let thunk = thunk?;
+            let (start, statistics) = (Instant::now(), interpreter.statistics());
+            let value = interpreter.call_procedure(&thunk, vec![])?;
+            let used = interpreter.statistics().since(&statistics);
+            let report = elapsed_report(start.elapsed(), &used);
+            let port = interpreter.current_output_port.borrow().clone();
+            port.write_str(&report)?;
+            port.write_str("\n")?;
+            Ok(value)
+        }
+        _ => logic_error!("time takes exactly one expression"),
+    }
+}
+
+pub(crate) fn time_library() -> HashMap<String, ValueType> {
+    fn current_second(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => {
+                let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                    Ok(elapsed) => elapsed.as_secs_f64(),
+                    Err(e) => -e.duration().as_secs_f64(),
+                };
+                Ok(ValueType::Number(Number::Real(seconds)))
+            }
+            _ => logic_error!("current-second takes no arguments"),
+        }
+    }
+
+    fn current_jiffy(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Number(Number::Integer(
+                jiffy_epoch().elapsed().as_micros() as i64,
+            ))),
+            _ => logic_error!("current-jiffy takes no arguments"),
+        }
+    }
+
+    fn jiffies_per_second(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Number(Number::Integer(JIFFIES_PER_SECOND))),
+            _ => logic_error!("jiffies-per-second takes no arguments"),
+        }
+    }
+
+    [
+        function_mapping!("current-second", current_second),
+        function_mapping!("current-jiffy", current_jiffy),
+        function_mapping!("jiffies-per-second", jiffies_per_second),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn clocks() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let jiffy = |interpreter: &Interpreter| match interpreter.eval("(current-jiffy)".chars()) {
+        Ok(Some(ValueType::Number(Number::Integer(jiffy)))) => jiffy,
+        other => panic!("current-jiffy should be an exact integer, got {:?}", other),
+    };
+    let start = jiffy(&interpreter);
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    assert!(jiffy(&interpreter) - start >= 2000);
+    match interpreter.eval("(current-second)".chars())? {
+        // after 2020-01-01
+        Some(ValueType::Number(Number::Real(seconds))) => assert!(seconds > 1.5e9),
+        other => panic!("current-second should be a real number, got {:?}", other),
+    }
+    assert_eq!(
+        interpreter.eval("(jiffies-per-second)".chars())?,
+        Some(ValueType::Number(Number::Integer(1_000_000)))
+    );
+    Ok(())
+}
+
+#[test]
+fn timing() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter
+        .current_output_port
+        .replace(Rc::new(Port::open_output_string()));
+    assert_eq!(
+        interpreter.eval("(time (+ 1 2))".chars())?,
+        Some(ValueType::Number(Number::Integer(3)))
+    );
+    let output = interpreter.current_output_port.borrow().output_string()?;
+    assert!(output.starts_with("; elapsed time: "), "{}", output);
+    assert!(output.contains(" evaluations, "), "{}", output);
+    assert_eq!(
+        interpreter.eval("(time 1 2)".chars()),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::InvalidForm("time".to_string())),
+            message: "time: expect exactly one expression".to_string()
+        })
+    );
+    // the form calls its builtin whatever the program defines
+    for engine in [vm::Engine::TreeWalker, vm::Engine::Bytecode] {
+        interpreter.engine.set(engine);
+        interpreter.eval("(define (|time thunk| thunk) 42) (define (|time| thunk) 42)".chars())?;
+        assert_eq!(
+            interpreter.eval("(time (+ 1 2))".chars())?,
+            Some(ValueType::Number(Number::Integer(3)))
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::interpreter::scheme::base::output_port;
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+pub(crate) fn write_library() -> HashMap<String, ValueType> {
+    fn display(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(value), port, None) => {
+                let value = value?;
+                let port = output_port("display", port, interpreter)?;
+                port.write_str(&format!("{}", Displayed(&value)))?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("display takes one or two arguments"),
+        }
+    }
+
+    macro_rules! write_procedure {
+        ($name:tt, $ident:tt, $labeling:expr) => {
+            fn $name(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(value), port, None) => {
+                        let value = value?;
+                        let port = output_port($ident, port, interpreter)?;
+                        port.write_str(&format!("{}", External::new(&value, true, $labeling)))?;
+                        Ok(ValueType::Void)
+                    }
+                    _ => logic_error!("{} takes one or two arguments", $ident),
+                }
+            }
+        };
+    }
+
+    write_procedure!(write, "write", Labeling::Cycles);
+    write_procedure!(write_shared, "write-shared", Labeling::Shared);
+    write_procedure!(write_simple, "write-simple", Labeling::Simple);
+
+    [
+        interpreter_function_mapping!("display", display),
+        interpreter_function_mapping!("write", write),
+        interpreter_function_mapping!("write-shared", write_shared),
+        interpreter_function_mapping!("write-simple", write_simple),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn output_forms() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let value = interpreter
+        .eval("(list \"a\\nb\" #\\c '|d e| (vector \"f\" #\\space) 1.5)".chars())?
+        .unwrap();
+    assert_eq!(
+        format!("{}", value),
+        "(\"a\\nb\" #\\c |d e| #(\"f\" #\\space) 1.5)"
+    );
+    assert_eq!(format!("{}", Displayed(&value)), "(a\nb c d e #(f  ) 1.5)");
+    assert_eq!(
+        interpreter.eval("(write \"\" (current-output-port))".chars())?,
+        Some(ValueType::Void)
+    );
+    assert_eq!(
+        interpreter.eval("(output-port? (current-error-port))".chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(display 1 'port)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "display: expect an output port, got port".to_string()
+        })
+    );
+    Ok(())
+}
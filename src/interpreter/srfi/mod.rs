@@ -0,0 +1,11 @@
+pub mod srfi_1;
+pub mod srfi_113;
+pub mod srfi_128;
+pub mod srfi_132;
+pub mod srfi_151;
+pub mod srfi_158;
+pub mod srfi_19;
+pub mod srfi_27;
+pub mod srfi_28;
+pub mod srfi_41;
+pub mod srfi_69;
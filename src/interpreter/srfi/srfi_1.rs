@@ -0,0 +1,320 @@
+use crate::interpreter::scheme::base::{expect_index, expect_list, expect_pair, list_tail};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// the elements of the lists at each position, up to the end of the shortest list
+fn rows(name: &str, lists: &[ValueType]) -> Result<Vec<Vec<ValueType>>> {
+    let lists = lists
+        .iter()
+        .map(|list| expect_list(name, list.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    let length = lists.iter().map(Vec::len).min().unwrap_or(0);
+    Ok((0..length)
+        .map(|i| lists.iter().map(|list| list[i].clone()).collect())
+        .collect())
+}
+
+pub(crate) fn srfi_1_library() -> HashMap<String, ValueType> {
+    fn fold(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        match arguments.as_slice() {
+            [kons, knil, lists @ ..] if !lists.is_empty() => rows("fold", lists)?
+                .into_iter()
+                .try_fold(knil.clone(), |acc, mut row| {
+                    row.push(acc);
+                    interpreter.call_procedure(kons, row)
+                }),
+            _ => logic_error!("fold takes at least three arguments"),
+        }
+    }
+
+    fn fold_right(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        match arguments.as_slice() {
+            [kons, knil, lists @ ..] if !lists.is_empty() => rows("fold-right", lists)?
+                .into_iter()
+                .rev()
+                .try_fold(knil.clone(), |acc, mut row| {
+                    row.push(acc);
+                    interpreter.call_procedure(kons, row)
+                }),
+            _ => logic_error!("fold-right takes at least three arguments"),
+        }
+    }
+
+    fn reduce(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(f), Some(ridentity), Some(list), None) => {
+                let (f, ridentity) = (f?, ridentity?);
+                let mut elements = expect_list("reduce", list?)?.into_iter();
+                match elements.next() {
+                    Some(first) => elements.try_fold(first, |acc, element| {
+                        interpreter.call_procedure(&f, vec![element, acc])
+                    }),
+                    None => Ok(ridentity),
+                }
+            }
+            _ => logic_error!("reduce takes exactly three arguments"),
+        }
+    }
+
+    // splits the list into the elements satisfying the predicate and the rest
+    fn partition_list(
+        name: &str,
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<(Vec<ValueType>, Vec<ValueType>)> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(pred), Some(list), None) => {
+                let pred = pred?;
+                let mut satisfied = vec![];
+                let mut rest = vec![];
+                for element in expect_list(name, list?)? {
+                    if interpreter
+                        .call_procedure(&pred, vec![element.clone()])?
+                        .is_true()
+                    {
+                        satisfied.push(element);
+                    } else {
+                        rest.push(element);
+                    }
+                }
+                Ok((satisfied, rest))
+            }
+            _ => logic_error!("{} takes exactly two arguments", name),
+        }
+    }
+
+    fn filter(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (satisfied, _) = partition_list("filter", arguments, interpreter)?;
+        Ok(ValueType::list(satisfied.into_iter()))
+    }
+
+    fn remove(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (_, rest) = partition_list("remove", arguments, interpreter)?;
+        Ok(ValueType::list(rest.into_iter()))
+    }
+
+    fn partition(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (satisfied, rest) = partition_list("partition", arguments, interpreter)?;
+        Ok(ValueType::Values(vec![
+            ValueType::list(satisfied.into_iter()),
+            ValueType::list(rest.into_iter()),
+        ]))
+    }
+
+    fn find(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(pred), Some(list), None) => {
+                let pred = pred?;
+                for element in expect_list("find", list?)? {
+                    if interpreter
+                        .call_procedure(&pred, vec![element.clone()])?
+                        .is_true()
+                    {
+                        return Ok(element);
+                    }
+                }
+                Ok(ValueType::Boolean(false))
+            }
+            _ => logic_error!("find takes exactly two arguments"),
+        }
+    }
+
+    // the first true value of the predicate
+    fn any(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        match arguments.as_slice() {
+            [pred, lists @ ..] if !lists.is_empty() => {
+                for row in rows("any", lists)? {
+                    let result = interpreter.call_procedure(pred, row)?;
+                    if result.is_true() {
+                        return Ok(result);
+                    }
+                }
+                Ok(ValueType::Boolean(false))
+            }
+            _ => logic_error!("any takes at least two arguments"),
+        }
+    }
+
+    // the last value of the predicate if all of them are true
+    fn every(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        match arguments.as_slice() {
+            [pred, lists @ ..] if !lists.is_empty() => {
+                let mut last = ValueType::Boolean(true);
+                for row in rows("every", lists)? {
+                    last = interpreter.call_procedure(pred, row)?;
+                    if !last.is_true() {
+                        break;
+                    }
+                }
+                Ok(last)
+            }
+            _ => logic_error!("every takes at least two arguments"),
+        }
+    }
+
+    fn iota(mut arguments: Arguments) -> Result<ValueType> {
+        let count = match arguments.next() {
+            Some(count) => expect_index("iota", count?)?,
+            None => logic_error!("iota takes one to three arguments"),
+        };
+        let mut next_number = |default| match arguments.next() {
+            Some(value) => match value? {
+                ValueType::Number(number) => Ok(number),
+                other => logic_error!("iota: expect a number, got {}", other),
+            },
+            None => Ok(Number::Integer(default)),
+        };
+        let start = next_number(0)?;
+        let step = next_number(1)?;
+        if arguments.next().is_some() {
+            logic_error!("iota takes one to three arguments")
+        }
+        let elements = (0..count)
+            .map(|i| start.clone() + (Number::Integer(i as i64) * step.clone())?)
+            .map(|number| number.map(ValueType::Number))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ValueType::list(elements.into_iter()))
+    }
+
+    // the prefix may be taken from an improper list
+    fn take(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(list), Some(k), None) => {
+                let mut current = list?;
+                let mut elements = vec![];
+                for _ in 0..expect_index("take", k?)? {
+                    let pair = expect_pair("take", current)?;
+                    elements.push(pair.borrow().car.clone());
+                    current = pair.borrow().cdr.clone();
+                }
+                Ok(ValueType::list(elements.into_iter()))
+            }
+            _ => logic_error!("take takes exactly two arguments"),
+        }
+    }
+
+    fn drop(mut arguments: Arguments) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(list), Some(k), None) => list_tail("drop", list?, expect_index("drop", k?)?),
+            _ => logic_error!("drop takes exactly two arguments"),
+        }
+    }
+
+    // keeps the first occurrence of each element, compared by equal? unless given a predicate
+    fn delete_duplicates(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (list, compare) = match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(list), compare, None) => (list?, compare.transpose()?),
+            _ => logic_error!("delete-duplicates takes one or two arguments"),
+        };
+        let mut kept: Vec<ValueType> = vec![];
+        for element in expect_list("delete-duplicates", list)? {
+            let mut duplicated = false;
+            for previous in &kept {
+                duplicated = match &compare {
+                    Some(compare) => interpreter
+                        .call_procedure(compare, vec![previous.clone(), element.clone()])?
+                        .is_true(),
+                    None => previous.equal(&element),
+                };
+                if duplicated {
+                    break;
+                }
+            }
+            if !duplicated {
+                kept.push(element);
+            }
+        }
+        Ok(ValueType::list(kept.into_iter()))
+    }
+
+    // the result shares structure with the last list produced, like append
+    fn append_map(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        match arguments.as_slice() {
+            [f, lists @ ..] if !lists.is_empty() => {
+                let mut results = rows("append-map", lists)?
+                    .into_iter()
+                    .map(|row| interpreter.call_procedure(f, row))
+                    .collect::<Result<Vec<_>>>()?;
+                let tail = results.pop().unwrap_or(ValueType::EmptyList);
+                let mut elements = vec![];
+                for result in results {
+                    elements.extend(expect_list("append-map", result)?);
+                }
+                Ok(ValueType::list_with_tail(elements.into_iter(), tail))
+            }
+            _ => logic_error!("append-map takes at least two arguments"),
+        }
+    }
+
+    [
+        interpreter_function_mapping!("fold", fold),
+        interpreter_function_mapping!("fold-right", fold_right),
+        interpreter_function_mapping!("reduce", reduce),
+        interpreter_function_mapping!("filter", filter),
+        interpreter_function_mapping!("remove", remove),
+        interpreter_function_mapping!("partition", partition),
+        interpreter_function_mapping!("find", find),
+        interpreter_function_mapping!("any", any),
+        interpreter_function_mapping!("every", every),
+        function_mapping!("iota", iota),
+        function_mapping!("take", take),
+        function_mapping!("drop", drop),
+        interpreter_function_mapping!("delete-duplicates", delete_duplicates),
+        interpreter_function_mapping!("append-map", append_map),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn list_operations() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(fold cons '() '(1 2 3))", "(3 2 1)"),
+            ("(fold + 0 '(1 2 3) '(10 20))", "33"),
+            ("(fold-right cons '() '(1 2 3))", "(1 2 3)"),
+            ("(reduce max 0 '(3 9 2))", "9"),
+            ("(reduce + 0 '())", "0"),
+            ("(filter pair? '(1 (2) 3 (4)))", "((2) (4))"),
+            ("(remove pair? '(1 (2) 3 (4)))", "(1 3)"),
+            ("(partition pair? '(1 (2) 3))", "((2)) (1 3)"),
+            ("(find pair? '(1 (2) (3)))", "(2)"),
+            ("(find pair? '(1 2))", "#f"),
+            ("(any (lambda (x) (memq x '(b c))) '(a b c))", "(b c)"),
+            ("(any < '(3 2) '(1 4))", "#t"),
+            ("(every pair? '())", "#t"),
+            ("(every (lambda (x) (memq x '(a b))) '(a b))", "(b)"),
+            ("(iota 5)", "(0 1 2 3 4)"),
+            ("(iota 3 1/2 1/2)", "(1/2 1 3/2)"),
+            ("(take '(1 2 3 . 4) 2)", "(1 2)"),
+            ("(drop '(1 2 3) 2)", "(3)"),
+            ("(delete-duplicates '(a b a (c) (c)))", "(a b (c))"),
+            (
+                "(delete-duplicates '(1 2 3 4) (lambda (a b) (= (modulo a 2) (modulo b 2))))",
+                "(1 2)",
+            ),
+            ("(append-map (lambda (x) (list x x)) '(1 2))", "(1 1 2 2)"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(take '(1) 2)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "take: expect a pair, got ()".to_string()
+        })
+    );
+    Ok(())
+}
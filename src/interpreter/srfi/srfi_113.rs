@@ -0,0 +1,831 @@
+use crate::interpreter::scheme::base::{expect_integer, expect_list};
+use crate::interpreter::srfi::srfi_128::{expect_comparator, Comparator};
+use crate::interpreter::srfi::srfi_69::{insert, locate, lookup, remove, HashTable};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// sets and bags share their representation, a set never counts an element more than once
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollectionKind {
+    Set,
+    Bag,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collection {
+    pub kind: CollectionKind,
+    pub comparator: Rc<Comparator>,
+    // each element mapped to its count
+    elements: RefCell<HashTable>,
+}
+
+impl Collection {
+    fn new(kind: CollectionKind, comparator: Rc<Comparator>) -> Self {
+        let elements = RefCell::new(HashTable::new(
+            comparator.equality.clone(),
+            comparator.hash.clone(),
+        ));
+        Self {
+            kind,
+            comparator,
+            elements,
+        }
+    }
+
+    // an empty collection of the same kind and comparator
+    fn empty_like(&self) -> Self {
+        Self::new(self.kind, self.comparator.clone())
+    }
+
+    fn copy(&self) -> Self {
+        Self {
+            kind: self.kind,
+            comparator: self.comparator.clone(),
+            elements: RefCell::new(self.elements.borrow().clone()),
+        }
+    }
+
+    fn counts(&self) -> Vec<(ValueType, i64)> {
+        self.elements
+            .borrow()
+            .entries()
+            .into_iter()
+            .map(|(element, count)| match count {
+                ValueType::Number(Number::Integer(count)) => (element, count),
+                _ => (element, 0),
+            })
+            .collect()
+    }
+
+    fn count(&self, interpreter: &Interpreter, element: &ValueType) -> Result<i64> {
+        Ok(match lookup(interpreter, &self.elements, element)? {
+            Some(ValueType::Number(Number::Integer(count))) => count,
+            _ => 0,
+        })
+    }
+
+    // the count is clamped to one for sets, and the element removed when it reaches zero
+    fn set_count(
+        &self,
+        name: &str,
+        interpreter: &Interpreter,
+        element: ValueType,
+        count: i64,
+    ) -> Result<()> {
+        self.comparator.check_type(name, interpreter, &element)?;
+        let count = match self.kind {
+            CollectionKind::Set => count.min(1),
+            CollectionKind::Bag => count,
+        };
+        if count > 0 {
+            insert(
+                interpreter,
+                &self.elements,
+                element,
+                ValueType::Number(Number::Integer(count)),
+            )
+        } else {
+            remove(interpreter, &self.elements, &element).map(|_| ())
+        }
+    }
+
+    fn add(
+        &self,
+        name: &str,
+        interpreter: &Interpreter,
+        element: ValueType,
+        count: i64,
+    ) -> Result<()> {
+        let current = self.count(interpreter, &element)?;
+        self.set_count(name, interpreter, element, current + count)
+    }
+
+    fn size(&self) -> i64 {
+        self.counts().iter().map(|(_, count)| count).sum()
+    }
+
+    fn elements(&self) -> Vec<ValueType> {
+        self.counts()
+            .into_iter()
+            .flat_map(|(element, count)| std::iter::repeat_n(element, count as usize))
+            .collect()
+    }
+
+    fn subset_of(&self, interpreter: &Interpreter, other: &Collection) -> Result<bool> {
+        for (element, count) in self.counts() {
+            if other.count(interpreter, &element)? < count {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn expect_collection(name: &str, kind: CollectionKind, value: ValueType) -> Result<Rc<Collection>> {
+    match value {
+        ValueType::Collection(collection) if collection.kind == kind => Ok(collection),
+        other => logic_error!(
+            "{}: expect a {}, got {}",
+            name,
+            match kind {
+                CollectionKind::Set => "set",
+                CollectionKind::Bag => "bag",
+            },
+            other
+        ),
+    }
+}
+
+fn collection_value(collection: Collection) -> ValueType {
+    ValueType::Collection(Rc::new(collection))
+}
+
+// every procedure is provided for sets and bags, named with their prefix
+macro_rules! collection_mapping {
+    ($ident:literal, $kind:expr, $function:ident) => {
+        (
+            $ident.to_string(),
+            ValueType::Procedure(Procedure::Buildin(BuildinProcedure(
+                $ident,
+                |arguments, interpreter| $function($ident, $kind, arguments, interpreter),
+            ))),
+        )
+    };
+}
+
+fn make(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+    let comparator = match arguments.next() {
+        Some(comparator) => expect_comparator(name, comparator)?,
+        None => logic_error!("{} takes a comparator and elements", name),
+    };
+    let collection = Collection::new(kind, comparator);
+    for element in arguments {
+        collection.add(name, interpreter, element, 1)?;
+    }
+    Ok(collection_value(collection))
+}
+
+fn from_list(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(comparator), Some(list), None) => {
+            let collection = Collection::new(kind, expect_comparator(name, comparator?)?);
+            for element in expect_list(name, list?)? {
+                collection.add(name, interpreter, element, 1)?;
+            }
+            Ok(collection_value(collection))
+        }
+        _ => logic_error!("{} takes exactly two arguments", name),
+    }
+}
+
+fn is_kind(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    _: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(value), None) => Ok(ValueType::Boolean(matches!(
+            value?,
+            ValueType::Collection(collection) if collection.kind == kind
+        ))),
+        _ => logic_error!("{} takes exactly one argument", name),
+    }
+}
+
+fn contains(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(collection), Some(element), None) => {
+            let collection = expect_collection(name, kind, collection?)?;
+            Ok(ValueType::Boolean(
+                locate(interpreter, &collection.elements, &element?)?
+                    .1
+                    .is_some(),
+            ))
+        }
+        _ => logic_error!("{} takes exactly two arguments", name),
+    }
+}
+
+// the element of the collection equal to the given one, or the default
+fn member(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+    ) {
+        (Some(collection), Some(element), Some(default), None) => {
+            let collection = expect_collection(name, kind, collection?)?;
+            let element = element?;
+            let default = default?;
+            let equality = collection.comparator.equality.clone();
+            for (candidate, _) in collection.counts() {
+                if interpreter
+                    .call_procedure(&equality, vec![candidate.clone(), element.clone()])?
+                    .is_true()
+                {
+                    return Ok(candidate);
+                }
+            }
+            Ok(default)
+        }
+        _ => logic_error!("{} takes exactly three arguments", name),
+    }
+}
+
+fn is_empty(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    _: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(collection), None) => Ok(ValueType::Boolean(
+            expect_collection(name, kind, collection?)?
+                .elements
+                .borrow()
+                .len()
+                == 0,
+        )),
+        _ => logic_error!("{} takes exactly one argument", name),
+    }
+}
+
+fn size(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    _: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(collection), None) => Ok(ValueType::Number(Number::Integer(
+            expect_collection(name, kind, collection?)?.size(),
+        ))),
+        _ => logic_error!("{} takes exactly one argument", name),
+    }
+}
+
+// the elements are listed in an unspecified order, bag elements as often as they occur
+fn to_list(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    _: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(collection), None) => Ok(ValueType::list(
+            expect_collection(name, kind, collection?)?
+                .elements()
+                .into_iter(),
+        )),
+        _ => logic_error!("{} takes exactly one argument", name),
+    }
+}
+
+fn element_comparator(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    _: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(collection), None) => Ok(ValueType::Comparator(
+            expect_collection(name, kind, collection?)?
+                .comparator
+                .clone(),
+        )),
+        _ => logic_error!("{} takes exactly one argument", name),
+    }
+}
+
+fn copy(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    _: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(collection), None) => Ok(collection_value(
+            expect_collection(name, kind, collection?)?.copy(),
+        )),
+        _ => logic_error!("{} takes exactly one argument", name),
+    }
+}
+
+// the functional updaters return a new collection, the linear ones update the given one
+fn update(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+    in_place: bool,
+    count: i64,
+) -> Result<ValueType> {
+    let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+    let collection = match arguments.next() {
+        Some(collection) => expect_collection(name, kind, collection)?,
+        None => logic_error!("{} takes a collection and elements", name),
+    };
+    let collection = match in_place {
+        true => collection,
+        false => Rc::new(collection.copy()),
+    };
+    for element in arguments {
+        collection.add(name, interpreter, element, count)?;
+    }
+    Ok(ValueType::Collection(collection))
+}
+
+fn adjoin(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    update(name, kind, arguments, interpreter, false, 1)
+}
+
+fn adjoin_in_place(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    update(name, kind, arguments, interpreter, true, 1)
+}
+
+fn delete(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    update(name, kind, arguments, interpreter, false, -1)
+}
+
+fn delete_in_place(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    update(name, kind, arguments, interpreter, true, -1)
+}
+
+// the collections compared from left to right share the first one's comparator
+fn collections(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    at_least: usize,
+) -> Result<Vec<Rc<Collection>>> {
+    let collections = arguments
+        .map(|collection| expect_collection(name, kind, collection?))
+        .collect::<Result<Vec<_>>>()?;
+    if collections.len() < at_least {
+        logic_error!("{} takes at least {} arguments", name, at_least)
+    }
+    Ok(collections)
+}
+
+macro_rules! comparison {
+    ($function:ident, $holds:expr) => {
+        fn $function(
+            name: &str,
+            kind: CollectionKind,
+            arguments: Arguments,
+            interpreter: &Interpreter,
+        ) -> Result<ValueType> {
+            let collections = collections(name, kind, arguments, 1)?;
+            for pair in collections.windows(2) {
+                let holds: fn(&Interpreter, &Collection, &Collection) -> Result<bool> = $holds;
+                if !holds(interpreter, &pair[0], &pair[1])? {
+                    return Ok(ValueType::Boolean(false));
+                }
+            }
+            Ok(ValueType::Boolean(true))
+        }
+    };
+}
+
+comparison!(equal, |interpreter, a, b| Ok(a
+    .subset_of(interpreter, b)?
+    && b.subset_of(interpreter, a)?));
+comparison!(subset, |interpreter, a, b| a.subset_of(interpreter, b));
+comparison!(proper_subset, |interpreter, a, b| Ok(a
+    .subset_of(interpreter, b)?
+    && !b.subset_of(interpreter, a)?));
+comparison!(superset, |interpreter, a, b| b.subset_of(interpreter, a));
+
+fn is_disjoint(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(a), Some(b), None) => {
+            let a = expect_collection(name, kind, a?)?;
+            let b = expect_collection(name, kind, b?)?;
+            for (element, _) in a.counts() {
+                if b.count(interpreter, &element)? > 0 {
+                    return Ok(ValueType::Boolean(false));
+                }
+            }
+            Ok(ValueType::Boolean(true))
+        }
+        _ => logic_error!("{} takes exactly two arguments", name),
+    }
+}
+
+// combines the counts of every element, starting from a copy of the first collection
+fn combine(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+    combine: fn(i64, i64) -> i64,
+    intersect: bool,
+) -> Result<ValueType> {
+    let collections = collections(name, kind, arguments, 1)?;
+    let result = collections[0].copy();
+    for other in &collections[1..] {
+        for (element, count) in other.counts() {
+            let current = result.count(interpreter, &element)?;
+            result.set_count(name, interpreter, element, combine(current, count))?;
+        }
+        // elements missing from the other collection are absent from an intersection
+        if intersect {
+            for (element, _) in result.counts() {
+                if other.count(interpreter, &element)? == 0 {
+                    result.set_count(name, interpreter, element, 0)?;
+                }
+            }
+        }
+    }
+    Ok(collection_value(result))
+}
+
+fn union(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    combine(name, kind, arguments, interpreter, i64::max, false)
+}
+
+fn intersection(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    combine(name, kind, arguments, interpreter, i64::min, true)
+}
+
+fn difference(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    combine(name, kind, arguments, interpreter, |a, b| a - b, false)
+}
+
+// elements in exactly one of two sets, or the absolute difference of bag counts
+fn xor(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(a), Some(b), None) => {
+            let a = expect_collection(name, kind, a?)?;
+            let b = expect_collection(name, kind, b?)?;
+            let result = a.empty_like();
+            for (element, count) in a.counts() {
+                let other = b.count(interpreter, &element)?;
+                result.set_count(name, interpreter, element, (count - other).abs())?;
+            }
+            for (element, count) in b.counts() {
+                if a.count(interpreter, &element)? == 0 {
+                    result.set_count(name, interpreter, element, count)?;
+                }
+            }
+            Ok(collection_value(result))
+        }
+        _ => logic_error!("{} takes exactly two arguments", name),
+    }
+}
+
+fn for_each(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(procedure), Some(collection), None) => {
+            let procedure = procedure?;
+            for element in expect_collection(name, kind, collection?)?.elements() {
+                interpreter.call_procedure(&procedure, vec![element])?;
+            }
+            Ok(ValueType::Void)
+        }
+        _ => logic_error!("{} takes exactly two arguments", name),
+    }
+}
+
+fn fold(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+    ) {
+        (Some(kons), Some(knil), Some(collection), None) => {
+            let kons = kons?;
+            let knil = knil?;
+            expect_collection(name, kind, collection?)?
+                .elements()
+                .into_iter()
+                .try_fold(knil, |accumulator, element| {
+                    interpreter.call_procedure(&kons, vec![element, accumulator])
+                })
+        }
+        _ => logic_error!("{} takes exactly three arguments", name),
+    }
+}
+
+// (set-map comparator procedure set) collects the results with the given comparator
+fn map(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+    ) {
+        (Some(comparator), Some(procedure), Some(collection), None) => {
+            let result = Collection::new(kind, expect_comparator(name, comparator?)?);
+            let procedure = procedure?;
+            for element in expect_collection(name, kind, collection?)?.elements() {
+                let mapped = interpreter.call_procedure(&procedure, vec![element])?;
+                result.add(name, interpreter, mapped, 1)?;
+            }
+            Ok(collection_value(result))
+        }
+        _ => logic_error!("{} takes exactly three arguments", name),
+    }
+}
+
+fn bag_element_count(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(bag), Some(element), None) => Ok(ValueType::Number(Number::Integer(
+            expect_collection(name, kind, bag?)?.count(interpreter, &element?)?,
+        ))),
+        _ => logic_error!("{} takes exactly two arguments", name),
+    }
+}
+
+fn counted_update(
+    name: &str,
+    kind: CollectionKind,
+    mut arguments: Arguments,
+    interpreter: &Interpreter,
+    sign: i64,
+) -> Result<ValueType> {
+    match (
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+        arguments.next(),
+    ) {
+        (Some(bag), Some(element), Some(count), None) => {
+            let bag = expect_collection(name, kind, bag?)?;
+            let count = match expect_integer(name, count?)? {
+                count if count >= 0 => count,
+                count => logic_error!("{}: expect a non-negative count, got {}", name, count),
+            };
+            bag.add(name, interpreter, element?, sign * count)?;
+            Ok(ValueType::Collection(bag))
+        }
+        _ => logic_error!("{} takes exactly three arguments", name),
+    }
+}
+
+fn bag_increment(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    counted_update(name, kind, arguments, interpreter, 1)
+}
+
+fn bag_decrement(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    counted_update(name, kind, arguments, interpreter, -1)
+}
+
+// the sum of the counts, where union takes the largest
+fn bag_sum(
+    name: &str,
+    kind: CollectionKind,
+    arguments: Arguments,
+    interpreter: &Interpreter,
+) -> Result<ValueType> {
+    combine(name, kind, arguments, interpreter, |a, b| a + b, false)
+}
+
+fn set_to_bag(
+    name: &str,
+    _: CollectionKind,
+    mut arguments: Arguments,
+    _: &Interpreter,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next()) {
+        (Some(set), None) => {
+            let set = expect_collection(name, CollectionKind::Set, set?)?;
+            let mut bag = set.copy();
+            bag.kind = CollectionKind::Bag;
+            Ok(collection_value(bag))
+        }
+        _ => logic_error!("{} takes exactly one argument", name),
+    }
+}
+
+pub(crate) fn srfi_113_library() -> HashMap<String, ValueType> {
+    use CollectionKind::{Bag, Set};
+    [
+        collection_mapping!("set", Set, make),
+        collection_mapping!("bag", Bag, make),
+        collection_mapping!("list->set", Set, from_list),
+        collection_mapping!("list->bag", Bag, from_list),
+        collection_mapping!("set?", Set, is_kind),
+        collection_mapping!("bag?", Bag, is_kind),
+        collection_mapping!("set-contains?", Set, contains),
+        collection_mapping!("bag-contains?", Bag, contains),
+        collection_mapping!("set-member", Set, member),
+        collection_mapping!("bag-member", Bag, member),
+        collection_mapping!("set-empty?", Set, is_empty),
+        collection_mapping!("bag-empty?", Bag, is_empty),
+        collection_mapping!("set-disjoint?", Set, is_disjoint),
+        collection_mapping!("bag-disjoint?", Bag, is_disjoint),
+        collection_mapping!("set-element-comparator", Set, element_comparator),
+        collection_mapping!("bag-element-comparator", Bag, element_comparator),
+        collection_mapping!("set-size", Set, size),
+        collection_mapping!("bag-size", Bag, size),
+        collection_mapping!("set->list", Set, to_list),
+        collection_mapping!("bag->list", Bag, to_list),
+        collection_mapping!("set-copy", Set, copy),
+        collection_mapping!("bag-copy", Bag, copy),
+        collection_mapping!("set-adjoin", Set, adjoin),
+        collection_mapping!("bag-adjoin", Bag, adjoin),
+        collection_mapping!("set-adjoin!", Set, adjoin_in_place),
+        collection_mapping!("bag-adjoin!", Bag, adjoin_in_place),
+        collection_mapping!("set-delete", Set, delete),
+        collection_mapping!("bag-delete", Bag, delete),
+        collection_mapping!("set-delete!", Set, delete_in_place),
+        collection_mapping!("bag-delete!", Bag, delete_in_place),
+        collection_mapping!("set=?", Set, equal),
+        collection_mapping!("bag=?", Bag, equal),
+        collection_mapping!("set<=?", Set, subset),
+        collection_mapping!("bag<=?", Bag, subset),
+        collection_mapping!("set<?", Set, proper_subset),
+        collection_mapping!("bag<?", Bag, proper_subset),
+        collection_mapping!("set>=?", Set, superset),
+        collection_mapping!("bag>=?", Bag, superset),
+        collection_mapping!("set-union", Set, union),
+        collection_mapping!("bag-union", Bag, union),
+        collection_mapping!("set-intersection", Set, intersection),
+        collection_mapping!("bag-intersection", Bag, intersection),
+        collection_mapping!("set-difference", Set, difference),
+        collection_mapping!("bag-difference", Bag, difference),
+        collection_mapping!("set-xor", Set, xor),
+        collection_mapping!("bag-xor", Bag, xor),
+        collection_mapping!("set-for-each", Set, for_each),
+        collection_mapping!("bag-for-each", Bag, for_each),
+        collection_mapping!("set-fold", Set, fold),
+        collection_mapping!("bag-fold", Bag, fold),
+        collection_mapping!("set-map", Set, map),
+        collection_mapping!("bag-map", Bag, map),
+        collection_mapping!("bag-sum", Bag, bag_sum),
+        collection_mapping!("bag-element-count", Bag, bag_element_count),
+        collection_mapping!("bag-increment!", Bag, bag_increment),
+        collection_mapping!("bag-decrement!", Bag, bag_decrement),
+        collection_mapping!("set->bag", Bag, set_to_bag),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn sets_and_bags() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "
+        (define c (make-default-comparator))
+        (define a (set c 1 2 3 2))
+        (define b (list->set c '(3 4)))"
+            .chars(),
+    )?;
+    assert_printed(
+        &interpreter,
+        &[
+            ("(set? a)", "#t"),
+            ("(bag? a)", "#f"),
+            ("(set-size a)", "3"),
+            ("(set-contains? a 2)", "#t"),
+            ("(set-contains? a 5)", "#f"),
+            ("(set-member a 2 #f)", "2"),
+            ("(list-sort < (set->list (set-union a b)))", "(1 2 3 4)"),
+            ("(set->list (set-intersection a b))", "(3)"),
+            ("(list-sort < (set->list (set-difference a b)))", "(1 2)"),
+            ("(list-sort < (set->list (set-xor a b)))", "(1 2 4)"),
+            ("(set-size (set-adjoin a 9))", "4"),
+            ("(set-size a)", "3"),
+            ("(set-size (set-delete a 1 2))", "1"),
+            ("(set-adjoin! a 0) (set-size a)", "4"),
+            ("(set=? (set c 1 2) (list->set c '(2 1)))", "#t"),
+            ("(set<=? (set c 1) (set c 1 2))", "#t"),
+            ("(set<? (set c 1 2) (set c 1 2))", "#f"),
+            ("(set-disjoint? (set c 1) (set c 2))", "#t"),
+            ("(set-fold + 0 (set c 1 2 3))", "6"),
+            ("(set-size (set-map c (lambda (x) (* x 0)) (set c 1 2 3)))", "1"),
+            ("(set-empty? (set c))", "#t"),
+            (
+                "(define bg (bag c 'x 'x 'y)) (list (bag-size bg) (bag-element-count bg 'x))",
+                "(3 2)",
+            ),
+            ("(bag-element-count (bag-increment! bg 'y 4) 'y)", "5"),
+            ("(bag-element-count (bag-decrement! bg 'y 9) 'y)", "0"),
+            ("(bag-size (bag-sum (bag c 1 1) (bag c 1)))", "3"),
+            ("(bag-size (bag-union (bag c 1 1) (bag c 1)))", "2"),
+            ("(bag-size (bag-intersection (bag c 1 1 2) (bag c 1 3)))", "1"),
+            ("(bag-size (set->bag (set c 1 2)))", "2"),
+            (
+                "(define strings (set (make-comparator #t string=? #f string-hash) \"a\")) (set-contains? strings (string #\\a))",
+                "#t",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(set-size (bag c))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "set-size: expect a set, got <bag>".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(set (make-comparator symbol? eq? #f #f) 1)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "set: 1 is not accepted by the comparator".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use crate::interpreter::srfi::srfi_69::{default_hash, equivalence_procedure};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// the parts of a comparator which sets and bags rely on, a missing type test accepts anything
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparator {
+    pub type_test: Option<ValueType>,
+    pub equality: ValueType,
+    pub ordering: Option<ValueType>,
+    pub hash: ValueType,
+}
+
+impl Comparator {
+    pub(crate) fn new(equality: ValueType) -> Self {
+        Self {
+            type_test: None,
+            hash: default_hash(&equality),
+            equality,
+            ordering: None,
+        }
+    }
+
+    pub(crate) fn check_type(
+        &self,
+        name: &str,
+        interpreter: &Interpreter,
+        value: &ValueType,
+    ) -> Result<()> {
+        if let Some(type_test) = &self.type_test {
+            if !interpreter
+                .call_procedure(type_test, vec![value.clone()])?
+                .is_true()
+            {
+                logic_error!("{}: {} is not accepted by the comparator", name, value)
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn expect_comparator(name: &str, value: ValueType) -> Result<Rc<Comparator>> {
+    match value {
+        ValueType::Comparator(comparator) => Ok(comparator),
+        other => logic_error!("{}: expect a comparator, got {}", name, other),
+    }
+}
+
+// values are tested by every procedure which is not #f
+fn optional_procedure(name: &str, value: ValueType) -> Result<Option<ValueType>> {
+    match value {
+        ValueType::Boolean(_) => Ok(None),
+        procedure @ ValueType::Procedure(_) => Ok(Some(procedure)),
+        other => logic_error!("{}: expect a procedure or a boolean, got {}", name, other),
+    }
+}
+
+pub(crate) fn srfi_128_library() -> HashMap<String, ValueType> {
+    // #t as the equality means equal?, and a missing hash puts every element in one bucket
+    fn make_comparator(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        fn constant_hash(
+            arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+        ) -> Result<ValueType> {
+            arguments.for_each(drop);
+            Ok(ValueType::Number(Number::Integer(0)))
+        }
+
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(type_test), Some(equality), Some(ordering), Some(hash), None) => {
+                let type_test = optional_procedure("make-comparator", type_test?)?;
+                let equality = optional_procedure("make-comparator", equality?)?
+                    .unwrap_or_else(|| equivalence_procedure(true));
+                let ordering = optional_procedure("make-comparator", ordering?)?;
+                let hash = optional_procedure("make-comparator", hash?)?
+                    .unwrap_or_else(|| function_mapping!("constant-hash", constant_hash).1);
+                Ok(ValueType::Comparator(Rc::new(Comparator {
+                    type_test,
+                    equality,
+                    ordering,
+                    hash,
+                })))
+            }
+            _ => logic_error!("make-comparator takes exactly four arguments"),
+        }
+    }
+
+    macro_rules! standard_comparator {
+        ($function:ident, $name:literal, $structural:expr) => {
+            fn $function(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match arguments.count() {
+                    0 => Ok(ValueType::Comparator(Rc::new(Comparator::new(
+                        equivalence_procedure($structural),
+                    )))),
+                    _ => logic_error!("{} takes no arguments", $name),
+                }
+            }
+        };
+    }
+
+    // eq? and eqv? are the same procedure in this implementation
+    standard_comparator!(make_default_comparator, "make-default-comparator", true);
+    standard_comparator!(make_equal_comparator, "make-equal-comparator", true);
+    standard_comparator!(make_eqv_comparator, "make-eqv-comparator", false);
+    standard_comparator!(make_eq_comparator, "make-eq-comparator", false);
+
+    fn is_comparator(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(
+                value?,
+                ValueType::Comparator(_)
+            ))),
+            _ => logic_error!("comparator? takes exactly one argument"),
+        }
+    }
+
+    fn comparator_test_type(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(comparator), Some(value), None) => {
+                let comparator = expect_comparator("comparator-test-type", comparator?)?;
+                Ok(match &comparator.type_test {
+                    Some(type_test) => interpreter.call_procedure(type_test, vec![value?])?,
+                    None => ValueType::Boolean(true),
+                })
+            }
+            _ => logic_error!("comparator-test-type takes exactly two arguments"),
+        }
+    }
+
+    fn comparator_hash(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(comparator), Some(value), None) => {
+                let comparator = expect_comparator("comparator-hash", comparator?)?;
+                interpreter.call_procedure(&comparator.hash, vec![value?])
+            }
+            _ => logic_error!("comparator-hash takes exactly two arguments"),
+        }
+    }
+
+    fn comparator_equality_predicate(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(comparator), None) => Ok(expect_comparator(
+                "comparator-equality-predicate",
+                comparator?,
+            )?
+            .equality
+            .clone()),
+            _ => logic_error!("comparator-equality-predicate takes exactly one argument"),
+        }
+    }
+
+    fn comparator_hash_function(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(comparator), None) => {
+                Ok(expect_comparator("comparator-hash-function", comparator?)?
+                    .hash
+                    .clone())
+            }
+            _ => logic_error!("comparator-hash-function takes exactly one argument"),
+        }
+    }
+
+    // (=? comparator a b c ...) holds when each neighbouring pair is equal
+    fn comparator_equal(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+        let comparator = match arguments.next() {
+            Some(comparator) => expect_comparator("=?", comparator)?,
+            None => logic_error!("=? takes a comparator and at least two values"),
+        };
+        let values = arguments.collect::<Vec<_>>();
+        if values.len() < 2 {
+            logic_error!("=? takes a comparator and at least two values")
+        }
+        for pair in values.windows(2) {
+            if !interpreter
+                .call_procedure(&comparator.equality, pair.to_vec())?
+                .is_true()
+            {
+                return Ok(ValueType::Boolean(false));
+            }
+        }
+        Ok(ValueType::Boolean(true))
+    }
+
+    fn comparator_less(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+        let comparator = match arguments.next() {
+            Some(comparator) => expect_comparator("<?", comparator)?,
+            None => logic_error!("<? takes a comparator and at least two values"),
+        };
+        let ordering = match &comparator.ordering {
+            Some(ordering) => ordering.clone(),
+            None => logic_error!("<?: the comparator has no ordering"),
+        };
+        let values = arguments.collect::<Vec<_>>();
+        if values.len() < 2 {
+            logic_error!("<? takes a comparator and at least two values")
+        }
+        for pair in values.windows(2) {
+            if !interpreter
+                .call_procedure(&ordering, pair.to_vec())?
+                .is_true()
+            {
+                return Ok(ValueType::Boolean(false));
+            }
+        }
+        Ok(ValueType::Boolean(true))
+    }
+
+    [
+        function_mapping!("make-comparator", make_comparator),
+        function_mapping!("make-default-comparator", make_default_comparator),
+        function_mapping!("make-equal-comparator", make_equal_comparator),
+        function_mapping!("make-eqv-comparator", make_eqv_comparator),
+        function_mapping!("make-eq-comparator", make_eq_comparator),
+        function_mapping!("comparator?", is_comparator),
+        interpreter_function_mapping!("comparator-test-type", comparator_test_type),
+        interpreter_function_mapping!("comparator-hash", comparator_hash),
+        function_mapping!(
+            "comparator-equality-predicate",
+            comparator_equality_predicate
+        ),
+        function_mapping!("comparator-hash-function", comparator_hash_function),
+        interpreter_function_mapping!("=?", comparator_equal),
+        interpreter_function_mapping!("<?", comparator_less),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn comparators() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "(define numbers (make-comparator (lambda (x) (eqv? x (+ x 0))) = < (lambda (x) 0)))"
+            .chars(),
+    )?;
+    assert_printed(
+        &interpreter,
+        &[
+            ("(comparator? numbers)", "#t"),
+            ("(comparator? =)", "#f"),
+            ("(=? numbers 1 1.0 1)", "#t"),
+            ("(<? numbers 1 2 3)", "#t"),
+            ("(<? numbers 1 3 2)", "#f"),
+            ("(comparator-hash numbers 5)", "0"),
+            ("(=? (make-default-comparator) '(1 \"a\") (list 1 \"a\"))", "#t"),
+            ("(=? (make-eqv-comparator) '(1) '(1))", "#f"),
+            ("(comparator-test-type (make-default-comparator) 'anything)", "#t"),
+            (
+                "(= (comparator-hash (make-equal-comparator) '(1 2)) (comparator-hash (make-equal-comparator) (list 1 2)))",
+                "#t",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(<? (make-default-comparator) 1 2)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "<?: the comparator has no ordering".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use crate::interpreter::scheme::base::{call_comparator, expect_list, expect_range, expect_vector};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// merges two sorted runs, taking from the left run on ties so sorting is stable
+fn merge(
+    interpreter: &Interpreter,
+    less: &ValueType,
+    left: Vec<ValueType>,
+    right: Vec<ValueType>,
+) -> Result<Vec<ValueType>> {
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if call_comparator(interpreter, less, b.clone(), a.clone())? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+// a merge sort, as the comparison may fail and must be called a predictable number of times
+fn sort(
+    interpreter: &Interpreter,
+    less: &ValueType,
+    mut elements: Vec<ValueType>,
+) -> Result<Vec<ValueType>> {
+    if elements.len() <= 1 {
+        return Ok(elements);
+    }
+    let right = elements.split_off(elements.len() / 2);
+    let left = sort(interpreter, less, elements)?;
+    let right = sort(interpreter, less, right)?;
+    merge(interpreter, less, left, right)
+}
+
+fn is_sorted(interpreter: &Interpreter, less: &ValueType, elements: &[ValueType]) -> Result<bool> {
+    for pair in elements.windows(2) {
+        if call_comparator(interpreter, less, pair[1].clone(), pair[0].clone())? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+pub(crate) fn srfi_132_library() -> HashMap<String, ValueType> {
+    fn list_sort(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(less), Some(list), None) => {
+                let less = less?;
+                let list = expect_list("list-sort", list?)?;
+                Ok(ValueType::list(sort(interpreter, &less, list)?.into_iter()))
+            }
+            _ => logic_error!("list-sort takes exactly two arguments"),
+        }
+    }
+
+    fn list_merge(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(less), Some(a), Some(b), None) => {
+                let less = less?;
+                let a = expect_list("list-merge", a?)?;
+                let b = expect_list("list-merge", b?)?;
+                Ok(ValueType::list(
+                    merge(interpreter, &less, a, b)?.into_iter(),
+                ))
+            }
+            _ => logic_error!("list-merge takes exactly three arguments"),
+        }
+    }
+
+    fn list_sorted(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(less), Some(list), None) => {
+                let less = less?;
+                let list = expect_list("list-sorted?", list?)?;
+                Ok(ValueType::Boolean(is_sorted(interpreter, &less, &list)?))
+            }
+            _ => logic_error!("list-sorted? takes exactly two arguments"),
+        }
+    }
+
+    fn vector_sort(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(less), Some(vector), start, end, None) => {
+                let less = less?;
+                let vector = expect_vector("vector-sort", vector?)?;
+                let elements = vector.borrow().clone();
+                let (start, end) = expect_range("vector-sort", elements.len(), start, end)?;
+                Ok(ValueType::vector(sort(
+                    interpreter,
+                    &less,
+                    elements[start..end].to_vec(),
+                )?))
+            }
+            _ => logic_error!("vector-sort takes two to four arguments"),
+        }
+    }
+
+    // the vector comes first, unlike the other sorting procedures
+    fn vector_sort_in_place(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(vector), Some(less), start, end, None) => {
+                let vector = expect_vector("vector-sort!", vector?)?;
+                let less = less?;
+                let elements = vector.borrow().clone();
+                let (start, end) = expect_range("vector-sort!", elements.len(), start, end)?;
+                let sorted = sort(interpreter, &less, elements[start..end].to_vec())?;
+                let mut elements = vector.borrow_mut();
+                if elements.len() < end {
+                    logic_error!("vector-sort!: the vector was shortened by the comparison")
+                }
+                elements.splice(start..end, sorted);
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("vector-sort! takes two to four arguments"),
+        }
+    }
+
+    fn vector_merge(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(less), Some(a), Some(b)) => {
+                let a = expect_vector("vector-merge", a)?.borrow().clone();
+                let b = expect_vector("vector-merge", b)?.borrow().clone();
+                let mut bounds = arguments.map(Ok);
+                let (start1, end1) =
+                    expect_range("vector-merge", a.len(), bounds.next(), bounds.next())?;
+                let (start2, end2) =
+                    expect_range("vector-merge", b.len(), bounds.next(), bounds.next())?;
+                if bounds.next().is_some() {
+                    logic_error!("vector-merge takes three to seven arguments")
+                }
+                Ok(ValueType::vector(merge(
+                    interpreter,
+                    &less,
+                    a[start1..end1].to_vec(),
+                    b[start2..end2].to_vec(),
+                )?))
+            }
+            _ => logic_error!("vector-merge takes three to seven arguments"),
+        }
+    }
+
+    fn vector_sorted(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(less), Some(vector), start, end, None) => {
+                let less = less?;
+                let elements = expect_vector("vector-sorted?", vector?)?.borrow().clone();
+                let (start, end) = expect_range("vector-sorted?", elements.len(), start, end)?;
+                Ok(ValueType::Boolean(is_sorted(
+                    interpreter,
+                    &less,
+                    &elements[start..end],
+                )?))
+            }
+            _ => logic_error!("vector-sorted? takes two to four arguments"),
+        }
+    }
+
+    // every sort is stable, so the stable names are the same procedures
+    [
+        interpreter_function_mapping!("list-sort", list_sort),
+        interpreter_function_mapping!("list-stable-sort", list_sort),
+        interpreter_function_mapping!("list-merge", list_merge),
+        interpreter_function_mapping!("list-sorted?", list_sorted),
+        interpreter_function_mapping!("vector-sort", vector_sort),
+        interpreter_function_mapping!("vector-stable-sort", vector_sort),
+        interpreter_function_mapping!("vector-sort!", vector_sort_in_place),
+        interpreter_function_mapping!("vector-stable-sort!", vector_sort_in_place),
+        interpreter_function_mapping!("vector-merge", vector_merge),
+        interpreter_function_mapping!("vector-sorted?", vector_sorted),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn sorting() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(list-sort < '(3 1 2 5 4))", "(1 2 3 4 5)"),
+            ("(list-sort < '())", "()"),
+            (
+                "(list-sort (lambda (a b) (< (car a) (car b))) '((1 . a) (0 . b) (1 . c) (0 . d)))",
+                "((0 . b) (0 . d) (1 . a) (1 . c))",
+            ),
+            ("(list-merge < '(1 3 5) '(2 4 6 8))", "(1 2 3 4 5 6 8)"),
+            ("(list-sorted? < '(1 2 2 3))", "#t"),
+            ("(list-sorted? < '(1 3 2))", "#f"),
+            ("(vector-sort < #(5 3 1 4) 1)", "#(1 3 4)"),
+            ("(vector-merge < #(1 4) #(0 2 3 9) 0 2 1 3)", "#(1 2 3 4)"),
+            ("(vector-sorted? > #(3 2 1))", "#t"),
+            (
+                "(define v (vector 9 8 7 6 5)) (vector-sort! v < 1 4) v",
+                "#(9 6 7 8 5)",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(list-sort car '(1 2))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "car takes exactly one argument".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,674 @@
+use crate::interpreter::bigint::BigInt;
+use crate::interpreter::number::{integer_overflow, MAX_INTEGER_BITS};
+use crate::interpreter::scheme::base::{expect_index, expect_list, expect_vector};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// integers are two's complement with the sign bit repeated forever, and of any size up to
+// the limit of the other exact arithmetic
+fn integer_result(n: BigInt) -> Result<ValueType> {
+    Ok(ValueType::Number(Number::integer(n)))
+}
+
+fn boolean(value: bool) -> ValueType {
+    ValueType::Boolean(value)
+}
+
+fn expect_integer(name: &str, value: ValueType) -> Result<BigInt> {
+    let integer = match &value {
+        ValueType::Number(n) => n.to_big(),
+        _ => None,
+    };
+    match integer {
+        Some(n) => Ok(n),
+        None => logic_error!("{}: expect an exact integer, got {}", name, value),
+    }
+}
+
+// fields past the limit would build integers too large for it
+fn bit_index(name: &str, value: ValueType) -> Result<usize> {
+    match expect_index(name, value)? {
+        index if index as u64 >= MAX_INTEGER_BITS => Err(integer_overflow()),
+        index => Ok(index),
+    }
+}
+
+fn field_mask(width: usize) -> BigInt {
+    BigInt::one().shift_left(width).subtract(&BigInt::one())
+}
+
+// the start and end of a field, which must not be reversed
+fn expect_field(name: &str, start: ValueType, end: ValueType) -> Result<(usize, usize)> {
+    let (start, end) = (bit_index(name, start)?, bit_index(name, end)?);
+    if start > end {
+        logic_error!(
+            "{}: expect start <= end, got start {} and end {}",
+            name,
+            start,
+            end
+        )
+    }
+    Ok((start, end))
+}
+
+fn bit_field(n: &BigInt, start: usize, end: usize) -> BigInt {
+    n.shift_right(start)
+        .bitwise(&field_mask(end - start), |a, b| a & b)
+}
+
+fn integer_length(n: &BigInt) -> u64 {
+    match n.is_negative() {
+        true => n.not().bits(),
+        false => n.bits(),
+    }
+}
+
+fn replace_field(destination: &BigInt, field: &BigInt, start: usize, end: usize) -> BigInt {
+    let mask = field_mask(end - start).shift_left(start);
+    let cleared = destination.bitwise(&mask, |a, b| a & !b);
+    let field = field.shift_left(start).bitwise(&mask, |a, b| a & b);
+    cleared.bitwise(&field, |a, b| a | b)
+}
+
+// the bits of a non-negative integer, the least significant first
+fn bits_of_field(field: &BigInt, width: usize) -> impl Iterator<Item = bool> + '_ {
+    let (_, limbs) = field.to_parts();
+    (0..width).map(move |bit| {
+        limbs
+            .get(bit / 32)
+            .is_some_and(|limb| limb >> (bit % 32) & 1 == 1)
+    })
+}
+
+fn integer_of_bits(bits: impl Iterator<Item = bool>) -> BigInt {
+    let mut limbs = vec![];
+    for (index, bit) in bits.enumerate() {
+        if index % 32 == 0 {
+            limbs.push(0);
+        }
+        *limbs.last_mut().unwrap() |= (bit as u32) << (index % 32);
+    }
+    BigInt::from_parts(false, limbs)
+}
+
+fn integers(name: &str, arguments: Arguments) -> Result<Vec<BigInt>> {
+    arguments
+        .map(|argument| expect_integer(name, argument?))
+        .collect()
+}
+
+macro_rules! integer_arguments {
+    ($name:literal, $arguments:expr, $($integer:ident),+) => {
+        let integers = integers($name, $arguments)?;
+        let ($($integer),+) = match integers.as_slice() {
+            [$($integer),+] => ($($integer),+),
+            _ => logic_error!(
+                "{} takes exactly {} arguments",
+                $name,
+                [$(stringify!($integer)),+].len()
+            ),
+        };
+    };
+}
+
+pub(crate) fn srfi_151_library() -> HashMap<String, ValueType> {
+    fn bitwise_not(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(n), None) => integer_result(expect_integer("bitwise-not", n?)?.not()),
+            _ => logic_error!("bitwise-not takes exactly one argument"),
+        }
+    }
+
+    macro_rules! bitwise_fold {
+        ($function:ident, $name:literal, $identity:expr, $operation:expr) => {
+            fn $function(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                let operation: fn(u32, u32) -> u32 = $operation;
+                integer_result(
+                    integers($name, arguments)?
+                        .iter()
+                        .fold(BigInt::from_i128($identity), |a, b| a.bitwise(b, operation)),
+                )
+            }
+        };
+    }
+
+    bitwise_fold!(bitwise_and, "bitwise-and", -1, |a, b| a & b);
+    bitwise_fold!(bitwise_ior, "bitwise-ior", 0, |a, b| a | b);
+    bitwise_fold!(bitwise_xor, "bitwise-xor", 0, |a, b| a ^ b);
+    bitwise_fold!(bitwise_eqv, "bitwise-eqv", -1, |a, b| !(a ^ b));
+
+    macro_rules! bitwise_binary {
+        ($function:ident, $name:literal, $operation:expr) => {
+            fn $function(
+                arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                integer_arguments!($name, arguments, a, b);
+                integer_result(a.bitwise(b, $operation))
+            }
+        };
+    }
+
+    bitwise_binary!(bitwise_nand, "bitwise-nand", |a, b| !(a & b));
+    bitwise_binary!(bitwise_nor, "bitwise-nor", |a, b| !(a | b));
+    bitwise_binary!(bitwise_andc1, "bitwise-andc1", |a, b| !a & b);
+    bitwise_binary!(bitwise_andc2, "bitwise-andc2", |a, b| a & !b);
+    bitwise_binary!(bitwise_orc1, "bitwise-orc1", |a, b| !a | b);
+    bitwise_binary!(bitwise_orc2, "bitwise-orc2", |a, b| a | !b);
+
+    // (bitwise-if mask i j) takes the bits of i where the mask is set and those of j elsewhere
+    fn bitwise_if(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        integer_arguments!("bitwise-if", arguments, mask, i, j);
+        let (i, j) = (
+            mask.bitwise(i, |a, b| a & b),
+            mask.bitwise(j, |a, b| !a & b),
+        );
+        integer_result(i.bitwise(&j, |a, b| a | b))
+    }
+
+    // shifting right past the length leaves only the sign
+    fn shift(n: &BigInt, count: i64) -> Result<ValueType> {
+        match count {
+            count if count < 0 => integer_result(n.shift_right(count.unsigned_abs() as usize)),
+            _ if n.is_zero() => integer_result(BigInt::zero()),
+            count if count as u64 + n.bits() > MAX_INTEGER_BITS => Err(integer_overflow()),
+            count => integer_result(n.shift_left(count as usize)),
+        }
+    }
+
+    fn shift_count(name: &str, value: ValueType) -> Result<i64> {
+        match expect_integer(name, value)? {
+            count if count.is_negative() => Ok(count.to_i64().unwrap_or(i64::MIN)),
+            count => Ok(count.to_i64().unwrap_or(i64::MAX)),
+        }
+    }
+
+    fn arithmetic_shift(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(n), Some(count), None) => shift(
+                &expect_integer("arithmetic-shift", n?)?,
+                shift_count("arithmetic-shift", count?)?,
+            ),
+            _ => logic_error!("arithmetic-shift takes exactly two arguments"),
+        }
+    }
+
+    fn shift_left(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(n), Some(count), None) => shift(
+                &expect_integer("shift-left", n?)?,
+                expect_index("shift-left", count?)? as i64,
+            ),
+            _ => logic_error!("shift-left takes exactly two arguments"),
+        }
+    }
+
+    fn shift_right(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(n), Some(count), None) => shift(
+                &expect_integer("shift-right", n?)?,
+                -(expect_index("shift-right", count?)? as i64),
+            ),
+            _ => logic_error!("shift-right takes exactly two arguments"),
+        }
+    }
+
+    // the set bits of a non-negative integer, the clear bits of a negative one
+    fn bit_count(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(n), None) => {
+                let n = expect_integer("bit-count", n?)?;
+                let bits = match n.is_negative() {
+                    true => n.not().count_ones(),
+                    false => n.count_ones(),
+                };
+                integer_result(BigInt::from_i128(bits as i128))
+            }
+            _ => logic_error!("bit-count takes exactly one argument"),
+        }
+    }
+
+    fn integer_length_procedure(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(n), None) => {
+                let length = integer_length(&expect_integer("integer-length", n?)?);
+                integer_result(BigInt::from_i128(length as i128))
+            }
+            _ => logic_error!("integer-length takes exactly one argument"),
+        }
+    }
+
+    // -1 for zero, which has no set bit
+    fn first_set_bit(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(n), None) => match expect_integer("first-set-bit", n?)? {
+                n if n.is_zero() => integer_result(BigInt::from_i128(-1)),
+                n => integer_result(BigInt::from_i128(n.trailing_zeros() as i128)),
+            },
+            _ => logic_error!("first-set-bit takes exactly one argument"),
+        }
+    }
+
+    fn bit_set(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(index), Some(n), None) => {
+                let index = bit_index("bit-set?", index?)?;
+                let n = expect_integer("bit-set?", n?)?;
+                Ok(boolean(!n.shift_right(index).is_even()))
+            }
+            _ => logic_error!("bit-set? takes exactly two arguments"),
+        }
+    }
+
+    fn copy_bit(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(index), Some(n), Some(bit), None) => {
+                let index = bit_index("copy-bit", index?)?;
+                let n = expect_integer("copy-bit", n?)?;
+                let bit = BigInt::from_i128(bit?.is_true() as i128);
+                integer_result(replace_field(&n, &bit, index, index + 1))
+            }
+            _ => logic_error!("copy-bit takes exactly three arguments"),
+        }
+    }
+
+    fn bit_swap(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(first), Some(second), Some(n), None) => {
+                let first = bit_index("bit-swap", first?)?;
+                let second = bit_index("bit-swap", second?)?;
+                let n = expect_integer("bit-swap", n?)?;
+                let swapped =
+                    replace_field(&n, &bit_field(&n, second, second + 1), first, first + 1);
+                integer_result(replace_field(
+                    &swapped,
+                    &bit_field(&n, first, first + 1),
+                    second,
+                    second + 1,
+                ))
+            }
+            _ => logic_error!("bit-swap takes exactly three arguments"),
+        }
+    }
+
+    fn any_bit_set(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        integer_arguments!("any-bit-set?", arguments, test, n);
+        Ok(boolean(!test.bitwise(n, |a, b| a & b).is_zero()))
+    }
+
+    fn every_bit_set(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        integer_arguments!("every-bit-set?", arguments, test, n);
+        Ok(boolean(test.bitwise(n, |a, b| a & b) == *test))
+    }
+
+    // the integer followed by the start and end of a field
+    fn field_arguments(
+        name: &str,
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<(BigInt, usize, usize)> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(n), Some(start), Some(end), None) => {
+                let n = expect_integer(name, n?)?;
+                let (start, end) = expect_field(name, start?, end?)?;
+                Ok((n, start, end))
+            }
+            _ => logic_error!("{} takes exactly three arguments", name),
+        }
+    }
+
+    fn bit_field_procedure(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let (n, start, end) = field_arguments("bit-field", arguments)?;
+        integer_result(bit_field(&n, start, end))
+    }
+
+    fn bit_field_any(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let (n, start, end) = field_arguments("bit-field-any?", arguments)?;
+        Ok(boolean(!bit_field(&n, start, end).is_zero()))
+    }
+
+    fn bit_field_every(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let (n, start, end) = field_arguments("bit-field-every?", arguments)?;
+        Ok(boolean(
+            bit_field(&n, start, end) == field_mask(end - start),
+        ))
+    }
+
+    fn bit_field_clear(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let (n, start, end) = field_arguments("bit-field-clear", arguments)?;
+        integer_result(replace_field(&n, &BigInt::zero(), start, end))
+    }
+
+    fn bit_field_set(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let (n, start, end) = field_arguments("bit-field-set", arguments)?;
+        integer_result(replace_field(&n, &BigInt::from_i128(-1), start, end))
+    }
+
+    // (bit-field-replace destination source start end) puts the low bits of source in the field
+    fn bit_field_replace(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let destination = match arguments.next() {
+            Some(destination) => expect_integer("bit-field-replace", destination?)?,
+            None => logic_error!("bit-field-replace takes exactly four arguments"),
+        };
+        let (source, start, end) = field_arguments("bit-field-replace", arguments)?;
+        integer_result(replace_field(&destination, &source, start, end))
+    }
+
+    // the bits of the field come from source at the same positions
+    fn bit_field_replace_same(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let destination = match arguments.next() {
+            Some(destination) => expect_integer("bit-field-replace-same", destination?)?,
+            None => logic_error!("bit-field-replace-same takes exactly four arguments"),
+        };
+        let (source, start, end) = field_arguments("bit-field-replace-same", arguments)?;
+        integer_result(replace_field(
+            &destination,
+            &bit_field(&source, start, end),
+            start,
+            end,
+        ))
+    }
+
+    // (bit-field-rotate n count start end) rotates the field towards its high end
+    fn bit_field_rotate(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(n), Some(count), Some(start), Some(end), None) => {
+                let n = expect_integer("bit-field-rotate", n?)?;
+                let count = expect_integer("bit-field-rotate", count?)?;
+                let (start, end) = expect_field("bit-field-rotate", start?, end?)?;
+                let width = end - start;
+                if width == 0 {
+                    return integer_result(n);
+                }
+                let (_, count) = count.divide_floor(&BigInt::from_i128(width as i128));
+                let count = count.to_i64().unwrap() as usize;
+                let field = bit_field(&n, start, end);
+                let rotated = field
+                    .shift_left(count)
+                    .bitwise(&field.shift_right(width - count), |a, b| a | b)
+                    .bitwise(&field_mask(width), |a, b| a & b);
+                integer_result(replace_field(&n, &rotated, start, end))
+            }
+            _ => logic_error!("bit-field-rotate takes exactly four arguments"),
+        }
+    }
+
+    fn bit_field_reverse(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let (n, start, end) = field_arguments("bit-field-reverse", arguments)?;
+        let field = bit_field(&n, start, end);
+        let bits = bits_of_field(&field, end - start).collect::<Vec<_>>();
+        let reversed = integer_of_bits(bits.into_iter().rev());
+        integer_result(replace_field(&n, &reversed, start, end))
+    }
+
+    // the least significant bit first, as many bits as the integer length by default
+    fn bits_of(
+        name: &str,
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<Vec<ValueType>> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(n), length, None) => {
+                let n = expect_integer(name, n?)?;
+                let length = match length {
+                    Some(length) => bit_index(name, length?)?,
+                    None => integer_length(&n) as usize,
+                };
+                let field = bit_field(&n, 0, length);
+                Ok(bits_of_field(&field, length).map(boolean).collect())
+            }
+            _ => logic_error!("{} takes one or two arguments", name),
+        }
+    }
+
+    // the integers built from bits are never negative
+    fn from_bits(bits: Vec<ValueType>) -> Result<ValueType> {
+        if bits.len() as u64 > MAX_INTEGER_BITS {
+            return Err(integer_overflow());
+        }
+        integer_result(integer_of_bits(bits.iter().map(ValueType::is_true)))
+    }
+
+    fn bits_to_list(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        Ok(ValueType::list(
+            bits_of("bits->list", arguments)?.into_iter(),
+        ))
+    }
+
+    fn bits_to_vector(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        Ok(ValueType::vector(bits_of("bits->vector", arguments)?))
+    }
+
+    fn list_to_bits(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => from_bits(expect_list("list->bits", list?)?),
+            _ => logic_error!("list->bits takes exactly one argument"),
+        }
+    }
+
+    fn vector_to_bits(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(vector), None) => {
+                let bits = expect_vector("vector->bits", vector?)?.borrow().clone();
+                from_bits(bits)
+            }
+            _ => logic_error!("vector->bits takes exactly one argument"),
+        }
+    }
+
+    fn bits(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        from_bits(arguments.collect::<Result<_>>()?)
+    }
+
+    [
+        function_mapping!("bitwise-not", bitwise_not),
+        function_mapping!("bitwise-and", bitwise_and),
+        function_mapping!("bitwise-ior", bitwise_ior),
+        function_mapping!("bitwise-xor", bitwise_xor),
+        function_mapping!("bitwise-eqv", bitwise_eqv),
+        function_mapping!("bitwise-nand", bitwise_nand),
+        function_mapping!("bitwise-nor", bitwise_nor),
+        function_mapping!("bitwise-andc1", bitwise_andc1),
+        function_mapping!("bitwise-andc2", bitwise_andc2),
+        function_mapping!("bitwise-orc1", bitwise_orc1),
+        function_mapping!("bitwise-orc2", bitwise_orc2),
+        function_mapping!("bitwise-if", bitwise_if),
+        function_mapping!("arithmetic-shift", arithmetic_shift),
+        function_mapping!("shift-left", shift_left),
+        function_mapping!("shift-right", shift_right),
+        function_mapping!("bit-count", bit_count),
+        function_mapping!("integer-length", integer_length_procedure),
+        function_mapping!("first-set-bit", first_set_bit),
+        function_mapping!("bit-set?", bit_set),
+        function_mapping!("copy-bit", copy_bit),
+        function_mapping!("bit-swap", bit_swap),
+        function_mapping!("any-bit-set?", any_bit_set),
+        function_mapping!("every-bit-set?", every_bit_set),
+        function_mapping!("bit-field", bit_field_procedure),
+        function_mapping!("bit-field-any?", bit_field_any),
+        function_mapping!("bit-field-every?", bit_field_every),
+        function_mapping!("bit-field-clear", bit_field_clear),
+        function_mapping!("bit-field-set", bit_field_set),
+        function_mapping!("bit-field-replace", bit_field_replace),
+        function_mapping!("bit-field-replace-same", bit_field_replace_same),
+        function_mapping!("bit-field-rotate", bit_field_rotate),
+        function_mapping!("bit-field-reverse", bit_field_reverse),
+        function_mapping!("bits->list", bits_to_list),
+        function_mapping!("bits->vector", bits_to_vector),
+        function_mapping!("list->bits", list_to_bits),
+        function_mapping!("vector->bits", vector_to_bits),
+        function_mapping!("bits", bits),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn bitwise_operations() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(bitwise-not 10)", "-11"),
+            ("(bitwise-and 12 10)", "8"),
+            ("(bitwise-and)", "-1"),
+            ("(bitwise-ior 12 10 1)", "15"),
+            ("(bitwise-xor 12 10)", "6"),
+            ("(bitwise-eqv -1 5)", "5"),
+            ("(bitwise-nand 12 10)", "-9"),
+            ("(bitwise-andc2 12 10)", "4"),
+            ("(bitwise-if 3 1 8)", "9"),
+            ("(arithmetic-shift 8 2)", "32"),
+            ("(arithmetic-shift -8 -2)", "-2"),
+            ("(arithmetic-shift -1 -100)", "-1"),
+            ("(arithmetic-shift 1 62)", "4611686018427387904"),
+            ("(shift-right 5 1)", "2"),
+            ("(bit-count 13)", "3"),
+            ("(bit-count -2)", "1"),
+            ("(integer-length 255)", "8"),
+            ("(integer-length -256)", "8"),
+            ("(integer-length -9223372036854775808)", "63"),
+            ("(first-set-bit 40)", "3"),
+            ("(bit-set? 1 6)", "#t"),
+            ("(bit-set? 200 -1)", "#t"),
+            ("(copy-bit 0 6 #t)", "7"),
+            ("(copy-bit 62 -1 #f)", "-4611686018427387905"),
+            ("(bit-swap 0 2 4)", "1"),
+            ("(any-bit-set? 3 6)", "#t"),
+            ("(every-bit-set? 3 6)", "#f"),
+            ("(bit-field 6 0 2)", "2"),
+            ("(bit-field -1 60 70)", "1023"),
+            ("(bit-field-any? 6 0 1)", "#f"),
+            ("(bit-field-every? 7 0 3)", "#t"),
+            ("(bit-field-clear 15 1 3)", "9"),
+            ("(bit-field-set 0 1 3)", "6"),
+            ("(bit-field-replace 10 2 1 3)", "12"),
+            ("(bit-field-replace-same 15 0 1 3)", "9"),
+            ("(bit-field-rotate 6 1 0 3)", "5"),
+            ("(bit-field-rotate 6 -1 0 3)", "3"),
+            ("(bit-field-reverse 1 0 4)", "8"),
+            ("(bits->list 6)", "(#f #t #t)"),
+            ("(bits->vector 1 3)", "#(#t #f #f)"),
+            ("(list->bits '(#t #f #t))", "5"),
+            ("(vector->bits #(#f #t))", "2"),
+            ("(bits #t #t)", "3"),
+            ("(arithmetic-shift 1 63)", "9223372036854775808"),
+            ("(arithmetic-shift (expt 2 100) -99)", "2"),
+            ("(arithmetic-shift -1 -100000000000)", "-1"),
+            ("(arithmetic-shift 5 (- (expt 2 70)))", "0"),
+            ("(copy-bit 63 1 #t)", "9223372036854775809"),
+            ("(copy-bit 63 -1 #f)", "-9223372036854775809"),
+            ("(bit-field -1 0 64)", "18446744073709551615"),
+            (
+                "(bit-field -1 0 200)",
+                "1606938044258990275541962092341162602522202993782792835301375",
+            ),
+            (
+                "(bitwise-and (expt 2 100) (- (expt 2 101) 1))",
+                "1267650600228229401496703205376",
+            ),
+            ("(bitwise-not (expt 2 64))", "-18446744073709551617"),
+            ("(integer-length (expt 2 100))", "101"),
+            ("(bit-count (- (expt 2 100)))", "100"),
+            ("(first-set-bit (expt 2 100))", "100"),
+            ("(bit-set? 100 (expt 2 100))", "#t"),
+            (
+                "(bit-field-reverse 1 0 100)",
+                "633825300114114700748351602688",
+            ),
+            (
+                "(list->bits (vector->list (make-vector 64 #t)))",
+                "18446744073709551615",
+            ),
+        ],
+    )?;
+    for source in [
+        "(arithmetic-shift 1 2000000)",
+        "(bit-field 1 0 2000000)",
+        "(bit-set? 2000000 1)",
+    ] {
+        assert_eq!(
+            interpreter.eval(source.chars()),
+            Err(Error {
+                category: ErrorType::Logic,
+                message: "integer overflow".to_string()
+            }),
+            "{}",
+            source
+        );
+    }
+    Ok(())
+}
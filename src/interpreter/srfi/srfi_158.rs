@@ -0,0 +1,976 @@
+use crate::interpreter::scheme::base::{
+    expect_character, expect_index, expect_list, expect_number, expect_range, expect_string,
+    expect_vector,
+};
+use crate::interpreter::*;
+use std::collections::{HashMap, VecDeque};
+
+// the state behind a generator procedure, the generators it draws from are procedures
+// so scheme generators and native ones combine freely
+#[derive(Debug, Clone, PartialEq)]
+pub enum Generator {
+    Values(VecDeque<ValueType>),
+    Circular(Vec<ValueType>, usize),
+    // counts down remaining when it is given and stops at end when it is given
+    Range {
+        next: Number,
+        step: Number,
+        end: Option<Number>,
+        remaining: Option<usize>,
+    },
+    Unfold {
+        stop: ValueType,
+        mapper: ValueType,
+        successor: ValueType,
+        seed: ValueType,
+    },
+    Append(VecDeque<ValueType>),
+    Map(ValueType, Vec<ValueType>),
+    // keeps the values satisfying the predicate, or the others for gremove
+    Filter(ValueType, ValueType, bool),
+    Take(ValueType, usize, Option<ValueType>),
+    Drop(ValueType, usize),
+    TakeWhile(ValueType, ValueType),
+    DropWhile(ValueType, ValueType),
+}
+
+impl Generator {
+    fn next(&mut self, interpreter: &Interpreter) -> Result<ValueType> {
+        Ok(match self {
+            Generator::Values(values) => values.pop_front().unwrap_or(ValueType::Eof),
+            Generator::Circular(values, index) => match values.get(*index) {
+                Some(value) => {
+                    let value = value.clone();
+                    *index = (*index + 1) % values.len();
+                    value
+                }
+                None => ValueType::Eof,
+            },
+            Generator::Range {
+                next,
+                step,
+                end,
+                remaining,
+            } => {
+                let ended = match end {
+                    Some(end) if *step < Number::Integer(0) => *next <= *end,
+                    Some(end) => *next >= *end,
+                    None => false,
+                };
+                match remaining {
+                    _ if ended => ValueType::Eof,
+                    Some(0) => ValueType::Eof,
+                    _ => {
+                        if let Some(remaining) = remaining {
+                            *remaining -= 1;
+                        }
+                        let value = next.clone();
+                        *next = (value.clone() + step.clone())?;
+                        ValueType::Number(value)
+                    }
+                }
+            }
+            Generator::Unfold {
+                stop,
+                mapper,
+                successor,
+                seed,
+            } => {
+                if interpreter
+                    .call_procedure(stop, vec![seed.clone()])?
+                    .is_true()
+                {
+                    ValueType::Eof
+                } else {
+                    let value = interpreter.call_procedure(mapper, vec![seed.clone()])?;
+                    *seed = interpreter.call_procedure(successor, vec![seed.clone()])?;
+                    value
+                }
+            }
+            Generator::Append(generators) => loop {
+                match generators.front() {
+                    Some(generator) => match interpreter.call_procedure(generator, vec![])? {
+                        ValueType::Eof => {
+                            generators.pop_front();
+                        }
+                        value => break value,
+                    },
+                    None => break ValueType::Eof,
+                }
+            },
+            // stops with the shortest generator
+            Generator::Map(procedure, generators) => {
+                let mut values = Vec::with_capacity(generators.len());
+                for generator in generators.iter() {
+                    match interpreter.call_procedure(generator, vec![])? {
+                        ValueType::Eof => return Ok(ValueType::Eof),
+                        value => values.push(value),
+                    }
+                }
+                interpreter.call_procedure(procedure, values)?
+            }
+            Generator::Filter(predicate, generator, keep) => loop {
+                match interpreter.call_procedure(generator, vec![])? {
+                    ValueType::Eof => break ValueType::Eof,
+                    value => {
+                        if interpreter
+                            .call_procedure(predicate, vec![value.clone()])?
+                            .is_true()
+                            == *keep
+                        {
+                            break value;
+                        }
+                    }
+                }
+            },
+            // short generators are padded when a padding is given
+            Generator::Take(generator, remaining, padding) => match remaining {
+                0 => ValueType::Eof,
+                _ => {
+                    *remaining -= 1;
+                    match (interpreter.call_procedure(generator, vec![])?, padding) {
+                        (ValueType::Eof, Some(padding)) => padding.clone(),
+                        (value, _) => value,
+                    }
+                }
+            },
+            Generator::Drop(generator, count) => {
+                while *count > 0 {
+                    *count -= 1;
+                    if let ValueType::Eof = interpreter.call_procedure(generator, vec![])? {
+                        return Ok(ValueType::Eof);
+                    }
+                }
+                interpreter.call_procedure(generator, vec![])?
+            }
+            Generator::TakeWhile(predicate, generator) => {
+                match interpreter.call_procedure(generator, vec![])? {
+                    ValueType::Eof => ValueType::Eof,
+                    value => {
+                        if interpreter
+                            .call_procedure(predicate, vec![value.clone()])?
+                            .is_true()
+                        {
+                            value
+                        } else {
+                            *self = Generator::Values(VecDeque::new());
+                            ValueType::Eof
+                        }
+                    }
+                }
+            }
+            Generator::DropWhile(predicate, generator) => {
+                let generator = generator.clone();
+                loop {
+                    match interpreter.call_procedure(&generator, vec![])? {
+                        ValueType::Eof => break ValueType::Eof,
+                        value => {
+                            if !interpreter
+                                .call_procedure(predicate, vec![value.clone()])?
+                                .is_true()
+                            {
+                                *self = Generator::Append(VecDeque::from(vec![generator]));
+                                break value;
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+// the state behind an accumulator procedure, which returns its result when given the end of file object
+#[derive(Debug, Clone, PartialEq)]
+pub enum Accumulator {
+    // the kons procedure, the state and the finalizer
+    Fold(Box<(ValueType, ValueType, ValueType)>),
+    Count(i64),
+    List(Vec<ValueType>),
+    ReverseList(Vec<ValueType>),
+    Vector(Vec<ValueType>),
+    String(String),
+    Sum(Number),
+    Product(Number),
+}
+
+impl Accumulator {
+    fn accumulate(&mut self, interpreter: &Interpreter, value: ValueType) -> Result<ValueType> {
+        if let ValueType::Eof = value {
+            return Ok(match self {
+                Accumulator::Fold(fold) => {
+                    let (_, state, finalizer) = fold.as_ref();
+                    interpreter.call_procedure(finalizer, vec![state.clone()])?
+                }
+                Accumulator::Count(count) => ValueType::Number(Number::Integer(*count)),
+                Accumulator::List(values) => ValueType::list(values.iter().cloned()),
+                Accumulator::ReverseList(values) => ValueType::list(values.iter().rev().cloned()),
+                Accumulator::Vector(values) => ValueType::vector(values.clone()),
+                Accumulator::String(string) => ValueType::String(string.clone()),
+                Accumulator::Sum(number) | Accumulator::Product(number) => {
+                    ValueType::Number(number.clone())
+                }
+            });
+        }
+        match self {
+            Accumulator::Fold(fold) => {
+                let (kons, state, _) = fold.as_mut();
+                *state = interpreter.call_procedure(kons, vec![value, state.clone()])?
+            }
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::List(values)
+            | Accumulator::ReverseList(values)
+            | Accumulator::Vector(values) => values.push(value),
+            Accumulator::String(string) => {
+                string.push(expect_character("string-accumulator", value)?)
+            }
+            Accumulator::Sum(number) => *number = (number.clone() + expect_number(value)?)?,
+            Accumulator::Product(number) => *number = (number.clone() * expect_number(value)?)?,
+        }
+        Ok(ValueType::Void)
+    }
+}
+
+// the generator procedure native code hands to scheme
+pub(crate) fn generator_procedure(interpreter: &Interpreter, generator: Generator) -> ValueType {
+    // the state is taken out while it runs, so a generator calling itself sees the end
+    fn next(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match arguments.next() {
+            Some(Ok(ValueType::Generator(state))) => {
+                let mut generator = state.replace(Generator::Values(VecDeque::new()));
+                let value = generator.next(interpreter);
+                state.replace(generator);
+                value
+            }
+            _ => logic_error!("generator: broken generator state"),
+        }
+    }
+    native_closure(
+        interpreter,
+        vec![ValueType::Generator(Rc::new(RefCell::new(generator)))],
+        BuildinProcedure("generator", next),
+        vec![],
+        None,
+    )
+}
+
+fn accumulator_procedure(interpreter: &Interpreter, accumulator: Accumulator) -> ValueType {
+    fn next(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(Ok(ValueType::Accumulator(state))), Some(value)) => {
+                let mut accumulator = state.borrow().clone();
+                let result = accumulator.accumulate(interpreter, value?);
+                state.replace(accumulator);
+                result
+            }
+            _ => logic_error!("accumulator: broken accumulator state"),
+        }
+    }
+    native_closure(
+        interpreter,
+        vec![ValueType::Accumulator(Rc::new(RefCell::new(accumulator)))],
+        BuildinProcedure("accumulator", next),
+        vec!["value".to_string()],
+        None,
+    )
+}
+
+// the values of any generator procedure, for native code consuming generators
+pub(crate) struct Generated<'a> {
+    interpreter: &'a Interpreter,
+    generator: ValueType,
+}
+
+impl<'a> Generated<'a> {
+    pub(crate) fn new(interpreter: &'a Interpreter, generator: ValueType) -> Self {
+        Self {
+            interpreter,
+            generator,
+        }
+    }
+}
+
+impl Iterator for Generated<'_> {
+    type Item = Result<ValueType>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.interpreter.call_procedure(&self.generator, vec![]) {
+            Ok(ValueType::Eof) => None,
+            value => Some(value),
+        }
+    }
+}
+
+fn expect_procedure(name: &str, value: ValueType) -> Result<ValueType> {
+    match value {
+        procedure @ ValueType::Procedure(_) => Ok(procedure),
+        other => logic_error!("{}: expect a procedure, got {}", name, other),
+    }
+}
+
+// the procedure followed by at least one generator
+fn procedure_and_generators(
+    name: &str,
+    arguments: Arguments,
+) -> Result<(ValueType, Vec<ValueType>)> {
+    let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+    match arguments.next() {
+        Some(procedure) if arguments.len() > 0 => Ok((
+            expect_procedure(name, procedure)?,
+            arguments
+                .map(|generator| expect_procedure(name, generator))
+                .collect::<Result<_>>()?,
+        )),
+        _ => logic_error!("{} takes a procedure and at least one generator", name),
+    }
+}
+
+pub(crate) fn srfi_158_library() -> HashMap<String, ValueType> {
+    fn generator(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Values(arguments.collect::<Result<_>>()?),
+        ))
+    }
+
+    fn circular_generator(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let values = arguments.collect::<Result<Vec<_>>>()?;
+        if values.is_empty() {
+            logic_error!("circular-generator takes at least one argument")
+        }
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Circular(values, 0),
+        ))
+    }
+
+    // (make-iota-generator count [start [step]])
+    fn make_iota_generator(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        if arguments.is_empty() || arguments.len() > 3 {
+            logic_error!("make-iota-generator takes one to three arguments")
+        }
+        let mut arguments = arguments.into_iter();
+        let count = expect_index("make-iota-generator", arguments.next().unwrap())?;
+        let start = arguments.next().map(expect_number).transpose()?;
+        let step = arguments.next().map(expect_number).transpose()?;
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Range {
+                next: start.unwrap_or(Number::Integer(0)),
+                step: step.unwrap_or(Number::Integer(1)),
+                end: None,
+                remaining: Some(count),
+            },
+        ))
+    }
+
+    // (make-range-generator start [end [step]])
+    fn make_range_generator(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let arguments = arguments.collect::<Result<Vec<_>>>()?;
+        if arguments.is_empty() || arguments.len() > 3 {
+            logic_error!("make-range-generator takes one to three arguments")
+        }
+        let mut arguments = arguments.into_iter();
+        let start = expect_number(arguments.next().unwrap())?;
+        let end = arguments.next().map(expect_number).transpose()?;
+        let step = arguments.next().map(expect_number).transpose()?;
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Range {
+                next: start,
+                step: step.unwrap_or(Number::Integer(1)),
+                end,
+                remaining: None,
+            },
+        ))
+    }
+
+    fn make_unfold_generator(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(stop), Some(mapper), Some(successor), Some(seed), None) => {
+                Ok(generator_procedure(
+                    interpreter,
+                    Generator::Unfold {
+                        stop: expect_procedure("make-unfold-generator", stop?)?,
+                        mapper: expect_procedure("make-unfold-generator", mapper?)?,
+                        successor: expect_procedure("make-unfold-generator", successor?)?,
+                        seed: seed?,
+                    },
+                ))
+            }
+            _ => logic_error!("make-unfold-generator takes exactly four arguments"),
+        }
+    }
+
+    fn list_to_generator(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => Ok(generator_procedure(
+                interpreter,
+                Generator::Values(expect_list("list->generator", list?)?.into()),
+            )),
+            _ => logic_error!("list->generator takes exactly one argument"),
+        }
+    }
+
+    fn vector_to_generator(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        let vector = match arguments.next() {
+            Some(vector) => expect_vector("vector->generator", vector?)?,
+            None => logic_error!("vector->generator takes one to three arguments"),
+        };
+        let vector = vector.borrow();
+        let (start, end) = expect_range(
+            "vector->generator",
+            vector.len(),
+            arguments.next(),
+            arguments.next(),
+        )?;
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Values(vector[start..end].iter().cloned().collect()),
+        ))
+    }
+
+    fn reverse_vector_to_generator(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        let vector = match arguments.next() {
+            Some(vector) => expect_vector("reverse-vector->generator", vector?)?,
+            None => logic_error!("reverse-vector->generator takes one to three arguments"),
+        };
+        let vector = vector.borrow();
+        let (start, end) = expect_range(
+            "reverse-vector->generator",
+            vector.len(),
+            arguments.next(),
+            arguments.next(),
+        )?;
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Values(vector[start..end].iter().rev().cloned().collect()),
+        ))
+    }
+
+    fn string_to_generator(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        let string = match arguments.next() {
+            Some(string) => expect_string("string->generator", string?)?,
+            None => logic_error!("string->generator takes one to three arguments"),
+        };
+        let characters = string.chars().collect::<Vec<_>>();
+        let (start, end) = expect_range(
+            "string->generator",
+            characters.len(),
+            arguments.next(),
+            arguments.next(),
+        )?;
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Values(
+                characters[start..end]
+                    .iter()
+                    .cloned()
+                    .map(ValueType::Character)
+                    .collect(),
+            ),
+        ))
+    }
+
+    // (gcons* value ... generator)
+    fn gcons(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut values = arguments.collect::<Result<VecDeque<_>>>()?;
+        let generator = match values.pop_back() {
+            Some(generator) => expect_procedure("gcons*", generator)?,
+            None => logic_error!("gcons* takes at least one argument"),
+        };
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Append(VecDeque::from(vec![
+                generator_procedure(interpreter, Generator::Values(values)),
+                generator,
+            ])),
+        ))
+    }
+
+    fn gappend(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let generators = arguments
+            .map(|generator| expect_procedure("gappend", generator?))
+            .collect::<Result<_>>()?;
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Append(generators),
+        ))
+    }
+
+    fn gmap(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (procedure, generators) = procedure_and_generators("gmap", arguments)?;
+        Ok(generator_procedure(
+            interpreter,
+            Generator::Map(procedure, generators),
+        ))
+    }
+
+    macro_rules! procedure_generator {
+        ($function:ident, $name:literal, $make:expr) => {
+            fn $function(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(procedure), Some(generator), None) => Ok(generator_procedure(
+                        interpreter,
+                        $make(
+                            expect_procedure($name, procedure?)?,
+                            expect_procedure($name, generator?)?,
+                        ),
+                    )),
+                    _ => logic_error!("{} takes exactly two arguments", $name),
+                }
+            }
+        };
+    }
+
+    procedure_generator!(gfilter, "gfilter", |predicate, generator| {
+        Generator::Filter(predicate, generator, true)
+    });
+    procedure_generator!(gremove, "gremove", |predicate, generator| {
+        Generator::Filter(predicate, generator, false)
+    });
+    procedure_generator!(gtake_while, "gtake-while", Generator::TakeWhile);
+    procedure_generator!(gdrop_while, "gdrop-while", Generator::DropWhile);
+
+    // (gtake generator k [padding])
+    fn gtake(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(generator), Some(count), padding, None) => Ok(generator_procedure(
+                interpreter,
+                Generator::Take(
+                    expect_procedure("gtake", generator?)?,
+                    expect_index("gtake", count?)?,
+                    padding.transpose()?,
+                ),
+            )),
+            _ => logic_error!("gtake takes two or three arguments"),
+        }
+    }
+
+    fn gdrop(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(generator), Some(count), None) => Ok(generator_procedure(
+                interpreter,
+                Generator::Drop(
+                    expect_procedure("gdrop", generator?)?,
+                    expect_index("gdrop", count?)?,
+                ),
+            )),
+            _ => logic_error!("gdrop takes exactly two arguments"),
+        }
+    }
+
+    // (generator->list generator [k]) takes at most k values
+    fn generator_values(
+        name: &str,
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<Vec<ValueType>> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(generator), count, None) => {
+                let generated = Generated::new(interpreter, expect_procedure(name, generator?)?);
+                match count {
+                    Some(count) => generated.take(expect_index(name, count?)?).collect(),
+                    None => generated.collect(),
+                }
+            }
+            _ => logic_error!("{} takes one or two arguments", name),
+        }
+    }
+
+    fn generator_to_list(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let values = generator_values("generator->list", arguments, interpreter)?;
+        Ok(ValueType::list(values.into_iter()))
+    }
+
+    fn generator_to_reverse_list(
+        arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        let values = generator_values("generator->reverse-list", arguments, interpreter)?;
+        Ok(ValueType::list(values.into_iter().rev()))
+    }
+
+    fn generator_to_vector(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let values = generator_values("generator->vector", arguments, interpreter)?;
+        Ok(ValueType::vector(values))
+    }
+
+    fn generator_to_string(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let values = generator_values("generator->string", arguments, interpreter)?;
+        Ok(ValueType::String(
+            values
+                .into_iter()
+                .map(|value| expect_character("generator->string", value))
+                .collect::<Result<_>>()?,
+        ))
+    }
+
+    // (generator-fold kons knil generator ...) calls (kons value ... state) until a generator ends
+    fn generator_fold(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+        let (kons, mut state) = match (arguments.next(), arguments.next()) {
+            (Some(kons), Some(knil)) if arguments.len() > 0 => {
+                (expect_procedure("generator-fold", kons)?, knil)
+            }
+            _ => {
+                logic_error!("generator-fold takes a procedure, a seed and at least one generator")
+            }
+        };
+        let generators = arguments
+            .map(|generator| expect_procedure("generator-fold", generator))
+            .collect::<Result<Vec<_>>>()?;
+        'fold: loop {
+            let mut values = Vec::with_capacity(generators.len() + 1);
+            for generator in &generators {
+                match interpreter.call_procedure(generator, vec![])? {
+                    ValueType::Eof => break 'fold,
+                    value => values.push(value),
+                }
+            }
+            values.push(state);
+            state = interpreter.call_procedure(&kons, values)?;
+        }
+        Ok(state)
+    }
+
+    fn generator_for_each(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (procedure, generators) = procedure_and_generators("generator-for-each", arguments)?;
+        let mapped = generator_procedure(interpreter, Generator::Map(procedure, generators));
+        for value in Generated::new(interpreter, mapped) {
+            value?;
+        }
+        Ok(ValueType::Void)
+    }
+
+    // the first value satisfying the predicate, #f when there is none
+    fn generator_find(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(predicate), Some(generator), None) => {
+                let predicate = expect_procedure("generator-find", predicate?)?;
+                let generator = expect_procedure("generator-find", generator?)?;
+                for value in Generated::new(interpreter, generator) {
+                    let value = value?;
+                    if interpreter
+                        .call_procedure(&predicate, vec![value.clone()])?
+                        .is_true()
+                    {
+                        return Ok(value);
+                    }
+                }
+                Ok(ValueType::Boolean(false))
+            }
+            _ => logic_error!("generator-find takes exactly two arguments"),
+        }
+    }
+
+    fn generator_count(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(predicate), Some(generator), None) => {
+                let predicate = expect_procedure("generator-count", predicate?)?;
+                let generator = expect_procedure("generator-count", generator?)?;
+                let mut count = 0;
+                for value in Generated::new(interpreter, generator) {
+                    if interpreter
+                        .call_procedure(&predicate, vec![value?])?
+                        .is_true()
+                    {
+                        count += 1;
+                    }
+                }
+                Ok(ValueType::Number(Number::Integer(count)))
+            }
+            _ => logic_error!("generator-count takes exactly two arguments"),
+        }
+    }
+
+    // the first true result of the predicate, or #f
+    fn generator_any(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(predicate), Some(generator), None) => {
+                let predicate = expect_procedure("generator-any", predicate?)?;
+                let generator = expect_procedure("generator-any", generator?)?;
+                for value in Generated::new(interpreter, generator) {
+                    let result = interpreter.call_procedure(&predicate, vec![value?])?;
+                    if result.is_true() {
+                        return Ok(result);
+                    }
+                }
+                Ok(ValueType::Boolean(false))
+            }
+            _ => logic_error!("generator-any takes exactly two arguments"),
+        }
+    }
+
+    // the last result of the predicate when all are true, #t for no values
+    fn generator_every(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(predicate), Some(generator), None) => {
+                let predicate = expect_procedure("generator-every", predicate?)?;
+                let generator = expect_procedure("generator-every", generator?)?;
+                let mut result = ValueType::Boolean(true);
+                for value in Generated::new(interpreter, generator) {
+                    result = interpreter.call_procedure(&predicate, vec![value?])?;
+                    if !result.is_true() {
+                        return Ok(result);
+                    }
+                }
+                Ok(result)
+            }
+            _ => logic_error!("generator-every takes exactly two arguments"),
+        }
+    }
+
+    fn make_accumulator(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(kons), Some(knil), Some(finalizer), None) => Ok(accumulator_procedure(
+                interpreter,
+                Accumulator::Fold(Box::new((
+                    expect_procedure("make-accumulator", kons?)?,
+                    knil?,
+                    expect_procedure("make-accumulator", finalizer?)?,
+                ))),
+            )),
+            _ => logic_error!("make-accumulator takes exactly three arguments"),
+        }
+    }
+
+    macro_rules! standard_accumulator {
+        ($function:ident, $name:literal, $accumulator:expr) => {
+            fn $function(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+                match arguments.count() {
+                    0 => Ok(accumulator_procedure(interpreter, $accumulator)),
+                    _ => logic_error!("{} takes no arguments", $name),
+                }
+            }
+        };
+    }
+
+    standard_accumulator!(
+        count_accumulator,
+        "count-accumulator",
+        Accumulator::Count(0)
+    );
+    standard_accumulator!(
+        list_accumulator,
+        "list-accumulator",
+        Accumulator::List(vec![])
+    );
+    standard_accumulator!(
+        reverse_list_accumulator,
+        "reverse-list-accumulator",
+        Accumulator::ReverseList(vec![])
+    );
+    standard_accumulator!(
+        vector_accumulator,
+        "vector-accumulator",
+        Accumulator::Vector(vec![])
+    );
+    standard_accumulator!(
+        string_accumulator,
+        "string-accumulator",
+        Accumulator::String(String::new())
+    );
+    standard_accumulator!(
+        sum_accumulator,
+        "sum-accumulator",
+        Accumulator::Sum(Number::Integer(0))
+    );
+    standard_accumulator!(
+        product_accumulator,
+        "product-accumulator",
+        Accumulator::Product(Number::Integer(1))
+    );
+
+    [
+        interpreter_function_mapping!("generator", generator),
+        // make-generator and gfold are the shorter names of generator and generator-fold
+        interpreter_function_mapping!("make-generator", generator),
+        interpreter_function_mapping!("circular-generator", circular_generator),
+        interpreter_function_mapping!("make-iota-generator", make_iota_generator),
+        interpreter_function_mapping!("make-range-generator", make_range_generator),
+        interpreter_function_mapping!("make-unfold-generator", make_unfold_generator),
+        interpreter_function_mapping!("list->generator", list_to_generator),
+        interpreter_function_mapping!("vector->generator", vector_to_generator),
+        interpreter_function_mapping!("reverse-vector->generator", reverse_vector_to_generator),
+        interpreter_function_mapping!("string->generator", string_to_generator),
+        interpreter_function_mapping!("gcons*", gcons),
+        interpreter_function_mapping!("gappend", gappend),
+        interpreter_function_mapping!("gmap", gmap),
+        interpreter_function_mapping!("gfilter", gfilter),
+        interpreter_function_mapping!("gremove", gremove),
+        interpreter_function_mapping!("gtake", gtake),
+        interpreter_function_mapping!("gdrop", gdrop),
+        interpreter_function_mapping!("gtake-while", gtake_while),
+        interpreter_function_mapping!("gdrop-while", gdrop_while),
+        interpreter_function_mapping!("generator->list", generator_to_list),
+        interpreter_function_mapping!("generator->reverse-list", generator_to_reverse_list),
+        interpreter_function_mapping!("generator->vector", generator_to_vector),
+        interpreter_function_mapping!("generator->string", generator_to_string),
+        interpreter_function_mapping!("generator-fold", generator_fold),
+        interpreter_function_mapping!("gfold", generator_fold),
+        interpreter_function_mapping!("generator-for-each", generator_for_each),
+        interpreter_function_mapping!("generator-find", generator_find),
+        interpreter_function_mapping!("generator-count", generator_count),
+        interpreter_function_mapping!("generator-any", generator_any),
+        interpreter_function_mapping!("generator-every", generator_every),
+        interpreter_function_mapping!("make-accumulator", make_accumulator),
+        interpreter_function_mapping!("count-accumulator", count_accumulator),
+        interpreter_function_mapping!("list-accumulator", list_accumulator),
+        interpreter_function_mapping!("reverse-list-accumulator", reverse_list_accumulator),
+        interpreter_function_mapping!("vector-accumulator", vector_accumulator),
+        interpreter_function_mapping!("string-accumulator", string_accumulator),
+        interpreter_function_mapping!("sum-accumulator", sum_accumulator),
+        interpreter_function_mapping!("product-accumulator", product_accumulator),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn generators() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "(define (odd n) (= 1 (remainder n 2))) (define (even n) (= 0 (remainder n 2)))".chars(),
+    )?;
+    assert_printed(
+        &interpreter,
+        &[
+            ("(generator->list (generator 1 2 3))", "(1 2 3)"),
+            ("(generator->list (make-iota-generator 3 1))", "(1 2 3)"),
+            ("(generator->list (make-range-generator 0 1 1/4))", "(0 1/4 1/2 3/4)"),
+            ("(generator->list (make-range-generator 3 0 -1))", "(3 2 1)"),
+            ("(generator->list (circular-generator 'a 'b) 5)", "(a b a b a)"),
+            ("(generator->list (vector->generator #(1 2 3 4) 1 3))", "(2 3)"),
+            ("(generator->list (reverse-vector->generator #(1 2 3)))", "(3 2 1)"),
+            ("(generator->string (string->generator \"abc\"))", "\"abc\""),
+            ("(generator->reverse-list (list->generator '(1 2 3)))", "(3 2 1)"),
+            ("(generator->vector (gcons* 1 2 (generator 3)))", "#(1 2 3)"),
+            (
+                "(generator->list (gappend (generator 1) (generator) (generator 2 3)))",
+                "(1 2 3)",
+            ),
+            (
+                "(generator->list (gmap + (generator 1 2 3) (make-iota-generator 10 10)))",
+                "(11 13 15)",
+            ),
+            (
+                "(generator->list (gfilter odd (make-iota-generator 10)))",
+                "(1 3 5 7 9)",
+            ),
+            ("(generator->list (gremove odd (make-iota-generator 5)))", "(0 2 4)"),
+            ("(generator->list (gtake (generator 1 2) 4 'x))", "(1 2 x x)"),
+            ("(generator->list (gdrop (make-iota-generator 5) 3))", "(3 4)"),
+            (
+                "(generator->list (gtake-while (lambda (n) (< n 3)) (make-iota-generator 10)))",
+                "(0 1 2)",
+            ),
+            (
+                "(generator->list (gdrop-while (lambda (n) (< n 3)) (generator 1 5 2)))",
+                "(5 2)",
+            ),
+            (
+                "(generator->list (make-unfold-generator (lambda (s) (> s 8)) (lambda (s) (* s s)) (lambda (s) (* s 2)) 1))",
+                "(1 4 16 64)",
+            ),
+            ("(generator-fold + 0 (make-iota-generator 101))", "5050"),
+            ("(gfold cons '() (generator 1 2 3))", "(3 2 1)"),
+            ("(generator-find even (generator 1 3 4 5))", "4"),
+            ("(generator-count odd (make-iota-generator 7))", "3"),
+            ("(generator-any (lambda (n) (> n 2)) (generator 1 2 3))", "#t"),
+            ("(generator-every odd (generator 1 2 3))", "#f"),
+            // a generator keeps returning the end of file object once it is exhausted
+            (
+                "(define g (make-generator 1)) (g) (list (eof-object? (g)) (eof-object? (g)))",
+                "(#t #t)",
+            ),
+            (
+                "(define port (open-output-string))
+                 (generator-for-each (lambda (c) (write-char c port)) (string->generator \"hey\"))
+                 (get-output-string port)",
+                "\"hey\"",
+            ),
+        ],
+    )?;
+    Ok(())
+}
+
+#[test]
+fn accumulators() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(define a (list-accumulator)) (a 1) (a 2) (a (eof-object))",
+                "(1 2)",
+            ),
+            (
+                "(define a (reverse-list-accumulator)) (a 1) (a 2) (a (eof-object))",
+                "(2 1)",
+            ),
+            (
+                "(define a (vector-accumulator)) (a 'x) (a (eof-object))",
+                "#(x)",
+            ),
+            (
+                "(define a (string-accumulator)) (a #\\o) (a #\\k) (a (eof-object))",
+                "\"ok\"",
+            ),
+            (
+                "(define a (sum-accumulator)) (a 1) (a 2.5) (a (eof-object))",
+                "3.5",
+            ),
+            (
+                "(define a (product-accumulator)) (a 2) (a 3) (a (eof-object))",
+                "6",
+            ),
+            (
+                "(define a (count-accumulator)) (a 'x) (a 'y) (a (eof-object))",
+                "2",
+            ),
+            (
+                "(define a (make-accumulator + 10 (lambda (n) (* n 2)))) (a 1) (a 2) (a (eof-object))",
+                "26",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("((string-accumulator) 1)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "string-accumulator: expect a character, got 1".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,864 @@
+use crate::interpreter::scheme::base::{expect_integer, expect_string};
+use crate::interpreter::scheme::time::jiffy_epoch;
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const NANOSECONDS_PER_SECOND: i64 = 1_000_000_000;
+const SECONDS_PER_DAY: i64 = 86400;
+
+const WEEK_DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeType {
+    Utc,
+    Monotonic,
+    Duration,
+}
+
+impl TimeType {
+    fn name(self) -> &'static str {
+        match self {
+            TimeType::Utc => "time-utc",
+            TimeType::Monotonic => "time-monotonic",
+            TimeType::Duration => "time-duration",
+        }
+    }
+}
+
+// the nanosecond is kept in [0, 10^9), so a negative time has a negative second and a positive
+// nanosecond
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Time {
+    pub kind: TimeType,
+    pub second: i64,
+    pub nanosecond: i64,
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "<time ({} {} {})>",
+            self.kind.name(),
+            self.second,
+            self.nanosecond
+        )
+    }
+}
+
+impl Time {
+    pub fn new(kind: TimeType, second: i64, nanosecond: i64) -> Self {
+        Self {
+            kind,
+            second: second + nanosecond.div_euclid(NANOSECONDS_PER_SECOND),
+            nanosecond: nanosecond.rem_euclid(NANOSECONDS_PER_SECOND),
+        }
+    }
+
+    fn nanoseconds(&self) -> i128 {
+        self.second as i128 * NANOSECONDS_PER_SECOND as i128 + self.nanosecond as i128
+    }
+
+    pub(crate) fn from_nanoseconds(kind: TimeType, nanoseconds: i128) -> Self {
+        let per_second = NANOSECONDS_PER_SECOND as i128;
+        Self {
+            kind,
+            second: nanoseconds.div_euclid(per_second) as i64,
+            nanosecond: nanoseconds.rem_euclid(per_second) as i64,
+        }
+    }
+}
+
+// days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// a calendar date in the time zone at zone_offset seconds east of UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Date {
+    pub nanosecond: i64,
+    pub second: i64,
+    pub minute: i64,
+    pub hour: i64,
+    pub day: i64,
+    pub month: i64,
+    pub year: i64,
+    pub zone_offset: i64,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match format_date(self, "~4") {
+            Ok(text) => write!(f, "<date ({})>", text),
+            Err(_) => Err(fmt::Error),
+        }
+    }
+}
+
+impl Date {
+    pub(crate) fn from_time(time: &Time, zone_offset: i64) -> Self {
+        let local = time.second + zone_offset;
+        let (year, month, day) = civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+        let seconds = local.rem_euclid(SECONDS_PER_DAY);
+        Self {
+            nanosecond: time.nanosecond,
+            second: seconds % 60,
+            minute: seconds / 60 % 60,
+            hour: seconds / 3600,
+            day,
+            month,
+            year,
+            zone_offset,
+        }
+    }
+
+    fn to_time(self) -> Time {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let seconds = days * SECONDS_PER_DAY + self.hour * 3600 + self.minute * 60 + self.second;
+        Time::new(TimeType::Utc, seconds - self.zone_offset, self.nanosecond)
+    }
+
+    // 0 is Sunday
+    fn week_day(&self) -> i64 {
+        (days_from_civil(self.year, self.month, self.day) + 4).rem_euclid(7)
+    }
+
+    // 1 is the first of January
+    fn year_day(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1) + 1
+    }
+
+    fn validate(self, name: &str) -> Result<Self> {
+        if !(1..=12).contains(&self.month)
+            || !(1..=days_in_month(self.year, self.month)).contains(&self.day)
+        {
+            logic_error!(
+                "{}: {}-{:02}-{:02} is not a valid date",
+                name,
+                self.year,
+                self.month,
+                self.day
+            )
+        }
+        // a second of 60 is a leap second
+        if !(0..24).contains(&self.hour)
+            || !(0..60).contains(&self.minute)
+            || !(0..=60).contains(&self.second)
+            || !(0..NANOSECONDS_PER_SECOND).contains(&self.nanosecond)
+        {
+            logic_error!(
+                "{}: {:02}:{:02}:{:02}.{:09} is not a valid time of day",
+                name,
+                self.hour,
+                self.minute,
+                self.second,
+                self.nanosecond
+            )
+        }
+        Ok(self)
+    }
+}
+
+// an RFC 822 zone like -0500
+fn format_zone(zone_offset: i64) -> String {
+    let sign = if zone_offset < 0 { '-' } else { '+' };
+    let minutes = zone_offset.abs() / 60;
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+pub(crate) fn format_date(date: &Date, template: &str) -> Result<String> {
+    let mut text = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            text.push(c);
+            continue;
+        }
+        let twelve_hour = (date.hour + 11) % 12 + 1;
+        match chars.next() {
+            Some('~') => text.push('~'),
+            Some('a') => text.push_str(&WEEK_DAYS[date.week_day() as usize][..3]),
+            Some('A') => text.push_str(WEEK_DAYS[date.week_day() as usize]),
+            Some('b') | Some('h') => text.push_str(&MONTHS[date.month as usize - 1][..3]),
+            Some('B') => text.push_str(MONTHS[date.month as usize - 1]),
+            Some('c') => text.push_str(&format_date(date, "~a ~b ~d ~H:~M:~S~z ~Y")?),
+            Some('d') => text.push_str(&format!("{:02}", date.day)),
+            Some('D') => text.push_str(&format_date(date, "~m/~d/~y")?),
+            Some('e') => text.push_str(&format!("{:>2}", date.day)),
+            Some('f') => {
+                text.push_str(&format!("{:02}", date.second));
+                if date.nanosecond > 0 {
+                    let fraction = format!("{:09}", date.nanosecond);
+                    text.push('.');
+                    text.push_str(fraction.trim_end_matches('0'));
+                }
+            }
+            Some('H') => text.push_str(&format!("{:02}", date.hour)),
+            Some('I') => text.push_str(&format!("{:02}", twelve_hour)),
+            Some('j') => text.push_str(&format!("{:03}", date.year_day())),
+            Some('k') => text.push_str(&format!("{:>2}", date.hour)),
+            Some('l') => text.push_str(&format!("{:>2}", twelve_hour)),
+            Some('m') => text.push_str(&format!("{:02}", date.month)),
+            Some('M') => text.push_str(&format!("{:02}", date.minute)),
+            Some('n') => text.push('\n'),
+            Some('N') => text.push_str(&format!("{:09}", date.nanosecond)),
+            Some('p') => text.push_str(if date.hour < 12 { "AM" } else { "PM" }),
+            Some('r') => text.push_str(&format_date(date, "~I:~M:~S ~p")?),
+            Some('s') => text.push_str(&date.to_time().second.to_string()),
+            Some('S') => text.push_str(&format!("{:02}", date.second)),
+            Some('t') => text.push('\t'),
+            Some('T') => text.push_str(&format_date(date, "~H:~M:~S")?),
+            Some('w') => text.push_str(&date.week_day().to_string()),
+            Some('y') => text.push_str(&format!("{:02}", date.year.rem_euclid(100))),
+            Some('Y') => text.push_str(&date.year.to_string()),
+            Some('z') => text.push_str(&format_zone(date.zone_offset)),
+            Some('1') => text.push_str(&format_date(date, "~Y-~m-~d")?),
+            Some('2') => text.push_str(&format_date(date, "~H:~M:~S~z")?),
+            Some('3') => text.push_str(&format_date(date, "~H:~M:~S")?),
+            Some('4') => text.push_str(&format_date(date, "~Y-~m-~dT~H:~M:~S~z")?),
+            Some('5') => text.push_str(&format_date(date, "~Y-~m-~dT~H:~M:~S")?),
+            Some(other) => logic_error!("date->string: unknown directive ~{}", other),
+            None => logic_error!(
+                "date->string: incomplete directive at the end of {}",
+                template
+            ),
+        }
+    }
+    Ok(text)
+}
+
+// up to max digits, at least one
+fn parse_digits(input: &mut Peekable<impl Iterator<Item = char>>, max: usize) -> Option<i64> {
+    let mut digits = String::new();
+    while digits.len() < max {
+        match input.peek() {
+            Some(c) if c.is_ascii_digit() => digits.push(*c),
+            _ => break,
+        }
+        input.next();
+    }
+    digits.parse().ok()
+}
+
+fn parse_word(input: &mut Peekable<impl Iterator<Item = char>>) -> String {
+    let mut word = String::new();
+    while let Some(c) = input.peek() {
+        if !c.is_alphabetic() {
+            break;
+        }
+        word.push(*c);
+        input.next();
+    }
+    word
+}
+
+// fields missing from the template are zero, or one for the day and month
+fn parse_date(text: &str, template: &str) -> Option<Date> {
+    let mut date = Date {
+        nanosecond: 0,
+        second: 0,
+        minute: 0,
+        hour: 0,
+        day: 1,
+        month: 1,
+        year: 0,
+        zone_offset: 0,
+    };
+    let mut input = text.chars().peekable();
+    let mut template = template.chars();
+    while let Some(c) = template.next() {
+        if c != '~' {
+            if input.next()? != c {
+                return None;
+            }
+            continue;
+        }
+        match template.next()? {
+            '~' => {
+                if input.next()? != '~' {
+                    return None;
+                }
+            }
+            'd' => date.day = parse_digits(&mut input, 2)?,
+            'e' => {
+                if input.peek() == Some(&' ') {
+                    input.next();
+                }
+                date.day = parse_digits(&mut input, 2)?
+            }
+            'H' | 'k' => date.hour = parse_digits(&mut input, 2)?,
+            'M' => date.minute = parse_digits(&mut input, 2)?,
+            'S' => date.second = parse_digits(&mut input, 2)?,
+            'N' => date.nanosecond = parse_digits(&mut input, 9)?,
+            'm' => date.month = parse_digits(&mut input, 2)?,
+            'y' => {
+                let year = parse_digits(&mut input, 2)?;
+                date.year = if year < 50 { 2000 + year } else { 1900 + year };
+            }
+            'Y' => date.year = parse_digits(&mut input, 9)?,
+            'b' | 'h' | 'B' => {
+                let word = parse_word(&mut input).to_lowercase();
+                let month = MONTHS
+                    .iter()
+                    .position(|month| word.len() >= 3 && month.to_lowercase().starts_with(&word))?;
+                date.month = month as i64 + 1;
+            }
+            'a' | 'A' => {
+                parse_word(&mut input);
+            }
+            'z' => match input.next()? {
+                'Z' => date.zone_offset = 0,
+                sign @ '+' | sign @ '-' => {
+                    let zone = parse_digits(&mut input, 4)?;
+                    let zone_offset = zone / 100 * 3600 + zone % 100 * 60;
+                    date.zone_offset = if sign == '-' {
+                        -zone_offset
+                    } else {
+                        zone_offset
+                    };
+                }
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    match input.next() {
+        Some(_) => None,
+        None => Some(date),
+    }
+}
+
+fn expect_time(name: &str, value: ValueType) -> Result<Time> {
+    match value {
+        ValueType::Time(time) => Ok(time),
+        other => logic_error!("{}: expect a time, got {}", name, other),
+    }
+}
+
+fn expect_date(name: &str, value: ValueType) -> Result<Date> {
+    match value {
+        ValueType::Date(date) => Ok(*date),
+        other => logic_error!("{}: expect a date, got {}", name, other),
+    }
+}
+
+fn expect_time_type(name: &str, value: ValueType) -> Result<TimeType> {
+    match value {
+        ValueType::Symbol(symbol) => match &*symbol {
+            "time-utc" => Ok(TimeType::Utc),
+            "time-monotonic" => Ok(TimeType::Monotonic),
+            "time-duration" => Ok(TimeType::Duration),
+            _ => logic_error!("{}: unknown time type {}", name, symbol),
+        },
+        other => logic_error!("{}: expect a time type, got {}", name, other),
+    }
+}
+
+fn expect_duration(name: &str, value: ValueType) -> Result<Time> {
+    match expect_time(name, value)? {
+        duration if duration.kind == TimeType::Duration => Ok(duration),
+        other => logic_error!("{}: expect a duration, got {}", name, other),
+    }
+}
+
+// the zone offset of the local time zone, which is UTC unless given
+fn zone_offset(name: &str, offset: Option<Result<ValueType>>) -> Result<i64> {
+    match offset {
+        Some(offset) => expect_integer(name, offset?),
+        None => Ok(0),
+    }
+}
+
+pub(crate) fn srfi_19_library() -> HashMap<String, ValueType> {
+    fn current_time(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let kind = match (arguments.next(), arguments.next()) {
+            (None, None) => TimeType::Utc,
+            (Some(kind), None) => expect_time_type("current-time", kind?)?,
+            _ => logic_error!("current-time takes zero or one argument"),
+        };
+        let elapsed = match kind {
+            TimeType::Utc => match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(elapsed) => elapsed.as_nanos() as i128,
+                Err(e) => -(e.duration().as_nanos() as i128),
+            },
+            TimeType::Monotonic => jiffy_epoch().elapsed().as_nanos() as i128,
+            TimeType::Duration => logic_error!("current-time: time-duration is not a clock"),
+        };
+        Ok(ValueType::Time(Time::from_nanoseconds(kind, elapsed)))
+    }
+
+    fn make_time(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(kind), Some(nanosecond), Some(second), None) => {
+                let kind = expect_time_type("make-time", kind?)?;
+                let nanosecond = expect_integer("make-time", nanosecond?)?;
+                let second = expect_integer("make-time", second?)?;
+                Ok(ValueType::Time(Time::new(kind, second, nanosecond)))
+            }
+            _ => logic_error!("make-time takes exactly three arguments"),
+        }
+    }
+
+    fn is_time(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Time(_)))),
+            _ => logic_error!("time? takes exactly one argument"),
+        }
+    }
+
+    macro_rules! time_accessor {
+        ($name:tt, $ident:tt, $field:expr) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(time), None) => Ok($field(expect_time($ident, time?)?)),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    time_accessor!(time_type, "time-type", |time: Time| ValueType::Symbol(
+        intern(time.kind.name())
+    ));
+    time_accessor!(time_second, "time-second", |time: Time| ValueType::Number(
+        Number::Integer(time.second)
+    ));
+    time_accessor!(time_nanosecond, "time-nanosecond", |time: Time| {
+        ValueType::Number(Number::Integer(time.nanosecond))
+    });
+
+    fn time_difference(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(a), Some(b), None) => {
+                let a = expect_time("time-difference", a?)?;
+                let b = expect_time("time-difference", b?)?;
+                if a.kind != b.kind {
+                    logic_error!(
+                        "time-difference: expect times of the same type, got {} and {}",
+                        a,
+                        b
+                    )
+                }
+                Ok(ValueType::Time(Time::from_nanoseconds(
+                    TimeType::Duration,
+                    a.nanoseconds() - b.nanoseconds(),
+                )))
+            }
+            _ => logic_error!("time-difference takes exactly two arguments"),
+        }
+    }
+
+    macro_rules! duration_arithmetic {
+        ($name:tt, $ident:tt, $combine:expr) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(time), Some(duration), None) => {
+                        let time = expect_time($ident, time?)?;
+                        let duration = expect_duration($ident, duration?)?;
+                        Ok(ValueType::Time(Time::from_nanoseconds(
+                            time.kind,
+                            $combine(time.nanoseconds(), duration.nanoseconds()),
+                        )))
+                    }
+                    _ => logic_error!("{} takes exactly two arguments", $ident),
+                }
+            }
+        };
+    }
+
+    duration_arithmetic!(add_duration, "add-duration", |a, b| a + b);
+    duration_arithmetic!(subtract_duration, "subtract-duration", |a, b| a - b);
+
+    macro_rules! time_comparison {
+        ($name:tt, $ident:tt, $op:tt) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(a), Some(b), None) => {
+                        let a = expect_time($ident, a?)?;
+                        let b = expect_time($ident, b?)?;
+                        if a.kind != b.kind {
+                            logic_error!(
+                                "{}: expect times of the same type, got {} and {}",
+                                $ident,
+                                a,
+                                b
+                            )
+                        }
+                        Ok(ValueType::Boolean(a.nanoseconds() $op b.nanoseconds()))
+                    }
+                    _ => logic_error!("{} takes exactly two arguments", $ident),
+                }
+            }
+        };
+    }
+
+    time_comparison!(time_equal, "time=?", ==);
+    time_comparison!(time_less, "time<?", <);
+    time_comparison!(time_greater, "time>?", >);
+    time_comparison!(time_less_equal, "time<=?", <=);
+    time_comparison!(time_greater_equal, "time>=?", >=);
+
+    fn current_date(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (offset, None) => {
+                let zone_offset = zone_offset("current-date", offset)?;
+                let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                    Ok(elapsed) => elapsed.as_nanos() as i128,
+                    Err(e) => -(e.duration().as_nanos() as i128),
+                };
+                let now = Time::from_nanoseconds(TimeType::Utc, now);
+                Ok(ValueType::Date(Box::new(Date::from_time(
+                    &now,
+                    zone_offset,
+                ))))
+            }
+            _ => logic_error!("current-date takes zero or one argument"),
+        }
+    }
+
+    fn make_date(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        let fields = arguments
+            .map(|field| expect_integer("make-date", field?))
+            .collect::<Result<Vec<_>>>()?;
+        match fields.as_slice() {
+            [nanosecond, second, minute, hour, day, month, year, zone_offset] => {
+                let date = Date {
+                    nanosecond: *nanosecond,
+                    second: *second,
+                    minute: *minute,
+                    hour: *hour,
+                    day: *day,
+                    month: *month,
+                    year: *year,
+                    zone_offset: *zone_offset,
+                };
+                Ok(ValueType::Date(Box::new(date.validate("make-date")?)))
+            }
+            _ => logic_error!("make-date takes exactly eight arguments"),
+        }
+    }
+
+    fn is_date(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Date(_)))),
+            _ => logic_error!("date? takes exactly one argument"),
+        }
+    }
+
+    macro_rules! date_accessor {
+        ($name:tt, $ident:tt, $field:expr) => {
+            fn $name(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(date), None) => Ok(ValueType::Number(Number::Integer($field(
+                        &expect_date($ident, date?)?,
+                    )))),
+                    _ => logic_error!("{} takes exactly one argument", $ident),
+                }
+            }
+        };
+    }
+
+    date_accessor!(date_nanosecond, "date-nanosecond", |date: &Date| date
+        .nanosecond);
+    date_accessor!(date_second, "date-second", |date: &Date| date.second);
+    date_accessor!(date_minute, "date-minute", |date: &Date| date.minute);
+    date_accessor!(date_hour, "date-hour", |date: &Date| date.hour);
+    date_accessor!(date_day, "date-day", |date: &Date| date.day);
+    date_accessor!(date_month, "date-month", |date: &Date| date.month);
+    date_accessor!(date_year, "date-year", |date: &Date| date.year);
+    date_accessor!(date_zone_offset, "date-zone-offset", |date: &Date| date
+        .zone_offset);
+    date_accessor!(date_year_day, "date-year-day", Date::year_day);
+    date_accessor!(date_week_day, "date-week-day", Date::week_day);
+
+    fn time_utc_to_date(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(time), offset, None) => {
+                let time = expect_time("time-utc->date", time?)?;
+                if time.kind != TimeType::Utc {
+                    logic_error!("time-utc->date: expect a time-utc, got {}", time)
+                }
+                let zone_offset = zone_offset("time-utc->date", offset)?;
+                Ok(ValueType::Date(Box::new(Date::from_time(
+                    &time,
+                    zone_offset,
+                ))))
+            }
+            _ => logic_error!("time-utc->date takes one or two arguments"),
+        }
+    }
+
+    fn date_to_time_utc(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(date), None) => Ok(ValueType::Time(
+                expect_date("date->time-utc", date?)?.to_time(),
+            )),
+            _ => logic_error!("date->time-utc takes exactly one argument"),
+        }
+    }
+
+    fn date_to_string(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(date), template, None) => {
+                let date = expect_date("date->string", date?)?;
+                let template = match template {
+                    Some(template) => expect_string("date->string", template?)?,
+                    None => "~c".to_string(),
+                };
+                Ok(ValueType::String(format_date(&date, &template)?))
+            }
+            _ => logic_error!("date->string takes one or two arguments"),
+        }
+    }
+
+    fn string_to_date(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(text), Some(template), None) => {
+                let text = expect_string("string->date", text?)?;
+                let template = expect_string("string->date", template?)?;
+                match parse_date(&text, &template) {
+                    Some(date) => Ok(ValueType::Date(Box::new(date.validate("string->date")?))),
+                    None => logic_error!(
+                        "string->date: {} does not match the template {}",
+                        ValueType::String(text),
+                        ValueType::String(template)
+                    ),
+                }
+            }
+            _ => logic_error!("string->date takes exactly two arguments"),
+        }
+    }
+
+    let time_types = [TimeType::Utc, TimeType::Monotonic, TimeType::Duration]
+        .iter()
+        .map(|kind| {
+            (
+                kind.name().to_string(),
+                ValueType::Symbol(intern(kind.name())),
+            )
+        })
+        .collect::<Vec<_>>();
+    [
+        function_mapping!("current-time", current_time),
+        function_mapping!("make-time", make_time),
+        function_mapping!("time?", is_time),
+        function_mapping!("time-type", time_type),
+        function_mapping!("time-second", time_second),
+        function_mapping!("time-nanosecond", time_nanosecond),
+        function_mapping!("time-difference", time_difference),
+        function_mapping!("add-duration", add_duration),
+        function_mapping!("subtract-duration", subtract_duration),
+        function_mapping!("time=?", time_equal),
+        function_mapping!("time<?", time_less),
+        function_mapping!("time>?", time_greater),
+        function_mapping!("time<=?", time_less_equal),
+        function_mapping!("time>=?", time_greater_equal),
+        function_mapping!("current-date", current_date),
+        function_mapping!("make-date", make_date),
+        function_mapping!("date?", is_date),
+        function_mapping!("date-nanosecond", date_nanosecond),
+        function_mapping!("date-second", date_second),
+        function_mapping!("date-minute", date_minute),
+        function_mapping!("date-hour", date_hour),
+        function_mapping!("date-day", date_day),
+        function_mapping!("date-month", date_month),
+        function_mapping!("date-year", date_year),
+        function_mapping!("date-zone-offset", date_zone_offset),
+        function_mapping!("date-year-day", date_year_day),
+        function_mapping!("date-week-day", date_week_day),
+        function_mapping!("time-utc->date", time_utc_to_date),
+        function_mapping!("date->time-utc", date_to_time_utc),
+        function_mapping!("date->string", date_to_string),
+        function_mapping!("string->date", string_to_date),
+    ]
+    .iter()
+    .cloned()
+    .chain(time_types)
+    .collect()
+}
+
+#[test]
+fn calendar() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval("(define d (make-date 0 5 4 3 2 1 2024 0))".chars())?;
+    assert_printed(
+        &interpreter,
+        &[
+            ("(date->string d \"~4\")", "\"2024-01-02T03:04:05+0000\""),
+            ("(date->string d)", "\"Tue Jan 02 03:04:05+0000 2024\""),
+            ("(date->string d \"~A ~e ~B ~j ~I~p ~~\")", "\"Tuesday  2 January 002 03AM ~\""),
+            ("(date-week-day d)", "2"),
+            ("(date-year-day (make-date 0 0 0 0 1 3 2024 0))", "61"),
+            ("(time-second (date->time-utc (make-date 0 0 0 0 1 1 1970 0)))", "0"),
+            ("(time-second (date->time-utc (make-date 0 0 0 1 1 1 1970 3600)))", "0"),
+            ("(date-hour (time-utc->date (make-time time-utc 0 0) 3600))", "1"),
+            ("(date->string (time-utc->date (make-time time-utc 0 -1) -5400) \"~5~z\")", "\"1969-12-31T22:29:59-0130\""),
+            ("(date->string (make-date 500 1 0 0 1 1 2000 0) \"~f ~N\")", "\"01.0000005 000000500\""),
+            ("(time-utc->date (make-time time-utc 0 951782400))", "<date (2000-02-29T00:00:00+0000)>"),
+            (
+                "(date->string (string->date \"2024-02-29 13:45:00 -0130\" \"~Y-~m-~d ~H:~M:~S ~z\") \"~4\")",
+                "\"2024-02-29T13:45:00-0130\"",
+            ),
+            ("(date-zone-offset (string->date \"Mar 5 99Z\" \"~b ~d ~y~z\"))", "0"),
+            ("(date-year (string->date \"Mar 5 99Z\" \"~b ~d ~y~z\"))", "1999"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(make-date 0 0 0 0 30 2 2023 0)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "make-date: 2023-02-30 is not a valid date".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(string->date \"12:00\" \"~Y\")".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "string->date: \"12:00\" does not match the template \"~Y\"".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn time_arithmetic() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(make-time time-duration -1 0)",
+                "<time (time-duration -1 999999999)>",
+            ),
+            (
+                "(time-difference (make-time time-utc 0 10) (make-time time-utc 500000000 8))",
+                "<time (time-duration 1 500000000)>",
+            ),
+            (
+                "(add-duration (make-time time-utc 600000000 1) (make-time time-duration 600000000 0))",
+                "<time (time-utc 2 200000000)>",
+            ),
+            (
+                "(subtract-duration (make-time time-utc 0 1) (make-time time-duration 1 0))",
+                "<time (time-utc 0 999999999)>",
+            ),
+            (
+                "(time<? (make-time time-utc 0 1) (make-time time-utc 1 1))",
+                "#t",
+            ),
+            (
+                "(time=? (make-time time-utc 0 1) (make-time time-utc 0 1))",
+                "#t",
+            ),
+            (
+                "(time-type (current-time time-monotonic))",
+                "time-monotonic",
+            ),
+            (
+                "(time>? (current-time) (make-time time-utc 0 1500000000))",
+                "#t",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(time<? (make-time time-utc 0 1) (make-time time-duration 0 1))".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "time<?: expect times of the same type, got <time (time-utc 1 0)> and <time (time-duration 1 0)>".to_string()
+        })
+    );
+    Ok(())
+}
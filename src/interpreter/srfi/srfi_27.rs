@@ -0,0 +1,400 @@
+use crate::interpreter::bigint::BigInt;
+use crate::interpreter::scheme::base::{expect_integer, expect_list};
+use crate::interpreter::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// xoshiro256** seeded through splitmix64, the state is never all zero
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomSource {
+    state: [u64; 4],
+}
+
+fn splitmix64(seed: &mut u64) -> u64 {
+    *seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl RandomSource {
+    pub fn new(mut seed: u64) -> Self {
+        Self {
+            state: [
+                splitmix64(&mut seed),
+                splitmix64(&mut seed),
+                splitmix64(&mut seed),
+                splitmix64(&mut seed),
+            ],
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    // uniform in [0, n), rejecting the values which would bias the remainder
+    fn integer(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - (u64::MAX - n + 1) % n;
+        loop {
+            let x = self.next_u64();
+            if x <= zone {
+                return x % n;
+            }
+        }
+    }
+
+    // uniform in [0, n) for n beyond fixnums, drawing as many random bits as n has and
+    // rejecting those not below it
+    fn big_integer(&mut self, n: &BigInt) -> BigInt {
+        let bits = n.bits() as usize;
+        loop {
+            let mut limbs: Vec<u32> = (0..bits.div_ceil(32))
+                .map(|_| (self.next_u64() >> 32) as u32)
+                .collect();
+            if !bits.is_multiple_of(32) {
+                *limbs.last_mut().unwrap() &= (1 << (bits % 32)) - 1;
+            }
+            let x = BigInt::from_parts(false, limbs);
+            if &x < n {
+                return x;
+            }
+        }
+    }
+
+    fn bounded(&mut self, n: &BigInt) -> Number {
+        match n.to_i64() {
+            Some(n) => Number::Integer(self.integer(n as u64) as i64),
+            None => Number::integer(self.big_integer(n)),
+        }
+    }
+
+    // uniform in (0, 1), never 0 or 1
+    fn real(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+}
+
+thread_local! {
+    static DEFAULT_SOURCE: Rc<RefCell<RandomSource>> = Rc::new(RefCell::new(RandomSource::new(0)));
+}
+
+fn expect_random_source(name: &str, value: ValueType) -> Result<Rc<RefCell<RandomSource>>> {
+    match value {
+        ValueType::RandomSource(source) => Ok(source),
+        other => logic_error!("{}: expect a random source, got {}", name, other),
+    }
+}
+
+// the range of random integers, which may be beyond fixnums
+fn expect_bound(name: &str, value: ValueType) -> Result<BigInt> {
+    match &value {
+        ValueType::Number(n) => match n.to_big() {
+            Some(n) if !n.is_negative() && !n.is_zero() => Ok(n),
+            _ => logic_error!("{}: expect a positive integer, got {}", name, value),
+        },
+        _ => logic_error!("{}: expect a positive integer, got {}", name, value),
+    }
+}
+
+pub(crate) fn srfi_27_library() -> HashMap<String, ValueType> {
+    fn source_integer(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(source), Some(n), None) => {
+                let source = expect_random_source("random-integer", source?)?;
+                let n = expect_bound("random-integer", n?)?;
+                let k = source.borrow_mut().bounded(&n);
+                Ok(ValueType::Number(k))
+            }
+            _ => logic_error!("random-integer takes exactly one argument"),
+        }
+    }
+
+    fn source_real(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(source), None) => {
+                let source = expect_random_source("random-real", source?)?;
+                let x = source.borrow_mut().real();
+                Ok(ValueType::Number(Number::Real(x)))
+            }
+            _ => logic_error!("random-real takes no arguments"),
+        }
+    }
+
+    fn random_integer(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(n), None) => {
+                let n = expect_bound("random-integer", n?)?;
+                Ok(ValueType::Number(
+                    DEFAULT_SOURCE.with(|source| source.borrow_mut().bounded(&n)),
+                ))
+            }
+            _ => logic_error!("random-integer takes exactly one argument"),
+        }
+    }
+
+    fn random_real_procedure(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::Number(Number::Real(
+                DEFAULT_SOURCE.with(|source| source.borrow_mut().real()),
+            ))),
+            _ => logic_error!("random-real takes no arguments"),
+        }
+    }
+
+    // every new source starts in the same state
+    fn make_random_source(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match arguments.count() {
+            0 => Ok(ValueType::RandomSource(Rc::new(RefCell::new(
+                RandomSource::new(0),
+            )))),
+            _ => logic_error!("make-random-source takes no arguments"),
+        }
+    }
+
+    fn is_random_source(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(
+                value?,
+                ValueType::RandomSource(_)
+            ))),
+            _ => logic_error!("random-source? takes exactly one argument"),
+        }
+    }
+
+    // the state is a list of four integers holding the bits of the generator words
+    fn random_source_state_ref(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(source), None) => {
+                let source = expect_random_source("random-source-state-ref", source?)?;
+                let state = source.borrow().state;
+                Ok(ValueType::list(state.iter().map(|word| {
+                    ValueType::Number(Number::Integer(*word as i64))
+                })))
+            }
+            _ => logic_error!("random-source-state-ref takes exactly one argument"),
+        }
+    }
+
+    fn random_source_state_set(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(source), Some(state), None) => {
+                let source = expect_random_source("random-source-state-set!", source?)?;
+                let state = expect_list("random-source-state-set!", state?)?
+                    .into_iter()
+                    .map(|word| Ok(expect_integer("random-source-state-set!", word)? as u64))
+                    .collect::<Result<Vec<_>>>()?;
+                match state.as_slice() {
+                    [a, b, c, d] if state.iter().any(|word| *word != 0) => {
+                        source.borrow_mut().state = [*a, *b, *c, *d];
+                        Ok(ValueType::Void)
+                    }
+                    _ => logic_error!(
+                        "random-source-state-set!: expect a state from random-source-state-ref"
+                    ),
+                }
+            }
+            _ => logic_error!("random-source-state-set! takes exactly two arguments"),
+        }
+    }
+
+    fn random_source_randomize(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(source), None) => {
+                let source = expect_random_source("random-source-randomize!", source?)?;
+                let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                    Ok(elapsed) => elapsed.as_nanos() as u64,
+                    Err(e) => e.duration().as_nanos() as u64,
+                };
+                let seed = now ^ (source.as_ptr() as u64).rotate_left(32);
+                *source.borrow_mut() = RandomSource::new(seed);
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("random-source-randomize! takes exactly one argument"),
+        }
+    }
+
+    // the same i and j always give the same state
+    fn random_source_pseudo_randomize(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(source), Some(i), Some(j), None) => {
+                let name = "random-source-pseudo-randomize!";
+                let source = expect_random_source(name, source?)?;
+                let i = expect_integer(name, i?)? as u64;
+                let j = expect_integer(name, j?)? as u64;
+                let mut seed = i;
+                let seed = splitmix64(&mut seed) ^ j;
+                *source.borrow_mut() = RandomSource::new(seed);
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("random-source-pseudo-randomize! takes exactly three arguments"),
+        }
+    }
+
+    fn random_source_make_integers(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(source), None) => {
+                let source = expect_random_source("random-source-make-integers", source?)?;
+                Ok(native_closure(
+                    interpreter,
+                    vec![ValueType::RandomSource(source)],
+                    BuildinProcedure("random-integer", |arguments, _| source_integer(arguments)),
+                    vec!["n".to_string()],
+                    None,
+                ))
+            }
+            _ => logic_error!("random-source-make-integers takes exactly one argument"),
+        }
+    }
+
+    fn random_source_make_reals(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(source), unit, None) => {
+                let source = expect_random_source("random-source-make-reals", source?)?;
+                // reals are always on the finest grid of a double, which any unit is coarser than
+                if let Some(unit) = unit {
+                    match unit? {
+                        ValueType::Number(unit) if unit.to_f64() > 0.0 && unit.to_f64() < 1.0 => (),
+                        other => logic_error!(
+                            "random-source-make-reals: expect a unit between 0 and 1, got {}",
+                            other
+                        ),
+                    }
+                }
+                Ok(native_closure(
+                    interpreter,
+                    vec![ValueType::RandomSource(source)],
+                    BuildinProcedure("random-real", |arguments, _| source_real(arguments)),
+                    vec![],
+                    None,
+                ))
+            }
+            _ => logic_error!("random-source-make-reals takes one or two arguments"),
+        }
+    }
+
+    [
+        function_mapping!("random-integer", random_integer),
+        function_mapping!("random-real", random_real_procedure),
+        function_mapping!("make-random-source", make_random_source),
+        function_mapping!("random-source?", is_random_source),
+        function_mapping!("random-source-state-ref", random_source_state_ref),
+        function_mapping!("random-source-state-set!", random_source_state_set),
+        function_mapping!("random-source-randomize!", random_source_randomize),
+        function_mapping!(
+            "random-source-pseudo-randomize!",
+            random_source_pseudo_randomize
+        ),
+        interpreter_function_mapping!("random-source-make-integers", random_source_make_integers),
+        interpreter_function_mapping!("random-source-make-reals", random_source_make_reals),
+        (
+            "default-random-source".to_string(),
+            ValueType::RandomSource(DEFAULT_SOURCE.with(Rc::clone)),
+        ),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn random_sources() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let program = "
+        (define s (make-random-source))
+        (random-source-pseudo-randomize! s 1 2)
+        (define integers (random-source-make-integers s))
+        (define state (random-source-state-ref s))
+        (define first (list (integers 1000) (integers 1000) (integers 1000)))
+        (random-source-state-set! s state)
+        (equal? first (list (integers 1000) (integers 1000) (integers 1000)))";
+    assert_eq!(
+        interpreter.eval(program.chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    let program = "
+        (define t (make-random-source))
+        (random-source-pseudo-randomize! t 1 2)
+        (define g (random-source-make-integers t))
+        (equal? first (list (g 1000) (g 1000) (g 1000)))";
+    assert_eq!(
+        interpreter.eval(program.chars())?,
+        Some(ValueType::Boolean(true))
+    );
+    for _ in 0..100 {
+        match interpreter.eval(
+            "(list (random-integer 6) ((random-source-make-reals s)) (random-real))".chars(),
+        )? {
+            Some(value) => match expect_list("random_sources", value)?.as_slice() {
+                [ValueType::Number(Number::Integer(k)), ValueType::Number(Number::Real(x)), ValueType::Number(Number::Real(y))] =>
+                {
+                    assert!((0..6).contains(k));
+                    assert!(*x > 0.0 && *x < 1.0 && *y > 0.0 && *y < 1.0);
+                }
+                other => panic!("unexpected random values {:?}", other),
+            },
+            None => panic!("expect random values"),
+        }
+    }
+    assert_eq!(
+        interpreter.eval("(random-integer 0)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "random-integer: expect a positive integer, got 0".to_string()
+        })
+    );
+    // ranges beyond fixnums
+    let program = "
+        (define r (random-source-make-integers s))
+        (define (below? n k) (if (<= 0 k) (< k n) #f))
+        (list (below? (expt 10 30) (r (expt 10 30))) (below? (expt 2 64) (random-integer (expt 2 64)))
+              (< (expt 10 20) (r (expt 10 30))))";
+    assert_eq!(
+        interpreter.eval(program.chars())?.unwrap().to_string(),
+        "(#t #t #t)"
+    );
+    Ok(())
+}
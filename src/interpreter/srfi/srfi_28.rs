@@ -0,0 +1,135 @@
+use crate::interpreter::scheme::base::expect_string;
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// ~a displays, ~s writes, ~d ~x ~o ~b write numbers in a radix, ~% is a newline and ~~ a tilde
+fn format_string(template: &str, mut arguments: impl Iterator<Item = ValueType>) -> Result<String> {
+    let mut text = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            text.push(c);
+            continue;
+        }
+        let directive = match chars.next() {
+            Some(directive) => directive.to_ascii_lowercase(),
+            None => logic_error!("format: incomplete directive at the end of {}", template),
+        };
+        let radix = match directive {
+            '%' | 'n' => {
+                text.push('\n');
+                continue;
+            }
+            '~' => {
+                text.push('~');
+                continue;
+            }
+            'd' => Some(10),
+            'x' => Some(16),
+            'o' => Some(8),
+            'b' => Some(2),
+            'a' | 's' => None,
+            _ => logic_error!("format: unknown directive ~{}", directive),
+        };
+        let argument = match arguments.next() {
+            Some(argument) => argument,
+            None => logic_error!("format: too few arguments for {}", template),
+        };
+        match (radix, argument) {
+            (Some(radix), ValueType::Number(number)) => {
+                text.push_str(&number.to_string_radix(radix)?)
+            }
+            (Some(_), other) => {
+                logic_error!("format: ~{} expects a number, got {}", directive, other)
+            }
+            (None, argument) if directive == 'a' => {
+                text.push_str(&format!("{}", Displayed(&argument)))
+            }
+            (None, argument) => text.push_str(&format!("{}", argument)),
+        }
+    }
+    if arguments.next().is_some() {
+        logic_error!("format: too many arguments for {}", template)
+    }
+    Ok(text)
+}
+
+pub(crate) fn srfi_28_library() -> HashMap<String, ValueType> {
+    // the destination is #f for a new string, #t for the current output port, or a port, and
+    // a leading template returns a new string like SRFI 28
+    fn format(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+        let destination = match arguments.next() {
+            Some(destination) => destination,
+            None => logic_error!("format takes at least one argument"),
+        };
+        let (port, template) = match destination {
+            ValueType::String(template) => (None, template),
+            ValueType::Boolean(false) => (None, String::new()),
+            ValueType::Boolean(true) => (
+                Some(interpreter.current_output_port.borrow().clone()),
+                String::new(),
+            ),
+            ValueType::Port(port) => (Some(port), String::new()),
+            other => logic_error!("format: expect #f, #t, a port or a string, got {}", other),
+        };
+        let template = match (template.is_empty(), port.is_some()) {
+            (false, _) => template,
+            _ => match arguments.next() {
+                Some(template) => expect_string("format", template)?,
+                None => logic_error!("format: expect a format string after the destination"),
+            },
+        };
+        let text = format_string(&template, arguments)?;
+        match port {
+            Some(port) => {
+                port.write_str(&text)?;
+                Ok(ValueType::Void)
+            }
+            None => Ok(ValueType::String(text)),
+        }
+    }
+
+    [interpreter_function_mapping!("format", format)]
+        .iter()
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn formatting() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            (
+                "(format #f \"~a and ~s~%\" \"x\" \"y\")",
+                "\"x and \\\"y\\\"\\n\"",
+            ),
+            (
+                "(format \"~d ~x ~o ~b ~~\" 10 255 8 5)",
+                "\"10 ff 10 101 ~\"",
+            ),
+            ("(format \"~a\" '(1 #\\a \"b\"))", "\"(1 a b)\""),
+            (
+                "(define out (open-output-string)) (format out \"~s\" #\\a) (get-output-string out)",
+                "\"#\\\\a\"",
+            ),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(format #f \"~a ~a\" 1)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "format: too few arguments for ~a ~a".to_string()
+        })
+    );
+    assert_eq!(
+        interpreter.eval("(format #f \"~d\" 'x)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "format: ~d expects a number, got x".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,633 @@
+use crate::interpreter::scheme::base::{expect_index, expect_list, expect_number};
+use crate::interpreter::scheme::lazy::{force, Promise};
+use crate::interpreter::*;
+use std::collections::HashMap;
+
+// a stream is a promise of either the empty list or a pair of the promise of its first element
+// and the stream of the rest, the procedures building streams defer their work to step procedures
+// which the promises apply to their state
+
+fn expect_stream(name: &str, value: ValueType) -> Result<Rc<RefCell<Promise>>> {
+    match value {
+        ValueType::Promise(promise) => Ok(promise),
+        other => logic_error!("{}: expect a stream, got {}", name, other),
+    }
+}
+
+// None for the empty stream, otherwise the promise of the car and the stream of the cdr
+fn force_stream(
+    name: &str,
+    interpreter: &Interpreter,
+    stream: &ValueType,
+) -> Result<Option<(ValueType, ValueType)>> {
+    match force(interpreter, &expect_stream(name, stream.clone())?)? {
+        ValueType::EmptyList => Ok(None),
+        ValueType::Pair(pair) => {
+            let pair = pair.borrow();
+            Ok(Some((pair.car.clone(), pair.cdr.clone())))
+        }
+        other => logic_error!("{}: expect a stream, got a promise of {}", name, other),
+    }
+}
+
+fn force_element(interpreter: &Interpreter, promise: &ValueType) -> Result<ValueType> {
+    match promise {
+        ValueType::Promise(promise) => force(interpreter, promise),
+        other => Ok(other.clone()),
+    }
+}
+
+fn stream_null() -> ValueType {
+    Promise::value(ValueType::EmptyList)
+}
+
+fn stream_pair(car: ValueType, cdr: ValueType) -> ValueType {
+    ValueType::cons(car, cdr)
+}
+
+// a stream whose pair is computed by the step procedure when forced
+macro_rules! lazy_stream {
+    ($name:literal, $step:ident, $($argument:expr),*) => {
+        Promise::delayed(
+            interpreter_function_mapping!($name, $step).1,
+            vec![$($argument),*],
+        )
+    };
+}
+
+fn arguments_vector(arguments: Arguments) -> Result<Vec<ValueType>> {
+    arguments.collect()
+}
+
+fn list_to_stream(elements: Vec<ValueType>) -> ValueType {
+    elements
+        .into_iter()
+        .rev()
+        .fold(stream_null(), |stream, element| {
+            Promise::value(stream_pair(Promise::value(element), stream))
+        })
+}
+
+// the step procedures, applied to the state kept by their promises
+
+fn append_step(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let mut streams = arguments_vector(arguments)?;
+    while !streams.is_empty() {
+        if let Some((car, cdr)) = force_stream("stream-append", interpreter, &streams[0])? {
+            streams[0] = cdr;
+            let rest = ValueType::list(streams.into_iter());
+            return Ok(stream_pair(
+                car,
+                lazy_stream!("stream-append", append_list_step, rest),
+            ));
+        }
+        streams.remove(0);
+    }
+    Ok(ValueType::EmptyList)
+}
+
+fn append_list_step(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let streams = expect_list("stream-append", arguments.next().unwrap()?)?;
+    append_step(Box::new(streams.into_iter().map(Ok)), interpreter)
+}
+
+fn map_step(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let mut arguments = arguments_vector(arguments)?.into_iter();
+    let procedure = arguments.next().unwrap();
+    let mut cars = vec![procedure.clone()];
+    let mut cdrs = vec![procedure];
+    for stream in arguments {
+        match force_stream("stream-map", interpreter, &stream)? {
+            Some((car, cdr)) => {
+                cars.push(car);
+                cdrs.push(cdr);
+            }
+            None => return Ok(ValueType::EmptyList),
+        }
+    }
+    Ok(stream_pair(
+        Promise::delayed(
+            interpreter_function_mapping!("stream-map", apply_step).1,
+            cars,
+        ),
+        Promise::delayed(
+            interpreter_function_mapping!("stream-map", map_step).1,
+            cdrs,
+        ),
+    ))
+}
+
+// forces the promised elements and applies the procedure to them
+fn apply_step(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let mut arguments = arguments_vector(arguments)?.into_iter();
+    let procedure = arguments.next().unwrap();
+    let elements = arguments
+        .map(|promise| force_element(interpreter, &promise))
+        .collect::<Result<Vec<_>>>()?;
+    interpreter.call_procedure(&procedure, elements)
+}
+
+// elements are skipped in a loop, so long runs of rejected elements do not nest
+fn filter_step(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let predicate = arguments.next().unwrap()?;
+    let mut stream = arguments.next().unwrap()?;
+    while let Some((car, cdr)) = force_stream("stream-filter", interpreter, &stream)? {
+        let element = force_element(interpreter, &car)?;
+        if interpreter
+            .call_procedure(&predicate, vec![element])?
+            .is_true()
+        {
+            return Ok(stream_pair(
+                car,
+                lazy_stream!("stream-filter", filter_step, predicate, cdr),
+            ));
+        }
+        stream = cdr;
+    }
+    Ok(ValueType::EmptyList)
+}
+
+fn take_step(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let count = expect_index("stream-take", arguments.next().unwrap()?)?;
+    let stream = arguments.next().unwrap()?;
+    if count == 0 {
+        return Ok(ValueType::EmptyList);
+    }
+    Ok(match force_stream("stream-take", interpreter, &stream)? {
+        Some((car, cdr)) => stream_pair(
+            car,
+            lazy_stream!(
+                "stream-take",
+                take_step,
+                ValueType::Number(Number::Integer(count as i64 - 1)),
+                cdr
+            ),
+        ),
+        None => ValueType::EmptyList,
+    })
+}
+
+fn take_while_step(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let predicate = arguments.next().unwrap()?;
+    let stream = arguments.next().unwrap()?;
+    Ok(
+        match force_stream("stream-take-while", interpreter, &stream)? {
+            Some((car, cdr)) => {
+                let element = force_element(interpreter, &car)?;
+                if interpreter
+                    .call_procedure(&predicate, vec![element])?
+                    .is_true()
+                {
+                    stream_pair(
+                        car,
+                        lazy_stream!("stream-take-while", take_while_step, predicate, cdr),
+                    )
+                } else {
+                    ValueType::EmptyList
+                }
+            }
+            None => ValueType::EmptyList,
+        },
+    )
+}
+
+fn drop_while_step(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let predicate = arguments.next().unwrap()?;
+    let mut stream = arguments.next().unwrap()?;
+    while let Some((car, cdr)) = force_stream("stream-drop-while", interpreter, &stream)? {
+        let element = force_element(interpreter, &car)?;
+        if !interpreter
+            .call_procedure(&predicate, vec![element])?
+            .is_true()
+        {
+            return Ok(stream_pair(car, cdr));
+        }
+        stream = cdr;
+    }
+    Ok(ValueType::EmptyList)
+}
+
+fn drop_step(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let mut count = expect_index("stream-drop", arguments.next().unwrap()?)?;
+    let mut stream = arguments.next().unwrap()?;
+    while let Some((car, cdr)) = force_stream("stream-drop", interpreter, &stream)? {
+        if count == 0 {
+            return Ok(stream_pair(car, cdr));
+        }
+        count -= 1;
+        stream = cdr;
+    }
+    Ok(ValueType::EmptyList)
+}
+
+// the stream of first, (f first), (f (f first)) and so on
+fn iterate_step(mut arguments: Arguments, _: &Interpreter) -> Result<ValueType> {
+    let procedure = arguments.next().unwrap()?;
+    let element = arguments.next().unwrap()?;
+    let next = Promise::delayed(procedure.clone(), vec![element.clone()]);
+    Ok(stream_pair(
+        Promise::value(element),
+        lazy_stream!("stream-iterate", iterate_forced_step, procedure, next),
+    ))
+}
+
+fn iterate_forced_step(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let procedure = arguments.next().unwrap()?;
+    let element = force_element(interpreter, &arguments.next().unwrap()?)?;
+    iterate_step(
+        Box::new(vec![Ok(procedure), Ok(element)].into_iter()),
+        interpreter,
+    )
+}
+
+// from, from + step, ... stopping before past when it is given
+fn range_step(mut arguments: Arguments, _: &Interpreter) -> Result<ValueType> {
+    let first = expect_number(arguments.next().unwrap()?)?;
+    let step = expect_number(arguments.next().unwrap()?)?;
+    let past = arguments.next().unwrap()?;
+    if let ValueType::Number(past) = &past {
+        let ended = match step < Number::Integer(0) {
+            true => first <= *past,
+            false => first >= *past,
+        };
+        if ended {
+            return Ok(ValueType::EmptyList);
+        }
+    }
+    let next = (first.clone() + step.clone())?;
+    Ok(stream_pair(
+        Promise::value(ValueType::Number(first)),
+        lazy_stream!(
+            "stream-range",
+            range_step,
+            ValueType::Number(next),
+            ValueType::Number(step),
+            past
+        ),
+    ))
+}
+
+fn zip_step(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+    let streams = arguments_vector(arguments)?;
+    let mut cars = vec![];
+    let mut cdrs = vec![];
+    for stream in streams {
+        match force_stream("stream-zip", interpreter, &stream)? {
+            Some((car, cdr)) => {
+                cars.push(force_element(interpreter, &car)?);
+                cdrs.push(cdr);
+            }
+            None => return Ok(ValueType::EmptyList),
+        }
+    }
+    Ok(stream_pair(
+        Promise::value(ValueType::list(cars.into_iter())),
+        Promise::delayed(
+            interpreter_function_mapping!("stream-zip", zip_step).1,
+            cdrs,
+        ),
+    ))
+}
+
+pub(crate) fn srfi_41_library() -> HashMap<String, ValueType> {
+    fn is_stream(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(value?, ValueType::Promise(_)))),
+            _ => logic_error!("stream? takes exactly one argument"),
+        }
+    }
+
+    fn is_stream_null(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(stream), None) => Ok(ValueType::Boolean(
+                force_stream("stream-null?", interpreter, &stream?)?.is_none(),
+            )),
+            _ => logic_error!("stream-null? takes exactly one argument"),
+        }
+    }
+
+    fn is_stream_pair(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(match value? {
+                stream @ ValueType::Promise(_) => {
+                    force_stream("stream-pair?", interpreter, &stream)?.is_some()
+                }
+                _ => false,
+            })),
+            _ => logic_error!("stream-pair? takes exactly one argument"),
+        }
+    }
+
+    fn stream_car(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(stream), None) => match force_stream("stream-car", interpreter, &stream?)? {
+                Some((car, _)) => force_element(interpreter, &car),
+                None => logic_error!("stream-car: expect a non-empty stream"),
+            },
+            _ => logic_error!("stream-car takes exactly one argument"),
+        }
+    }
+
+    fn stream_cdr(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(stream), None) => match force_stream("stream-cdr", interpreter, &stream?)? {
+                Some((_, cdr)) => Ok(cdr),
+                None => logic_error!("stream-cdr: expect a non-empty stream"),
+            },
+            _ => logic_error!("stream-cdr takes exactly one argument"),
+        }
+    }
+
+    fn stream(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+        Ok(list_to_stream(arguments_vector(arguments)?))
+    }
+
+    fn list_to_stream_procedure(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(list), None) => Ok(list_to_stream(expect_list("list->stream", list?)?)),
+            _ => logic_error!("list->stream takes exactly one argument"),
+        }
+    }
+
+    // (stream->list [count] stream), all of an infinite stream never ends
+    fn stream_to_list(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let (count, mut stream) = match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(stream), None, None) => (None, stream?),
+            (Some(count), Some(stream), None) => {
+                (Some(expect_index("stream->list", count?)?), stream?)
+            }
+            _ => logic_error!("stream->list takes one or two arguments"),
+        };
+        let mut elements = vec![];
+        while count.is_none_or(|count| elements.len() < count) {
+            match force_stream("stream->list", interpreter, &stream)? {
+                Some((car, cdr)) => {
+                    elements.push(force_element(interpreter, &car)?);
+                    stream = cdr;
+                }
+                None => break,
+            }
+        }
+        Ok(ValueType::list(elements.into_iter()))
+    }
+
+    fn stream_append(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let streams = arguments_vector(arguments)?;
+        for stream in &streams {
+            expect_stream("stream-append", stream.clone())?;
+        }
+        Ok(Promise::delayed(
+            interpreter_function_mapping!("stream-append", append_step).1,
+            streams,
+        ))
+    }
+
+    fn stream_map(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        let arguments = arguments_vector(arguments)?;
+        if arguments.len() < 2 {
+            logic_error!("stream-map takes a procedure and at least one stream")
+        }
+        Ok(Promise::delayed(
+            interpreter_function_mapping!("stream-map", map_step).1,
+            arguments,
+        ))
+    }
+
+    macro_rules! two_argument_stream {
+        ($function:ident, $name:literal, $step:ident) => {
+            fn $function(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next(), arguments.next()) {
+                    (Some(a), Some(b), None) => Ok(lazy_stream!($name, $step, a?, b?)),
+                    _ => logic_error!("{} takes exactly two arguments", $name),
+                }
+            }
+        };
+    }
+
+    two_argument_stream!(stream_filter, "stream-filter", filter_step);
+    two_argument_stream!(stream_take, "stream-take", take_step);
+    two_argument_stream!(stream_take_while, "stream-take-while", take_while_step);
+    two_argument_stream!(stream_drop, "stream-drop", drop_step);
+    two_argument_stream!(stream_drop_while, "stream-drop-while", drop_while_step);
+    two_argument_stream!(stream_iterate, "stream-iterate", iterate_step);
+
+    fn stream_zip(
+        arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        Ok(Promise::delayed(
+            interpreter_function_mapping!("stream-zip", zip_step).1,
+            arguments_vector(arguments)?,
+        ))
+    }
+
+    fn stream_from(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(first), step, None) => {
+                let step = step.unwrap_or(Ok(ValueType::Number(Number::Integer(1))))?;
+                Ok(lazy_stream!(
+                    "stream-range",
+                    range_step,
+                    first?,
+                    step,
+                    ValueType::Boolean(false)
+                ))
+            }
+            _ => logic_error!("stream-from takes one or two arguments"),
+        }
+    }
+
+    fn stream_range(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(first), Some(past), step, None) => {
+                let first = first?;
+                let past = past?;
+                let ascending = expect_number(first.clone())? < expect_number(past.clone())?;
+                let step =
+                    step.unwrap_or(Ok(ValueType::Number(Number::Integer(if ascending {
+                        1
+                    } else {
+                        -1
+                    }))))?;
+                Ok(lazy_stream!("stream-range", range_step, first, step, past))
+            }
+            _ => logic_error!("stream-range takes two or three arguments"),
+        }
+    }
+
+    fn stream_ref(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(stream), Some(index), None) => {
+                let mut stream = stream?;
+                let mut index = expect_index("stream-ref", index?)?;
+                while let Some((car, cdr)) = force_stream("stream-ref", interpreter, &stream)? {
+                    if index == 0 {
+                        return force_element(interpreter, &car);
+                    }
+                    index -= 1;
+                    stream = cdr;
+                }
+                logic_error!("stream-ref: index out of range")
+            }
+            _ => logic_error!("stream-ref takes exactly two arguments"),
+        }
+    }
+
+    fn stream_length(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(stream), None) => {
+                let mut stream = stream?;
+                let mut length = 0;
+                while let Some((_, cdr)) = force_stream("stream-length", interpreter, &stream)? {
+                    length += 1;
+                    stream = cdr;
+                }
+                Ok(ValueType::Number(Number::Integer(length)))
+            }
+            _ => logic_error!("stream-length takes exactly one argument"),
+        }
+    }
+
+    // (stream-fold kons base stream) calls (kons base element) from the front
+    fn stream_fold(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(kons), Some(base), Some(stream), None) => {
+                let kons = kons?;
+                let mut accumulator = base?;
+                let mut stream = stream?;
+                while let Some((car, cdr)) = force_stream("stream-fold", interpreter, &stream)? {
+                    let element = force_element(interpreter, &car)?;
+                    accumulator = interpreter.call_procedure(&kons, vec![accumulator, element])?;
+                    stream = cdr;
+                }
+                Ok(accumulator)
+            }
+            _ => logic_error!("stream-fold takes exactly three arguments"),
+        }
+    }
+
+    fn stream_for_each(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(procedure), Some(stream), None) => {
+                let procedure = procedure?;
+                let mut stream = stream?;
+                while let Some((car, cdr)) = force_stream("stream-for-each", interpreter, &stream)?
+                {
+                    let element = force_element(interpreter, &car)?;
+                    interpreter.call_procedure(&procedure, vec![element])?;
+                    stream = cdr;
+                }
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("stream-for-each takes exactly two arguments"),
+        }
+    }
+
+    let mut library: HashMap<String, ValueType> = [
+        function_mapping!("stream?", is_stream),
+        interpreter_function_mapping!("stream-null?", is_stream_null),
+        interpreter_function_mapping!("stream-pair?", is_stream_pair),
+        interpreter_function_mapping!("stream-car", stream_car),
+        interpreter_function_mapping!("stream-cdr", stream_cdr),
+        function_mapping!("stream", stream),
+        function_mapping!("list->stream", list_to_stream_procedure),
+        interpreter_function_mapping!("stream->list", stream_to_list),
+        function_mapping!("stream-append", stream_append),
+        function_mapping!("stream-map", stream_map),
+        function_mapping!("stream-filter", stream_filter),
+        function_mapping!("stream-take", stream_take),
+        function_mapping!("stream-take-while", stream_take_while),
+        function_mapping!("stream-drop", stream_drop),
+        function_mapping!("stream-drop-while", stream_drop_while),
+        function_mapping!("stream-iterate", stream_iterate),
+        function_mapping!("stream-zip", stream_zip),
+        function_mapping!("stream-from", stream_from),
+        function_mapping!("stream-range", stream_range),
+        interpreter_function_mapping!("stream-ref", stream_ref),
+        interpreter_function_mapping!("stream-length", stream_length),
+        interpreter_function_mapping!("stream-fold", stream_fold),
+        interpreter_function_mapping!("stream-for-each", stream_for_each),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+    library.insert("stream-null".to_string(), stream_null());
+    library
+}
+
+#[test]
+fn streams() -> Result<()> {
+    let interpreter = Interpreter::new();
+    assert_printed(
+        &interpreter,
+        &[
+            ("(stream->list (stream 1 2 3))", "(1 2 3)"),
+            ("(stream-null? stream-null)", "#t"),
+            ("(stream-pair? (stream-cons 1 stream-null))", "#t"),
+            ("(stream-car (stream-cdr (list->stream '(a b))))", "b"),
+            // the car of a stream-cons is not evaluated until it is needed
+            (
+                "(stream-car (stream-cdr (stream-cons (car '()) (stream-cons 2 stream-null))))",
+                "2",
+            ),
+            (
+                "(define-stream (naturals n) (stream-cons n (naturals (+ n 1)))) (stream->list 5 (naturals 0))",
+                "(0 1 2 3 4)",
+            ),
+            (
+                "(stream->list (stream-take 3 (stream-filter (lambda (n) (= 0 (remainder n 3))) (naturals 1))))",
+                "(3 6 9)",
+            ),
+            (
+                "(stream->list 3 (stream-map + (stream-from 0) (stream-from 10 10)))",
+                "(10 21 32)",
+            ),
+            (
+                "(stream->list (stream-append (stream 1 2) stream-null (stream 3)))",
+                "(1 2 3)",
+            ),
+            ("(stream->list (stream-range 5 0 -2))", "(5 3 1)"),
+            ("(stream->list (stream-range 0 3))", "(0 1 2)"),
+            ("(stream-ref (stream-iterate (lambda (x) (* x 2)) 1) 10)", "1024"),
+            (
+                "(stream->list (stream-take-while (lambda (n) (< n 3)) (stream-from 0)))",
+                "(0 1 2)",
+            ),
+            ("(stream-car (stream-drop-while (lambda (n) (< n 3)) (stream-from 0)))", "3"),
+            ("(stream->list (stream-drop 2 (stream 1 2 3)))", "(3)"),
+            ("(stream-length (stream-range 0 10))", "10"),
+            ("(stream-fold + 0 (stream-range 0 101))", "5050"),
+            ("(stream->list 2 (stream-zip (stream-from 0) (stream 'a 'b 'c)))", "((0 a) (1 b))"),
+            // filtering skips long runs of elements iteratively
+            ("(stream-car (stream-filter (lambda (n) (> n 100000)) (stream-from 0)))", "100001"),
+            (
+                "(define s (stream-lambda (n) (stream n))) (stream->list (s 4))",
+                "(4)",
+            ),
+        ],
+    )?;
+    Ok(())
+}
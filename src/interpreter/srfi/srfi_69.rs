@@ -0,0 +1,852 @@
+use crate::interpreter::scheme::base::{expect_integer, expect_list, expect_pair, expect_string};
+use crate::interpreter::scheme::char::foldcase;
+use crate::interpreter::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// how deep equal? hashing looks into pairs and vectors, so circular structures terminate
+const HASH_DEPTH: usize = 4;
+
+// entries are bucketed by hash code, and compared with the equivalence procedure within a bucket
+#[derive(Debug, Clone)]
+pub struct HashTable {
+    equivalence: ValueType,
+    hash: ValueType,
+    buckets: HashMap<u64, Vec<(ValueType, ValueType)>>,
+    size: usize,
+}
+
+// hash tables are mutable objects, compared by identity like vectors
+impl PartialEq for HashTable {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl HashTable {
+    pub(crate) fn new(equivalence: ValueType, hash: ValueType) -> Self {
+        Self {
+            equivalence,
+            hash,
+            buckets: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    pub(crate) fn entries(&self) -> Vec<(ValueType, ValueType)> {
+        self.buckets.values().flatten().cloned().collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.size
+    }
+
+    // the equivalence and hash procedures, and the entries with the hash codes of their keys,
+    // as an image saves them
+    pub(crate) fn parts(&self) -> (&ValueType, &ValueType, Vec<(u64, ValueType, ValueType)>) {
+        let entries = self
+            .buckets
+            .iter()
+            .flat_map(|(&code, bucket)| {
+                bucket
+                    .iter()
+                    .map(move |(key, value)| (code, key.clone(), value.clone()))
+            })
+            .collect();
+        (&self.equivalence, &self.hash, entries)
+    }
+
+    // a table of the parts an image saved, its keys hashed again by the builtin hash
+    // procedures, which hash objects by an identity they no longer have, and the codes of other
+    // procedures kept
+    pub(crate) fn restored(
+        equivalence: ValueType,
+        hash: ValueType,
+        entries: Vec<(u64, ValueType, ValueType)>,
+    ) -> Self {
+        let mut table = Self::new(equivalence, hash);
+        for (code, key, value) in entries {
+            let code = match builtin_hash_code(&table.hash, &key) {
+                Some(Ok(code)) => code,
+                _ => code,
+            };
+            table.buckets.entry(code).or_default().push((key, value));
+            table.size += 1;
+        }
+        table
+    }
+}
+
+fn buildin_name(procedure: &ValueType) -> Option<&'static str> {
+    match procedure {
+        ValueType::Procedure(Procedure::Buildin(BuildinProcedure(name, _))) => Some(name),
+        _ => None,
+    }
+}
+
+fn hash_into(value: &ValueType, structural: bool, depth: usize, hasher: &mut DefaultHasher) {
+    std::mem::discriminant(value).hash(hasher);
+    match value {
+        ValueType::Number(n) => format!("{}", n).hash(hasher),
+        ValueType::Boolean(b) => b.hash(hasher),
+        ValueType::Character(c) => c.hash(hasher),
+        ValueType::String(s) => s.hash(hasher),
+        ValueType::Symbol(s) => s.hash(hasher),
+        ValueType::Pair(pair) if structural && depth > 0 => {
+            let pair = pair.borrow();
+            hash_into(&pair.car, structural, depth - 1, hasher);
+            hash_into(&pair.cdr, structural, depth - 1, hasher);
+        }
+        ValueType::Pair(_) if structural => (),
+        ValueType::Vector(vector) if structural => {
+            let vector = vector.borrow();
+            vector.len().hash(hasher);
+            if depth > 0 {
+                for element in vector.iter().take(HASH_DEPTH) {
+                    hash_into(element, structural, depth - 1, hasher);
+                }
+            }
+        }
+        ValueType::Bytevector(bytes) if structural => bytes.borrow().hash(hasher),
+        ValueType::Pair(pair) => (pair.as_ptr() as usize).hash(hasher),
+        ValueType::Vector(vector) => (vector.as_ptr() as usize).hash(hasher),
+        ValueType::Bytevector(bytes) => (bytes.as_ptr() as usize).hash(hasher),
+        ValueType::Port(port) => (Rc::as_ptr(port) as usize).hash(hasher),
+        ValueType::ErrorObject(object) => (Rc::as_ptr(object) as usize).hash(hasher),
+        ValueType::RandomSource(source) => (source.as_ptr() as usize).hash(hasher),
+        ValueType::HashTable(table) => (table.as_ptr() as usize).hash(hasher),
+        ValueType::Comparator(comparator) => (Rc::as_ptr(comparator) as usize).hash(hasher),
+        ValueType::Collection(collection) => (Rc::as_ptr(collection) as usize).hash(hasher),
+        ValueType::Promise(promise) => (promise.as_ptr() as usize).hash(hasher),
+        ValueType::Generator(generator) => (generator.as_ptr() as usize).hash(hasher),
+        ValueType::Accumulator(accumulator) => (accumulator.as_ptr() as usize).hash(hasher),
+        ValueType::Regexp(regexp) => (Rc::as_ptr(regexp) as usize).hash(hasher),
+        ValueType::RegexpMatch(found) => (Rc::as_ptr(found) as usize).hash(hasher),
+        ValueType::HttpResponse(response) => (Rc::as_ptr(response) as usize).hash(hasher),
+        ValueType::Socket(socket) => (Rc::as_ptr(socket) as usize).hash(hasher),
+        ValueType::Memo(memo) => (memo.as_ptr() as usize).hash(hasher),
+        // the remaining objects only hash by kind, which is still consistent with their equivalence
+        _ => (),
+    }
+}
+
+pub(crate) fn value_hash(value: &ValueType, structural: bool) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_into(value, structural, HASH_DEPTH, &mut hasher);
+    hasher.finish()
+}
+
+fn string_hash_code(name: &str, value: ValueType, fold: bool) -> Result<u64> {
+    let mut string = expect_string(name, value)?;
+    if fold {
+        string = foldcase(&string);
+    }
+    let mut hasher = DefaultHasher::new();
+    string.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+// the hash procedures provided here are computed directly, others are called
+fn builtin_hash_code(hash: &ValueType, key: &ValueType) -> Option<Result<u64>> {
+    Some(match buildin_name(hash)? {
+        "hash" => Ok(value_hash(key, true)),
+        "hash-by-identity" => Ok(value_hash(key, false)),
+        "string-hash" => string_hash_code("string-hash", key.clone(), false),
+        "string-ci-hash" => string_hash_code("string-ci-hash", key.clone(), true),
+        _ => return None,
+    })
+}
+
+fn hash_code(interpreter: &Interpreter, hash: &ValueType, key: &ValueType) -> Result<u64> {
+    match builtin_hash_code(hash, key) {
+        Some(code) => code,
+        None => Ok(expect_integer(
+            "hash-table",
+            interpreter.call_procedure(hash, vec![key.clone()])?,
+        )? as u64),
+    }
+}
+
+fn equivalent(
+    interpreter: &Interpreter,
+    equivalence: &ValueType,
+    a: &ValueType,
+    b: &ValueType,
+) -> Result<bool> {
+    Ok(match buildin_name(equivalence) {
+        Some("eq?") | Some("eqv?") => a.eqv(b),
+        Some("equal?") => a.equal(b),
+        _ => interpreter
+            .call_procedure(equivalence, vec![a.clone(), b.clone()])?
+            .is_true(),
+    })
+}
+
+// the hash of the key and the index of its entry in the bucket,
+// procedures are called without the table borrowed so they may use it
+pub(crate) fn locate(
+    interpreter: &Interpreter,
+    table: &RefCell<HashTable>,
+    key: &ValueType,
+) -> Result<(u64, Option<usize>)> {
+    let (equivalence, hash) = {
+        let table = table.borrow();
+        (table.equivalence.clone(), table.hash.clone())
+    };
+    let code = hash_code(interpreter, &hash, key)?;
+    let keys = match table.borrow().buckets.get(&code) {
+        Some(bucket) => bucket.iter().map(|(k, _)| k.clone()).collect(),
+        None => vec![],
+    };
+    for (index, candidate) in keys.iter().enumerate() {
+        if equivalent(interpreter, &equivalence, candidate, key)? {
+            return Ok((code, Some(index)));
+        }
+    }
+    Ok((code, None))
+}
+
+pub(crate) fn lookup(
+    interpreter: &Interpreter,
+    table: &RefCell<HashTable>,
+    key: &ValueType,
+) -> Result<Option<ValueType>> {
+    let (code, index) = locate(interpreter, table, key)?;
+    Ok(index.and_then(|index| {
+        table.borrow().buckets[&code]
+            .get(index)
+            .map(|(_, value)| value.clone())
+    }))
+}
+
+pub(crate) fn insert(
+    interpreter: &Interpreter,
+    table: &RefCell<HashTable>,
+    key: ValueType,
+    value: ValueType,
+) -> Result<()> {
+    let (code, index) = locate(interpreter, table, &key)?;
+    let mut table = table.borrow_mut();
+    let bucket = table.buckets.entry(code).or_default();
+    match index.and_then(|index| bucket.get_mut(index)) {
+        Some(entry) => entry.1 = value,
+        None => {
+            bucket.push((key, value));
+            table.size += 1;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn remove(
+    interpreter: &Interpreter,
+    table: &RefCell<HashTable>,
+    key: &ValueType,
+) -> Result<bool> {
+    let (code, index) = locate(interpreter, table, key)?;
+    let mut table = table.borrow_mut();
+    let removed = match (index, table.buckets.get_mut(&code)) {
+        (Some(index), Some(bucket)) if index < bucket.len() => {
+            bucket.remove(index);
+            if bucket.is_empty() {
+                table.buckets.remove(&code);
+            }
+            true
+        }
+        _ => false,
+    };
+    if removed {
+        table.size -= 1;
+    }
+    Ok(removed)
+}
+
+fn expect_hash_table(name: &str, value: ValueType) -> Result<Rc<RefCell<HashTable>>> {
+    match value {
+        ValueType::HashTable(table) => Ok(table),
+        other => logic_error!("{}: expect a hash table, got {}", name, other),
+    }
+}
+
+// the optional bound limits the hash to 0 <= hash < bound
+fn bounded_hash(
+    name: &str,
+    mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    hash: impl Fn(ValueType) -> Result<u64>,
+) -> Result<ValueType> {
+    match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(value), bound, None) => {
+            let code = hash(value?)?;
+            let bound = match bound {
+                Some(bound) => match expect_integer(name, bound?)? {
+                    bound if bound > 0 => bound as u64,
+                    bound => logic_error!("{}: expect a positive bound, got {}", name, bound),
+                },
+                None => i64::MAX as u64,
+            };
+            Ok(ValueType::Number(Number::Integer((code % bound) as i64)))
+        }
+        _ => logic_error!("{} takes one or two arguments", name),
+    }
+}
+
+fn hash(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+    bounded_hash("hash", arguments, |value| Ok(value_hash(&value, true)))
+}
+
+fn hash_by_identity(
+    arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+) -> Result<ValueType> {
+    bounded_hash("hash-by-identity", arguments, |value| {
+        Ok(value_hash(&value, false))
+    })
+}
+
+fn string_hash(arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>) -> Result<ValueType> {
+    bounded_hash("string-hash", arguments, |value| {
+        string_hash_code("string-hash", value, false)
+    })
+}
+
+fn string_ci_hash(
+    arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+) -> Result<ValueType> {
+    bounded_hash("string-ci-hash", arguments, |value| {
+        string_hash_code("string-ci-hash", value, true)
+    })
+}
+
+// the hash procedure matching a provided equivalence, and hash for any other
+pub(crate) fn default_hash(equivalence: &ValueType) -> ValueType {
+    match buildin_name(equivalence) {
+        Some("eq?") | Some("eqv?") => function_mapping!("hash-by-identity", hash_by_identity).1,
+        Some("string=?") => function_mapping!("string-hash", string_hash).1,
+        Some("string-ci=?") => function_mapping!("string-ci-hash", string_ci_hash).1,
+        _ => function_mapping!("hash", hash).1,
+    }
+}
+
+// the equivalence defaults to equal?, as in SRFI 69
+fn new_table(
+    name: &str,
+    equivalence: Option<Result<ValueType>>,
+    hash: Option<Result<ValueType>>,
+) -> Result<HashTable> {
+    let equivalence = match equivalence.transpose()? {
+        Some(procedure @ ValueType::Procedure(_)) => procedure,
+        Some(other) => logic_error!("{}: expect an equivalence procedure, got {}", name, other),
+        None => equivalence_procedure(true),
+    };
+    let hash = match hash.transpose()? {
+        Some(procedure @ ValueType::Procedure(_)) => procedure,
+        Some(other) => logic_error!("{}: expect a hash procedure, got {}", name, other),
+        None => default_hash(&equivalence),
+    };
+    Ok(HashTable::new(equivalence, hash))
+}
+
+// equal? or eqv?, which tables recognize and compare with directly
+pub(crate) fn equivalence_procedure(structural: bool) -> ValueType {
+    fn is_eqv(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(a), Some(b), None) => Ok(ValueType::Boolean(a?.eqv(&b?))),
+            _ => logic_error!("eqv? takes exactly two arguments"),
+        }
+    }
+
+    fn is_equal(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(a), Some(b), None) => Ok(ValueType::Boolean(a?.equal(&b?))),
+            _ => logic_error!("equal? takes exactly two arguments"),
+        }
+    }
+
+    if structural {
+        function_mapping!("equal?", is_equal).1
+    } else {
+        function_mapping!("eqv?", is_eqv).1
+    }
+}
+
+pub(crate) fn srfi_69_library() -> HashMap<String, ValueType> {
+    fn make_hash_table(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (equivalence, hash, None) => Ok(ValueType::HashTable(Rc::new(RefCell::new(
+                new_table("make-hash-table", equivalence, hash)?,
+            )))),
+            _ => logic_error!("make-hash-table takes zero to two arguments"),
+        }
+    }
+
+    fn is_hash_table(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(value), None) => Ok(ValueType::Boolean(matches!(
+                value?,
+                ValueType::HashTable(_)
+            ))),
+            _ => logic_error!("hash-table? takes exactly one argument"),
+        }
+    }
+
+    // earlier associations take precedence over later ones with the same key
+    fn alist_to_hash_table(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(alist), equivalence, hash, None) => {
+                let alist = expect_list("alist->hash-table", alist?)?;
+                let table = RefCell::new(new_table("alist->hash-table", equivalence, hash)?);
+                for association in alist.into_iter().rev() {
+                    let pair = expect_pair("alist->hash-table", association)?;
+                    let (key, value) = {
+                        let pair = pair.borrow();
+                        (pair.car.clone(), pair.cdr.clone())
+                    };
+                    insert(interpreter, &table, key, value)?;
+                }
+                Ok(ValueType::HashTable(Rc::new(table)))
+            }
+            _ => logic_error!("alist->hash-table takes one to three arguments"),
+        }
+    }
+
+    fn hash_table_equivalence_function(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(table), None) => Ok(expect_hash_table(
+                "hash-table-equivalence-function",
+                table?,
+            )?
+            .borrow()
+            .equivalence
+            .clone()),
+            _ => logic_error!("hash-table-equivalence-function takes exactly one argument"),
+        }
+    }
+
+    fn hash_table_hash_function(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(table), None) => Ok(expect_hash_table("hash-table-hash-function", table?)?
+                .borrow()
+                .hash
+                .clone()),
+            _ => logic_error!("hash-table-hash-function takes exactly one argument"),
+        }
+    }
+
+    // the failure thunk is called for a missing key, the success procedure with a present value
+    fn hash_table_ref(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(table), Some(key), failure, success, None) => {
+                let table = expect_hash_table("hash-table-ref", table?)?;
+                let key = key?;
+                match (lookup(interpreter, &table, &key)?, failure, success) {
+                    (Some(value), _, Some(success)) => {
+                        interpreter.call_procedure(&success?, vec![value])
+                    }
+                    (Some(value), _, None) => Ok(value),
+                    (None, Some(failure), _) => interpreter.call_procedure(&failure?, vec![]),
+                    (None, None, _) => {
+                        logic_error!("hash-table-ref: no value associated with {}", key)
+                    }
+                }
+            }
+            _ => logic_error!("hash-table-ref takes two to four arguments"),
+        }
+    }
+
+    fn hash_table_ref_default(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(table), Some(key), Some(default), None) => {
+                let table = expect_hash_table("hash-table-ref/default", table?)?;
+                let key = key?;
+                let default = default?;
+                Ok(lookup(interpreter, &table, &key)?.unwrap_or(default))
+            }
+            _ => logic_error!("hash-table-ref/default takes exactly three arguments"),
+        }
+    }
+
+    // several keys and values may be given, as in SRFI 125
+    fn hash_table_set(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+        let table = match arguments.next() {
+            Some(table) => expect_hash_table("hash-table-set!", table)?,
+            None => logic_error!("hash-table-set! takes a hash table, keys and values"),
+        };
+        if arguments.len() % 2 != 0 {
+            logic_error!("hash-table-set!: expect a value for every key")
+        }
+        while let (Some(key), Some(value)) = (arguments.next(), arguments.next()) {
+            insert(interpreter, &table, key, value)?;
+        }
+        Ok(ValueType::Void)
+    }
+
+    // SRFI 125 returns the number of keys deleted
+    fn hash_table_delete(arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        let mut arguments = arguments.collect::<Result<Vec<_>>>()?.into_iter();
+        let table = match arguments.next() {
+            Some(table) => expect_hash_table("hash-table-delete!", table)?,
+            None => logic_error!("hash-table-delete! takes a hash table and keys"),
+        };
+        let mut deleted = 0;
+        for key in arguments {
+            if remove(interpreter, &table, &key)? {
+                deleted += 1;
+            }
+        }
+        Ok(ValueType::Number(Number::Integer(deleted)))
+    }
+
+    fn hash_table_contains(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(table), Some(key), None) => {
+                let table = expect_hash_table("hash-table-contains?", table?)?;
+                let (_, index) = locate(interpreter, &table, &key?)?;
+                Ok(ValueType::Boolean(index.is_some()))
+            }
+            _ => logic_error!("hash-table-contains? takes exactly two arguments"),
+        }
+    }
+
+    // the current value is found as by hash-table-ref, and replaced by the updater's result
+    fn hash_table_update(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(table), Some(key), Some(updater), failure, success, None) => {
+                let table = expect_hash_table("hash-table-update!", table?)?;
+                let key = key?;
+                let updater = updater?;
+                let value = match (lookup(interpreter, &table, &key)?, failure, success) {
+                    (Some(value), _, Some(success)) => {
+                        interpreter.call_procedure(&success?, vec![value])?
+                    }
+                    (Some(value), _, None) => value,
+                    (None, Some(failure), _) => interpreter.call_procedure(&failure?, vec![])?,
+                    (None, None, _) => {
+                        logic_error!("hash-table-update!: no value associated with {}", key)
+                    }
+                };
+                let updated = interpreter.call_procedure(&updater, vec![value])?;
+                insert(interpreter, &table, key, updated)?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("hash-table-update! takes three to five arguments"),
+        }
+    }
+
+    fn hash_table_update_default(
+        mut arguments: Arguments,
+        interpreter: &Interpreter,
+    ) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(table), Some(key), Some(updater), Some(default), None) => {
+                let table = expect_hash_table("hash-table-update!/default", table?)?;
+                let key = key?;
+                let updater = updater?;
+                let default = default?;
+                let value = lookup(interpreter, &table, &key)?.unwrap_or(default);
+                let updated = interpreter.call_procedure(&updater, vec![value])?;
+                insert(interpreter, &table, key, updated)?;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("hash-table-update!/default takes exactly four arguments"),
+        }
+    }
+
+    fn hash_table_size(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(table), None) => Ok(ValueType::Number(Number::Integer(
+                expect_hash_table("hash-table-size", table?)?.borrow().size as i64,
+            ))),
+            _ => logic_error!("hash-table-size takes exactly one argument"),
+        }
+    }
+
+    fn is_hash_table_empty(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(table), None) => Ok(ValueType::Boolean(
+                expect_hash_table("hash-table-empty?", table?)?
+                    .borrow()
+                    .size
+                    == 0,
+            )),
+            _ => logic_error!("hash-table-empty? takes exactly one argument"),
+        }
+    }
+
+    // the entries are listed in an unspecified order
+    macro_rules! entries_procedure {
+        ($function:ident, $name:literal, $entry:expr) => {
+            fn $function(
+                mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+            ) -> Result<ValueType> {
+                match (arguments.next(), arguments.next()) {
+                    (Some(table), None) => {
+                        let entries = expect_hash_table($name, table?)?.borrow().entries();
+                        Ok(ValueType::list(entries.into_iter().map($entry)))
+                    }
+                    _ => logic_error!("{} takes exactly one argument", $name),
+                }
+            }
+        };
+    }
+
+    entries_procedure!(hash_table_keys, "hash-table-keys", |(key, _)| key);
+    entries_procedure!(hash_table_values, "hash-table-values", |(_, value)| value);
+    entries_procedure!(hash_table_to_alist, "hash-table->alist", |(key, value)| {
+        ValueType::cons(key, value)
+    });
+
+    // the procedure may modify the table, as it walks a snapshot of the entries
+    fn hash_table_walk(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(table), Some(procedure), None) => {
+                let entries = expect_hash_table("hash-table-walk", table?)?
+                    .borrow()
+                    .entries();
+                let procedure = procedure?;
+                for (key, value) in entries {
+                    interpreter.call_procedure(&procedure, vec![key, value])?;
+                }
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("hash-table-walk takes exactly two arguments"),
+        }
+    }
+
+    // SRFI 69 takes (table kons knil), SRFI 125 takes (kons knil table)
+    fn hash_table_fold(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+            arguments.next(),
+        ) {
+            (Some(a), Some(b), Some(c), None) => {
+                let (table, kons, knil) = match (a?, b?, c?) {
+                    (ValueType::HashTable(table), kons, knil) => (table, kons, knil),
+                    (kons, knil, table) => {
+                        (expect_hash_table("hash-table-fold", table)?, kons, knil)
+                    }
+                };
+                let entries = table.borrow().entries();
+                entries
+                    .into_iter()
+                    .try_fold(knil, |accumulator, (key, value)| {
+                        interpreter.call_procedure(&kons, vec![key, value, accumulator])
+                    })
+            }
+            _ => logic_error!("hash-table-fold takes exactly three arguments"),
+        }
+    }
+
+    fn hash_table_copy(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            // every table is mutable, so the mutability flag of SRFI 125 is accepted and ignored
+            (Some(table), _, None) => {
+                let copy = expect_hash_table("hash-table-copy", table?)?
+                    .borrow()
+                    .clone();
+                Ok(ValueType::HashTable(Rc::new(RefCell::new(copy))))
+            }
+            _ => logic_error!("hash-table-copy takes one or two arguments"),
+        }
+    }
+
+    fn hash_table_clear(
+        mut arguments: Box<dyn Iterator<Item = Result<ValueType>> + '_>,
+    ) -> Result<ValueType> {
+        match (arguments.next(), arguments.next()) {
+            (Some(table), None) => {
+                let table = expect_hash_table("hash-table-clear!", table?)?;
+                let mut table = table.borrow_mut();
+                table.buckets.clear();
+                table.size = 0;
+                Ok(ValueType::Void)
+            }
+            _ => logic_error!("hash-table-clear! takes exactly one argument"),
+        }
+    }
+
+    // the entries of the second table are added to the first, which keeps its own on conflicts
+    fn hash_table_merge(mut arguments: Arguments, interpreter: &Interpreter) -> Result<ValueType> {
+        match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(a), Some(b), None) => {
+                let a = a?;
+                let table = expect_hash_table("hash-table-merge!", a.clone())?;
+                let entries = expect_hash_table("hash-table-merge!", b?)?
+                    .borrow()
+                    .entries();
+                for (key, value) in entries {
+                    if locate(interpreter, &table, &key)?.1.is_none() {
+                        insert(interpreter, &table, key, value)?;
+                    }
+                }
+                Ok(a)
+            }
+            _ => logic_error!("hash-table-merge! takes exactly two arguments"),
+        }
+    }
+
+    [
+        function_mapping!("make-hash-table", make_hash_table),
+        function_mapping!("hash-table?", is_hash_table),
+        interpreter_function_mapping!("alist->hash-table", alist_to_hash_table),
+        function_mapping!(
+            "hash-table-equivalence-function",
+            hash_table_equivalence_function
+        ),
+        function_mapping!("hash-table-hash-function", hash_table_hash_function),
+        interpreter_function_mapping!("hash-table-ref", hash_table_ref),
+        interpreter_function_mapping!("hash-table-ref/default", hash_table_ref_default),
+        interpreter_function_mapping!("hash-table-set!", hash_table_set),
+        interpreter_function_mapping!("hash-table-delete!", hash_table_delete),
+        interpreter_function_mapping!("hash-table-exists?", hash_table_contains),
+        interpreter_function_mapping!("hash-table-contains?", hash_table_contains),
+        interpreter_function_mapping!("hash-table-update!", hash_table_update),
+        interpreter_function_mapping!("hash-table-update!/default", hash_table_update_default),
+        function_mapping!("hash-table-size", hash_table_size),
+        function_mapping!("hash-table-empty?", is_hash_table_empty),
+        function_mapping!("hash-table-keys", hash_table_keys),
+        function_mapping!("hash-table-values", hash_table_values),
+        function_mapping!("hash-table->alist", hash_table_to_alist),
+        interpreter_function_mapping!("hash-table-walk", hash_table_walk),
+        interpreter_function_mapping!("hash-table-fold", hash_table_fold),
+        function_mapping!("hash-table-copy", hash_table_copy),
+        function_mapping!("hash-table-clear!", hash_table_clear),
+        interpreter_function_mapping!("hash-table-merge!", hash_table_merge),
+        function_mapping!("hash", hash),
+        function_mapping!("hash-by-identity", hash_by_identity),
+        function_mapping!("string-hash", string_hash),
+        function_mapping!("string-ci-hash", string_ci_hash),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+#[test]
+fn hash_tables() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter.eval(
+        "
+        (define table (make-hash-table))
+        (hash-table-set! table 'a 1 '(b c) 2 \"d\" 3)"
+            .chars(),
+    )?;
+    assert_printed(
+        &interpreter,
+        &[
+            ("(hash-table? table)", "#t"),
+            ("(hash-table? '())", "#f"),
+            ("(hash-table-ref table (list 'b 'c))", "2"),
+            ("(hash-table-ref table 'x (lambda () 'missing))", "missing"),
+            ("(hash-table-ref table 'a (lambda () 0) (lambda (v) (* v 10)))", "10"),
+            ("(hash-table-ref/default table \"d\" 0)", "3"),
+            ("(hash-table-ref/default table 'x 0)", "0"),
+            ("(hash-table-contains? table 'a)", "#t"),
+            ("(hash-table-exists? table 'x)", "#f"),
+            ("(hash-table-size table)", "3"),
+            ("(hash-table-update! table 'a (lambda (v) (+ v 1))) (hash-table-ref table 'a)", "2"),
+            (
+                "(hash-table-update!/default table 'n (lambda (v) (+ v 1)) 0) (hash-table-ref table 'n)",
+                "1",
+            ),
+            ("(hash-table-delete! table 'n 'x)", "1"),
+            ("(hash-table-fold table (lambda (k v acc) (+ v acc)) 0)", "7"),
+            ("(hash-table-fold (lambda (k v acc) (+ v acc)) 0 table)", "7"),
+            ("(list-sort < (hash-table-values table))", "(2 2 3)"),
+            (
+                "(define out (open-output-string)) (hash-table-walk (alist->hash-table '((k . v))) (lambda (k v) (write (list k v) out))) (get-output-string out)",
+                "\"(k v)\"",
+            ),
+            (
+                "(define other (hash-table-copy table)) (hash-table-clear! table) (list (hash-table-size table) (hash-table-size other) (hash-table-empty? table))",
+                "(0 3 #t)",
+            ),
+            (
+                "(hash-table->alist (alist->hash-table '((a . 1) (a . 2)) eq?))",
+                "((a . 1))",
+            ),
+            (
+                "(define strings (make-hash-table string-ci=?)) (hash-table-set! strings \"Key\" 1) (hash-table-ref strings \"KEY\")",
+                "1",
+            ),
+            (
+                "(define numbers (make-hash-table = (lambda (n) 0))) (hash-table-set! numbers 1 'one) (hash-table-ref numbers 1.0)",
+                "one",
+            ),
+            (
+                "(define merged (hash-table-merge! (alist->hash-table '((a . 1))) (alist->hash-table '((a . 2) (b . 3))))) (list (hash-table-ref merged 'a) (hash-table-ref merged 'b))",
+                "(1 3)",
+            ),
+            ("(= (hash '(1 #(2 \"x\"))) (hash (list 1 (vector 2 \"x\"))))", "#t"),
+            ("(< (string-hash \"abc\" 10) 10)", "#t"),
+            ("(= (string-ci-hash \"ABC\") (string-ci-hash \"abc\"))", "#t"),
+            ("(eq? (hash-table-equivalence-function strings) string-ci=?)", "#t"),
+        ],
+    )?;
+    assert_eq!(
+        interpreter.eval("(hash-table-ref (make-hash-table) 'x)".chars()),
+        Err(Error {
+            category: ErrorType::Logic,
+            message: "hash-table-ref: no value associated with x".to_string()
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,561 @@
+use crate::environment::Environment;
+use crate::interpreter::compiler::*;
+use crate::interpreter::*;
+
+// how the interpreter evaluates programs, the tree-walker being kept for debugging the compiler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    // compiling each top level form to bytecode run by a stack vm
+    Bytecode,
+    // evaluating the syntax tree as it is read
+    TreeWalker,
+}
+
+// the state of a procedure a call returns to
+struct CallFrame {
+    code: Rc<Code>,
+    pc: usize,
+    env: Rc<Environment>,
+    // the height of the value stack the frame's values start at
+    base: usize,
+}
+
+// whether the code returns from the instruction at pc without running any other, jumping if
+// need be, as after a call in the tail position
+fn returns(code: &Code, mut pc: usize) -> bool {
+    for _ in 0..code.instructions.len() {
+        match code.instructions[pc] {
+            Instruction::Return => return true,
+            Instruction::Jump(to) => pc = to as usize,
+            _ => return false,
+        }
+    }
+    false
+}
+
+impl Interpreter {
+    // applies a compiled procedure to arguments, counting the call as apply_procedure does
+    pub(crate) fn apply_compiled(
+        &self,
+        code: &Rc<Code>,
+        closure_env: &Rc<Environment>,
+        args: Arguments,
+    ) -> Result<ValueType> {
+        let procedure = code.procedure.as_ref().unwrap();
+        self.enter_call(procedure)?;
+        let result = self
+            .bind_arguments(procedure, closure_env, args)
+            .and_then(|env| {
+                self.execute(code, &env)
+                    .map(|value| value.unwrap_or(ValueType::Void))
+            });
+        self.call_depth.set(self.call_depth.get() - 1);
+        result
+    }
+
+    fn bind_arguments(
+        &self,
+        procedure: &SchemeProcedure,
+        closure_env: &Rc<Environment>,
+        args: impl Iterator<Item = Result<ValueType>>,
+    ) -> Result<Rc<Environment>> {
+        let formals = &procedure.0;
+        let env = Rc::new(Environment::child(closure_env.clone()));
+        let mut arg_count = 0;
+        for arg in args {
+            let arg = arg?;
+            if let Some(formal) = formals.get(arg_count) {
+                env.define(formal.clone(), arg);
+            }
+            arg_count += 1;
+        }
+        if arg_count != formals.len() {
+            return Err(arity_error(procedure, arg_count));
+        }
+        Ok(env)
+    }
+
+    // runs code in the environment, the calls it makes to compiled procedures on frames of its
+    // own rather than on the native stack, returning the value it leaves if any
+    pub(crate) fn execute(
+        &self,
+        code: &Rc<Code>,
+        env: &Rc<Environment>,
+    ) -> Result<Option<ValueType>> {
+        let mut frames: Vec<CallFrame> = vec![];
+        let mut stack: Vec<ValueType> = vec![];
+        let mut code = code.clone();
+        let mut env = env.clone();
+        let mut pc = 0;
+        let mut base = 0;
+        loop {
+            let instruction = code.instructions[pc];
+            self.evaluations.set(self.evaluations.get() + 1);
+            pc += 1;
+            // the errors of the instruction unwind the frames of the calls it is in
+            macro_rules! fail {
+                ($error:expr) => {{
+                    let error = $error;
+                    self.unwind_frames(frames);
+                    return Err(error);
+                }};
+            }
+            macro_rules! attempt {
+                ($result:expr) => {
+                    match $result {
+                        Ok(value) => value,
+                        Err(error) => fail!(error),
+                    }
+                };
+            }
+            match instruction {
+                Instruction::Constant(i) => stack.push(code.constants[i as usize].clone()),
+                Instruction::Datum(i) => stack.push((&code.data[i as usize]).into()),
+                Instruction::Primitive(i) => stack.push(primitive(Primitive::ALL[i as usize])),
+                Instruction::Global(i, depth) => {
+                    let name = &code.names[i as usize];
+                    let top = env.ancestor(depth as usize);
+                    match top.get_cached(name, &code.caches[i as usize]) {
+                        Some(value) => stack.push(value),
+                        None => fail!(undefined_identifier(name, &env)),
+                    }
+                }
+                Instruction::Local(i, depth, index) => {
+                    match env.get_at(depth as usize, index as usize) {
+                        Some(value) => stack.push(value),
+                        None => {
+                            let name = &code.names[i as usize];
+                            match env.get(name) {
+                                Some(value) => stack.push(value),
+                                None => fail!(undefined_identifier(name, &env)),
+                            }
+                        }
+                    }
+                }
+                Instruction::Define(i) => {
+                    let value = stack.pop().unwrap();
+                    env.define(code.names[i as usize].clone(), value);
+                }
+                Instruction::Closure(i) => {
+                    gc::register_environment(&env);
+                    stack.push(ValueType::Procedure(Procedure::Compiled(
+                        code.codes[i as usize].clone(),
+                        env.clone(),
+                    )))
+                }
+                Instruction::FlatClosure(i, depth) => {
+                    let closure = &code.codes[i as usize];
+                    let top = env.ancestor(depth as usize).clone();
+                    let captured = match closure.captured.is_empty() {
+                        true => top,
+                        false => {
+                            let captured = Rc::new(Environment::child(top));
+                            for (name, depth, index) in &closure.captured {
+                                let value = env.get_at(*depth as usize, *index as usize);
+                                match value.or_else(|| env.get(name)) {
+                                    Some(value) => captured.define(name.clone(), value),
+                                    None => fail!(undefined_identifier(name, &env)),
+                                }
+                            }
+                            captured
+                        }
+                    };
+                    stack.push(ValueType::Procedure(Procedure::Compiled(
+                        closure.clone(),
+                        captured,
+                    )))
+                }
+                Instruction::Call(i)
+                | Instruction::CallGlobal(i)
+                | Instruction::ConstantCall(_, i)
+                | Instruction::ConstantCallGlobal(_, i) => {
+                    if let Instruction::ConstantCall(c, _) | Instruction::ConstantCallGlobal(c, _) =
+                        instruction
+                    {
+                        stack.push(code.constants[c as usize].clone());
+                    }
+                    let site = &code.calls[i as usize];
+                    let start = stack.len() - site.arguments;
+                    // the operator of a call of a top level variable is looked up after the
+                    // arguments, which are only read then, rather than pushed under them
+                    let (operator, under) = match (instruction, site.global) {
+                        (
+                            Instruction::CallGlobal(_) | Instruction::ConstantCallGlobal(..),
+                            Some((name, depth)),
+                        ) => {
+                            let cache = &code.caches[name as usize];
+                            let name = &code.names[name as usize];
+                            match env.ancestor(depth as usize).get_cached(name, cache) {
+                                Some(operator) => (operator, 0),
+                                None => fail!(undefined_identifier(name, &env)),
+                            }
+                        }
+                        _ => (std::mem::replace(&mut stack[start - 1], ValueType::Void), 1),
+                    };
+                    let fixnum = match site.arguments {
+                        2 => fixnum_operation(&operator, &stack[start], &stack[start + 1]),
+                        _ => None,
+                    };
+                    if let Some(value) = fixnum {
+                        stack.truncate(start - under);
+                        stack.push(value);
+                    } else if let ValueType::Procedure(Procedure::Compiled(callee, closure_env)) =
+                        &operator
+                    {
+                        let callee = callee.clone();
+                        let procedure = callee.procedure.as_ref().unwrap();
+                        // a procedure calling itself last runs the call in its own frame, which
+                        // returns what the call does, so that loops written as such calls do
+                        // not nest
+                        if Rc::ptr_eq(&callee, &code) && returns(&code, pc) {
+                            let args = stack.drain(start..).map(Ok);
+                            match self.bind_arguments(procedure, closure_env, args) {
+                                Ok(callee_env) => env = callee_env,
+                                Err(error) => {
+                                    self.unwind_site(site, &operator);
+                                    fail!(error)
+                                }
+                            }
+                            stack.truncate(base);
+                            pc = 0;
+                            continue;
+                        }
+                        let entered = self.enter_call(procedure).and_then(|_| {
+                            let args = stack.drain(start..).map(Ok);
+                            let bound = self.bind_arguments(procedure, closure_env, args);
+                            if bound.is_err() {
+                                self.call_depth.set(self.call_depth.get() - 1);
+                            }
+                            bound
+                        });
+                        let callee_env = match entered {
+                            Ok(env) => env,
+                            Err(error) => {
+                                self.unwind_site(site, &operator);
+                                fail!(error)
+                            }
+                        };
+                        stack.truncate(start - under);
+                        frames.push(CallFrame {
+                            code: std::mem::replace(&mut code, callee),
+                            pc,
+                            env: std::mem::replace(&mut env, callee_env),
+                            base,
+                        });
+                        pc = 0;
+                        base = stack.len();
+                    } else {
+                        let args: Vec<ValueType> = stack.drain(start..).collect();
+                        stack.truncate(start - under);
+                        match self.apply_procedure(&operator, Box::new(args.into_iter().map(Ok))) {
+                            Ok(value) => stack.push(value),
+                            Err(error) => {
+                                self.unwind_site(site, &operator);
+                                fail!(error)
+                            }
+                        }
+                    }
+                }
+                Instruction::Jump(to) => pc = to as usize,
+                Instruction::Unchanged(redefinitions, to) => {
+                    if env.redefinitions() != redefinitions {
+                        pc = to as usize;
+                    }
+                }
+                Instruction::JumpUnless(to) => match stack.pop().unwrap() {
+                    ValueType::Boolean(true) => (),
+                    ValueType::Boolean(false) => pc = to as usize,
+                    _ => fail!(Error {
+                        category: ErrorType::Logic,
+                        message: "if condition should be a boolean expression".to_string(),
+                    }),
+                },
+                Instruction::Pop => {
+                    stack.pop();
+                }
+                Instruction::Vector(count) => {
+                    let values = stack.split_off(stack.len() - count as usize);
+                    stack.push(ValueType::vector(values));
+                }
+                Instruction::Delay => {
+                    let thunk = stack.pop().unwrap();
+                    stack.push(scheme::lazy::Promise::delayed(thunk, vec![]));
+                }
+                Instruction::DelayForce => {
+                    let thunk = stack.pop().unwrap();
+                    stack.push(scheme::lazy::Promise::delayed_force(thunk, vec![]));
+                }
+                Instruction::StreamCons => {
+                    let cdr = stack.pop().unwrap();
+                    let car = stack.pop().unwrap();
+                    stack.push(scheme::lazy::Promise::value(ValueType::cons(
+                        scheme::lazy::Promise::delayed(car, vec![]),
+                        scheme::lazy::Promise::delayed_force(cdr, vec![]),
+                    )));
+                }
+                Instruction::Match(i) => {
+                    let value = stack.pop().unwrap();
+                    let result = self.execute_match(&code.matches[i as usize], value, &env);
+                    stack.push(attempt!(result));
+                }
+                Instruction::Guard(i) => {
+                    let result = self.execute_guard(&code.guards[i as usize], &env);
+                    stack.push(attempt!(result));
+                }
+                Instruction::Return => {
+                    let value = match stack.len() > base {
+                        true => stack.pop(),
+                        false => None,
+                    };
+                    match frames.pop() {
+                        None => return Ok(value),
+                        Some(caller) => {
+                            self.call_depth.set(self.call_depth.get() - 1);
+                            stack.truncate(base);
+                            stack.push(value.unwrap_or(ValueType::Void));
+                            code = caller.code;
+                            pc = caller.pc;
+                            env = caller.env;
+                            base = caller.base;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // the calls of the frames an error returns through, innermost first
+    fn unwind_frames(&self, mut frames: Vec<CallFrame>) {
+        while let Some(caller) = frames.pop() {
+            self.call_depth.set(self.call_depth.get() - 1);
+            if let Instruction::Call(i)
+            | Instruction::CallGlobal(i)
+            | Instruction::ConstantCall(_, i)
+            | Instruction::ConstantCallGlobal(_, i) = caller.code.instructions[caller.pc - 1]
+            {
+                let site = &caller.code.calls[i as usize];
+                if let Some(span) = site.span {
+                    let procedure = match site.identifier {
+                        true => site.operator.clone(),
+                        false => "lambda".to_string(),
+                    };
+                    self.backtrace.borrow_mut().push(Frame { procedure, span });
+                }
+            }
+        }
+    }
+
+    // records a located call an error is raised in, named as unwind_call names it
+    fn unwind_site(&self, site: &CallSite, operator: &ValueType) {
+        if let Some(span) = site.span {
+            let procedure = match (site.identifier, operator) {
+                (true, _) => site.operator.clone(),
+                (_, ValueType::Procedure(Procedure::Buildin(BuildinProcedure(name, _)))) => {
+                    name.to_string()
+                }
+                (_, ValueType::Procedure(_)) => "lambda".to_string(),
+                _ => site.operator.clone(),
+            };
+            self.backtrace.borrow_mut().push(Frame { procedure, span });
+        }
+    }
+
+    fn execute_match(
+        &self,
+        clauses: &[(Pattern, Rc<Code>)],
+        value: ValueType,
+        env: &Rc<Environment>,
+    ) -> Result<ValueType> {
+        for (pattern, body) in clauses {
+            let mut bindings = HashMap::new();
+            if self.match_pattern(pattern, &value, env, &mut bindings)? {
+                let arguments = pattern
+                    .variables()
+                    .iter()
+                    .map(|variable| bindings.remove(variable).unwrap())
+                    .collect();
+                let body = self.execute(body, env)?.unwrap_or(ValueType::Void);
+                return self.call_procedure(&body, arguments);
+            }
+        }
+        logic_error!("match: no clause matches {}", value)
+    }
+
+    fn execute_guard(&self, guard: &GuardHandler, env: &Rc<Environment>) -> Result<ValueType> {
+        let depth = self.backtrace.borrow().len();
+        let error = match self.execute(&guard.body, env) {
+            Ok(value) => return Ok(value.unwrap_or(ValueType::Void)),
+            // exiting is not an exception
+            Err(error) if error.exit_code().is_some() => return Err(error),
+            Err(error) => error,
+        };
+        // the frames the error unwound through stay with it if it is raised again
+        let unwound = self.backtrace.borrow_mut().split_off(depth);
+        let clause_env = Rc::new(Environment::child(env.clone()));
+        clause_env.define(guard.variable.clone(), condition_object(&error));
+        for (test, body) in &guard.clauses {
+            let value = match test {
+                Some(test) => self.execute(test, &clause_env)?.unwrap_or(ValueType::Void),
+                None => ValueType::Boolean(true),
+            };
+            if value.is_true() {
+                return match body {
+                    Some(body) => Ok(self.execute(body, &clause_env)?.unwrap_or(ValueType::Void)),
+                    None => Ok(value),
+                };
+            }
+        }
+        self.backtrace.borrow_mut().extend(unwound);
+        Err(error)
+    }
+}
+
+#[test]
+fn engines_agree() {
+    let programs = [
+        "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))) (fib 15)",
+        "(define (f x) (define y (* x 2)) (define (g z) (+ y z)) (g 1)) (f 3)",
+        "((lambda (x) (if x 'yes)) #f)",
+        "(vector 1 (+ 1 1) #(3 (4)) '(5 . 6) #u8(7) 1/2 2.5 #\\a \"s\")",
+        "(define l '(1 2)) (set-car! l 3) (define (m) '(1 2)) (set-car! (m) 3) (list l (m))",
+        "(guard (e (#f 1) ((symbol? e) (list 'caught e))) (raise 'oops))",
+        "(guard (e ((string? e) e)) (guard (e ((number? e) e)) (raise \"inner\")))",
+        "(guard (e (else)) (car 1))",
+        "(match '(1 2 3) ((a b ... ) (list a b)))",
+        "(match 5 ((? odd? n) (* n 2)) (_ 0))",
+        "(force (delay (+ 1 2)))",
+        "(define-stream (ints n) (stream-cons n (ints (+ n 1)))) (stream->list (stream-take (ints 0) 5))",
+        "(map (lambda (x) (* x x)) '(1 2 3))",
+        "((cut list <> 'b <>) 'a 'c)",
+        "(define x 1) (define x (+ x 1)) x",
+        // a variable read before its definition is looked up around it
+        "(define b 'global) (define (f) (define (g) b) (define a (g)) (define b 2) (list a b)) (f)",
+        "(define (adder n) (lambda (x) (+ x n))) ((adder 2) 3)",
+        "(define (f a) (lambda (b) (lambda (c) (list a b c)))) (((f 1) 2) 3)",
+        "(define (f n) (lambda (v) (match v ((? (lambda (x) (> x n)) y) y) (_ 'small)))) ((f 3) 5)",
+        "((guard (e (#t (lambda () e))) (raise 'caught)))",
+        "(define (f n) (define m n) (define (g) (list m n)) g) ((f 1))",
+        "(define (h e) (guard (e ((symbol? e) (list e ((lambda () e))))) (raise e))) (h 'x)",
+        "(if 1 2 3)",
+        "(undefined-procedure 1)",
+        "(undefined-procedure (car 1))",
+        "(define (f n) (g n 'a)) (f 1)",
+        "(define (f n) (define (g) (car n)) (g)) (f 1)",
+        "((lambda (x) x))",
+        "(1 2)",
+    ];
+    for program in programs {
+        let results: Vec<String> = [Engine::TreeWalker, Engine::Bytecode]
+            .iter()
+            .map(|&engine| {
+                let interpreter = Interpreter::new();
+                interpreter.engine.set(engine);
+                match interpreter.eval_source(program) {
+                    Ok(value) => format!("{:?}", value.map(|value| value.to_string())),
+                    Err((error, span)) => format!("{} at {:?}", error, span),
+                }
+            })
+            .collect();
+        assert_eq!(results[0], results[1], "{}", program);
+    }
+}
+
+#[test]
+fn backtraces() {
+    // the calls an error is raised in, not those whose arguments it is raised in
+    let programs = [
+        "(display (car '()))",
+        "(define (f x) (car x)) (display (f 1))",
+        "(define (f x) (+ x 1)) (f (f (vector-ref (vector) 0)))",
+        "(define (f x) (if (= x 0) (car x) (+ (f (- x 1)) 1))) (list 1 (f 2))",
+        "(define (f x) x) (f (f 1 2))",
+        "(define (call f x) (f x)) (call (lambda (x) (car x)) (cdr '(1)))",
+    ];
+    for program in programs {
+        let backtraces: Vec<Vec<(String, usize)>> = [Engine::TreeWalker, Engine::Bytecode]
+            .iter()
+            .map(|&engine| {
+                let interpreter = Interpreter::new();
+                interpreter.engine.set(engine);
+                assert!(interpreter.eval_source(program).is_err(), "{}", program);
+                interpreter
+                    .backtrace()
+                    .into_iter()
+                    .map(|frame| (frame.procedure, frame.span.start))
+                    .collect()
+            })
+            .collect();
+        assert!(!backtraces[1].is_empty(), "{}", program);
+        assert_eq!(backtraces[0], backtraces[1], "{}", program);
+    }
+}
+
+#[test]
+fn frames() -> Result<()> {
+    let interpreter = Interpreter::new();
+    // calls between compiled procedures take no native stack, so they nest as deep as allowed
+    interpreter
+        .eval_source("(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1)))))")
+        .unwrap();
+    assert_eq!(
+        interpreter.eval_source("(count 9000)").unwrap(),
+        Some(ValueType::Number(Number::Integer(9000)))
+    );
+    assert_eq!(interpreter.call_depth.get(), 0);
+    let (error, _) = interpreter.eval_source("(count 20000)").unwrap_err();
+    assert!(error
+        .message
+        .ends_with("maximum call depth of 10000 exceeded"));
+    assert_eq!(interpreter.call_depth.get(), 0);
+    assert_eq!(interpreter.backtrace().len(), 10001);
+    Ok(())
+}
+
+#[test]
+fn global_caches() -> Result<()> {
+    let interpreter = Interpreter::new();
+    interpreter
+        .eval_source("(define x '(1)) (define (f) (car x))")
+        .unwrap();
+    assert_eq!(
+        interpreter.eval_source("(f)").unwrap(),
+        Some(ValueType::Number(Number::Integer(1)))
+    );
+    let code = match interpreter.env.get("f") {
+        Some(ValueType::Procedure(Procedure::Compiled(code, _))) => code,
+        _ => unreachable!(),
+    };
+    assert!(code.caches.iter().all(|cache| cache.get() != usize::MAX));
+    // the variables cached are read again where they are defined anew
+    interpreter
+        .eval_source("(define x '(2)) (define (car l) (cdr l))")
+        .unwrap();
+    assert_eq!(
+        interpreter.eval_source("(f)").unwrap(),
+        Some(ValueType::EmptyList)
+    );
+    Ok(())
+}
+
+#[test]
+fn flat_closures() {
+    let interpreter = Interpreter::new();
+    interpreter
+        .eval_source("(define (adder n) (define table (make-vector 1000 n)) (lambda (x) (+ x n)))")
+        .unwrap();
+    let closure = interpreter.eval_source("(adder 2)").unwrap().unwrap();
+    // the closure keeps the variable it uses and not the frame of the call making it
+    match &closure {
+        ValueType::Procedure(Procedure::Compiled(_, env)) => {
+            assert_eq!(env.names().len(), env.parent().unwrap().names().len() + 1);
+            assert!(Rc::ptr_eq(env.parent().unwrap(), &interpreter.env));
+        }
+        _ => unreachable!(),
+    }
+    assert_eq!(
+        interpreter
+            .call_procedure(&closure, vec![ValueType::Number(Number::Integer(3))])
+            .unwrap(),
+        ValueType::Number(Number::Integer(5))
+    );
+}
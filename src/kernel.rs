@@ -0,0 +1,879 @@
+use crate::diagnostic;
+use crate::error::{Error, ErrorType};
+use crate::interpreter::port::file_error;
+use crate::interpreter::ruschm::digest::{constant_time_eq, hex, hmac_sha256, sha256};
+use crate::interpreter::ruschm::json::{parse_json, to_json};
+use crate::interpreter::srfi::srfi_19::{format_date, Date, Time, TimeType};
+use crate::interpreter::{Interpreter, Number, Port, ValueType};
+use crate::lexer::intern;
+use crate::parser;
+use crate::repl::completions;
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the version of the jupyter messaging protocol spoken
+const PROTOCOL_VERSION: &str = "5.3";
+// separates the routing identities of a message from its parts
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+// the frame flags of ZMTP 3.0, which carries the messages between kernel and clients
+const MORE: u8 = 1;
+const LONG: u8 = 2;
+const COMMAND: u8 = 4;
+// messages are read before their signature is checked, so a peer sending a larger one than
+// any notebook would is dropped instead of having the kernel buffer whatever it claims
+const MAX_MESSAGE: u64 = 64 << 20;
+
+fn write_frame(out: &mut impl Write, flags: u8, body: &[u8]) -> io::Result<()> {
+    match u8::try_from(body.len()) {
+        Ok(size) => out.write_all(&[flags, size])?,
+        Err(_) => {
+            out.write_all(&[flags | LONG])?;
+            out.write_all(&(body.len() as u64).to_be_bytes())?;
+        }
+    }
+    out.write_all(body)
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "a message too large")
+}
+
+// the body is read as it arrives rather than allocated from the size given
+fn read_frame(input: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0; 1];
+    input.read_exact(&mut flags)?;
+    let size = match flags[0] & LONG {
+        0 => {
+            let mut size = [0; 1];
+            input.read_exact(&mut size)?;
+            size[0] as u64
+        }
+        _ => {
+            let mut size = [0; 8];
+            input.read_exact(&mut size)?;
+            u64::from_be_bytes(size)
+        }
+    };
+    if size > MAX_MESSAGE {
+        return Err(too_large());
+    }
+    let mut body = vec![];
+    input.take(size).read_to_end(&mut body)?;
+    if body.len() as u64 != size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok((flags[0], body))
+}
+
+// the frames of the next message, commands like pings and subscriptions are skipped
+fn read_message(input: &mut impl Read) -> io::Result<Vec<Vec<u8>>> {
+    let mut frames = vec![];
+    let mut size = 0;
+    loop {
+        let (flags, body) = read_frame(input)?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        size += body.len() as u64;
+        if size > MAX_MESSAGE {
+            return Err(too_large());
+        }
+        frames.push(body);
+        if flags & MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+fn write_message(out: &mut impl Write, frames: &[Vec<u8>]) -> io::Result<()> {
+    for (index, frame) in frames.iter().enumerate() {
+        let flags = match index + 1 < frames.len() {
+            true => MORE,
+            false => 0,
+        };
+        write_frame(out, flags, frame)?;
+    }
+    out.flush()
+}
+
+// the greeting and the READY command of the NULL mechanism, as the server of the connection
+fn handshake(stream: &mut TcpStream, socket_type: &str) -> io::Result<()> {
+    let mut greeting = [0; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting[32] = 1;
+    stream.write_all(&greeting)?;
+    let mut peer = [0; 64];
+    stream.read_exact(&mut peer)?;
+    let mut null = [0; 20];
+    null[..4].copy_from_slice(b"NULL");
+    if peer[0] != 0xff || peer[9] != 0x7f || peer[10] < 3 || peer[12..32] != null {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expect a ZMTP 3 peer with the NULL mechanism",
+        ));
+    }
+    let mut ready = b"\x05READY\x0bSocket-Type".to_vec();
+    ready.extend_from_slice(&(socket_type.len() as u32).to_be_bytes());
+    ready.extend_from_slice(socket_type.as_bytes());
+    write_frame(stream, COMMAND, &ready)?;
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & COMMAND != 0 && body.starts_with(b"\x05READY") {
+            return Ok(());
+        }
+    }
+}
+
+// accepts the connections of the listener on another thread, serving each on a thread of its
+// own after the handshake; a client going away only ends its own connection
+fn accept(
+    listener: TcpListener,
+    socket_type: &'static str,
+    serve: impl Fn(TcpStream) -> io::Result<()> + Send + Sync + 'static,
+) {
+    let serve = Arc::new(serve);
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let serve = serve.clone();
+            thread::spawn(move || {
+                let _ = handshake(&mut stream, socket_type).and_then(|_| serve(stream));
+            });
+        }
+    });
+}
+
+// a JSON object, as an alist keyed by symbols
+fn object(members: Vec<(&str, ValueType)>) -> ValueType {
+    ValueType::list(
+        members
+            .into_iter()
+            .map(|(key, value)| ValueType::cons(ValueType::Symbol(intern(key)), value)),
+    )
+}
+
+fn string(text: &str) -> ValueType {
+    ValueType::String(text.to_string())
+}
+
+fn integer(n: i64) -> ValueType {
+    ValueType::Number(Number::Integer(n))
+}
+
+fn member(object: &ValueType, key: &str) -> Option<ValueType> {
+    object
+        .list_to_vec()?
+        .into_iter()
+        .find_map(|member| match member {
+            ValueType::Pair(pair) => match &pair.borrow().car {
+                ValueType::Symbol(name) if &**name == key => Some(pair.borrow().cdr.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+fn string_member(object: &ValueType, key: &str) -> String {
+    match member(object, key) {
+        Some(ValueType::String(text)) => text,
+        _ => String::new(),
+    }
+}
+
+fn boolean_member(object: &ValueType, key: &str, default: bool) -> bool {
+    match member(object, key) {
+        Some(ValueType::Boolean(value)) => value,
+        _ => default,
+    }
+}
+
+fn integer_member(object: &ValueType, key: &str) -> Option<i64> {
+    match member(object, key) {
+        Some(ValueType::Number(Number::Integer(n))) => Some(n),
+        _ => None,
+    }
+}
+
+fn now() -> String {
+    let nanoseconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as i128);
+    let time = Time::from_nanoseconds(TimeType::Utc, nanoseconds);
+    match format_date(&Date::from_time(&time, 0), "~5") {
+        Ok(date) => format!("{}Z", date),
+        Err(_) => String::new(),
+    }
+}
+
+fn other_error(e: impl ToString) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+// the name of the error kind a notebook shows above the traceback
+fn error_name(error: &Error) -> &'static str {
+    match error.category {
+        ErrorType::Lexical => "LexicalError",
+        ErrorType::Syntax(_) => "SyntaxError",
+        ErrorType::File => "FileError",
+        ErrorType::Raise(_) => "Raise",
+        ErrorType::Logic | ErrorType::Exit(_) => "Error",
+    }
+}
+
+// one message of the protocol, the JSON parts read with the default representations
+struct Message {
+    identities: Vec<Vec<u8>>,
+    header: ValueType,
+    content: ValueType,
+}
+
+// what running a cell writes to its output ports and displays, in the order it happens
+struct Capture {
+    streams: [(&'static str, Rc<Port>, Cell<usize>); 2],
+    outputs: RefCell<Vec<(&'static str, ValueType)>>,
+}
+
+impl Capture {
+    fn new() -> Self {
+        Self {
+            streams: [
+                ("stdout", Rc::new(Port::open_output_string()), Cell::new(0)),
+                ("stderr", Rc::new(Port::open_output_string()), Cell::new(0)),
+            ],
+            outputs: RefCell::new(vec![]),
+        }
+    }
+
+    // the text written since the last call becomes stream messages
+    fn flush(&self) {
+        for (name, port, written) in &self.streams {
+            let text = port.output_string().unwrap_or_default();
+            if text.len() > written.get() {
+                self.outputs.borrow_mut().push((
+                    "stream",
+                    object(vec![
+                        ("name", string(name)),
+                        ("text", string(&text[written.get()..])),
+                    ]),
+                ));
+                written.set(text.len());
+            }
+        }
+    }
+
+    fn display(&self, mime_type: &str, text: &str) {
+        self.flush();
+        self.outputs.borrow_mut().push((
+            "display_data",
+            object(vec![
+                ("data", object(vec![(mime_type, string(text))])),
+                ("metadata", ValueType::EmptyList),
+                ("transient", ValueType::EmptyList),
+            ]),
+        ));
+    }
+}
+
+struct Kernel {
+    interpreter: Interpreter,
+    key: Vec<u8>,
+    session: String,
+    sent: u64,
+    execution_count: i64,
+    subscribers: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Kernel {
+    // the hex HMAC-SHA256 of the JSON parts, empty when messages are not signed
+    fn sign(&self, parts: &[&[u8]]) -> String {
+        match self.key.is_empty() {
+            true => String::new(),
+            false => hex(&hmac_sha256(&self.key, &parts.concat())),
+        }
+    }
+
+    fn decode(&self, frames: Vec<Vec<u8>>) -> Result<Message, String> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .ok_or("a message without the delimiter")?;
+        let parts = &frames[delimiter + 1..];
+        if parts.len() < 5 {
+            return Err("a message without all of its parts".to_string());
+        }
+        let signature = self.sign(&[&parts[1], &parts[2], &parts[3], &parts[4]]);
+        if !constant_time_eq(&parts[0], signature.as_bytes()) {
+            return Err("a message with a wrong signature".to_string());
+        }
+        let json = |part: &[u8]| {
+            parse_json(&self.interpreter, &String::from_utf8_lossy(part)).map_err(|e| e.to_string())
+        };
+        Ok(Message {
+            identities: frames[..delimiter].to_vec(),
+            header: json(&parts[1])?,
+            content: json(&parts[4])?,
+        })
+    }
+
+    fn encode(
+        &mut self,
+        identities: Vec<Vec<u8>>,
+        message_type: &str,
+        parent: &ValueType,
+        content: ValueType,
+    ) -> io::Result<Vec<Vec<u8>>> {
+        self.sent += 1;
+        let header = object(vec![
+            ("msg_id", string(&format!("{}-{}", self.session, self.sent))),
+            ("session", string(&self.session)),
+            ("username", string("ruschm")),
+            ("date", string(&now())),
+            ("msg_type", string(message_type)),
+            ("version", string(PROTOCOL_VERSION)),
+        ]);
+        let parts: Vec<Vec<u8>> = [&header, parent, &ValueType::EmptyList, &content]
+            .iter()
+            .map(|part| to_json(part).map(String::into_bytes))
+            .collect::<Result<_, _>>()
+            .map_err(other_error)?;
+        let signature = self.sign(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+        let mut frames = identities;
+        frames.push(DELIMITER.to_vec());
+        frames.push(signature.into_bytes());
+        frames.extend(parts);
+        Ok(frames)
+    }
+
+    fn reply(
+        &mut self,
+        out: &Mutex<TcpStream>,
+        request: &Message,
+        message_type: &str,
+        content: ValueType,
+    ) -> io::Result<()> {
+        let frames = self.encode(
+            request.identities.clone(),
+            message_type,
+            &request.header,
+            content,
+        )?;
+        write_message(&mut *out.lock().unwrap(), &frames)
+    }
+
+    // sends the message to every client subscribed to the iopub socket, forgetting the gone ones
+    fn publish(
+        &mut self,
+        request: &Message,
+        message_type: &str,
+        content: ValueType,
+    ) -> io::Result<()> {
+        let topic = message_type.as_bytes().to_vec();
+        let frames = self.encode(vec![topic], message_type, &request.header, content)?;
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain_mut(|subscriber| write_message(subscriber, &frames).is_ok());
+        Ok(())
+    }
+
+    fn kernel_info(&self) -> ValueType {
+        object(vec![
+            ("status", string("ok")),
+            ("protocol_version", string(PROTOCOL_VERSION)),
+            ("implementation", string("ruschm")),
+            ("implementation_version", string(env!("CARGO_PKG_VERSION"))),
+            (
+                "language_info",
+                object(vec![
+                    ("name", string("scheme")),
+                    ("version", string("r7rs-small")),
+                    ("mimetype", string("text/x-scheme")),
+                    ("file_extension", string(".scm")),
+                    ("codemirror_mode", string("scheme")),
+                ]),
+            ),
+            (
+                "banner",
+                string(&format!("Ruschm Version {}", env!("CARGO_PKG_VERSION"))),
+            ),
+            ("help_links", ValueType::vector(vec![])),
+        ])
+    }
+
+    // runs a cell, the output going out as it was written and displayed, then the result
+    // returns the content of the reply and the exit code if the code called exit
+    fn execute(&mut self, request: &Message) -> io::Result<(ValueType, Option<i32>)> {
+        let code = string_member(&request.content, "code");
+        let silent = boolean_member(&request.content, "silent", false);
+        if !silent && boolean_member(&request.content, "store_history", true) {
+            self.execution_count += 1;
+        }
+        let count = integer(self.execution_count);
+        if !silent {
+            let content = object(vec![
+                ("code", string(&code)),
+                ("execution_count", count.clone()),
+            ]);
+            self.publish(request, "execute_input", content)?;
+        }
+        let capture = Rc::new(Capture::new());
+        let interpreter = &self.interpreter;
+        interpreter
+            .current_output_port
+            .replace(capture.streams[0].1.clone());
+        interpreter
+            .current_error_port
+            .replace(capture.streams[1].1.clone());
+        let display = capture.clone();
+        *interpreter.display_handler.borrow_mut() = Some(Box::new(move |mime_type, text| {
+            display.display(mime_type, text)
+        }));
+        let result = interpreter.eval_source(&code);
+        capture.flush();
+        for (message_type, content) in capture.outputs.take() {
+            self.publish(request, message_type, content)?;
+        }
+        let ok = || {
+            object(vec![
+                ("status", string("ok")),
+                ("execution_count", count.clone()),
+                ("user_expressions", ValueType::EmptyList),
+                ("payload", ValueType::vector(vec![])),
+            ])
+        };
+        match result {
+            Ok(Some(ValueType::Void)) | Ok(None) => Ok((ok(), None)),
+            Ok(Some(value)) => {
+                if !silent {
+                    let content = object(vec![
+                        ("execution_count", count.clone()),
+                        (
+                            "data",
+                            object(vec![("text/plain", string(&value.to_string()))]),
+                        ),
+                        ("metadata", ValueType::EmptyList),
+                    ]);
+                    self.publish(request, "execute_result", content)?;
+                }
+                Ok((ok(), None))
+            }
+            Err((e, _)) if e.exit_code().is_some() => Ok((ok(), e.exit_code())),
+            Err((e, span)) => {
+                let mut rendered = vec![];
+                diagnostic::render(&mut rendered, &e, span, &code, None, true)?;
+                let traceback: Vec<ValueType> = String::from_utf8_lossy(&rendered)
+                    .lines()
+                    .map(string)
+                    .collect();
+                let error = || {
+                    vec![
+                        ("ename", string(error_name(&e))),
+                        ("evalue", string(&e.message)),
+                        ("traceback", ValueType::vector(traceback.clone())),
+                    ]
+                };
+                self.publish(request, "error", object(error()))?;
+                let mut content = vec![("status", string("error"))];
+                content.extend(error());
+                content.push(("execution_count", count.clone()));
+                Ok((object(content), None))
+            }
+        }
+    }
+
+    fn complete(&self, request: &Message) -> ValueType {
+        let code = string_member(&request.content, "code");
+        // positions are counted in characters
+        let cursor = integer_member(&request.content, "cursor_pos").unwrap_or(0) as usize;
+        let end = code
+            .char_indices()
+            .nth(cursor)
+            .map_or(code.len(), |(i, _)| i);
+        let names = self.interpreter.global_environment().names();
+        let (start, matches) = completions(&names, &code, end);
+        object(vec![
+            ("status", string("ok")),
+            (
+                "matches",
+                ValueType::vector(matches.iter().map(|name| string(name)).collect()),
+            ),
+            (
+                "cursor_start",
+                integer(code[..start].chars().count() as i64),
+            ),
+            ("cursor_end", integer(code[..end].chars().count() as i64)),
+            ("metadata", ValueType::EmptyList),
+        ])
+    }
+
+    // answers a request of the shell or control socket, the kernel being busy meanwhile
+    // returns the exit code once the kernel is to stop
+    fn handle(&mut self, out: &Mutex<TcpStream>, request: Message) -> io::Result<Option<i32>> {
+        let message_type = string_member(&request.header, "msg_type");
+        let status = |state| object(vec![("execution_state", string(state))]);
+        self.publish(&request, "status", status("busy"))?;
+        let mut exit = None;
+        match message_type.as_str() {
+            "kernel_info_request" => {
+                let content = self.kernel_info();
+                self.reply(out, &request, "kernel_info_reply", content)?
+            }
+            "execute_request" => {
+                let (content, code) = self.execute(&request)?;
+                exit = code;
+                self.reply(out, &request, "execute_reply", content)?
+            }
+            "is_complete_request" => {
+                let code = string_member(&request.content, "code");
+                let content = match parser::is_incomplete(&code) {
+                    true => object(vec![
+                        ("status", string("incomplete")),
+                        ("indent", string("")),
+                    ]),
+                    false => object(vec![("status", string("complete"))]),
+                };
+                self.reply(out, &request, "is_complete_reply", content)?
+            }
+            "complete_request" => {
+                let content = self.complete(&request);
+                self.reply(out, &request, "complete_reply", content)?
+            }
+            "history_request" => {
+                let content = object(vec![
+                    ("status", string("ok")),
+                    ("history", ValueType::vector(vec![])),
+                ]);
+                self.reply(out, &request, "history_reply", content)?
+            }
+            "comm_info_request" => {
+                let content = object(vec![
+                    ("status", string("ok")),
+                    ("comms", ValueType::EmptyList),
+                ]);
+                self.reply(out, &request, "comm_info_reply", content)?
+            }
+            // cells run one after another on this thread, so there is nothing to interrupt
+            "interrupt_request" => {
+                let content = object(vec![("status", string("ok"))]);
+                self.reply(out, &request, "interrupt_reply", content)?
+            }
+            "shutdown_request" => {
+                let restart = boolean_member(&request.content, "restart", false);
+                let content = object(vec![
+                    ("status", string("ok")),
+                    ("restart", ValueType::Boolean(restart)),
+                ]);
+                self.reply(out, &request, "shutdown_reply", content)?;
+                exit = Some(0);
+            }
+            other => eprintln!("ruschm kernel: ignoring a {}", other),
+        }
+        self.publish(&request, "status", status("idle"))?;
+        Ok(exit)
+    }
+}
+
+// the sockets of a kernel, bound to the ports of its connection file
+pub struct Sockets {
+    pub shell: TcpListener,
+    pub control: TcpListener,
+    pub iopub: TcpListener,
+    pub stdin: TcpListener,
+    pub heartbeat: TcpListener,
+}
+
+// a kernel speaking the jupyter messaging protocol on the sockets, signing its messages with
+// the key unless it is empty; returns the exit code once shut down or the code calls exit
+pub fn serve(sockets: Sockets, key: &str) -> io::Result<i32> {
+    let seed = format!("{} {:?}", process::id(), SystemTime::now());
+    let subscribers = Arc::new(Mutex::new(vec![]));
+    let mut kernel = Kernel {
+        interpreter: Interpreter::new(),
+        key: key.as_bytes().to_vec(),
+        session: hex(&sha256(seed.as_bytes())[..16]),
+        sent: 0,
+        execution_count: 0,
+        subscribers: subscribers.clone(),
+    };
+    // the notebook runs code of the user who started the kernel
+    kernel.interpreter.allow_subprocesses.set(true);
+    kernel.interpreter.allow_network.set(true);
+    // requests come from the connections of both sockets to this thread, which owns the
+    // interpreter, with the connection the reply goes to
+    let (requests, received) = channel();
+    type Requests = Sender<(Arc<Mutex<TcpStream>>, Vec<Vec<u8>>)>;
+    let forward = |requests: Requests| {
+        move |stream: TcpStream| -> io::Result<()> {
+            let out = Arc::new(Mutex::new(stream.try_clone()?));
+            let mut input = BufReader::new(stream);
+            loop {
+                let frames = read_message(&mut input)?;
+                requests.send((out.clone(), frames)).map_err(other_error)?;
+            }
+        }
+    };
+    accept(sockets.shell, "ROUTER", forward(requests.clone()));
+    accept(sockets.control, "ROUTER", forward(requests));
+    accept(sockets.iopub, "PUB", move |stream| {
+        subscribers.lock().unwrap().push(stream.try_clone()?);
+        let mut input = BufReader::new(stream);
+        loop {
+            read_message(&mut input)?;
+        }
+    });
+    // input requests are not made, so the stdin socket only takes connections
+    accept(sockets.stdin, "ROUTER", |stream| {
+        let mut input = BufReader::new(stream);
+        loop {
+            read_message(&mut input)?;
+        }
+    });
+    accept(sockets.heartbeat, "REP", |stream| {
+        let mut out = stream.try_clone()?;
+        let mut input = BufReader::new(stream);
+        loop {
+            let frames = read_message(&mut input)?;
+            write_message(&mut out, &frames)?;
+        }
+    });
+    for (out, frames) in received {
+        let request = match kernel.decode(frames) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("ruschm kernel: ignoring {}", e);
+                continue;
+            }
+        };
+        if let Some(code) = kernel.handle(&out, request)? {
+            return Ok(code);
+        }
+    }
+    Ok(0)
+}
+
+// runs the kernel of the connection file jupyter starts it with
+pub fn run(connection_file: &str) -> io::Result<i32> {
+    let interpreter = Interpreter::new();
+    let text = fs::read_to_string(connection_file)
+        .map_err(|e| other_error(file_error("read", connection_file, e)))?;
+    let connection = parse_json(&interpreter, &text).map_err(other_error)?;
+    let transport = string_member(&connection, "transport");
+    if transport != "tcp" {
+        return Err(other_error(format!("unsupported transport {}", transport)));
+    }
+    let scheme = string_member(&connection, "signature_scheme");
+    if !scheme.is_empty() && scheme != "hmac-sha256" {
+        return Err(other_error(format!(
+            "unsupported signature scheme {}",
+            scheme
+        )));
+    }
+    let ip = string_member(&connection, "ip");
+    let bind = |name: &str| {
+        let port = integer_member(&connection, name)
+            .ok_or_else(|| other_error(format!("the connection file has no {}", name)))?;
+        TcpListener::bind((ip.as_str(), port as u16))
+    };
+    let sockets = Sockets {
+        shell: bind("shell_port")?,
+        control: bind("control_port")?,
+        iopub: bind("iopub_port")?,
+        stdin: bind("stdin_port")?,
+        heartbeat: bind("hb_port")?,
+    };
+    serve(sockets, &string_member(&connection, "key"))
+}
+
+#[test]
+fn notebook_session() -> io::Result<()> {
+    // a frame claiming more than a message may hold is refused before it is read
+    let mut claimed = vec![LONG];
+    claimed.extend_from_slice(&u64::MAX.to_be_bytes());
+    assert_eq!(
+        read_frame(&mut &claimed[..]).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(
+        read_frame(&mut &[0, 5, b'a'][..]).unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+    let listen = || TcpListener::bind("127.0.0.1:0");
+    let sockets = Sockets {
+        shell: listen()?,
+        control: listen()?,
+        iopub: listen()?,
+        stdin: listen()?,
+        heartbeat: listen()?,
+    };
+    let address = |listener: &TcpListener| listener.local_addr();
+    let addresses = [
+        address(&sockets.shell)?,
+        address(&sockets.control)?,
+        address(&sockets.iopub)?,
+        address(&sockets.heartbeat)?,
+    ];
+    let kernel = thread::spawn(move || serve(sockets, "secret").unwrap());
+    let connect = |index: usize, socket_type| -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(addresses[index])?;
+        handshake(&mut stream, socket_type)?;
+        Ok(stream)
+    };
+    let mut shell = connect(0, "DEALER")?;
+    let mut control = connect(1, "DEALER")?;
+    let mut iopub = connect(2, "SUB")?;
+    let mut heartbeat = connect(3, "REQ")?;
+    write_message(&mut iopub, &[b"\x01".to_vec()])?;
+    write_message(&mut heartbeat, &[vec![], b"ping".to_vec()])?;
+    assert_eq!(
+        read_message(&mut heartbeat)?,
+        vec![vec![], b"ping".to_vec()]
+    );
+    // the subscriber is known to the kernel once its connection is served
+    thread::sleep(std::time::Duration::from_millis(100));
+
+    let interpreter = Interpreter::new();
+    let request = |out: &mut TcpStream, message_type: &str, content: &str, key: &[u8]| {
+        let header = format!("{{\"msg_id\":\"1\",\"msg_type\":\"{}\"}}", message_type);
+        let parts = [header.as_bytes(), b"{}", b"{}", content.as_bytes()];
+        let mut frames = vec![DELIMITER.to_vec()];
+        frames.push(hex(&hmac_sha256(key, &parts.concat())).into_bytes());
+        frames.extend(parts.iter().map(|part| part.to_vec()));
+        write_message(out, &frames)
+    };
+    // the type and the content of the next message, checking its signature
+    let receive = |input: &mut TcpStream| -> io::Result<(String, String)> {
+        let frames = read_message(input)?;
+        let parts = &frames[frames.iter().position(|frame| frame == DELIMITER).unwrap() + 1..];
+        let signature = hex(&hmac_sha256(b"secret", &parts[1..5].concat()));
+        assert_eq!(parts[0], signature.into_bytes());
+        let header = parse_json(&interpreter, &String::from_utf8_lossy(&parts[1])).unwrap();
+        // replies and the messages published meanwhile have the request as their parent
+        assert!(parts[2].starts_with(b"{\"msg_id\":\"1\""));
+        Ok((
+            string_member(&header, "msg_type"),
+            String::from_utf8_lossy(&parts[4]).to_string(),
+        ))
+    };
+    let messages = |input: &mut TcpStream, count| -> io::Result<Vec<(String, String)>> {
+        (0..count).map(|_| receive(input)).collect()
+    };
+    let message =
+        |message_type: &str, content: &str| (message_type.to_string(), content.to_string());
+    let busy = message("status", "{\"execution_state\":\"busy\"}");
+    let idle = message("status", "{\"execution_state\":\"idle\"}");
+
+    request(&mut shell, "kernel_info_request", "{}", b"secret")?;
+    let (message_type, content) = receive(&mut shell)?;
+    assert_eq!(message_type, "kernel_info_reply");
+    assert!(content.contains("\"implementation\":\"ruschm\""));
+    assert_eq!(messages(&mut iopub, 2)?, vec![busy.clone(), idle.clone()]);
+
+    let code = "(display \\\"hi\\\") (display-data \\\"text/html\\\" \\\"<b>x</b>\\\") (+ 1 2)";
+    request(
+        &mut shell,
+        "execute_request",
+        &format!("{{\"code\":\"{}\"}}", code),
+        b"secret",
+    )?;
+    assert_eq!(
+        receive(&mut shell)?,
+        message(
+            "execute_reply",
+            "{\"status\":\"ok\",\"execution_count\":1,\"user_expressions\":{},\"payload\":[]}"
+        )
+    );
+    assert_eq!(
+        messages(&mut iopub, 6)?,
+        vec![
+            busy.clone(),
+            message(
+                "execute_input",
+                &format!("{{\"code\":\"{}\",\"execution_count\":1}}", code)
+            ),
+            message("stream", "{\"name\":\"stdout\",\"text\":\"hi\"}"),
+            message(
+                "display_data",
+                "{\"data\":{\"text/html\":\"<b>x</b>\"},\"metadata\":{},\"transient\":{}}"
+            ),
+            message(
+                "execute_result",
+                "{\"execution_count\":1,\"data\":{\"text/plain\":\"3\"},\"metadata\":{}}"
+            ),
+            idle.clone(),
+        ]
+    );
+
+    request(
+        &mut shell,
+        "execute_request",
+        "{\"code\":\"(car 1)\"}",
+        b"secret",
+    )?;
+    let (message_type, content) = receive(&mut shell)?;
+    assert_eq!(message_type, "execute_reply");
+    assert!(content.starts_with(
+        "{\"status\":\"error\",\"ename\":\"Error\",\"evalue\":\"car: expect a pair, got 1\""
+    ));
+    assert!(content.ends_with(",\"execution_count\":2}"));
+    let published = messages(&mut iopub, 4)?;
+    assert_eq!(published[2].0, "error");
+
+    // a message with a wrong signature is ignored
+    request(
+        &mut shell,
+        "is_complete_request",
+        "{\"code\":\"1\"}",
+        b"guess",
+    )?;
+    request(
+        &mut shell,
+        "is_complete_request",
+        "{\"code\":\"(define (f x)\"}",
+        b"secret",
+    )?;
+    assert_eq!(
+        receive(&mut shell)?,
+        message(
+            "is_complete_reply",
+            "{\"status\":\"incomplete\",\"indent\":\"\"}"
+        )
+    );
+    request(
+        &mut shell,
+        "complete_request",
+        "{\"code\":\"(vector-fil\",\"cursor_pos\":11}",
+        b"secret",
+    )?;
+    assert_eq!(
+        receive(&mut shell)?,
+        message(
+            "complete_reply",
+            "{\"status\":\"ok\",\"matches\":[\"vector-fill!\"],\"cursor_start\":1,\"cursor_end\":11,\"metadata\":{}}"
+        )
+    );
+
+    request(
+        &mut control,
+        "shutdown_request",
+        "{\"restart\":false}",
+        b"secret",
+    )?;
+    assert_eq!(
+        receive(&mut control)?,
+        message("shutdown_reply", "{\"status\":\"ok\",\"restart\":false}")
+    );
+    assert_eq!(kernel.join().unwrap(), 0);
+    Ok(())
+}
@@ -1,22 +1,106 @@
 #![allow(dead_code)]
 use crate::error::*;
+use crate::interpreter::number::Number;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::fmt;
 use std::iter::Iterator;
 use std::iter::Peekable;
+use std::rc::Rc;
 
 type Result<T> = std::result::Result<T, Error>;
 
+// the text of identifiers, symbols and decimal literals, interned so that the tokens, syntax
+// trees and symbols of a name share one allocation however often it appears
+pub type Name = Rc<str>;
+
+// the table is let go of the names nothing else refers to when it has doubled since
+const MIN_INTERNED: usize = 1024;
+
+// the symbols quoted code and the builtins make over and over, kept in the table for good so
+// that making them again never allocates
+const COMMON_NAMES: &[&str] = &[
+    "quote",
+    "quasiquote",
+    "unquote",
+    "unquote-splicing",
+    "else",
+    "=>",
+    "...",
+    "_",
+    "define",
+    "lambda",
+    "if",
+    "set!",
+    "let",
+    "begin",
+    "null",
+    "time-utc",
+    "time-monotonic",
+    "time-duration",
+];
+
+struct Names {
+    names: HashSet<Name>,
+    limit: usize,
+    // the common names, whose count this keeps above one so the table never lets go of them
+    common: Vec<Name>,
+}
+
+impl Names {
+    fn new() -> Self {
+        let common: Vec<Name> = COMMON_NAMES.iter().map(|&name| Rc::from(name)).collect();
+        Self {
+            names: common.iter().cloned().collect(),
+            limit: MIN_INTERNED,
+            common,
+        }
+    }
+}
+
+thread_local! {
+    static NAMES: RefCell<Names> = RefCell::new(Names::new());
+}
+
+pub fn intern(text: &str) -> Name {
+    NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+        if let Some(name) = names.names.get(text) {
+            return name.clone();
+        }
+        if names.names.len() >= names.limit {
+            names.names.retain(|name| Rc::strong_count(name) > 1);
+            names.limit = MIN_INTERNED.max(names.names.len() * 2);
+        }
+        let name: Name = Rc::from(text);
+        names.names.insert(name.clone());
+        name
+    })
+}
+
+// the variables derived forms bind for themselves are named starting with a character the
+// lexer lets into no identifier, so a program can neither refer to them nor bind them
+const GENERATED: char = '\u{0}';
+
+pub(crate) fn generated_name(label: &str) -> Name {
+    intern(&format!("{}{}", GENERATED, label))
+}
+
+pub(crate) fn is_generated(name: &str) -> bool {
+    name.starts_with(GENERATED)
+}
+
 macro_rules! invalid_token {
     ($($arg:tt)*) => (
-        return Err(Error {category: ErrorType::Lexical, message: format!($($arg)*) });
+        return Err(Error {category: ErrorType::Lexical, message: format!($($arg)*) })
     )
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token {
-    Identifier(String),
+    Identifier(Name),
     Boolean(bool),
-    Real(String), // delay the conversion of demical literal to internal represent for different virtual machines (for example, fixed-points).
+    Real(Name), // delay the conversion of demical literal to internal represent for different virtual machines (for example, fixed-points), and of the integers and rationals out of the range of i64.
     Integer(i64),
     Rational(i64, u64),
     Character(char),
@@ -54,6 +138,75 @@ impl<'a, CharIter: Iterator<Item = char>> Iterator for TokenGenerator<'a, CharIt
     }
 }
 
+// the inverse of string literal escaping, used to write strings as re-readable literals
+pub(crate) fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '\u{007}' => escaped.push_str("\\a"),
+            '\u{008}' => escaped.push_str("\\b"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+const CHARACTER_NAMES: [(&str, char); 9] = [
+    ("alarm", '\u{007}'),
+    ("backspace", '\u{008}'),
+    ("delete", '\u{07f}'),
+    ("escape", '\u{01b}'),
+    ("newline", '\n'),
+    ("null", '\u{000}'),
+    ("return", '\r'),
+    ("space", ' '),
+    ("tab", '\t'),
+];
+
+// the inverse of character literal parsing, used to write characters as re-readable literals
+pub(crate) fn escape_character(c: char) -> String {
+    match CHARACTER_NAMES.iter().find(|(_, named)| *named == c) {
+        Some((name, _)) => format!("#\\{}", name),
+        None if c.is_control() => format!("#\\x{:x}", c as u32),
+        None => format!("#\\{}", c),
+    }
+}
+
+// symbols are written in |bars| unless they read back as the same plain identifier
+pub(crate) fn escape_symbol(name: &str) -> String {
+    let mut chars = name.chars().peekable();
+    if !name.starts_with('|') {
+        let tokens: Result<Vec<Token>> = TokenGenerator::new(&mut chars).collect();
+        if let Ok([Token::Identifier(identifier)]) = tokens.as_deref() {
+            if &**identifier == name {
+                return name.to_string();
+            }
+        }
+    }
+    let mut escaped = String::with_capacity(name.len() + 2);
+    escaped.push('|');
+    for c in name.chars() {
+        match c {
+            '|' => escaped.push_str("\\|"),
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:x};", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('|');
+    escaped
+}
+
 fn test_delimiter(c: char) -> Result<()> {
     match c {
         ' ' | '\t' | '\n' | '\r' | '(' | ')' | '"' | ';' | '|' => Ok(()),
@@ -62,7 +215,8 @@ fn test_delimiter(c: char) -> Result<()> {
 }
 
 fn is_identifier_initial(c: char) -> bool {
-    match c {
+    matches!(
+        c,
         'a'..='z'
         | 'A'..='Z'
         | '!'
@@ -79,16 +233,15 @@ fn is_identifier_initial(c: char) -> bool {
         | '@'
         | '^'
         | '_'
-        | '~' => true,
-        _ => false,
-    }
+        | '~'
+    )
 }
 
 impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
     pub fn new(text_iterator: &'a mut Peekable<CharIter>) -> TokenGenerator<'a, CharIter> {
         Self {
             current: None,
-            text_iterator: text_iterator,
+            text_iterator,
         }
     }
 
@@ -104,15 +257,15 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
                         '(' => Ok(Some(Token::VecConsIntro)),
                         't' => Ok(Some(Token::Boolean(true))),
                         'f' => Ok(Some(Token::Boolean(false))),
-                        '\\' => match self.advance(1).take() {
-                            Some(cnn) => Ok(Some(Token::Character(cnn))),
-                            None => invalid_token!("expect character after #\\"),
-                        },
+                        '\\' => self.character(),
+                        'x' | 'X' | 'b' | 'B' | 'o' | 'O' | 'd' | 'D' | 'e' | 'E' | 'i' | 'I' => {
+                            self.number("#")
+                        }
                         'u' => {
                             if Some('8') == self.advance(1).take()
                                 && Some('(') == self.advance(1).take()
                             {
-                                return Ok(Some(Token::ByteVecConsIntro));
+                                Ok(Some(Token::ByteVecConsIntro))
                             } else {
                                 invalid_token!("Imcomplete bytevector constant introducer");
                             }
@@ -133,14 +286,17 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
                     },
                     None => Ok(None),
                 },
-                '.' => self.percular_identifier(),
+                '.' => match self.text_iterator.peek() {
+                    Some('0'..='9') => self.number(""),
+                    _ => self.percular_identifier(),
+                },
                 '+' | '-' => match self.text_iterator.peek() {
-                    Some('0'..='9') => self.number(),
-                    Some('.') => self.number(),
+                    Some('0'..='9') => self.number(""),
+                    Some('.') => self.number(""),
                     _ => self.percular_identifier(),
                 },
                 '"' => self.string(),
-                '0'..='9' => self.number(),
+                '0'..='9' => self.number(""),
                 '|' => self.quote_identifier(),
                 _ => self.normal_identifier(),
             },
@@ -156,6 +312,38 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
         &mut self.current
     }
 
+    // #\a, #\space or #\x41, names only continue with alphanumeric characters
+    fn character(&mut self) -> Result<Option<Token>> {
+        let first = match self.advance(1).take() {
+            Some(c) => c,
+            None => invalid_token!("expect character after #\\"),
+        };
+        let mut name = first.to_string();
+        if first.is_alphabetic() {
+            while let Some(c) = self.text_iterator.peek() {
+                if !c.is_alphanumeric() {
+                    break;
+                }
+                name.push(*c);
+                self.advance(1);
+            }
+        }
+        if name.chars().count() == 1 {
+            return Ok(Some(Token::Character(first)));
+        }
+        if let Some((_, c)) = CHARACTER_NAMES.iter().find(|(named, _)| *named == name) {
+            return Ok(Some(Token::Character(*c)));
+        }
+        match name
+            .strip_prefix('x')
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(std::char::from_u32)
+        {
+            Some(c) => Ok(Some(Token::Character(c))),
+            None => invalid_token!("unknown character name #\\{}", name),
+        }
+    }
+
     fn atmosphere(&mut self) -> Result<Option<Token>> {
         while let Some(c) = self.text_iterator.peek() {
             match c {
@@ -180,25 +368,22 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
 
     fn normal_identifier(&mut self) -> Result<Option<Token>> {
         match self.current {
+            Some(GENERATED) => invalid_token!("identifiers cannot contain the null character"),
             Some(c) => {
                 let mut identifier_str = String::new();
                 identifier_str.push(c);
-                loop {
-                    if let Some(nc) = self.text_iterator.peek() {
-                        match nc {
-                            _ if is_identifier_initial(*nc) => identifier_str.push(*nc),
-                            '0'..='9' | '+' | '-' | '.' | '@' => identifier_str.push(*nc),
-                            _ => {
-                                test_delimiter(*nc)?;
-                                break;
-                            }
+                while let Some(nc) = self.text_iterator.peek() {
+                    match nc {
+                        _ if is_identifier_initial(*nc) => identifier_str.push(*nc),
+                        '0'..='9' | '+' | '-' | '.' | '@' => identifier_str.push(*nc),
+                        _ => {
+                            test_delimiter(*nc)?;
+                            break;
                         }
-                        self.advance(1);
-                    } else {
-                        break;
                     }
+                    self.advance(1);
                 }
-                Ok(Some(Token::Identifier(identifier_str)))
+                Ok(Some(Token::Identifier(intern(&identifier_str))))
             }
             None => Ok(None),
         }
@@ -211,19 +396,20 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
                 _ => is_identifier_initial(*c),
             };
             match valid {
-                true => loop {
-                    match self.advance(1).take() {
-                        Some(nc) => match nc {
-                            _ if is_identifier_initial(nc) => identifier_str.push(nc),
-                            '0'..='9' | '+' | '-' | '.' | '@' => identifier_str.push(nc),
+                // the delimiter is left for the next token, like after normal identifiers
+                true => {
+                    while let Some(nc) = self.text_iterator.peek() {
+                        match nc {
+                            _ if is_identifier_initial(*nc) => identifier_str.push(*nc),
+                            '0'..='9' | '+' | '-' | '.' | '@' => identifier_str.push(*nc),
                             _ => {
-                                test_delimiter(nc)?;
+                                test_delimiter(*nc)?;
                                 break;
                             }
-                        },
-                        None => invalid_token!("Invalid Indentifer {}", identifier_str),
+                        }
+                        self.advance(1);
                     }
-                },
+                }
                 false => {
                     test_delimiter(*c)?;
                 }
@@ -258,7 +444,10 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
                 }
                 match identifier_str.as_str() {
                     "." => Ok(Some(Token::Period)),
-                    _ => Ok(Some(Token::Identifier(identifier_str))),
+                    "+inf.0" | "-inf.0" | "+nan.0" | "-nan.0" => {
+                        number_token(&identifier_str).map(Some)
+                    }
+                    _ => Ok(Some(Token::Identifier(intern(&identifier_str)))),
                 }
             }
             None => Ok(None),
@@ -271,12 +460,56 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
             self.current = self.text_iterator.next();
             match self.current {
                 None => invalid_token!("Incomplete identifier {}", identifier_str),
-                Some('|') => break Ok(Some(Token::Identifier(identifier_str))),
+                Some('|') => break Ok(Some(Token::Identifier(intern(&identifier_str)))),
+                Some(GENERATED) => invalid_token!("identifiers cannot contain the null character"),
+                Some('\\') => match self.escape_sequence()? {
+                    Some(GENERATED) => {
+                        invalid_token!("identifiers cannot contain the null character")
+                    }
+                    Some(ec) => identifier_str.push(ec),
+                    None => (),
+                },
                 Some(nc) => identifier_str.push(nc),
             }
         }
     }
 
+    // the escaped character after a backslash in strings and |identifiers|
+    fn escape_sequence(&mut self) -> Result<Option<char>> {
+        match self.advance(1).take() {
+            Some(ec) => Ok(Some(match ec {
+                'a' => '\u{007}',
+                'b' => '\u{008}',
+                't' => '\u{009}',
+                'n' => '\n',
+                'r' => '\r',
+                '"' => '"',
+                '\\' => '\\',
+                '|' => '|',
+                'x' => {
+                    let mut hex = String::new();
+                    loop {
+                        match self.advance(1).take() {
+                            Some(';') => break,
+                            Some(c) if c.is_ascii_hexdigit() => hex.push(c),
+                            _ => invalid_token!("expect hex digits ended with ';' after \\x"),
+                        }
+                    }
+                    match u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                    {
+                        Some(c) => c,
+                        None => invalid_token!("Invalid hex escape \\x{};", hex),
+                    }
+                }
+                ' ' => return Ok(None), // TODO: line continuation
+                _ => invalid_token!("Unknown escape character"),
+            })),
+            None => invalid_token!("Incomplete escape character"),
+        }
+    }
+
     fn string(&mut self) -> Result<Option<Token>> {
         match self.current {
             Some(_c) => {
@@ -286,23 +519,8 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
                         match c {
                             '"' => break Ok(Some(Token::String(string_literal))),
                             '\\' => {
-                                match self.advance(1) {
-                                    Some(ec) => {
-                                        match ec {
-                                            'a' => string_literal.push('\u{007}'),
-                                            'b' => string_literal.push('\u{008}'),
-                                            't' => string_literal.push('\u{009}'),
-                                            'n' => string_literal.push('\n'),
-                                            'r' => string_literal.push('\r'),
-                                            '"' => string_literal.push('"'),
-                                            '\\' => string_literal.push('\\'),
-                                            '|' => string_literal.push('|'),
-                                            'x' => (), // TODO: 'x' for hex value
-                                            ' ' => (), // TODO: space for nothing
-                                            _ => invalid_token!("Unknown escape character"),
-                                        }
-                                    }
-                                    None => invalid_token!("Incomplete escape character"),
+                                if let Some(ec) = self.escape_sequence()? {
+                                    string_literal.push(ec);
                                 }
                             }
                             _ => string_literal.push(c),
@@ -316,99 +534,30 @@ impl<'a, CharIter: Iterator<Item = char>> TokenGenerator<'a, CharIter> {
         }
     }
 
-    fn digital10(&mut self, number_literal: &mut String) -> Result<()> {
-        loop {
-            match self.text_iterator.peek() {
-                Some(nc) => match nc {
-                    '0'..='9' => number_literal.push(*nc),
-                    _ => {
-                        break Ok(());
-                    }
-                },
-                None => break Ok(()),
+    // numbers are read up to a delimiter and converted as string->number does
+    fn number(&mut self, prefix: &str) -> Result<Option<Token>> {
+        let mut literal = prefix.to_string();
+        literal.extend(self.current.take());
+        while let Some(&c) = self.text_iterator.peek() {
+            if test_delimiter(c).is_ok() {
+                break;
             }
+            literal.push(c);
             self.advance(1);
         }
+        number_token(&literal).map(Some)
     }
+}
 
-    fn number_suffix(&mut self, number_literal: &mut String) -> Result<()> {
-        self.advance(1);
-        number_literal.push('e');
-        if let Some(sign) = self.text_iterator.peek() {
-            if (*sign == '+') || (*sign == '-') {
-                number_literal.push(*sign);
-                self.advance(1);
-            }
-        }
-        self.digital10(number_literal)
-    }
-
-    fn real(&mut self, number_literal: &mut String) -> Result<()> {
-        number_literal.push('.');
-        self.advance(1);
-        match self.text_iterator.peek() {
-            Some(nc) => match nc {
-                'e' => self.number_suffix(number_literal),
-                '0'..='9' => {
-                    self.digital10(number_literal)?;
-                    match self.text_iterator.peek() {
-                        Some('e') => self.number_suffix(number_literal),
-                        Some(nnc) => test_delimiter(*nnc),
-                        None => Ok(()),
-                    }
-                }
-                _ => {
-                    test_delimiter(*nc)?;
-                    Ok(())
-                }
-            },
-            None => Ok(()),
-        }
-    }
-
-    fn number(&mut self) -> Result<Option<Token>> {
-        match self.current.take() {
-            Some(c) => {
-                let mut number_literal = String::new();
-                number_literal.push(c);
-                loop {
-                    let peek = self.text_iterator.peek();
-                    match peek {
-                        Some(nc) => match nc {
-                            '0'..='9' => self.digital10(&mut number_literal)?,
-                            'e' => {
-                                self.number_suffix(&mut number_literal)?;
-                                break Ok(Some(Token::Real(number_literal)));
-                            }
-                            '.' => {
-                                self.real(&mut number_literal)?;
-                                break Ok(Some(Token::Real(number_literal)));
-                            }
-                            '/' => {
-                                let mut denominator = String::new();
-                                self.advance(1);
-                                self.digital10(&mut denominator)?;
-                                break Ok(Some(Token::Rational(
-                                    number_literal.parse::<i64>().unwrap(),
-                                    denominator.parse::<u64>().unwrap(),
-                                )));
-                            }
-                            _ => {
-                                test_delimiter(*nc)?;
-                                break Ok(Some(Token::Integer(
-                                    number_literal.parse::<i64>().unwrap(),
-                                )));
-                            }
-                        },
-                        None => {
-                            break Ok(Some(Token::Integer(number_literal.parse::<i64>().unwrap())))
-                        }
-                    }
-                }
-            }
-            None => Ok(None),
-        }
-    }
+// fixnums and their fractions are converted when read, other numbers are kept as written, for
+// the virtual machine to convert as string->number does
+fn number_token(literal: &str) -> Result<Token> {
+    Ok(match Number::parse(literal, 10) {
+        Some(Number::Integer(n)) => Token::Integer(n),
+        Some(Number::Rational(a, b)) => Token::Rational(a, b as u64),
+        Some(_) => Token::Real(intern(literal)),
+        None => invalid_token!("invalid number {}", literal),
+    })
 }
 
 fn tokenize(text: &str) -> Result<Vec<Token>> {
@@ -453,24 +602,105 @@ fn identifier() -> Result<()> {
         |two words| |two; words|"
         )?,
         vec![
-            Token::Identifier(String::from("...")),
-            Token::Identifier(String::from("+")),
-            Token::Identifier(String::from("+soup+")),
-            Token::Identifier(String::from("<=?")),
-            Token::Identifier(String::from("->string")),
-            Token::Identifier(String::from("a34kTMNs")),
-            Token::Identifier(String::from("lambda")),
-            Token::Identifier(String::from("list->vector")),
-            Token::Identifier(String::from("q")),
-            Token::Identifier(String::from("V17a")),
-            Token::Identifier(String::from("two words")),
-            Token::Identifier(String::from("two; words"))
+            Token::Identifier("...".into()),
+            Token::Identifier("+".into()),
+            Token::Identifier("+soup+".into()),
+            Token::Identifier("<=?".into()),
+            Token::Identifier("->string".into()),
+            Token::Identifier("a34kTMNs".into()),
+            Token::Identifier("lambda".into()),
+            Token::Identifier("list->vector".into()),
+            Token::Identifier("q".into()),
+            Token::Identifier("V17a".into()),
+            Token::Identifier("two words".into()),
+            Token::Identifier("two; words".into())
         ]
     );
+    assert_eq!(
+        tokenize("(a ...)(+b)...")?,
+        vec![
+            Token::LeftParen,
+            Token::Identifier("a".into()),
+            Token::Identifier("...".into()),
+            Token::RightParen,
+            Token::LeftParen,
+            Token::Identifier("+b".into()),
+            Token::RightParen,
+            Token::Identifier("...".into()),
+        ]
+    );
+    assert_eq!(
+        tokenize("|a\\|b\\x3bb;|")?,
+        vec![Token::Identifier("a|bλ".into())]
+    );
+    for name in &[
+        "abc",
+        "two words",
+        "",
+        "1",
+        "a|b\\",
+        "tab\t",
+        "|",
+        "#t",
+        ".",
+    ] {
+        assert_eq!(
+            tokenize(escape_symbol(name).as_str())?,
+            vec![Token::Identifier((*name).into())],
+            "{}",
+            escape_symbol(name)
+        );
+    }
+    assert_eq!(escape_symbol("list->vector"), "list->vector");
+    // the names of generated variables cannot be read back
+    let generated = generated_name("slot 1");
+    assert!(is_generated(&generated));
+    for text in &[escape_symbol(&generated), "|\\x0;slot 1|".to_string()] {
+        assert_eq!(
+            tokenize(text),
+            Err(Error {
+                category: ErrorType::Lexical,
+                message: "identifiers cannot contain the null character".to_string()
+            }),
+            "{}",
+            text
+        );
+    }
 
     Ok(())
 }
 
+#[test]
+fn interning() -> Result<()> {
+    let tokens = tokenize("car 1.5 (car 1.5) |car|")?;
+    let names = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Identifier(name) | Token::Real(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), 5);
+    assert!(Rc::ptr_eq(&names[0], &names[2]));
+    assert!(Rc::ptr_eq(&names[0], &names[4]));
+    assert!(Rc::ptr_eq(&names[1], &names[3]));
+    assert!(Rc::ptr_eq(&names[0], &intern("car")));
+    // names no longer used are dropped when the table grows
+    for i in 0..MIN_INTERNED * 2 {
+        intern(&format!("name-{}", i));
+    }
+    assert!(NAMES.with(|names| names.borrow().names.len()) <= MIN_INTERNED * 2);
+    assert!(Rc::ptr_eq(&names[0], &intern("car")));
+    // common names are kept even when nothing else refers to them
+    let quote = Rc::as_ptr(&intern("quote"));
+    for i in 0..MIN_INTERNED * 2 {
+        intern(&format!("name-{}", i));
+    }
+    assert!(Rc::strong_count(&intern("quote")) > 1);
+    assert_eq!(Rc::as_ptr(&intern("quote")), quote);
+    Ok(())
+}
+
 #[test]
 fn character() -> Result<()> {
     assert_eq!(
@@ -481,6 +711,31 @@ fn character() -> Result<()> {
             Token::Character('\t')
         ]
     );
+    assert_eq!(
+        tokenize("#\\space #\\x41 #\\x #\\λ(#\\newline)")?,
+        vec![
+            Token::Character(' '),
+            Token::Character('A'),
+            Token::Character('x'),
+            Token::Character('λ'),
+            Token::LeftParen,
+            Token::Character('\n'),
+            Token::RightParen
+        ]
+    );
+    assert_eq!(
+        tokenize("#\\spaces"),
+        Err(Error {
+            category: ErrorType::Lexical,
+            message: "unknown character name #\\spaces".to_string()
+        })
+    );
+    for c in &['a', ' ', '\u{01b}', '\u{001}', '(', '\u{07f}'] {
+        assert_eq!(
+            tokenize(escape_character(*c).as_str())?,
+            vec![Token::Character(*c)]
+        );
+    }
     Ok(())
 }
 
@@ -511,21 +766,48 @@ fn number() -> Result<()> {
             Token::Integer(123),
             Token::Integer(123),
             Token::Integer(-123),
-            Token::Real("1.23".to_string()),
-            Token::Real("-12.34".to_string()),
-            Token::Real("1.".to_string()),
-            Token::Real("0.".to_string()),
-            Token::Real("+.0".to_string()),
-            Token::Real("-.1".to_string()),
-            Token::Real("1e10".to_string()),
-            Token::Real("1.3e20".to_string()),
-            Token::Real("-43.e-12".to_string()),
-            Token::Real("+.12e+12".to_string()),
+            Token::Real("1.23".into()),
+            Token::Real("-12.34".into()),
+            Token::Real("1.".into()),
+            Token::Real("0.".into()),
+            Token::Real("+.0".into()),
+            Token::Real("-.1".into()),
+            Token::Real("1e10".into()),
+            Token::Real("1.3e20".into()),
+            Token::Real("-43.e-12".into()),
+            Token::Real("+.12e+12".into()),
             Token::Rational(1, 2),
             Token::Rational(1, 2),
             Token::Rational(-32, 3),
         ]
     );
+    // prefixes, infinities and numbers beyond fixnums are read as string->number reads them
+    assert_eq!(
+        tokenize("#xff #B101 #e1.5 #i1/2 .5 +inf.0 -inf.0 +nan.0 #x10000000000000000 6/4")?,
+        vec![
+            Token::Integer(255),
+            Token::Integer(5),
+            Token::Rational(3, 2),
+            Token::Real("#i1/2".into()),
+            Token::Real(".5".into()),
+            Token::Real("+inf.0".into()),
+            Token::Real("-inf.0".into()),
+            Token::Real("+nan.0".into()),
+            Token::Real("#x10000000000000000".into()),
+            Token::Rational(3, 2),
+        ]
+    );
+    for text in &["1/0", "#x1g", "12a", "#e+nan.0"] {
+        assert_eq!(
+            tokenize(text),
+            Err(Error {
+                category: ErrorType::Lexical,
+                message: format!("invalid number {}", text)
+            }),
+            "{}",
+            text
+        );
+    }
     Ok(())
 }
 
@@ -536,10 +818,10 @@ fn atmosphere() -> Result<()> {
         tokenize("\t(- \n4\r(+ 1 2))")?,
         vec![
             Token::LeftParen,
-            Token::Identifier(String::from("-")),
+            Token::Identifier("-".into()),
             Token::Integer(4),
             Token::LeftParen,
-            Token::Identifier(String::from("+")),
+            Token::Identifier("+".into()),
             Token::Integer(1),
             Token::Integer(2),
             Token::RightParen,
@@ -553,7 +835,17 @@ fn atmosphere() -> Result<()> {
 fn comment() -> Result<()> {
     assert_eq!(
         tokenize("abcd;+-12\t\n\r 12")?,
-        vec![Token::Identifier(String::from("abcd")), Token::Integer(12)]
+        vec![Token::Identifier("abcd".into()), Token::Integer(12)]
+    );
+    Ok(())
+}
+
+#[test]
+fn escape() -> Result<()> {
+    let text = "a\u{007}\u{008}\t\r\n\\\"";
+    assert_eq!(
+        tokenize(escape_string(text).as_str())?,
+        vec![Token::String(text.to_string())]
     );
     Ok(())
 }
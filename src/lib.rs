@@ -1,6 +1,11 @@
+pub mod analysis;
+pub mod bundle;
+pub mod diagnostic;
 pub mod environment;
 pub mod error;
 pub mod interpreter;
+pub mod kernel;
 pub mod lexer;
 pub mod parser;
 pub mod repl;
+pub use error::RuschmError;
@@ -1,24 +1,528 @@
-mod environment;
-mod error;
-mod interpreter;
-mod lexer;
-mod parser;
-mod repl;
+use ruschm::analysis;
+use ruschm::bundle;
+use ruschm::diagnostic::{self, Diagnostic};
+use ruschm::interpreter::bytecode;
+use ruschm::interpreter::optimizer::MAX_OPTIMIZATION_LEVEL;
+use ruschm::interpreter::port::file_error;
+use ruschm::interpreter::{
+    read_source, set_thread_stack_size, Engine, Interpreter, ValueType, MAX_CALL_DEPTH_STACK,
+};
+use ruschm::kernel;
+use ruschm::repl;
 
 use std::env;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-
-fn main() -> Result<(), error::Error> {
-    Ok(match env::args().skip(1).next() {
-        Some(file) => {
-            let f = BufReader::new(File::open(file.as_str()).expect("no such file or directory"));
-            let it = interpreter::Interpreter::new();
-            it.eval(
-                f.lines()
-                    .flat_map(|line| line.unwrap().chars().collect::<Vec<_>>().into_iter()),
-            )?;
-        }
-        None => repl::run(),
-    })
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+
+// the status of a program stopped by an error it did not handle, as after (exit #f)
+const ERROR_STATUS: i32 = 1;
+// the status of a command line which cannot be understood
+const USAGE_STATUS: i32 = 2;
+
+const USAGE: &str = "usage: ruschm [--no-init] [--diagnostics=text|json] [--max-errors N] [--engine=bytecode|tree]
+              [--optimize=0|1|2] [--image IMAGE] [--eval EXPRESSION | FILE [ARGUMENT ...]]
+       ruschm [--no-init] --listen ADDRESS [--token TOKEN]
+       ruschm --kernel CONNECTION_FILE
+       ruschm build FILE [-o OUTPUT]
+       ruschm compile FILE [-o OUTPUT]
+the program is read from the standard input when FILE is - or, without FILE, when it is not a terminal
+with --diagnostics=json its warnings and errors are written to the standard error as JSON, one per line
+with --max-errors only the first N of them are shown, the program is read no further than its Nth error
+with --engine=tree the program is evaluated by walking its syntax tree rather than compiled to bytecode
+with --optimize=0 it is compiled as written, the default 1 folds arithmetic on constants and
+conditionals of a constant test, and 2 also propagates the constants procedures bind
+and inlines the small procedures bodies define
+build makes an executable running the program compiled to bytecode, by default named as FILE without its extension
+with --image the variables saved in the image by (save-image IMAGE) are defined first
+compile writes the program compiled to bytecode, by default to FILE with the extension rbc, which
+ruschm runs as FILE would be, running the source instead if it changed since";
+
+// writes a diagnostic of the program to the standard error, for tools as JSON if asked for
+fn report(diagnostic: &Diagnostic, program: &str, name: Option<&str>, json: bool) {
+    let stderr = io::stderr();
+    let color = diagnostic::use_color(&stderr);
+    let mut stderr = stderr.lock();
+    let _ = match json {
+        true => writeln!(stderr, "{}", diagnostic.to_json(program, name)),
+        false => diagnostic.render(&mut stderr, program, name, color),
+    };
+}
+
+// evaluates the top level forms of a program, returning the exit status
+// the warnings found in the program are shown before it runs, and the errors after it, a form
+// which cannot be read not keeping the forms after it from running
+// the max_errors of the interpreter limits the warnings and errors shown together
+// the forms compiled from the program, if given, are run rather than it
+fn run(
+    it: &Interpreter,
+    program: &str,
+    name: Option<&str>,
+    forms: Option<&bytecode::Forms>,
+    print_result: bool,
+    json: bool,
+) -> i32 {
+    let max_errors = it.max_errors.get();
+    let mut left = max_errors.unwrap_or(usize::MAX);
+    for warning in analysis::warnings(program).iter().take(left) {
+        report(&warning.diagnostic(), program, name, json);
+        left -= 1;
+    }
+    // the program is read up to its first error at least, which stops it if none are left
+    it.max_errors.set(max_errors.map(|_| left.max(1)));
+    let loaded = match forms {
+        Some(forms) => it.load_compiled(forms),
+        None => it.load_source(program, name),
+    };
+    it.max_errors.set(max_errors);
+    let errors = match loaded {
+        Ok(Some(ValueType::Void)) | Ok(None) => return 0,
+        Ok(Some(value)) => {
+            if print_result {
+                println!("{}", value);
+            }
+            return 0;
+        }
+        Err(errors) => errors,
+    };
+    let mut status = ERROR_STATUS;
+    // the backtrace is of the error which stopped the program, the last, if it was shown
+    let mut shown_last = false;
+    for (e, span) in &errors {
+        shown_last = e.exit_code().is_none() && left > 0;
+        match e.exit_code() {
+            Some(code) => status = code,
+            None if shown_last => {
+                report(&Diagnostic::from_error(e, *span), program, name, json);
+                left -= 1;
+            }
+            None => (),
+        }
+    }
+    let backtrace = it.backtrace();
+    if shown_last && !backtrace.is_empty() && !json {
+        let stderr = io::stderr();
+        let color = diagnostic::use_color(&stderr);
+        let _ = diagnostic::render_backtrace(&mut stderr.lock(), &backtrace, program, name, color);
+    }
+    status
+}
+
+// programs run from the command line may do what the user running them may
+fn granted(it: Interpreter) -> Interpreter {
+    it.allow_subprocesses.set(true);
+    it.allow_network.set(true);
+    it
+}
+
+// an interpreter for a program, with the settings of the command line, none if its image
+// cannot be loaded
+fn configured(
+    max_errors: Option<usize>,
+    engine: Engine,
+    optimization_level: u8,
+    image: Option<&str>,
+) -> Option<Interpreter> {
+    let it = granted(Interpreter::new());
+    it.max_errors.set(max_errors);
+    it.engine.set(engine);
+    it.optimization_level.set(optimization_level);
+    if let Err(e) = image.map_or(Ok(()), |image| it.load_image(image)) {
+        eprintln!("{}", e);
+        return None;
+    }
+    Some(it)
+}
+
+// the line of an executable script naming its interpreter, which is not scheme code
+// the line break stays for the lines after it to keep their numbers
+fn strip_shebang(program: &str) -> &str {
+    match program.starts_with("#!") {
+        true => &program[program.find('\n').unwrap_or(program.len())..],
+        false => program,
+    }
+}
+
+fn read_stdin() -> io::Result<String> {
+    let mut program = String::new();
+    io::stdin().read_to_string(&mut program)?;
+    Ok(program)
+}
+
+// the command line a script sees, its path then the arguments after it, the interpreter's
+// options left out
+fn script_command_line(path: &str, arguments: &[&str]) -> Vec<String> {
+    std::iter::once(path)
+        .chain(arguments.iter().copied())
+        .map(String::from)
+        .collect()
+}
+
+// the program is read from the standard input when the path is -
+fn run_script(path: &str, arguments: &[&str], json: bool, it: Interpreter) -> i32 {
+    if path.ends_with(".rbc") {
+        return run_compiled(path, arguments, json, it);
+    }
+    let (program, name) = match path {
+        "-" => (read_stdin(), "<stdin>"),
+        _ => (fs::read_to_string(path), path),
+    };
+    let program = match program {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", file_error("read", name, e));
+            return ERROR_STATUS;
+        }
+    };
+    // the script sees itself as the command
+    it.command_line
+        .replace(script_command_line(path, arguments));
+    run(&it, strip_shebang(&program), Some(name), None, false, json)
+}
+
+// the compiled program runs the source instead when the file it was compiled from changed
+// since, or when it was compiled by another version
+fn run_compiled(path: &str, arguments: &[&str], json: bool, it: Interpreter) -> i32 {
+    let program = match fs::read(path) {
+        Ok(bytes) => bytecode::read(&bytes),
+        Err(e) => {
+            eprintln!("{}", file_error("read", path, e));
+            return ERROR_STATUS;
+        }
+    };
+    let program = match program {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("ruschm: {}: {}", path, e.message);
+            return ERROR_STATUS;
+        }
+    };
+    let name = program.name.as_deref();
+    let changed = name
+        .and_then(|name| fs::read_to_string(name).ok())
+        .filter(|source| bytecode::hash(strip_shebang(source)) != program.hash);
+    it.command_line
+        .replace(script_command_line(path, arguments));
+    match (changed, &program.forms) {
+        (Some(source), _) => run(&it, strip_shebang(&source), name, None, false, json),
+        (None, forms) => run(&it, &program.source, name, forms.as_ref(), false, json),
+    }
+}
+
+// the paths of the file a command makes something of, and of what it makes, which is the file
+// with the extension unless given
+fn paths<'a>(command: &str, args: &[&'a str], extension: &str) -> Option<(&'a str, PathBuf)> {
+    let (path, output) = match *args {
+        [path] => (path, Path::new(path).with_extension(extension)),
+        [path, "-o", output] | ["-o", output, path] => (path, PathBuf::from(output)),
+        _ => {
+            eprintln!("ruschm: {} expects a file\n{}", command, USAGE);
+            return None;
+        }
+    };
+    if output == Path::new(path) {
+        eprintln!("ruschm: {} needs -o for {}", command, path);
+        return None;
+    }
+    Some((path, output))
+}
+
+// the program in the file if it can be read, as it is not built nor compiled otherwise
+fn readable(path: &str) -> Option<String> {
+    let program = match fs::read_to_string(path) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", file_error("read", path, e));
+            return None;
+        }
+    };
+    match read_source(strip_shebang(&program), Some(path), |_, _| Ok(())) {
+        Ok(()) => Some(program),
+        Err((e, span)) => {
+            report(
+                &Diagnostic::from_error(&e, span),
+                strip_shebang(&program),
+                Some(path),
+                false,
+            );
+            None
+        }
+    }
+}
+
+// the program in the file compiled to bytecode, none if it cannot be read or compiled
+fn compiled(path: &str) -> Option<Vec<u8>> {
+    let program = readable(path)?;
+    let program = strip_shebang(&program);
+    match bytecode::compile(&Interpreter::new(), program, Some(path)) {
+        Ok(compiled) => Some(compiled),
+        Err((e, span)) => {
+            report(
+                &Diagnostic::from_error(&e, span),
+                program,
+                Some(path),
+                false,
+            );
+            None
+        }
+    }
+}
+
+// writes the program in the file compiled to bytecode
+fn compile(args: &[&str]) -> i32 {
+    let (path, output) = match paths("compile", args, "rbc") {
+        Some(paths) => paths,
+        None => return USAGE_STATUS,
+    };
+    let compiled = match compiled(path) {
+        Some(compiled) => compiled,
+        None => return ERROR_STATUS,
+    };
+    match fs::write(&output, compiled) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", file_error("write", &output.to_string_lossy(), e));
+            ERROR_STATUS
+        }
+    }
+}
+
+// bundles the program in the file, compiled to bytecode, with the interpreter into an
+// executable running it
+fn build(args: &[&str]) -> i32 {
+    let (path, output) = match paths("build", args, "") {
+        Some(paths) => paths,
+        None => return USAGE_STATUS,
+    };
+    let program = match compiled(path) {
+        Some(program) => program,
+        None => return ERROR_STATUS,
+    };
+    let runtime = match env::current_exe().and_then(|runtime| {
+        let permissions = fs::metadata(&runtime)?.permissions();
+        Ok((fs::read(&runtime)?, permissions))
+    }) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("ruschm: cannot read the interpreter: {}", e);
+            return ERROR_STATUS;
+        }
+    };
+    let written = fs::write(&output, bundle::bundle(&runtime.0, &program))
+        .and_then(|_| fs::set_permissions(&output, runtime.1));
+    match written {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", file_error("write", &output.to_string_lossy(), e));
+            ERROR_STATUS
+        }
+    }
+}
+
+// the compiled program an executable made by build runs, none for the interpreter itself
+fn bundled_program() -> Option<Vec<u8>> {
+    let mut executable = fs::File::open(env::current_exe().ok()?).ok()?;
+    bundle::bundled(&mut executable).ok()?
+}
+
+// serves the repl at the address, e.g. 127.0.0.1:7000, to clients giving the token if any
+fn listen(address: &str, token: Option<&str>, init: bool) -> i32 {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("ruschm: cannot listen on {}: {}", address, e);
+            return ERROR_STATUS;
+        }
+    };
+    if let Ok(address) = listener.local_addr() {
+        eprintln!("ruschm: listening on {}", address);
+    }
+    let mut session = repl::Session::remote(granted(Interpreter::new()), init);
+    match repl::serve(listener, token, &mut session) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("ruschm: {}", e);
+            ERROR_STATUS
+        }
+    }
+}
+
+// the exit status of the command line
+fn start() -> i32 {
+    set_thread_stack_size(MAX_CALL_DEPTH_STACK);
+    let args: Vec<String> = env::args().collect();
+    // every argument of a built executable is the program's
+    if let Some(program) = bundled_program() {
+        let program = match bytecode::read(&program) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("ruschm: {}: {}", args[0], e.message);
+                return ERROR_STATUS;
+            }
+        };
+        let it = granted(Interpreter::new());
+        it.command_line.replace(args.clone());
+        return run(
+            &it,
+            &program.source,
+            Some(&args[0]),
+            program.forms.as_ref(),
+            false,
+            false,
+        );
+    }
+    let command: Vec<&str> = args.iter().skip(2).map(String::as_str).collect();
+    match args.get(1).map(String::as_str) {
+        Some("build") => return build(&command),
+        Some("compile") => return compile(&command),
+        _ => (),
+    }
+    let mut init = true;
+    let mut json = false;
+    let mut max_errors = None;
+    let mut engine = Engine::Bytecode;
+    let mut optimization_level = 1;
+    let mut image = None;
+    let mut address = None;
+    let mut token = None;
+    let mut rest = args.iter().skip(1).map(String::as_str);
+    let status = loop {
+        break match rest.next() {
+            Some(flag @ "-e") | Some(flag @ "--eval") => match rest.next() {
+                Some(expression) => configured(max_errors, engine, optimization_level, image)
+                    .map_or(ERROR_STATUS, |it| {
+                        run(&it, expression, None, None, true, json)
+                    }),
+                None => {
+                    eprintln!("ruschm: {} expects an expression\n{}", flag, USAGE);
+                    USAGE_STATUS
+                }
+            },
+            Some("--no-init") => {
+                init = false;
+                continue;
+            }
+            Some(flag) if flag.starts_with("--diagnostics=") => {
+                match &flag["--diagnostics=".len()..] {
+                    "text" => json = false,
+                    "json" => json = true,
+                    format => {
+                        eprintln!("ruschm: unknown diagnostics format {}\n{}", format, USAGE);
+                        break USAGE_STATUS;
+                    }
+                }
+                continue;
+            }
+            Some("--max-errors") => match rest.next().map(str::parse) {
+                Some(Ok(count)) if count > 0 => {
+                    max_errors = Some(count);
+                    continue;
+                }
+                _ => {
+                    eprintln!("ruschm: --max-errors expects a positive number\n{}", USAGE);
+                    USAGE_STATUS
+                }
+            },
+            Some(flag) if flag.starts_with("--engine=") => {
+                match &flag["--engine=".len()..] {
+                    "bytecode" => engine = Engine::Bytecode,
+                    "tree" => engine = Engine::TreeWalker,
+                    name => {
+                        eprintln!("ruschm: unknown engine {}\n{}", name, USAGE);
+                        break USAGE_STATUS;
+                    }
+                }
+                continue;
+            }
+            Some(flag) if flag.starts_with("--optimize=") => {
+                match flag["--optimize=".len()..].parse() {
+                    Ok(level) if level <= MAX_OPTIMIZATION_LEVEL => optimization_level = level,
+                    _ => {
+                        eprintln!("ruschm: unknown optimization level in {}\n{}", flag, USAGE);
+                        break USAGE_STATUS;
+                    }
+                }
+                continue;
+            }
+            Some(flag @ "--listen") | Some(flag @ "--token") | Some(flag @ "--image") => {
+                match rest.next() {
+                    Some(value) => {
+                        match flag {
+                            "--listen" => address = Some(value),
+                            "--image" => image = Some(value),
+                            _ => token = Some(value),
+                        }
+                        continue;
+                    }
+                    None => {
+                        eprintln!("ruschm: {} expects a value\n{}", flag, USAGE);
+                        USAGE_STATUS
+                    }
+                }
+            }
+            // jupyter starts the kernel with the file naming its ports and key
+            Some("--kernel") => match rest.next() {
+                Some(connection_file) => match kernel::run(connection_file) {
+                    Ok(code) => code,
+                    Err(e) => {
+                        eprintln!("ruschm: kernel: {}", e);
+                        ERROR_STATUS
+                    }
+                },
+                None => {
+                    eprintln!("ruschm: --kernel expects a connection file\n{}", USAGE);
+                    USAGE_STATUS
+                }
+            },
+            Some("-h") | Some("--help") => {
+                println!("{}", USAGE);
+                0
+            }
+            Some(flag) if flag.starts_with('-') && flag != "-" => {
+                eprintln!("ruschm: unknown option {}\n{}", flag, USAGE);
+                USAGE_STATUS
+            }
+            Some(_) if address.is_some() => {
+                eprintln!("ruschm: --listen takes no program\n{}", USAGE);
+                USAGE_STATUS
+            }
+            Some(file) => {
+                let arguments: Vec<&str> = rest.by_ref().collect();
+                configured(max_errors, engine, optimization_level, image)
+                    .map_or(ERROR_STATUS, |it| run_script(file, &arguments, json, it))
+            }
+            None if address.is_some() => listen(address.unwrap(), token, init),
+            None if token.is_some() => {
+                eprintln!("ruschm: --token is for --listen\n{}", USAGE);
+                USAGE_STATUS
+            }
+            // a program piped in is run rather than read as lines of a session
+            None if !io::stdin().is_terminal() => {
+                configured(max_errors, engine, optimization_level, image)
+                    .map_or(ERROR_STATUS, |it| run_script("-", &[], json, it))
+            }
+            None => match repl::run(init, image) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ERROR_STATUS
+                }
+            },
+        };
+    };
+    status
+}
+
+// programs run on a stack deep enough for the calls the interpreter allows
+fn main() {
+    let status = thread::Builder::new()
+        .stack_size(MAX_CALL_DEPTH_STACK)
+        .spawn(start)
+        .expect("cannot start the interpreter thread")
+        .join()
+        .unwrap_or(ERROR_STATUS);
+    process::exit(status)
 }
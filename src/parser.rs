@@ -1,15 +1,24 @@
 #![allow(dead_code)]
 use crate::error::*;
-use crate::lexer::Token;
+use crate::lexer::{
+    escape_character, escape_string, escape_symbol, generated_name, intern, Name, Token,
+    TokenGenerator,
+};
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fmt;
 use std::iter::{FromIterator, Iterator, Peekable};
+use std::rc::Rc;
 
 type Result<T> = std::result::Result<T, Error>;
 pub type ParseResult = Result<Option<Statement>>;
 
 macro_rules! syntax_error {
-    ($($arg:tt)*) => (
-        return Err(Error {category: ErrorType::Syntax, message: format!($($arg)*) });
+    ($kind:expr) => (
+        return Err(Error::from($kind))
+    );
+    ($kind:expr, $($arg:tt)*) => (
+        return Err(Error {category: ErrorType::Syntax($kind), message: format!($($arg)*) })
     )
 }
 
@@ -37,6 +46,8 @@ pub enum Statement {
     ImportDeclaration(Vec<ImportSet>),
     Definition(Definition),
     Expression(Expression),
+    // the statements of a clause chosen by cond-expand, spliced where it appears
+    Sequence(Vec<Statement>),
 }
 
 impl fmt::Display for Statement {
@@ -53,16 +64,186 @@ impl fmt::Display for Statement {
             ),
             Statement::Definition(def) => write!(f, "{}", def),
             Statement::Expression(expr) => write!(f, "{}", expr),
+            Statement::Sequence(statements) => {
+                write!(f, "(begin {})", join_displayable(statements))
+            }
         }
     }
 }
 
+// the keywords of the forms parsed specially rather than as procedure calls
+pub const KEYWORDS: &[&str] = &[
+    "cond-expand",
+    "cut",
+    "cute",
+    "define",
+    "define-stream",
+    "delay",
+    "delay-force",
+    "guard",
+    "if",
+    "import",
+    "lambda",
+    "let-values",
+    "match",
+    "quote",
+    "receive",
+    "stream-cons",
+    "stream-lambda",
+    "time",
+];
+
+// libraries which can be imported, as named in (library ...) requirements
+const LIBRARIES: &[&str] = &[
+    "(scheme base)",
+    "(scheme char)",
+    "(scheme cxr)",
+    "(scheme file)",
+    "(scheme inexact)",
+    "(scheme lazy)",
+    "(scheme process-context)",
+    "(scheme read)",
+    "(scheme time)",
+    "(scheme write)",
+    "(srfi 1)",
+    "(srfi 19)",
+    "(srfi 26)",
+    "(srfi 27)",
+    "(srfi 28)",
+    "(srfi 41)",
+    "(srfi 69)",
+    "(srfi 113)",
+    "(srfi 125)",
+    "(srfi 128)",
+    "(srfi 132)",
+    "(srfi 151)",
+    "(srfi 158)",
+    "(ruschm digest)",
+    "(ruschm display)",
+    "(ruschm encoding)",
+    "(ruschm http)",
+    "(ruschm image)",
+    "(ruschm json)",
+    "(ruschm log)",
+    "(ruschm memoize)",
+    "(ruschm parallel)",
+    "(ruschm path)",
+    "(ruschm process)",
+    "(ruschm regexp)",
+    "(ruschm socket)",
+    "(ruschm statistics)",
+];
+
+// the feature identifiers cond-expand tests and the features procedure returns
+pub fn features() -> Vec<String> {
+    let mut features = vec![
+        "r7rs",
+        "exact-closed",
+        "ratios",
+        "full-unicode",
+        "ruschm",
+        concat!("ruschm-", env!("CARGO_PKG_VERSION")),
+        "srfi-1",
+        "srfi-8",
+        "srfi-19",
+        "srfi-26",
+        "srfi-27",
+        "srfi-28",
+        "srfi-41",
+        "srfi-69",
+        "srfi-113",
+        "srfi-125",
+        "srfi-128",
+        "srfi-132",
+        "srfi-151",
+        "srfi-158",
+    ];
+    features.push(match std::env::consts::FAMILY {
+        "windows" => "windows",
+        _ => "unix",
+    });
+    if std::env::consts::FAMILY == "unix" {
+        features.push("posix");
+    }
+    features.extend(match std::env::consts::OS {
+        "linux" => Some("gnu-linux"),
+        "macos" => Some("darwin"),
+        "freebsd" | "openbsd" | "netbsd" => Some("bsd"),
+        _ => None,
+    });
+    features.push(match std::env::consts::ARCH {
+        "x86" => "i386",
+        "x86_64" => "x86-64",
+        "powerpc" | "powerpc64" => "ppc",
+        "sparc" | "sparc64" => "sparc",
+        arch => arch,
+    });
+    features.push(if cfg!(target_pointer_width = "64") {
+        "lp64"
+    } else {
+        "ilp32"
+    });
+    features.push(if cfg!(target_endian = "big") {
+        "big-endian"
+    } else {
+        "little-endian"
+    });
+    features.into_iter().map(String::from).collect()
+}
+
+fn feature_requirement(requirement: &Datum) -> Result<bool> {
+    Ok(match requirement {
+        Datum::Symbol(feature) => features().iter().any(|name| **name == **feature),
+        Datum::List(list) => match list.split_first() {
+            Some((Datum::Symbol(keyword), requirements)) => {
+                match (keyword.as_ref(), requirements) {
+                    ("and", _) => {
+                        for requirement in requirements {
+                            if !feature_requirement(requirement)? {
+                                return Ok(false);
+                            }
+                        }
+                        true
+                    }
+                    ("or", _) => {
+                        for requirement in requirements {
+                            if feature_requirement(requirement)? {
+                                return Ok(true);
+                            }
+                        }
+                        false
+                    }
+                    ("not", [requirement]) => !feature_requirement(requirement)?,
+                    ("library", [name @ Datum::List(_)]) => {
+                        LIBRARIES.contains(&format!("{}", name).as_str())
+                    }
+                    _ => syntax_error!(
+                        SyntaxError::InvalidForm("cond-expand".to_string()),
+                        "cond-expand: invalid feature requirement {}",
+                        requirement
+                    ),
+                }
+            }
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("cond-expand".to_string()),
+                "cond-expand: invalid feature requirement {}",
+                requirement
+            ),
+        },
+        _ => syntax_error!(
+            SyntaxError::InvalidForm("cond-expand".to_string()),
+            "cond-expand: invalid feature requirement {}",
+            requirement
+        ),
+    })
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct Definition(pub String, pub Expression);
 
 impl fmt::Display for Definition {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "(define {} {})", self.0, self.1)
+        write!(f, "(define {} {})", escape_symbol(&self.0), self.1)
     }
 }
 
@@ -75,6 +256,19 @@ pub enum ImportSet {
     Rename(Box<ImportSet>, Vec<(String, String)>),
 }
 
+impl ImportSet {
+    // the name of the library the set imports from
+    pub fn library(&self) -> &str {
+        match self {
+            Self::Direct(name) => name,
+            Self::Only(set, _)
+            | Self::Except(set, _)
+            | Self::Prefix(set, _)
+            | Self::Rename(set, _) => set.library(),
+        }
+    }
+}
+
 impl fmt::Display for ImportSet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -98,62 +292,296 @@ impl fmt::Display for ImportSet {
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Expression {
-    Identifier(String),
+    Identifier(Name),
     Integer(i64),
     Boolean(bool),
-    Real(String),
+    Real(Name),
     Rational(i64, u64),
+    Character(char),
+    String(String),
     Vector(Vec<Expression>),
     Procedure(SchemeProcedure),
     ProcedureCall(Box<Expression>, Vec<Expression>),
+    // a call read from a source, with the span of the call expression for backtraces
+    LocatedCall(Box<(Expression, Vec<Expression>, Span)>),
     Conditional(Box<(Expression, Expression, Option<Expression>)>),
-    Datum(Box<Statement>),
+    // the variable bound to the raised object, the clauses and the body called as a thunk
+    Guard(Box<(String, Vec<GuardClause>, Expression)>),
+    // promises of the thunk's value, delay-force promises take over the promise the thunk returns
+    Delay(Box<Expression>),
+    DelayForce(Box<Expression>),
+    // the thunks of the stream's car and of the stream of its cdr
+    StreamCons(Box<(Expression, Expression)>),
+    // the value matched and the clauses tried in order
+    Match(Box<(Expression, Vec<MatchClause>)>),
+    Datum(Box<Datum>),
+    // a builtin a derived form calls, whatever the program binds to its names
+    Primitive(Primitive),
+}
+
+// the builtins of derived forms, referred to by the form rather than looked up
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Primitive {
+    // calls a thunk and reports the time it took
+    Time,
+    // calls the consumer with the values of the producer
+    CallWithValues,
+    // calls the consumer with as many values of the producer as given and a list of the others
+    CallWithRestValues,
+}
+
+impl Primitive {
+    pub const ALL: [Primitive; 3] = [
+        Primitive::Time,
+        Primitive::CallWithValues,
+        Primitive::CallWithRestValues,
+    ];
+
+    pub fn index(self) -> u32 {
+        Primitive::ALL.iter().position(|&p| p == self).unwrap() as u32
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Primitive::Time => "time",
+            Primitive::CallWithValues => "call-with-values",
+            Primitive::CallWithRestValues => "call-with-rest-values",
+        }
+    }
 }
 
 // external representation, code as data
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Expression::Identifier(s) => write!(f, "{}", s),
+            Expression::Identifier(s) => write!(f, "{}", escape_symbol(s)),
             Expression::Integer(n) => write!(f, "{}", n),
             Expression::Real(n) => write!(f, "{:?}", n),
             Expression::Rational(a, b) => write!(f, "{}/{}", a, b),
-            Expression::Vector(vector) => write!(f, "({})", join_displayable(vector)),
+            Expression::Character(c) => write!(f, "{}", escape_character(*c)),
+            Expression::String(s) => write!(f, "{}", escape_string(s)),
+            Expression::Vector(vector) => write!(f, "#({})", join_displayable(vector)),
             Expression::Procedure(p) => write!(f, "{}", p),
+            Expression::ProcedureCall(op, args) if args.is_empty() => write!(f, "({})", op),
             Expression::ProcedureCall(op, args) => write!(f, "({} {})", op, join_displayable(args)),
+            Expression::LocatedCall(call) if call.1.is_empty() => write!(f, "({})", call.0),
+            Expression::LocatedCall(call) => {
+                write!(f, "({} {})", call.0, join_displayable(&call.1))
+            }
             Expression::Conditional(cond) => {
                 let (test, consequent, alternative) = &cond.as_ref();
                 match alternative {
-                    Some(alt) => write!(f, "({} {}{})", test, consequent, alt),
-                    None => write!(f, "({} {})", test, consequent),
+                    Some(alt) => write!(f, "(if {} {} {})", test, consequent, alt),
+                    None => write!(f, "(if {} {})", test, consequent),
                 }
             }
-            Expression::Datum(datum) => write!(f, "{}", datum),
+            Expression::Guard(guard) => {
+                let (variable, clauses, body) = guard.as_ref();
+                write!(
+                    f,
+                    "(guard ({} {}) {})",
+                    variable,
+                    join_displayable(clauses),
+                    body
+                )
+            }
+            Expression::Delay(thunk) => write!(f, "(delay {})", thunk),
+            Expression::DelayForce(thunk) => write!(f, "(delay-force {})", thunk),
+            Expression::StreamCons(stream) => write!(f, "(stream-cons {} {})", stream.0, stream.1),
+            Expression::Match(matching) => write!(
+                f,
+                "(match {} {})",
+                matching.0,
+                join_displayable(&matching.1)
+            ),
+            Expression::Datum(datum) => write!(f, "(quote {})", datum),
+            Expression::Primitive(primitive) => write!(f, "#<primitive {}>", primitive.name()),
             Expression::Boolean(true) => write!(f, "#t"),
             Expression::Boolean(false) => write!(f, "#f"),
         }
     }
 }
 
+// a guard clause without a test is the else clause
 #[derive(PartialEq, Debug, Clone)]
-pub struct SchemeProcedure(pub Vec<String>, pub Vec<Definition>, pub Vec<Expression>);
+pub struct GuardClause(pub Option<Expression>, pub Vec<Expression>);
+
+impl fmt::Display for GuardClause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Some(test) if self.1.is_empty() => write!(f, "({})", test),
+            Some(test) => write!(f, "({} {})", test, join_displayable(&self.1)),
+            None => write!(f, "(else {})", join_displayable(&self.1)),
+        }
+    }
+}
+
+// the body is a procedure of the pattern's variables, in the order they appear
+#[derive(PartialEq, Debug, Clone)]
+pub struct MatchClause(pub Pattern, pub Expression);
+
+impl fmt::Display for MatchClause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.1 {
+            Expression::Procedure(SchemeProcedure(_, definitions, expressions, _)) => write!(
+                f,
+                "({} {} {})",
+                self.0,
+                join_displayable(definitions),
+                join_displayable(expressions)
+            ),
+            body => write!(f, "({} {})", self.0, body),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Pattern {
+    Wildcard,
+    Variable(String),
+    // literals and quoted data, matched with equal?
+    Datum(Datum),
+    // the tail matches what follows the elements, which is the empty list for proper lists
+    List(SequencePattern, Box<Pattern>),
+    Vector(SequencePattern),
+    // (? predicate pattern ...) matches values satisfying the predicate and every pattern
+    Predicate(Expression, Vec<Pattern>),
+}
+
+// the element patterns of a list or vector and the index of the one followed by ...,
+// which matches any number of elements and binds its variables to lists
+#[derive(PartialEq, Debug, Clone)]
+pub struct SequencePattern(pub Vec<Pattern>, pub Option<usize>);
+
+impl Pattern {
+    pub fn variables(&self) -> Vec<String> {
+        match self {
+            Pattern::Variable(variable) => vec![variable.clone()],
+            Pattern::List(SequencePattern(elements, _), tail) => elements
+                .iter()
+                .chain(std::iter::once(tail.as_ref()))
+                .flat_map(Pattern::variables)
+                .collect(),
+            Pattern::Vector(SequencePattern(elements, _)) | Pattern::Predicate(_, elements) => {
+                elements.iter().flat_map(Pattern::variables).collect()
+            }
+            Pattern::Wildcard | Pattern::Datum(_) => vec![],
+        }
+    }
+}
+
+impl fmt::Display for SequencePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let SequencePattern(elements, ellipsis) = self;
+        let mut written = elements
+            .iter()
+            .map(|e| format!("{}", e))
+            .collect::<Vec<_>>();
+        if let Some(index) = ellipsis {
+            written.insert(index + 1, "...".to_string());
+        }
+        write!(f, "{}", written.join(" "))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Variable(variable) => write!(f, "{}", variable),
+            Pattern::Datum(datum @ Datum::Symbol(_))
+            | Pattern::Datum(datum @ Datum::List(_))
+            | Pattern::Datum(datum @ Datum::Pair(..)) => write!(f, "'{}", datum),
+            Pattern::Datum(datum) => write!(f, "{}", datum),
+            Pattern::List(elements, tail) => match tail.as_ref() {
+                Pattern::Datum(Datum::List(empty)) if empty.is_empty() => {
+                    write!(f, "({})", elements)
+                }
+                tail => write!(f, "({} . {})", elements, tail),
+            },
+            Pattern::Vector(elements) => write!(f, "#({})", elements),
+            Pattern::Predicate(predicate, patterns) if patterns.is_empty() => {
+                write!(f, "(? {})", predicate)
+            }
+            Pattern::Predicate(predicate, patterns) => {
+                write!(f, "(? {} {})", predicate, join_displayable(patterns))
+            }
+        }
+    }
+}
+
+// quoted data, which is not evaluated
+#[derive(PartialEq, Debug, Clone)]
+pub enum Datum {
+    Boolean(bool),
+    Integer(i64),
+    Real(Name),
+    Rational(i64, u64),
+    Character(char),
+    String(String),
+    Symbol(Name),
+    List(Vec<Datum>),
+    // an improper list, and the last element is the tail
+    Pair(Vec<Datum>, Box<Datum>),
+    Vector(Vec<Datum>),
+    Bytevector(Vec<u8>),
+}
+
+impl fmt::Display for Datum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Datum::Boolean(true) => write!(f, "#t"),
+            Datum::Boolean(false) => write!(f, "#f"),
+            Datum::Integer(n) => write!(f, "{}", n),
+            Datum::Real(n) => write!(f, "{}", n),
+            Datum::Rational(a, b) => write!(f, "{}/{}", a, b),
+            Datum::Character(c) => write!(f, "{}", escape_character(*c)),
+            Datum::String(s) => write!(f, "{}", escape_string(s)),
+            Datum::Symbol(s) => write!(f, "{}", escape_symbol(s)),
+            Datum::List(list) => write!(f, "({})", join_displayable(list)),
+            Datum::Pair(list, tail) => write!(f, "({} . {})", join_displayable(list), tail),
+            Datum::Vector(vector) => write!(f, "#({})", join_displayable(vector)),
+            Datum::Bytevector(bytes) => write!(f, "#u8({})", join_displayable(bytes)),
+        }
+    }
+}
+
+// the formals, definitions and expressions of a procedure, and where it was written if read
+// from a located source
+#[derive(PartialEq, Debug, Clone)]
+pub struct SchemeProcedure(
+    pub Vec<String>,
+    pub Vec<Definition>,
+    pub Vec<Expression>,
+    pub Option<Rc<Origin>>,
+);
 
 impl fmt::Display for SchemeProcedure {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let SchemeProcedure(formals, definitions, expressions) = self;
-        write!(
-            f,
-            "(lambda ({}) {} {})",
-            formals.join(" "),
-            join_displayable(definitions),
-            join_displayable(expressions)
-        )
+        let SchemeProcedure(formals, definitions, expressions, _) = self;
+        let formals = formals.iter().map(|formal| escape_symbol(formal));
+        write!(f, "(lambda ({})", join_displayable(formals))?;
+        for definition in definitions {
+            write!(f, " {}", definition)?;
+        }
+        for expression in expressions {
+            write!(f, " {}", expression)?;
+        }
+        write!(f, ")")
     }
 }
 
 pub struct Parser<TokenIter: Iterator<Item = Token>> {
     pub current: Option<Token>,
     pub lexer: Peekable<TokenIter>,
+    // the spans of the tokens lexed and not taken yet, for a parser locating calls
+    spans: Option<Rc<RefCell<VecDeque<Span>>>>,
+    // the lines of the source the tokens are read from, for procedures to know their origin
+    lines: Option<Rc<SourceLines>>,
+    // the span of the current token
+    span: Span,
+    // the spans of the names defined by the definitions read and not yet taken by a body
+    defined: Vec<Span>,
 }
 
 impl FromIterator<Token> for ParseResult {
@@ -179,6 +607,24 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
         Self {
             current: None,
             lexer: lexer.peekable(),
+            spans: None,
+            lines: None,
+            span: Span { start: 0, end: 0 },
+            defined: vec![],
+        }
+    }
+
+    // a parser making located calls and procedures, the lexer pushing the span of each token it
+    // yields
+    pub fn located(
+        lexer: TokenIter,
+        spans: Rc<RefCell<VecDeque<Span>>>,
+        lines: Rc<SourceLines>,
+    ) -> Parser<TokenIter> {
+        Self {
+            spans: Some(spans),
+            lines: Some(lines),
+            ..Self::new(lexer)
         }
     }
 
@@ -190,26 +636,54 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
                 Token::Real(a) => Ok(expr_to_statement!(Expression::Real(a))),
                 Token::Rational(a, b) => Ok(expr_to_statement!(Expression::Rational(a, b))),
                 Token::Identifier(a) => Ok(expr_to_statement!(Expression::Identifier(a))),
+                Token::Character(a) => Ok(expr_to_statement!(Expression::Character(a))),
+                Token::String(a) => Ok(expr_to_statement!(Expression::String(a))),
                 Token::LeftParen => match self.lexer.peek() {
-                    Some(Token::Identifier(ident)) => match ident.as_str() {
+                    Some(Token::Identifier(ident)) => match ident.as_ref() {
                         "lambda" => Ok(expr_to_statement!(self.lambda()?)),
+                        "quote" => Ok(expr_to_statement!(self.quotation()?)),
                         "define" => Ok(def_to_statement!(self.definition()?)),
                         "import" => Ok(Some(self.import_declaration()?)),
                         "if" => Ok(expr_to_statement!(self.condition()?)),
+                        "guard" => Ok(expr_to_statement!(self.guard()?)),
+                        "cond-expand" => Ok(Some(self.cond_expand()?)),
+                        "delay" => Ok(expr_to_statement!(Expression::Delay(Box::new(
+                            self.delayed("delay")?
+                        )))),
+                        "delay-force" => Ok(expr_to_statement!(Expression::DelayForce(Box::new(
+                            self.delayed("delay-force")?
+                        )))),
+                        "stream-cons" => Ok(expr_to_statement!(self.stream_cons()?)),
+                        "stream-lambda" => Ok(expr_to_statement!(stream_procedure(self.lambda()?))),
+                        "define-stream" => Ok(def_to_statement!(self.stream_definition()?)),
+                        "cut" => Ok(expr_to_statement!(self.cut(false)?)),
+                        "match" => Ok(expr_to_statement!(self.matching()?)),
+                        "cute" => Ok(expr_to_statement!(self.cut(true)?)),
+                        "time" => Ok(expr_to_statement!(self.timed()?)),
+                        "receive" => Ok(expr_to_statement!(self.receive()?)),
+                        "let-values" => Ok(expr_to_statement!(self.let_values()?)),
                         _ => Ok(expr_to_statement!(self.procedure_call()?)),
                     },
-                    Some(Token::RightParen) => syntax_error!("empty procedure call"),
+                    Some(Token::RightParen) => {
+                        syntax_error!(SyntaxError::EmptyCall)
+                    }
                     _ => Ok(expr_to_statement!(self.procedure_call()?)),
                 },
-                Token::RightParen => syntax_error!("Unmatched Parentheses!"),
+                Token::RightParen => {
+                    syntax_error!(SyntaxError::UnmatchedParenthesis)
+                }
                 Token::VecConsIntro => Ok(expr_to_statement!(self.vector()?)),
-                Token::Quote => Ok(expr_to_statement!(Expression::Datum(Box::new(match self
-                    .parse()?
-                {
-                    Some(statement) => statement,
-                    None => syntax_error!("expect something to be quoted!"),
-                },)))),
-                _ => syntax_error!("unsupported grammar"),
+                // bytevectors are self-evaluating
+                Token::ByteVecConsIntro => Ok(expr_to_statement!(Expression::Datum(Box::new(
+                    self.bytevector()?
+                )))),
+                Token::Quote => {
+                    self.advance(1);
+                    Ok(expr_to_statement!(Expression::Datum(Box::new(
+                        self.datum()?
+                    ))))
+                }
+                other => syntax_error!(self.unexpected(Expected::Expression, Some(other))),
             },
             None => Ok(None),
         }
@@ -218,7 +692,7 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
     pub fn parse_current_expression(&mut self) -> Result<Expression> {
         match self.parse_current()? {
             Some(Statement::Expression(expr)) => Ok(expr),
-            _ => syntax_error!("expect a expression here"),
+            _ => syntax_error!(SyntaxError::NotAnExpression),
         }
     }
 
@@ -227,31 +701,48 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
         self.parse_current()
     }
 
+    // a single datum as read by the read procedure, None at the end of input
+    pub fn parse_datum(&mut self) -> Result<Option<Datum>> {
+        match self.advance(1) {
+            Some(_) => Ok(Some(self.datum()?)),
+            None => Ok(None),
+        }
+    }
+
     // we know it will never be RightParen
     fn get_identifier(&mut self) -> Result<String> {
         match self.current.take() {
-            Some(Token::Identifier(ident)) => Ok(ident.clone()),
-            _ => syntax_error!("expect an identifier"),
+            Some(Token::Identifier(ident)) => Ok(ident.to_string()),
+            other => syntax_error!(self.unexpected(Expected::Identifier, other)),
+        }
+    }
+
+    // an identifier with its span
+    fn get_formal(&mut self) -> Result<(String, Span)> {
+        let span = self.span;
+        self.get_identifier().map(|identifier| (identifier, span))
+    }
+
+    fn get_library_name_part(&mut self) -> Result<String> {
+        match self.current.take() {
+            Some(Token::Identifier(ident)) => Ok(ident.to_string()),
+            Some(Token::Integer(n)) if n >= 0 => Ok(n.to_string()),
+            other => syntax_error!(self.unexpected(Expected::IdentifierOrExactInteger, other)),
         }
     }
 
     fn get_identifier_pair(&mut self) -> Result<(String, String)> {
-        match (
-            self.current.take(),
-            self.advance(1).take(),
-            self.advance(1).take(),
-            self.advance(1).take(),
-        ) {
-            (
-                Some(Token::LeftParen),
-                Some(Token::Identifier(ident1)),
-                Some(Token::Identifier(ident2)),
-                Some(Token::RightParen),
-            ) => Ok((ident1, ident2)),
-            other => syntax_error!(
-                "expect an identifier pair: (ident1, ident2), got {:?}",
-                other
-            ),
+        match self.current.take() {
+            Some(Token::LeftParen) => (),
+            other => syntax_error!(self.unexpected(Expected::IdentifierPair, other)),
+        }
+        self.advance(1);
+        let first = self.get_identifier()?;
+        self.advance(1);
+        let second = self.get_identifier()?;
+        match self.advance(1).take() {
+            Some(Token::RightParen) => Ok((first, second)),
+            other => syntax_error!(self.unexpected(Expected::ClosingParenthesis, other)),
         }
     }
 
@@ -266,7 +757,7 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
                     self.advance(1);
                     break Ok(collection);
                 }
-                None => syntax_error!("unexpect end of input"),
+                None => syntax_error!(SyntaxError::UnterminatedList),
                 _ => {
                     self.advance(1);
                     let ele = get_element(self)?;
@@ -276,6 +767,98 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
         }
     }
 
+    fn datum(&mut self) -> Result<Datum> {
+        Ok(match self.current.take() {
+            Some(Token::Boolean(b)) => Datum::Boolean(b),
+            Some(Token::Integer(a)) => Datum::Integer(a),
+            Some(Token::Real(a)) => Datum::Real(a),
+            Some(Token::Rational(a, b)) => Datum::Rational(a, b),
+            Some(Token::Character(a)) => Datum::Character(a),
+            Some(Token::String(a)) => Datum::String(a),
+            Some(Token::Identifier(a)) => Datum::Symbol(a),
+            Some(Token::LeftParen) => self.list_datum()?,
+            Some(Token::VecConsIntro) => Datum::Vector(self.collect(Self::datum)?),
+            Some(Token::ByteVecConsIntro) => self.bytevector()?,
+            Some(Token::Quote) => self.abbreviation("quote")?,
+            Some(Token::Quasiquote) => self.abbreviation("quasiquote")?,
+            Some(Token::Unquote) => self.abbreviation("unquote")?,
+            Some(Token::UnquoteSplicing) => self.abbreviation("unquote-splicing")?,
+            Some(Token::RightParen) => {
+                syntax_error!(SyntaxError::UnmatchedParenthesis)
+            }
+            None => syntax_error!(SyntaxError::UnexpectedEnd),
+            Some(other) => syntax_error!(self.unexpected(Expected::Datum, Some(other))),
+        })
+    }
+
+    // (quote a) is the same datum as 'a
+    fn quotation(&mut self) -> Result<Expression> {
+        self.advance(2);
+        let datum = self.datum()?;
+        match self.advance(1) {
+            Some(Token::RightParen) => Ok(Expression::Datum(Box::new(datum))),
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("quote".to_string()),
+                "quote: expect exactly one datum"
+            ),
+        }
+    }
+
+    // 'a is (quote a)
+    fn abbreviation(&mut self, keyword: &str) -> Result<Datum> {
+        self.advance(1);
+        Ok(Datum::List(vec![
+            Datum::Symbol(intern(keyword)),
+            self.datum()?,
+        ]))
+    }
+
+    fn list_datum(&mut self) -> Result<Datum> {
+        let mut elements = vec![];
+        loop {
+            match self.lexer.peek() {
+                Some(Token::RightParen) => {
+                    self.advance(1);
+                    break Ok(Datum::List(elements));
+                }
+                Some(Token::Period) => {
+                    if elements.is_empty() {
+                        let period = self.advance(1).take();
+                        syntax_error!(self.unexpected(Expected::Datum, period))
+                    }
+                    self.advance(2);
+                    let tail = self.datum()?;
+                    match self.advance(1).take() {
+                        Some(Token::RightParen) => break Ok(Datum::Pair(elements, Box::new(tail))),
+                        other => {
+                            syntax_error!(self.unexpected(Expected::ClosingParenthesis, other))
+                        }
+                    }
+                }
+                None => syntax_error!(SyntaxError::UnterminatedList),
+                _ => {
+                    self.advance(1);
+                    elements.push(self.datum()?);
+                }
+            }
+        }
+    }
+
+    fn bytevector(&mut self) -> Result<Datum> {
+        let mut bytes = vec![];
+        for element in self.collect(Self::datum)? {
+            match element {
+                Datum::Integer(n) if (0..=255).contains(&n) => bytes.push(n as u8),
+                other => syntax_error!(
+                    SyntaxError::InvalidDatum(other.clone()),
+                    "bytevector elements should be integers between 0 and 255, got {}",
+                    other
+                ),
+            }
+        }
+        Ok(Datum::Bytevector(bytes))
+    }
+
     fn vector(&mut self) -> Result<Expression> {
         Ok(Expression::Vector(
             self.collect(Self::parse_current_expression)?,
@@ -283,45 +866,79 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
     }
 
     fn lambda(&mut self) -> Result<Expression> {
-        let mut formals = vec![];
-        match self.advance(2).take() {
-            Some(Token::Identifier(ident)) => {
-                formals.push(ident);
-            }
-            Some(Token::LeftParen) => {
-                formals = self.collect(Self::get_identifier)?;
-            }
-            _ => syntax_error!("expect identifiers"),
+        let start = self.span.start;
+        let (formals, spans) = match self.advance(2).take() {
+            Some(Token::Identifier(ident)) => (vec![ident.to_string()], vec![self.span]),
+            Some(Token::LeftParen) => self.collect(Self::get_formal)?.into_iter().unzip(),
+            other => syntax_error!(self.unexpected(Expected::Formals, other)),
+        };
+        let (procedure, definitions) = self.located_body(formals)?;
+        Ok(self.originate(procedure, start, spans, definitions))
+    }
+
+    // a procedure read by a located parser knows the line its form starts at, and where its
+    // formals and the names its body defines are
+    fn originate(
+        &self,
+        mut procedure: Expression,
+        start: usize,
+        formals: Vec<Span>,
+        definitions: Vec<Span>,
+    ) -> Expression {
+        if let (Expression::Procedure(SchemeProcedure(.., origin)), Some(lines)) =
+            (&mut procedure, &self.lines)
+        {
+            *origin = Some(Rc::new(Origin {
+                formals,
+                definitions,
+                ..lines.origin(start)
+            }));
         }
-        self.procedure_body(formals)
+        procedure
     }
 
     fn procedure_body(&mut self, formals: Vec<String>) -> Result<Expression> {
-        let statements = self.collect(Self::parse_current)?;
+        self.located_body(formals).map(|(procedure, _)| procedure)
+    }
+
+    // the procedure with the spans of the names its body defines
+    fn located_body(&mut self, formals: Vec<String>) -> Result<(Expression, Vec<Span>)> {
+        let outer = self.defined.len();
+        let mut statements = self.collect(Self::parse_current)?;
+        let defined = self.defined.split_off(outer);
         let mut definitions = vec![];
         let mut expressions = vec![];
-        for statement in statements {
+        statements.reverse();
+        while let Some(statement) = statements.pop() {
             match statement {
+                Some(Statement::Sequence(sequence)) => {
+                    statements.extend(sequence.into_iter().rev().map(Some))
+                }
                 Some(Statement::Definition(def)) => {
                     if expressions.is_empty() {
                         definitions.push(def)
                     } else {
-                        syntax_error!("unexpect definition af expression")
+                        syntax_error!(
+                            SyntaxError::InvalidBody,
+                            "unexpect definition af expression"
+                        )
                     }
                 }
                 Some(Statement::Expression(expr)) => expressions.push(expr),
-                None => syntax_error!("lambda body empty"),
-                _ => syntax_error!("procedure body can only contains definition or expression"),
+                None => syntax_error!(SyntaxError::InvalidBody, "lambda body empty"),
+                _ => syntax_error!(
+                    SyntaxError::InvalidBody,
+                    "procedure body can only contains definition or expression"
+                ),
             }
         }
         if expressions.is_empty() {
-            syntax_error!("no expression in procedure body")
+            syntax_error!(SyntaxError::InvalidBody, "no expression in procedure body")
         }
-        Ok(Expression::Procedure(SchemeProcedure(
-            formals,
-            definitions,
-            expressions,
-        )))
+        Ok((
+            Expression::Procedure(SchemeProcedure(formals, definitions, expressions, None)),
+            defined,
+        ))
     }
 
     fn import_declaration(&mut self) -> Result<Statement> {
@@ -342,30 +959,511 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
                 self.advance(1);
                 Ok(Expression::Conditional(Box::new((test, consequent, None))))
             }
-            (
-                Some(Statement::Expression(test)),
-                Some(Statement::Expression(consequent)),
-                Some(_),
-            ) => match self.parse()? {
-                Some(Statement::Expression(alternative)) => {
+            (
+                Some(Statement::Expression(test)),
+                Some(Statement::Expression(consequent)),
+                Some(_),
+            ) => match self.parse()? {
+                Some(Statement::Expression(alternative)) => {
+                    self.advance(1);
+                    Ok(Expression::Conditional(Box::new((
+                        test,
+                        consequent,
+                        Some(alternative),
+                    ))))
+                }
+                other => syntax_error!(
+                    SyntaxError::InvalidForm("if".to_string()),
+                    "expect condition alternatives, got {:?}",
+                    other
+                ),
+            },
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("if".to_string()),
+                "conditional syntax error"
+            ),
+        }
+    }
+
+    fn guard(&mut self) -> Result<Expression> {
+        let variable = match (self.advance(2).take(), self.advance(1).take()) {
+            (Some(Token::LeftParen), Some(Token::Identifier(variable))) => variable.to_string(),
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("guard".to_string()),
+                "guard: expect (variable clause ...) after guard"
+            ),
+        };
+        let clauses = self.collect(Self::guard_clause)?;
+        let body = self.procedure_body(vec![])?;
+        Ok(Expression::Guard(Box::new((
+            variable,
+            clauses,
+            Expression::ProcedureCall(Box::new(body), vec![]),
+        ))))
+    }
+
+    fn guard_clause(&mut self) -> Result<GuardClause> {
+        match self.current.take() {
+            Some(Token::LeftParen) => (),
+            other => syntax_error!(self.unexpected(Expected::GuardClause, other)),
+        }
+        let test = match self.lexer.peek() {
+            Some(Token::Identifier(ident)) if &**ident == "else" => {
+                self.advance(1);
+                None
+            }
+            _ => match self.parse()? {
+                Some(Statement::Expression(test)) => Some(test),
+                _ => syntax_error!(
+                    SyntaxError::InvalidForm("guard".to_string()),
+                    "guard: expect a test expression in clause"
+                ),
+            },
+        };
+        let body = self.collect(Self::parse_current_expression)?;
+        if test.is_none() && body.is_empty() {
+            syntax_error!(
+                SyntaxError::InvalidForm("guard".to_string()),
+                "guard: expect expressions in else clause"
+            )
+        }
+        Ok(GuardClause(test, body))
+    }
+
+    fn thunk_expression(&mut self, keyword: &str) -> Result<Expression> {
+        match self.parse()? {
+            Some(Statement::Expression(expression)) => Ok(simple_procedure(vec![], expression)),
+            _ => syntax_error!(
+                SyntaxError::InvalidForm(keyword.to_string()),
+                "{}: expect an expression",
+                keyword
+            ),
+        }
+    }
+
+    // the expression is wrapped in a thunk, called when the promise is forced
+    fn delayed(&mut self, keyword: &str) -> Result<Expression> {
+        self.advance(1);
+        let thunk = self.thunk_expression(keyword)?;
+        match self.advance(1) {
+            Some(Token::RightParen) => Ok(thunk),
+            _ => syntax_error!(
+                SyntaxError::InvalidForm(keyword.to_string()),
+                "{}: expect exactly one expression",
+                keyword
+            ),
+        }
+    }
+
+    // (time expression) calls the builtin timing a thunk of the expression
+    fn timed(&mut self) -> Result<Expression> {
+        let thunk = self.delayed("time")?;
+        Ok(Expression::ProcedureCall(
+            Box::new(Expression::Primitive(Primitive::Time)),
+            vec![thunk],
+        ))
+    }
+
+    // (receive formals expression body...) calls the builtin consuming the values of a thunk
+    fn receive(&mut self) -> Result<Expression> {
+        let start = self.span.start;
+        self.advance(2);
+        let (formals, rest, spans) = self.formals("receive")?;
+        let producer = self.thunk_expression("receive")?;
+        let count = formals.len();
+        let (consumer, definitions) =
+            self.located_body(formals.into_iter().chain(rest.clone()).collect())?;
+        Ok(receive_values(
+            producer,
+            count,
+            rest.is_some(),
+            self.originate(consumer, start, spans, definitions),
+        ))
+    }
+
+    // each binding receives its values into temporaries, the inits being evaluated outside of
+    // all of them, and the body is applied to the temporaries
+    fn let_values(&mut self) -> Result<Expression> {
+        let start = self.span.start;
+        match self.advance(2).take() {
+            Some(Token::LeftParen) => (),
+            other => syntax_error!(self.unexpected(Expected::LetValuesBindings, other)),
+        }
+        let (mut bindings, mut formal_spans) = (vec![], vec![]);
+        loop {
+            match self.advance(1).take() {
+                Some(Token::RightParen) => break,
+                Some(Token::LeftParen) => (),
+                other => syntax_error!(self.unexpected(Expected::LetValuesBinding, other)),
+            }
+            self.advance(1);
+            let (formals, rest, spans) = self.formals("let-values")?;
+            let producer = self.thunk_expression("let-values")?;
+            match self.advance(1) {
+                Some(Token::RightParen) => {
+                    bindings.push((formals, rest, producer));
+                    formal_spans.extend(spans);
+                }
+                _ => syntax_error!(
+                    SyntaxError::InvalidForm("let-values".to_string()),
+                    "let-values: expect formals and exactly one expression in a binding"
+                ),
+            }
+        }
+        let temporaries = bindings
+            .iter()
+            .enumerate()
+            .map(|(index, (formals, rest, _))| {
+                (0..formals.len() + rest.is_some() as usize)
+                    .map(|position| generated_name(&format!("values {} {}", index, position)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let formals = bindings
+            .iter()
+            .flat_map(|(formals, rest, _)| formals.iter().chain(rest).cloned());
+        let (body, definitions) = self.located_body(formals.collect())?;
+        let mut call = Expression::ProcedureCall(
+            Box::new(self.originate(body, start, formal_spans, definitions)),
+            temporaries
+                .iter()
+                .flatten()
+                .map(|temporary| Expression::Identifier(temporary.clone()))
+                .collect(),
+        );
+        for ((formals, rest, producer), temporaries) in bindings.into_iter().zip(temporaries).rev()
+        {
+            let temporaries = temporaries.iter().map(|name| name.to_string()).collect();
+            call = receive_values(
+                producer,
+                formals.len(),
+                rest.is_some(),
+                simple_procedure(temporaries, call),
+            );
+        }
+        Ok(call)
+    }
+
+    // the formals of receive and let-values, a list of identifiers which may end with a dot and
+    // the identifier bound to a list of the values left, or that identifier alone
+    fn formals(&mut self, keyword: &str) -> Result<(Vec<String>, Option<String>, Vec<Span>)> {
+        match self.current.take() {
+            Some(Token::Identifier(ident)) => {
+                Ok((vec![], Some(ident.to_string()), vec![self.span]))
+            }
+            Some(Token::LeftParen) => {
+                let (mut formals, mut spans) = (vec![], vec![]);
+                loop {
+                    match self.advance(1).take() {
+                        Some(Token::RightParen) => break Ok((formals, None, spans)),
+                        Some(Token::Identifier(ident)) => {
+                            formals.push(ident.to_string());
+                            spans.push(self.span);
+                        }
+                        Some(Token::Period) => {
+                            let rest = self.advance(1).take();
+                            spans.push(self.span);
+                            match (rest, self.advance(1).take()) {
+                                (Some(Token::Identifier(rest)), Some(Token::RightParen)) => {
+                                    break Ok((formals, Some(rest.to_string()), spans))
+                                }
+                                _ => syntax_error!(
+                                    SyntaxError::InvalidForm(keyword.to_string()),
+                                    "{}: expect one identifier after the dot of formals",
+                                    keyword
+                                ),
+                            }
+                        }
+                        None => {
+                            syntax_error!(SyntaxError::UnterminatedList)
+                        }
+                        other => syntax_error!(self.unexpected(Expected::Identifier, other)),
+                    }
+                }
+            }
+            other => syntax_error!(self.unexpected(Expected::Formals, other)),
+        }
+    }
+
+    fn stream_cons(&mut self) -> Result<Expression> {
+        self.advance(1);
+        let car = self.thunk_expression("stream-cons")?;
+        let cdr = self.thunk_expression("stream-cons")?;
+        match self.advance(1) {
+            Some(Token::RightParen) => Ok(Expression::StreamCons(Box::new((car, cdr)))),
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("stream-cons".to_string()),
+                "stream-cons: expect exactly two expressions"
+            ),
+        }
+    }
+
+    // (define-stream (name formal ...) body ...) defines a stream-lambda
+    fn stream_definition(&mut self) -> Result<Definition> {
+        match self.definition()? {
+            Definition(name, procedure @ Expression::Procedure(_)) => {
+                Ok(Definition(name, stream_procedure(procedure)))
+            }
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("define-stream".to_string()),
+                "define-stream: expect (name formal ...) and a body"
+            ),
+        }
+    }
+
+    fn matching(&mut self) -> Result<Expression> {
+        self.advance(1);
+        let value = match self.parse()? {
+            Some(Statement::Expression(value)) => value,
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("match".to_string()),
+                "match: expect an expression to match"
+            ),
+        };
+        let clauses = self.collect(Self::match_clause)?;
+        Ok(Expression::Match(Box::new((value, clauses))))
+    }
+
+    fn match_clause(&mut self) -> Result<MatchClause> {
+        match self.current.take() {
+            Some(Token::LeftParen) => (),
+            other => syntax_error!(self.unexpected(Expected::MatchClause, other)),
+        }
+        self.advance(1);
+        let pattern = self.pattern()?;
+        let variables = pattern.variables();
+        for (index, variable) in variables.iter().enumerate() {
+            if variables[..index].contains(variable) {
+                syntax_error!(
+                    SyntaxError::InvalidForm("match".to_string()),
+                    "match: duplicate pattern variable {}",
+                    variable
+                )
+            }
+        }
+        Ok(MatchClause(pattern, self.procedure_body(variables)?))
+    }
+
+    fn pattern(&mut self) -> Result<Pattern> {
+        Ok(match self.current.take() {
+            Some(Token::Identifier(identifier)) => match identifier.as_ref() {
+                "_" => Pattern::Wildcard,
+                "..." => syntax_error!(
+                    SyntaxError::InvalidForm("match".to_string()),
+                    "match: ... should follow a pattern in a list or vector"
+                ),
+                _ => Pattern::Variable(identifier.to_string()),
+            },
+            Some(Token::LeftParen) => match self.lexer.peek() {
+                Some(Token::Identifier(identifier)) if &**identifier == "?" => {
+                    self.advance(1);
+                    let predicate = match self.parse()? {
+                        Some(Statement::Expression(predicate)) => predicate,
+                        _ => syntax_error!(
+                            SyntaxError::InvalidForm("match".to_string()),
+                            "match: expect a predicate after ?"
+                        ),
+                    };
+                    Pattern::Predicate(predicate, self.collect(Self::pattern)?)
+                }
+                _ => {
+                    let (elements, tail) = self.sequence_pattern(true)?;
+                    Pattern::List(elements, Box::new(tail))
+                }
+            },
+            Some(Token::VecConsIntro) => Pattern::Vector(self.sequence_pattern(false)?.0),
+            Some(Token::Quote) => {
+                self.advance(1);
+                Pattern::Datum(self.datum()?)
+            }
+            token => {
+                self.current = token;
+                Pattern::Datum(self.datum()?)
+            }
+        })
+    }
+
+    // the elements up to the closing parenthesis, and the tail after a period when allowed
+    fn sequence_pattern(&mut self, allow_tail: bool) -> Result<(SequencePattern, Pattern)> {
+        let mut elements = vec![];
+        let mut ellipsis = None;
+        let mut tail = Pattern::Datum(Datum::List(vec![]));
+        loop {
+            match self.lexer.peek() {
+                Some(Token::RightParen) => {
+                    self.advance(1);
+                    break;
+                }
+                Some(Token::Identifier(identifier)) if &**identifier == "..." => {
+                    if elements.is_empty() || ellipsis.is_some() {
+                        syntax_error!(
+                            SyntaxError::InvalidForm("match".to_string()),
+                            "match: ... should follow exactly one pattern of a sequence"
+                        )
+                    }
+                    self.advance(1);
+                    ellipsis = Some(elements.len() - 1);
+                }
+                Some(Token::Period) if allow_tail && !elements.is_empty() => {
+                    self.advance(2);
+                    tail = self.pattern()?;
+                    match self.advance(1) {
+                        Some(Token::RightParen) => break,
+                        _ => syntax_error!(
+                            SyntaxError::InvalidForm("match".to_string()),
+                            "match: expect exactly one pattern after the period"
+                        ),
+                    }
+                }
+                None => syntax_error!(SyntaxError::UnterminatedList),
+                _ => {
+                    self.advance(1);
+                    elements.push(self.pattern()?);
+                }
+            }
+        }
+        Ok((SequencePattern(elements, ellipsis), tail))
+    }
+
+    // (cut operator-or-slot argument-or-slot ...) is a procedure of the <> slots, cute evaluates
+    // the other expressions once when the procedure is made rather than at every call
+    fn cut(&mut self, evaluate_once: bool) -> Result<Expression> {
+        let keyword = if evaluate_once { "cute" } else { "cut" };
+        self.advance(1);
+        let parts = self.collect(Self::cut_part)?;
+        if parts.is_empty() {
+            syntax_error!(
+                SyntaxError::InvalidForm(keyword.to_string()),
+                "{}: expect an operator",
+                keyword
+            )
+        }
+        if parts.iter().flatten().any(
+            |part| matches!(part, Expression::Identifier(identifier) if &**identifier == "<...>"),
+        ) {
+            syntax_error!(
+                SyntaxError::InvalidForm(keyword.to_string()),
+                "{}: variadic slots are not supported",
+                keyword
+            )
+        }
+        let mut slots = vec![];
+        let mut values = vec![];
+        let mut bound = vec![];
+        let mut call = vec![];
+        for (index, part) in parts.into_iter().enumerate() {
+            match part {
+                None => {
+                    let slot = generated_name(&format!("slot {}", index));
+                    slots.push(slot.to_string());
+                    call.push(Expression::Identifier(slot));
+                }
+                Some(expression) if evaluate_once => {
+                    let value = generated_name(&format!("value {}", index));
+                    values.push(value.to_string());
+                    bound.push(expression);
+                    call.push(Expression::Identifier(value));
+                }
+                Some(expression) => call.push(expression),
+            }
+        }
+        let operator = call.remove(0);
+        let procedure =
+            simple_procedure(slots, Expression::ProcedureCall(Box::new(operator), call));
+        Ok(match evaluate_once {
+            true => Expression::ProcedureCall(Box::new(simple_procedure(values, procedure)), bound),
+            false => procedure,
+        })
+    }
+
+    // None for a <> slot
+    fn cut_part(&mut self) -> Result<Option<Expression>> {
+        match &self.current {
+            Some(Token::Identifier(identifier)) if &**identifier == "<>" => Ok(None),
+            _ => Ok(Some(self.parse_current_expression()?)),
+        }
+    }
+
+    // the first clause whose requirement holds is expanded, the others are only read as data
+    fn cond_expand(&mut self) -> Result<Statement> {
+        self.advance(1);
+        let mut chosen = None;
+        loop {
+            match self.lexer.peek() {
+                Some(Token::RightParen) => {
+                    self.advance(1);
+                    break;
+                }
+                None => syntax_error!(SyntaxError::UnterminatedList),
+                _ => (),
+            }
+            match self.advance(1).take() {
+                Some(Token::LeftParen) => (),
+                other => syntax_error!(self.unexpected(Expected::CondExpandClause, other)),
+            }
+            let fulfilled = match self.lexer.peek() {
+                Some(Token::Identifier(ident)) if &**ident == "else" => {
                     self.advance(1);
-                    Ok(Expression::Conditional(Box::new((
-                        test,
-                        consequent,
-                        Some(alternative),
-                    ))))
+                    true
                 }
-                other => syntax_error!("expect condition alternatives, got {:?}", other),
-            },
-            _ => syntax_error!("conditional syntax error"),
+                Some(Token::RightParen) | None => {
+                    syntax_error!(
+                        SyntaxError::InvalidForm("cond-expand".to_string()),
+                        "cond-expand: expect a feature requirement in clause"
+                    )
+                }
+                _ => {
+                    self.advance(1);
+                    let requirement = self.datum()?;
+                    chosen.is_none() && feature_requirement(&requirement)?
+                }
+            };
+            if fulfilled && chosen.is_none() {
+                chosen = Some(
+                    self.collect(Self::parse_current)?
+                        .into_iter()
+                        .flatten()
+                        .collect(),
+                );
+            } else {
+                self.collect(Self::datum)?;
+            }
+        }
+        let mut statements: Vec<Statement> = chosen.unwrap_or_default();
+        if statements.len() == 1 {
+            return Ok(statements.pop().unwrap());
+        }
+        // expressions alone are evaluated in order so the expansion works as an expression too
+        if !statements.is_empty()
+            && statements
+                .iter()
+                .all(|statement| matches!(statement, Statement::Expression(_)))
+        {
+            let expressions = statements
+                .into_iter()
+                .filter_map(|statement| match statement {
+                    Statement::Expression(expr) => Some(expr),
+                    _ => None,
+                })
+                .collect();
+            return Ok(Statement::Expression(Expression::ProcedureCall(
+                Box::new(Expression::Procedure(SchemeProcedure(
+                    vec![],
+                    vec![],
+                    expressions,
+                    None,
+                ))),
+                vec![],
+            )));
         }
+        Ok(Statement::Sequence(statements))
     }
 
     fn import_set(&mut self) -> Result<ImportSet> {
         Ok(match self.current.take() {
-            Some(Token::Identifier(libname)) => Ok(ImportSet::Direct(libname))?,
+            Some(Token::Identifier(libname)) => ImportSet::Direct(libname.to_string()),
             Some(Token::LeftParen) => match self.advance(1).take() {
-                Some(Token::Identifier(ident)) => match ident.as_str() {
+                Some(Token::Identifier(ident)) => match ident.as_ref() {
                     "only" => {
                         self.advance(1);
                         ImportSet::Only(
@@ -382,9 +1480,12 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
                     }
                     "prefix" => match self.advance(2).take() {
                         Some(Token::Identifier(identifier)) => {
-                            ImportSet::Prefix(Box::new(self.import_set()?), identifier)
+                            ImportSet::Prefix(Box::new(self.import_set()?), identifier.to_string())
                         }
-                        _ => syntax_error!("expect a prefix name after import"),
+                        _ => syntax_error!(
+                            SyntaxError::InvalidForm("import".to_string()),
+                            "expect a prefix name after import"
+                        ),
                     },
                     "rename" => {
                         self.advance(1);
@@ -393,36 +1494,63 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
                             self.collect(Self::get_identifier_pair)?,
                         )
                     }
-                    _ => syntax_error!("import: expect sub import set"),
+                    // a library name as (srfi 1)
+                    _ => {
+                        let parts = self.collect(Self::get_library_name_part)?;
+                        ImportSet::Direct(format!("({} {})", ident, parts.join(" ")))
+                    }
                 },
-                _ => syntax_error!("import: expect library name or sub import sets"),
+                _ => syntax_error!(
+                    SyntaxError::InvalidForm("import".to_string()),
+                    "import: expect library name or sub import sets"
+                ),
             },
-            other => syntax_error!("expect an import set, got {:?}", other),
+            other => syntax_error!(self.unexpected(Expected::ImportSet, other)),
         })
     }
 
     fn definition(&mut self) -> Result<Definition> {
+        let start = self.span.start;
         let current = self.advance(2).take();
         match current {
-            Some(Token::Identifier(identifier)) => match (self.parse()?, self.advance(1)) {
-                (Some(Statement::Expression(expr)), Some(Token::RightParen)) => {
-                    Ok(Definition(identifier, expr))
+            Some(Token::Identifier(identifier)) => {
+                let span = self.span;
+                match (self.parse()?, self.advance(1)) {
+                    (Some(Statement::Expression(expr)), Some(Token::RightParen)) => {
+                        self.defined.push(span);
+                        Ok(Definition(identifier.to_string(), expr))
+                    }
+                    _ => syntax_error!(
+                        SyntaxError::InvalidForm("define".to_string()),
+                        "define: expect identifier and expression"
+                    ),
                 }
-                _ => syntax_error!("define: expect identifier and expression"),
-            },
+            }
             Some(Token::LeftParen) => match self.advance(1).take() {
                 Some(Token::Identifier(identifier)) => {
-                    let formals = self.collect(Self::get_identifier)?;
-                    let body = self.procedure_body(formals)?;
-                    Ok(Definition(identifier, body))
+                    let span = self.span;
+                    let (formals, spans) = self.collect(Self::get_formal)?.into_iter().unzip();
+                    let (body, definitions) = self.located_body(formals)?;
+                    self.defined.push(span);
+                    Ok(Definition(
+                        identifier.to_string(),
+                        self.originate(body, start, spans, definitions),
+                    ))
                 }
-                _ => syntax_error!("define: expect identifier and expression"),
+                _ => syntax_error!(
+                    SyntaxError::InvalidForm("define".to_string()),
+                    "define: expect identifier and expression"
+                ),
             },
-            _ => syntax_error!("define: expect identifier and expression"),
+            _ => syntax_error!(
+                SyntaxError::InvalidForm("define".to_string()),
+                "define: expect identifier and expression"
+            ),
         }
     }
 
     fn procedure_call(&mut self) -> Result<Expression> {
+        let start = self.span.start;
         match self.parse()? {
             Some(Statement::Expression(operator)) => {
                 let mut arguments: Vec<Expression> = vec![];
@@ -430,32 +1558,125 @@ impl<TokenIter: Iterator<Item = Token>> Parser<TokenIter> {
                     match self.lexer.peek() {
                         Some(Token::RightParen) => {
                             self.advance(1);
-                            return Ok(Expression::ProcedureCall(Box::new(operator), arguments));
+                            return Ok(match self.spans {
+                                Some(_) => {
+                                    let span = Span {
+                                        start,
+                                        end: self.span.end,
+                                    };
+                                    Expression::LocatedCall(Box::new((operator, arguments, span)))
+                                }
+                                None => Expression::ProcedureCall(Box::new(operator), arguments),
+                            });
+                        }
+                        None => {
+                            syntax_error!(SyntaxError::UnterminatedList)
                         }
-                        None => syntax_error!("Unmatched Parentheses!"),
                         _ => arguments.push(match self.parse()? {
                             Some(Statement::Expression(subexpr)) => subexpr,
-                            _ => syntax_error!("Unmatched Parentheses!"),
+                            _ => syntax_error!(SyntaxError::NotAnExpression),
                         }),
                     }
                 }
             }
-            _ => syntax_error!("operator should be an expression"),
+            _ => syntax_error!(
+                SyntaxError::NotAnExpression,
+                "operator should be an expression"
+            ),
+        }
+    }
+
+    // the kind of error for the token found in place of what was expected, spanning the token
+    // when the spans are known, as they are not for the end of input
+    fn unexpected(&self, expected: Expected, found: Option<Token>) -> SyntaxError {
+        SyntaxError::UnexpectedToken {
+            span: match (&found, &self.spans) {
+                (Some(_), Some(_)) => Some(self.span),
+                _ => None,
+            },
+            expected,
+            found,
         }
     }
 
     fn advance(&mut self, count: usize) -> &mut Option<Token> {
-        for _ in 1..count {
-            self.lexer.next();
+        for _ in 0..count {
+            self.current = self.lexer.next();
+            if let (Some(_), Some(spans)) = (&self.current, &self.spans) {
+                self.span = spans.borrow_mut().pop_front().unwrap_or(self.span);
+            }
         }
-        self.current = self.lexer.next();
         &mut self.current
     }
 }
 
 pub fn simple_procedure(formals: Vec<String>, expression: Expression) -> Expression {
-    Expression::Procedure(SchemeProcedure(formals, vec![], vec![expression]))
+    Expression::Procedure(SchemeProcedure(formals, vec![], vec![expression], None))
+}
+
+// calls the consumer with the values of the producer, the last formal of a consumer with a
+// rest being bound to a list of the values after the others
+fn receive_values(
+    producer: Expression,
+    count: usize,
+    rest: bool,
+    consumer: Expression,
+) -> Expression {
+    match rest {
+        false => Expression::ProcedureCall(
+            Box::new(Expression::Primitive(Primitive::CallWithValues)),
+            vec![producer, consumer],
+        ),
+        true => Expression::ProcedureCall(
+            Box::new(Expression::Primitive(Primitive::CallWithRestValues)),
+            vec![producer, consumer, Expression::Integer(count as i64)],
+        ),
+    }
+}
+
+// a stream-lambda returns a delay-force promise of its body
+fn stream_procedure(procedure: Expression) -> Expression {
+    match procedure {
+        Expression::Procedure(SchemeProcedure(formals, definitions, expressions, origin)) => {
+            let body = Expression::DelayForce(Box::new(Expression::Procedure(SchemeProcedure(
+                vec![],
+                definitions,
+                expressions,
+                origin.clone(),
+            ))));
+            Expression::Procedure(SchemeProcedure(formals, vec![], vec![body], origin))
+        }
+        other => other,
+    }
+}
+
+// whether the source ends inside a datum, so that more input could complete it
+// errors are left for parsing to report, except for literals cut off by the end of input
+pub fn is_incomplete(source: &str) -> bool {
+    let mut chars = source.chars().peekable();
+    let mut depth = 0;
+    let mut abbreviated = false;
+    for token in TokenGenerator::new(&mut chars) {
+        match token {
+            Ok(Token::LeftParen) | Ok(Token::VecConsIntro) | Ok(Token::ByteVecConsIntro) => {
+                depth += 1
+            }
+            Ok(Token::RightParen) if depth == 0 => return false,
+            Ok(Token::RightParen) => depth -= 1,
+            Ok(_) => (),
+            Err(_) => return chars.peek().is_none(),
+        }
+        abbreviated = matches!(
+            token,
+            Ok(Token::Quote)
+                | Ok(Token::Quasiquote)
+                | Ok(Token::Unquote)
+                | Ok(Token::UnquoteSplicing)
+        );
+    }
+    depth > 0 || abbreviated
 }
+
 #[test]
 fn empty() -> Result<()> {
     let tokens = Vec::new();
@@ -475,10 +1696,10 @@ fn integer() -> Result<()> {
 
 #[test]
 fn real_number() -> Result<()> {
-    let tokens = vec![Token::Real("1.2".to_string())];
+    let tokens = vec![Token::Real("1.2".into())];
     let mut parser = Parser::new(tokens.into_iter());
     let ast = parser.parse()?;
-    assert_eq!(ast, expr_to_statement!(Expression::Real("1.2".to_string())));
+    assert_eq!(ast, expr_to_statement!(Expression::Real("1.2".into())));
     Ok(())
 }
 
@@ -491,14 +1712,26 @@ fn rational() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn string() -> Result<()> {
+    let tokens = vec![Token::String("abc".to_string())];
+    let mut parser = Parser::new(tokens.into_iter());
+    let ast = parser.parse()?;
+    assert_eq!(
+        ast,
+        expr_to_statement!(Expression::String("abc".to_string()))
+    );
+    Ok(())
+}
+
 #[test]
 fn identifier() -> Result<()> {
-    let tokens = vec![Token::Identifier("test".to_string())];
+    let tokens = vec![Token::Identifier("test".into())];
     let mut parser = Parser::new(tokens.into_iter());
     let ast = parser.parse()?;
     assert_eq!(
         ast,
-        expr_to_statement!(Expression::Identifier("test".to_string()))
+        expr_to_statement!(Expression::Identifier("test".into()))
     );
     Ok(())
 }
@@ -527,7 +1760,7 @@ fn vector() -> Result<()> {
 fn procedure_call() -> Result<()> {
     let tokens = vec![
         Token::LeftParen,
-        Token::Identifier("+".to_string()),
+        Token::Identifier("+".into()),
         Token::Integer(1),
         Token::Integer(2),
         Token::Integer(3),
@@ -538,7 +1771,7 @@ fn procedure_call() -> Result<()> {
     assert_eq!(
         ast,
         expr_to_statement!(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("+".to_string())),
+            Box::new(Expression::Identifier("+".into())),
             vec![
                 Expression::Integer(1),
                 Expression::Integer(2),
@@ -553,7 +1786,7 @@ fn procedure_call() -> Result<()> {
 fn unmatched_parantheses() {
     let tokens = vec![
         Token::LeftParen,
-        Token::Identifier("+".to_string()),
+        Token::Identifier("+".into()),
         Token::Integer(1),
         Token::Integer(2),
         Token::Integer(3),
@@ -562,8 +1795,16 @@ fn unmatched_parantheses() {
     assert_eq!(
         parser.parse(),
         Err(Error {
-            category: ErrorType::Syntax,
-            message: "Unmatched Parentheses!".to_string()
+            category: ErrorType::Syntax(SyntaxError::UnterminatedList),
+            message: "unterminated list".to_string()
+        })
+    );
+    let mut parser = Parser::new(vec![Token::RightParen].into_iter());
+    assert_eq!(
+        parser.parse(),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::UnmatchedParenthesis),
+            message: "unmatched closing parenthesis".to_string()
         })
     );
 }
@@ -574,8 +1815,8 @@ fn definition() -> Result<()> {
         {
             let tokens = vec![
                 Token::LeftParen,
-                Token::Identifier("define".to_string()),
-                Token::Identifier("a".to_string()),
+                Token::Identifier("define".into()),
+                Token::Identifier("a".into()),
                 Token::Integer(1),
                 Token::RightParen,
             ];
@@ -589,16 +1830,16 @@ fn definition() -> Result<()> {
         {
             let tokens = vec![
                 Token::LeftParen,
-                Token::Identifier("define".to_string()),
+                Token::Identifier("define".into()),
                 Token::LeftParen,
-                Token::Identifier("add".to_string()),
-                Token::Identifier("x".to_string()),
-                Token::Identifier("y".to_string()),
+                Token::Identifier("add".into()),
+                Token::Identifier("x".into()),
+                Token::Identifier("y".into()),
                 Token::RightParen,
                 Token::LeftParen,
-                Token::Identifier("+".to_string()),
-                Token::Identifier("x".to_string()),
-                Token::Identifier("y".to_string()),
+                Token::Identifier("+".into()),
+                Token::Identifier("x".into()),
+                Token::Identifier("y".into()),
                 Token::RightParen,
                 Token::RightParen,
             ];
@@ -611,10 +1852,10 @@ fn definition() -> Result<()> {
                     simple_procedure(
                         vec!["x".to_string(), "y".to_string()],
                         Expression::ProcedureCall(
-                            Box::new(Expression::Identifier("+".to_string())),
+                            Box::new(Expression::Identifier("+".into())),
                             vec![
-                                Expression::Identifier("x".to_string()),
-                                Expression::Identifier("y".to_string()),
+                                Expression::Identifier("x".into()),
+                                Expression::Identifier("y".into()),
                             ]
                         )
                     )
@@ -629,10 +1870,10 @@ fn definition() -> Result<()> {
 fn nested_procedure_call() -> Result<()> {
     let tokens = vec![
         Token::LeftParen,
-        Token::Identifier("+".to_string()),
+        Token::Identifier("+".into()),
         Token::Integer(1),
         Token::LeftParen,
-        Token::Identifier("-".to_string()),
+        Token::Identifier("-".into()),
         Token::Integer(2),
         Token::Integer(3),
         Token::RightParen,
@@ -643,11 +1884,11 @@ fn nested_procedure_call() -> Result<()> {
     assert_eq!(
         ast,
         expr_to_statement!(Expression::ProcedureCall(
-            Box::new(Expression::Identifier("+".to_string())),
+            Box::new(Expression::Identifier("+".into())),
             vec![
                 Expression::Integer(1),
                 Expression::ProcedureCall(
-                    Box::new(Expression::Identifier("-".to_string())),
+                    Box::new(Expression::Identifier("-".into())),
                     vec![Expression::Integer(2), Expression::Integer(3)]
                 ),
             ]
@@ -661,15 +1902,15 @@ fn lambda() -> Result<()> {
     {
         let tokens = vec![
             Token::LeftParen,
-            Token::Identifier("lambda".to_string()),
+            Token::Identifier("lambda".into()),
             Token::LeftParen,
-            Token::Identifier("x".to_string()),
-            Token::Identifier("y".to_string()),
+            Token::Identifier("x".into()),
+            Token::Identifier("y".into()),
             Token::RightParen,
             Token::LeftParen,
-            Token::Identifier("+".to_string()),
-            Token::Identifier("x".to_string()),
-            Token::Identifier("y".to_string()),
+            Token::Identifier("+".into()),
+            Token::Identifier("x".into()),
+            Token::Identifier("y".into()),
             Token::RightParen,
             Token::RightParen,
         ];
@@ -680,10 +1921,10 @@ fn lambda() -> Result<()> {
             Some(Statement::Expression(simple_procedure(
                 vec!["x".to_string(), "y".to_string()],
                 Expression::ProcedureCall(
-                    Box::new(Expression::Identifier("+".to_string())),
+                    Box::new(Expression::Identifier("+".into())),
                     vec![
-                        Expression::Identifier("x".to_string()),
-                        Expression::Identifier("y".to_string())
+                        Expression::Identifier("x".into()),
+                        Expression::Identifier("y".into())
                     ]
                 )
             )))
@@ -693,19 +1934,19 @@ fn lambda() -> Result<()> {
     {
         let tokens = vec![
             Token::LeftParen,
-            Token::Identifier("lambda".to_string()),
+            Token::Identifier("lambda".into()),
             Token::LeftParen,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::RightParen,
             Token::LeftParen,
-            Token::Identifier("define".to_string()),
-            Token::Identifier("y".to_string()),
+            Token::Identifier("define".into()),
+            Token::Identifier("y".into()),
             Token::Integer(1),
             Token::RightParen,
             Token::LeftParen,
-            Token::Identifier("+".to_string()),
-            Token::Identifier("x".to_string()),
-            Token::Identifier("y".to_string()),
+            Token::Identifier("+".into()),
+            Token::Identifier("x".into()),
+            Token::Identifier("y".into()),
             Token::RightParen,
             Token::RightParen,
         ];
@@ -718,12 +1959,13 @@ fn lambda() -> Result<()> {
                     vec!["x".to_string()],
                     vec![Definition("y".to_string(), Expression::Integer(1))],
                     vec![Expression::ProcedureCall(
-                        Box::new(Expression::Identifier("+".to_string())),
+                        Box::new(Expression::Identifier("+".into())),
                         vec![
-                            Expression::Identifier("x".to_string()),
-                            Expression::Identifier("y".to_string())
+                            Expression::Identifier("x".into()),
+                            Expression::Identifier("y".into())
                         ]
-                    )]
+                    )],
+                    None
                 )
             )))
         );
@@ -732,13 +1974,13 @@ fn lambda() -> Result<()> {
     {
         let tokens = vec![
             Token::LeftParen,
-            Token::Identifier("lambda".to_string()),
+            Token::Identifier("lambda".into()),
             Token::LeftParen,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::RightParen,
             Token::LeftParen,
-            Token::Identifier("define".to_string()),
-            Token::Identifier("y".to_string()),
+            Token::Identifier("define".into()),
+            Token::Identifier("y".into()),
             Token::Integer(1),
             Token::RightParen,
             Token::RightParen,
@@ -748,7 +1990,7 @@ fn lambda() -> Result<()> {
         assert_eq!(
             err,
             Err(Error {
-                category: ErrorType::Syntax,
+                category: ErrorType::Syntax(SyntaxError::InvalidBody),
                 message: "no expression in procedure body".to_string()
             })
         );
@@ -761,7 +2003,7 @@ fn lambda() -> Result<()> {
 fn conditional() -> Result<()> {
     let tokens = vec![
         Token::LeftParen,
-        Token::Identifier("if".to_string()),
+        Token::Identifier("if".into()),
         Token::Boolean(true),
         Token::Integer(1),
         Token::Integer(2),
@@ -780,6 +2022,185 @@ fn conditional() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn guard() -> Result<()> {
+    let tokens = vec![
+        Token::LeftParen,
+        Token::Identifier("guard".into()),
+        Token::LeftParen,
+        Token::Identifier("e".into()),
+        Token::LeftParen,
+        Token::Identifier("e".into()),
+        Token::RightParen,
+        Token::LeftParen,
+        Token::Identifier("else".into()),
+        Token::Integer(1),
+        Token::RightParen,
+        Token::RightParen,
+        Token::Integer(2),
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse()?,
+        Some(Statement::Expression(Expression::Guard(Box::new((
+            "e".to_string(),
+            vec![
+                GuardClause(Some(Expression::Identifier("e".into())), vec![]),
+                GuardClause(None, vec![Expression::Integer(1)])
+            ],
+            Expression::ProcedureCall(
+                Box::new(simple_procedure(vec![], Expression::Integer(2))),
+                vec![]
+            )
+        )))))
+    );
+    assert_eq!(parser.parse()?, None);
+    Ok(())
+}
+
+#[test]
+fn cond_expand() -> Result<()> {
+    let tokens = vec![
+        Token::LeftParen,
+        Token::Identifier("cond-expand".into()),
+        Token::LeftParen,
+        Token::Identifier("no-such-feature".into()),
+        Token::Identifier("if".into()),
+        Token::RightParen,
+        Token::LeftParen,
+        Token::LeftParen,
+        Token::Identifier("or".into()),
+        Token::Identifier("r7rs".into()),
+        Token::RightParen,
+        Token::LeftParen,
+        Token::Identifier("define".into()),
+        Token::Identifier("a".into()),
+        Token::Integer(1),
+        Token::RightParen,
+        Token::Identifier("a".into()),
+        Token::RightParen,
+        Token::LeftParen,
+        Token::Identifier("else".into()),
+        Token::Integer(2),
+        Token::RightParen,
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse()?,
+        Some(Statement::Sequence(vec![
+            Statement::Definition(Definition("a".to_string(), Expression::Integer(1))),
+            Statement::Expression(Expression::Identifier("a".into()))
+        ]))
+    );
+    assert_eq!(parser.parse()?, None);
+    Ok(())
+}
+
+#[test]
+fn match_patterns() -> Result<()> {
+    let tokens = vec![
+        Token::LeftParen,
+        Token::Identifier("match".into()),
+        Token::Identifier("x".into()),
+        Token::LeftParen,
+        Token::LeftParen,
+        Token::Identifier("a".into()),
+        Token::Identifier("...".into()),
+        Token::Period,
+        Token::Identifier("_".into()),
+        Token::RightParen,
+        Token::Identifier("a".into()),
+        Token::RightParen,
+        Token::LeftParen,
+        Token::VecConsIntro,
+        Token::LeftParen,
+        Token::Identifier("?".into()),
+        Token::Identifier("odd?".into()),
+        Token::Identifier("n".into()),
+        Token::RightParen,
+        Token::Quote,
+        Token::Identifier("b".into()),
+        Token::RightParen,
+        Token::Identifier("n".into()),
+        Token::RightParen,
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse()?,
+        Some(Statement::Expression(Expression::Match(Box::new((
+            Expression::Identifier("x".into()),
+            vec![
+                MatchClause(
+                    Pattern::List(
+                        SequencePattern(vec![Pattern::Variable("a".to_string())], Some(0)),
+                        Box::new(Pattern::Wildcard)
+                    ),
+                    simple_procedure(vec!["a".to_string()], Expression::Identifier("a".into()))
+                ),
+                MatchClause(
+                    Pattern::Vector(SequencePattern(
+                        vec![
+                            Pattern::Predicate(
+                                Expression::Identifier("odd?".into()),
+                                vec![Pattern::Variable("n".to_string())]
+                            ),
+                            Pattern::Datum(Datum::Symbol("b".into()))
+                        ],
+                        None
+                    )),
+                    simple_procedure(vec!["n".to_string()], Expression::Identifier("n".into()))
+                )
+            ]
+        )))))
+    );
+    Ok(())
+}
+
+#[test]
+fn cut() -> Result<()> {
+    let tokens = vec![
+        Token::LeftParen,
+        Token::Identifier("cut".into()),
+        Token::Identifier("+".into()),
+        Token::Integer(1),
+        Token::Identifier("<>".into()),
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse()?,
+        Some(Statement::Expression(simple_procedure(
+            vec![generated_name("slot 2").to_string()],
+            Expression::ProcedureCall(
+                Box::new(Expression::Identifier("+".into())),
+                vec![
+                    Expression::Integer(1),
+                    Expression::Identifier(generated_name("slot 2"))
+                ]
+            )
+        )))
+    );
+    let tokens = vec![
+        Token::LeftParen,
+        Token::Identifier("cute".into()),
+        Token::Identifier("<>".into()),
+        Token::Identifier("<...>".into()),
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse(),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::InvalidForm("cute".to_string())),
+            message: "cute: variadic slots are not supported".to_string()
+        })
+    );
+    Ok(())
+}
+
 /* (import
 (only example-lib a b)
 (rename example-lib (old new))
@@ -789,19 +2210,19 @@ fn import_declaration() -> Result<()> {
     {
         let tokens = vec![
             Token::LeftParen,
-            Token::Identifier("import".to_string()),
+            Token::Identifier("import".into()),
             Token::LeftParen,
-            Token::Identifier("only".to_string()),
-            Token::Identifier("example-lib".to_string()),
-            Token::Identifier("a".to_string()),
-            Token::Identifier("b".to_string()),
+            Token::Identifier("only".into()),
+            Token::Identifier("example-lib".into()),
+            Token::Identifier("a".into()),
+            Token::Identifier("b".into()),
             Token::RightParen,
             Token::LeftParen,
-            Token::Identifier("rename".to_string()),
-            Token::Identifier("example-lib".to_string()),
+            Token::Identifier("rename".into()),
+            Token::Identifier("example-lib".into()),
             Token::LeftParen,
-            Token::Identifier("old".to_string()),
-            Token::Identifier("new".to_string()),
+            Token::Identifier("old".into()),
+            Token::Identifier("new".into()),
             Token::RightParen,
             Token::RightParen,
             Token::RightParen,
@@ -823,5 +2244,154 @@ fn import_declaration() -> Result<()> {
             ]))
         );
     }
+    {
+        // (import (only (srfi 1) iota))
+        let tokens = vec![
+            Token::LeftParen,
+            Token::Identifier("import".into()),
+            Token::LeftParen,
+            Token::Identifier("only".into()),
+            Token::LeftParen,
+            Token::Identifier("srfi".into()),
+            Token::Integer(1),
+            Token::RightParen,
+            Token::Identifier("iota".into()),
+            Token::RightParen,
+            Token::RightParen,
+        ];
+        let mut parser = Parser::new(tokens.into_iter());
+        let import = ImportSet::Only(
+            Box::new(ImportSet::Direct("(srfi 1)".to_string())),
+            vec!["iota".to_string()],
+        );
+        assert_eq!(import.library(), "(srfi 1)");
+        assert_eq!(
+            parser.parse()?,
+            Some(Statement::ImportDeclaration(vec![import]))
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn quote() -> Result<()> {
+    // '(a 1 . "s")
+    let tokens = vec![
+        Token::Quote,
+        Token::LeftParen,
+        Token::Identifier("a".into()),
+        Token::Integer(1),
+        Token::Period,
+        Token::String("s".to_string()),
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse()?,
+        expr_to_statement!(Expression::Datum(Box::new(Datum::Pair(
+            vec![Datum::Symbol("a".into()), Datum::Integer(1)],
+            Box::new(Datum::String("s".to_string()))
+        ))))
+    );
+
+    // '#(''() define)
+    let tokens = vec![
+        Token::Quote,
+        Token::VecConsIntro,
+        Token::Quote,
+        Token::LeftParen,
+        Token::RightParen,
+        Token::Identifier("define".into()),
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse()?,
+        expr_to_statement!(Expression::Datum(Box::new(Datum::Vector(vec![
+            Datum::List(vec![Datum::Symbol("quote".into()), Datum::List(vec![])]),
+            Datum::Symbol("define".into())
+        ]))))
+    );
+
+    let tokens = vec![
+        Token::Quote,
+        Token::LeftParen,
+        Token::Integer(1),
+        Token::Period,
+        Token::Integer(2),
+        Token::Integer(3),
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse(),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::UnexpectedToken {
+                expected: Expected::ClosingParenthesis,
+                found: Some(Token::Integer(3)),
+                span: None,
+            }),
+            message: "expect a closing parenthesis, got Integer(3)".to_string()
+        })
+    );
+
+    // (quote (a)) is '(a)
+    let tokens = vec![
+        Token::LeftParen,
+        Token::Identifier("quote".into()),
+        Token::LeftParen,
+        Token::Identifier("a".into()),
+        Token::RightParen,
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse()?,
+        expr_to_statement!(Expression::Datum(Box::new(Datum::List(vec![
+            Datum::Symbol("a".into())
+        ]))))
+    );
+    let tokens = vec![
+        Token::LeftParen,
+        Token::Identifier("quote".into()),
+        Token::Integer(1),
+        Token::Integer(2),
+        Token::RightParen,
+    ];
+    let mut parser = Parser::new(tokens.into_iter());
+    assert_eq!(
+        parser.parse(),
+        Err(Error {
+            category: ErrorType::Syntax(SyntaxError::InvalidForm("quote".to_string())),
+            message: "quote: expect exactly one datum".to_string()
+        })
+    );
     Ok(())
 }
+
+#[test]
+fn incomplete_input() {
+    for source in [
+        "(define (f x)\n",
+        "#(1 (2)\n",
+        "'\n",
+        "(display \"a)\n",
+        "(list |two\n",
+    ]
+    .iter()
+    {
+        assert!(is_incomplete(source), "{}", source);
+    }
+    for source in [
+        "",
+        "(define (f x) x)\n",
+        "(display \"(\")\n",
+        "(list #\\( ; (\n)\n",
+        "(+ 1 2))\n(\n",
+        "(+ 1/0\n",
+    ]
+    .iter()
+    {
+        assert!(!is_incomplete(source), "{}", source);
+    }
+}
@@ -1,59 +1,701 @@
-use crate::interpreter;
+use crate::diagnostic;
+use crate::environment::Environment;
+use crate::error::Error;
+use crate::interpreter::port::{file_error, Port};
+use crate::interpreter::ruschm::digest::constant_time_eq;
+use crate::interpreter::ruschm::trace;
+use crate::interpreter::scheme::time::elapsed_report;
+use crate::interpreter::{condition_object, External, Interpreter, Labeling, Limits, ValueType};
+use crate::lexer::TokenGenerator;
+use crate::parser;
+use crate::parser::{Parser, Statement};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
 use std::io;
-use std::io::Write;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use rustyline::completion::Completer;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, EditMode, Editor, Helper};
 
-fn check_bracket_closed(chars: impl Iterator<Item = char>) -> bool {
-    let mut count = 0;
-    let mut in_comment = false;
-    for c in chars {
-        match (c, in_comment) {
-            ('(', false) => count = count + 1,
-            (')', false) => count = count - 1,
-            (';', false) => in_comment = true,
-            ('\n', true) => in_comment = false,
-            _ => (),
+const HISTORY_SIZE: usize = 1000;
+// clients are served one at a time, so one that stops sending is disconnected for the next,
+// and sooner while another client waits
+const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(30);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+const TURN_TIMEOUT: Duration = Duration::from_secs(30);
+// how long to wait before accepting again when a connection cannot be accepted
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+// results are abbreviated so that huge structures do not flood the terminal
+const PRINT_DEPTH: usize = 10;
+const PRINT_LENGTH: usize = 100;
+
+// RUSCHM_PRINT_DEPTH and RUSCHM_PRINT_LENGTH override the limits, where none lifts them
+fn print_limit(variable: &str, default: usize) -> Option<usize> {
+    match env::var(variable) {
+        Ok(limit) if limit == "none" => None,
+        Ok(limit) => Some(limit.parse().unwrap_or(default)),
+        Err(_) => Some(default),
+    }
+}
+
+fn print_limits() -> Limits {
+    Limits {
+        depth: print_limit("RUSCHM_PRINT_DEPTH", PRINT_DEPTH),
+        length: print_limit("RUSCHM_PRINT_LENGTH", PRINT_LENGTH),
+    }
+}
+
+// completes keywords and the names bound in the environment, which grows with user definitions
+struct IdentifierCompleter(Rc<Environment>);
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()'`,\";".contains(c)
+}
+
+// the start of the identifier before the cursor and the names it could be completed to
+pub(crate) fn completions(names: &[String], line: &str, pos: usize) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .rfind(is_delimiter)
+        .map_or(0, |delimiter| delimiter + 1);
+    let prefix = &line[start..pos];
+    let mut candidates: Vec<String> = parser::KEYWORDS
+        .iter()
+        .map(|keyword| keyword.to_string())
+        .chain(names.iter().cloned())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+impl Completer for IdentifierCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(completions(&self.0.names(), line, pos))
+    }
+}
+
+impl Hinter for IdentifierCompleter {}
+
+impl Highlighter for IdentifierCompleter {}
+
+impl Validator for IdentifierCompleter {}
+
+impl Helper for IdentifierCompleter {}
+
+// RUSCHM_HISTORY, where an empty path keeps no history, or ruschm/history in the XDG data home
+fn history_path() -> Option<PathBuf> {
+    match env::var_os("RUSCHM_HISTORY") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => {
+            let data_home = match env::var_os("XDG_DATA_HOME") {
+                Some(path) if !path.is_empty() => PathBuf::from(path),
+                _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+            };
+            Some(data_home.join("ruschm").join("history"))
+        }
+    }
+}
+
+// the most recent entries, each kept only where it was last entered
+fn deduplicate<'a>(
+    entries: impl DoubleEndedIterator<Item = &'a String>,
+    size: usize,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut kept: Vec<String> = entries
+        .rev()
+        .filter(|entry| seen.insert(entry.as_str()))
+        .take(size)
+        .cloned()
+        .collect();
+    kept.reverse();
+    kept
+}
+
+// history is a convenience, so failing to save it never interrupts the session
+fn save_history(rl: &mut Editor<IdentifierCompleter>, path: &Path) {
+    let entries = deduplicate(rl.history().iter(), HISTORY_SIZE);
+    rl.clear_history();
+    for entry in entries {
+        rl.add_history_entry(entry);
+    }
+    if let Some(directory) = path.parent() {
+        let _ = fs::create_dir_all(directory);
+    }
+    let _ = rl.save_history(path);
+}
+
+const COMMANDS: &[(&str, &str)] = &[
+    (",help", "show this list of commands"),
+    (
+        ",load FILE",
+        "evaluate the definitions and expressions of FILE",
+    ),
+    (
+        ",env [PREFIX]",
+        "list the definitions of the session, or every name bound with PREFIX",
+    ),
+    (
+        ",clear",
+        "discard the definitions of the session and start over",
+    ),
+    (
+        ",time EXPRESSION",
+        "evaluate EXPRESSION and report the time and memory it took",
+    ),
+    (
+        ",expand FORM",
+        "show FORM with its macros expanded, without evaluating it",
+    ),
+    (
+        ",trace [NAME ...]",
+        "report the calls and results of the procedures NAME, or list the traced ones",
+    ),
+    (
+        ",untrace [NAME ...]",
+        "stop tracing the procedures NAME, or every traced one",
+    ),
+    (",quit", "leave the repl"),
+];
+
+// the variables bound to recent results and the last error, which ,env leaves out
+const RESULT_NAMES: &[&str] = &["*1", "*2", "*3", "*e"];
+
+// the width ,expand lays forms out in
+const EXPANSION_WIDTH: usize = 80;
+
+// written code as nested lists, for laying it out over lines
+#[derive(Debug, PartialEq)]
+enum Layout {
+    Atom(String),
+    // the opening like ( or #( and the elements
+    List(String, Vec<Layout>),
+}
+
+impl Layout {
+    // reads the text written by the parser's display, which is balanced
+    fn read(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<Layout> {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut atom = String::new();
+        while let Some(&c) = chars.peek() {
+            match c {
+                '(' => {
+                    chars.next();
+                    atom.push(c);
+                    let mut elements = vec![];
+                    while let Some(element) = Layout::read(chars) {
+                        elements.push(element);
+                    }
+                    chars.next();
+                    return Some(Layout::List(atom, elements));
+                }
+                ')' if atom.is_empty() => return None,
+                _ if c.is_whitespace() || c == ')' => break,
+                // strings and |identifiers| run to their closing delimiter
+                '"' | '|' => {
+                    chars.next();
+                    atom.push(c);
+                    while let Some(next) = chars.next() {
+                        atom.push(next);
+                        match next {
+                            '\\' => atom.extend(chars.next()),
+                            _ if next == c => break,
+                            _ => (),
+                        }
+                    }
+                }
+                _ => {
+                    chars.next();
+                    atom.push(c);
+                    // the character of #\( and the like is not a delimiter
+                    if atom == "#\\" {
+                        atom.extend(chars.next());
+                    }
+                }
+            }
+        }
+        match atom.is_empty() {
+            true => None,
+            false => Some(Layout::Atom(atom)),
+        }
+    }
+
+    fn flat(&self) -> String {
+        match self {
+            Layout::Atom(atom) => atom.clone(),
+            Layout::List(opening, elements) => format!(
+                "{}{})",
+                opening,
+                elements
+                    .iter()
+                    .map(Layout::flat)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+
+    // one line when it fits, otherwise the operator and first operand on the first line and the
+    // other elements each on a line of their own, indented by two
+    fn write(&self, text: &mut String, indent: usize, width: usize) {
+        let flat = self.flat();
+        let (opening, elements) = match self {
+            Layout::List(opening, elements)
+                if indent + flat.len() > width && elements.len() > 1 =>
+            {
+                (opening, elements)
+            }
+            _ => return text.push_str(&flat),
+        };
+        text.push_str(opening);
+        let inner = indent + opening.len();
+        let (first, rest) = match &elements[0] {
+            Layout::Atom(operator) => {
+                text.push_str(operator);
+                text.push(' ');
+                elements[1].write(text, inner + operator.len() + 1, width);
+                (inner + 1, &elements[2..])
+            }
+            operator => {
+                operator.write(text, inner, width);
+                (inner, &elements[1..])
+            }
+        };
+        for element in rest {
+            text.push('\n');
+            text.push_str(&" ".repeat(first));
+            element.write(text, first, width);
         }
+        text.push(')');
     }
-    count <= 0
 }
 
-pub fn run() {
-    let it = interpreter::Interpreter::new();
-    let mut rl = Editor::<()>::new();
+fn pretty(text: &str, width: usize) -> String {
+    let mut chars = text.chars().peekable();
+    let mut laid_out = String::new();
+    if let Some(layout) = Layout::read(&mut chars) {
+        layout.write(&mut laid_out, 0, width);
+    }
+    laid_out
+}
+
+// what the loop does after a complete input
+#[derive(Debug, PartialEq)]
+enum Flow {
+    Continue,
+    Quit,
+    // scheme code called exit with the code
+    Exit(i32),
+}
+
+// the interpreter of a repl session and the meta-commands handled outside of it
+pub struct Session {
+    interpreter: Interpreter,
+    limits: Limits,
+    // the names bound before any input, which ,env leaves out
+    initial_names: HashSet<String>,
+    // whether errors are rendered in color
+    color: bool,
+    // the script evaluated before any input, and again when the session starts over
+    init_file: Option<PathBuf>,
+    // the traced procedures by name, with the original and the wrapper bound in its place
+    traced: BTreeMap<String, (ValueType, ValueType)>,
+    // how long a remote client may leave the session waiting for a line
+    idle_timeout: Duration,
+    // how long it may do so while another client waits for the session
+    turn_timeout: Duration,
+}
+
+impl Session {
+    fn new(limits: Limits, color: bool, init_file: Option<PathBuf>) -> Self {
+        Self::with_interpreter(Interpreter::new(), limits, color, init_file)
+    }
+
+    // a session served to remote clients on an interpreter made by the embedder, e.g. with
+    // an image loaded, evaluating the init file first when init is true
+    pub fn remote(interpreter: Interpreter, init: bool) -> Self {
+        let init_file = match init {
+            true => init_path(),
+            false => None,
+        };
+        Self::with_interpreter(interpreter, print_limits(), false, init_file)
+    }
+
+    fn with_interpreter(
+        interpreter: Interpreter,
+        limits: Limits,
+        color: bool,
+        init_file: Option<PathBuf>,
+    ) -> Self {
+        let initial_names = interpreter
+            .global_environment()
+            .names()
+            .into_iter()
+            .collect();
+        Self {
+            interpreter,
+            limits,
+            initial_names,
+            color,
+            init_file,
+            traced: BTreeMap::new(),
+            idle_timeout: IDLE_TIMEOUT,
+            turn_timeout: TURN_TIMEOUT,
+        }
+    }
+
+    // the interpreter, which ,clear replaces with a fresh one
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    fn trace(&mut self, name: &str, err: &mut dyn Write) -> io::Result<()> {
+        let environment = self.environment();
+        let procedure = match environment.get(name) {
+            Some(procedure) => procedure,
+            None => return writeln!(err, ",trace: {} is not bound", name),
+        };
+        if self.traced.contains_key(name) {
+            return Ok(());
+        }
+        match trace::trace(&self.interpreter, name, procedure.clone()) {
+            Ok(wrapper) => {
+                environment.define(name.to_string(), wrapper.clone());
+                self.traced.insert(name.to_string(), (procedure, wrapper));
+                Ok(())
+            }
+            Err(e) => writeln!(err, "{}", e),
+        }
+    }
+
+    // a procedure defined again since it was traced keeps the new definition
+    fn untrace(&mut self, name: &str, err: &mut dyn Write) -> io::Result<()> {
+        match self.traced.remove(name) {
+            Some((original, wrapper)) => {
+                let environment = self.environment();
+                if environment.get(name) == Some(wrapper) {
+                    environment.define(name.to_string(), original);
+                }
+                Ok(())
+            }
+            None => writeln!(err, ",untrace: {} is not traced", name),
+        }
+    }
+
+    // evaluates the init file if there is one, its results are not printed
+    fn initialize(&mut self, err: &mut dyn Write) -> io::Result<Flow> {
+        let path = match &self.init_file {
+            Some(path) => path.clone(),
+            None => return Ok(Flow::Continue),
+        };
+        let name = path.to_string_lossy();
+        match fs::read_to_string(&path) {
+            Ok(program) => self.evaluate(&program, Some(&name), &mut io::sink(), err),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Flow::Continue),
+            Err(e) => {
+                writeln!(err, "{}", file_error("read", &name, e))?;
+                Ok(Flow::Continue)
+            }
+        }
+    }
+
+    fn environment(&self) -> Rc<Environment> {
+        self.interpreter.global_environment().clone()
+    }
+
+    fn print(&self, out: &mut dyn Write, value: &ValueType) -> io::Result<()> {
+        writeln!(
+            out,
+            "{}",
+            External::new(value, true, Labeling::Cycles).limited(self.limits)
+        )
+    }
+
+    // binds the result to *1, moving the earlier ones to *2 and *3
+    fn remember(&self, value: ValueType) {
+        let environment = self.environment();
+        for (name, earlier) in [("*3", "*2"), ("*2", "*1")].iter() {
+            if let Some(result) = environment.get(earlier) {
+                environment.define(name.to_string(), result);
+            }
+        }
+        environment.define("*1".to_string(), value);
+    }
+
+    // evaluates a complete input, writing results to out and errors to err
+    // a bug of the interpreter panicking loses the input, but not the session
+    fn execute(
+        &mut self,
+        source: &str,
+        out: &mut dyn Write,
+        err: &mut dyn Write,
+    ) -> io::Result<Flow> {
+        let flow =
+            panic::catch_unwind(AssertUnwindSafe(|| match source.trim().strip_prefix(',') {
+                Some(command) => self.command(command, out, err),
+                None => self.evaluate(source, None, out, err),
+            }));
+        match flow {
+            Ok(flow) => flow,
+            Err(_) => {
+                self.interpreter.call_depth.set(0);
+                self.interpreter.trace_depth.set(0);
+                writeln!(err, "internal error, the session goes on")?;
+                Ok(Flow::Continue)
+            }
+        }
+    }
+
+    // scheme code, from a file of the name if any
+    fn evaluate(
+        &mut self,
+        source: &str,
+        name: Option<&str>,
+        out: &mut dyn Write,
+        err: &mut dyn Write,
+    ) -> io::Result<Flow> {
+        // the rest of an input is abandoned at an error, a file goes on after forms which
+        // cannot be read
+        let result = match name {
+            Some(_) => self.interpreter.load_source(source, name),
+            None => self
+                .interpreter
+                .eval_named_source(source, name)
+                .map_err(|error| vec![error]),
+        };
+        match result {
+            Ok(Some(ValueType::Void)) | Ok(None) => (),
+            Ok(Some(value)) => {
+                self.print(out, &value)?;
+                self.remember(value);
+            }
+            Err(errors) => {
+                for (e, span) in errors {
+                    if let Some(code) = e.exit_code() {
+                        return Ok(Flow::Exit(code));
+                    }
+                    diagnostic::render(err, &e, span, source, name, self.color)?;
+                    self.environment()
+                        .define("*e".to_string(), condition_object(&e));
+                }
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn command(
+        &mut self,
+        command: &str,
+        out: &mut dyn Write,
+        err: &mut dyn Write,
+    ) -> io::Result<Flow> {
+        let (name, argument) = match command.find(char::is_whitespace) {
+            Some(end) => (&command[..end], command[end..].trim()),
+            None => (command, ""),
+        };
+        match (name, argument) {
+            ("help", "") => {
+                for (usage, description) in COMMANDS {
+                    writeln!(out, "{:<20} {}", usage, description)?;
+                }
+            }
+            ("load", "") => writeln!(err, ",load: expect a file name")?,
+            ("load", path) => {
+                // the name may be quoted like a string, as for load
+                let path = path
+                    .strip_prefix('"')
+                    .and_then(|path| path.strip_suffix('"'))
+                    .unwrap_or(path);
+                match fs::read_to_string(path) {
+                    Ok(program) => return self.evaluate(&program, Some(path), out, err),
+                    Err(e) => writeln!(err, "{}", file_error("load", path, e))?,
+                }
+            }
+            ("env", prefix) => {
+                let environment = self.environment();
+                for name in environment.names() {
+                    match prefix {
+                        "" if !self.initial_names.contains(&name)
+                            && !RESULT_NAMES.contains(&name.as_str()) =>
+                        {
+                            write!(out, "{} = ", name)?;
+                            self.print(out, &environment.get(&name).unwrap())?;
+                        }
+                        "" => (),
+                        _ if name.starts_with(prefix) => writeln!(out, "{}", name)?,
+                        _ => (),
+                    }
+                }
+            }
+            ("time", "") => writeln!(err, ",time: expect an expression")?,
+            ("time", expression) => {
+                let (start, statistics) = (Instant::now(), self.interpreter.statistics());
+                let flow = self.evaluate(expression, None, out, err)?;
+                let used = self.interpreter.statistics().since(&statistics);
+                writeln!(out, "{}", elapsed_report(start.elapsed(), &used))?;
+                return Ok(flow);
+            }
+            ("expand", "") => writeln!(err, ",expand: expect a form")?,
+            ("expand", form) => {
+                let mut chars = form.chars().peekable();
+                let tokens: Result<Vec<_>, _> = TokenGenerator::new(&mut chars).collect();
+                match tokens.and_then(|tokens| Parser::new(tokens.into_iter()).collect()) {
+                    Ok(statements) => {
+                        let statements: Vec<Statement> = statements;
+                        for statement in statements {
+                            writeln!(out, "{}", pretty(&statement.to_string(), EXPANSION_WIDTH))?;
+                        }
+                    }
+                    Err(e) => writeln!(err, "{}", e)?,
+                }
+            }
+            ("trace", "") => {
+                for name in self.traced.keys() {
+                    writeln!(out, "{}", name)?;
+                }
+            }
+            ("trace", names) => {
+                for name in names.split_whitespace() {
+                    self.trace(name, err)?;
+                }
+            }
+            ("untrace", names) => {
+                let names: Vec<String> = match names {
+                    "" => self.traced.keys().cloned().collect(),
+                    _ => names.split_whitespace().map(str::to_string).collect(),
+                };
+                for name in names {
+                    self.untrace(&name, err)?;
+                }
+            }
+            ("clear", "") => {
+                *self = Session::new(self.limits, self.color, self.init_file.take());
+                return self.initialize(err);
+            }
+            ("quit", "") => return Ok(Flow::Quit),
+            ("help", _) | ("clear", _) | ("quit", _) => {
+                writeln!(err, ",{}: expect no arguments", name)?
+            }
+            _ => writeln!(
+                err,
+                "unknown command ,{}, type ,help for the commands",
+                name
+            )?,
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+// the init file evaluated when the repl starts, ~/.ruschmrc
+fn init_path() -> Option<PathBuf> {
+    Some(PathBuf::from(env::var_os("HOME")?).join(".ruschmrc"))
+}
+
+// runs the repl on the terminal, evaluating the init file first when init is true, with the
+// variables of the image if given
+pub fn run(init: bool, image: Option<&str>) -> Result<(), Error> {
+    let init_file = match init {
+        true => init_path(),
+        false => None,
+    };
+    let mut session = Session::new(
+        print_limits(),
+        diagnostic::use_color(&io::stderr()),
+        init_file,
+    );
+    // the terminal is the user's own, as the command line is
+    session.interpreter.allow_subprocesses.set(true);
+    session.interpreter.allow_network.set(true);
+    if let Some(image) = image {
+        session.interpreter.load_image(image)?;
+    }
+    // emacs key bindings give ctrl-a/ctrl-e movement and the ctrl-k/ctrl-y kill ring
+    let config = Config::builder()
+        .edit_mode(EditMode::Emacs)
+        .max_history_size(HISTORY_SIZE)
+        .history_ignore_dups(true)
+        .history_ignore_space(true)
+        .completion_type(CompletionType::List)
+        .build();
+    let mut rl = Editor::with_config(config);
+    rl.set_helper(Some(IdentifierCompleter(session.environment())));
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = rl.load_history(path);
+    }
     io::stdout().flush().unwrap();
     let mut source = String::new();
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-    println!("Ruschm Version {}", VERSION);
+    println!("Ruschm Version {}, type ,help for the commands", VERSION);
+    if let Flow::Exit(code) = session.initialize(&mut io::stderr()).unwrap() {
+        std::process::exit(code);
+    }
     loop {
         let readline = match source.is_empty() {
             true => rl.readline("> "),
-            false => rl.readline(""),
+            false => rl.readline("... "),
         };
         match readline {
             Ok(line) => {
-                if line.is_empty() {
+                // lines read without a terminal keep their line break
+                let line = line.trim_end_matches(['\n', '\r']);
+                // blank lines are kept within unfinished input, e.g. strings
+                if line.is_empty() && source.is_empty() {
                     continue;
                 }
-                source.push_str(line.as_str());
-                source.push_str("\n");
-                if check_bracket_closed(source.chars()) {
-                    match it.eval(source.chars()) {
-                        Ok(opt) => {
-                            if let Some(value) = opt {
-                                match value {
-                                    interpreter::ValueType::Void => (),
-                                    _ => println!("{}", value),
-                                }
-                            }
-                        }
-                        Err(e) => eprintln!("{}", e),
+                source.push_str(line);
+                source.push('\n');
+                // commands take a single line, a comma never starts scheme code at the top level
+                let command = source.trim_start().starts_with(',');
+                if command || !parser::is_incomplete(&source) {
+                    // an expression spanning several lines is recalled as one entry
+                    rl.add_history_entry(source.trim_end().replace('\n', " "));
+                    if let Some(path) = &history {
+                        save_history(&mut rl, path);
                     }
+                    let flow = session
+                        .execute(&source, &mut io::stdout(), &mut io::stderr())
+                        .unwrap();
                     source.clear();
+                    // ,clear starts over with another environment
+                    if let Some(helper) = rl.helper_mut() {
+                        helper.0 = session.environment();
+                    }
+                    match flow {
+                        Flow::Continue => (),
+                        Flow::Quit => {
+                            println!("exited. have a nice day.");
+                            break;
+                        }
+                        Flow::Exit(code) => std::process::exit(code),
+                    }
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -71,4 +713,535 @@ pub fn run() {
             }
         }
     }
+    Ok(())
+}
+
+// the text written through a port, so that results and errors keep their place among the
+// output of the program
+struct PortWriter(Rc<Port>);
+
+impl Write for PortWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .write_str(&String::from_utf8_lossy(buf))
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+// the output and error ports of the interpreter while a client is connected, given back
+// however the connection ends
+struct RemotePorts<'a> {
+    session: &'a mut Session,
+    port: Rc<Port>,
+    output: Rc<Port>,
+    error: Rc<Port>,
+}
+
+impl<'a> RemotePorts<'a> {
+    fn new(session: &'a mut Session, port: Rc<Port>) -> Self {
+        let interpreter = &session.interpreter;
+        let output = interpreter.current_output_port.replace(port.clone());
+        let error = interpreter.current_error_port.replace(port.clone());
+        Self {
+            session,
+            port,
+            output,
+            error,
+        }
+    }
+}
+
+impl Drop for RemotePorts<'_> {
+    // ,clear gives another interpreter, which keeps the ports it started with
+    fn drop(&mut self) {
+        let interpreter = &self.session.interpreter;
+        if Rc::ptr_eq(&interpreter.current_output_port.borrow(), &self.port) {
+            interpreter.current_output_port.replace(self.output.clone());
+            interpreter.current_error_port.replace(self.error.clone());
+        }
+    }
+}
+
+fn timed_out(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+enum Received {
+    Line(usize),
+    Idle,
+    // another client waits and this one has had its turn
+    Preempted,
+}
+
+// reads a line into line, giving up after limit, or after turn while others are waiting
+fn receive(
+    lines: &mut BufReader<TcpStream>,
+    line: &mut String,
+    limit: Duration,
+    turn: Duration,
+    waiting: &AtomicUsize,
+) -> io::Result<Received> {
+    let started = Instant::now();
+    lines.get_ref().set_read_timeout(Some(limit.min(turn)))?;
+    loop {
+        // a timeout keeps the part of the line read so far in line
+        match lines.read_line(line) {
+            Ok(read) => return Ok(Received::Line(read)),
+            Err(e) if timed_out(&e) => {
+                let idle = started.elapsed();
+                if idle >= limit {
+                    return Ok(Received::Idle);
+                }
+                if idle >= turn && waiting.load(Ordering::SeqCst) > 0 {
+                    return Ok(Received::Preempted);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// a session for one client, whose output and errors go to the connection
+// the first line must be the token when one is given
+fn connect(
+    session: &mut Session,
+    stream: TcpStream,
+    token: Option<&str>,
+    waiting: &AtomicUsize,
+) -> io::Result<Flow> {
+    let mut lines = BufReader::new(stream.try_clone()?);
+    let port = Rc::new(Port::socket_output("remote", stream, true));
+    let mut out = PortWriter(port.clone());
+    let mut line = String::new();
+    let turn = session.turn_timeout;
+    if let Some(token) = token {
+        write!(out, "token: ")?;
+        out.flush()?;
+        let given = match receive(&mut lines, &mut line, AUTHENTICATION_TIMEOUT, turn, waiting)? {
+            Received::Line(_) => line.trim_end_matches(['\n', '\r']),
+            _ => "",
+        };
+        if !constant_time_eq(given.as_bytes(), token.as_bytes()) {
+            writeln!(out, "authentication failed")?;
+            return out.flush().map(|_| Flow::Quit);
+        }
+    }
+    let idle_timeout = session.idle_timeout;
+    const VERSION: &str = env!("CARGO_PKG_VERSION");
+    writeln!(
+        out,
+        "Ruschm Version {}, type ,help for the commands",
+        VERSION
+    )?;
+    let ports = RemotePorts::new(session, port.clone());
+    let mut source = String::new();
+    let flow = loop {
+        write!(
+            out,
+            "{}",
+            match source.is_empty() {
+                true => "> ",
+                false => "... ",
+            }
+        )?;
+        out.flush()?;
+        line.clear();
+        match receive(&mut lines, &mut line, idle_timeout, turn, waiting)? {
+            Received::Line(0) => break Flow::Quit,
+            Received::Line(_) => (),
+            Received::Idle => {
+                writeln!(out, "\nidle for too long, disconnected")?;
+                break Flow::Quit;
+            }
+            Received::Preempted => {
+                writeln!(out, "\nidle while another client waits, disconnected")?;
+                break Flow::Quit;
+            }
+        }
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() && source.is_empty() {
+            continue;
+        }
+        source.push_str(line);
+        source.push('\n');
+        if source.trim_start().starts_with(',') || !parser::is_incomplete(&source) {
+            let flow = ports
+                .session
+                .execute(&source, &mut out, &mut PortWriter(port.clone()))?;
+            source.clear();
+            if flow != Flow::Continue {
+                break flow;
+            }
+        }
+    };
+    drop(ports);
+    out.flush()?;
+    Ok(flow)
+}
+
+// serves the repl to the clients of the listener one after another, all in the one session,
+// evaluating the init file of the session first
+// connections are accepted on a thread of their own so that a client idling while others wait
+// is disconnected, and a failure of one connection only ends that one
+// returns the exit code once a client calls exit
+pub fn serve(listener: TcpListener, token: Option<&str>, session: &mut Session) -> io::Result<i32> {
+    if let Flow::Exit(code) = session.initialize(&mut io::stderr())? {
+        return Ok(code);
+    }
+    let waiting = Arc::new(AtomicUsize::new(0));
+    let (sender, streams) = mpsc::channel();
+    let accepted = waiting.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    // the session is over once nobody receives
+                    if sender.send(stream).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("ruschm: cannot accept a connection: {}", e);
+                    thread::sleep(ACCEPT_RETRY);
+                }
+            }
+        }
+    });
+    for stream in streams {
+        waiting.fetch_sub(1, Ordering::SeqCst);
+        let peer = match stream.peer_addr() {
+            Ok(peer) => peer,
+            Err(e) => {
+                eprintln!("ruschm: cannot serve a connection: {}", e);
+                continue;
+            }
+        };
+        eprintln!("ruschm: connection from {}", peer);
+        // a client going away leaves the session to the next one
+        match connect(session, stream, token, &waiting) {
+            Ok(Flow::Exit(code)) => return Ok(code),
+            Ok(_) => (),
+            Err(e) => eprintln!("ruschm: connection from {}: {}", peer, e),
+        }
+        eprintln!("ruschm: {} disconnected", peer);
+    }
+    Ok(0)
+}
+
+#[test]
+fn history_deduplication() {
+    let entries: Vec<String> = ["(f 1)", "(g)", "(f 1)", "(h)", "(g)"]
+        .iter()
+        .map(|entry| entry.to_string())
+        .collect();
+    assert_eq!(deduplicate(entries.iter(), 10), vec!["(f 1)", "(h)", "(g)"]);
+    assert_eq!(deduplicate(entries.iter(), 2), vec!["(h)", "(g)"]);
+}
+
+#[test]
+fn identifier_completion() {
+    let names: Vec<String> = ["car", "cdr", "char?", "define-values"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    assert_eq!(
+        completions(&names, "(map (lambda (x) (cd x", 20),
+        (18, vec!["cdr".to_string()])
+    );
+    assert_eq!(
+        completions(&names, "(defi", 5),
+        (
+            1,
+            vec![
+                "define".to_string(),
+                "define-stream".to_string(),
+                "define-values".to_string()
+            ]
+        )
+    );
+    assert_eq!(completions(&names, "\"x", 2), (1, vec![]));
+}
+
+#[test]
+fn meta_commands() -> io::Result<()> {
+    let mut session = Session::new(Limits::default(), false, None);
+    let mut execute = |source: &str| -> io::Result<(Flow, String, String)> {
+        let (mut out, mut err) = (vec![], vec![]);
+        let flow = session.execute(source, &mut out, &mut err)?;
+        Ok((
+            flow,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        ))
+    };
+    let continued = |out: &str, err: &str| (Flow::Continue, out.to_string(), err.to_string());
+    assert_eq!(execute("(define x '(1 2))\n")?, continued("", ""));
+    assert_eq!(execute("(car x)\n")?, continued("1\n", ""));
+    assert_eq!(execute(",env\n")?, continued("x = (1 2)\n", ""));
+    assert_eq!(
+        execute(",env stream-c\n")?,
+        continued("stream-car\nstream-cdr\n", "")
+    );
+    assert_eq!(execute(",clear\n")?, continued("", ""));
+    assert_eq!(execute(",env\n")?, continued("", ""));
+    assert_eq!(
+        execute(",load \"/nonexistent.scm\"\n")?,
+        continued(
+            "",
+            "file error: cannot load /nonexistent.scm: No such file or directory (os error 2)\n"
+        )
+    );
+    assert_eq!(
+        execute(",frobnicate\n")?,
+        continued(
+            "",
+            "unknown command ,frobnicate, type ,help for the commands\n"
+        )
+    );
+    assert_eq!(
+        execute("(car x)\n")?,
+        continued(
+            "",
+            "error: undefined identifier: x\n  |\n1 | (car x)\n  | ^^^^^^^\n"
+        )
+    );
+    let help = execute(",help\n")?.1;
+    for usage in [
+        ",time EXPRESSION",
+        ",expand FORM",
+        ",trace [NAME ...]",
+        ",untrace [NAME ...]",
+    ]
+    .iter()
+    {
+        assert!(help.contains(usage), "{}", usage);
+    }
+    assert_eq!(execute(",quit\n")?.0, Flow::Quit);
+    assert_eq!(execute("(exit 3)\n")?.0, Flow::Exit(3));
+    Ok(())
+}
+
+#[test]
+fn result_history() -> io::Result<()> {
+    let mut session = Session::new(Limits::default(), false, None);
+    let mut execute = |source: &str| -> io::Result<String> {
+        let mut out = vec![];
+        session.execute(source, &mut out, &mut io::sink())?;
+        Ok(String::from_utf8(out).unwrap())
+    };
+    for source in ["1", "(+ *1 1)", "(define x 3)", "(* *1 10)"].iter() {
+        execute(source)?;
+    }
+    assert_eq!(execute("(list *1 *2 *3)")?, "(20 2 1)\n");
+    assert_eq!(execute("(list *1 *2 *3)")?, "((20 2 1) 20 2)\n");
+    execute("(car '())")?;
+    assert_eq!(
+        execute("(error-object-message *e)")?,
+        "\"car: expect a pair, got ()\"\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn init_file() -> io::Result<()> {
+    let path = env::temp_dir().join(format!("ruschmrc-{}", std::process::id()));
+    fs::write(&path, "(define (twice x) (* 2 x))\n(twice 1)\n")?;
+    let mut session = Session::new(Limits::default(), false, Some(path.clone()));
+    let mut out = vec![];
+    assert_eq!(session.initialize(&mut out)?, Flow::Continue);
+    session.execute(",clear", &mut io::sink(), &mut out)?;
+    session.execute("(twice 21)", &mut out, &mut io::sink())?;
+    fs::remove_file(&path)?;
+    assert_eq!(String::from_utf8(out).unwrap(), "42\n");
+    Ok(())
+}
+
+#[test]
+fn expansion() -> io::Result<()> {
+    let mut session = Session::new(Limits::default(), false, None);
+    let mut expand = |form: &str| -> io::Result<String> {
+        let mut out = vec![];
+        session.execute(&format!(",expand {}", form), &mut out, &mut io::stderr())?;
+        Ok(String::from_utf8(out).unwrap())
+    };
+    assert_eq!(
+        expand("(cut list 'a <> #(1 \"b c\"))")?,
+        "(lambda (|\\x0;slot 2|) (list (quote a) |\\x0;slot 2| #(1 \"b c\")))\n"
+    );
+    assert_eq!(
+        expand("(time (if #t 1))")?,
+        "(#<primitive time> (lambda () (if #t 1)))\n"
+    );
+    assert_eq!(
+        expand(
+            "(define (classify n) (if (< n 0) 'negative (if (= n 0) 'zero (if (< n 10) 'small 'large))))"
+        )?,
+        "(define classify
+  (lambda (n)
+    (if (< n 0)
+      (quote negative)
+      (if (= n 0) (quote zero) (if (< n 10) (quote small) (quote large))))))\n"
+    );
+    // which reads back as the forms it expands
+    let expanded = expand("(define (pair) (cons 'a '(b)))")?;
+    let mut out = vec![];
+    session.execute(&expanded, &mut out, &mut io::stderr())?;
+    session.execute("(pair)", &mut out, &mut io::stderr())?;
+    assert!(String::from_utf8(out).unwrap().ends_with("(a b)\n"));
+    Ok(())
+}
+
+#[test]
+fn tracing_commands() -> io::Result<()> {
+    let mut session = Session::new(Limits::default(), false, None);
+    // the calls are written to the output port of the program, the commands to out and err
+    let mut execute = |source: &str| -> io::Result<(String, String, String)> {
+        let port = Rc::new(Port::open_output_string());
+        session
+            .interpreter
+            .current_output_port
+            .replace(port.clone());
+        let (mut out, mut err) = (vec![], vec![]);
+        session.execute(source, &mut out, &mut err)?;
+        Ok((
+            port.output_string().unwrap(),
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        ))
+    };
+    let output = |calls: &str, out: &str| (calls.to_string(), out.to_string(), String::new());
+    execute("(define (double x) (* 2 x)) (define (quadruple x) (double (double x)))")?;
+    assert_eq!(execute(",trace double quadruple")?, output("", ""));
+    assert_eq!(execute(",trace")?, output("", "double\nquadruple\n"));
+    assert_eq!(
+        execute("(quadruple 1)")?,
+        output(
+            "(quadruple 1)\n  (double 1)\n  => 2\n  (double 2)\n  => 4\n=> 4\n",
+            "4\n"
+        )
+    );
+    assert_eq!(execute(",untrace quadruple")?, output("", ""));
+    assert_eq!(
+        execute("(quadruple 1)")?,
+        output("(double 1)\n=> 2\n(double 2)\n=> 4\n", "4\n")
+    );
+    assert_eq!(execute(",untrace")?, output("", ""));
+    assert_eq!(execute("(quadruple 1)")?, output("", "4\n"));
+    let error = |message: &str| (String::new(), String::new(), message.to_string());
+    assert_eq!(
+        execute(",untrace double")?,
+        error(",untrace: double is not traced\n")
+    );
+    assert_eq!(
+        execute(",trace triple")?,
+        error(",trace: triple is not bound\n")
+    );
+    assert_eq!(
+        execute(",trace car")?,
+        error("error: trace: car is a builtin procedure, only procedures defined in scheme can be traced\n")
+    );
+    Ok(())
+}
+
+#[test]
+fn remote_session() -> io::Result<()> {
+    use std::io::Read;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let server = thread::spawn(move || {
+        let mut session = Session::remote(Interpreter::new(), false);
+        session.idle_timeout = Duration::from_millis(400);
+        session.turn_timeout = Duration::from_millis(50);
+        let output = session.interpreter().current_output_port.borrow().clone();
+        let code = serve(listener, Some("secret"), &mut session).unwrap();
+        // the ports of the interpreter are its own again
+        let restored = session.interpreter().current_output_port.borrow().clone();
+        assert!(Rc::ptr_eq(&output, &restored));
+        code
+    });
+    let session = |input: &str| -> io::Result<String> {
+        let mut stream = TcpStream::connect(address)?;
+        stream.write_all(input.as_bytes())?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+        Ok(output)
+    };
+    let banner = format!(
+        "Ruschm Version {}, type ,help for the commands\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    assert_eq!(session("guess\n")?, "token: authentication failed\n");
+    assert_eq!(
+        session("secret\n(define x 1)\n(display \"x is \")\nx\n(car\n x)\n")?,
+        format!(
+            "token: {}> > x is > 1\n> ... error: car: expect a pair, got 1\n  |\n1 | (car\n  | ^^^^\n> ",
+            banner
+        )
+    );
+    // a client which stops sending gives way to the next
+    let mut idle = TcpStream::connect(address)?;
+    idle.write_all(b"secret\n(display 1)\n")?;
+    let mut output = String::new();
+    idle.read_to_string(&mut output)?;
+    assert_eq!(
+        output,
+        format!("token: {}> 1> \nidle for too long, disconnected\n", banner)
+    );
+    // nor does it hold up a client waiting for the session
+    let mut idle = TcpStream::connect(address)?;
+    idle.write_all(b"secret\n")?;
+    let greeting = format!("token: {}> ", banner);
+    let mut output = vec![0; greeting.len()];
+    idle.read_exact(&mut output)?;
+    assert_eq!(String::from_utf8(output).unwrap(), greeting);
+    assert_eq!(
+        session("secret\n(+ x 1)\n")?,
+        format!("token: {}> 2\n> ", banner)
+    );
+    let mut output = String::new();
+    idle.read_to_string(&mut output)?;
+    assert_eq!(output, "\nidle while another client waits, disconnected\n");
+    // results are abbreviated as on the terminal
+    assert_eq!(
+        session("secret\n(make-vector 120 0)\n")?,
+        format!("token: {}> #({} ...)\n> ", banner, vec!["0"; 100].join(" "))
+    );
+    // the definitions of a client are there for the next one
+    assert_eq!(
+        session("secret\n(+ x 1)\n(exit 3)\n")?,
+        format!("token: {}> 2\n> ", banner)
+    );
+    assert_eq!(server.join().unwrap(), 3);
+    Ok(())
+}
+
+#[test]
+fn error_isolation() -> io::Result<()> {
+    let mut session = Session::new(Limits::default(), false, None);
+    session.interpreter.max_call_depth.set(30);
+    let mut execute = |source: &str| -> io::Result<(String, bool)> {
+        let (mut out, mut err) = (vec![], vec![]);
+        session.execute(source, &mut out, &mut err)?;
+        Ok((String::from_utf8(out).unwrap(), !err.is_empty()))
+    };
+    execute("(define x 1)")?;
+    assert_eq!(execute("(define x (car 1))")?, (String::new(), true));
+    assert_eq!(execute("x")?, ("1\n".to_string(), false));
+    // the forms after an error are not evaluated, and the next input is read afresh
+    assert_eq!(execute("(car 1) (define y 2)")?, (String::new(), true));
+    assert_eq!(execute("(display #z) y")?, (String::new(), true));
+    execute("(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1)))))")?;
+    assert_eq!(execute("(count 60)")?, (String::new(), true));
+    assert_eq!(execute("(count 10) x")?, ("1\n".to_string(), false));
+    Ok(())
 }